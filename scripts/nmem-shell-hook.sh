@@ -0,0 +1,38 @@
+# nmem shell hook — records interactive commands into per-day operator sessions.
+# Source from ~/.zshrc or ~/.bashrc:   . /path/to/nmem-shell-hook.sh
+# Commands starting with a space are skipped (same convention as HISTCONTROL=ignorespace).
+# Recording runs in the background; output is discarded so the prompt never blocks.
+
+_nmem_bin="${NMEM_BIN:-nmem}"
+
+_nmem_record() {
+  # $1 = exit code, $2 = command
+  case "$2" in
+    ""|" "*) return ;;
+  esac
+  ( "$_nmem_bin" ingest command --cwd "$PWD" --exit-code "$1" -- "$2" >/dev/null 2>&1 & )
+}
+
+if [ -n "$ZSH_VERSION" ]; then
+  _nmem_preexec() { _nmem_last_cmd="$1"; }
+  _nmem_precmd() {
+    local code=$?
+    [ -n "$_nmem_last_cmd" ] && _nmem_record "$code" "$_nmem_last_cmd"
+    _nmem_last_cmd=""
+  }
+  autoload -Uz add-zsh-hook
+  add-zsh-hook preexec _nmem_preexec
+  add-zsh-hook precmd _nmem_precmd
+elif [ -n "$BASH_VERSION" ]; then
+  _nmem_prompt() {
+    local code=$?
+    local cmd
+    cmd="$(HISTTIMEFORMAT='' history 1 | sed -e 's/^ *[0-9]* *//')"
+    if [ -n "$cmd" ] && [ "$cmd" != "$_nmem_last_cmd" ]; then
+      _nmem_last_cmd="$cmd"
+      _nmem_record "$code" "$cmd"
+    fi
+    return $code
+  }
+  PROMPT_COMMAND="_nmem_prompt${PROMPT_COMMAND:+; $PROMPT_COMMAND}"
+fi
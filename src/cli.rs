@@ -1,12 +1,13 @@
-use clap::{Parser, Subcommand};
+use clap::{ArgGroup, Parser, Subcommand};
 use std::path::PathBuf;
 
 #[derive(Parser)]
 #[command(name = "nmem", version, about = "Cross-session memory for Claude Code")]
 pub struct Cli {
-    /// Database path
+    /// Database path. `serve` accepts it repeatedly: the first is the primary
+    /// store, the rest are attached read-only
     #[arg(long, env = "NMEM_DB", global = true)]
-    pub db: Option<PathBuf>,
+    pub db: Vec<PathBuf>,
 
     #[command(subcommand)]
     pub command: Command,
@@ -15,23 +16,33 @@ pub struct Cli {
 #[derive(Subcommand)]
 pub enum Command {
     /// Record a hook event from stdin
-    Record,
+    Record(RecordArgs),
     /// Start MCP query server on stdio
-    Serve,
+    Serve(ServeArgs),
     /// Purge observations, prompts, and sessions
     Purge(PurgeArgs),
+    /// Re-insert rows saved by `nmem purge --export-before`
+    Restore(RestoreArgs),
     /// Run database maintenance (vacuum, WAL checkpoint, FTS integrity)
     Maintain(MaintainArgs),
     /// Show database health: size, counts, last session
-    Status,
+    Status(StatusArgs),
     /// Search observations by full-text query
     Search(SearchArgs),
+    /// Complete a half-typed request from past prompts and episode intents
+    Suggest(SuggestArgs),
+    /// Regex scan over stored content, for patterns full-text search can't express
+    Grep(GrepArgs),
     /// Encrypt the database (migrate from unencrypted to SQLCipher)
     Encrypt,
     /// Pin an observation (exempt from retention sweeps)
     Pin(PinArgs),
     /// Unpin an observation (restore to normal retention)
     Unpin(PinArgs),
+    /// Mask an observation's content, keeping the original for audit (--restore undoes it)
+    Redact(RedactArgs),
+    /// Attach a correcting note to an observation
+    Annotate(AnnotateArgs),
     /// Show what nmem would inject at session start
     Context(ContextArgs),
     /// Queue a task for later execution
@@ -46,10 +57,553 @@ pub enum Command {
     Backfill(BackfillArgs),
     /// Create an agent-authored marker observation
     Mark(MarkArgs),
+    /// Bracket a line of inquiry: start with a question, conclude with the answer
+    Investigate(InvestigateArgs),
     /// Run LSP server (stdio) — emits git history diagnostics on file open/save
     Lsp,
     /// Connect to fleet NATS and respond to federated search queries
     Beacon(BeaconArgs),
+    /// Ingest activity from outside agent sessions (shell history, shell hook)
+    Ingest(IngestArgs),
+    /// Developer tooling: synthetic fixture databases
+    Fixtures(FixturesArgs),
+    /// Inspect the MCP query log: what gets retrieved, and how often
+    Audit(AuditArgs),
+    /// Check backups by restoring them into a scratch directory
+    Backup(BackupArgs),
+    /// Resource accounting reports
+    Stats(StatsArgs),
+    /// Cluster related episodes across sessions into LLM-written topic memories
+    Consolidate(ConsolidateArgs),
+    /// Compose LLM-written weekly and monthly project summaries for context past the recent sessions
+    Rollup(RollupArgs),
+    /// List projects and archive the ones that are done
+    Project(ProjectArgs),
+    /// Print the newest observations and prompts as JSON lines; --follow streams new ones
+    Tail(TailArgs),
+    /// Rows inserted, updated, or deleted since a checkpoint, as JSON lines, for export and sync
+    Changes(ChangesArgs),
+    /// Generate LLM narratives for past episodes (e.g. after enabling summarization)
+    Summarize(SummarizeArgs),
+    /// Secret filter tools: review and restore quarantined redactions
+    Filter(FilterArgs),
+    /// Correct episode boundaries: merge over-split episodes or split merged ones
+    Episode(EpisodeArgs),
+    /// Heat-ranked unresolved reads and recurring errors; `queue <id>` makes one a dispatcher task
+    Revisit(RevisitArgs),
+    /// Learned entries from old summaries that may no longer hold, stalest first
+    Stale(StaleArgs),
+    /// Run read-only SQL with the scoring functions registered; rows as JSON lines
+    Query(QueryArgs),
+    /// Replay a scripted hook scenario against a throwaway database and check the outcome
+    Simulate(SimulateArgs),
+    /// Stop capturing (all projects, or one) until `nmem resume` or --for elapses
+    Pause(PauseArgs),
+    /// Lift capture pauses
+    Resume(ResumeArgs),
+    /// Keep a file or topic out of injected context and file_history (clean-room work); lists active rules
+    Suppress(SuppressArgs),
+    /// Print the version; --check asks the release endpoint for a newer one
+    Version(VersionArgs),
+    /// Download the latest release, verify its checksum, and replace this binary
+    SelfUpdate(SelfUpdateArgs),
+}
+
+#[derive(Parser)]
+pub struct RecordArgs {
+    /// Record a progress marker for a long-running command: stdin is
+    /// `{"session_id", "message"?, "tool_use_id"?, "cwd"?}`
+    #[arg(long)]
+    pub heartbeat: bool,
+}
+
+#[derive(Parser)]
+pub struct ServeArgs {
+    /// Small-machine mode: single-threaded runtime, no metrics export,
+    /// scoring functions registered on first use, smaller caches
+    /// (also `[serve] lean = true`)
+    #[arg(long)]
+    pub lean: bool,
+}
+
+#[derive(Parser)]
+pub struct PauseArgs {
+    /// How long: "30m", "2h", "1d" (default: until `nmem resume`)
+    #[arg(long = "for")]
+    pub duration: Option<String>,
+    /// Only this project (default: all)
+    #[arg(long)]
+    pub project: Option<String>,
+}
+
+#[derive(Parser)]
+pub struct ResumeArgs {
+    /// Only this project's pause (default: every pause)
+    #[arg(long)]
+    pub project: Option<String>,
+}
+
+#[derive(Parser)]
+pub struct SuppressArgs {
+    /// Path to hold back: a file, a directory, or a trailing part of either
+    #[arg(long, conflicts_with_all = ["topic", "remove"])]
+    pub file: Option<String>,
+    /// Keywords; items mentioning all of them are held back
+    #[arg(long, conflicts_with = "remove")]
+    pub topic: Option<String>,
+    /// How long: "2h", "1d", "7d" (default: until removed)
+    #[arg(long = "for")]
+    pub duration: Option<String>,
+    /// Only this project (default: all)
+    #[arg(long)]
+    pub project: Option<String>,
+    /// Remove the rule with this id
+    #[arg(long)]
+    pub remove: Option<i64>,
+}
+
+#[derive(Parser)]
+pub struct SimulateArgs {
+    /// Scenario YAML: steps (hook events, waits) and expected outcomes
+    #[arg(long)]
+    pub script: PathBuf,
+    /// Config to validate (default: the one hooks load)
+    #[arg(long)]
+    pub config: Option<PathBuf>,
+    /// Write the database here and keep it, instead of a temp file
+    #[arg(long)]
+    pub keep_db: Option<PathBuf>,
+}
+
+#[derive(Parser)]
+pub struct QueryArgs {
+    /// SQL to run; without it, `;`-terminated statements are read from stdin
+    pub sql: Option<String>,
+    /// Maximum rows per statement
+    #[arg(long, default_value = "200")]
+    pub limit: usize,
+}
+
+#[derive(Parser)]
+pub struct RevisitArgs {
+    #[command(subcommand)]
+    pub command: Option<RevisitCommand>,
+    /// Items to list
+    #[arg(long, default_value = "10")]
+    pub limit: usize,
+    /// Minimum sessions for a pattern to qualify
+    #[arg(long, default_value = "3")]
+    pub threshold: i64,
+    /// Half-life in hours for heat decay
+    #[arg(long, default_value = "168")]
+    pub half_life: f64,
+}
+
+#[derive(Subcommand)]
+pub enum RevisitCommand {
+    /// Queue an item as a dispatcher task, with its evidence in the prompt
+    Queue(RevisitQueueArgs),
+}
+
+#[derive(Parser)]
+pub struct RevisitQueueArgs {
+    /// Item id from `nmem revisit`
+    pub id: String,
+    /// When to run: "now", "5m", "tomorrow", "tonight", or ISO datetime
+    #[arg(long, default_value = "now")]
+    pub after: String,
+    /// Project scope (defaults to the project of most evidence sessions)
+    #[arg(long)]
+    pub project: Option<String>,
+    /// Working directory (defaults to current)
+    #[arg(long)]
+    pub cwd: Option<String>,
+}
+
+#[derive(Parser)]
+pub struct StaleArgs {
+    /// Only this project (default: all)
+    #[arg(long)]
+    pub project: Option<String>,
+    /// Entries to list
+    #[arg(long, default_value = "20")]
+    pub limit: usize,
+    /// Minimum staleness score: 1.0 is ~4 months of age, or ~20 later edits/runs of what the entry names
+    #[arg(long, default_value = "1.0")]
+    pub min_score: f64,
+    /// Print JSON lines instead of a table
+    #[arg(long)]
+    pub json: bool,
+}
+
+#[derive(Parser)]
+pub struct EpisodeArgs {
+    #[command(subcommand)]
+    pub command: EpisodeCommand,
+}
+
+#[derive(Subcommand)]
+pub enum EpisodeCommand {
+    /// Merge two adjacent episodes of the same session into one
+    Merge(EpisodeMergeArgs),
+    /// Split an episode in two, starting the second at a user prompt
+    Split(EpisodeSplitArgs),
+}
+
+#[derive(Parser)]
+pub struct EpisodeMergeArgs {
+    /// Episode (work_unit) id
+    pub first: i64,
+    /// Episode to merge into it
+    pub second: i64,
+}
+
+#[derive(Parser)]
+pub struct EpisodeSplitArgs {
+    /// Episode (work_unit) id
+    pub id: i64,
+    /// User prompt id that starts the second episode
+    #[arg(long)]
+    pub at_prompt: i64,
+}
+
+#[derive(Parser)]
+pub struct FilterArgs {
+    #[command(subcommand)]
+    pub command: FilterCommand,
+}
+
+#[derive(Subcommand)]
+pub enum FilterCommand {
+    /// Originals of redacted records, kept when `[filter] quarantine = true`
+    Quarantine(QuarantineArgs),
+}
+
+#[derive(Parser)]
+pub struct QuarantineArgs {
+    #[command(subcommand)]
+    pub command: QuarantineCommand,
+}
+
+#[derive(Subcommand)]
+pub enum QuarantineCommand {
+    /// Unexpired entries with the snippets that were redacted, as JSON
+    List,
+    /// Put an entry's original text back into its observation or prompt
+    Restore(QuarantineRestoreArgs),
+}
+
+#[derive(Parser)]
+pub struct QuarantineRestoreArgs {
+    /// Quarantine entry id (from `list`)
+    pub id: i64,
+}
+
+#[derive(Parser)]
+pub struct SummarizeArgs {
+    /// Summarize episodes (work_units); currently the only mode
+    #[arg(long, required = true)]
+    pub episodes: bool,
+    /// Only this project
+    #[arg(long)]
+    pub project: Option<String>,
+    /// Skip episodes that already have a narrative
+    #[arg(long)]
+    pub missing_only: bool,
+    /// Episodes per batch
+    #[arg(long, default_value = "10")]
+    pub batch_size: usize,
+    /// Seconds to wait between batches, to go easy on the endpoint
+    #[arg(long, default_value = "5")]
+    pub pause_secs: u64,
+}
+
+#[derive(Parser)]
+pub struct StatusArgs {
+    /// List the sessions with the most captured bytes, by obs_type
+    #[arg(long)]
+    pub sessions: bool,
+    /// Sessions to list with --sessions
+    #[arg(long, default_value = "10")]
+    pub limit: i64,
+}
+
+#[derive(Parser)]
+pub struct TailArgs {
+    /// Only this session
+    #[arg(long)]
+    pub session: Option<String>,
+    /// Keep printing entries as they are recorded
+    #[arg(long, short)]
+    pub follow: bool,
+    /// Recent entries to print first
+    #[arg(long, short = 'n', default_value = "20")]
+    pub lines: i64,
+}
+
+#[derive(Parser)]
+pub struct ChangesArgs {
+    /// Checkpoint: the `seq` of the last change already shipped (0 for the whole changelog)
+    #[arg(long, default_value = "0")]
+    pub since: i64,
+    /// Changes to print at most; continue from the last `seq`
+    #[arg(long, default_value = "10000")]
+    pub limit: i64,
+    /// Keep home paths as recorded instead of applying `[filter] anonymize_paths`
+    #[arg(long)]
+    pub raw_paths: bool,
+}
+
+#[derive(Parser)]
+pub struct ProjectArgs {
+    #[command(subcommand)]
+    pub command: ProjectCommand,
+}
+
+#[derive(Subcommand)]
+pub enum ProjectCommand {
+    /// List projects with session counts, last activity, and archive state
+    List,
+    /// Archive a project: out of cross-project context, learn reports, and
+    /// default search; swept sooner
+    Archive(ProjectNameArgs),
+    /// Restore an archived project
+    Unarchive(ProjectNameArgs),
+}
+
+#[derive(Parser)]
+pub struct ProjectNameArgs {
+    /// Project name
+    pub name: String,
+}
+
+#[derive(Parser)]
+pub struct ConsolidateArgs {
+    /// Only this project (default: all)
+    #[arg(long)]
+    pub project: Option<String>,
+    /// Print the clusters that would be consolidated; no LLM calls
+    #[arg(long)]
+    pub dry_run: bool,
+}
+
+#[derive(Parser)]
+pub struct RollupArgs {
+    /// Only this project (default: all)
+    #[arg(long)]
+    pub project: Option<String>,
+    /// Print the periods that would be rolled up; no LLM calls
+    #[arg(long)]
+    pub dry_run: bool,
+}
+
+#[derive(Parser)]
+#[command(group(ArgGroup::new("category").required(true).args(["llm", "capture", "dirs", "confidence", "models", "near_duplicates"])))]
+pub struct StatsArgs {
+    /// LLM token usage per month and task, against `monthly_token_budget`
+    #[arg(long)]
+    pub llm: bool,
+    /// Captured bytes per session and obs_type, largest sessions first
+    #[arg(long)]
+    pub capture: bool,
+    /// Observations per working directory and obs_type, busiest first
+    #[arg(long)]
+    pub dirs: bool,
+    /// Classifier confidence per dimension: mean, low-confidence count, and
+    /// confidence-weighted label counts
+    #[arg(long)]
+    pub confidence: bool,
+    /// Failures, friction, and error classes per project and model
+    #[arg(long)]
+    pub models: bool,
+    /// Clusters of near-duplicate observations by MinHash similarity:
+    /// how much dedup could drop, per obs_type, and the largest clusters
+    #[arg(long)]
+    pub near_duplicates: bool,
+    /// With --near-duplicates, the estimated similarity that counts as a duplicate
+    #[arg(long, default_value = "0.8", requires = "near_duplicates")]
+    pub min_similarity: f64,
+    /// With --models, only this project
+    #[arg(long, requires = "models")]
+    pub project: Option<String>,
+    /// With --models, only models whose id contains this (e.g. opus)
+    #[arg(long, requires = "models")]
+    pub model: Option<String>,
+    /// With --dirs, only this directory and those below it (relative to the
+    /// current directory)
+    #[arg(long, requires = "dirs")]
+    pub cwd: Option<String>,
+    /// Months to report, counting the current one
+    #[arg(long, default_value = "6")]
+    pub months: i64,
+}
+
+#[derive(Parser)]
+pub struct AuditArgs {
+    #[command(subcommand)]
+    pub command: AuditCommand,
+}
+
+#[derive(Subcommand)]
+pub enum AuditCommand {
+    /// List recent MCP tool calls (newest first)
+    Queries(AuditQueriesArgs),
+    /// Per-tool call stats and which observations get retrieved
+    Usage(AuditUsageArgs),
+    /// Purges, sweeps, and quota evictions (newest first)
+    Deletions(AuditDeletionsArgs),
+}
+
+#[derive(Parser)]
+pub struct BackupArgs {
+    #[command(subcommand)]
+    pub command: BackupCommand,
+}
+
+#[derive(Subcommand)]
+pub enum BackupCommand {
+    /// Restore a snapshot or export, migrate it, and run sanity checks
+    Verify(BackupVerifyArgs),
+}
+
+#[derive(Parser)]
+pub struct BackupVerifyArgs {
+    /// Pre-migration snapshot (`backups/*.db`) or `purge --export-before` file
+    pub snapshot: PathBuf,
+    /// Allowed relative difference from the live store's row counts
+    #[arg(long, default_value = "0.5")]
+    pub tolerance: f64,
+    /// Random observations and prompts to read back
+    #[arg(long, default_value = "20")]
+    pub samples: usize,
+}
+
+#[derive(Parser)]
+pub struct AuditQueriesArgs {
+    /// Only calls to this tool
+    #[arg(long)]
+    pub tool: Option<String>,
+    /// Look back this many days
+    #[arg(long, default_value = "7")]
+    pub days: i64,
+    /// Max rows
+    #[arg(long, default_value = "50")]
+    pub limit: i64,
+}
+
+#[derive(Parser)]
+pub struct AuditDeletionsArgs {
+    /// Only this operation: purge, sweep, or quota
+    #[arg(long)]
+    pub operation: Option<String>,
+    /// Look back this many days
+    #[arg(long, default_value = "90")]
+    pub days: i64,
+    /// Max rows
+    #[arg(long, default_value = "50")]
+    pub limit: i64,
+    /// Check the whole log's signature chain instead of listing entries
+    #[arg(long)]
+    pub verify: bool,
+}
+
+#[derive(Parser)]
+pub struct AuditUsageArgs {
+    /// Look back this many days
+    #[arg(long, default_value = "30")]
+    pub days: i64,
+    /// Max observations in the most-retrieved list
+    #[arg(long, default_value = "20")]
+    pub limit: i64,
+}
+
+#[derive(Parser)]
+pub struct FixturesArgs {
+    #[command(subcommand)]
+    pub command: FixturesCommand,
+}
+
+#[derive(Subcommand)]
+pub enum FixturesCommand {
+    /// Fill a database with realistic synthetic sessions (use a scratch --db)
+    Generate(FixturesGenerateArgs),
+}
+
+#[derive(Parser)]
+pub struct FixturesGenerateArgs {
+    /// Number of sessions
+    #[arg(long, default_value = "200")]
+    pub sessions: usize,
+    /// Total observations, spread evenly across sessions
+    #[arg(long, default_value = "100000")]
+    pub observations: usize,
+    /// Number of projects (max 8)
+    #[arg(long, default_value = "3")]
+    pub projects: usize,
+    /// Spread sessions over this many days, ending now
+    #[arg(long, default_value = "90")]
+    pub days: i64,
+    /// PRNG seed — same seed, same data
+    #[arg(long, default_value = "42")]
+    pub seed: u64,
+    /// Allow writing into a database that already holds real sessions
+    #[arg(long)]
+    pub force: bool,
+}
+
+#[derive(Parser)]
+pub struct IngestArgs {
+    #[command(subcommand)]
+    pub source: IngestSource,
+}
+
+#[derive(Subcommand)]
+pub enum IngestSource {
+    /// Import commands from a bash/zsh history file into operator sessions
+    ShellHistory(ShellHistoryArgs),
+    /// Record a single operator command (called from the shell hook)
+    Command(IngestCommandArgs),
+    /// Index project docs (CLAUDE.md, README.md, docs/) as searchable doc_knowledge
+    Docs(IngestDocsArgs),
+}
+
+#[derive(Parser)]
+pub struct ShellHistoryArgs {
+    /// History file (default: $HISTFILE, then ~/.zsh_history, ~/.bash_history)
+    #[arg(long)]
+    pub file: Option<PathBuf>,
+    /// Only ingest commands newer than this ("2h", "1d", "1w", or Unix timestamp)
+    #[arg(long)]
+    pub since: Option<String>,
+    /// Project name (defaults to cwd-derived)
+    #[arg(long)]
+    pub project: Option<String>,
+}
+
+#[derive(Parser)]
+pub struct IngestCommandArgs {
+    /// Working directory the command ran in (defaults to current)
+    #[arg(long)]
+    pub cwd: Option<String>,
+    /// Exit status of the command
+    #[arg(long, allow_hyphen_values = true)]
+    pub exit_code: Option<i32>,
+    /// The command line
+    #[arg(last = true, required = true)]
+    pub command: Vec<String>,
+}
+
+#[derive(Parser)]
+pub struct IngestDocsArgs {
+    /// Markdown files or directories (default: CLAUDE.md, README.md, docs/)
+    #[arg(long, num_args = 1..)]
+    pub paths: Vec<PathBuf>,
+    /// Project name (defaults to cwd-derived)
+    #[arg(long)]
+    pub project: Option<String>,
+    /// Keep running and re-index files as they change
+    #[arg(long)]
+    pub watch: bool,
 }
 
 #[derive(Parser)]
@@ -75,11 +629,53 @@ pub struct MarkArgs {
     /// Project name (defaults to cwd-derived)
     #[arg(long)]
     pub project: Option<String>,
+    /// Reply to this marker, continuing its thread
+    #[arg(long, value_name = "OBS_ID")]
+    pub reply_to: Option<i64>,
+    /// The thread's status as of this marker (e.g. decided, superseded, done)
+    #[arg(long)]
+    pub status: Option<String>,
+}
+
+#[derive(Parser)]
+pub struct InvestigateArgs {
+    #[command(subcommand)]
+    pub command: InvestigateCommand,
+}
+
+#[derive(Subcommand)]
+pub enum InvestigateCommand {
+    /// Open an investigation; prints its id
+    Start(InvestigateStartArgs),
+    /// Attach the conclusion and the files touched since start; prints JSON
+    Conclude(InvestigateConcludeArgs),
+}
+
+#[derive(Parser)]
+pub struct InvestigateStartArgs {
+    /// The question being investigated
+    pub question: String,
+    /// Project name (defaults to cwd-derived)
+    #[arg(long)]
+    pub project: Option<String>,
+}
+
+#[derive(Parser)]
+pub struct InvestigateConcludeArgs {
+    /// What was found
+    pub conclusion: String,
+    /// Investigation to conclude (default: the project's newest open one)
+    #[arg(long)]
+    pub id: Option<i64>,
+    /// Project name (defaults to cwd-derived)
+    #[arg(long)]
+    pub project: Option<String>,
 }
 
 #[derive(Parser)]
 pub struct BackfillArgs {
-    /// Dimension to backfill: phase, scope, locus, novelty, friction (default: phase)
+    /// Dimension to backfill: phase, scope, locus, novelty, friction, error_class,
+    /// or all (every one but narrative, in dependency order, resumable) (default: phase)
     #[arg(long, default_value = "phase")]
     pub dimension: String,
 
@@ -102,6 +698,16 @@ pub struct BackfillArgs {
     /// Extra notes for the classifier run metadata JSON
     #[arg(long)]
     pub notes: Option<String>,
+
+    /// Re-classify labeled observations whose confidence is below --below
+    /// (or was never stored) instead of unlabeled ones; with --dry-run, list
+    /// them (phase, scope, locus, novelty)
+    #[arg(long)]
+    pub low_confidence_only: bool,
+
+    /// Confidence under which a label counts as low
+    #[arg(long, default_value = "0.6", requires = "low_confidence_only")]
+    pub below: f64,
 }
 
 impl BackfillArgs {
@@ -149,6 +755,43 @@ pub struct PurgeArgs {
     /// Skip confirmation — actually delete
     #[arg(long)]
     pub confirm: bool,
+
+    /// Review matches, select/deselect, then confirm (deletes selected observations only)
+    #[arg(long, conflicts_with = "confirm")]
+    pub interactive: bool,
+
+    /// Write every deleted row to this JSONL file first (restore with `nmem restore`)
+    #[arg(long, value_name = "FILE")]
+    pub export_before: Option<PathBuf>,
+}
+
+#[derive(Parser)]
+pub struct RestoreArgs {
+    /// JSONL file written by `nmem purge --export-before`
+    pub file: PathBuf,
+}
+
+#[derive(Parser)]
+pub struct SuggestArgs {
+    /// What has been typed so far; a trailing space completes the last word
+    #[arg(long, default_value = "")]
+    pub prefix: String,
+
+    /// Project to draw from (default: derived from the working directory)
+    #[arg(long, conflicts_with = "all_projects")]
+    pub project: Option<String>,
+
+    /// Draw from every project
+    #[arg(long)]
+    pub all_projects: bool,
+
+    /// Max completions
+    #[arg(long, default_value = "10")]
+    pub limit: usize,
+
+    /// Print JSON lines with source, count, last use, and score
+    #[arg(long)]
+    pub json: bool,
 }
 
 #[derive(Parser)]
@@ -164,6 +807,14 @@ pub struct SearchArgs {
     #[arg(long = "type")]
     pub obs_type: Option<String>,
 
+    /// Filter MCP calls by server (e.g. context7 for mcp__context7__query-docs)
+    #[arg(long)]
+    pub mcp_server: Option<String>,
+
+    /// Filter MCP calls by tool within the server (e.g. query-docs)
+    #[arg(long)]
+    pub mcp_tool: Option<String>,
+
     /// Maximum results (default 20, max 100)
     #[arg(long, default_value = "20")]
     pub limit: i64,
@@ -179,6 +830,49 @@ pub struct SearchArgs {
     /// Ranking order: "relevance" (BM25 only) or "blended" (BM25 + recency + type weight)
     #[arg(long, default_value = "relevance")]
     pub order_by: String,
+
+    /// Only observations recorded in this directory or below it (relative to
+    /// the current directory), e.g. one crate of a monorepo
+    #[arg(long)]
+    pub cwd: Option<String>,
+
+    /// Include archived projects (always included when --project names one)
+    #[arg(long)]
+    pub include_archived: bool,
+}
+
+#[derive(Parser)]
+pub struct GrepArgs {
+    /// Regular expression (Rust regex syntax), e.g. 'E0(5|6)\d\d'
+    pub pattern: String,
+
+    /// Filter by project name
+    #[arg(long)]
+    pub project: Option<String>,
+
+    /// Filter by observation type (e.g. command, file_edit)
+    #[arg(long = "type")]
+    pub obs_type: Option<String>,
+
+    /// Only scan observations matching this FTS query
+    #[arg(long)]
+    pub fts: Option<String>,
+
+    /// Case-insensitive match
+    #[arg(short, long)]
+    pub ignore_case: bool,
+
+    /// Maximum matches (max 500)
+    #[arg(long, default_value = "20")]
+    pub limit: usize,
+
+    /// Stop after scanning this many observations, newest first
+    #[arg(long, default_value = "100000")]
+    pub max_scan: i64,
+
+    /// Print JSON lines instead of a table
+    #[arg(long)]
+    pub json: bool,
 }
 
 #[derive(Parser)]
@@ -187,11 +881,51 @@ pub struct PinArgs {
     pub id: i64,
 }
 
+#[derive(Parser)]
+pub struct RedactArgs {
+    /// Observation ID
+    pub id: i64,
+
+    /// Why the content is masked; shown in its place
+    #[arg(long, required_unless_present = "restore")]
+    pub reason: Option<String>,
+
+    /// Put back the original of the latest redaction
+    #[arg(long, conflicts_with = "reason")]
+    pub restore: bool,
+}
+
+#[derive(Parser)]
+pub struct AnnotateArgs {
+    /// Observation ID
+    pub id: i64,
+
+    /// Note returned with the observation
+    pub text: String,
+}
+
 #[derive(Parser)]
 pub struct ContextArgs {
     /// Project name (defaults to current directory)
     #[arg(long)]
     pub project: Option<String>,
+
+    /// Preview the injection for a SessionStart source: startup, resume,
+    /// compact, or clear. The project's newest session stands in as current.
+    #[arg(long, default_value = "startup")]
+    pub event: String,
+
+    /// Context as it would have been at this Unix timestamp
+    #[arg(long)]
+    pub before: Option<i64>,
+
+    /// Print to stdout (the default)
+    #[arg(long, conflicts_with = "file")]
+    pub stdout: bool,
+
+    /// Write to this file instead of stdout; empty when there is no context
+    #[arg(long)]
+    pub file: Option<std::path::PathBuf>,
 }
 
 #[derive(Parser)]
@@ -208,6 +942,11 @@ pub struct MaintainArgs {
     #[arg(long)]
     pub sweep: bool,
 
+    /// With --sweep, skip the safety checks: delete from summarized sessions
+    /// without episodes, past the summary, and younger than min_age_hours
+    #[arg(long, requires = "sweep")]
+    pub force: bool,
+
     /// Regenerate all existing session summaries via LLM
     #[arg(long)]
     pub resummarize: bool,
@@ -215,6 +954,23 @@ pub struct MaintainArgs {
     /// Summarize sessions that were missed (ended but never summarized, >= 3 observations)
     #[arg(long)]
     pub catch_up: bool,
+
+    /// Replace the database with its newest pre-migration backup at this
+    /// schema version (the current database is backed up first)
+    #[arg(long, value_name = "VERSION")]
+    pub rollback_to: Option<usize>,
+
+    /// Run sweeps and summarization even in quiet hours or on battery
+    #[arg(long)]
+    pub now: bool,
+
+    /// Suggest indexes for the statements in the slow query log
+    #[arg(long)]
+    pub advise_indexes: bool,
+
+    /// With --advise-indexes, create the suggested indexes
+    #[arg(long, requires = "advise_indexes")]
+    pub apply: bool,
 }
 
 #[derive(Parser)]
@@ -251,6 +1007,18 @@ pub struct DispatchArgs {
     /// tmux session name (default "nmem")
     #[arg(long, default_value = "nmem")]
     pub tmux_session: String,
+
+    /// Always open a new window instead of reusing an idle pane the task's project last ran in
+    #[arg(long)]
+    pub no_affinity: bool,
+
+    /// Don't prepend the project's nmem context to the prompt
+    #[arg(long)]
+    pub no_context: bool,
+
+    /// Dispatch even in quiet hours or on battery
+    #[arg(long)]
+    pub now: bool,
 }
 
 #[derive(Parser)]
@@ -276,4 +1044,31 @@ pub struct LearnArgs {
     /// Half-life in hours for heat decay (default: 168 = 1 week)
     #[arg(long, default_value = "168")]
     pub half_life: f64,
+
+    /// Report format: markdown (written to --output), json, or sarif
+    /// (printed to stdout unless --output is given)
+    #[arg(long, default_value = "markdown")]
+    pub format: String,
+
+    /// Exit non-zero if any pattern is at or above this severity
+    /// (error, warning, note). Overrides `[learn] fail_on`
+    #[arg(long)]
+    pub fail_on: Option<String>,
+}
+
+#[derive(Parser)]
+pub struct VersionArgs {
+    /// Compare with the latest release at `[update] endpoint`
+    #[arg(long)]
+    pub check: bool,
+}
+
+#[derive(Parser)]
+pub struct SelfUpdateArgs {
+    /// Download and verify, but leave the installed binary alone
+    #[arg(long)]
+    pub dry_run: bool,
+    /// Install the release even when it isn't newer
+    #[arg(long)]
+    pub force: bool,
 }
@@ -1,5 +1,5 @@
 use crate::config::load_config;
-use crate::schema::MIGRATIONS;
+use crate::s3_importance::ImportanceModel;
 use crate::NmemError;
 use rusqlite::Connection;
 use std::path::Path;
@@ -81,6 +81,37 @@ fn load_or_create_key() -> Result<String, NmemError> {
     Ok(key)
 }
 
+/// Key for per-secret redaction tags: {install_dir}/redaction.key, created on
+/// first use. Separate from the database key so tags stay stable if the
+/// database is re-keyed. None when the file can't be read or written, in
+/// which case secrets get the plain `[REDACTED]` placeholder.
+pub fn load_or_create_redaction_key() -> Option<Vec<u8>> {
+    static KEY: std::sync::OnceLock<Option<Vec<u8>>> = std::sync::OnceLock::new();
+    KEY.get_or_init(|| match load_or_create_side_key("redaction.key") {
+        Ok(key) => Some(key.into_bytes()),
+        Err(e) => {
+            log::debug!("redaction key unavailable: {e}");
+            None
+        }
+    })
+    .clone()
+}
+
+/// Hex key in {install_dir}/`file_name`, generated and written (0600) on
+/// first use. For secondary stores that must not share the database key.
+pub(crate) fn load_or_create_side_key(file_name: &str) -> Result<String, NmemError> {
+    let path = crate::install_dir().join(file_name);
+    if let Ok(k) = std::fs::read_to_string(&path) {
+        let trimmed = k.trim();
+        if !trimmed.is_empty() {
+            return Ok(trimmed.to_string());
+        }
+    }
+    let key = generate_random_key()?;
+    write_key_file(&path, &key)?;
+    Ok(key)
+}
+
 fn default_key_path() -> std::path::PathBuf {
     crate::install_dir().join("nmem.key")
 }
@@ -116,12 +147,11 @@ fn write_key_file(path: &std::path::Path, key: &str) -> Result<(), NmemError> {
 
 /// Apply encryption key to a connection. MUST be the first statement.
 /// Uses raw hex key format (x'...') to skip PBKDF2.
-fn apply_key(conn: &Connection, key: &str) -> Result<(), NmemError> {
+pub(crate) fn apply_key(conn: &Connection, key: &str) -> Result<(), NmemError> {
     let pragma_value = format!("x'{key}'");
     conn.pragma_update(None, "key", &pragma_value)?;
-    // Verify key works
-    conn.query_row("SELECT count(*) FROM sqlite_master", [], |_| Ok(()))
-        .map_err(|_| NmemError::Config("wrong encryption key or corrupt database".into()))?;
+    // Verify key works; a wrong key reads as "file is not a database"
+    conn.query_row("SELECT count(*) FROM sqlite_master", [], |_| Ok(()))?;
     Ok(())
 }
 
@@ -142,6 +172,11 @@ pub fn is_db_encrypted(db_path: &Path) -> bool {
     }
 }
 
+/// `[diagnostics] slow_query_ms`, defaulted when the config doesn't load.
+fn slow_query_ms() -> u64 {
+    load_config().unwrap_or_default().diagnostics.slow_query_ms
+}
+
 /// Apply standard PRAGMAs (after key, before migrations).
 fn apply_pragmas(conn: &Connection, readonly: bool) -> Result<(), NmemError> {
     conn.pragma_update(None, "journal_mode", "WAL")?;
@@ -158,11 +193,12 @@ fn apply_pragmas(conn: &Connection, readonly: bool) -> Result<(), NmemError> {
 // --- Public open functions ---
 
 pub fn open_db_readonly(db_path: &Path) -> Result<Connection, NmemError> {
+    open_readonly(db_path).map_err(|e| e.context_path(crate::diagnostic::OPEN_DB, db_path))
+}
+
+fn open_readonly(db_path: &Path) -> Result<Connection, NmemError> {
     if !db_path.exists() {
-        return Err(NmemError::Config(format!(
-            "database not found: {}",
-            db_path.display()
-        )));
+        return Err(NmemError::Io(std::io::Error::new(std::io::ErrorKind::NotFound, "database not found")));
     }
 
     let conn = Connection::open_with_flags(
@@ -175,10 +211,15 @@ pub fn open_db_readonly(db_path: &Path) -> Result<Connection, NmemError> {
     }
 
     apply_pragmas(&conn, true)?;
+    crate::s3_slow_query::instrument(&conn, slow_query_ms());
     Ok(conn)
 }
 
 pub fn open_db(db_path: &Path) -> Result<Connection, NmemError> {
+    open(db_path).map_err(|e| e.context_path(crate::diagnostic::OPEN_DB, db_path))
+}
+
+fn open(db_path: &Path) -> Result<Connection, NmemError> {
     ensure_secure_permissions(db_path)?;
 
     let is_new = !db_path.exists();
@@ -204,7 +245,11 @@ pub fn open_db(db_path: &Path) -> Result<Connection, NmemError> {
     }
 
     apply_pragmas(&conn, false)?;
-    MIGRATIONS.to_latest(&mut conn)?;
+    let config = load_config().ok();
+    let keep_backups = config.as_ref().map(|c| c.migration.keep_backups).unwrap_or(crate::s5_config::DEFAULT_KEEP_BACKUPS);
+    crate::s3_migrate::migrate(&mut conn, db_path, keep_backups).map_err(|e| e.context(crate::diagnostic::MIGRATE))?;
+    // After migrating, so schema changes aren't logged as slow queries
+    crate::s3_slow_query::instrument(&conn, config.map(|c| c.diagnostics.slow_query_ms).unwrap_or_else(slow_query_ms));
 
     // Set file permissions after DB creation
     #[cfg(unix)]
@@ -257,9 +302,9 @@ fn migrate_to_encrypted(db_path: &Path, key: &str) -> Result<(), NmemError> {
 
 // --- BUSY retry ---
 
-/// Check if an NmemError wraps a SQLite BUSY error.
+/// Check if an NmemError wraps a SQLite BUSY error, under any context.
 fn is_busy(e: &NmemError) -> bool {
-    if let NmemError::Database(re) = e {
+    if let NmemError::Database(re) = e.root() {
         matches!(re, rusqlite::Error::SqliteFailure(ffi, _)
             if ffi.code == rusqlite::ffi::ErrorCode::DatabaseBusy)
     } else {
@@ -291,21 +336,112 @@ where
 
 // --- UDF registration ---
 
-/// Register custom SQL functions (exp_decay) needed by composite scoring queries.
-pub fn register_udfs(conn: &Connection) -> rusqlite::Result<()> {
-    conn.create_scalar_function(
-        "exp_decay",
-        2,
-        rusqlite::functions::FunctionFlags::SQLITE_DETERMINISTIC,
-        |ctx| {
-            let age: f64 = ctx.get(0)?;
-            let half_life: f64 = ctx.get(1)?;
-            if half_life <= 0.0 {
-                return Ok(0.0f64);
-            }
-            Ok((-std::f64::consts::LN_2 * age / half_life).exp())
+/// A token list for `jaccard()`: a JSON array of strings, or whitespace-
+/// separated text. Tokens are lowercased.
+fn udf_tokens(value: Option<String>) -> Vec<String> {
+    let Some(value) = value else {
+        return Vec::new();
+    };
+    match serde_json::from_str::<Vec<String>>(&value) {
+        Ok(list) => list.into_iter().map(|t| t.to_lowercase()).collect(),
+        Err(_) => value.split_whitespace().map(str::to_lowercase).collect(),
+    }
+}
+
+/// Bucket width in seconds for `time_bucket()`: a count of seconds, or
+/// `hour`, `day`, `week`.
+fn udf_bucket_width(value: rusqlite::types::ValueRef<'_>) -> Option<i64> {
+    use rusqlite::types::ValueRef;
+    match value {
+        ValueRef::Integer(n) => Some(n),
+        ValueRef::Real(f) => Some(f as i64),
+        ValueRef::Text(t) => match std::str::from_utf8(t).ok()?.trim() {
+            "minute" => Some(60),
+            "hour" => Some(3600),
+            "day" => Some(86400),
+            "week" => Some(604800),
+            other => other.parse().ok(),
         },
-    )
+        _ => None,
+    }
+    .filter(|w| *w > 0)
+}
+
+/// Register the scoring SQL functions with the default importance model.
+pub fn register_udfs(conn: &Connection) -> rusqlite::Result<()> {
+    register_udfs_with(conn, ImportanceModel::default())
+}
+
+/// Register the scoring SQL functions used by composite ranking queries and
+/// available to user SQL (`nmem query`):
+///
+/// - `exp_decay(age, half_life)` — 1.0 at age 0, halving every `half_life`
+/// - `bm25_norm(rank, min_rank, max_rank)` — FTS5 rank scaled to 0–1, best = 1
+/// - `type_weight(obs_type)` — `[scoring] type_weights`, else the defaults
+/// - `search_score(bm25_norm, recency, obs_type, is_pinned)` and
+///   `recent_score(recency, obs_type, is_pinned, in_project)` — the
+///   importance model's blends (see `s3_importance`); `in_project` NULL
+///   when there is no project to match
+/// - `jaccard(a, b)` — overlap of two token lists (JSON arrays or plain text)
+/// - `time_bucket(ts, width)` — start of the bucket holding unix time `ts`;
+///   `width` in seconds or `minute`/`hour`/`day`/`week`
+pub fn register_udfs_with(conn: &Connection, model: ImportanceModel) -> rusqlite::Result<()> {
+    use rusqlite::functions::FunctionFlags;
+    let flags = FunctionFlags::SQLITE_UTF8 | FunctionFlags::SQLITE_DETERMINISTIC;
+
+    conn.create_scalar_function("exp_decay", 2, flags, |ctx| {
+        let age: f64 = ctx.get(0)?;
+        let half_life: f64 = ctx.get(1)?;
+        if half_life <= 0.0 {
+            return Ok(0.0f64);
+        }
+        Ok((-std::f64::consts::LN_2 * age / half_life).exp())
+    })?;
+
+    // FTS5 rank is negative, lower is better
+    conn.create_scalar_function("bm25_norm", 3, flags, |ctx| {
+        let rank: f64 = ctx.get(0)?;
+        let min_rank: f64 = ctx.get(1)?;
+        let max_rank: f64 = ctx.get(2)?;
+        if max_rank == min_rank {
+            return Ok(1.0f64);
+        }
+        Ok((rank - max_rank) / (min_rank - max_rank))
+    })?;
+
+    let model = std::sync::Arc::new(model);
+    let m = model.clone();
+    conn.create_scalar_function("type_weight", 1, flags, move |ctx| {
+        let obs_type: Option<String> = ctx.get(0)?;
+        Ok(m.types.get(obs_type.as_deref()))
+    })?;
+
+    let m = model.clone();
+    conn.create_scalar_function("search_score", 4, flags, move |ctx| {
+        let obs_type: Option<String> = ctx.get(2)?;
+        Ok(m.search_score(ctx.get(0)?, ctx.get(1)?, obs_type.as_deref(), ctx.get::<Option<bool>>(3)?.unwrap_or(false)))
+    })?;
+
+    conn.create_scalar_function("recent_score", 4, flags, move |ctx| {
+        let obs_type: Option<String> = ctx.get(1)?;
+        let pinned = ctx.get::<Option<bool>>(2)?.unwrap_or(false);
+        Ok(model.recent_score(ctx.get(0)?, obs_type.as_deref(), pinned, ctx.get(3)?))
+    })?;
+
+    conn.create_scalar_function("jaccard", 2, flags, |ctx| {
+        let a = udf_tokens(ctx.get(0)?);
+        let b = udf_tokens(ctx.get(1)?);
+        Ok(crate::s3_learn::jaccard(&a, &b))
+    })?;
+
+    conn.create_scalar_function("time_bucket", 2, flags, |ctx| {
+        let ts: Option<i64> = ctx.get(0)?;
+        let width = udf_bucket_width(ctx.get_raw(1));
+        Ok(match (ts, width) {
+            (Some(ts), Some(width)) => Some(ts - ts.rem_euclid(width)),
+            _ => None,
+        })
+    })
 }
 
 // --- Encrypt subcommand ---
@@ -351,6 +487,32 @@ mod tests {
         assert!(key.chars().all(|c| c.is_ascii_hexdigit()));
     }
 
+    #[test]
+    fn scoring_udfs() {
+        let conn = Connection::open_in_memory().unwrap();
+        let mut model = ImportanceModel::default();
+        model.types.weights.insert("file_read".into(), 0.4);
+        register_udfs_with(&conn, model).unwrap();
+        let row = |sql: &str| -> Option<f64> { conn.query_row(sql, [], |r| r.get(0)).unwrap() };
+
+        assert_eq!(row("SELECT exp_decay(7.0, 7.0)"), Some(0.5));
+        assert_eq!(row("SELECT bm25_norm(-10.0, -10.0, -2.0)"), Some(1.0));
+        assert_eq!(row("SELECT bm25_norm(-2.0, -10.0, -2.0)"), Some(0.0));
+        assert_eq!(row("SELECT bm25_norm(-3.0, -3.0, -3.0)"), Some(1.0));
+        assert_eq!(row("SELECT type_weight('file_read')"), Some(0.4));
+        assert_eq!(row("SELECT type_weight('file_edit')"), Some(1.0));
+        assert_eq!(row("SELECT type_weight(NULL)"), Some(0.17));
+        assert_eq!(row("SELECT search_score(1.0, 1.0, 'file_edit', 0)"), Some(1.0));
+        assert_eq!(row("SELECT recent_score(1.0, 'file_edit', 1, NULL)"), Some(1.0));
+        assert_eq!(row("SELECT round(recent_score(1.0, 'file_edit', 0, 0), 2)"), Some(0.86));
+        assert_eq!(row("SELECT jaccard('[\"Auth\",\"login\"]', 'auth token')"), Some(1.0 / 3.0));
+        assert_eq!(row("SELECT jaccard(NULL, NULL)"), Some(0.0));
+        assert_eq!(row("SELECT time_bucket(90061, 'day')"), Some(86400.0));
+        assert_eq!(row("SELECT time_bucket(90061, 3600)"), Some(90000.0));
+        assert_eq!(row("SELECT time_bucket(-1, 'hour')"), Some(-3600.0));
+        assert_eq!(row("SELECT time_bucket(90061, 'fortnight')"), None);
+    }
+
     #[test]
     fn apply_key_to_in_memory_db() {
         let conn = Connection::open_in_memory().unwrap();
@@ -413,7 +575,7 @@ mod tests {
 
         // Conn A: hold an exclusive write transaction
         let conn_a = Connection::open(&db_path).unwrap();
-        conn_a.pragma_update(None, "busy_timeout", &0).unwrap();
+        conn_a.pragma_update(None, "busy_timeout", 0).unwrap();
         conn_a.execute_batch("BEGIN IMMEDIATE").unwrap();
         conn_a.execute("UPDATE test SET val = 'from_a'", []).unwrap();
         // Transaction is open — conn_a holds the write lock
@@ -436,7 +598,7 @@ mod tests {
 
         // Better approach: hold the lock on a thread, release after delay
         let conn_blocker = Connection::open(&db_path).unwrap();
-        conn_blocker.pragma_update(None, "busy_timeout", &0).unwrap();
+        conn_blocker.pragma_update(None, "busy_timeout", 0).unwrap();
         conn_blocker.execute_batch("BEGIN IMMEDIATE").unwrap();
         conn_blocker.execute("UPDATE test SET val = 'blocking'", []).unwrap();
 
@@ -450,7 +612,7 @@ mod tests {
         let db_for_retry = db_path.clone();
         let result = retry_on_busy(|| {
             let conn = Connection::open(&db_for_retry).map_err(NmemError::Database)?;
-            conn.pragma_update(None, "busy_timeout", &0).map_err(NmemError::Database)?;
+            conn.pragma_update(None, "busy_timeout", 0).map_err(NmemError::Database)?;
             conn.execute("UPDATE test SET val = 'from_retry'", [])
                 .map_err(NmemError::Database)?;
             Ok(())
@@ -482,7 +644,7 @@ mod tests {
 
         // Hold the lock for the entire test
         let blocker = Connection::open(&db_path).unwrap();
-        blocker.pragma_update(None, "busy_timeout", &0).unwrap();
+        blocker.pragma_update(None, "busy_timeout", 0).unwrap();
         blocker.execute_batch("BEGIN IMMEDIATE").unwrap();
         blocker.execute("UPDATE test SET val = 'blocked'", []).unwrap();
 
@@ -496,7 +658,7 @@ mod tests {
             let mut last_err = None;
             for attempt in 0..=retries {
                 let conn = Connection::open(&db_for_retry).map_err(NmemError::Database).unwrap();
-                conn.pragma_update(None, "busy_timeout", &0).map_err(NmemError::Database).unwrap();
+                conn.pragma_update(None, "busy_timeout", 0).map_err(NmemError::Database).unwrap();
                 match conn.execute("UPDATE test SET val = 'retry'", []) {
                     Ok(_) => { last_err = None; break; }
                     Err(e) => {
@@ -0,0 +1,263 @@
+//! Diagnostics for failed commands and tool calls.
+//!
+//! `NmemError` prints as a terse chain ("database: disk I/O error"). A
+//! `Diagnostic` adds what a user needs to act on it: a stable code, the
+//! operation and file involved (from `NmemError::Context`), and a
+//! remediation hint. The CLI prints it on exit; the MCP server attaches it
+//! to error responses as `data`.
+//!
+//! | code | cause |
+//! |---|---|
+//! | `db_not_found` | no database at the path |
+//! | `db_key_missing` | database is encrypted, no key found |
+//! | `db_key_mismatch` | key doesn't open the database, or it isn't one |
+//! | `db_busy` | another process holds the lock |
+//! | `db_corrupt` | SQLite reports corruption |
+//! | `db_readonly` | file or directory not writable |
+//! | `db_cannot_open` | SQLite can't open the path |
+//! | `disk_full` | no space left |
+//! | `disk_io` | I/O error under SQLite |
+//! | `migration_failed` | schema migration failed or the store is newer |
+//! | `config_invalid` | config file doesn't parse or validate |
+//! | `permission_denied`, `not_found` | filesystem errors |
+//! | `invalid_json`, `nats`, `database`, `io`, `invalid_input` | everything else, by kind |
+
+use crate::NmemError;
+use rusqlite::ffi::ErrorCode;
+use serde::Serialize;
+use std::path::PathBuf;
+
+/// Operations `NmemError::Context` is attached with that change the diagnosis.
+pub const OPEN_DB: &str = "open database";
+pub const MIGRATE: &str = "migrate schema";
+pub const LOAD_CONFIG: &str = "load config";
+
+#[derive(Debug, Clone, Serialize, PartialEq)]
+pub struct Diagnostic {
+    pub code: &'static str,
+    /// The underlying error.
+    pub message: String,
+    /// What was being attempted, outermost first.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub operation: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub path: Option<PathBuf>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub hint: Option<String>,
+}
+
+impl std::fmt::Display for Diagnostic {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "[{}] ", self.code)?;
+        if let Some(op) = &self.operation {
+            write!(f, "{op}")?;
+            if let Some(path) = &self.path {
+                write!(f, " ({})", path.display())?;
+            }
+            write!(f, ": ")?;
+        }
+        write!(f, "{}", self.message)?;
+        if let Some(hint) = &self.hint {
+            write!(f, "\n  hint: {hint}")?;
+        }
+        Ok(())
+    }
+}
+
+/// Diagnose `err`. `key_configured` says whether an encryption key was
+/// found (`db::load_key`), which decides between the two key hints.
+pub fn diagnose_with(err: &NmemError, key_configured: bool) -> Diagnostic {
+    let mut ops = Vec::new();
+    let mut path = None;
+    let mut cur = err;
+    while let NmemError::Context { op, path: p, source } = cur {
+        ops.push(op.as_str());
+        if path.is_none() {
+            path = p.clone();
+        }
+        cur = source;
+    }
+    let operation = (!ops.is_empty()).then(|| ops.join(": "));
+    let migrating = ops.contains(&MIGRATE);
+    let (code, hint) = match cur {
+        NmemError::Database(e) => classify_sqlite(e, key_configured, migrating),
+        other => classify(other, &ops),
+    };
+    Diagnostic { code, message: cur.to_string(), operation, path, hint: hint.map(str::to_string) }
+}
+
+/// Diagnose `err`, looking up whether an encryption key is configured when
+/// that decides the hint.
+pub fn diagnose(err: &NmemError) -> Diagnostic {
+    let key_configured = matches!(err.root(), NmemError::Database(e) if is_not_a_database(e)) && crate::db::load_key().is_some();
+    diagnose_with(err, key_configured)
+}
+
+/// Errors the MCP server can attach a `Diagnostic` to.
+pub trait Diagnose {
+    fn diagnostic(&self) -> Option<Diagnostic>;
+}
+
+impl Diagnose for NmemError {
+    fn diagnostic(&self) -> Option<Diagnostic> {
+        Some(diagnose(self))
+    }
+}
+
+impl Diagnose for rusqlite::Error {
+    fn diagnostic(&self) -> Option<Diagnostic> {
+        let key_configured = is_not_a_database(self) && crate::db::load_key().is_some();
+        let (code, hint) = classify_sqlite(self, key_configured, false);
+        Some(Diagnostic {
+            code,
+            message: format!("database: {self}"),
+            operation: None,
+            path: None,
+            hint: hint.map(str::to_string),
+        })
+    }
+}
+
+impl Diagnose for std::io::Error {
+    fn diagnostic(&self) -> Option<Diagnostic> {
+        let (code, hint) = classify_io(self, &[]);
+        Some(Diagnostic { code, message: format!("io: {self}"), operation: None, path: None, hint: hint.map(str::to_string) })
+    }
+}
+
+impl Diagnose for serde_json::Error {
+    fn diagnostic(&self) -> Option<Diagnostic> {
+        None
+    }
+}
+
+impl<T> Diagnose for std::sync::PoisonError<T> {
+    fn diagnostic(&self) -> Option<Diagnostic> {
+        None
+    }
+}
+
+fn is_not_a_database(e: &rusqlite::Error) -> bool {
+    matches!(e, rusqlite::Error::SqliteFailure(ffi, _) if ffi.code == ErrorCode::NotADatabase)
+}
+
+fn classify_sqlite(e: &rusqlite::Error, key_configured: bool, migrating: bool) -> (&'static str, Option<&'static str>) {
+    let rusqlite::Error::SqliteFailure(ffi, _) = e else {
+        return if migrating { ("migration_failed", Some(MIGRATION_HINT)) } else { ("database", None) };
+    };
+    match ffi.code {
+        ErrorCode::NotADatabase if key_configured => (
+            "db_key_mismatch",
+            Some("the key (NMEM_KEY, [encryption] key_file, or nmem.key) doesn't open this database, or the file is damaged; restore one from the backups directory with `nmem maintain --rollback-to <version>`"),
+        ),
+        ErrorCode::NotADatabase => (
+            "db_key_missing",
+            Some("the database is encrypted but no key was found; set NMEM_KEY or [encryption] key_file to the key it was created with (see `nmem encrypt --help`)"),
+        ),
+        ErrorCode::DatabaseBusy | ErrorCode::DatabaseLocked => (
+            "db_busy",
+            Some("another nmem process (serve, maintain, a hook) is holding the lock; retry in a moment"),
+        ),
+        ErrorCode::DatabaseCorrupt => (
+            "db_corrupt",
+            Some("restore a backup with `nmem maintain --rollback-to <version>`, or recover with `sqlite3 nmem.db .recover`"),
+        ),
+        ErrorCode::ReadOnly => (
+            "db_readonly",
+            Some("check write permission on the database file, its -wal/-shm files, and their directory"),
+        ),
+        ErrorCode::CannotOpen => (
+            "db_cannot_open",
+            Some("check the path (--db, NMEM_DB) exists and its directory is accessible"),
+        ),
+        ErrorCode::DiskFull => ("disk_full", Some(DISK_FULL_HINT)),
+        ErrorCode::SystemIoFailure => (
+            "disk_io",
+            Some("check free space and the filesystem holding the database; a network or full disk is the usual cause"),
+        ),
+        _ if migrating => ("migration_failed", Some(MIGRATION_HINT)),
+        _ => ("database", None),
+    }
+}
+
+fn classify(err: &NmemError, ops: &[&str]) -> (&'static str, Option<&'static str>) {
+    let migrating = ops.contains(&MIGRATE);
+    match err {
+        NmemError::Io(e) => classify_io(e, ops),
+        NmemError::Json(_) => ("invalid_json", None),
+        NmemError::Nats(_) => ("nats", Some("check [beacon] nats_url and that the NATS server is reachable")),
+        NmemError::Config(_) if migrating => ("migration_failed", Some(MIGRATION_HINT)),
+        NmemError::Config(_) if ops.contains(&LOAD_CONFIG) => (
+            "config_invalid",
+            Some("fix the file, or point NMEM_CONFIG at another one; the README lists every key"),
+        ),
+        NmemError::Config(_) => ("invalid_input", None),
+        // Unwrapped by the caller
+        NmemError::Database(_) | NmemError::Context { .. } => ("database", None),
+    }
+}
+
+fn classify_io(e: &std::io::Error, ops: &[&str]) -> (&'static str, Option<&'static str>) {
+    let migrating = ops.contains(&MIGRATE);
+    match e.kind() {
+        std::io::ErrorKind::NotFound if migrating => ("migration_failed", Some(MIGRATION_HINT)),
+        std::io::ErrorKind::NotFound if ops.contains(&OPEN_DB) => (
+            "db_not_found",
+            Some("nothing recorded yet at this path; install the hooks and start a Claude Code session, or pass --db"),
+        ),
+        std::io::ErrorKind::NotFound => ("not_found", None),
+        std::io::ErrorKind::PermissionDenied => (
+            "permission_denied",
+            Some("check ownership and permissions of the file and its directory (nmem keeps its files at 0600)"),
+        ),
+        std::io::ErrorKind::StorageFull => ("disk_full", Some(DISK_FULL_HINT)),
+        _ => ("io", None),
+    }
+}
+
+const DISK_FULL_HINT: &str = "free disk space, or shorten [retention] days and run `nmem maintain --sweep`";
+const MIGRATION_HINT: &str = "the store may come from a newer nmem — upgrade the binary — or a migration failed; `nmem maintain --rollback-to <version>` restores a pre-migration backup";
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sqlite(code: i32) -> NmemError {
+        NmemError::Database(rusqlite::Error::SqliteFailure(rusqlite::ffi::Error::new(code), None))
+    }
+
+    #[test]
+    fn encrypted_database_hints_depend_on_the_key() {
+        let err = sqlite(rusqlite::ffi::SQLITE_NOTADB).context_path(OPEN_DB, std::path::Path::new("/x/nmem.db"));
+        let missing = diagnose_with(&err, false);
+        assert_eq!(missing.code, "db_key_missing");
+        assert_eq!(missing.operation.as_deref(), Some("open database"));
+        assert!(missing.hint.as_deref().unwrap().contains("NMEM_KEY"));
+        assert_eq!(diagnose_with(&err, true).code, "db_key_mismatch");
+
+        let shown = missing.to_string();
+        assert!(shown.starts_with("[db_key_missing] open database (/x/nmem.db): database: "), "{shown}");
+        assert!(shown.contains("\n  hint: "));
+    }
+
+    #[test]
+    fn codes_follow_the_root_cause() {
+        assert_eq!(diagnose_with(&sqlite(rusqlite::ffi::SQLITE_BUSY), false).code, "db_busy");
+        assert_eq!(diagnose_with(&sqlite(rusqlite::ffi::SQLITE_IOERR), false).code, "disk_io");
+        assert_eq!(diagnose_with(&sqlite(rusqlite::ffi::SQLITE_FULL), false).code, "disk_full");
+
+        let missing = NmemError::Io(std::io::Error::new(std::io::ErrorKind::NotFound, "database not found"))
+            .context_path(OPEN_DB, std::path::Path::new("/x/nmem.db"));
+        assert_eq!(diagnose_with(&missing, false).code, "db_not_found");
+
+        let newer = NmemError::Config("migration: database too far ahead".into()).context(MIGRATE);
+        assert_eq!(diagnose_with(&newer, false).code, "migration_failed");
+
+        let toml = NmemError::Config("expected `=`".into()).context_path(LOAD_CONFIG, std::path::Path::new("/c.toml"));
+        assert_eq!(diagnose_with(&toml, false).code, "config_invalid");
+
+        let plain = diagnose_with(&NmemError::Config("bad --after".into()), false);
+        assert_eq!(plain.to_string(), "[invalid_input] config: bad --after");
+        assert_eq!((plain.code, plain.hint, plain.operation), ("invalid_input", None, None));
+    }
+}
@@ -0,0 +1,370 @@
+//! Synthetic database fixtures — realistic sessions without private data.
+//!
+//! Generates projects, sessions, prompts, observations (with classifier labels
+//! and failures), episodes, and summaries shaped like real nmem data, from a
+//! seeded PRNG so the same spec always yields the same database. Used for
+//! benchmarks, demos, and reproducing ranking issues (`nmem fixtures generate`).
+
+#[cfg(feature = "cli")]
+use crate::cli::{FixturesArgs, FixturesCommand};
+use crate::NmemError;
+use rusqlite::{Connection, params};
+use std::path::Path;
+
+/// Session ids carry this prefix so fixtures are recognizable in any DB.
+pub const SESSION_PREFIX: &str = "fixture-";
+
+pub struct FixtureSpec {
+    pub sessions: usize,
+    pub observations: usize,
+    pub projects: usize,
+    /// Sessions are spread over this many days ending at `now`.
+    pub days: i64,
+    pub seed: u64,
+    pub now: i64,
+}
+
+impl Default for FixtureSpec {
+    fn default() -> Self {
+        Self {
+            sessions: 20,
+            observations: 1000,
+            projects: 3,
+            days: 30,
+            seed: 42,
+            now: std::time::SystemTime::now()
+                .duration_since(std::time::UNIX_EPOCH)
+                .unwrap_or_default()
+                .as_secs() as i64,
+        }
+    }
+}
+
+#[derive(Debug, Default, PartialEq, Eq)]
+pub struct FixtureStats {
+    pub sessions: usize,
+    pub prompts: usize,
+    pub observations: usize,
+    pub failures: usize,
+    pub episodes: usize,
+}
+
+/// SplitMix64 — tiny, deterministic, good enough for fixture data.
+struct Rng(u64);
+
+impl Rng {
+    fn next(&mut self) -> u64 {
+        self.0 = self.0.wrapping_add(0x9E37_79B9_7F4A_7C15);
+        let mut z = self.0;
+        z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+        z ^ (z >> 31)
+    }
+
+    fn below(&mut self, n: usize) -> usize {
+        (self.next() % n.max(1) as u64) as usize
+    }
+
+    fn chance(&mut self, pct: u64) -> bool {
+        self.next() % 100 < pct
+    }
+
+    fn pick<'a, T>(&mut self, items: &'a [T]) -> &'a T {
+        &items[self.below(items.len())]
+    }
+}
+
+const PROJECT_NAMES: &[&str] = &["atlas", "beacon", "cobalt", "delta", "ember", "fjord", "garnet", "harbor"];
+const MODULES: &[&str] = &[
+    "auth", "config", "parser", "router", "storage", "cache", "metrics", "scheduler", "client", "schema",
+];
+const INTENTS: &[&str] = &[
+    "fix token validation in {m}",
+    "add retry logic to {m}",
+    "refactor {m} error handling",
+    "investigate slow queries in {m}",
+    "write tests for {m}",
+    "migrate {m} to the new config format",
+    "debug flaky {m} test",
+    "document the {m} API",
+];
+const SEARCHES: &[&str] = &["TODO", "fn new", "unwrap()", "impl Display", "#[test]", "deprecated"];
+/// (command, failure response) — responses exercise every S2 error class.
+const COMMANDS: &[(&str, &str)] = &[
+    ("cargo build", "error[E0308]: mismatched types\nerror: could not compile `app`"),
+    ("cargo test", "thread 'tests::it_works' panicked at src/lib.rs:42:9\ntest result: FAILED. 11 passed; 1 failed"),
+    ("cargo clippy -- -D warnings", "error: unused variable: `x`"),
+    ("curl -s http://localhost:8080/health", "curl: (7) Failed to connect to localhost port 8080: Connection refused"),
+    ("python scripts/report.py", "ModuleNotFoundError: No module named 'pandas'"),
+    ("cat /var/log/app.log", "cat: /var/log/app.log: Permission denied"),
+    ("make lint", "make: *** [lint] Error 2"),
+];
+const LEARNED: &[&str] = &[
+    "{m} reads config lazily — tests must set env before first access",
+    "the {m} cache key must include the project name",
+    "{m} errors were swallowed by a blanket map_err",
+    "retries in {m} need jitter to avoid thundering herd",
+];
+
+fn fill(template: &str, module: &str) -> String {
+    template.replace("{m}", module)
+}
+
+/// Populate `conn` (already migrated) with synthetic data.
+pub fn generate(conn: &Connection, spec: &FixtureSpec) -> Result<FixtureStats, NmemError> {
+    let mut rng = Rng(spec.seed);
+    let mut stats = FixtureStats::default();
+    let sessions = spec.sessions.max(1);
+    let projects: Vec<String> = (0..spec.projects.clamp(1, PROJECT_NAMES.len()))
+        .map(|i| PROJECT_NAMES[i].to_string())
+        .collect();
+    let span = spec.days.max(1) * 86400;
+
+    let tx = conn.unchecked_transaction()?;
+    {
+        let mut ins_session = tx.prepare("INSERT INTO sessions (id, project, started_at) VALUES (?1, ?2, ?3)")?;
+        let mut end_session = tx.prepare("UPDATE sessions SET ended_at = ?2, signature = ?3, summary = ?4 WHERE id = ?1")?;
+        let mut ins_prompt = tx.prepare(
+            "INSERT INTO prompts (session_id, timestamp, source, content) VALUES (?1, ?2, 'user', ?3)",
+        )?;
+        let mut ins_obs = tx.prepare(
+            "INSERT INTO observations (session_id, prompt_id, timestamp, obs_type, source_event, tool_name, file_path,
+                                       content, metadata, phase, scope, locus, novelty, friction, error_class)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12, ?13, ?14, ?15)",
+        )?;
+        let mut ins_episode = tx.prepare(
+            "INSERT INTO work_units (session_id, started_at, ended_at, intent, first_prompt_id, last_prompt_id,
+                                     hot_files, phase_signature, obs_count, summary)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10)",
+        )?;
+
+        for s in 0..sessions {
+            // Even share of the observation budget, remainder to the earliest sessions
+            let budget = spec.observations / sessions + usize::from(s < spec.observations % sessions);
+            let project = rng.pick(&projects).clone();
+            let session_id = format!("{SESSION_PREFIX}{s:05}");
+            let started = spec.now - span + (span * s as i64) / sessions as i64 + rng.below(600) as i64;
+            let cwd = format!("/home/dev/src/{project}");
+            ins_session.execute(params![session_id, project, started])?;
+
+            let episodes = 1 + rng.below(3).min(budget / 10);
+            let mut ts = started;
+            let mut remaining = budget;
+            let mut edited: Vec<String> = Vec::new();
+            let mut learned: Vec<String> = Vec::new();
+            let mut type_counts: std::collections::BTreeMap<&str, i64> = Default::default();
+            let mut episode_rows = Vec::new();
+
+            for e in 0..episodes {
+                let module = *rng.pick(MODULES);
+                let intent = fill(rng.pick::<&str>(INTENTS), module);
+                ins_prompt.execute(params![session_id, ts, intent])?;
+                let prompt_id = tx.last_insert_rowid();
+                stats.prompts += 1;
+
+                let n = if e + 1 == episodes { remaining } else { remaining / (episodes - e) };
+                remaining -= n;
+                let files: Vec<String> = (0..3).map(|i| format!("{cwd}/src/{module}/{}.rs", ["mod", "types", "tests"][i])).collect();
+                let episode_start = ts;
+                let (mut investigate, mut execute, mut failures) = (0i64, 0i64, 0i64);
+                let mut obs_rows = Vec::with_capacity(n);
+
+                for i in 0..n {
+                    ts += 5 + rng.below(90) as i64;
+                    let roll = rng.below(100);
+                    let (obs_type, tool, file, content, failed) = if roll < 35 {
+                        let f = rng.pick(&files).clone();
+                        ("file_read", "Read", Some(f.clone()), format!("Read {f}"), None)
+                    } else if roll < 55 {
+                        let f = rng.pick(&files).clone();
+                        ("file_edit", "Edit", Some(f.clone()), format!("Edit {f}"), None)
+                    } else if roll < 65 {
+                        ("search", "Grep", None, format!("{} in src/{module}", rng.pick(SEARCHES)), None)
+                    } else if roll < 97 || i + 1 == n {
+                        let (cmd, resp) = *rng.pick(COMMANDS);
+                        let failed = rng.chance(12).then_some(resp);
+                        ("command", "Bash", None, cmd.to_string(), failed)
+                    } else {
+                        ("git_commit", "Bash", None, format!("git commit -m \"{intent}\""), None)
+                    };
+                    let phase = if matches!(obs_type, "file_read" | "search") { "think" } else { "act" };
+                    if phase == "think" { investigate += 1 } else { execute += 1 }
+                    if obs_type == "file_edit"
+                        && let Some(ref f) = file
+                        && !edited.contains(f)
+                    {
+                        edited.push(f.clone());
+                    }
+                    let error_class = failed.map(|r| crate::s2_error::classify_error(&content, r));
+                    let metadata = failed.map(|r| serde_json::json!({"failed": true, "response": r}).to_string());
+                    if failed.is_some() {
+                        failures += 1;
+                    }
+                    *type_counts.entry(obs_type).or_default() += 1;
+                    obs_rows.push((obs_type, tool, file, content, metadata, phase, error_class, ts));
+                }
+
+                let friction = if failures > 0 { "friction" } else { "smooth" };
+                for (obs_type, tool, file, content, metadata, phase, error_class, ots) in obs_rows {
+                    let source_event = if error_class.is_some() { "PostToolUseFailure" } else { "PostToolUse" };
+                    let scope = if rng.chance(60) { "converge" } else { "diverge" };
+                    let locus = if obs_type == "search" && rng.chance(30) { "external" } else { "internal" };
+                    let novelty = if rng.chance(70) { "routine" } else { "novel" };
+                    ins_obs.execute(params![
+                        session_id, prompt_id, ots, obs_type, source_event, tool, file, content, metadata,
+                        phase, scope, locus, novelty, friction, error_class,
+                    ])?;
+                    stats.observations += 1;
+                    if error_class.is_some() {
+                        stats.failures += 1;
+                    }
+                }
+
+                let lesson = fill(rng.pick::<&str>(LEARNED), module);
+                learned.push(lesson.clone());
+                if n > 0 {
+                    let sig = serde_json::json!({
+                        "investigate": investigate, "execute": execute, "failures": failures,
+                        "friction": i64::from(failures > 0), "smooth": i64::from(failures == 0),
+                    });
+                    let summary = serde_json::json!({"intent": intent, "learned": [lesson], "notes": null});
+                    episode_rows.push((episode_start, ts, intent, prompt_id, files, sig, n, summary));
+                }
+                ts += 60 + rng.below(600) as i64;
+            }
+
+            for (start, end, intent, prompt_id, files, sig, n, summary) in episode_rows {
+                ins_episode.execute(params![
+                    session_id, start, end, intent, prompt_id, prompt_id,
+                    serde_json::to_string(&files)?, sig.to_string(), n as i64, summary.to_string(),
+                ])?;
+                stats.episodes += 1;
+            }
+
+            let signature: Vec<(&str, i64)> = type_counts.into_iter().collect();
+            let summary = serde_json::json!({
+                "intent": format!("work on {project}"),
+                "learned": learned,
+                "completed": [],
+                "next_steps": ["follow up on remaining failures"],
+                "files_read": [],
+                "files_edited": edited,
+                "notes": null,
+            });
+            end_session.execute(params![session_id, ts, serde_json::to_string(&signature)?, summary.to_string()])?;
+            stats.sessions += 1;
+        }
+    }
+    tx.commit()?;
+    Ok(stats)
+}
+
+/// In-memory migrated DB populated from `spec`, for downstream tests and benches.
+#[cfg(feature = "test-util")]
+pub fn fixture_db(spec: &FixtureSpec) -> Result<(Connection, FixtureStats), NmemError> {
+    let mut conn = Connection::open_in_memory()?;
+    crate::schema::MIGRATIONS
+        .to_latest(&mut conn)
+        .map_err(|e| NmemError::Config(format!("migration: {e}")))?;
+    crate::db::register_udfs(&conn)?;
+    let stats = generate(&conn, spec)?;
+    Ok((conn, stats))
+}
+
+#[cfg(feature = "cli")]
+pub fn handle_fixtures(db_path: &Path, args: &FixturesArgs) -> Result<(), NmemError> {
+    match &args.command {
+        FixturesCommand::Generate(gen_args) => {
+            let conn = crate::db::open_db(db_path)?;
+            let real: i64 = conn.query_row(
+                "SELECT COUNT(*) FROM sessions WHERE id NOT LIKE ?1",
+                params![format!("{SESSION_PREFIX}%")],
+                |r| r.get(0),
+            )?;
+            if real > 0 && !gen_args.force {
+                return Err(NmemError::Config(format!(
+                    "{} already holds {real} real sessions — use a scratch --db, or --force",
+                    db_path.display()
+                )));
+            }
+            let spec = FixtureSpec {
+                sessions: gen_args.sessions,
+                observations: gen_args.observations,
+                projects: gen_args.projects,
+                days: gen_args.days,
+                seed: gen_args.seed,
+                ..FixtureSpec::default()
+            };
+            let stats = generate(&conn, &spec)?;
+            log::info!(
+                "fixtures — {} sessions, {} prompts, {} observations ({} failures), {} episodes",
+                stats.sessions, stats.prompts, stats.observations, stats.failures, stats.episodes
+            );
+            Ok(())
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn conn() -> Connection {
+        let mut conn = Connection::open_in_memory().unwrap();
+        crate::schema::MIGRATIONS.to_latest(&mut conn).unwrap();
+        conn
+    }
+
+    fn spec() -> FixtureSpec {
+        FixtureSpec { sessions: 12, observations: 600, now: 1_700_000_000, ..FixtureSpec::default() }
+    }
+
+    #[test]
+    fn generates_requested_volume() {
+        let conn = conn();
+        let stats = generate(&conn, &spec()).unwrap();
+        assert_eq!(stats.sessions, 12);
+        assert_eq!(stats.observations, 600);
+        assert!(stats.failures > 0, "should include failures");
+        assert!(stats.episodes >= 12);
+
+        let (obs, summaries): (i64, i64) = conn
+            .query_row(
+                "SELECT (SELECT COUNT(*) FROM observations), (SELECT COUNT(*) FROM sessions WHERE summary IS NOT NULL)",
+                [],
+                |r| Ok((r.get(0)?, r.get(1)?)),
+            )
+            .unwrap();
+        assert_eq!((obs, summaries), (600, 12));
+
+        // FTS stays in sync, so search works on fixture data
+        let hits: i64 = conn
+            .query_row("SELECT COUNT(*) FROM observations_fts WHERE observations_fts MATCH 'cargo'", [], |r| r.get(0))
+            .unwrap();
+        assert!(hits > 0);
+    }
+
+    #[test]
+    fn same_seed_same_data() {
+        let dump = |conn: &Connection| -> Vec<(String, String, Option<String>)> {
+            let mut stmt = conn.prepare("SELECT session_id, content, error_class FROM observations ORDER BY id").unwrap();
+            stmt.query_map([], |r| Ok((r.get(0)?, r.get(1)?, r.get(2)?))).unwrap().map(Result::unwrap).collect()
+        };
+        let (a, b, c) = (conn(), conn(), conn());
+        generate(&a, &spec()).unwrap();
+        generate(&b, &spec()).unwrap();
+        generate(&c, &FixtureSpec { seed: 7, ..spec() }).unwrap();
+        assert_eq!(dump(&a), dump(&b));
+        assert_ne!(dump(&a), dump(&c));
+    }
+
+    #[test]
+    fn summaries_parse_as_session_summary() {
+        let conn = conn();
+        generate(&conn, &spec()).unwrap();
+        let json: String = conn.query_row("SELECT summary FROM sessions LIMIT 1", [], |r| r.get(0)).unwrap();
+        let summary: crate::s1_4_summarize::SessionSummary = serde_json::from_str(&json).unwrap();
+        assert!(summary.intent.starts_with("work on "));
+        assert!(!summary.learned.is_empty());
+    }
+}
@@ -1,60 +1,141 @@
+// Handlers and the state they set up are reached only through the CLI; a
+// library-only build keeps them compiled but unused
+#![cfg_attr(not(feature = "cli"), allow(dead_code, unused_imports))]
+
 // Infrastructure (no prefix)
+#[cfg(feature = "cli")]
 pub mod cli;
 pub mod db;
+pub mod diagnostic;
+pub mod fixtures;
 pub mod metrics;
 pub mod query;
 pub mod schema;
 pub mod status;
+pub mod store;
+pub mod timefmt;
+pub mod update;
 
 // S1 Operations — capture, store, retrieve
+pub mod s1_alias;
+pub mod s1_changes;
+pub mod s1_chunk;
+pub mod s1_correct;
+pub mod s1_docs;
 pub mod s1_extract;
 pub mod s1_git;
+pub mod s1_grep;
+pub mod s1_heartbeat;
+pub mod s1_infer;
+#[cfg(feature = "cli")]
+pub mod s1_ingest;
+#[cfg(feature = "cli")]
+pub mod s1_input;
+pub mod s1_intent;
+pub mod s1_investigate;
+pub mod s1_lang;
+#[cfg(feature = "cli")]
 pub mod s1_lsp;
+#[cfg(feature = "cli")]
 pub mod s1_mark;
+pub mod s1_minhash;
+pub mod s1_onboard;
 pub mod s1_pin;
+pub mod s1_plan;
 pub mod s1_record;
+pub mod s1_remember;
+pub mod s1_repeat;
+pub mod s1_query;
 pub mod s1_search;
+#[cfg(feature = "cli")]
 pub mod s1_serve;
+#[cfg(feature = "cli")]
+pub mod s1_simulate;
+pub mod s1_snapshot;
+pub mod s1_suggest;
+pub mod s1_tail;
 
 // S2 Coordination — classification, dedup
+pub mod s2_backfill;
 pub mod s2_classify;
+pub mod s2_error;
 pub mod s2_inference;
 pub mod s2_locus;
 pub mod s2_novelty;
 pub mod s2_scope;
+pub mod s2_test_failure;
 
 // S1's S4 — session intelligence (VSM recursion within S1)
 pub mod s1_4_inference;
+pub mod s1_4_salience;
 pub mod s1_4_summarize;
 pub mod s1_4_transcript;
 
 // S3 Control — retention, compaction, integrity
+pub mod s3_archive;
+pub mod s3_audit;
+pub mod s3_autopin;
+pub mod s3_backup;
+pub mod s3_capture;
+pub mod s3_deletions;
+pub mod s3_importance;
 pub mod s3_learn;
+pub mod s3_limits;
+pub mod s3_llm_usage;
 pub mod s3_maintain;
+pub mod s3_migrate;
+pub mod s3_model;
+pub mod s3_pause;
+pub mod s3_pending;
 pub mod s3_purge;
+pub mod s3_schedule;
+pub mod s3_slow_query;
+pub mod s3_suppress;
 pub mod s3_sweep;
 
-// S4 Intelligence — context injection, task dispatch, cross-session patterns, episodic memory, fleet beacon
+// S4 Intelligence — context injection, context providers, task dispatch, cross-session patterns, episodic memory, topic consolidation, fleet beacon
+#[cfg(feature = "cli")]
 pub mod s4_beacon;
+pub mod s4_consolidate;
 pub mod s4_context;
+pub mod s4_decay;
 pub mod s4_dispatch;
 pub mod s4_memory;
+pub mod s4_outcome;
+pub mod s4_overlap;
+pub mod s4_pack;
+pub mod s4_providers;
+pub mod s4_revisit;
+pub mod s4_risk;
+pub mod s4_rollup;
+pub mod s4_stance;
+pub mod s4_window;
 
 // S5 Policy — config, boundaries, identity
+pub mod s5_anonymize;
 pub mod s5_config;
 pub mod s5_filter;
 pub mod s5_project;
+pub mod s5_quarantine;
 
 // Backward-compat aliases — external code (main.rs, tests) can use old names
 pub use s4_context as context;
 pub use s1_extract as extract;
+#[cfg(feature = "cli")]
+pub use s1_ingest as ingest;
+pub use s1_investigate as investigate;
+#[cfg(feature = "cli")]
 pub use s1_mark as mark;
 pub use s1_pin as pin;
 pub use s1_record as record;
 pub use s1_search as search;
+#[cfg(feature = "cli")]
 pub use s1_serve as serve;
 pub use s1_4_summarize as summarize;
 pub use s1_4_transcript as transcript;
+pub use s3_archive as archive;
+pub use s3_audit as audit;
+pub use s3_autopin as autopin;
 pub use s3_learn as learn;
 pub use s3_maintain as maintain;
 pub use s3_purge as purge;
@@ -72,6 +153,33 @@ pub enum NmemError {
     Json(serde_json::Error),
     Config(String),
     Nats(String),
+    /// `source` while attempting `op`, on `path` when one is involved.
+    /// `diagnostic::diagnose` turns the chain into a code and a hint.
+    Context {
+        op: String,
+        path: Option<std::path::PathBuf>,
+        source: Box<NmemError>,
+    },
+}
+
+impl NmemError {
+    /// Wrap with the operation being attempted.
+    pub fn context(self, op: impl Into<String>) -> Self {
+        NmemError::Context { op: op.into(), path: None, source: Box::new(self) }
+    }
+
+    /// Wrap with the operation being attempted and the file it involved.
+    pub fn context_path(self, op: impl Into<String>, path: &std::path::Path) -> Self {
+        NmemError::Context { op: op.into(), path: Some(path.to_path_buf()), source: Box::new(self) }
+    }
+
+    /// The underlying error, beneath any context.
+    pub fn root(&self) -> &NmemError {
+        match self {
+            NmemError::Context { source, .. } => source.root(),
+            other => other,
+        }
+    }
 }
 
 impl std::fmt::Display for NmemError {
@@ -82,6 +190,8 @@ impl std::fmt::Display for NmemError {
             NmemError::Json(e) => write!(f, "json: {e}"),
             NmemError::Config(msg) => write!(f, "config: {msg}"),
             NmemError::Nats(msg) => write!(f, "nats: {msg}"),
+            NmemError::Context { op, path: Some(path), source } => write!(f, "{op} ({}): {source}", path.display()),
+            NmemError::Context { op, path: None, source } => write!(f, "{op}: {source}"),
         }
     }
 }
@@ -13,7 +13,8 @@ fn default_db_path() -> PathBuf {
 /// Hooks and MCP server are quiet; CLI commands show info.
 fn default_log_level(cmd: &Command) -> &'static str {
     match cmd {
-        Command::Record | Command::Serve | Command::Lsp => "warn",
+        Command::Record(_) | Command::Serve(_) | Command::Lsp => "warn",
+        Command::Ingest(args) if matches!(args.source, nmem::cli::IngestSource::Command(_)) => "warn",
         _ => "info",
     }
 }
@@ -38,46 +39,96 @@ fn init_logger(cmd: &Command) {
 fn run() -> Result<(), NmemError> {
     let cli = Cli::parse();
     init_logger(&cli.command);
-    let db_path = cli.db.unwrap_or_else(default_db_path);
+    let mut dbs = cli.db.into_iter();
+    let db_path = dbs.next().unwrap_or_else(default_db_path);
+    let attach: Vec<PathBuf> = dbs.collect();
+    if !attach.is_empty() && !matches!(cli.command, Command::Serve(_)) {
+        return Err(NmemError::Config("multiple --db paths are only supported by serve".into()));
+    }
 
-    match cli.command {
-        Command::Record => nmem::record::handle_record(&db_path),
-        Command::Serve => nmem::serve::handle_serve(&db_path),
+    // Hooks stay off a second connection and stderr; their slow statements go unlogged
+    let flush_slow_queries = !matches!(cli.command, Command::Record(_));
+    let result = match cli.command {
+        Command::Record(args) => nmem::record::handle_record(&db_path, args.heartbeat),
+        Command::Serve(args) => nmem::serve::handle_serve(&db_path, &attach, args.lean),
         Command::Purge(args) => nmem::purge::handle_purge(&db_path, &args),
+        Command::Restore(args) => nmem::purge::handle_restore(&db_path, &args),
         Command::Maintain(args) => nmem::maintain::handle_maintain(&db_path, &args),
-        Command::Status => nmem::status::handle_status(&db_path),
+        Command::Status(args) => nmem::status::handle_status(&db_path, &args),
         Command::Search(args) => nmem::search::handle_search(&db_path, &args),
+        Command::Suggest(args) => nmem::s1_suggest::handle_suggest(&db_path, &args),
+        Command::Grep(args) => nmem::s1_grep::handle_grep(&db_path, &args),
         Command::Encrypt => nmem::db::handle_encrypt(&db_path),
         Command::Pin(args) => nmem::pin::handle_pin(&db_path, args.id),
         Command::Unpin(args) => nmem::pin::handle_unpin(&db_path, args.id),
+        Command::Redact(args) => nmem::s1_correct::handle_redact(&db_path, &args),
+        Command::Annotate(args) => nmem::s1_correct::handle_annotate(&db_path, &args),
         Command::Context(args) => nmem::context::handle_context(&db_path, &args),
         Command::Queue(args) => nmem::dispatch::handle_queue(&db_path, &args),
         Command::Dispatch(args) => nmem::dispatch::handle_dispatch(&db_path, &args),
         Command::Task(args) => nmem::dispatch::handle_task(&db_path, &args),
         Command::Learn(args) => nmem::learn::handle_learn(&db_path, &args),
         Command::Mark(args) => nmem::mark::handle_mark(&db_path, &args),
+        Command::Investigate(args) => nmem::investigate::handle_investigate(&db_path, &args),
         Command::Lsp => nmem::s1_lsp::handle_lsp(&db_path),
         Command::Beacon(args) => nmem::s4_beacon::handle_beacon(&db_path, &args),
+        Command::Ingest(args) => nmem::ingest::handle_ingest(&db_path, &args),
+        Command::Fixtures(args) => nmem::fixtures::handle_fixtures(&db_path, &args),
+        Command::Audit(args) => nmem::audit::handle_audit(&db_path, &args),
+        Command::Backup(args) => nmem::s3_backup::handle_backup(&db_path, &args),
+        Command::Stats(args) => nmem::s3_llm_usage::handle_stats(&db_path, &args),
+        Command::Consolidate(args) => nmem::s4_consolidate::handle_consolidate(&db_path, &args),
+        Command::Rollup(args) => nmem::s4_rollup::handle_rollup(&db_path, &args),
+        Command::Project(args) => nmem::archive::handle_project(&db_path, &args),
+        Command::Tail(args) => nmem::s1_tail::handle_tail(&db_path, &args),
+        Command::Changes(args) => nmem::s1_changes::handle_changes(&db_path, &args),
+        Command::Summarize(args) => nmem::s4_memory::handle_summarize(&db_path, &args),
+        Command::Filter(args) => nmem::s5_quarantine::handle_filter(&db_path, &args),
+        Command::Episode(args) => nmem::s4_memory::handle_episode(&db_path, &args),
+        Command::Revisit(args) => nmem::s4_revisit::handle_revisit(&db_path, &args),
+        Command::Stale(args) => nmem::s4_decay::handle_stale(&db_path, &args),
+        Command::Query(args) => nmem::s1_query::handle_query(&db_path, &args),
+        Command::Simulate(args) => nmem::s1_simulate::handle_simulate(&args),
+        Command::Pause(args) => nmem::s3_pause::handle_pause(&db_path, &args),
+        Command::Resume(args) => nmem::s3_pause::handle_resume(&db_path, &args),
+        Command::Suppress(args) => nmem::s3_suppress::handle_suppress(&db_path, &args),
+        Command::Version(args) => nmem::update::handle_version(&args),
+        Command::SelfUpdate(args) => nmem::update::handle_self_update(&args),
+        Command::Backfill(args)
+            if args.low_confidence_only
+                && !nmem::s2_inference::CONFIDENCE_DIMENSIONS.contains(&args.dimension.as_str()) =>
+        {
+            Err(NmemError::Config(format!(
+                "--low-confidence-only needs a classifier dimension (phase, scope, locus, novelty), got {}",
+                args.dimension
+            )))
+        }
         Command::Backfill(args) => match args.dimension.as_str() {
+            "all" => nmem::s2_backfill::handle_backfill_all(&db_path, &args),
             "phase" => nmem::s2_classify::handle_backfill(&db_path, &args),
             "scope" => nmem::s2_scope::handle_backfill_scope(&db_path, &args),
             "locus" => nmem::s2_locus::handle_backfill_locus(&db_path, &args),
             "novelty" => nmem::s2_novelty::handle_backfill_novelty(&db_path, &args),
             "friction" => nmem::s4_memory::backfill_episode_friction(&db_path),
+            "error_class" => nmem::s2_error::handle_backfill_error_class(&db_path, &args),
             "obs_trace" => nmem::s4_memory::backfill_obs_trace(&db_path),
             "narrative" => nmem::s4_memory::backfill_narratives(&db_path),
             other => Err(NmemError::Config(format!(
-                "unknown dimension: {other} (expected: phase, scope, locus, novelty, friction, obs_trace, narrative)"
+                "unknown dimension: {other} (expected: all, phase, scope, locus, novelty, friction, error_class, obs_trace, narrative)"
             ))),
         },
+    };
+    if flush_slow_queries {
+        nmem::s3_slow_query::flush_pending(&db_path);
     }
+    result
 }
 
 fn main() -> ExitCode {
     match run() {
         Ok(()) => ExitCode::SUCCESS,
         Err(e) => {
-            log::error!("{e}");
+            log::error!("{}", nmem::diagnostic::diagnose(&e));
             ExitCode::from(1)
         }
     }
@@ -1,10 +1,36 @@
+#[cfg(feature = "cli")]
 use opentelemetry::KeyValue;
+#[cfg(feature = "cli")]
 use opentelemetry_otlp::{Protocol, WithExportConfig};
+#[cfg(feature = "cli")]
 use opentelemetry_sdk::metrics::SdkMeterProvider;
+#[cfg(feature = "cli")]
 use opentelemetry_sdk::Resource;
+use crate::NmemError;
+#[cfg(feature = "cli")]
+use opentelemetry::metrics::Meter;
+use rusqlite::Connection;
 use serde::Deserialize;
+use std::path::Path;
+#[cfg(feature = "cli")]
 use std::time::Duration;
 
+/// Tables whose row counts are exported as `nmem_db_rows{table}`.
+const COUNTED_TABLES: &[&str] = &[
+    "observations",
+    "prompts",
+    "sessions",
+    "work_units",
+    "observation_chunks",
+    "file_snapshots",
+    "llm_usage",
+    "query_log",
+    "pending_work",
+];
+
+/// FTS5 indexes whose shadow `_data` tables are sized for `nmem_fts_bytes{index}`.
+const FTS_INDEXES: &[&str] = &["observations_fts", "observation_chunks_fts", "prompts_fts"];
+
 #[derive(Debug, Deserialize, Default, Clone)]
 pub struct MetricsConfig {
     #[serde(default)]
@@ -25,6 +51,7 @@ fn default_endpoint() -> String {
 
 /// Build and register a global meter provider.
 /// For gRPC transport, requires an active tokio runtime context.
+#[cfg(feature = "cli")]
 pub fn init_meter_provider(config: &MetricsConfig) -> Option<SdkMeterProvider> {
     if !config.enabled {
         return None;
@@ -68,3 +95,154 @@ pub fn init_meter_provider(config: &MetricsConfig) -> Option<SdkMeterProvider> {
     opentelemetry::global::set_meter_provider(provider.clone());
     Some(provider)
 }
+
+/// A provider that lives for one short-lived process (a hook, `nmem
+/// maintain`) and flushes on drop. Instruments recorded through
+/// `opentelemetry::global::meter("nmem")` meanwhile are exported.
+#[cfg(feature = "cli")]
+pub struct Exporter {
+    provider: SdkMeterProvider,
+    _rt: tokio::runtime::Runtime,
+}
+
+#[cfg(feature = "cli")]
+impl Exporter {
+    /// None when metrics are disabled or the exporter can't be built.
+    pub fn start(config: &MetricsConfig) -> Option<Self> {
+        if !config.enabled {
+            return None;
+        }
+        let rt = tokio::runtime::Builder::new_multi_thread()
+            .worker_threads(1)
+            .enable_all()
+            .build()
+            .ok()?;
+        let provider = rt.block_on(async { init_meter_provider(config) })?;
+        Some(Self { provider, _rt: rt })
+    }
+}
+
+#[cfg(feature = "cli")]
+impl Drop for Exporter {
+    fn drop(&mut self) {
+        let _ = self.provider.shutdown();
+    }
+}
+
+/// Store health at one point in time.
+#[derive(Debug, Default, PartialEq)]
+pub struct DbStats {
+    /// Main database file, excluding the WAL.
+    pub db_bytes: u64,
+    pub wal_bytes: u64,
+    /// Pages freed by deletes and not yet vacuumed.
+    pub free_bytes: u64,
+    /// Size of each FTS index's stored segments.
+    pub fts_bytes: Vec<(&'static str, u64)>,
+    pub rows: Vec<(&'static str, i64)>,
+}
+
+/// Measure the store at `db_path` through `conn`. Tables and indexes absent
+/// from an older schema are skipped.
+pub fn db_stats(conn: &Connection, db_path: &Path) -> Result<DbStats, NmemError> {
+    let page_size: i64 = conn.pragma_query_value(None, "page_size", |r| r.get(0))?;
+    let page_count: i64 = conn.pragma_query_value(None, "page_count", |r| r.get(0))?;
+    let freelist: i64 = conn.pragma_query_value(None, "freelist_count", |r| r.get(0))?;
+    let mut wal = db_path.as_os_str().to_owned();
+    wal.push("-wal");
+    let mut stats = DbStats {
+        db_bytes: (page_size * page_count).max(0) as u64,
+        wal_bytes: std::fs::metadata(Path::new(&wal)).map(|m| m.len()).unwrap_or(0),
+        free_bytes: (page_size * freelist).max(0) as u64,
+        ..Default::default()
+    };
+    for &table in COUNTED_TABLES {
+        if let Ok(n) = conn.query_row(&format!("SELECT COUNT(*) FROM {table}"), [], |r| r.get(0)) {
+            stats.rows.push((table, n));
+        }
+    }
+    for &index in FTS_INDEXES {
+        let sql = format!("SELECT COALESCE(SUM(length(block)), 0) FROM {index}_data");
+        if let Ok(n) = conn.query_row(&sql, [], |r| r.get::<_, i64>(0)) {
+            stats.fts_bytes.push((index, n.max(0) as u64));
+        }
+    }
+    Ok(stats)
+}
+
+/// Record `stats` as gauges, plus whether the monthly token budget has
+/// paused episode narratives and topic consolidation (`nmem_llm_paused`).
+#[cfg(feature = "cli")]
+pub fn record_db_stats(meter: &Meter, stats: &DbStats, llm_paused: bool) {
+    meter.u64_gauge("nmem_db_size_bytes").build().record(stats.db_bytes, &[]);
+    meter.u64_gauge("nmem_wal_size_bytes").build().record(stats.wal_bytes, &[]);
+    meter.u64_gauge("nmem_db_free_bytes").build().record(stats.free_bytes, &[]);
+    let fts = meter.u64_gauge("nmem_fts_bytes").build();
+    for (index, bytes) in &stats.fts_bytes {
+        fts.record(*bytes, &[KeyValue::new("index", *index)]);
+    }
+    let rows = meter.u64_gauge("nmem_db_rows").build();
+    for (table, n) in &stats.rows {
+        rows.record((*n).max(0) as u64, &[KeyValue::new("table", *table)]);
+    }
+    meter.u64_gauge("nmem_llm_paused").build().record(llm_paused as u64, &[]);
+}
+
+/// Count a retention sweep's deletions by obs_type.
+#[cfg(feature = "cli")]
+pub fn record_sweep(meter: &Meter, result: &crate::s3_sweep::SweepResult) {
+    let deleted = meter.u64_counter("nmem_sweep_deleted_total").build();
+    for (obs_type, n) in &result.by_type {
+        deleted.add(*n as u64, &[KeyValue::new("obs_type", obs_type.clone())]);
+    }
+    meter
+        .u64_counter("nmem_sweep_orphans_total")
+        .build()
+        .add(result.orphans_cleaned as u64, &[]);
+    meter
+        .u64_counter("nmem_sweep_snapshots_total")
+        .build()
+        .add(result.snapshots_deleted as u64, &[]);
+}
+
+/// Measure the store and record its gauges — non-fatal.
+#[cfg(feature = "cli")]
+pub fn export_db_stats(conn: &Connection, db_path: &Path, config: &crate::s5_config::NmemConfig) {
+    let now = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs() as i64;
+    match db_stats(conn, db_path) {
+        Ok(stats) => {
+            let paused = crate::s3_llm_usage::over_budget(conn, &config.summarization, now).unwrap_or(false);
+            record_db_stats(&opentelemetry::global::meter("nmem"), &stats, paused);
+        }
+        Err(e) => log::debug!("db stats: {e}"),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn measures_sizes_and_rows() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let path = dir.path().join("nmem.db");
+        let conn = crate::db::open_db(&path).unwrap();
+        conn.execute_batch(
+            "INSERT INTO sessions (id, project, started_at) VALUES ('s', 'p', 1);
+             INSERT INTO observations (session_id, timestamp, obs_type, source_event, content)
+             VALUES ('s', 1, 'command', 'PostToolUse', 'cargo build --release');",
+        )
+        .unwrap();
+
+        let stats = db_stats(&conn, &path).unwrap();
+        assert!(stats.db_bytes > 0);
+        assert!(stats.wal_bytes > 0, "writes sit in the WAL until a checkpoint");
+        assert!(stats.rows.contains(&("observations", 1)));
+        assert!(stats.rows.contains(&("sessions", 1)));
+        let fts = stats.fts_bytes.iter().find(|(i, _)| *i == "observations_fts").unwrap();
+        assert!(fts.1 > 0);
+    }
+}
@@ -14,67 +14,74 @@ const STOPWORDS: &[&str] = &[
 
 /// Sanitize user input for FTS5 MATCH queries.
 ///
-/// FTS5 treats `-` as NOT and `*` as prefix wildcard. This function quotes
-/// individual terms that contain characters that would be misinterpreted
-/// (hyphens, colons, dots, slashes), while preserving valid FTS5 operators
-/// (AND, OR, NOT, NEAR) and already-quoted phrases.
+/// FTS5 treats `-` as NOT, `:` as a column filter, and most punctuation as
+/// a syntax error. This function quotes individual terms that aren't plain
+/// words (hyphens, colons, dots, slashes — file paths), doubling any quote
+/// inside them, while preserving valid FTS5 operators (AND, OR, NOT between
+/// terms), a trailing `*` prefix wildcard, and quoted phrases. An
+/// unterminated phrase is closed; an operator with nothing to join is
+/// quoted as a literal word; control characters separate terms.
 ///
 /// Returns `None` if the input produces no usable tokens.
 pub fn sanitize_fts_query(input: &str) -> Option<String> {
-    let trimmed = input.trim();
-    if trimmed.is_empty() {
-        return None;
-    }
-
-    // If the entire input is already a quoted phrase, pass through
-    if trimmed.starts_with('"') && trimmed.ends_with('"') && trimmed.len() > 1 {
-        return Some(trimmed.to_string());
-    }
-
-    let fts_operators = ["AND", "OR", "NOT", "NEAR"];
-    let needs_quoting = |w: &str| -> bool {
-        w.contains('-')
-            || w.contains(':')
-            || w.contains('.')
-            || w.contains('/')
-            || w.contains('\\')
-            || w.contains('(')
-            || w.contains(')')
-            || w.contains('{')
-            || w.contains('}')
-            || w.contains('[')
-            || w.contains(']')
-    };
+    let fts_operators = ["AND", "OR", "NOT"];
 
-    let words: Vec<&str> = trimmed.split_whitespace().collect();
-
-    // If there are no non-operator terms, operators can't be preserved
-    let has_operands = words.iter().any(|w| !fts_operators.contains(w));
-
-    let terms: Vec<String> = words
-        .into_iter()
-        .map(|w| {
-            // Already quoted — pass through
-            if w.starts_with('"') && w.ends_with('"') && w.len() > 1 {
-                return w.to_string();
+    // (text, is_operator); phrases and quoted words are operands
+    let mut items: Vec<(String, bool)> = Vec::new();
+    // Control characters separate terms; a NUL would end the query string
+    let mut chars = input.chars().map(|c| if c.is_control() { ' ' } else { c }).peekable();
+    while let Some(&c) = chars.peek() {
+        if c.is_whitespace() {
+            chars.next();
+            continue;
+        }
+        if c == '"' {
+            chars.next();
+            let phrase: String = chars.by_ref().take_while(|&c| c != '"').collect();
+            if !phrase.trim().is_empty() {
+                items.push((format!("\"{phrase}\""), false));
             }
-            // FTS5 operators — only pass through if there are actual operands
-            if fts_operators.contains(&w) {
-                if has_operands {
-                    return w.to_string();
-                }
-                // No operands — quote the operator so it's treated as literal
-                return format!("\"{}\"", w);
+            continue;
+        }
+        let mut word = String::new();
+        while let Some(&c) = chars.peek() {
+            if c.is_whitespace() {
+                break;
             }
-            // Terms with problematic characters — quote them
-            if needs_quoting(w) {
-                return format!("\"{}\"", w.replace('"', ""));
-            }
-            // Plain words — pass through
-            w.to_string()
-        })
-        .filter(|t| !t.is_empty())
-        .collect();
+            word.push(c);
+            chars.next();
+        }
+        if fts_operators.contains(&word.as_str()) {
+            items.push((word, true));
+            continue;
+        }
+        let core = word.strip_suffix('*').unwrap_or(&word);
+        if core.is_empty() {
+            continue;
+        }
+        if core.chars().all(is_bareword_char) {
+            items.push((word, false));
+        } else {
+            items.push((format!("\"{}\"", word.replace('"', "\"\"")), false));
+        }
+    }
+
+    let mut terms: Vec<String> = Vec::with_capacity(items.len());
+    let mut prev_operand = false;
+    for (i, (text, is_op)) in items.iter().enumerate() {
+        let joins = prev_operand && items.get(i + 1).is_some_and(|(_, next_op)| !next_op);
+        if *is_op && joins {
+            terms.push(text.clone());
+            prev_operand = false;
+        } else if *is_op {
+            // Nothing to join — treat the operator as a literal word
+            terms.push(format!("\"{text}\""));
+            prev_operand = true;
+        } else {
+            terms.push(text.clone());
+            prev_operand = true;
+        }
+    }
 
     if terms.is_empty() {
         None
@@ -83,6 +90,11 @@ pub fn sanitize_fts_query(input: &str) -> Option<String> {
     }
 }
 
+/// Characters FTS5 accepts in an unquoted term.
+fn is_bareword_char(c: char) -> bool {
+    c.is_alphanumeric() || c == '_' || !c.is_ascii()
+}
+
 /// Generate tiered FTS5 query variants for first-shot accuracy.
 ///
 /// Returns queries ordered from highest precision to lowest:
@@ -200,6 +212,25 @@ mod tests {
         );
     }
 
+    #[test]
+    fn sanitize_escapes_quotes_and_punctuation_in_terms() {
+        assert_eq!(
+            sanitize_fts_query("src/\"odd\".rs user@host ^start"),
+            Some("\"src/\"\"odd\"\".rs\" \"user@host\" \"^start\"".into())
+        );
+        assert_eq!(sanitize_fts_query("auth* *"), Some("auth*".into()));
+    }
+
+    #[test]
+    fn sanitize_closes_phrases_and_quotes_dangling_operators() {
+        assert_eq!(sanitize_fts_query("fix \"token refresh"), Some("fix \"token refresh\"".into()));
+        assert_eq!(sanitize_fts_query("AND cargo OR"), Some("\"AND\" cargo \"OR\"".into()));
+        assert_eq!(sanitize_fts_query("a AND OR b"), Some("a \"AND\" OR b".into()));
+        assert_eq!(sanitize_fts_query("OR"), Some("\"OR\"".into()));
+        assert_eq!(sanitize_fts_query("\"\""), None);
+        assert_eq!(sanitize_fts_query("auth\0token"), Some("auth token".into()));
+    }
+
     #[test]
     fn sanitize_mixed_plain_and_special() {
         assert_eq!(
@@ -177,10 +177,9 @@ impl InferenceEngine {
 
         let mut output = String::new();
         let mut n_generated = 0usize;
-        let mut pos = n_prompt_tokens as i32;
         let mut decoder = encoding_rs::UTF_8.new_decoder();
 
-        for _ in 0..self.params.max_tokens {
+        for pos in (n_prompt_tokens as i32..).take(self.params.max_tokens as usize) {
             let new_token = sampler.sample(&ctx, batch.n_tokens() - 1);
             sampler.accept(new_token);
 
@@ -198,7 +197,6 @@ impl InferenceEngine {
             batch
                 .add(new_token, pos, &[0], true)
                 .map_err(|e| NmemError::Config(format!("batch add: {e}")))?;
-            pos += 1;
             ctx.decode(&mut batch)
                 .map_err(|e| NmemError::Config(format!("decode: {e}")))?;
         }
@@ -220,6 +218,7 @@ pub fn generate(
     system_prompt: &str,
     user_prompt: &str,
 ) -> Result<GenerateResult, NmemError> {
+    let _slot = crate::s3_schedule::llm_slot()?;
     let engine = InferenceEngine::new(InferenceParams {
         model_path: params.model_path.clone(),
         temperature: params.temperature,
@@ -0,0 +1,165 @@
+//! S1's S4 — extractive pre-pass for summarization payloads.
+//!
+//! Session and episode payloads used to take the first rows of a session, so
+//! on long ones the model never saw how the work ended. The pre-pass reads
+//! every action line, collapses repeats (`cargo test` run twelve times is
+//! one line marked `(×12)`), scores each line by the TF-IDF weight of its
+//! terms across the payload, and keeps the most salient lines that fit a
+//! token budget, in their original order. Failure lines are always kept:
+//! they feed `notes`, and dropping them would hide failed approaches.
+
+use std::collections::{HashMap, HashSet};
+
+/// One formatted action line and the observation it came from.
+#[derive(Debug, Clone)]
+pub struct Candidate {
+    pub text: String,
+    pub source: i64,
+    pub failed: bool,
+}
+
+#[derive(Debug, Default)]
+pub struct Selection {
+    /// Chosen lines in their original order, repeats suffixed `(×N)`.
+    pub lines: Vec<String>,
+    /// Observation ids behind the chosen lines, repeats included.
+    pub sources: Vec<i64>,
+    /// Candidates read.
+    pub total: usize,
+    /// Candidates left out, repeats folded into a chosen line not counted.
+    pub omitted: usize,
+}
+
+/// Rough token count for budgeting: ~4 characters per token.
+pub fn estimate_tokens(text: &str) -> usize {
+    text.chars().count().div_ceil(4)
+}
+
+/// Lowercased words of 3+ characters that aren't plain numbers.
+fn terms(text: &str) -> HashSet<String> {
+    text.split(|c: char| !(c.is_alphanumeric() || c == '_'))
+        .filter(|w| w.chars().count() >= 3 && !w.chars().all(|c| c.is_ascii_digit()))
+        .map(str::to_lowercase)
+        .collect()
+}
+
+/// Lines equal up to case, digits, and whitespace are repeats.
+fn repeat_key(text: &str) -> String {
+    text.split_whitespace()
+        .map(|w| w.chars().map(|c| if c.is_ascii_digit() { '#' } else { c.to_ascii_lowercase() }).collect::<String>())
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+struct Group {
+    first: Candidate,
+    count: usize,
+    sources: Vec<i64>,
+    failed: bool,
+}
+
+/// Pick the lines to send within `budget_tokens`. Failures are kept even
+/// past the budget.
+pub fn select(candidates: Vec<Candidate>, budget_tokens: usize) -> Selection {
+    let total = candidates.len();
+    let mut groups: Vec<Group> = Vec::new();
+    let mut by_key: HashMap<String, usize> = HashMap::new();
+    for c in candidates {
+        match by_key.get(&repeat_key(&c.text)) {
+            Some(&i) => {
+                let g = &mut groups[i];
+                g.count += 1;
+                g.sources.push(c.source);
+                g.failed |= c.failed;
+            }
+            None => {
+                by_key.insert(repeat_key(&c.text), groups.len());
+                groups.push(Group { sources: vec![c.source], failed: c.failed, count: 1, first: c });
+            }
+        }
+    }
+
+    let docs: Vec<HashSet<String>> = groups.iter().map(|g| terms(&g.first.text)).collect();
+    let mut df: HashMap<&str, usize> = HashMap::new();
+    for doc in &docs {
+        for t in doc {
+            *df.entry(t.as_str()).or_default() += 1;
+        }
+    }
+    let n = groups.len() as f64;
+    let scores: Vec<f64> = docs
+        .iter()
+        .map(|doc| {
+            let weight: f64 = doc.iter().map(|t| ((1.0 + n) / (1.0 + df[t.as_str()] as f64)).ln() + 1.0).sum();
+            weight / (doc.len().max(1) as f64).sqrt()
+        })
+        .collect();
+
+    let mut order: Vec<usize> = (0..groups.len()).collect();
+    order.sort_by(|&a, &b| {
+        groups[b].failed.cmp(&groups[a].failed).then(scores[b].total_cmp(&scores[a])).then(a.cmp(&b))
+    });
+
+    let line = |g: &Group| {
+        if g.count > 1 { format!("{} (×{})", g.first.text, g.count) } else { g.first.text.clone() }
+    };
+    let mut used = 0;
+    let mut chosen = Vec::new();
+    for i in order {
+        let cost = estimate_tokens(&line(&groups[i])) + 1;
+        if groups[i].failed || used + cost <= budget_tokens {
+            used += cost;
+            chosen.push(i);
+        }
+    }
+    chosen.sort_unstable();
+
+    let mut selection = Selection { total, ..Default::default() };
+    for i in chosen {
+        selection.lines.push(line(&groups[i]));
+        selection.sources.extend(&groups[i].sources);
+    }
+    selection.omitted = total - selection.sources.len();
+    selection
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn candidate(source: i64, text: &str, failed: bool) -> Candidate {
+        Candidate { text: text.into(), source, failed }
+    }
+
+    #[test]
+    fn collapses_repeats_and_keeps_order() {
+        let selection = select(
+            vec![
+                candidate(1, "[command] cargo test", false),
+                candidate(2, "[file_edit] src/db.rs - fn open_db", false),
+                candidate(3, "[command] cargo  test", false),
+                candidate(4, "[command] Cargo test", false),
+            ],
+            1000,
+        );
+        assert_eq!(selection.lines, vec!["[command] cargo test (×3)", "[file_edit] src/db.rs - fn open_db"]);
+        assert_eq!(selection.sources, vec![1, 3, 4, 2]);
+        assert_eq!((selection.total, selection.omitted), (4, 0));
+    }
+
+    #[test]
+    fn budget_prefers_salient_lines_and_always_keeps_failures() {
+        let mut candidates: Vec<Candidate> =
+            (0..40).map(|i| candidate(i, &format!("[file_read] src/common.rs - common {}", "x".repeat(i as usize + 1)), false)).collect();
+        candidates.insert(10, candidate(100, "[command] sqlite3 migrate rollback schema v12 FAILED: locked", true));
+        candidates.push(candidate(200, "[file_edit] src/schema.rs - add migration for tool_intents", false));
+        let selection = select(candidates, 40);
+
+        assert!(selection.sources.contains(&100), "failures kept: {:?}", selection.lines);
+        assert!(selection.sources.contains(&200), "distinctive line kept: {:?}", selection.lines);
+        assert!(selection.omitted > 30);
+        let failure = selection.lines.iter().position(|l| l.contains("FAILED")).unwrap();
+        let edit = selection.lines.iter().position(|l| l.contains("tool_intents")).unwrap();
+        assert!(failure < edit, "original order kept");
+    }
+}
@@ -1,10 +1,11 @@
 use crate::s1_4_inference;
+use crate::s1_4_salience::{self, Candidate, estimate_tokens};
 use crate::s5_config::SummarizationConfig;
 use crate::NmemError;
 use rusqlite::{Connection, params};
 use serde::{Deserialize, Serialize};
 
-const SYSTEM_PROMPT: &str = "You produce structured JSON summaries of coding sessions for an AI agent's cross-session memory. The consumer is the next AI session, not a human.\n\nPriority: intent > learned > notes > completed > next_steps. files_read and files_edited are low priority — extract unique paths from the actions list.\n\nRules:\n- intent: one sentence, the primary goal. NOT a list of actions.\n- learned: decisions and conclusions the next session should NOT re-derive. Each entry should be specific enough to act on.\n- notes: errors, failed approaches, things that didn't work. null if none.\n- files_read, files_edited: unique file paths only, no descriptions.\n- All array fields MUST be JSON arrays of strings, never a single string.\n\nReturn ONLY valid JSON. No markdown fences, no explanation.";
+const SYSTEM_PROMPT: &str = "You produce structured JSON summaries of coding sessions for an AI agent's cross-session memory. The consumer is the next AI session, not a human.\n\nPriority: intent > learned > notes > completed > next_steps. files_read and files_edited are low priority — extract unique paths from the actions list.\n\nRules:\n- intent: one sentence, the primary goal. NOT a list of actions.\n- learned: decisions and conclusions the next session should NOT re-derive. Each entry should be specific enough to act on.\n- notes: errors, failed approaches, things that didn't work. null if none.\n- completed: only work the actions show and the assistant's reports confirm. If a report says something failed or was left unfinished, it belongs in notes or next_steps, not completed.\n- files_read, files_edited: unique file paths only, no descriptions.\n- All array fields MUST be JSON arrays of strings, never a single string.\n\nReturn ONLY valid JSON. No markdown fences, no explanation.";
 
 const USER_PROMPT_TEMPLATE: &str = r#"Summarize this coding session. The summary is injected as context so the next AI session can continue without re-deriving conclusions.
 
@@ -75,12 +76,28 @@ where
     deserializer.deserialize_any(StringOrVec)
 }
 
+/// Text handed to the model, plus the ids of the observations it was built
+/// from — the provenance stored alongside every field of the resulting summary.
+#[derive(Debug)]
+pub struct Payload {
+    pub text: String,
+    pub sources: Vec<i64>,
+}
+
+/// Observations read into the extractive pre-pass, per payload.
+pub(crate) const MAX_ACTION_ROWS: i64 = 5000;
+
 /// Gather prompts and observations for the session into a text payload.
 /// Returns None if fewer than 3 observations exist.
 ///
 /// User prompts are untruncated (drive intent inference).
-/// Thinking blocks, observations, and content are truncated to fit context.
-pub fn gather_session_payload(conn: &Connection, session_id: &str) -> Result<Option<String>, NmemError> {
+/// Thinking blocks are truncated; action lines are picked by the extractive
+/// pre-pass (`s1_4_salience`) to fit `budget_tokens`.
+pub fn gather_session_payload(
+    conn: &Connection,
+    session_id: &str,
+    budget_tokens: usize,
+) -> Result<Option<Payload>, NmemError> {
     let obs_count: i64 = conn.query_row(
         "SELECT COUNT(*) FROM observations WHERE session_id = ?1",
         params![session_id],
@@ -130,36 +147,152 @@ pub fn gather_session_payload(conn: &Connection, session_id: &str) -> Result<Opt
         out.push('\n');
     }
 
-    // Gather observations (most recent 50, chronological)
+    // Actions: every observation, cut to the most salient within the budget
+    // left after the prompts (never under half of it)
     let mut obs_stmt = conn.prepare(
-        "SELECT obs_type, file_path, content, phase, scope, locus, novelty, metadata
+        "SELECT obs_type, file_path, content, phase, scope, locus, novelty, metadata, id
          FROM observations
          WHERE session_id = ?1
-         ORDER BY timestamp ASC LIMIT 50",
+         ORDER BY timestamp ASC LIMIT ?2",
     )?;
+    let candidates: Vec<Candidate> = obs_stmt
+        .query_map(params![session_id, MAX_ACTION_ROWS], action_candidate)?
+        .collect::<Result<_, _>>()?;
+    let budget = budget_tokens.saturating_sub(estimate_tokens(&out)).max(budget_tokens / 2);
+    let selection = s1_4_salience::select(candidates, budget);
 
     out.push_str("Actions:\n");
+    push_actions(&mut out, &selection);
+    let sources = selection.sources;
+
+    // What the assistant told the user at the end of its last turns — grounds
+    // "completed" in what was reported, not just what was attempted
+    let mut response_stmt = conn.prepare(
+        "SELECT content FROM (
+             SELECT id, content FROM responses WHERE session_id = ?1 ORDER BY id DESC LIMIT 3
+         ) ORDER BY id ASC",
+    )?;
+    let responses: Vec<String> = response_stmt
+        .query_map(params![session_id], |r| r.get(0))?
+        .collect::<Result<_, _>>()?;
+
+    if !responses.is_empty() {
+        out.push_str("\nAssistant reported:\n");
+        for r in &responses {
+            // Keep the end, where outcomes are stated
+            let n = r.chars().count();
+            let tail: String = r.chars().skip(n.saturating_sub(600)).collect();
+            out.push_str(&format!("- {}\n", tail.replace('\n', " ")));
+        }
+    }
+
+    Ok(Some(Payload { text: out, sources }))
+}
 
-    let mut rows = obs_stmt.query(params![session_id])?;
-    while let Some(row) = rows.next()? {
-        let obs_type: String = row.get(0)?;
-        let file_path: Option<String> = row.get(1)?;
-        let content: String = row.get(2)?;
-        let phase: Option<String> = row.get(3)?;
-        let scope: Option<String> = row.get(4)?;
-        let locus: Option<String> = row.get(5)?;
-        let novelty: Option<String> = row.get(6)?;
-        let metadata_str: Option<String> = row.get(7)?;
-
-        let display = format_action_line(
-            &obs_type, file_path.as_deref(), &content,
-            phase.as_deref(), scope.as_deref(), locus.as_deref(), novelty.as_deref(),
-            metadata_str.as_deref(),
-        );
-        out.push_str(&format!("{display}\n"));
+/// File references in a summary (session or episode JSON) that none of its
+/// source observations mention — likely hallucinated. References are the
+/// `files_read`/`files_edited` entries plus path-like words (a `/` and an
+/// extension) in the other fields. A reference is supported when a source
+/// observation's `file_path` equals it or ends with it, or its content
+/// contains it.
+pub fn verify_summary(
+    conn: &Connection,
+    summary: &serde_json::Value,
+    sources: &[i64],
+) -> Result<Vec<String>, NmemError> {
+    let sources_json = serde_json::to_string(sources)?;
+    let mut stmt = conn.prepare_cached(
+        "SELECT EXISTS (
+             SELECT 1 FROM observations o JOIN json_each(?1) j ON j.value = o.id
+             WHERE o.file_path = ?2 OR substr(o.file_path, -length(?2) - 1) = '/' || ?2
+                OR instr(o.content, ?2) > 0
+         )",
+    )?;
+    let mut unsupported = Vec::new();
+    for reference in file_references(summary) {
+        let found: bool = stmt.query_row(params![sources_json, reference], |r| r.get(0))?;
+        if !found {
+            unsupported.push(reference);
+        }
     }
+    Ok(unsupported)
+}
 
-    Ok(Some(out))
+fn file_references(summary: &serde_json::Value) -> Vec<String> {
+    fn strings(v: &serde_json::Value, out: &mut Vec<String>) {
+        match v {
+            serde_json::Value::String(s) => out.push(s.clone()),
+            serde_json::Value::Array(items) => items.iter().for_each(|i| strings(i, out)),
+            _ => {}
+        }
+    }
+
+    let mut refs = Vec::new();
+    let Some(fields) = summary.as_object() else {
+        return refs;
+    };
+    for (key, value) in fields {
+        let mut texts = Vec::new();
+        strings(value, &mut texts);
+        if key == "files_read" || key == "files_edited" {
+            refs.extend(texts.into_iter().map(|t| t.trim().to_string()).filter(|t| !t.is_empty()));
+            continue;
+        }
+        for text in texts {
+            for word in text.split_whitespace() {
+                let word = word.trim_matches(|c: char| !(c.is_alphanumeric() || "/._-".contains(c)));
+                let word = word.trim_end_matches('.');
+                let looks_like_path = word.contains('/')
+                    && !word.contains("://")
+                    && word.rsplit('/').next().is_some_and(|name| {
+                        name.rsplit_once('.').is_some_and(|(stem, ext)| {
+                            !stem.is_empty() && !ext.is_empty() && ext.chars().all(|c| c.is_ascii_alphanumeric())
+                        })
+                    });
+                if looks_like_path {
+                    refs.push(word.to_string());
+                }
+            }
+        }
+    }
+    refs.sort();
+    refs.dedup();
+    refs
+}
+
+/// Read an observation row (obs_type, file_path, content, phase, scope,
+/// locus, novelty, metadata, id) as a pre-pass candidate.
+pub(crate) fn action_candidate(row: &rusqlite::Row) -> rusqlite::Result<Candidate> {
+    let metadata: Option<String> = row.get(7)?;
+    let text = format_action_line(
+        &row.get::<_, String>(0)?,
+        row.get::<_, Option<String>>(1)?.as_deref(),
+        &row.get::<_, String>(2)?,
+        row.get::<_, Option<String>>(3)?.as_deref(),
+        row.get::<_, Option<String>>(4)?.as_deref(),
+        row.get::<_, Option<String>>(5)?.as_deref(),
+        row.get::<_, Option<String>>(6)?.as_deref(),
+        metadata.as_deref(),
+    );
+    let failed = metadata
+        .as_deref()
+        .and_then(|m| serde_json::from_str::<serde_json::Value>(m).ok())
+        .and_then(|m| m.get("failed").and_then(|v| v.as_bool()))
+        .unwrap_or(false);
+    Ok(Candidate { text, source: row.get(8)?, failed })
+}
+
+/// Append the selected action lines, noting what the pre-pass left out.
+pub(crate) fn push_actions(out: &mut String, selection: &s1_4_salience::Selection) {
+    for line in &selection.lines {
+        out.push_str(&format!("{line}\n"));
+    }
+    if selection.omitted > 0 {
+        out.push_str(&format!(
+            "({} of {} actions omitted as routine)\n",
+            selection.omitted, selection.total
+        ));
+    }
 }
 
 /// Format a single observation action line for LLM payloads.
@@ -215,7 +348,7 @@ pub(crate) fn format_action_line(
 }
 
 /// Strip markdown code fences from LLM response.
-fn strip_fences(text: &str) -> &str {
+pub(crate) fn strip_fences(text: &str) -> &str {
     let t = text.trim();
     if let Some(rest) = t.strip_prefix("```") {
         // Skip optional language tag on same line
@@ -240,8 +373,9 @@ pub fn summarize_session(
     }
 
     let inference_params = s1_4_inference::params_from_config(config)?;
+    let _slot = crate::s3_schedule::llm_slot()?;
     let engine = s1_4_inference::InferenceEngine::new(inference_params)?;
-    summarize_session_with_engine(conn, session_id, &engine)
+    summarize_session_with_engine(conn, session_id, &engine, config)
 }
 
 /// Summarize a session using a pre-loaded engine. Use this in loops to avoid
@@ -250,15 +384,30 @@ pub fn summarize_session_with_engine(
     conn: &Connection,
     session_id: &str,
     engine: &s1_4_inference::InferenceEngine,
+    config: &SummarizationConfig,
 ) -> Result<(), NmemError> {
-    let payload = match gather_session_payload(conn, session_id)? {
+    let payload = match gather_session_payload(conn, session_id, config.payload_tokens)? {
         Some(p) => p,
         None => return Ok(()),
     };
 
-    let user_content = USER_PROMPT_TEMPLATE.replace("{PAYLOAD}", &payload);
+    let user_content = USER_PROMPT_TEMPLATE.replace("{PAYLOAD}", &payload.text);
 
     let result = engine.generate(SYSTEM_PROMPT, &user_content)?;
+    let now = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs() as i64;
+    if let Err(e) = crate::s3_llm_usage::record(
+        conn,
+        crate::s3_llm_usage::SESSION_SUMMARY,
+        session_id,
+        None,
+        &result,
+        now,
+    ) {
+        log::warn!("llm usage not recorded: {e}");
+    }
 
     let cleaned = strip_fences(&result.text);
     let summary: SessionSummary = serde_json::from_str(cleaned)
@@ -266,8 +415,10 @@ pub fn summarize_session_with_engine(
     let summary_json = serde_json::to_string(&summary)?;
 
     conn.execute(
-        "UPDATE sessions SET summary = ?1, summarization_ms = ?2 WHERE id = ?3",
-        params![summary_json, result.total_ms as i64, session_id],
+        "UPDATE sessions SET summary = ?1, summarization_ms = ?2, summarized_at = unixepoch(),
+                summary_sources = ?4
+         WHERE id = ?3",
+        params![summary_json, result.total_ms as i64, session_id, serde_json::to_string(&payload.sources)?],
     )?;
 
     log::info!(
@@ -346,7 +497,7 @@ pub fn write_sentinel_summary(conn: &Connection, session_id: &str) -> Result<(),
         "notes": null
     });
     conn.execute(
-        "UPDATE sessions SET summary = ?1 WHERE id = ?2",
+        "UPDATE sessions SET summary = ?1, summarized_at = unixepoch() WHERE id = ?2",
         params![sentinel.to_string(), session_id],
     )?;
     Ok(())
@@ -424,7 +575,7 @@ mod tests {
             .unwrap();
         }
 
-        let result = gather_session_payload(&conn, "s1").unwrap();
+        let result = gather_session_payload(&conn, "s1", 2000).unwrap();
         assert!(result.is_none());
     }
 
@@ -452,14 +603,66 @@ mod tests {
             .unwrap();
         }
 
-        let result = gather_session_payload(&conn, "s1").unwrap();
+        let result = gather_session_payload(&conn, "s1", 2000).unwrap();
         assert!(result.is_some());
-        let payload = result.unwrap();
+        let payload = result.unwrap().text;
         assert!(payload.contains("fix the bug"));
         assert!(payload.contains("[file_read]"));
         assert!(payload.contains("src/main.rs"));
     }
 
+    #[test]
+    fn gather_includes_recent_responses() {
+        let mut conn = rusqlite::Connection::open_in_memory().unwrap();
+        crate::schema::MIGRATIONS.to_latest(&mut conn).unwrap();
+        conn.execute_batch(
+            "INSERT INTO sessions (id, project, started_at) VALUES ('s1', 'test', 1000);
+             INSERT INTO observations (session_id, timestamp, obs_type, source_event, content)
+                 VALUES ('s1', 1001, 'command', 'PostToolUse', 'cargo test'),
+                        ('s1', 1002, 'command', 'PostToolUse', 'cargo test'),
+                        ('s1', 1003, 'command', 'PostToolUse', 'cargo test');
+             INSERT INTO responses (session_id, timestamp, content) VALUES
+                 ('s1', 1010, 'first turn'), ('s1', 1020, 'second turn'),
+                 ('s1', 1030, 'third turn'), ('s1', 1040, 'Tests pass.\nThe migration is still TODO.');",
+        )
+        .unwrap();
+
+        let payload = gather_session_payload(&conn, "s1", 2000).unwrap().unwrap();
+        assert_eq!(payload.sources, vec![1, 2, 3]);
+        let reported = payload.text.split("Assistant reported:").nth(1).unwrap();
+        assert!(!reported.contains("first turn"));
+        assert!(reported.find("second turn").unwrap() < reported.find("third turn").unwrap());
+        assert!(reported.contains("- Tests pass. The migration is still TODO."));
+    }
+
+    #[test]
+    fn verify_summary_flags_unsourced_files() {
+        let mut conn = rusqlite::Connection::open_in_memory().unwrap();
+        crate::schema::MIGRATIONS.to_latest(&mut conn).unwrap();
+        conn.execute_batch(
+            "INSERT INTO sessions (id, project, started_at) VALUES ('s1', 'test', 1000);
+             INSERT INTO observations (session_id, timestamp, obs_type, source_event, content, file_path)
+                 VALUES ('s1', 1001, 'file_read', 'PostToolUse', 'read', '/home/u/proj/src/db.rs'),
+                        ('s1', 1002, 'command', 'PostToolUse', 'cargo test -p core tests/it.rs', NULL),
+                        ('s1', 1003, 'file_edit', 'PostToolUse', 'edit', '/home/u/proj/src/outside.rs');",
+        )
+        .unwrap();
+        let summary = serde_json::json!({
+            "intent": "tighten db",
+            "learned": ["src/db.rs needs WAL", "see https://sqlite.org/wal.html", "investigate/execute split"],
+            "completed": ["ran tests/it.rs"],
+            "files_read": ["src/db.rs", "src/ghost.rs"],
+            "files_edited": ["src/outside.rs"],
+            "notes": "touched docs/plan.md too"
+        });
+        assert_eq!(file_references(&summary), vec![
+            "docs/plan.md", "src/db.rs", "src/ghost.rs", "src/outside.rs", "tests/it.rs",
+        ]);
+        // Observation 3 is outside the window, so src/outside.rs is unsupported too
+        let unverified = verify_summary(&conn, &summary, &[1, 2]).unwrap();
+        assert_eq!(unverified, vec!["docs/plan.md", "src/ghost.rs", "src/outside.rs"]);
+    }
+
     #[test]
     fn disabled_config_returns_ok() {
         let conn = rusqlite::Connection::open_in_memory().unwrap();
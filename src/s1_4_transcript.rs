@@ -1,5 +1,6 @@
+use crate::s5_filter::SecretFilter;
 use crate::NmemError;
-use rusqlite::{Connection, params};
+use rusqlite::{Connection, OptionalExtension, params};
 use std::io::BufRead;
 use std::path::Path;
 
@@ -31,6 +32,7 @@ pub fn scan_transcript(
     let reader = std::io::BufReader::new(file);
 
     let mut new_cursor = cursor;
+    let (mut model, mut agent_version) = (None, None);
     for (i, line) in reader.lines().enumerate() {
         let line_num = i as i64;
         if line_num < cursor {
@@ -59,6 +61,9 @@ pub fn scan_transcript(
             new_cursor = line_num + 1;
             continue;
         }
+        let (m, v) = crate::s3_model::from_transcript_entry(&entry);
+        model = m.or(model);
+        agent_version = v.or(agent_version);
 
         let content_blocks = entry
             .get("message")
@@ -102,6 +107,8 @@ pub fn scan_transcript(
         new_cursor = line_num + 1;
     }
 
+    crate::s3_model::set_identity(conn, session_id, model.as_deref(), agent_version.as_deref())?;
+
     // Update cursor
     conn.execute(
         "INSERT OR REPLACE INTO _cursor (session_id, line_number) VALUES (?1, ?2)",
@@ -125,3 +132,95 @@ pub fn get_current_prompt_id(
         .ok();
     Ok(id)
 }
+
+/// The assistant's text for the last turn: every text block after the most
+/// recent user prompt (tool results don't count as prompts), joined in order.
+/// `None` when the turn produced no text.
+pub fn final_response(transcript_path: &str) -> Result<Option<String>, NmemError> {
+    let path = Path::new(transcript_path);
+    if !path.exists() {
+        return Ok(None);
+    }
+    let reader = std::io::BufReader::new(std::fs::File::open(path)?);
+
+    let mut turn: Vec<String> = Vec::new();
+    for line in reader.lines() {
+        let Ok(line) = line else { continue };
+        let Ok(entry) = serde_json::from_str::<serde_json::Value>(line.trim()) else {
+            continue;
+        };
+        let content = entry.get("message").and_then(|m| m.get("content"));
+        match entry.get("type").and_then(|v| v.as_str()) {
+            Some("user") if is_user_prompt(content) => turn.clear(),
+            Some("assistant") => {
+                let blocks = content.and_then(|c| c.as_array()).into_iter().flatten();
+                for block in blocks {
+                    if block.get("type").and_then(|v| v.as_str()) != Some("text") {
+                        continue;
+                    }
+                    if let Some(text) = block.get("text").and_then(|v| v.as_str())
+                        && !text.trim().is_empty()
+                    {
+                        turn.push(text.trim().to_string());
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+    Ok((!turn.is_empty()).then(|| turn.join("\n\n")))
+}
+
+/// A user entry is a prompt unless it only carries tool results.
+fn is_user_prompt(content: Option<&serde_json::Value>) -> bool {
+    match content {
+        Some(serde_json::Value::String(_)) => true,
+        Some(serde_json::Value::Array(blocks)) => blocks
+            .iter()
+            .any(|b| b.get("type").and_then(|v| v.as_str()) != Some("tool_result")),
+        _ => false,
+    }
+}
+
+/// Store the last turn's response for the session, keeping its final
+/// `max_chars` characters — closing claims come at the end. Secrets are
+/// redacted. A response identical to the session's previous one is skipped.
+/// Returns whether a row was written.
+pub fn capture_response(
+    conn: &Connection,
+    filter: &SecretFilter,
+    session_id: &str,
+    transcript_path: &str,
+    max_chars: usize,
+    ts: i64,
+) -> Result<bool, NmemError> {
+    if max_chars == 0 {
+        return Ok(false);
+    }
+    let Some(text) = final_response(transcript_path)? else {
+        return Ok(false);
+    };
+    let (text, _) = filter.redact(&text);
+    let total = text.chars().count();
+    let truncated = total > max_chars;
+    let content: String = text.chars().skip(total.saturating_sub(max_chars)).collect();
+
+    let previous: Option<String> = conn
+        .query_row(
+            "SELECT content FROM responses WHERE session_id = ?1 ORDER BY id DESC LIMIT 1",
+            params![session_id],
+            |r| r.get(0),
+        )
+        .optional()?;
+    if previous.as_deref() == Some(content.as_str()) {
+        return Ok(false);
+    }
+
+    conn.execute(
+        "INSERT INTO responses (session_id, prompt_id, timestamp, content, truncated)
+         VALUES (?1, (SELECT MAX(id) FROM prompts WHERE session_id = ?1 AND source = 'user'),
+                 ?2, ?3, ?4)",
+        params![session_id, ts, content, truncated],
+    )?;
+    Ok(true)
+}
@@ -0,0 +1,481 @@
+//! File rename tracking — `file_aliases` maps old paths to new ones.
+//!
+//! Renames are detected from `mv` / `git mv` commands at record time, from a
+//! file deleted and a same-named file created by consecutive observations
+//! (`rm old/x.rs` then a Write of `new/x.rs`, in either order), and from
+//! git's similarity index when a commit is recorded. Readers call
+//! [`alias_chain`] to expand a path into every name it has had, and
+//! [`resolve_path_query`] to find the recorded path an agent meant when it
+//! asks with a relative path or a bare file name.
+
+use crate::NmemError;
+use rusqlite::{Connection, params};
+use serde::Serialize;
+use std::collections::HashSet;
+use std::path::Path;
+
+/// Store a rename. No-op for self-renames and already-known pairs.
+pub fn record_alias(
+    conn: &Connection,
+    old_path: &str,
+    new_path: &str,
+    source: &str,
+    ts: i64,
+) -> Result<bool, NmemError> {
+    if old_path == new_path {
+        return Ok(false);
+    }
+    let n = conn.execute(
+        "INSERT OR IGNORE INTO file_aliases (old_path, new_path, source, detected_at)
+         VALUES (?1, ?2, ?3, ?4)",
+        params![old_path, new_path, source, ts],
+    )?;
+    Ok(n > 0)
+}
+
+/// Every name `path` has had: `path` itself first, then prior names (nearest first).
+/// Follows chains (a → b → c) and tolerates cycles (a → b → a).
+pub fn alias_chain(conn: &Connection, path: &str) -> Result<Vec<String>, NmemError> {
+    let mut stmt = conn.prepare_cached(
+        "SELECT old_path FROM file_aliases WHERE new_path = ?1 ORDER BY detected_at DESC",
+    )?;
+    let mut chain = vec![path.to_string()];
+    let mut seen: HashSet<String> = chain.iter().cloned().collect();
+    let mut i = 0;
+    while i < chain.len() {
+        let olds: Vec<String> = stmt
+            .query_map(params![chain[i]], |r| r.get(0))?
+            .collect::<Result<_, _>>()?;
+        for old in olds {
+            if seen.insert(old.clone()) {
+                chain.push(old);
+            }
+        }
+        i += 1;
+    }
+    Ok(chain)
+}
+
+/// How a queried path was matched to a recorded one.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum PathMatch {
+    Exact,
+    /// The query is a trailing run of path segments (`src/db.rs`).
+    Suffix,
+    /// Only the file name matched; the query's directories did not.
+    Basename,
+}
+
+/// A recorded path a query could mean.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct PathCandidate {
+    pub path: String,
+    pub touches: i64,
+    pub last_at: i64,
+}
+
+#[derive(Debug, Default, PartialEq)]
+pub struct PathResolution {
+    /// The recorded path, when the query names exactly one.
+    pub resolved: Option<String>,
+    pub matched: Option<PathMatch>,
+    /// Recorded paths the query could mean, most touched first, when it
+    /// names more than one.
+    pub candidates: Vec<PathCandidate>,
+}
+
+/// Most candidate paths listed for an ambiguous query.
+const MAX_CANDIDATES: i64 = 10;
+
+/// Recorded paths ending in `/suffix` (or equal to it), most touched first;
+/// those `project` touched are counted in the last column.
+fn paths_ending_with(
+    conn: &Connection,
+    suffix: &str,
+    project: Option<&str>,
+) -> Result<Vec<(PathCandidate, i64)>, NmemError> {
+    let mut stmt = conn.prepare_cached(
+        "SELECT o.file_path, COUNT(*), MAX(o.timestamp), COALESCE(SUM(s.project = ?2), 0)
+         FROM observations o JOIN sessions s ON s.id = o.session_id
+         WHERE o.file_path IS NOT NULL
+           AND (o.file_path = ?1 OR substr(o.file_path, -length(?1) - 1) = '/' || ?1)
+         GROUP BY o.file_path
+         ORDER BY COUNT(*) DESC, MAX(o.timestamp) DESC
+         LIMIT ?3",
+    )?;
+    let rows = stmt
+        .query_map(params![suffix, project, MAX_CANDIDATES], |r| {
+            Ok((PathCandidate { path: r.get(0)?, touches: r.get(1)?, last_at: r.get(2)? }, r.get(3)?))
+        })?
+        .collect::<Result<_, _>>()?;
+    Ok(rows)
+}
+
+/// Find the recorded path `query` means. A path recorded as-is (or under an
+/// earlier name) matches exactly; otherwise the query is tried as a path
+/// suffix, then by file name alone. Several matches narrow to those
+/// `project` touched; if more than one remains, they are returned as
+/// candidates instead of a guess.
+pub fn resolve_path_query(conn: &Connection, query: &str, project: Option<&str>) -> Result<PathResolution, NmemError> {
+    let exact: bool = conn.query_row(
+        "SELECT EXISTS (SELECT 1 FROM observations WHERE file_path = ?1)
+             OR EXISTS (SELECT 1 FROM file_aliases WHERE new_path = ?1)",
+        params![query],
+        |r| r.get(0),
+    )?;
+    if exact {
+        return Ok(PathResolution { resolved: Some(query.to_string()), matched: Some(PathMatch::Exact), ..Default::default() });
+    }
+
+    let trimmed = query.trim_start_matches("./").trim_start_matches('/');
+    let basename = trimmed.rsplit('/').next().unwrap_or(trimmed);
+    if basename.is_empty() {
+        return Ok(PathResolution::default());
+    }
+    let mut attempts = vec![(trimmed, PathMatch::Suffix)];
+    if basename != trimmed {
+        attempts.push((basename, PathMatch::Basename));
+    }
+    for (suffix, kind) in attempts {
+        let mut found = paths_ending_with(conn, suffix, project)?;
+        if found.len() > 1 && project.is_some() && found.iter().any(|(_, in_project)| *in_project > 0) {
+            found.retain(|(_, in_project)| *in_project > 0);
+        }
+        match found.len() {
+            0 => continue,
+            1 => {
+                let (c, _) = found.remove(0);
+                return Ok(PathResolution { resolved: Some(c.path), matched: Some(kind), candidates: Vec::new() });
+            }
+            _ => {
+                return Ok(PathResolution {
+                    resolved: None,
+                    matched: Some(kind),
+                    candidates: found.into_iter().map(|(c, _)| c).collect(),
+                });
+            }
+        }
+    }
+    Ok(PathResolution::default())
+}
+
+/// Parse `mv SRC DST` / `git mv SRC DST` out of a shell command.
+/// Returns absolute (old, new) paths, resolved against `cwd` as changed by
+/// any `cd` before the move. Only simple two-operand moves are recognized;
+/// globs and multi-source moves are ignored, as are moves after a `cd` whose
+/// target can't be resolved.
+pub fn parse_move_command(command: &str, cwd: &str) -> Vec<(String, String)> {
+    segments_with_dir(command, cwd)
+        .into_iter()
+        .filter_map(|(dir, segment)| parse_move_segment(segment, &dir?))
+        .collect()
+}
+
+/// Parse `rm FILE` / `git rm FILE` out of a shell command: the absolute path
+/// of each single file removed, resolved like [`parse_move_command`].
+/// Recursive and multi-operand removals are ignored.
+pub fn parse_delete_command(command: &str, cwd: &str) -> Vec<String> {
+    segments_with_dir(command, cwd)
+        .into_iter()
+        .filter_map(|(dir, segment)| parse_delete_segment(segment, &dir?))
+        .collect()
+}
+
+/// The `&&`/`;`-separated commands of `command`, each with the directory it
+/// runs in: `cwd`, then wherever each `cd` leads. `None` once a `cd` goes
+/// somewhere that can't be resolved lexically (`cd`, `cd -`, `cd ~/x`,
+/// `cd $DIR`).
+fn segments_with_dir<'a>(command: &'a str, cwd: &str) -> Vec<(Option<String>, &'a str)> {
+    let mut dir = Some(cwd.to_string());
+    let mut out = Vec::new();
+    for segment in command.split("&&").flat_map(|s| s.split(';')) {
+        let segment = segment.trim();
+        let words: Vec<&str> = segment.split_whitespace().collect();
+        if let ["cd", rest @ ..] = words.as_slice() {
+            dir = match rest {
+                [target] if !target.starts_with(['~', '-']) && !target.contains(['$', '`', '*', '?']) => {
+                    dir.map(|d| resolve(&d, target.trim_matches(|c| c == '"' || c == '\'')))
+                }
+                _ => None,
+            };
+            continue;
+        }
+        out.push((dir.clone(), segment));
+    }
+    out
+}
+
+fn parse_delete_segment(segment: &str, cwd: &str) -> Option<String> {
+    let words: Vec<&str> = segment.split_whitespace().collect();
+    let args = match words.as_slice() {
+        ["git", "rm", rest @ ..] => rest,
+        ["rm", rest @ ..] => rest,
+        _ => return None,
+    };
+    if args.iter().any(|a| a.starts_with('-') && a.contains(['r', 'R'])) {
+        return None;
+    }
+    let operands: Vec<&str> = args
+        .iter()
+        .filter(|a| !a.starts_with('-'))
+        .map(|a| a.trim_matches(|c| c == '"' || c == '\''))
+        .collect();
+    let [path] = operands.as_slice() else {
+        return None;
+    };
+    if path.contains(['*', '?', '$', '`', '|', '>']) {
+        return None;
+    }
+    Some(resolve(cwd, path))
+}
+
+/// Record a rename made as a delete and a create: observation `obs_id` and
+/// the one before it in the session are a successful `rm` of a single file
+/// nmem has history for and a Write creating a new file of the same name, in
+/// either order. Returns whether an alias was added.
+pub fn record_delete_create(conn: &Connection, session_id: &str, obs_id: i64, ts: i64) -> Result<bool, NmemError> {
+    let mut stmt = conn.prepare_cached(
+        "SELECT id, obs_type, tool_name, file_path, content, cwd FROM observations
+         WHERE session_id = ?1 AND id <= ?2 ORDER BY id DESC LIMIT 2",
+    )?;
+    type Row = (i64, String, Option<String>, Option<String>, String, Option<String>);
+    let rows: Vec<Row> = stmt
+        .query_map(params![session_id, obs_id], |r| {
+            Ok((r.get(0)?, r.get(1)?, r.get(2)?, r.get(3)?, r.get(4)?, r.get(5)?))
+        })?
+        .collect::<Result<_, _>>()?;
+    let [a, b] = rows.as_slice() else {
+        return Ok(false);
+    };
+    let (write, bash) = match (a.1.as_str(), b.2.as_deref(), b.1.as_str(), a.2.as_deref()) {
+        ("file_write", Some("Bash"), _, _) => (a, b),
+        (_, _, "file_write", Some("Bash")) => (b, a),
+        _ => return Ok(false),
+    };
+    let Some(new) = write.3.as_deref() else {
+        return Ok(false);
+    };
+    let deleted = parse_delete_command(&bash.4, bash.5.as_deref().unwrap_or(""));
+    let [old] = deleted.as_slice() else {
+        return Ok(false);
+    };
+    if old == new || Path::new(old).file_name() != Path::new(new).file_name() {
+        return Ok(false);
+    }
+    let (old_known, new_existed): (bool, bool) = conn.query_row(
+        "SELECT EXISTS (SELECT 1 FROM observations WHERE file_path = ?1),
+                EXISTS (SELECT 1 FROM observations WHERE file_path = ?2 AND id < ?3)",
+        params![old, new, write.0],
+        |r| Ok((r.get(0)?, r.get(1)?)),
+    )?;
+    if !old_known || new_existed {
+        return Ok(false);
+    }
+    record_alias(conn, old, new, "delete_create", ts)
+}
+
+fn parse_move_segment(segment: &str, cwd: &str) -> Option<(String, String)> {
+    let words: Vec<&str> = segment.split_whitespace().collect();
+    let args = match words.as_slice() {
+        ["git", "mv", rest @ ..] => rest,
+        ["mv", rest @ ..] => rest,
+        _ => return None,
+    };
+    let operands: Vec<&str> = args
+        .iter()
+        .filter(|a| !a.starts_with('-'))
+        .map(|a| a.trim_matches(|c| c == '"' || c == '\''))
+        .collect();
+    let [src, dst] = operands.as_slice() else {
+        return None;
+    };
+    if [src, dst].iter().any(|p| p.contains(['*', '?', '$', '`', '|', '>'])) {
+        return None;
+    }
+
+    let old = resolve(cwd, src);
+    let mut new = resolve(cwd, dst);
+    // `mv file dir/` moves into the directory, keeping the file name
+    if dst.ends_with('/') || Path::new(&new).is_dir() {
+        let name = Path::new(&old).file_name()?.to_string_lossy().to_string();
+        new = Path::new(&new).join(name).to_string_lossy().to_string();
+    }
+    Some((old, new))
+}
+
+fn resolve(cwd: &str, p: &str) -> String {
+    let path = Path::new(p);
+    let joined = if path.is_absolute() || cwd.is_empty() {
+        path.to_path_buf()
+    } else {
+        Path::new(cwd).join(path)
+    };
+    // Lexical normalization only — the old path no longer exists to canonicalize
+    let mut out = std::path::PathBuf::new();
+    for comp in joined.components() {
+        match comp {
+            std::path::Component::CurDir => {}
+            std::path::Component::ParentDir => {
+                out.pop();
+            }
+            c => out.push(c),
+        }
+    }
+    out.to_string_lossy().trim_end_matches('/').to_string()
+}
+
+/// Record renames in the HEAD commit of the repo containing `cwd`.
+/// Called after a `git_commit` observation; returns how many were new.
+pub fn record_head_renames(conn: &Connection, cwd: &str, ts: i64) -> Result<usize, NmemError> {
+    let renames = crate::s1_git::head_renames(Path::new(cwd))?;
+    let mut added = 0;
+    for (old, new) in renames {
+        if record_alias(conn, &old, &new, "git", ts)? {
+            added += 1;
+        }
+    }
+    Ok(added)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn conn() -> Connection {
+        let mut conn = Connection::open_in_memory().unwrap();
+        crate::schema::MIGRATIONS.to_latest(&mut conn).unwrap();
+        conn
+    }
+
+    #[test]
+    fn parses_mv_and_git_mv() {
+        assert_eq!(
+            parse_move_command("git mv src/old.rs src/new.rs", "/repo"),
+            vec![("/repo/src/old.rs".into(), "/repo/src/new.rs".into())]
+        );
+        assert_eq!(
+            parse_move_command("cd /repo && mv -f ./a.txt ../b.txt", "/repo/sub"),
+            vec![("/repo/a.txt".into(), "/b.txt".into())]
+        );
+        assert_eq!(
+            parse_move_command("mv a b; cd sub; mv c ../d", "/repo"),
+            vec![("/repo/a".into(), "/repo/b".into()), ("/repo/sub/c".into(), "/repo/d".into())]
+        );
+        assert_eq!(
+            parse_move_command("mv a.rs lib/", "/repo"),
+            vec![("/repo/a.rs".into(), "/repo/lib/a.rs".into())]
+        );
+    }
+
+    #[test]
+    fn ignores_non_simple_moves() {
+        assert!(parse_move_command("mv *.rs dest/", "/repo").is_empty());
+        assert!(parse_move_command("mv a b c/", "/repo").is_empty());
+        assert!(parse_move_command("echo mv a b", "/repo").is_empty());
+        // After a cd that can't be resolved, the move's directory is unknown
+        assert!(parse_move_command("cd $OUT && mv a b", "/repo").is_empty());
+        assert!(parse_move_command("cd && mv a b", "/repo").is_empty());
+    }
+
+    #[test]
+    fn parses_single_file_deletes() {
+        assert_eq!(parse_delete_command("cd src && rm -f old/x.rs", "/repo"), vec!["/repo/src/old/x.rs"]);
+        assert_eq!(parse_delete_command("git rm lib.rs", "/repo"), vec!["/repo/lib.rs"]);
+        assert!(parse_delete_command("rm -rf build", "/repo").is_empty());
+        assert!(parse_delete_command("rm a b", "/repo").is_empty());
+        assert!(parse_delete_command("rm *.o", "/repo").is_empty());
+    }
+
+    #[test]
+    fn consecutive_delete_and_create_is_a_rename() {
+        let conn = conn();
+        let obs = |ty: &str, tool: &str, path: Option<&str>, content: &str| {
+            conn.execute(
+                "INSERT INTO observations (session_id, timestamp, obs_type, source_event, tool_name, file_path, content, cwd)
+                 VALUES ('s', 1, ?1, 'PostToolUse', ?2, ?3, ?4, '/w')",
+                params![ty, tool, path, content],
+            )
+            .unwrap();
+            conn.last_insert_rowid()
+        };
+        conn.execute("INSERT INTO sessions (id, project, started_at) VALUES ('s', 'w', 0)", []).unwrap();
+        obs("file_edit", "Edit", Some("/w/old/x.rs"), "e");
+        obs("command", "Bash", None, "rm old/x.rs");
+        let write = obs("file_write", "Write", Some("/w/new/x.rs"), "w");
+        assert!(record_delete_create(&conn, "s", write, 2).unwrap());
+        assert_eq!(alias_chain(&conn, "/w/new/x.rs").unwrap(), vec!["/w/new/x.rs", "/w/old/x.rs"]);
+
+        // Create first, then delete
+        obs("file_edit", "Edit", Some("/w/a/y.rs"), "e");
+        obs("file_write", "Write", Some("/w/b/y.rs"), "w");
+        let rm = obs("command", "Bash", None, "rm -f a/y.rs");
+        assert!(record_delete_create(&conn, "s", rm, 3).unwrap());
+
+        // A different file name, or a file nmem never saw, is not a rename
+        obs("command", "Bash", None, "rm old/x.rs");
+        let other = obs("file_write", "Write", Some("/w/new/z.rs"), "w");
+        assert!(!record_delete_create(&conn, "s", other, 4).unwrap());
+        obs("command", "Bash", None, "rm unseen/q.rs");
+        let unseen = obs("file_write", "Write", Some("/w/new/q.rs"), "w");
+        assert!(!record_delete_create(&conn, "s", unseen, 5).unwrap());
+    }
+
+    #[test]
+    fn chain_follows_multiple_renames_and_cycles() {
+        let conn = conn();
+        record_alias(&conn, "/a", "/b", "command", 1).unwrap();
+        record_alias(&conn, "/b", "/c", "git", 2).unwrap();
+        assert_eq!(alias_chain(&conn, "/c").unwrap(), vec!["/c", "/b", "/a"]);
+
+        // Renamed back — chain must terminate
+        record_alias(&conn, "/c", "/a", "command", 3).unwrap();
+        let chain = alias_chain(&conn, "/a").unwrap();
+        assert_eq!(chain.len(), 3);
+        assert_eq!(chain[0], "/a");
+    }
+
+    #[test]
+    fn resolves_relative_paths_and_basenames() {
+        let conn = conn();
+        conn.execute_batch(
+            "INSERT INTO sessions (id, project, started_at) VALUES ('a', 'app', 1), ('b', 'lib', 2);
+             INSERT INTO observations (session_id, timestamp, obs_type, source_event, file_path, content) VALUES
+               ('a', 10, 'file_edit', 'PostToolUse', '/w/app/src/db.rs', 'e'),
+               ('a', 11, 'file_read', 'PostToolUse', '/w/app/src/db.rs', 'r'),
+               ('b', 12, 'file_read', 'PostToolUse', '/w/lib/src/db.rs', 'r'),
+               ('a', 13, 'file_read', 'PostToolUse', '/w/app/Cargo.toml', 'r');",
+        )
+        .unwrap();
+
+        let exact = resolve_path_query(&conn, "/w/app/Cargo.toml", None).unwrap();
+        assert_eq!((exact.resolved.as_deref(), exact.matched), (Some("/w/app/Cargo.toml"), Some(PathMatch::Exact)));
+
+        let suffix = resolve_path_query(&conn, "./Cargo.toml", None).unwrap();
+        assert_eq!((suffix.resolved.as_deref(), suffix.matched), (Some("/w/app/Cargo.toml"), Some(PathMatch::Suffix)));
+
+        // Two projects have a src/db.rs: ambiguous unless the project narrows it
+        let ambiguous = resolve_path_query(&conn, "src/db.rs", None).unwrap();
+        assert_eq!(ambiguous.resolved, None);
+        let paths: Vec<&str> = ambiguous.candidates.iter().map(|c| c.path.as_str()).collect();
+        assert_eq!(paths, vec!["/w/app/src/db.rs", "/w/lib/src/db.rs"]);
+        assert_eq!(ambiguous.candidates[0].touches, 2);
+        let narrowed = resolve_path_query(&conn, "src/db.rs", Some("lib")).unwrap();
+        assert_eq!(narrowed.resolved.as_deref(), Some("/w/lib/src/db.rs"));
+
+        // Wrong directory: falls back to the file name
+        let basename = resolve_path_query(&conn, "crates/core/Cargo.toml", None).unwrap();
+        assert_eq!((basename.resolved.as_deref(), basename.matched), (Some("/w/app/Cargo.toml"), Some(PathMatch::Basename)));
+
+        // Suffixes match whole segments only
+        assert_eq!(resolve_path_query(&conn, "b.rs", None).unwrap(), PathResolution::default());
+    }
+
+    #[test]
+    fn self_and_duplicate_aliases_are_ignored() {
+        let conn = conn();
+        assert!(!record_alias(&conn, "/a", "/a", "command", 1).unwrap());
+        assert!(record_alias(&conn, "/a", "/b", "command", 1).unwrap());
+        assert!(!record_alias(&conn, "/a", "/b", "git", 2).unwrap());
+    }
+}
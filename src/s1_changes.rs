@@ -0,0 +1,230 @@
+//! S1 Operations — change feed for export and sync.
+//!
+//! Triggers on `sessions`, `prompts`, `observations`, and `work_units` append
+//! one `changelog` row per insert, update, or delete: table, row id, and a
+//! monotonically increasing `seq`. `nmem changes --since <seq>` reads the
+//! entries past that checkpoint, collapses them to one per row, and prints
+//! JSON lines — `upsert` with the row as it is now, or `delete` with only
+//! the id — so a consumer ships deltas instead of rescanning tables. An
+//! observation's `content` is its full text, chunks included, and a change
+//! to its chunks alone logs it as updated. The
+//! last `seq` printed is the next checkpoint. Rows that existed before the
+//! changelog did are not in it: a full export is the baseline.
+//!
+//! Home paths in the rows are rewritten per `[filter] anonymize_paths`
+//! (`s5_anonymize`), one mapping per run; `--raw-paths` keeps them.
+//!
+//! `maintain --sweep` prunes entries older than `RETENTION_DAYS`; a
+//! checkpoint from before the pruned horizon is rejected, not silently
+//! answered with a partial feed.
+
+#[cfg(feature = "cli")]
+use crate::cli::ChangesArgs;
+#[cfg(feature = "cli")]
+use crate::s5_anonymize::PathAnonymization;
+use crate::NmemError;
+use rusqlite::types::ValueRef;
+use rusqlite::{Connection, OptionalExtension, params};
+use serde::Serialize;
+#[cfg(feature = "cli")]
+use std::path::Path;
+
+/// Tables the changelog triggers cover.
+pub const TRACKED: [&str; 4] = ["sessions", "prompts", "observations", "work_units"];
+
+/// Days of changelog kept by `maintain --sweep`.
+pub const RETENTION_DAYS: i64 = 90;
+
+#[derive(Debug, Serialize, PartialEq)]
+pub struct Change {
+    /// Checkpoint after this change.
+    pub seq: i64,
+    pub table: String,
+    pub id: String,
+    /// `upsert` or `delete`.
+    pub op: &'static str,
+    pub changed_at: i64,
+    /// Current row for an upsert.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub row: Option<serde_json::Value>,
+}
+
+/// Newest `seq`, the checkpoint a consumer starting now would save.
+pub fn checkpoint(conn: &Connection) -> Result<i64, NmemError> {
+    let seq: Option<i64> = conn
+        .query_row("SELECT seq FROM sqlite_sequence WHERE name = 'changelog'", [], |r| r.get(0))
+        .optional()?;
+    Ok(seq.unwrap_or(0))
+}
+
+/// Changes after checkpoint `since`, one per row (its latest), in `seq`
+/// order, at most `limit`.
+pub fn changes_since(conn: &Connection, since: i64, limit: i64) -> Result<Vec<Change>, NmemError> {
+    let oldest: Option<i64> = conn.query_row("SELECT MIN(seq) FROM changelog", [], |r| r.get(0))?;
+    let horizon = oldest.unwrap_or_else(|| checkpoint(conn).unwrap_or(0) + 1);
+    if since + 1 < horizon {
+        return Err(NmemError::Config(format!(
+            "checkpoint {since} predates the changelog (oldest entry {horizon}); re-export in full"
+        )));
+    }
+
+    let mut stmt = conn.prepare(
+        "SELECT MAX(seq), tbl, row_id, MAX(changed_at) FROM changelog
+         WHERE seq > ?1 GROUP BY tbl, row_id ORDER BY MAX(seq) LIMIT ?2",
+    )?;
+    let entries: Vec<(i64, String, String, i64)> = stmt
+        .query_map(params![since, limit], |r| Ok((r.get(0)?, r.get(1)?, r.get(2)?, r.get(3)?)))?
+        .collect::<Result<_, _>>()?;
+
+    let mut out = Vec::with_capacity(entries.len());
+    for (seq, table, id, changed_at) in entries {
+        if !TRACKED.contains(&table.as_str()) {
+            continue;
+        }
+        let row = current_row(conn, &table, &id)?;
+        out.push(Change {
+            seq,
+            table,
+            id,
+            op: if row.is_some() { "upsert" } else { "delete" },
+            changed_at,
+            row,
+        });
+    }
+    Ok(out)
+}
+
+/// `table`'s row `id` as a JSON object, if it still exists.
+fn current_row(conn: &Connection, table: &str, id: &str) -> Result<Option<serde_json::Value>, NmemError> {
+    // Integer keys bind as integers to use the primary key; session ids are
+    // text, and the column's affinity converts a numeric-looking one back
+    let key: rusqlite::types::Value = id.parse::<i64>().map(Into::into).unwrap_or_else(|_| id.to_string().into());
+    // Observations keep only their head in `content`; the later column of
+    // the same name overwrites it with the full text
+    let sql = if table == "observations" {
+        concat!(
+            "SELECT o.*, ",
+            crate::s1_chunk::full_content_sql!(),
+            " AS content FROM observations o WHERE o.id = ?1"
+        )
+        .to_string()
+    } else {
+        format!("SELECT * FROM {table} WHERE id = ?1")
+    };
+    let mut stmt = conn.prepare_cached(&sql)?;
+    let names: Vec<String> = stmt.column_names().into_iter().map(String::from).collect();
+    Ok(stmt
+        .query_row(params![key], |r| {
+            let mut obj = serde_json::Map::new();
+            for (i, name) in names.iter().enumerate() {
+                let value = match r.get_ref(i)? {
+                    ValueRef::Null => serde_json::Value::Null,
+                    ValueRef::Integer(n) => n.into(),
+                    ValueRef::Real(f) => f.into(),
+                    ValueRef::Text(t) => String::from_utf8_lossy(t).into_owned().into(),
+                    ValueRef::Blob(_) => continue,
+                };
+                obj.insert(name.clone(), value);
+            }
+            Ok(serde_json::Value::Object(obj))
+        })
+        .optional()?)
+}
+
+/// Drop changelog entries recorded before `cutoff`. Returns how many.
+pub fn prune(conn: &Connection, cutoff: i64) -> Result<usize, NmemError> {
+    Ok(conn.execute("DELETE FROM changelog WHERE changed_at < ?1", params![cutoff])?)
+}
+
+#[cfg(feature = "cli")]
+pub fn handle_changes(db_path: &Path, args: &ChangesArgs) -> Result<(), NmemError> {
+    let config = crate::s5_config::load_config().unwrap_or_default();
+    let conn = crate::db::open_db_readonly(db_path)?;
+    let mode = if args.raw_paths { PathAnonymization::Off } else { config.filter.anonymize_paths };
+    let mut paths = crate::s5_anonymize::PathMap::for_db(&conn, mode)?;
+    let mut changes = changes_since(&conn, args.since, args.limit.max(1))?;
+    let next = changes.last().map(|c| c.seq).unwrap_or(args.since.max(checkpoint(&conn)?));
+    for change in &mut changes {
+        if let Some(row) = change.row.as_mut() {
+            paths.json(row);
+        }
+        println!("{}", serde_json::to_string(change)?);
+    }
+    log::info!("{} changes; next checkpoint {next}", changes.len());
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn feed_collapses_to_latest_state_per_row() {
+        let mut conn = Connection::open_in_memory().unwrap();
+        crate::schema::MIGRATIONS.to_latest(&mut conn).unwrap();
+        conn.execute_batch(
+            "INSERT INTO sessions (id, project, started_at) VALUES ('s1', 'p', 100);
+             INSERT INTO observations (id, session_id, timestamp, obs_type, source_event, content)
+                 VALUES (1, 's1', 110, 'command', 'PostToolUse', 'ls');",
+        )
+        .unwrap();
+        let start = checkpoint(&conn).unwrap();
+        assert_eq!(start, 2);
+
+        conn.execute_batch(
+            "INSERT INTO observations (id, session_id, timestamp, obs_type, source_event, content)
+                 VALUES (2, 's1', 120, 'command', 'PostToolUse', 'make');
+             UPDATE observations SET is_pinned = 1 WHERE id = 2;
+             DELETE FROM observations WHERE id = 1;",
+        )
+        .unwrap();
+
+        let changes = changes_since(&conn, start, 100).unwrap();
+        let summary: Vec<(&str, &str, &str)> =
+            changes.iter().map(|c| (c.table.as_str(), c.id.as_str(), c.op)).collect();
+        assert_eq!(summary, vec![("observations", "2", "upsert"), ("observations", "1", "delete")]);
+        assert_eq!(changes[0].row.as_ref().unwrap()["is_pinned"], 1);
+        assert_eq!(changes[0].row.as_ref().unwrap()["content"], "make");
+        assert!(changes[1].row.is_none());
+
+        // Everything since the beginning, sessions keyed by text
+        let all = changes_since(&conn, 0, 100).unwrap();
+        assert_eq!(all[0].table, "sessions");
+        assert_eq!(all[0].row.as_ref().unwrap()["project"], "p");
+
+        // A checkpoint before the pruned horizon is refused
+        conn.execute("UPDATE changelog SET changed_at = 0 WHERE seq <= 3", []).unwrap();
+        assert_eq!(prune(&conn, 1).unwrap(), 3);
+        assert!(changes_since(&conn, 0, 100).is_err());
+        assert_eq!(changes_since(&conn, 3, 100).unwrap().len(), 2);
+    }
+
+    #[test]
+    fn feed_exports_full_chunked_content() {
+        let mut conn = Connection::open_in_memory().unwrap();
+        crate::schema::MIGRATIONS.to_latest(&mut conn).unwrap();
+        conn.execute("INSERT INTO sessions (id, project, started_at) VALUES ('s1', 'p', 100)", []).unwrap();
+        let content = "line of build output\n".repeat(100);
+        let (head, chunks) = crate::s1_chunk::split(&content, 1000);
+        assert!(!chunks.is_empty());
+        conn.execute(
+            "INSERT INTO observations (id, session_id, timestamp, obs_type, source_event, content)
+             VALUES (1, 's1', 110, 'command', 'PostToolUse', ?1)",
+            params![head],
+        )
+        .unwrap();
+        crate::s1_chunk::store(&conn, 1, &chunks).unwrap();
+
+        let changes = changes_since(&conn, 0, 100).unwrap();
+        let obs = changes.iter().find(|c| c.table == "observations").unwrap();
+        assert_eq!(obs.row.as_ref().unwrap()["content"], content.as_str());
+
+        // Rewriting only the chunks still shows up as a change
+        let start = checkpoint(&conn).unwrap();
+        conn.execute("DELETE FROM observation_chunks WHERE obs_id = 1", []).unwrap();
+        let changes = changes_since(&conn, start, 100).unwrap();
+        assert_eq!(changes.len(), 1);
+        assert_eq!(changes[0].op, "upsert");
+        assert_eq!(changes[0].row.as_ref().unwrap()["content"], head.as_str());
+    }
+}
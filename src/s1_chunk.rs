@@ -0,0 +1,175 @@
+//! S1 Operations — chunked storage for long observation content.
+//!
+//! BM25 punishes long documents, and a 120-character preview of one rarely
+//! shows why it matched. Content longer than `[record] chunk_chars` is split:
+//! the observation row keeps the first chunk (so everything that reads
+//! `observations.content` sees a bounded head), and the rest go to
+//! `observation_chunks`, each indexed on its own in `observation_chunks_fts`.
+//! Search ranks an observation by its best-matching chunk and reports where
+//! that chunk sits; `get_observations` stitches the content back together.
+
+use crate::NmemError;
+use rusqlite::{Connection, params};
+
+/// SQL expression for the full content of observation `o`: its head plus
+/// every chunk in order.
+macro_rules! full_content_sql {
+    () => {
+        "o.content || COALESCE((SELECT group_concat(c.content, '' ORDER BY c.seq)
+                        FROM observation_chunks c WHERE c.obs_id = o.id), '')"
+    };
+}
+pub(crate) use full_content_sql;
+
+/// `WITH` clause body binding `fts_best(obs_id, rank, chunk_seq, chunk_start,
+/// chunk_end, snippet)`: each observation matching FTS query `?1`, in its head
+/// (`observations_fts`) or any chunk, with its best rank. The chunk columns
+/// are NULL when the head matched best.
+macro_rules! fts_best_cte {
+    () => {
+        "fts_hits AS (
+    SELECT rowid AS obs_id, rank, NULL AS chunk_seq, NULL AS chunk_start,
+           NULL AS chunk_end, NULL AS snippet
+    FROM observations_fts WHERE observations_fts MATCH ?1
+    UNION ALL
+    SELECT c.obs_id, observation_chunks_fts.rank, c.seq, c.start,
+           c.start + length(c.content), snippet(observation_chunks_fts, 0, '', '', '…', 24)
+    FROM observation_chunks_fts JOIN observation_chunks c ON c.id = observation_chunks_fts.rowid
+    WHERE observation_chunks_fts MATCH ?1
+),
+fts_best AS (
+    SELECT obs_id, MIN(rank) AS rank, chunk_seq, chunk_start, chunk_end, snippet
+    FROM fts_hits GROUP BY obs_id
+)"
+    };
+}
+pub(crate) use fts_best_cte;
+
+/// Where in a chunked observation a search matched. Offsets are characters
+/// into the full content.
+#[derive(Debug, Clone, serde::Serialize)]
+pub struct ChunkMatch {
+    pub seq: i64,
+    pub start: i64,
+    pub end: i64,
+}
+
+impl ChunkMatch {
+    /// From the `chunk_seq, chunk_start, chunk_end` columns of `fts_best`.
+    pub fn from_row(row: &rusqlite::Row, first: usize) -> rusqlite::Result<Option<Self>> {
+        let seq: Option<i64> = row.get(first)?;
+        Ok(match seq {
+            Some(seq) => Some(ChunkMatch { seq, start: row.get(first + 1)?, end: row.get(first + 2)? }),
+            None => None,
+        })
+    }
+}
+
+/// Split `content` into a head and `(start, text)` tail chunks of at most
+/// `chunk_chars` characters each, breaking at a newline or space in the back
+/// half of a chunk when there is one. Content that fits — or `chunk_chars`
+/// of 0 — comes back whole with no chunks.
+pub fn split(content: &str, chunk_chars: usize) -> (String, Vec<(usize, String)>) {
+    let chars: Vec<char> = content.chars().collect();
+    if chunk_chars == 0 || chars.len() <= chunk_chars {
+        return (content.to_string(), Vec::new());
+    }
+    let mut pieces = Vec::new();
+    let mut start = 0;
+    while start < chars.len() {
+        let mut end = (start + chunk_chars).min(chars.len());
+        if end < chars.len()
+            && let Some(brk) = chars[start + chunk_chars / 2..end]
+                .iter()
+                .rposition(|c| *c == '\n' || *c == ' ')
+        {
+            end = start + chunk_chars / 2 + brk + 1;
+        }
+        pieces.push((start, chars[start..end].iter().collect::<String>()));
+        start = end;
+    }
+    let (_, head) = pieces.remove(0);
+    (head, pieces)
+}
+
+/// Store the tail chunks of observation `obs_id`, numbered from 1 (the head
+/// is chunk 0).
+pub fn store(conn: &Connection, obs_id: i64, chunks: &[(usize, String)]) -> Result<(), NmemError> {
+    let mut stmt = conn.prepare_cached(
+        "INSERT INTO observation_chunks (obs_id, seq, start, content) VALUES (?1, ?2, ?3, ?4)",
+    )?;
+    for (i, (start, text)) in chunks.iter().enumerate() {
+        stmt.execute(params![obs_id, i as i64 + 1, *start as i64, text])?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn split_round_trips_and_prefers_breaks() {
+        assert_eq!(split("short", 10), ("short".into(), vec![]));
+        assert_eq!(split("no limit at all", 0).1.len(), 0);
+
+        let text = "alpha beta gamma delta epsilon zeta eta theta iota kappa";
+        let (head, chunks) = split(text, 16);
+        assert!(head.chars().count() <= 16);
+        assert!(head.ends_with(' '), "should break after a space: {head:?}");
+        let mut rebuilt = head.clone();
+        for (start, chunk) in &chunks {
+            assert_eq!(*start, rebuilt.chars().count());
+            assert!(chunk.chars().count() <= 16);
+            rebuilt.push_str(chunk);
+        }
+        assert_eq!(rebuilt, text);
+
+        // No break character: hard split on characters, not bytes
+        let (head, chunks) = split(&"é".repeat(25), 10);
+        assert_eq!(head, "é".repeat(10));
+        assert_eq!(chunks.iter().map(|(s, _)| *s).collect::<Vec<_>>(), vec![10, 20]);
+    }
+
+    #[test]
+    fn chunks_match_search_and_reassemble() {
+        let mut conn = Connection::open_in_memory().unwrap();
+        conn.pragma_update(None, "foreign_keys", "ON").unwrap();
+        crate::schema::MIGRATIONS.to_latest(&mut conn).unwrap();
+        let content = format!("plan: {} then migrate the zeppelin hangar", "step ".repeat(60));
+        let (head, chunks) = split(&content, 100);
+        conn.execute("INSERT INTO sessions (id, project, started_at) VALUES ('s', 'p', 1)", []).unwrap();
+        conn.execute(
+            "INSERT INTO observations (session_id, timestamp, obs_type, source_event, content)
+             VALUES ('s', 1, 'plan', 'PostToolUse', ?1)",
+            [&head],
+        )
+        .unwrap();
+        store(&conn, 1, &chunks).unwrap();
+
+        let full: String = conn
+            .query_row(concat!("SELECT ", full_content_sql!(), " FROM observations o WHERE o.id = 1"), [], |r| r.get(0))
+            .unwrap();
+        assert_eq!(full, content);
+
+        let sql = concat!("WITH ", fts_best_cte!(), " SELECT obs_id, snippet, chunk_seq, chunk_start, chunk_end FROM fts_best");
+        let (id, snippet, m): (i64, String, Option<ChunkMatch>) = conn
+            .query_row(sql, ["zeppelin"], |r| Ok((r.get(0)?, r.get(1)?, ChunkMatch::from_row(r, 2)?)))
+            .unwrap();
+        assert_eq!(id, 1);
+        assert!(snippet.contains("zeppelin"));
+        let m = m.unwrap();
+        assert_eq!(m.seq as usize, chunks.len());
+        let tail: String = content.chars().skip(m.start as usize).take((m.end - m.start) as usize).collect();
+        assert!(tail.contains("zeppelin"));
+
+        // Head matches carry no chunk; deleting the observation drops its chunks
+        let seq: Option<i64> = conn.query_row(sql, ["plan"], |r| r.get(2)).unwrap();
+        assert_eq!(seq, None);
+        conn.execute("DELETE FROM observations", []).unwrap();
+        let left: i64 = conn
+            .query_row("SELECT COUNT(*) FROM observation_chunks_fts WHERE observation_chunks_fts MATCH 'zeppelin'", [], |r| r.get(0))
+            .unwrap();
+        assert_eq!(left, 0);
+    }
+}
@@ -0,0 +1,255 @@
+//! S1 Operations — observation corrections.
+//!
+//! Purge deletes and an UPDATE forgets. Corrections are recorded instead, as
+//! an append-only log in `corrections`:
+//!
+//! - `redact` masks an observation — content becomes `[redacted: <reason>]`,
+//!   metadata and chunks are dropped, a Read snapshot loses its head — after
+//!   copying what it replaced into `observation_originals`. The mask is
+//!   written into the row itself so the FTS index, previews, and the change
+//!   feed stop carrying the text; no read path needs to know.
+//! - `restore` puts the latest redacted original back.
+//! - `annotate` attaches a note; `get_observations` returns an
+//!   observation's log as `corrections`, so the note travels with it.
+//!
+//! `observation_originals` is restricted: no tool reads it and `nmem query`
+//! refuses statements that name it. Purging an observation drops its
+//! corrections and originals with it.
+
+#[cfg(feature = "cli")]
+use crate::cli::{AnnotateArgs, RedactArgs};
+use crate::NmemError;
+use rusqlite::{Connection, OptionalExtension, params};
+use serde::Serialize;
+#[cfg(feature = "cli")]
+use std::path::Path;
+
+pub const REDACT: &str = "redact";
+pub const RESTORE: &str = "restore";
+pub const ANNOTATE: &str = "annotate";
+
+/// Table holding redacted originals, off limits to queries.
+pub const ORIGINALS_TABLE: &str = "observation_originals";
+
+#[derive(Debug, Clone, Serialize, PartialEq)]
+pub struct Correction {
+    pub id: i64,
+    /// `redact`, `restore`, or `annotate`.
+    pub kind: String,
+    /// Redaction reason or annotation.
+    pub text: String,
+    pub created_at: i64,
+}
+
+fn record(conn: &Connection, obs_id: i64, kind: &str, text: &str, now: i64) -> Result<i64, NmemError> {
+    conn.execute(
+        "INSERT INTO corrections (obs_id, kind, text, created_at) VALUES (?1, ?2, ?3, ?4)",
+        params![obs_id, kind, text, now],
+    )?;
+    Ok(conn.last_insert_rowid())
+}
+
+/// Whether the latest redact/restore of `obs_id` is a redact.
+pub fn is_redacted(conn: &Connection, obs_id: i64) -> Result<bool, NmemError> {
+    let last: Option<String> = conn
+        .query_row(
+            "SELECT kind FROM corrections WHERE obs_id = ?1 AND kind IN (?2, ?3) ORDER BY id DESC LIMIT 1",
+            params![obs_id, REDACT, RESTORE],
+            |r| r.get(0),
+        )
+        .optional()?;
+    Ok(last.as_deref() == Some(REDACT))
+}
+
+fn require_observation(conn: &Connection, obs_id: i64) -> Result<(), NmemError> {
+    let exists: bool =
+        conn.query_row("SELECT EXISTS(SELECT 1 FROM observations WHERE id = ?1)", params![obs_id], |r| r.get(0))?;
+    if !exists {
+        return Err(NmemError::Config(format!("observation {obs_id} not found")));
+    }
+    Ok(())
+}
+
+/// Mask observation `obs_id`, keeping its original. Returns the correction id.
+pub fn redact(conn: &Connection, obs_id: i64, reason: &str, now: i64) -> Result<i64, NmemError> {
+    let reason = reason.trim();
+    if reason.is_empty() {
+        return Err(NmemError::Config("a redaction needs a reason".into()));
+    }
+    require_observation(conn, obs_id)?;
+    if is_redacted(conn, obs_id)? {
+        return Err(NmemError::Config(format!("observation {obs_id} is already redacted")));
+    }
+
+    let tx = conn.unchecked_transaction()?;
+    let (content, metadata): (String, Option<String>) = tx.query_row(
+        concat!(
+            "SELECT ",
+            crate::s1_chunk::full_content_sql!(),
+            ", o.metadata FROM observations o WHERE o.id = ?1"
+        ),
+        params![obs_id],
+        |r| Ok((r.get(0)?, r.get(1)?)),
+    )?;
+    let head: Option<String> = tx
+        .query_row("SELECT head FROM file_snapshots WHERE obs_id = ?1", params![obs_id], |r| r.get(0))
+        .optional()?
+        .flatten();
+
+    let id = record(&tx, obs_id, REDACT, reason, now)?;
+    tx.execute(
+        "INSERT INTO observation_originals (correction_id, obs_id, content, metadata, snapshot_head)
+         VALUES (?1, ?2, ?3, ?4, ?5)",
+        params![id, obs_id, content, metadata, head],
+    )?;
+    tx.execute("DELETE FROM observation_chunks WHERE obs_id = ?1", params![obs_id])?;
+    crate::s1_minhash::remove(&tx, obs_id)?;
+    tx.execute(
+        "UPDATE observations SET content = ?1, metadata = NULL WHERE id = ?2",
+        params![format!("[redacted: {reason}]"), obs_id],
+    )?;
+    tx.execute("UPDATE file_snapshots SET head = NULL WHERE obs_id = ?1", params![obs_id])?;
+    tx.commit()?;
+    Ok(id)
+}
+
+/// Put back the original of the latest redaction of `obs_id`, re-chunked at
+/// `chunk_chars`. Returns the correction id.
+pub fn restore(conn: &Connection, obs_id: i64, chunk_chars: usize, now: i64) -> Result<i64, NmemError> {
+    require_observation(conn, obs_id)?;
+    if !is_redacted(conn, obs_id)? {
+        return Err(NmemError::Config(format!("observation {obs_id} is not redacted")));
+    }
+    let (content, metadata, head): (String, Option<String>, Option<String>) = conn.query_row(
+        "SELECT content, metadata, snapshot_head FROM observation_originals
+         WHERE obs_id = ?1 ORDER BY correction_id DESC LIMIT 1",
+        params![obs_id],
+        |r| Ok((r.get(0)?, r.get(1)?, r.get(2)?)),
+    )?;
+
+    let tx = conn.unchecked_transaction()?;
+    let (first, chunks) = crate::s1_chunk::split(&content, chunk_chars);
+    tx.execute(
+        "UPDATE observations SET content = ?1, metadata = ?2 WHERE id = ?3",
+        params![first, metadata, obs_id],
+    )?;
+    crate::s1_chunk::store(&tx, obs_id, &chunks)?;
+    crate::s1_minhash::store(&tx, obs_id, &content)?;
+    if head.is_some() {
+        tx.execute("UPDATE file_snapshots SET head = ?1 WHERE obs_id = ?2", params![head, obs_id])?;
+    }
+    let id = record(&tx, obs_id, RESTORE, "restored original", now)?;
+    tx.commit()?;
+    Ok(id)
+}
+
+/// Attach `text` to observation `obs_id`. Returns the correction id.
+pub fn annotate(conn: &Connection, obs_id: i64, text: &str, now: i64) -> Result<i64, NmemError> {
+    let text = text.trim();
+    if text.is_empty() {
+        return Err(NmemError::Config("annotation text is empty".into()));
+    }
+    require_observation(conn, obs_id)?;
+    record(conn, obs_id, ANNOTATE, text, now)
+}
+
+/// The correction log of `obs_id`, oldest first.
+pub fn corrections(conn: &Connection, obs_id: i64) -> Result<Vec<Correction>, NmemError> {
+    let mut stmt = conn.prepare_cached(
+        "SELECT id, kind, text, created_at FROM corrections WHERE obs_id = ?1 ORDER BY id",
+    )?;
+    let rows = stmt
+        .query_map(params![obs_id], |r| {
+            Ok(Correction { id: r.get(0)?, kind: r.get(1)?, text: r.get(2)?, created_at: r.get(3)? })
+        })?
+        .collect::<Result<_, _>>()?;
+    Ok(rows)
+}
+
+#[cfg(feature = "cli")]
+fn now() -> i64 {
+    std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs() as i64
+}
+
+#[cfg(feature = "cli")]
+pub fn handle_redact(db_path: &Path, args: &RedactArgs) -> Result<(), NmemError> {
+    let conn = crate::db::open_db(db_path)?;
+    if args.restore {
+        let chunk_chars = crate::s5_config::load_config().unwrap_or_default().record.chunk_chars;
+        restore(&conn, args.id, chunk_chars, now())?;
+        log::info!("restored observation {}", args.id);
+        return Ok(());
+    }
+    let Some(ref reason) = args.reason else {
+        return Err(NmemError::Config("--reason is required (or --restore)".into()));
+    };
+    redact(&conn, args.id, reason, now())?;
+    log::info!("redacted observation {}; original kept (nmem redact {} --restore undoes it)", args.id, args.id);
+    Ok(())
+}
+
+#[cfg(feature = "cli")]
+pub fn handle_annotate(db_path: &Path, args: &AnnotateArgs) -> Result<(), NmemError> {
+    let conn = crate::db::open_db(db_path)?;
+    let id = annotate(&conn, args.id, &args.text, now())?;
+    log::info!("annotated observation {} (correction {id})", args.id);
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn redact_masks_and_restore_brings_back_the_original() {
+        let mut conn = Connection::open_in_memory().unwrap();
+        conn.pragma_update(None, "foreign_keys", "ON").unwrap();
+        crate::schema::MIGRATIONS.to_latest(&mut conn).unwrap();
+        conn.execute_batch(
+            r#"INSERT INTO sessions (id, project, started_at) VALUES ('s', 'p', 1);
+               INSERT INTO observations (id, session_id, timestamp, obs_type, source_event, content, metadata)
+                   VALUES (1, 's', 2, 'command', 'PostToolUse', 'curl -H token=hunter2 ', '{"failed":true}');
+               INSERT INTO observation_chunks (obs_id, seq, start, content) VALUES (1, 1, 22, 'https://api');"#,
+        )
+        .unwrap();
+        let fts_hits = |conn: &Connection, q: &str| -> i64 {
+            conn.query_row("SELECT COUNT(*) FROM observations_fts WHERE observations_fts MATCH ?1", [q], |r| r.get(0))
+                .unwrap()
+        };
+
+        redact(&conn, 1, "leaked token", 10).unwrap();
+        let (content, metadata): (String, Option<String>) =
+            conn.query_row("SELECT content, metadata FROM observations WHERE id = 1", [], |r| Ok((r.get(0)?, r.get(1)?)))
+                .unwrap();
+        assert_eq!((content.as_str(), metadata), ("[redacted: leaked token]", None));
+        assert_eq!(fts_hits(&conn, "hunter2"), 0);
+        assert!(redact(&conn, 1, "again", 11).is_err());
+
+        annotate(&conn, 1, "token was rotated", 12).unwrap();
+        restore(&conn, 1, 0, 13).unwrap();
+        let content: String = conn
+            .query_row(
+                concat!("SELECT ", crate::s1_chunk::full_content_sql!(), " FROM observations o WHERE o.id = 1"),
+                [],
+                |r| r.get(0),
+            )
+            .unwrap();
+        assert_eq!(content, "curl -H token=hunter2 https://api");
+        assert_eq!(fts_hits(&conn, "hunter2"), 1);
+
+        let kinds: Vec<String> = corrections(&conn, 1).unwrap().into_iter().map(|c| c.kind).collect();
+        assert_eq!(kinds, vec![REDACT, ANNOTATE, RESTORE]);
+        assert!(restore(&conn, 1, 0, 14).is_err());
+
+        // Purging the observation takes its log and originals with it
+        conn.execute("DELETE FROM observations WHERE id = 1", []).unwrap();
+        let left: i64 = conn
+            .query_row("SELECT (SELECT COUNT(*) FROM corrections) + (SELECT COUNT(*) FROM observation_originals)", [], |r| r.get(0))
+            .unwrap();
+        assert_eq!(left, 0);
+        assert!(annotate(&conn, 1, "gone", 15).is_err());
+    }
+}
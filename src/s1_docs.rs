@@ -0,0 +1,428 @@
+//! Project documentation as memory — CLAUDE.md, README, and docs/.
+//!
+//! A fresh install knows nothing until sessions accumulate, yet most
+//! projects already write down how they build, test, and hang together.
+//! `nmem ingest docs` splits markdown at headings into `doc_knowledge`
+//! observations in one session per project (`docs-{project}`,
+//! `sessions.kind = 'docs'`), so search finds them beside agent work and
+//! context surfaces the sections matching recent intents. Each chunk keeps
+//! its file's SHA-256; re-ingesting replaces a file's chunks only when the
+//! file changed, and drops those of files that are gone. `--watch` polls
+//! the files and re-indexes on change.
+
+#[cfg(feature = "cli")]
+use crate::cli::IngestDocsArgs;
+use crate::s5_filter::SecretFilter;
+use crate::NmemError;
+use rusqlite::{Connection, params};
+use std::collections::HashSet;
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+pub const DOC_OBS_TYPE: &str = "doc_knowledge";
+
+/// Source event for ingested documentation.
+pub const DOC_SOURCE_EVENT: &str = "DocIngest";
+
+/// Sections longer than this split at paragraph breaks.
+const MAX_CHUNK_CHARS: usize = 1500;
+
+/// Terms a chunk must share with recent intents to be surfaced in context.
+const MIN_SHARED_TERMS: usize = 2;
+
+#[cfg(feature = "cli")]
+const WATCH_POLL: std::time::Duration = std::time::Duration::from_secs(2);
+
+/// One section of a markdown file.
+#[derive(Debug, PartialEq)]
+pub struct DocChunk {
+    /// Heading trail, `README.md > Install > From source`.
+    pub heading: String,
+    pub text: String,
+}
+
+/// A documentation chunk chosen for context.
+#[derive(Debug, Clone)]
+pub struct DocHit {
+    pub id: i64,
+    pub heading: String,
+    pub text: String,
+}
+
+/// Split markdown at ATX headings (outside code fences). Each chunk is
+/// headed by `title` and the headings above it; text before the first
+/// heading is headed by `title` alone.
+pub fn chunk_markdown(title: &str, content: &str) -> Vec<DocChunk> {
+    let mut chunks = Vec::new();
+    let mut trail: Vec<(usize, String)> = Vec::new();
+    let mut body = String::new();
+    let mut fence: Option<&str> = None;
+
+    let heading_of = |trail: &[(usize, String)]| {
+        std::iter::once(title).chain(trail.iter().map(|(_, h)| h.as_str())).collect::<Vec<_>>().join(" > ")
+    };
+
+    for line in content.lines() {
+        let trimmed = line.trim_start();
+        if let Some(marker) = ["```", "~~~"].into_iter().find(|m| trimmed.starts_with(m)) {
+            fence = match fence {
+                Some(open) if open == marker => None,
+                None => Some(marker),
+                open => open,
+            };
+        }
+        if fence.is_none()
+            && let Some((level, text)) = atx_heading(line)
+        {
+            push_section(&mut chunks, &heading_of(&trail), &body);
+            body.clear();
+            trail.retain(|(l, _)| *l < level);
+            trail.push((level, text.to_string()));
+            continue;
+        }
+        body.push_str(line);
+        body.push('\n');
+    }
+    push_section(&mut chunks, &heading_of(&trail), &body);
+    chunks
+}
+
+/// `## Title` → (2, "Title").
+fn atx_heading(line: &str) -> Option<(usize, &str)> {
+    let level = line.chars().take_while(|&c| c == '#').count();
+    if !(1..=6).contains(&level) {
+        return None;
+    }
+    let rest = &line[level..];
+    if !rest.starts_with(' ') {
+        return None;
+    }
+    let text = rest.trim().trim_end_matches('#').trim();
+    (!text.is_empty()).then_some((level, text))
+}
+
+/// Add a section's body, split at blank lines when it runs long.
+fn push_section(chunks: &mut Vec<DocChunk>, heading: &str, body: &str) {
+    let mut part = String::new();
+    for paragraph in body.split("\n\n").map(str::trim).filter(|p| !p.is_empty()) {
+        if !part.is_empty() && part.chars().count() + paragraph.chars().count() > MAX_CHUNK_CHARS {
+            chunks.push(DocChunk { heading: heading.to_string(), text: std::mem::take(&mut part) });
+        }
+        if !part.is_empty() {
+            part.push_str("\n\n");
+        }
+        part.push_str(paragraph);
+    }
+    if !part.is_empty() {
+        chunks.push(DocChunk { heading: heading.to_string(), text: part });
+    }
+}
+
+/// Docs session ID for a project: `docs-{project}`.
+pub fn docs_session_id(project: &str) -> String {
+    format!("docs-{project}")
+}
+
+/// Ensure the project's docs session exists. Like operator sessions it gets
+/// a fixed summary and is never LLM-summarized.
+fn ensure_docs_session(conn: &Connection, project: &str, ts: i64) -> Result<String, NmemError> {
+    let session_id = docs_session_id(project);
+    let summary = serde_json::json!({
+        "intent": "project documentation",
+        "learned": [],
+        "completed": [],
+        "next_steps": [],
+        "files_read": [],
+        "files_edited": [],
+        "notes": null
+    });
+    conn.execute(
+        "INSERT INTO sessions (id, project, started_at, ended_at, summary, kind)
+         VALUES (?1, ?2, ?3, ?3, ?4, 'docs')
+         ON CONFLICT(id) DO UPDATE SET
+             started_at = MIN(started_at, excluded.started_at),
+             ended_at = MAX(COALESCE(ended_at, excluded.ended_at), excluded.ended_at)",
+        params![session_id, project, ts, summary.to_string()],
+    )?;
+    Ok(session_id)
+}
+
+/// What indexing one file did.
+#[derive(Debug, PartialEq)]
+pub enum Indexed {
+    Unchanged,
+    /// Chunks written (replacing any earlier ones).
+    Chunks(usize),
+}
+
+/// Index `content` as the file `name` (its path relative to the project
+/// root). A file whose hash matches what is stored is left alone.
+pub fn index_file(
+    conn: &Connection,
+    filter: &SecretFilter,
+    project: &str,
+    name: &str,
+    content: &str,
+    mtime: i64,
+) -> Result<Indexed, NmemError> {
+    let sha = crate::update::sha256_hex(content.as_bytes());
+    let session_id = docs_session_id(project);
+    let stored: Option<String> = conn
+        .query_row(
+            "SELECT json_extract(metadata, '$.sha256') FROM observations
+             WHERE session_id = ?1 AND file_path = ?2 AND obs_type = ?3 LIMIT 1",
+            params![session_id, name, DOC_OBS_TYPE],
+            |r| r.get(0),
+        )
+        .ok()
+        .flatten();
+    if stored.as_deref() == Some(sha.as_str()) {
+        return Ok(Indexed::Unchanged);
+    }
+
+    remove_file(conn, project, name)?;
+    ensure_docs_session(conn, project, mtime)?;
+    let chunks = chunk_markdown(name, content);
+    for (i, chunk) in chunks.iter().enumerate() {
+        let (text, redacted) = filter.redact(&format!("{}\n{}", chunk.heading, chunk.text));
+        let mut meta = serde_json::json!({"heading": chunk.heading, "chunk": i, "sha256": sha});
+        if redacted {
+            meta["redacted"] = true.into();
+        }
+        conn.execute(
+            "INSERT INTO observations (session_id, timestamp, obs_type, source_event, file_path, content, metadata)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)",
+            params![session_id, mtime, DOC_OBS_TYPE, DOC_SOURCE_EVENT, name, text, meta.to_string()],
+        )?;
+    }
+    Ok(Indexed::Chunks(chunks.len()))
+}
+
+/// Drop a file's chunks. Returns how many were removed.
+pub fn remove_file(conn: &Connection, project: &str, name: &str) -> Result<usize, NmemError> {
+    Ok(conn.execute(
+        "DELETE FROM observations WHERE session_id = ?1 AND file_path = ?2 AND obs_type = ?3",
+        params![docs_session_id(project), name, DOC_OBS_TYPE],
+    )?)
+}
+
+/// Files with indexed chunks in the project's docs session.
+pub fn indexed_files(conn: &Connection, project: &str) -> Result<Vec<String>, NmemError> {
+    let files = conn
+        .prepare("SELECT DISTINCT file_path FROM observations WHERE session_id = ?1 AND obs_type = ?2 AND file_path IS NOT NULL")?
+        .query_map(params![docs_session_id(project), DOC_OBS_TYPE], |r| r.get(0))?
+        .collect::<Result<_, _>>()?;
+    Ok(files)
+}
+
+/// Up to `limit` chunks of the project's docs sharing the most terms with
+/// `recent_terms` (at least `MIN_SHARED_TERMS`).
+pub fn related(
+    conn: &Connection,
+    project: &str,
+    recent_terms: &HashSet<String>,
+    limit: usize,
+) -> Result<Vec<DocHit>, NmemError> {
+    if recent_terms.len() < MIN_SHARED_TERMS || limit == 0 {
+        return Ok(Vec::new());
+    }
+    let mut stmt = conn.prepare(
+        "SELECT id, content FROM observations WHERE session_id = ?1 AND obs_type = ?2 ORDER BY id",
+    )?;
+    let rows = stmt.query_map(params![docs_session_id(project), DOC_OBS_TYPE], |r| {
+        Ok((r.get::<_, i64>(0)?, r.get::<_, String>(1)?))
+    })?;
+    let mut scored = Vec::new();
+    for row in rows {
+        let (id, content) = row?;
+        let shared = crate::s4_context::terms(&content).intersection(recent_terms).count();
+        if shared >= MIN_SHARED_TERMS {
+            let (heading, text) = content.split_once('\n').unwrap_or((&content, ""));
+            scored.push((shared, DocHit { id, heading: heading.to_string(), text: text.to_string() }));
+        }
+    }
+    scored.sort_by_key(|(shared, _)| std::cmp::Reverse(*shared));
+    Ok(scored.into_iter().take(limit).map(|(_, hit)| hit).collect())
+}
+
+/// Markdown files under `paths` (directories are walked, skipping hidden
+/// ones), sorted.
+pub fn doc_files(paths: &[PathBuf]) -> Vec<PathBuf> {
+    fn walk(dir: &Path, out: &mut Vec<PathBuf>) {
+        let Ok(entries) = std::fs::read_dir(dir) else { return };
+        for entry in entries.flatten() {
+            let path = entry.path();
+            if entry.file_name().to_string_lossy().starts_with('.') {
+                continue;
+            }
+            if path.is_dir() {
+                walk(&path, out);
+            } else if is_markdown(&path) {
+                out.push(path);
+            }
+        }
+    }
+    let mut files = Vec::new();
+    for path in paths {
+        if path.is_dir() {
+            walk(path, &mut files);
+        } else if path.is_file() {
+            files.push(path.clone());
+        }
+    }
+    files.sort();
+    files.dedup();
+    files
+}
+
+fn is_markdown(path: &Path) -> bool {
+    path.extension().is_some_and(|e| e == "md" || e == "markdown")
+}
+
+/// Name a file is indexed under: relative to `root` when inside it.
+fn doc_name(root: &Path, file: &Path) -> String {
+    file.strip_prefix(root).unwrap_or(file).to_string_lossy().to_string()
+}
+
+fn mtime_of(file: &Path) -> Option<SystemTime> {
+    std::fs::metadata(file).and_then(|m| m.modified()).ok()
+}
+
+/// Outcome of one pass over the docs.
+#[derive(Debug, Default, PartialEq)]
+pub struct SyncReport {
+    pub files: usize,
+    pub chunks: usize,
+    pub unchanged: usize,
+    pub removed: usize,
+}
+
+/// Index `files` and drop chunks of indexed files that no longer exist.
+pub fn sync(
+    conn: &Connection,
+    filter: &SecretFilter,
+    project: &str,
+    root: &Path,
+    files: &[PathBuf],
+) -> Result<SyncReport, NmemError> {
+    let mut report = SyncReport::default();
+    for file in files {
+        let bytes = std::fs::read(file).map_err(|e| NmemError::from(e).context_path("ingest docs", file))?;
+        let mtime = mtime_of(file)
+            .and_then(|t| t.duration_since(UNIX_EPOCH).ok())
+            .map_or(0, |d| d.as_secs() as i64);
+        match index_file(conn, filter, project, &doc_name(root, file), &String::from_utf8_lossy(&bytes), mtime)? {
+            Indexed::Unchanged => report.unchanged += 1,
+            Indexed::Chunks(n) => {
+                report.files += 1;
+                report.chunks += n;
+            }
+        }
+    }
+    for name in indexed_files(conn, project)? {
+        if !root.join(&name).is_file() {
+            report.removed += remove_file(conn, project, &name)?;
+        }
+    }
+    Ok(report)
+}
+
+#[cfg(feature = "cli")]
+pub fn handle_ingest_docs(db_path: &Path, args: &IngestDocsArgs) -> Result<(), NmemError> {
+    let config = crate::s5_config::load_config().unwrap_or_default();
+    let root = std::env::current_dir()?;
+    let project = args.project.clone().unwrap_or_else(|| {
+        crate::s5_project::derive_project_with_strategy(&root.to_string_lossy(), config.project.strategy)
+    });
+    let filter = SecretFilter::with_params(crate::s5_config::resolve_filter_params(&config, Some(&project)));
+    let paths: Vec<PathBuf> = if args.paths.is_empty() {
+        ["CLAUDE.md", "README.md", "docs"].iter().map(|p| root.join(p)).collect()
+    } else {
+        args.paths.iter().map(|p| root.join(p)).collect()
+    };
+
+    let mut last: Option<Vec<(PathBuf, Option<SystemTime>)>> = None;
+    loop {
+        let files = doc_files(&paths);
+        let state: Vec<_> = files.iter().map(|f| (f.clone(), mtime_of(f))).collect();
+        if last.as_ref() != Some(&state) {
+            let report = crate::db::retry_on_busy(|| {
+                let conn = crate::db::open_db(db_path)?;
+                let tx = conn.unchecked_transaction()?;
+                let report = sync(&tx, &filter, &project, &root, &files)?;
+                tx.commit()?;
+                Ok(report)
+            })?;
+            if last.is_none() || report.files > 0 || report.removed > 0 {
+                log::info!(
+                    "indexed {} chunks from {} files into project {project} ({} unchanged, {} chunks removed)",
+                    report.chunks,
+                    report.files,
+                    report.unchanged,
+                    report.removed
+                );
+            }
+            last = Some(state);
+        }
+        if !args.watch {
+            return Ok(());
+        }
+        std::thread::sleep(WATCH_POLL);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn chunks_at_headings_outside_code_fences() {
+        let md = "Intro line.\n\n# Build\nRun cargo.\n\n```sh\n# not a heading\ncargo build\n```\n\n## Tests\nUse nextest.\n# Layout\n###### Deep\nx\n";
+        let chunks = chunk_markdown("README.md", md);
+        let headings: Vec<&str> = chunks.iter().map(|c| c.heading.as_str()).collect();
+        assert_eq!(
+            headings,
+            vec!["README.md", "README.md > Build", "README.md > Build > Tests", "README.md > Layout > Deep"]
+        );
+        assert!(chunks[1].text.contains("# not a heading\ncargo build"));
+        assert_eq!(chunks[2].text, "Use nextest.");
+        assert_eq!(atx_heading("#hashtag"), None);
+    }
+
+    #[test]
+    fn long_sections_split_at_paragraphs() {
+        let para = "word ".repeat(200);
+        let chunks = chunk_markdown("a.md", &format!("# Big\n{para}\n\n{para}\n\n{para}\n"));
+        assert_eq!(chunks.len(), 3);
+        assert!(chunks.iter().all(|c| c.heading == "a.md > Big"));
+    }
+
+    #[test]
+    fn reindexes_only_changed_files_and_surfaces_related_chunks() {
+        let mut conn = Connection::open_in_memory().unwrap();
+        crate::schema::MIGRATIONS.to_latest(&mut conn).unwrap();
+        let filter = SecretFilter::with_params(Default::default());
+        let doc = "# Release\nTag the version, then publish binaries to the mirror.\n# Style\nNo unwrap outside tests.\n";
+
+        assert_eq!(index_file(&conn, &filter, "app", "CLAUDE.md", doc, 100).unwrap(), Indexed::Chunks(2));
+        assert_eq!(index_file(&conn, &filter, "app", "CLAUDE.md", doc, 200).unwrap(), Indexed::Unchanged);
+        let edited = doc.replace("No unwrap", "Avoid unwrap");
+        assert_eq!(index_file(&conn, &filter, "app", "CLAUDE.md", &edited, 300).unwrap(), Indexed::Chunks(2));
+        let (count, kind): (i64, String) = conn
+            .query_row(
+                "SELECT COUNT(*), MAX(s.kind) FROM observations o JOIN sessions s ON s.id = o.session_id",
+                [],
+                |r| Ok((r.get(0)?, r.get(1)?)),
+            )
+            .unwrap();
+        assert_eq!((count, kind.as_str()), (2, "docs"));
+
+        let recent = crate::s4_context::terms("publish release binaries");
+        let hits = related(&conn, "app", &recent, 3).unwrap();
+        assert_eq!(hits.len(), 1);
+        assert_eq!(hits[0].heading, "CLAUDE.md > Release");
+        assert!(related(&conn, "other", &recent, 3).unwrap().is_empty());
+
+        assert_eq!(remove_file(&conn, "app", "CLAUDE.md").unwrap(), 2);
+    }
+}
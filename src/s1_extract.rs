@@ -12,11 +12,23 @@ pub fn classify_tool(name: &str) -> &'static str {
         "Task" => "task_spawn",
         "WebFetch" => "web_fetch",
         "WebSearch" => "web_search",
+        "TodoWrite" | "ExitPlanMode" => "plan",
         _ if name.contains("__") => "mcp_call",
         _ => "tool_other",
     }
 }
 
+/// Split an MCP tool name (`mcp__<server>__<tool>`) into server and tool.
+/// Server names may contain single underscores (`plugin_nmem_nmem`); the first
+/// `__` after the prefix is the separator.
+pub fn parse_mcp_tool(name: &str) -> Option<(&str, &str)> {
+    let (server, tool) = name.strip_prefix("mcp__")?.split_once("__")?;
+    if server.is_empty() || tool.is_empty() {
+        return None;
+    }
+    Some((server, tool))
+}
+
 /// Sub-classify a Bash command. Returns a more specific obs_type if the
 /// command is a git commit, push, or gh CLI call, otherwise returns "command".
 pub fn classify_bash(command: &str) -> &'static str {
@@ -123,6 +135,15 @@ pub fn extract_content(name: &str, tool_input: &Value) -> String {
             .and_then(|v| v.as_str())
             .unwrap_or("")
             .into(),
+        "TodoWrite" => crate::s1_plan::todo_summary(tool_input),
+        // Plans run long; storage chunks them ([record] chunk_chars)
+        "ExitPlanMode" => tool_input
+            .get("plan")
+            .and_then(|v| v.as_str())
+            .unwrap_or("")
+            .chars()
+            .take(8000)
+            .collect(),
         "AskUserQuestion" => tool_input
             .get("questions")
             .and_then(|v| v.as_array())
@@ -150,6 +171,46 @@ pub fn extract_file_path(name: &str, tool_input: &Value) -> Option<String> {
     }
 }
 
+/// `path` made absolute against the hook's `cwd`, with `.` and `..` resolved
+/// lexically. Absolute paths, and any path when `cwd` isn't absolute, come
+/// back unchanged.
+pub fn resolve_path(path: &str, cwd: &str) -> String {
+    let p = std::path::Path::new(path);
+    if p.is_absolute() || !std::path::Path::new(cwd).is_absolute() {
+        return path.to_string();
+    }
+    let mut out = std::path::PathBuf::from(cwd);
+    for component in p.components() {
+        match component {
+            std::path::Component::CurDir => {}
+            std::path::Component::ParentDir => {
+                out.pop();
+            }
+            other => out.push(other),
+        }
+    }
+    out.to_string_lossy().into_owned()
+}
+
+/// `cwd` as stored and matched: absolute, without a trailing slash. None for
+/// a relative or empty one.
+pub fn normalize_cwd(cwd: &str) -> Option<String> {
+    if !cwd.starts_with('/') {
+        return None;
+    }
+    let trimmed = cwd.trim_end_matches('/');
+    Some(if trimmed.is_empty() { "/".to_string() } else { trimmed.to_string() })
+}
+
+/// A `--cwd` filter argument as a normalized cwd; a relative one resolves
+/// against the current directory.
+pub fn cwd_filter(arg: &str) -> Option<String> {
+    match std::env::current_dir() {
+        Ok(dir) => normalize_cwd(&resolve_path(arg, &dir.to_string_lossy())),
+        Err(_) => normalize_cwd(arg),
+    }
+}
+
 /// Extract structured metadata from git commit/push tool_response.
 /// Returns a map with commit_hash, commit_message, branch, diffstat fields.
 pub fn extract_git_metadata(obs_type: &str, tool_response: &str) -> Map<String, Value> {
@@ -259,10 +320,21 @@ mod tests {
         assert_eq!(classify_tool("Task"), "task_spawn");
         assert_eq!(classify_tool("WebFetch"), "web_fetch");
         assert_eq!(classify_tool("WebSearch"), "web_search");
+        assert_eq!(classify_tool("TodoWrite"), "plan");
+        assert_eq!(classify_tool("ExitPlanMode"), "plan");
         assert_eq!(classify_tool("mcp__server__tool"), "mcp_call");
         assert_eq!(classify_tool("Unknown"), "tool_other");
     }
 
+    #[test]
+    fn parse_mcp_tool_names() {
+        assert_eq!(parse_mcp_tool("mcp__context7__query-docs"), Some(("context7", "query-docs")));
+        assert_eq!(parse_mcp_tool("mcp__plugin_nmem_nmem__search"), Some(("plugin_nmem_nmem", "search")));
+        assert_eq!(parse_mcp_tool("mcp__github__get__file"), Some(("github", "get__file")));
+        assert_eq!(parse_mcp_tool("mcp__broken"), None);
+        assert_eq!(parse_mcp_tool("Read"), None);
+    }
+
     #[test]
     fn test_classify_bash() {
         assert_eq!(classify_bash("git push"), "git_push");
@@ -324,6 +396,18 @@ mod tests {
         assert_eq!(extract_file_path("Bash", &json!({"command": "ls"})), None);
     }
 
+    #[test]
+    fn test_resolve_path() {
+        assert_eq!(resolve_path("src/", "/repo/crates/api"), "/repo/crates/api/src");
+        assert_eq!(resolve_path("./a/../b.rs", "/repo"), "/repo/b.rs");
+        assert_eq!(resolve_path("../shared/lib.rs", "/repo/crates/api"), "/repo/crates/shared/lib.rs");
+        assert_eq!(resolve_path("/etc/hosts", "/repo"), "/etc/hosts");
+        assert_eq!(resolve_path("src/main.rs", ""), "src/main.rs");
+        assert_eq!(normalize_cwd("/repo/crates/"), Some("/repo/crates".into()));
+        assert_eq!(normalize_cwd("/"), Some("/".into()));
+        assert_eq!(normalize_cwd("repo"), None);
+    }
+
     #[test]
     fn test_extract_git_commit_metadata() {
         let response = "[main 5356097] Add S2 scope classifier\n 14 files changed, 921 insertions(+), 29 deletions(-)\n create mode 100644 src/s2_scope.rs\n create mode 100644 models/converge-diverge.json";
@@ -1,4 +1,4 @@
-use git2::{Patch, Repository, Sort};
+use git2::{Delta, DiffFindOptions, Patch, Repository, Sort};
 use regex::Regex;
 use serde::Serialize;
 use std::collections::{HashMap, HashSet};
@@ -38,6 +38,9 @@ pub struct CoChange {
 #[derive(Debug, Clone, Serialize)]
 pub struct FileHistory {
     pub path: String,
+    /// Earlier names of the file, found by following renames (nearest first).
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub renamed_from: Vec<String>,
     pub commits: Vec<FileCommit>,
     pub churn: ChurnMetrics,
     pub co_changes: Vec<CoChange>,
@@ -91,6 +94,9 @@ pub fn file_history(repo_path: &Path, file_path: &str, opts: &QueryOpts) -> Resu
     let mut commits = Vec::new();
     let mut co_change_counts: HashMap<String, usize> = HashMap::new();
     let mut found_any = false;
+    // Path the file had at the commit being visited — changes when walking past a rename
+    let mut tracked = file_path.to_string();
+    let mut renamed_from: Vec<String> = Vec::new();
 
     for oid_result in revwalk {
         let oid = oid_result.map_err(|e| NmemError::Config(format!("git: {e}")))?;
@@ -99,7 +105,7 @@ pub fn file_history(repo_path: &Path, file_path: &str, opts: &QueryOpts) -> Resu
         let tree = commit.tree()
             .map_err(|e| NmemError::Config(format!("git: {e}")))?;
 
-        let entry = match tree.get_path(Path::new(file_path)) {
+        let entry = match tree.get_path(Path::new(&tracked)) {
             Ok(e) => e,
             Err(_) => continue,
         };
@@ -116,19 +122,28 @@ pub fn file_history(repo_path: &Path, file_path: &str, opts: &QueryOpts) -> Resu
         };
 
         // Fast: compare blob OIDs — skip if unchanged
-        if let Some(ref pt) = parent_tree
-            && let Ok(parent_entry) = pt.get_path(Path::new(file_path))
-            && parent_entry.id() == entry.id()
-        {
+        let parent_entry = parent_tree.as_ref().and_then(|pt| pt.get_path(Path::new(&tracked)).ok());
+        if parent_entry.as_ref().is_some_and(|pe| pe.id() == entry.id()) {
             continue;
         }
 
-        let diff = repo.diff_tree_to_tree(
+        let mut diff = repo.diff_tree_to_tree(
             parent_tree.as_ref(),
             Some(&tree),
             None,
         ).map_err(|e| NmemError::Config(format!("git: {e}")))?;
 
+        // Added relative to the parent — may be a rename; similarity detection tells
+        let mut renamed_to_old: Option<String> = None;
+        if parent_tree.is_some() && parent_entry.is_none() {
+            diff.find_similar(Some(DiffFindOptions::new().renames(true)))
+                .map_err(|e| NmemError::Config(format!("git: {e}")))?;
+            renamed_to_old = diff.deltas()
+                .find(|d| d.status() == Delta::Renamed
+                    && d.new_file().path().is_some_and(|p| p == Path::new(&tracked)))
+                .and_then(|d| d.old_file().path().map(|p| p.to_string_lossy().to_string()));
+        }
+
         let mut insertions = 0;
         let mut deletions = 0;
         let mut co_changed = Vec::new();
@@ -142,7 +157,7 @@ pub fn file_history(repo_path: &Path, file_path: &str, opts: &QueryOpts) -> Resu
                 None => continue,
             };
 
-            if delta_path_str == file_path {
+            if delta_path_str == tracked {
                 if let Ok(Some(ref mut patch)) = Patch::from_diff(&diff, idx)
                     && let Ok((_ctx, add, del)) = patch.line_stats()
                 {
@@ -174,6 +189,11 @@ pub fn file_history(repo_path: &Path, file_path: &str, opts: &QueryOpts) -> Resu
         if commits.len() >= opts.max_commits {
             break;
         }
+
+        if let Some(old) = renamed_to_old {
+            renamed_from.push(old.clone());
+            tracked = old;
+        }
     }
 
     if !found_any {
@@ -193,17 +213,46 @@ pub fn file_history(repo_path: &Path, file_path: &str, opts: &QueryOpts) -> Resu
         .into_iter()
         .map(|(path, frequency)| CoChange { path, frequency })
         .collect();
-    co_changes.sort_by(|a, b| b.frequency.cmp(&a.frequency));
+    co_changes.sort_by_key(|c| std::cmp::Reverse(c.frequency));
     co_changes.truncate(opts.max_co_changes);
 
     Ok(FileHistory {
         path: file_path.to_string(),
+        renamed_from,
         commits,
         churn,
         co_changes,
     })
 }
 
+/// Renames in the HEAD commit (vs. its first parent), as absolute (old, new) paths.
+pub fn head_renames(repo_path: &Path) -> Result<Vec<(String, String)>, NmemError> {
+    let git_err = |e: git2::Error| NmemError::Config(format!("git: {e}"));
+    let repo = Repository::discover(repo_path).map_err(git_err)?;
+    let Some(workdir) = repo.workdir().map(Path::to_path_buf) else {
+        return Ok(Vec::new());
+    };
+    let commit = repo.head().and_then(|h| h.peel_to_commit()).map_err(git_err)?;
+    if commit.parent_count() == 0 {
+        return Ok(Vec::new());
+    }
+    let parent_tree = commit.parent(0).and_then(|p| p.tree()).map_err(git_err)?;
+    let tree = commit.tree().map_err(git_err)?;
+
+    let mut diff = repo.diff_tree_to_tree(Some(&parent_tree), Some(&tree), None).map_err(git_err)?;
+    diff.find_similar(Some(DiffFindOptions::new().renames(true))).map_err(git_err)?;
+
+    Ok(diff
+        .deltas()
+        .filter(|d| d.status() == Delta::Renamed)
+        .filter_map(|d| {
+            let old = workdir.join(d.old_file().path()?);
+            let new = workdir.join(d.new_file().path()?);
+            Some((old.to_string_lossy().to_string(), new.to_string_lossy().to_string()))
+        })
+        .collect())
+}
+
 pub fn dense_summary(history: &FileHistory) -> String {
     let mut out = String::new();
 
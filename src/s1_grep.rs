@@ -0,0 +1,260 @@
+//! S1 Operations — regex search over stored content.
+//!
+//! FTS matches tokens, so it can't find `E0(5|6)\d\d` or `timeout=\d{4,}`.
+//! `nmem grep` and the MCP `grep` tool scan observations newest first —
+//! content (with its chunks) and then metadata, where failed commands keep
+//! their error output — and stop at `limit` matches or after `max_scan`
+//! rows, whichever comes first. An optional FTS query narrows the scan to
+//! its matches first; use it for broad patterns over a large store.
+
+#[cfg(feature = "cli")]
+use crate::cli::GrepArgs;
+use crate::NmemError;
+use regex::{Regex, RegexBuilder};
+use rusqlite::{Connection, params};
+use serde::Serialize;
+#[cfg(feature = "cli")]
+use std::path::Path;
+
+/// Longest pattern accepted.
+pub const MAX_PATTERN_CHARS: usize = 500;
+/// Compiled-regex size cap, so a pathological pattern fails to compile
+/// instead of eating memory.
+const REGEX_SIZE_LIMIT: usize = 1 << 20;
+/// Chars of the matching line kept around the match.
+const LINE_CHARS: usize = 200;
+
+#[derive(Debug, Serialize)]
+pub struct GrepMatch {
+    pub id: i64,
+    pub timestamp: i64,
+    pub obs_type: String,
+    pub session_id: String,
+    pub project: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub file_path: Option<String>,
+    /// `content` or `metadata`.
+    pub field: &'static str,
+    /// The first match.
+    pub matched: String,
+    /// The line it is on, cut to `LINE_CHARS` around it.
+    pub line: String,
+}
+
+#[derive(Debug, Serialize)]
+pub struct GrepResult {
+    pub matches: Vec<GrepMatch>,
+    /// Rows examined.
+    pub scanned: i64,
+    /// The scan stopped at `max_scan` before reaching the oldest row, so
+    /// older matches may exist.
+    pub scan_limited: bool,
+}
+
+#[derive(Debug, Default)]
+pub struct GrepFilters<'a> {
+    pub project: Option<&'a str>,
+    pub obs_type: Option<&'a str>,
+    /// FTS query the rows must also match.
+    pub fts: Option<&'a str>,
+    pub before: Option<i64>,
+    pub after: Option<i64>,
+}
+
+/// Compile `pattern` within the size and length limits.
+pub fn compile(pattern: &str, ignore_case: bool) -> Result<Regex, NmemError> {
+    if pattern.is_empty() {
+        return Err(NmemError::Config("grep pattern is empty".into()));
+    }
+    if pattern.chars().count() > MAX_PATTERN_CHARS {
+        return Err(NmemError::Config(format!("grep pattern is longer than {MAX_PATTERN_CHARS} chars")));
+    }
+    RegexBuilder::new(pattern)
+        .case_insensitive(ignore_case)
+        .size_limit(REGEX_SIZE_LIMIT)
+        .build()
+        .map_err(|e| NmemError::Config(format!("invalid grep pattern: {e}")))
+}
+
+/// Matches of `re`, newest first, at most `limit`, examining at most
+/// `max_scan` rows.
+pub fn grep(
+    conn: &Connection,
+    re: &Regex,
+    filters: &GrepFilters,
+    limit: usize,
+    max_scan: i64,
+) -> Result<GrepResult, NmemError> {
+    let fts = match filters.fts {
+        Some(q) => match crate::sanitize_fts_query(q) {
+            Some(q) => Some(q),
+            None => return Ok(GrepResult { matches: Vec::new(), scanned: 0, scan_limited: false }),
+        },
+        None => None,
+    };
+    let sql = concat!(
+        "SELECT o.id, o.timestamp, o.obs_type, o.session_id, s.project, o.file_path, ",
+        crate::s1_chunk::full_content_sql!(),
+        ", o.metadata
+         FROM observations o JOIN sessions s ON s.id = o.session_id
+         WHERE (?2 IS NULL OR s.project = ?2)
+           AND (?3 IS NULL OR o.obs_type = ?3)
+           AND (?4 IS NULL OR o.timestamp < ?4)
+           AND (?5 IS NULL OR o.timestamp > ?5)
+           AND (?1 IS NULL OR o.id IN (
+               SELECT rowid FROM observations_fts WHERE observations_fts MATCH ?1
+               UNION
+               SELECT c.obs_id FROM observation_chunks_fts
+               JOIN observation_chunks c ON c.id = observation_chunks_fts.rowid
+               WHERE observation_chunks_fts MATCH ?1))
+         ORDER BY o.id DESC"
+    );
+    let mut stmt = conn.prepare(sql)?;
+    let mut rows = stmt.query(params![fts, filters.project, filters.obs_type, filters.before, filters.after])?;
+
+    let mut matches = Vec::new();
+    let mut scanned = 0;
+    let mut scan_limited = false;
+    while let Some(row) = rows.next()? {
+        if scanned >= max_scan {
+            scan_limited = true;
+            break;
+        }
+        scanned += 1;
+        let content: String = row.get(6)?;
+        let metadata: Option<String> = row.get(7)?;
+        let hit = re
+            .find(&content)
+            .map(|m| ("content", line_around(&content, m.start(), m.end()), m.as_str().to_string()))
+            .or_else(|| {
+                let metadata = metadata.as_deref()?;
+                let m = re.find(metadata)?;
+                Some(("metadata", line_around(metadata, m.start(), m.end()), m.as_str().to_string()))
+            });
+        let Some((field, line, matched)) = hit else { continue };
+        matches.push(GrepMatch {
+            id: row.get(0)?,
+            timestamp: row.get(1)?,
+            obs_type: row.get(2)?,
+            session_id: row.get(3)?,
+            project: row.get(4)?,
+            file_path: row.get(5)?,
+            field,
+            matched,
+            line,
+        });
+        if matches.len() >= limit {
+            break;
+        }
+    }
+    Ok(GrepResult { matches, scanned, scan_limited })
+}
+
+/// The line of `text` holding bytes `start..end`, cut to `LINE_CHARS`
+/// centred on the match.
+fn line_around(text: &str, start: usize, end: usize) -> String {
+    let line_start = text[..start].rfind('\n').map_or(0, |i| i + 1);
+    let line_end = text[end..].find('\n').map_or(text.len(), |i| end + i);
+    let line = &text[line_start..line_end];
+    if line.chars().count() <= LINE_CHARS {
+        return line.trim().to_string();
+    }
+    let before = text[line_start..start].chars().count();
+    let skip = before.saturating_sub(LINE_CHARS / 2);
+    let cut: String = line.chars().skip(skip).take(LINE_CHARS).collect();
+    let mut out = String::new();
+    if skip > 0 {
+        out.push('…');
+    }
+    out.push_str(cut.trim());
+    if skip + LINE_CHARS < line.chars().count() {
+        out.push('…');
+    }
+    out
+}
+
+#[cfg(feature = "cli")]
+pub fn handle_grep(db_path: &Path, args: &GrepArgs) -> Result<(), NmemError> {
+    let re = compile(&args.pattern, args.ignore_case)?;
+    let conn = crate::db::open_db_readonly(db_path)?;
+    let filters = GrepFilters {
+        project: args.project.as_deref(),
+        obs_type: args.obs_type.as_deref(),
+        fts: args.fts.as_deref(),
+        ..GrepFilters::default()
+    };
+    let result = grep(&conn, &re, &filters, args.limit.clamp(1, 500), args.max_scan.max(1))?;
+    for m in &result.matches {
+        if args.json {
+            println!("{}", serde_json::to_string(m)?);
+        } else {
+            println!(
+                "#{:<7} {:>8}  {:<12} {:<14} {}",
+                m.id,
+                crate::timefmt::relative(m.timestamp),
+                m.obs_type,
+                m.project,
+                m.line
+            );
+        }
+    }
+    log::info!(
+        "{} matches in {} rows scanned{}",
+        result.matches.len(),
+        result.scanned,
+        if result.scan_limited { " (scan limit reached; narrow with --fts, --project or --type)" } else { "" }
+    );
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn regex_scan_finds_content_and_metadata() {
+        let mut conn = Connection::open_in_memory().unwrap();
+        crate::schema::MIGRATIONS.to_latest(&mut conn).unwrap();
+        conn.execute_batch(
+            r#"INSERT INTO sessions (id, project, started_at) VALUES ('s', 'p', 0), ('t', 'q', 0);
+               INSERT INTO observations (id, session_id, timestamp, obs_type, source_event, content, metadata) VALUES
+                 (1, 's', 10, 'command', 'PostToolUse', 'cargo build', '{"failed":true,"response":"error[E0502]: cannot borrow"}'),
+                 (2, 's', 20, 'command', 'PostToolUse', 'rustc --explain E0599', NULL),
+                 (3, 's', 30, 'command', 'PostToolUse', 'rustc --explain E0433', NULL),
+                 (4, 't', 40, 'file_edit', 'PostToolUse', 'note about E0501', NULL);"#,
+        )
+        .unwrap();
+        let re = compile(r"E0(5|6)\d\d", false).unwrap();
+
+        let all = grep(&conn, &re, &GrepFilters::default(), 10, 1000).unwrap();
+        let ids: Vec<(i64, &str)> = all.matches.iter().map(|m| (m.id, m.field)).collect();
+        assert_eq!(ids, vec![(4, "content"), (2, "content"), (1, "metadata")]);
+        assert_eq!(all.matches[1].matched, "E0599");
+        assert_eq!((all.scanned, all.scan_limited), (4, false));
+
+        let filtered = GrepFilters { project: Some("p"), obs_type: Some("command"), ..GrepFilters::default() };
+        assert_eq!(grep(&conn, &re, &filtered, 1, 1000).unwrap().matches[0].id, 2);
+
+        // The scan stops at max_scan rows
+        let bounded = grep(&conn, &re, &GrepFilters::default(), 10, 2).unwrap();
+        assert_eq!((bounded.matches.len(), bounded.scan_limited), (1, true));
+
+        // An FTS pre-filter narrows the rows scanned
+        let narrowed = GrepFilters { fts: Some("rustc"), ..GrepFilters::default() };
+        let result = grep(&conn, &re, &narrowed, 10, 1000).unwrap();
+        assert_eq!((result.matches.len(), result.scanned), (1, 2));
+
+        assert!(compile("(unclosed", false).is_err());
+        assert!(compile("", false).is_err());
+    }
+
+    #[test]
+    fn long_lines_are_cut_around_the_match() {
+        let text = format!("first\n{}needle{}\nlast", "a".repeat(300), "b".repeat(300));
+        let start = text.find("needle").unwrap();
+        let line = line_around(&text, start, start + 6);
+        assert!(line.starts_with('…') && line.ends_with('…'));
+        assert!(line.contains("needle"));
+        assert_eq!(line.chars().count(), LINE_CHARS + 2);
+    }
+}
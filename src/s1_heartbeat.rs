@@ -0,0 +1,164 @@
+//! S1 Operations — heartbeats from long-running commands.
+//!
+//! A build or migration that runs for many minutes lands as one observation
+//! when it finishes, so the timeline and stance see nothing while it runs.
+//! A heartbeat — a `Heartbeat` hook event, or `nmem record --heartbeat` from
+//! a wrapper script — stores a `heartbeat` observation carrying the progress
+//! message, tied to the running call: the payload's `tool_use_id`, else the
+//! newest pending Bash call in `tool_intents`. When that call's PostToolUse
+//! arrives, its heartbeats gain `command_obs`, the id of the command
+//! observation.
+
+use crate::NmemError;
+use rusqlite::{Connection, OptionalExtension, params};
+
+/// Hook event name of a heartbeat.
+pub const EVENT: &str = "Heartbeat";
+/// Obs_type heartbeats are stored under.
+pub const OBS_TYPE: &str = "heartbeat";
+
+/// Chars of the running command kept in a heartbeat's content.
+const COMMAND_CHARS: usize = 200;
+
+struct Running {
+    intent_id: i64,
+    tool_use_id: Option<String>,
+    tool_name: String,
+    started_at: i64,
+    content: String,
+}
+
+/// The pending call a heartbeat belongs to.
+fn running(conn: &Connection, session_id: &str, tool_use_id: Option<&str>) -> Result<Option<Running>, NmemError> {
+    Ok(conn
+        .query_row(
+            "SELECT id, tool_use_id, tool_name, timestamp, content FROM tool_intents
+             WHERE session_id = ?1 AND status = ?2
+               AND (tool_use_id = ?3 OR (?3 IS NULL AND tool_name = 'Bash'))
+             ORDER BY id DESC LIMIT 1",
+            params![session_id, crate::s1_intent::PENDING, tool_use_id],
+            |r| {
+                Ok(Running {
+                    intent_id: r.get(0)?,
+                    tool_use_id: r.get(1)?,
+                    tool_name: r.get(2)?,
+                    started_at: r.get(3)?,
+                    content: r.get(4)?,
+                })
+            },
+        )
+        .optional()?)
+}
+
+/// Store a heartbeat observation. `message` must already be redacted.
+/// Returns the observation id.
+#[allow(clippy::too_many_arguments)]
+pub fn record(
+    conn: &Connection,
+    session_id: &str,
+    prompt_id: Option<i64>,
+    tool_use_id: Option<&str>,
+    message: Option<&str>,
+    cwd: Option<&str>,
+    ts: i64,
+) -> Result<i64, NmemError> {
+    let call = running(conn, session_id, tool_use_id)?;
+    let message = message.map(str::trim).filter(|m| !m.is_empty()).unwrap_or("still running");
+    let content = match &call {
+        Some(c) => format!("{} — {message}", c.content.chars().take(COMMAND_CHARS).collect::<String>()),
+        None => message.to_string(),
+    };
+    let mut meta = serde_json::Map::new();
+    if let Some(id) = tool_use_id.map(String::from).or_else(|| call.as_ref().and_then(|c| c.tool_use_id.clone())) {
+        meta.insert("tool_use_id".into(), id.into());
+    }
+    if let Some(c) = &call {
+        meta.insert("intent_id".into(), c.intent_id.into());
+        meta.insert("elapsed_s".into(), (ts - c.started_at).max(0).into());
+    }
+    let metadata = (!meta.is_empty()).then(|| serde_json::Value::Object(meta).to_string());
+    conn.execute(
+        "INSERT INTO observations (session_id, prompt_id, timestamp, obs_type, source_event, tool_name, content, metadata, cwd)
+         VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9)",
+        params![
+            session_id,
+            prompt_id,
+            ts,
+            OBS_TYPE,
+            EVENT,
+            call.as_ref().map(|c| c.tool_name.as_str()),
+            content,
+            metadata,
+            cwd
+        ],
+    )?;
+    Ok(conn.last_insert_rowid())
+}
+
+/// Point the heartbeats of the call behind command observation `obs_id` at
+/// it, matched by `tool_use_id` or the call's intent. Returns how many were.
+pub fn link(
+    conn: &Connection,
+    session_id: &str,
+    tool_use_id: Option<&str>,
+    intent_id: Option<i64>,
+    obs_id: i64,
+) -> Result<usize, NmemError> {
+    if tool_use_id.is_none() && intent_id.is_none() {
+        return Ok(0);
+    }
+    Ok(conn.execute(
+        "UPDATE observations SET metadata = json_set(COALESCE(metadata, '{}'), '$.command_obs', ?4)
+         WHERE session_id = ?1 AND obs_type = ?5
+           AND json_extract(metadata, '$.command_obs') IS NULL
+           AND (json_extract(metadata, '$.tool_use_id') = ?2 OR json_extract(metadata, '$.intent_id') = ?3)",
+        params![session_id, tool_use_id, intent_id, obs_id, OBS_TYPE],
+    )?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn heartbeats_attach_to_the_running_call_and_then_its_observation() {
+        let mut conn = Connection::open_in_memory().unwrap();
+        crate::schema::MIGRATIONS.to_latest(&mut conn).unwrap();
+        conn.execute("INSERT INTO sessions (id, project, started_at) VALUES ('s', 'p', 1)", []).unwrap();
+        let intent =
+            crate::s1_intent::record(&conn, "s", None, Some("toolu_1"), "Bash", None, "cargo build --release", 100)
+                .unwrap();
+
+        let a = record(&conn, "s", None, None, Some("Compiling serde (41/310)"), None, 160).unwrap();
+        let b = record(&conn, "s", None, Some("toolu_1"), None, None, 220).unwrap();
+        let stray = record(&conn, "s", None, Some("toolu_other"), Some("done?"), None, 230).unwrap();
+
+        let (content, meta): (String, String) = conn
+            .query_row("SELECT content, metadata FROM observations WHERE id = ?1", [a], |r| Ok((r.get(0)?, r.get(1)?)))
+            .unwrap();
+        assert_eq!(content, "cargo build --release — Compiling serde (41/310)");
+        let meta: serde_json::Value = serde_json::from_str(&meta).unwrap();
+        assert_eq!(meta["tool_use_id"], "toolu_1");
+        assert_eq!(meta["elapsed_s"], 60);
+
+        conn.execute(
+            "INSERT INTO observations (id, session_id, timestamp, obs_type, source_event, content)
+             VALUES (50, 's', 300, 'command', 'PostToolUse', 'cargo build --release')",
+            [],
+        )
+        .unwrap();
+        assert_eq!(link(&conn, "s", Some("toolu_1"), Some(intent), 50).unwrap(), 2);
+        let linked: Vec<Option<i64>> = [a, b, stray]
+            .iter()
+            .map(|id| {
+                conn.query_row(
+                    "SELECT json_extract(metadata, '$.command_obs') FROM observations WHERE id = ?1",
+                    [id],
+                    |r| r.get(0),
+                )
+                .unwrap()
+            })
+            .collect();
+        assert_eq!(linked, vec![Some(50), Some(50), None]);
+    }
+}
@@ -0,0 +1,162 @@
+//! S1 Operations — query-time project inference.
+//!
+//! Questions often name a file ("why does src/s3_sweep.rs keep changing?")
+//! without naming the project. Paths mentioned in a query are matched
+//! against observed `file_path`s; when the matches point at one project,
+//! search is scoped to it and the response says so.
+
+use crate::NmemError;
+use rusqlite::{Connection, params};
+use serde::Serialize;
+use std::collections::BTreeMap;
+
+/// Paths considered per query.
+const MAX_PATHS: usize = 5;
+
+#[derive(Debug, Serialize, PartialEq)]
+pub struct InferredProject {
+    pub project: String,
+    /// Mentioned paths that matched observations in `project`.
+    pub paths: Vec<String>,
+    /// Share of matching observations that belong to `project`.
+    pub confidence: f64,
+}
+
+/// Path-like tokens in `query`: anything with a `/`, or a `name.ext` file
+/// name. URLs are skipped.
+pub fn mentioned_paths(query: &str) -> Vec<String> {
+    let mut paths: Vec<String> = Vec::new();
+    for token in query.split_whitespace() {
+        let token = token
+            .trim_matches(|c: char| matches!(c, '"' | '\'' | '`' | '(' | ')' | '[' | ']' | ',' | ';' | ':' | '?' | '!'))
+            .trim_end_matches('.');
+        let token = token.strip_prefix("./").unwrap_or(token);
+        if token.contains("://") || token.is_empty() || !looks_like_path(token) {
+            continue;
+        }
+        if !paths.iter().any(|p| p == token) {
+            paths.push(token.to_string());
+        }
+        if paths.len() == MAX_PATHS {
+            break;
+        }
+    }
+    paths
+}
+
+fn looks_like_path(token: &str) -> bool {
+    if token.contains('/') {
+        return token.chars().any(|c| c.is_alphanumeric());
+    }
+    match token.rsplit_once('.') {
+        Some((stem, ext)) => {
+            stem.len() >= 2
+                && !ext.is_empty()
+                && ext.len() <= 5
+                && ext.chars().all(|c| c.is_ascii_alphanumeric())
+                && ext.chars().any(|c| c.is_ascii_alphabetic())
+        }
+        None => false,
+    }
+}
+
+/// Observation counts per (project, mentioned path). A path matches a
+/// `file_path` equal to it or ending in `/<path>`.
+pub fn path_project_counts(
+    conn: &Connection,
+    paths: &[String],
+) -> Result<Vec<(String, String, i64)>, NmemError> {
+    let mut stmt = conn.prepare(
+        "SELECT s.project, COUNT(*)
+         FROM observations o JOIN sessions s ON s.id = o.session_id
+         WHERE o.file_path IS NOT NULL AND s.project IS NOT NULL
+           AND (o.file_path = ?1
+                OR (length(o.file_path) > length(?1)
+                    AND substr(o.file_path, -length(?1) - 1) = '/' || ?1))
+         GROUP BY s.project",
+    )?;
+    let mut counts = Vec::new();
+    for path in paths {
+        let rows = stmt.query_map(params![path], |r| Ok((r.get::<_, String>(0)?, r.get::<_, i64>(1)?)))?;
+        for row in rows {
+            let (project, n) = row?;
+            counts.push((project, path.clone(), n));
+        }
+    }
+    Ok(counts)
+}
+
+/// The project most matching observations belong to, if it holds a clear
+/// majority (over half).
+pub fn pick_project(counts: &[(String, String, i64)]) -> Option<InferredProject> {
+    let mut by_project: BTreeMap<&str, (i64, Vec<&str>)> = BTreeMap::new();
+    for (project, path, n) in counts {
+        let entry = by_project.entry(project).or_default();
+        entry.0 += n;
+        if !entry.1.contains(&path.as_str()) {
+            entry.1.push(path);
+        }
+    }
+    let total: i64 = by_project.values().map(|(n, _)| n).sum();
+    let mut ranked: Vec<_> = by_project.into_iter().collect();
+    ranked.sort_by_key(|(_, (n, _))| std::cmp::Reverse(*n));
+    let (project, (n, paths)) = ranked.first()?;
+    let confidence = *n as f64 / total as f64;
+    if confidence <= 0.5 {
+        return None;
+    }
+    Some(InferredProject {
+        project: project.to_string(),
+        paths: paths.iter().map(|p| p.to_string()).collect(),
+        confidence: (confidence * 100.0).round() / 100.0,
+    })
+}
+
+/// Infer a project from the paths in `query` using one store.
+pub fn infer_project(conn: &Connection, query: &str) -> Result<Option<InferredProject>, NmemError> {
+    let paths = mentioned_paths(query);
+    if paths.is_empty() {
+        return Ok(None);
+    }
+    Ok(pick_project(&path_project_counts(conn, &paths)?))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn extracts_paths_and_file_names() {
+        assert_eq!(
+            mentioned_paths("why does src/s3_sweep.rs keep changing? see `Cargo.toml`, e.g. ./lib/x.ts."),
+            vec!["src/s3_sweep.rs", "Cargo.toml", "lib/x.ts"]
+        );
+        assert!(mentioned_paths("fetch https://example.com/a.json and v1.2 or 3.14").is_empty());
+        assert!(mentioned_paths("retry backoff").is_empty());
+    }
+
+    #[test]
+    fn infers_the_majority_project() {
+        let mut conn = Connection::open_in_memory().unwrap();
+        crate::schema::MIGRATIONS.to_latest(&mut conn).unwrap();
+        conn.execute_batch(
+            "INSERT INTO sessions (id, project, started_at) VALUES ('a', 'nmem', 1), ('b', 'other', 1);
+             INSERT INTO observations (session_id, timestamp, obs_type, source_event, file_path, content)
+                 VALUES ('a', 1, 'file_edit', 'PostToolUse', '/home/u/nmem/src/s3_sweep.rs', 'e'),
+                        ('a', 2, 'file_read', 'PostToolUse', 'src/s3_sweep.rs', 'r'),
+                        ('b', 3, 'file_read', 'PostToolUse', '/home/u/other/src/s3_sweep.rs', 'r'),
+                        ('b', 4, 'file_read', 'PostToolUse', '/home/u/other/README.md', 'r'),
+                        ('b', 5, 'file_read', 'PostToolUse', '/home/u/other/xsrc/s3_sweep.rs', 'r');",
+        )
+        .unwrap();
+
+        let inferred = infer_project(&conn, "why does src/s3_sweep.rs keep changing?").unwrap().unwrap();
+        assert_eq!(inferred.project, "nmem");
+        assert_eq!(inferred.paths, vec!["src/s3_sweep.rs"]);
+        assert_eq!(inferred.confidence, 0.67);
+
+        // Split evenly: no inference
+        assert!(infer_project(&conn, "src/s3_sweep.rs vs README.md").unwrap().is_none());
+        assert!(infer_project(&conn, "nothing/here.rs").unwrap().is_none());
+    }
+}
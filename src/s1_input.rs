@@ -0,0 +1,429 @@
+//! S1 Operations — limits on MCP tool inputs.
+//!
+//! Each tool clamps its own `limit`, but nothing bounded the rest: a
+//! 10,000-character query expands into thousands of FTS terms, a huge
+//! `offset` scans the whole table, and out-of-range timestamps overflow
+//! window arithmetic. Every `do_*` entry point whose params carry text, ids
+//! or timestamps validates them here first; pure counts (`limit`, `days`)
+//! stay clamped by the tool. A violation is an `INVALID_PARAMS` error whose
+//! `data` names the parameter, the reason (`too_long`, `too_many`,
+//! `too_few`, `out_of_range`, `empty_range`, `too_wide`, `duplicate`), and
+//! the limit.
+
+use crate::s1_serve::*;
+use rmcp::ErrorData;
+use rmcp::model::ErrorCode;
+use serde_json::json;
+
+/// Longest FTS query (`search.query`, `grep.fts`).
+pub const MAX_QUERY_CHARS: usize = 512;
+/// Most whitespace-separated terms in an FTS query.
+pub const MAX_QUERY_TERMS: usize = 32;
+/// Longest file path or working directory.
+pub const MAX_PATH_CHARS: usize = 1024;
+/// Longest identifier: project, session id, type and class names, keys.
+pub const MAX_NAME_CHARS: usize = 256;
+/// Longest page cursor.
+pub const MAX_CURSOR_CHARS: usize = 512;
+/// Longest free text written by a tool (marker, task prompt, question, conclusion).
+pub const MAX_TEXT_CHARS: usize = 10_000;
+/// Most ids fetched at once.
+pub const MAX_IDS: usize = 50;
+/// Most sub-requests in one `bundle`.
+pub const MAX_BUNDLE_REQUESTS: usize = 10;
+/// Deepest `offset` paged to.
+pub const MAX_OFFSET: i64 = 10_000;
+/// Most commits `git_file_summary` walks.
+pub const MAX_COMMITS: usize = 1000;
+/// Latest timestamp accepted, 9999-12-31.
+pub const MAX_TIMESTAMP: i64 = 253_402_300_799;
+/// Widest `after`..`before` window, in seconds (366 days). Omit a bound to
+/// search open-ended.
+pub const MAX_RANGE_SECS: i64 = 366 * 86_400;
+
+/// Params a tool checks before touching the database.
+pub trait Validate {
+    fn validate(&self) -> Result<(), ErrorData>;
+}
+
+/// `INVALID_PARAMS` for `param`, with `data` for programmatic handling.
+pub fn invalid(param: &str, reason: &str, message: String, limit: serde_json::Value) -> ErrorData {
+    ErrorData::new(
+        ErrorCode::INVALID_PARAMS,
+        format!("{param}: {message}"),
+        Some(json!({"param": param, "reason": reason, "limit": limit})),
+    )
+}
+
+fn text(param: &str, value: &str, max: usize) -> Result<(), ErrorData> {
+    let len = value.chars().count();
+    if len > max {
+        return Err(invalid(param, "too_long", format!("{len} chars, max {max}"), json!(max)));
+    }
+    Ok(())
+}
+
+fn opt(param: &str, value: &Option<String>, max: usize) -> Result<(), ErrorData> {
+    value.as_deref().map_or(Ok(()), |v| text(param, v, max))
+}
+
+fn query(param: &str, value: &str) -> Result<(), ErrorData> {
+    text(param, value, MAX_QUERY_CHARS)?;
+    let terms = value.split_whitespace().count();
+    if terms > MAX_QUERY_TERMS {
+        return Err(invalid(param, "too_many", format!("{terms} terms, max {MAX_QUERY_TERMS}"), json!(MAX_QUERY_TERMS)));
+    }
+    Ok(())
+}
+
+fn timestamp(param: &str, value: Option<i64>) -> Result<(), ErrorData> {
+    match value {
+        Some(ts) if !(0..=MAX_TIMESTAMP).contains(&ts) => Err(invalid(
+            param,
+            "out_of_range",
+            format!("{ts} is not a Unix timestamp between 0 and {MAX_TIMESTAMP}"),
+            json!([0, MAX_TIMESTAMP]),
+        )),
+        _ => Ok(()),
+    }
+}
+
+/// `after` and `before` are valid timestamps and leave a window between
+/// them no wider than [`MAX_RANGE_SECS`].
+fn range(after: Option<i64>, before: Option<i64>) -> Result<(), ErrorData> {
+    timestamp("after", after)?;
+    timestamp("before", before)?;
+    if let (Some(a), Some(b)) = (after, before) {
+        if a >= b {
+            return Err(invalid("after", "empty_range", format!("{a} is not before `before` ({b})"), json!(b)));
+        }
+        if b - a > MAX_RANGE_SECS {
+            return Err(invalid(
+                "before",
+                "too_wide",
+                format!("{}s after `after`, max {MAX_RANGE_SECS}s — omit a bound to search open-ended", b - a),
+                json!(MAX_RANGE_SECS),
+            ));
+        }
+    }
+    Ok(())
+}
+
+fn offset(value: Option<i64>) -> Result<(), ErrorData> {
+    match value {
+        Some(n) if n > MAX_OFFSET => Err(invalid(
+            "offset",
+            "out_of_range",
+            format!("{n}, max {MAX_OFFSET} — narrow the query or page with cursor"),
+            json!(MAX_OFFSET),
+        )),
+        _ => Ok(()),
+    }
+}
+
+impl Validate for SearchParams {
+    fn validate(&self) -> Result<(), ErrorData> {
+        query("query", &self.query)?;
+        opt("project", &self.project, MAX_NAME_CHARS)?;
+        opt("obs_type", &self.obs_type, MAX_NAME_CHARS)?;
+        opt("error_class", &self.error_class, MAX_NAME_CHARS)?;
+        opt("mcp_server", &self.mcp_server, MAX_NAME_CHARS)?;
+        opt("mcp_tool", &self.mcp_tool, MAX_NAME_CHARS)?;
+        opt("cwd", &self.cwd, MAX_PATH_CHARS)?;
+        opt("cursor", &self.cursor, MAX_CURSOR_CHARS)?;
+        offset(self.offset)?;
+        opt("window", &self.window, MAX_NAME_CHARS)?;
+        range(self.after, self.before)
+    }
+}
+
+impl Validate for TimelineParams {
+    fn validate(&self) -> Result<(), ErrorData> {
+        if self.anchor < 1 {
+            return Err(invalid("anchor", "out_of_range", format!("{} is not an observation id", self.anchor), json!(1)));
+        }
+        Ok(())
+    }
+}
+
+impl Validate for GetObservationsParams {
+    fn validate(&self) -> Result<(), ErrorData> {
+        if self.ids.len() > MAX_IDS {
+            return Err(invalid("ids", "too_many", format!("{} ids, max {MAX_IDS}", self.ids.len()), json!(MAX_IDS)));
+        }
+        Ok(())
+    }
+}
+
+impl Validate for SessionSummariesParams {
+    fn validate(&self) -> Result<(), ErrorData> {
+        opt("project", &self.project, MAX_NAME_CHARS)?;
+        opt("model", &self.model, MAX_NAME_CHARS)?;
+        opt("cursor", &self.cursor, MAX_CURSOR_CHARS)?;
+        opt("window", &self.window, MAX_NAME_CHARS)?;
+        range(self.after, self.before)
+    }
+}
+
+impl Validate for RecentContextParams {
+    fn validate(&self) -> Result<(), ErrorData> {
+        opt("project", &self.project, MAX_NAME_CHARS)?;
+        opt("session_id", &self.session_id, MAX_NAME_CHARS)?;
+        opt("cursor", &self.cursor, MAX_CURSOR_CHARS)?;
+        opt("window", &self.window, MAX_NAME_CHARS)?;
+        range(self.after, self.before)
+    }
+}
+
+impl Validate for RegenerateContextParams {
+    fn validate(&self) -> Result<(), ErrorData> {
+        text("project", &self.project, MAX_NAME_CHARS)?;
+        timestamp("before", self.before)
+    }
+}
+
+impl Validate for SessionTraceParams {
+    fn validate(&self) -> Result<(), ErrorData> {
+        text("session_id", &self.session_id, MAX_NAME_CHARS)?;
+        opt("window", &self.window, MAX_NAME_CHARS)?;
+        range(self.after, self.before)
+    }
+}
+
+impl Validate for FileHistoryParams {
+    fn validate(&self) -> Result<(), ErrorData> {
+        text("file_path", &self.file_path, MAX_PATH_CHARS)?;
+        opt("project", &self.project, MAX_NAME_CHARS)?;
+        opt("window", &self.window, MAX_NAME_CHARS)?;
+        range(self.after, self.before)
+    }
+}
+
+impl Validate for FileSnapshotParams {
+    fn validate(&self) -> Result<(), ErrorData> {
+        text("file_path", &self.file_path, MAX_PATH_CHARS)?;
+        opt("session_id", &self.session_id, MAX_NAME_CHARS)?;
+        timestamp("before", self.before)
+    }
+}
+
+impl Validate for PlanHistoryParams {
+    fn validate(&self) -> Result<(), ErrorData> {
+        opt("session_id", &self.session_id, MAX_NAME_CHARS)?;
+        opt("project", &self.project, MAX_NAME_CHARS)
+    }
+}
+
+impl Validate for GitFileSummaryParams {
+    fn validate(&self) -> Result<(), ErrorData> {
+        text("file_path", &self.file_path, MAX_PATH_CHARS)?;
+        if self.max_commits > MAX_COMMITS {
+            return Err(invalid(
+                "max_commits",
+                "out_of_range",
+                format!("{}, max {MAX_COMMITS}", self.max_commits),
+                json!(MAX_COMMITS),
+            ));
+        }
+        Ok(())
+    }
+}
+
+impl Validate for ContextPackParams {
+    fn validate(&self) -> Result<(), ErrorData> {
+        opt("name", &self.name, MAX_NAME_CHARS)?;
+        opt("project", &self.project, MAX_NAME_CHARS)?;
+        opt("session_id", &self.session_id, MAX_NAME_CHARS)
+    }
+}
+
+impl Validate for QueueTaskParams {
+    fn validate(&self) -> Result<(), ErrorData> {
+        text("prompt", &self.prompt, MAX_TEXT_CHARS)?;
+        opt("project", &self.project, MAX_NAME_CHARS)?;
+        opt("cwd", &self.cwd, MAX_PATH_CHARS)?;
+        text("after", &self.after, MAX_NAME_CHARS)
+    }
+}
+
+impl Validate for CreateMarkerParams {
+    fn validate(&self) -> Result<(), ErrorData> {
+        text("text", &self.text, MAX_TEXT_CHARS)?;
+        opt("project", &self.project, MAX_NAME_CHARS)?;
+        opt("status", &self.status, MAX_NAME_CHARS)
+    }
+}
+
+/// `scope` of a memory tool: `project` or `global`.
+fn memory_scope(scope: &Option<String>) -> Result<(), ErrorData> {
+    match scope.as_deref() {
+        None | Some("project" | "global") => Ok(()),
+        Some(other) => Err(invalid(
+            "scope",
+            "invalid_value",
+            format!("{other:?} is not \"project\" or \"global\""),
+            json!(["project", "global"]),
+        )),
+    }
+}
+
+impl Validate for RememberParams {
+    fn validate(&self) -> Result<(), ErrorData> {
+        text("key", &self.key, crate::s1_remember::MAX_KEY_CHARS)?;
+        text("content", &self.content, MAX_TEXT_CHARS)?;
+        opt("project", &self.project, MAX_NAME_CHARS)?;
+        memory_scope(&self.scope)?;
+        match self.ttl.as_deref() {
+            Some(ttl) if crate::s4_dispatch::parse_relative(&ttl.trim().to_lowercase()).is_none() => Err(invalid(
+                "ttl",
+                "unparsable",
+                format!("{ttl:?} is not a duration like \"2h\" or \"7d\""),
+                json!(null),
+            )),
+            _ => Ok(()),
+        }
+    }
+}
+
+impl Validate for ForgetParams {
+    fn validate(&self) -> Result<(), ErrorData> {
+        text("key", &self.key, crate::s1_remember::MAX_KEY_CHARS)?;
+        opt("project", &self.project, MAX_NAME_CHARS)?;
+        memory_scope(&self.scope)
+    }
+}
+
+impl Validate for StartInvestigationParams {
+    fn validate(&self) -> Result<(), ErrorData> {
+        text("question", &self.question, MAX_TEXT_CHARS)?;
+        opt("project", &self.project, MAX_NAME_CHARS)
+    }
+}
+
+impl Validate for ConcludeInvestigationParams {
+    fn validate(&self) -> Result<(), ErrorData> {
+        text("conclusion", &self.conclusion, MAX_TEXT_CHARS)?;
+        opt("project", &self.project, MAX_NAME_CHARS)
+    }
+}
+
+impl Validate for BundleParams {
+    fn validate(&self) -> Result<(), ErrorData> {
+        let n = self.requests.len();
+        if n == 0 {
+            return Err(invalid("requests", "too_few", "empty, at least 1 request".into(), json!(1)));
+        }
+        if n > MAX_BUNDLE_REQUESTS {
+            return Err(invalid(
+                "requests",
+                "too_many",
+                format!("{n} requests, max {MAX_BUNDLE_REQUESTS}"),
+                json!(MAX_BUNDLE_REQUESTS),
+            ));
+        }
+        for req in &self.requests {
+            opt("requests[].key", &req.key, MAX_NAME_CHARS)?;
+            text("requests[].tool", &req.tool, MAX_NAME_CHARS)?;
+        }
+        Ok(())
+    }
+}
+
+impl Validate for CurrentStanceParams {
+    fn validate(&self) -> Result<(), ErrorData> {
+        opt("session_id", &self.session_id, MAX_NAME_CHARS)
+    }
+}
+
+impl Validate for WorkingSetParams {
+    fn validate(&self) -> Result<(), ErrorData> {
+        opt("session_id", &self.session_id, MAX_NAME_CHARS)
+    }
+}
+
+impl Validate for SimilarObservationsParams {
+    fn validate(&self) -> Result<(), ErrorData> {
+        match self.min_similarity {
+            Some(s) if !(0.0..=1.0).contains(&s) => Err(invalid(
+                "min_similarity",
+                "out_of_range",
+                format!("{s} is not between 0 and 1"),
+                json!([0, 1]),
+            )),
+            _ => Ok(()),
+        }
+    }
+}
+
+impl Validate for FlakyTestsParams {
+    fn validate(&self) -> Result<(), ErrorData> {
+        opt("project", &self.project, MAX_NAME_CHARS)
+    }
+}
+
+impl Validate for TestHistoryParams {
+    fn validate(&self) -> Result<(), ErrorData> {
+        text("name", &self.name, crate::s2_test_failure::MAX_NAME_CHARS)?;
+        opt("project", &self.project, MAX_NAME_CHARS)
+    }
+}
+
+impl Validate for WindowSummaryParams {
+    fn validate(&self) -> Result<(), ErrorData> {
+        text("window", &self.window, MAX_NAME_CHARS)?;
+        opt("project", &self.project, MAX_NAME_CHARS)
+    }
+}
+
+impl Validate for GrepParams {
+    fn validate(&self) -> Result<(), ErrorData> {
+        text("pattern", &self.pattern, crate::s1_grep::MAX_PATTERN_CHARS)?;
+        opt("project", &self.project, MAX_NAME_CHARS)?;
+        opt("obs_type", &self.obs_type, MAX_NAME_CHARS)?;
+        if let Some(ref fts) = self.fts {
+            query("fts", fts)?;
+        }
+        opt("window", &self.window, MAX_NAME_CHARS)?;
+        range(self.after, self.before)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn search(query: &str) -> SearchParams {
+        serde_json::from_value(json!({"query": query})).unwrap()
+    }
+
+    #[test]
+    fn rejects_long_or_wide_queries_with_structured_data() {
+        assert!(search("token refresh").validate().is_ok());
+        let err = search(&"x".repeat(MAX_QUERY_CHARS + 1)).validate().unwrap_err();
+        assert_eq!(err.code, ErrorCode::INVALID_PARAMS);
+        assert_eq!(err.data.unwrap(), json!({"param": "query", "reason": "too_long", "limit": MAX_QUERY_CHARS}));
+        let err = search(&"a ".repeat(MAX_QUERY_TERMS + 1)).validate().unwrap_err();
+        assert_eq!(err.data.unwrap()["reason"], "too_many");
+    }
+
+    #[test]
+    fn time_ranges_must_be_real_and_non_empty() {
+        let with = |after: i64, before: i64| {
+            let p: SearchParams = serde_json::from_value(json!({"query": "x", "after": after, "before": before})).unwrap();
+            p.validate().map_err(|e| e.data.unwrap()["reason"].clone())
+        };
+        assert!(with(1_700_000_000, 1_700_000_100).is_ok());
+        assert!(with(1_700_000_000, 1_700_000_000 + MAX_RANGE_SECS).is_ok());
+        assert_eq!(with(1_700_000_100, 1_700_000_000), Err(json!("empty_range")));
+        assert_eq!(with(1_700_000_000, 1_700_000_001 + MAX_RANGE_SECS), Err(json!("too_wide")));
+        assert_eq!(with(-1, 10), Err(json!("out_of_range")));
+        assert_eq!(with(0, i64::MAX), Err(json!("out_of_range")));
+    }
+
+    #[test]
+    fn timeline_anchor_must_be_an_id() {
+        let anchor = |id: i64| serde_json::from_value::<TimelineParams>(json!({"anchor": id})).unwrap().validate();
+        assert!(anchor(1).is_ok());
+        assert_eq!(anchor(0).unwrap_err().data.unwrap()["reason"], "out_of_range");
+    }
+}
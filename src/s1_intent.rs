@@ -0,0 +1,181 @@
+//! S1 Operations — tool call intents.
+//!
+//! PostToolUse only fires for calls that ran, so a call the user denied or a
+//! hook blocked leaves no trace. PreToolUse records a lightweight row in
+//! `tool_intents` — tool, file, redacted input — before the call runs. The
+//! matching PostToolUse links it to the observation it produced (by Claude
+//! Code's `tool_use_id`, else the oldest pending call of the same tool in the
+//! session) and marks it `matched`. Whatever is still pending when the Stop
+//! hook fires is marked `unmatched`: denied, blocked, or interrupted calls.
+//! `session_trace` lists them.
+
+use crate::NmemError;
+use rusqlite::{Connection, OptionalExtension, params};
+use serde::Serialize;
+
+/// Awaiting its PostToolUse.
+pub const PENDING: &str = "pending";
+/// Linked to the observation the call produced.
+pub const MATCHED: &str = "matched";
+/// The agent stopped without the call completing.
+pub const UNMATCHED: &str = "unmatched";
+
+/// Chars of tool input kept per intent.
+const INTENT_CHARS: usize = 2000;
+
+#[derive(Debug, Serialize, PartialEq)]
+pub struct ToolIntent {
+    pub id: i64,
+    pub timestamp: i64,
+    pub tool_name: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub file_path: Option<String>,
+    pub content: String,
+    pub status: String,
+}
+
+/// Record that `tool_name` is about to run. `content` must already be
+/// redacted.
+#[allow(clippy::too_many_arguments)]
+pub fn record(
+    conn: &Connection,
+    session_id: &str,
+    prompt_id: Option<i64>,
+    tool_use_id: Option<&str>,
+    tool_name: &str,
+    file_path: Option<&str>,
+    content: &str,
+    ts: i64,
+) -> Result<i64, NmemError> {
+    let content: String = content.chars().take(INTENT_CHARS).collect();
+    conn.execute(
+        "INSERT INTO tool_intents (session_id, prompt_id, tool_use_id, timestamp, tool_name, file_path, content, status)
+         VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8)",
+        params![session_id, prompt_id, tool_use_id, ts, tool_name, file_path, content, PENDING],
+    )?;
+    Ok(conn.last_insert_rowid())
+}
+
+/// Link the pending intent behind observation `obs_id` to it. Returns the
+/// intent's id, or `None` when no PreToolUse was recorded for the call.
+pub fn resolve(
+    conn: &Connection,
+    session_id: &str,
+    tool_use_id: Option<&str>,
+    tool_name: &str,
+    obs_id: i64,
+    ts: i64,
+) -> Result<Option<i64>, NmemError> {
+    let by_id = match tool_use_id {
+        Some(id) => conn
+            .query_row(
+                "SELECT id FROM tool_intents WHERE session_id = ?1 AND tool_use_id = ?2 AND status = ?3",
+                params![session_id, id, PENDING],
+                |r| r.get(0),
+            )
+            .optional()?,
+        None => None,
+    };
+    let intent: Option<i64> = match by_id {
+        Some(id) => Some(id),
+        None => conn
+            .query_row(
+                "SELECT id FROM tool_intents
+                 WHERE session_id = ?1 AND tool_name = ?2 AND status = ?3
+                   AND (tool_use_id IS NULL OR ?4 IS NULL)
+                 ORDER BY id LIMIT 1",
+                params![session_id, tool_name, PENDING, tool_use_id],
+                |r| r.get(0),
+            )
+            .optional()?,
+    };
+    if let Some(id) = intent {
+        conn.execute(
+            "UPDATE tool_intents SET status = ?1, obs_id = ?2, resolved_at = ?3 WHERE id = ?4",
+            params![MATCHED, obs_id, ts, id],
+        )?;
+    }
+    Ok(intent)
+}
+
+/// Mark every intent of `session_id` still pending as unmatched. Returns how
+/// many were.
+pub fn close_session(conn: &Connection, session_id: &str, ts: i64) -> Result<usize, NmemError> {
+    Ok(conn.execute(
+        "UPDATE tool_intents SET status = ?1, resolved_at = ?2 WHERE session_id = ?3 AND status = ?4",
+        params![UNMATCHED, ts, session_id, PENDING],
+    )?)
+}
+
+/// Intents of `session_id` without an observation — unmatched, or still
+/// pending in a live session — oldest first, within the optional bounds.
+pub fn unresolved(
+    conn: &Connection,
+    session_id: &str,
+    before: Option<i64>,
+    after: Option<i64>,
+) -> Result<Vec<ToolIntent>, NmemError> {
+    let mut stmt = conn.prepare(
+        "SELECT id, timestamp, tool_name, file_path, content, status FROM tool_intents
+         WHERE session_id = ?1 AND status != ?2
+           AND (?3 IS NULL OR timestamp < ?3)
+           AND (?4 IS NULL OR timestamp > ?4)
+         ORDER BY id",
+    )?;
+    let rows = stmt
+        .query_map(params![session_id, MATCHED, before, after], |r| {
+            Ok(ToolIntent {
+                id: r.get(0)?,
+                timestamp: r.get(1)?,
+                tool_name: r.get(2)?,
+                file_path: r.get(3)?,
+                content: r.get(4)?,
+                status: r.get(5)?,
+            })
+        })?
+        .collect::<Result<_, _>>()?;
+    Ok(rows)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn intents_pair_with_observations_or_stay_unmatched() {
+        let mut conn = Connection::open_in_memory().unwrap();
+        conn.pragma_update(None, "foreign_keys", "ON").unwrap();
+        crate::schema::MIGRATIONS.to_latest(&mut conn).unwrap();
+        conn.execute("INSERT INTO sessions (id, project, started_at) VALUES ('s', 'p', 1)", []).unwrap();
+        for id in [1, 2] {
+            conn.execute(
+                "INSERT INTO observations (id, session_id, timestamp, obs_type, source_event, content)
+                 VALUES (?1, 's', 2, 'command', 'PostToolUse', 'ls')",
+                [id],
+            )
+            .unwrap();
+        }
+
+        let a = record(&conn, "s", None, Some("toolu_a"), "Bash", None, "ls", 1).unwrap();
+        let b = record(&conn, "s", None, Some("toolu_b"), "Bash", None, "rm -rf build", 1).unwrap();
+        let c = record(&conn, "s", None, None, "Read", Some("/x.rs"), "/x.rs", 1).unwrap();
+
+        // By tool_use_id, regardless of order
+        assert_eq!(resolve(&conn, "s", Some("toolu_a"), "Bash", 1, 2).unwrap(), Some(a));
+        // Without an id, the oldest pending call of the tool
+        assert_eq!(resolve(&conn, "s", None, "Read", 2, 2).unwrap(), Some(c));
+        // Nothing pending for an unknown id with only id-bearing intents left
+        assert_eq!(resolve(&conn, "s", Some("toolu_z"), "Bash", 2, 2).unwrap(), None);
+
+        assert_eq!(close_session(&conn, "s", 3).unwrap(), 1);
+        let left = unresolved(&conn, "s", None, None).unwrap();
+        assert_eq!(left.len(), 1);
+        assert_eq!((left[0].id, left[0].status.as_str()), (b, UNMATCHED));
+
+        // Deleting the observation keeps the intent, unlinked
+        conn.execute("DELETE FROM observations WHERE id = 1", []).unwrap();
+        let linked: Option<i64> =
+            conn.query_row("SELECT obs_id FROM tool_intents WHERE id = ?1", [a], |r| r.get(0)).unwrap();
+        assert_eq!(linked, None);
+    }
+}
@@ -0,0 +1,289 @@
+//! S1 Operations — investigations.
+//!
+//! An investigation brackets a line of inquiry: `start` records the question
+//! and an observation watermark, `conclude` attaches the answer along with the
+//! files the session touched in between. Concluded investigations resurface
+//! in context injection when later work touches the same files or terms.
+
+#[cfg(feature = "cli")]
+use crate::cli::{InvestigateArgs, InvestigateCommand};
+use crate::db::open_db;
+use crate::s5_config::{load_config, resolve_filter_params};
+use crate::s5_filter::SecretFilter;
+use crate::s5_project::derive_project_with_strategy;
+use crate::NmemError;
+use rusqlite::{Connection, OptionalExtension, params};
+use serde::Serialize;
+use std::path::Path;
+
+/// Files kept on a concluded investigation, busiest first.
+const MAX_FILES: i64 = 10;
+
+#[derive(Debug, Serialize)]
+pub struct Concluded {
+    pub id: i64,
+    pub question: String,
+    pub conclusion: String,
+    pub obs_count: i64,
+    pub files: Vec<String>,
+}
+
+/// A concluded investigation as read back for context injection.
+#[derive(Debug)]
+pub(crate) struct Investigation {
+    pub question: String,
+    pub conclusion: String,
+    pub files: Vec<String>,
+    pub concluded_at: i64,
+}
+
+/// Open an investigation in the project's newest agent session. Only one
+/// investigation per session can be open at a time.
+pub fn start(conn: &Connection, project: &str, question: &str, now: i64) -> Result<i64, NmemError> {
+    let tx = conn.unchecked_transaction()?;
+    let session_id = current_session(&tx, project, now)?;
+
+    let open: Option<i64> = tx
+        .query_row(
+            "SELECT id FROM investigations WHERE session_id = ?1 AND concluded_at IS NULL",
+            params![session_id],
+            |r| r.get(0),
+        )
+        .optional()?;
+    if let Some(id) = open {
+        return Err(NmemError::Config(format!(
+            "investigation {id} is still open; conclude it first"
+        )));
+    }
+
+    tx.execute(
+        "INSERT INTO investigations (session_id, question, started_at, start_obs_id)
+         VALUES (?1, ?2, ?3, (SELECT COALESCE(MAX(id), 0) FROM observations))",
+        params![session_id, question, now],
+    )?;
+    let id = tx.last_insert_rowid();
+    tx.commit()?;
+    Ok(id)
+}
+
+/// Close investigation `id` (default: the project's newest open one) with
+/// `conclusion`, capturing the observations recorded since it started.
+pub fn conclude(
+    conn: &Connection,
+    project: &str,
+    id: Option<i64>,
+    conclusion: &str,
+    now: i64,
+) -> Result<Concluded, NmemError> {
+    let tx = conn.unchecked_transaction()?;
+    let found: Option<(i64, String, String, i64)> = tx
+        .query_row(
+            "SELECT i.id, i.session_id, i.question, i.start_obs_id
+             FROM investigations i JOIN sessions s ON s.id = i.session_id
+             WHERE i.concluded_at IS NULL
+               AND (?1 IS NULL OR i.id = ?1)
+               AND (?1 IS NOT NULL OR s.project = ?2)
+             ORDER BY i.started_at DESC, i.id DESC LIMIT 1",
+            params![id, project],
+            |r| Ok((r.get(0)?, r.get(1)?, r.get(2)?, r.get(3)?)),
+        )
+        .optional()?;
+    let Some((id, session_id, question, start_obs_id)) = found else {
+        return Err(NmemError::Config(match id {
+            Some(id) => format!("no open investigation {id}"),
+            None => format!("no open investigation for project {project}"),
+        }));
+    };
+
+    let end_obs_id: i64 =
+        tx.query_row("SELECT COALESCE(MAX(id), 0) FROM observations", [], |r| r.get(0))?;
+    let obs_count: i64 = tx.query_row(
+        "SELECT COUNT(*) FROM observations WHERE session_id = ?1 AND id > ?2 AND id <= ?3",
+        params![session_id, start_obs_id, end_obs_id],
+        |r| r.get(0),
+    )?;
+    let files: Vec<String> = tx
+        .prepare(
+            "SELECT file_path FROM observations
+             WHERE session_id = ?1 AND id > ?2 AND id <= ?3 AND file_path IS NOT NULL
+             GROUP BY file_path ORDER BY COUNT(*) DESC, MAX(id) DESC LIMIT ?4",
+        )?
+        .query_map(params![session_id, start_obs_id, end_obs_id, MAX_FILES], |r| r.get(0))?
+        .collect::<Result<_, _>>()?;
+
+    tx.execute(
+        "UPDATE investigations SET concluded_at = ?2, end_obs_id = ?3, conclusion = ?4,
+                obs_count = ?5, files = ?6
+         WHERE id = ?1",
+        params![id, now, end_obs_id, conclusion, obs_count, serde_json::to_string(&files)?],
+    )?;
+    tx.commit()?;
+
+    Ok(Concluded {
+        id,
+        question,
+        conclusion: conclusion.to_string(),
+        obs_count,
+        files,
+    })
+}
+
+/// Concluded investigations for `project`, newest first.
+pub(crate) fn concluded(
+    conn: &Connection,
+    project: &str,
+    before: Option<i64>,
+    limit: i64,
+) -> Result<Vec<Investigation>, NmemError> {
+    let mut stmt = conn.prepare(
+        "SELECT i.question, i.conclusion, i.files, i.concluded_at
+         FROM investigations i JOIN sessions s ON s.id = i.session_id
+         WHERE s.project = ?1 AND i.concluded_at IS NOT NULL
+           AND (?2 IS NULL OR i.concluded_at < ?2)
+         ORDER BY i.concluded_at DESC LIMIT ?3",
+    )?;
+    let rows = stmt
+        .query_map(params![project, before, limit], |r| {
+            let files: Option<String> = r.get(2)?;
+            Ok(Investigation {
+                question: r.get(0)?,
+                conclusion: r.get::<_, Option<String>>(1)?.unwrap_or_default(),
+                files: files
+                    .and_then(|f| serde_json::from_str(&f).ok())
+                    .unwrap_or_default(),
+                concluded_at: r.get(3)?,
+            })
+        })?
+        .collect::<Result<_, _>>()?;
+    Ok(rows)
+}
+
+/// Newest agent session for the project; a placeholder session is created
+/// when there is none, as `nmem mark` does.
+fn current_session(conn: &Connection, project: &str, now: i64) -> Result<String, NmemError> {
+    let existing: Option<String> = conn
+        .query_row(
+            "SELECT id FROM sessions WHERE project = ?1 AND kind = 'agent'
+             ORDER BY started_at DESC LIMIT 1",
+            params![project],
+            |r| r.get(0),
+        )
+        .optional()?;
+    if let Some(id) = existing {
+        return Ok(id);
+    }
+    let id = format!("investigation-{now}");
+    conn.execute(
+        "INSERT INTO sessions (id, project, started_at) VALUES (?1, ?2, ?3)",
+        params![id, project, now],
+    )?;
+    Ok(id)
+}
+
+#[cfg(feature = "cli")]
+pub fn handle_investigate(db_path: &Path, args: &InvestigateArgs) -> Result<(), NmemError> {
+    let conn = open_db(db_path)?;
+    let config = load_config().unwrap_or_default();
+    let now = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs() as i64;
+
+    let project_arg = match &args.command {
+        InvestigateCommand::Start(a) => &a.project,
+        InvestigateCommand::Conclude(a) => &a.project,
+    };
+    let project = project_arg.clone().unwrap_or_else(|| {
+        let cwd = std::env::current_dir()
+            .map(|p| p.to_string_lossy().to_string())
+            .unwrap_or_default();
+        derive_project_with_strategy(&cwd, config.project.strategy)
+    });
+    let filter = SecretFilter::with_params(resolve_filter_params(&config, Some(&project)));
+
+    match &args.command {
+        InvestigateCommand::Start(a) => {
+            let (question, _) = filter.redact(&a.question);
+            let id = start(&conn, &project, &question, now)?;
+            println!("{id}");
+        }
+        InvestigateCommand::Conclude(a) => {
+            let (conclusion, _) = filter.redact(&a.conclusion);
+            let done = conclude(&conn, &project, a.id, &conclusion, now)?;
+            println!("{}", serde_json::to_string(&done)?);
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::schema::MIGRATIONS;
+
+    fn setup_db() -> Connection {
+        let mut conn = Connection::open_in_memory().unwrap();
+        conn.pragma_update(None, "foreign_keys", "ON").unwrap();
+        MIGRATIONS.to_latest(&mut conn).unwrap();
+        conn.execute_batch(
+            "INSERT INTO sessions (id, project, started_at) VALUES ('s1', 'proj', 100);
+             INSERT INTO observations (session_id, timestamp, obs_type, source_event, file_path, content)
+                 VALUES ('s1', 110, 'file_read', 'PostToolUse', 'src/before.rs', 'read');",
+        )
+        .unwrap();
+        conn
+    }
+
+    fn add_obs(conn: &Connection, session: &str, file: &str) {
+        conn.execute(
+            "INSERT INTO observations (session_id, timestamp, obs_type, source_event, file_path, content)
+             VALUES (?1, 200, 'file_read', 'PostToolUse', ?2, 'read')",
+            params![session, file],
+        )
+        .unwrap();
+    }
+
+    #[test]
+    fn conclude_brackets_observations_since_start() {
+        let conn = setup_db();
+        let id = start(&conn, "proj", "why is auth slow?", 150).unwrap();
+        add_obs(&conn, "s1", "src/auth.rs");
+        add_obs(&conn, "s1", "src/auth.rs");
+        add_obs(&conn, "s1", "src/cache.rs");
+
+        let done = conclude(&conn, "proj", None, "token cache was disabled", 300).unwrap();
+        assert_eq!(done.id, id);
+        assert_eq!(done.question, "why is auth slow?");
+        assert_eq!(done.obs_count, 3);
+        assert_eq!(done.files, vec!["src/auth.rs", "src/cache.rs"]);
+
+        let listed = concluded(&conn, "proj", None, 5).unwrap();
+        assert_eq!(listed.len(), 1);
+        assert_eq!(listed[0].conclusion, "token cache was disabled");
+        assert_eq!(listed[0].files, done.files);
+        assert!(concluded(&conn, "proj", Some(300), 5).unwrap().is_empty());
+    }
+
+    #[test]
+    fn one_open_investigation_per_session() {
+        let conn = setup_db();
+        let id = start(&conn, "proj", "first", 150).unwrap();
+        let err = start(&conn, "proj", "second", 160).unwrap_err();
+        assert!(err.to_string().contains(&format!("investigation {id} is still open")));
+
+        conclude(&conn, "proj", Some(id), "done", 170).unwrap();
+        assert!(conclude(&conn, "proj", Some(id), "again", 180).is_err());
+        assert!(conclude(&conn, "proj", None, "nothing open", 180).is_err());
+        start(&conn, "proj", "second", 190).unwrap();
+    }
+
+    #[test]
+    fn start_without_session_creates_placeholder() {
+        let conn = setup_db();
+        start(&conn, "other", "where is config loaded?", 500).unwrap();
+        let project: String = conn
+            .query_row("SELECT project FROM sessions WHERE id = 'investigation-500'", [], |r| r.get(0))
+            .unwrap();
+        assert_eq!(project, "other");
+    }
+}
@@ -0,0 +1,229 @@
+//! S1 Operations — language-aware keywords for prompts and intents.
+//!
+//! Episode boundaries, intent clustering, and the similar-episode note all
+//! compare keyword bags. Splitting on spaces and dropping English function
+//! words works for English only: German prompts keep `und`/`nicht`/`eine`
+//! and cluster on them, and Japanese or Chinese prompts — no spaces — come
+//! out as one giant keyword that never matches anything.
+//!
+//! `detect` guesses a prompt's language from its script (kana → `ja`, Han
+//! alone → `zh`, Hangul → `ko`, Cyrillic → `ru`) or, for Latin text, from
+//! which stopword list it hits most (`en` when none stands out). `keywords`
+//! tokenizes on Unicode word boundaries, cuts Han runs into overlapping
+//! bigrams, keeps katakana runs whole (mostly loanwords), drops hiragana
+//! runs (particles and inflections), and removes that language's stopwords
+//! plus English ones — prompts about code mix in English terms.
+//!
+//! `[language] stopwords` adds words per language code, extending the
+//! built-in lists or defining new languages for detection.
+
+use std::collections::{HashMap, HashSet};
+use std::sync::LazyLock;
+
+const EN: &[&str] = &[
+    "a", "an", "the", "and", "or", "to", "of", "in", "for", "with", "on", "at", "by", "from",
+    "is", "it", "this", "that", "be", "as", "are", "was", "were", "been", "do", "does", "did",
+    "will", "would", "could", "should", "may", "might", "can", "has", "have", "had", "not", "no",
+    "up", "out", "about", "into", "over", "after", "before",
+];
+
+const DE: &[&str] = &[
+    "der", "die", "das", "den", "dem", "des", "ein", "eine", "einen", "einem", "einer", "und",
+    "oder", "aber", "ist", "sind", "war", "wird", "werden", "nicht", "kein", "keine", "mit",
+    "von", "zu", "zum", "zur", "auf", "für", "bei", "nach", "aus", "auch", "noch", "dass", "wie",
+    "ich", "du", "wir", "sie", "es", "bitte", "mal", "doch", "jetzt", "hier", "dann", "wenn",
+    "kann", "soll", "muss", "diese", "dieser", "dieses", "sich", "haben", "hat",
+];
+
+const FR: &[&str] = &[
+    "le", "la", "les", "un", "une", "des", "du", "de", "et", "ou", "mais", "est", "sont", "pas",
+    "ne", "que", "qui", "dans", "pour", "avec", "sur", "par", "au", "aux", "ce", "cette", "ces",
+    "je", "tu", "nous", "vous", "il", "elle", "ils", "elles", "être", "avoir", "fait", "faire",
+    "plus", "aussi", "comme", "peux", "peut", "merci",
+];
+
+const ES: &[&str] = &[
+    "el", "la", "los", "las", "un", "una", "unos", "unas", "y", "o", "pero", "es", "son", "está",
+    "no", "que", "de", "del", "en", "para", "con", "por", "al", "lo", "se", "su", "sus", "este",
+    "esta", "estos", "como", "más", "también", "yo", "tú", "nosotros", "hay", "hacer", "puedes",
+    "favor", "gracias",
+];
+
+/// Han and katakana tokens too generic to tell intents apart.
+const JA: &[&str] = &["場合", "必要", "以下", "以上", "今回", "部分", "全部", "確認", "対応"];
+
+const ZH: &[&str] = &[
+    "我们", "这个", "那个", "一个", "什么", "可以", "没有", "因为", "所以", "如果", "但是", "现在",
+    "需要", "一下", "的话", "还是", "就是",
+];
+
+/// Built-in stopwords per language code.
+const BUILTIN: &[(&str, &[&str])] = &[("en", EN), ("de", DE), ("fr", FR), ("es", ES), ("ja", JA), ("zh", ZH)];
+
+/// Built-ins plus `[language] stopwords`, resolved once per process.
+static STOPWORDS: LazyLock<HashMap<String, HashSet<String>>> = LazyLock::new(|| {
+    let configured = crate::s5_config::load_config().map(|c| c.language.stopwords).unwrap_or_default();
+    stopword_lists(&configured)
+});
+
+fn stopword_lists(configured: &std::collections::BTreeMap<String, Vec<String>>) -> HashMap<String, HashSet<String>> {
+    let mut lists: HashMap<String, HashSet<String>> = BUILTIN
+        .iter()
+        .map(|(lang, words)| (lang.to_string(), words.iter().map(|w| w.to_string()).collect()))
+        .collect();
+    for (lang, words) in configured {
+        lists
+            .entry(lang.to_lowercase())
+            .or_default()
+            .extend(words.iter().map(|w| w.to_lowercase()));
+    }
+    lists
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum Script {
+    Han,
+    Hiragana,
+    Katakana,
+    Other,
+}
+
+fn script(c: char) -> Script {
+    match c {
+        '\u{3040}'..='\u{309F}' => Script::Hiragana,
+        '\u{30A0}'..='\u{30FF}' | '\u{31F0}'..='\u{31FF}' | '\u{FF66}'..='\u{FF9F}' => Script::Katakana,
+        '\u{3400}'..='\u{4DBF}' | '\u{4E00}'..='\u{9FFF}' | '\u{F900}'..='\u{FAFF}' => Script::Han,
+        _ => Script::Other,
+    }
+}
+
+/// Language code for `text`: by script where one decides it, otherwise the
+/// stopword list with the most hits, `en` on no hits or a tie with English.
+pub fn detect(text: &str) -> String {
+    detect_with(text, &STOPWORDS)
+}
+
+fn detect_with(text: &str, lists: &HashMap<String, HashSet<String>>) -> String {
+    let (mut kana, mut han, mut hangul, mut cyrillic) = (0, 0, 0, 0);
+    for c in text.chars() {
+        match (script(c), c) {
+            (Script::Hiragana | Script::Katakana, _) => kana += 1,
+            (Script::Han, _) => han += 1,
+            (_, '\u{AC00}'..='\u{D7AF}' | '\u{1100}'..='\u{11FF}') => hangul += 1,
+            (_, '\u{0400}'..='\u{04FF}') => cyrillic += 1,
+            _ => {}
+        }
+    }
+    if kana > 0 {
+        return "ja".into();
+    }
+    if let Some((_, lang)) = [(han, "zh"), (hangul, "ko"), (cyrillic, "ru")].into_iter().filter(|(n, _)| *n > 0).max() {
+        return lang.into();
+    }
+
+    let lower = text.to_lowercase();
+    let words: Vec<&str> = lower.split(|c: char| !c.is_alphanumeric()).filter(|w| !w.is_empty()).collect();
+    let hits = |lang: &str| lists.get(lang).map_or(0, |list| words.iter().filter(|w| list.contains(**w)).count());
+    let english = hits("en");
+    let mut best: Option<(usize, &str)> = None;
+    for lang in lists.keys().filter(|l| *l != "en") {
+        let n = hits(lang);
+        // Ties go to the lexically first code so detection is deterministic
+        if n > english && best.is_none_or(|(b, l)| n > b || (n == b && lang.as_str() < l)) {
+            best = Some((n, lang));
+        }
+    }
+    best.map_or("en", |(_, lang)| lang).into()
+}
+
+/// Unicode word tokens, lowercased: Latin-style words as they are, Han runs
+/// as overlapping bigrams (a lone character stays whole), katakana runs
+/// whole, hiragana dropped.
+pub fn tokens(text: &str) -> Vec<String> {
+    let lower = text.to_lowercase();
+    let mut out = Vec::new();
+    for word in lower.split(|c: char| !c.is_alphanumeric() && c != '_').filter(|w| !w.is_empty()) {
+        let chars: Vec<char> = word.chars().collect();
+        let mut start = 0;
+        while start < chars.len() {
+            let kind = script(chars[start]);
+            let end = (start..chars.len()).find(|&i| script(chars[i]) != kind).unwrap_or(chars.len());
+            let run = &chars[start..end];
+            match kind {
+                Script::Han if run.len() > 1 => out.extend(run.windows(2).map(|pair| pair.iter().collect::<String>())),
+                Script::Hiragana => {}
+                _ => out.push(run.iter().collect()),
+            }
+            start = end;
+        }
+    }
+    out
+}
+
+/// Rough word count: whitespace-separated words, with every two Han or kana
+/// characters counting as one — CJK prompts have no spaces to count.
+pub fn word_count(text: &str) -> usize {
+    text.split_whitespace()
+        .map(|word| {
+            let cjk = word.chars().filter(|c| script(*c) != Script::Other).count();
+            if cjk == 0 { 1 } else { (cjk / 2).max(1) + usize::from(cjk < word.chars().count()) }
+        })
+        .sum()
+}
+
+/// Keywords of `text` in `lang`: its tokens minus that language's and
+/// English stopwords. Latin-style words need three characters; Han bigrams
+/// and katakana words are kept at any length.
+pub fn keywords(text: &str, lang: &str) -> Vec<String> {
+    keywords_with(text, lang, &STOPWORDS)
+}
+
+fn keywords_with(text: &str, lang: &str, lists: &HashMap<String, HashSet<String>>) -> Vec<String> {
+    let stop = |w: &str| ["en", lang].iter().any(|l| lists.get(*l).is_some_and(|list| list.contains(w)));
+    tokens(text)
+        .into_iter()
+        .filter(|w| {
+            let cjk = w.chars().next().is_some_and(|c| script(c) != Script::Other);
+            (cjk || w.chars().count() > 2) && !stop(w)
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::BTreeMap;
+
+    #[test]
+    fn detects_language_by_script_and_stopwords() {
+        let lists = stopword_lists(&BTreeMap::new());
+        assert_eq!(detect_with("fix the login bug in the auth module", &lists), "en");
+        assert_eq!(detect_with("Bitte den Login-Fehler im Auth-Modul beheben, das ist nicht gut", &lists), "de");
+        assert_eq!(detect_with("ログインのバグを修正して", &lists), "ja");
+        assert_eq!(detect_with("修复登录模块的错误", &lists), "zh");
+        assert_eq!(detect_with("cargo build", &lists), "en");
+
+        let custom = stopword_lists(&BTreeMap::from([("nl".to_string(), vec!["het".into(), "een".into(), "niet".into()])]));
+        assert_eq!(detect_with("het werkt niet, een fout in de build", &custom), "nl");
+    }
+
+    #[test]
+    fn keywords_drop_stopwords_and_bigram_han() {
+        let lists = stopword_lists(&BTreeMap::new());
+        assert_eq!(
+            keywords_with("Bitte den Login-Fehler im Auth-Modul beheben", "de", &lists),
+            ["login", "fehler", "auth", "modul", "beheben"]
+        );
+        assert_eq!(keywords_with("ログインのバグを修正して", "ja", &lists), ["ログイン", "バグ", "修正"]);
+        assert_eq!(keywords_with("修复登录模块", "zh", &lists), ["修复", "复登", "登录", "录模", "模块"]);
+        // English stopwords go regardless of the prompt's language
+        assert_eq!(keywords_with("die the Datenbank", "de", &lists), ["datenbank"]);
+    }
+
+    #[test]
+    fn word_count_counts_cjk_characters() {
+        assert_eq!(word_count("fix the login bug"), 4);
+        assert_eq!(word_count("ログインのバグを修正してください"), 8);
+        assert_eq!(word_count("cargo のテスト"), 3);
+    }
+}
@@ -5,11 +5,11 @@ use crate::s2_classify;
 use crate::s2_locus;
 use crate::s2_novelty;
 use crate::s2_scope;
-use crate::s5_config::{load_config, resolve_filter_params};
+use crate::s5_config::{load_config, resolve_filter_params, NmemConfig};
 use crate::s5_filter::SecretFilter;
 use crate::s5_project::derive_project_with_strategy;
 use crate::NmemError;
-use rusqlite::params;
+use rusqlite::{Connection, OptionalExtension, params};
 use std::path::Path;
 use std::time::{SystemTime, UNIX_EPOCH};
 
@@ -29,10 +29,59 @@ pub fn handle_mark(db_path: &Path, args: &MarkArgs) -> Result<(), NmemError> {
         derive_project_with_strategy(&cwd, config.project.strategy)
     });
 
+    let reply = Reply { to: args.reply_to, status: args.status.as_deref() };
+    let obs_id = create(&conn, &config, &project, &args.text, reply, ts)?;
+    println!("{obs_id}");
+    Ok(())
+}
+
+/// Where a marker sits in a thread. Replies carry `reply_to` and the
+/// thread's root marker as `thread` in their metadata; `status` (`decided`,
+/// `superseded`, ...) is the thread's state as of this marker.
+#[derive(Default, Clone, Copy)]
+pub struct Reply<'a> {
+    pub to: Option<i64>,
+    pub status: Option<&'a str>,
+}
+
+/// Metadata of a marker replying to `reply.to`, which must be a marker.
+fn thread_metadata(conn: &Connection, reply: Reply) -> Result<Option<String>, NmemError> {
+    let mut meta = serde_json::Map::new();
+    if let Some(parent) = reply.to {
+        let root: Option<i64> = conn
+            .query_row(
+                "SELECT COALESCE(json_extract(metadata, '$.thread'), id) FROM observations
+                 WHERE id = ?1 AND obs_type = 'marker'",
+                params![parent],
+                |r| r.get(0),
+            )
+            .optional()?;
+        let root = root.ok_or_else(|| NmemError::Config(format!("#{parent} is not a marker")))?;
+        meta.insert("reply_to".into(), parent.into());
+        meta.insert("thread".into(), root.into());
+    }
+    if let Some(status) = reply.status.map(str::trim).filter(|s| !s.is_empty()) {
+        meta.insert("status".into(), status.into());
+    }
+    Ok((!meta.is_empty()).then(|| serde_json::Value::Object(meta).to_string()))
+}
+
+/// Record `text` (redacted, classified) as a marker in the project's newest
+/// agent session, creating a placeholder session when there is none.
+pub fn create(
+    conn: &Connection,
+    config: &NmemConfig,
+    project: &str,
+    text: &str,
+    reply: Reply,
+    ts: i64,
+) -> Result<i64, NmemError> {
+    let metadata = thread_metadata(conn, reply)?;
+
     // Filter secrets
-    let filter_params = resolve_filter_params(&config, Some(&project));
+    let filter_params = resolve_filter_params(config, Some(project));
     let filter = SecretFilter::with_params(filter_params);
-    let (filtered_text, redacted) = filter.redact(&args.text);
+    let (filtered_text, redacted) = filter.redact(text);
 
     if redacted {
         log::warn!("redacted potential secret from marker");
@@ -41,7 +90,7 @@ pub fn handle_mark(db_path: &Path, args: &MarkArgs) -> Result<(), NmemError> {
     // Find most recent session for this project, or create one
     let session_id: String = conn
         .query_row(
-            "SELECT s.id FROM sessions s WHERE s.project = ?1 ORDER BY s.started_at DESC LIMIT 1",
+            "SELECT s.id FROM sessions s WHERE s.project = ?1 AND s.kind = 'agent' ORDER BY s.started_at DESC LIMIT 1",
             params![project],
             |r| r.get(0),
         )
@@ -105,8 +154,8 @@ pub fn handle_mark(db_path: &Path, args: &MarkArgs) -> Result<(), NmemError> {
 
     // Insert observation
     tx.execute(
-        "INSERT INTO observations (session_id, prompt_id, timestamp, obs_type, source_event, content, phase, classifier_run_id, scope, scope_run_id, locus, locus_run_id, novelty, novelty_run_id, friction, friction_run_id)
-         VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12, ?13, ?14, ?15, ?16)",
+        "INSERT INTO observations (session_id, prompt_id, timestamp, obs_type, source_event, content, phase, classifier_run_id, scope, scope_run_id, locus, locus_run_id, novelty, novelty_run_id, friction, friction_run_id, phase_confidence, scope_confidence, locus_confidence, novelty_confidence, metadata)
+         VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12, ?13, ?14, ?15, ?16, ?17, ?18, ?19, ?20, ?21)",
         params![
             session_id,
             prompt_id,
@@ -124,16 +173,22 @@ pub fn handle_mark(db_path: &Path, args: &MarkArgs) -> Result<(), NmemError> {
             novelty_run_id,
             friction,
             friction_run_id,
+            phase_result.as_ref().map(|r| r.confidence),
+            scope_result.as_ref().map(|r| r.confidence),
+            locus_result.as_ref().map(|r| r.confidence),
+            novelty_result.as_ref().map(|r| r.confidence),
+            metadata,
         ],
     )?;
 
     let obs_id = tx.last_insert_rowid();
+    crate::s3_autopin::evaluate_observation(&tx, &config.auto_pin, obs_id)?;
     tx.commit()?;
 
     // Stream to VictoriaLogs — non-fatal
     stream_observation_to_logs(
         &session_id,
-        &project,
+        project,
         "marker",
         "",
         None,
@@ -146,6 +201,5 @@ pub fn handle_mark(db_path: &Path, args: &MarkArgs) -> Result<(), NmemError> {
         &None,
     );
 
-    println!("{obs_id}");
-    Ok(())
+    Ok(obs_id)
 }
@@ -0,0 +1,340 @@
+//! S1 Operations — MinHash signatures for similarity without a model.
+//!
+//! FTS finds observations sharing a query's terms; it cannot say which
+//! observations read alike. Each observation's content is cut into
+//! three-word shingles and summarized by `HASHES` minimum hashes, whose
+//! agreement estimates the Jaccard similarity of two shingle sets. The
+//! signature is stored at write time with its `BANDS` locality-sensitive
+//! buckets (`ROWS` hashes each), so candidates are the observations sharing
+//! a bucket — pairs near 0.5 similarity or above — rather than a table scan.
+//!
+//! `similar_observations` ranks those candidates by estimated similarity;
+//! `nmem stats --near-duplicates` clusters them to show how much of the
+//! store repeats itself. `nmem maintain` signs observations recorded before
+//! this existed, `BACKFILL_BATCH` at a time.
+
+use crate::NmemError;
+use rusqlite::{Connection, OptionalExtension, params};
+use serde::Serialize;
+use std::collections::{HashMap, HashSet};
+
+/// Minimum hashes per signature.
+pub const HASHES: usize = 64;
+/// LSH bands; `BANDS * ROWS == HASHES`.
+pub const BANDS: usize = 16;
+const ROWS: usize = HASHES / BANDS;
+/// Words per shingle.
+const SHINGLE_WORDS: usize = 3;
+/// Content with fewer words than this is too short to compare meaningfully.
+const MIN_WORDS: usize = 5;
+/// Leading characters signed; the rest adds cost, not discrimination.
+const MAX_CHARS: usize = 20_000;
+/// Bucket members read per band when looking for candidates, newest first.
+const BUCKET_CANDIDATES: i64 = 500;
+/// Observations `nmem maintain` signs per run.
+pub const BACKFILL_BATCH: i64 = 5000;
+
+/// FNV-1a, stable across builds and platforms.
+fn fnv1a(bytes: impl IntoIterator<Item = u8>) -> u64 {
+    bytes.into_iter().fold(0xcbf2_9ce4_8422_2325, |h, b| (h ^ b as u64).wrapping_mul(0x0100_0000_01b3))
+}
+
+/// SplitMix64 finalizer: one independent hash function per seed.
+fn mix(mut x: u64) -> u64 {
+    x = (x ^ (x >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+    x = (x ^ (x >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+    x ^ (x >> 31)
+}
+
+/// The MinHash signature of `text`, or `None` when it has fewer than
+/// `MIN_WORDS` words.
+pub fn signature(text: &str) -> Option<Vec<u32>> {
+    let text: String = text.chars().take(MAX_CHARS).collect::<String>().to_lowercase();
+    let words: Vec<&str> = text.split(|c: char| !c.is_alphanumeric() && c != '_').filter(|w| !w.is_empty()).collect();
+    if words.len() < MIN_WORDS {
+        return None;
+    }
+    let shingles: HashSet<u64> = words
+        .windows(SHINGLE_WORDS)
+        .map(|w| fnv1a(w.join(" ").bytes()))
+        .collect();
+    let seeds: Vec<u64> = (0..HASHES as u64).map(|i| mix(i.wrapping_add(0x9e37_79b9_7f4a_7c15))).collect();
+    Some(
+        seeds
+            .iter()
+            .map(|seed| shingles.iter().map(|s| mix(s ^ seed) as u32).min().unwrap_or(u32::MAX))
+            .collect(),
+    )
+}
+
+/// Estimated Jaccard similarity: the share of agreeing hashes.
+pub fn similarity(a: &[u32], b: &[u32]) -> f64 {
+    if a.is_empty() || a.len() != b.len() {
+        return 0.0;
+    }
+    a.iter().zip(b).filter(|(x, y)| x == y).count() as f64 / a.len() as f64
+}
+
+/// `(band, bucket)` for each band of a signature.
+fn buckets(sig: &[u32]) -> impl Iterator<Item = (i64, i64)> + '_ {
+    sig.chunks(ROWS)
+        .enumerate()
+        .map(|(band, rows)| (band as i64, fnv1a(rows.iter().flat_map(|r| r.to_le_bytes())) as i64))
+}
+
+fn to_blob(sig: &[u32]) -> Vec<u8> {
+    sig.iter().flat_map(|h| h.to_le_bytes()).collect()
+}
+
+fn from_blob(blob: &[u8]) -> Vec<u32> {
+    blob.chunks_exact(4).map(|b| u32::from_le_bytes([b[0], b[1], b[2], b[3]])).collect()
+}
+
+/// Sign observation `obs_id` from its full `content`, replacing any earlier
+/// signature. Content too short to sign gets an empty one, so backfill
+/// passes it by. Returns whether it was long enough.
+pub fn store(conn: &Connection, obs_id: i64, content: &str) -> Result<bool, NmemError> {
+    remove(conn, obs_id)?;
+    let sig = signature(content);
+    conn.prepare_cached("INSERT INTO observation_minhash (obs_id, signature) VALUES (?1, ?2)")?
+        .execute(params![obs_id, to_blob(sig.as_deref().unwrap_or_default())])?;
+    let Some(sig) = sig else {
+        return Ok(false);
+    };
+    let mut stmt = conn.prepare_cached("INSERT OR IGNORE INTO observation_lsh (band, bucket, obs_id) VALUES (?1, ?2, ?3)")?;
+    for (band, bucket) in buckets(&sig) {
+        stmt.execute(params![band, bucket, obs_id])?;
+    }
+    Ok(true)
+}
+
+/// Drop the signature of `obs_id` (its content was redacted or replaced).
+pub fn remove(conn: &Connection, obs_id: i64) -> Result<(), NmemError> {
+    conn.prepare_cached("DELETE FROM observation_lsh WHERE obs_id = ?1")?.execute([obs_id])?;
+    conn.prepare_cached("DELETE FROM observation_minhash WHERE obs_id = ?1")?.execute([obs_id])?;
+    Ok(())
+}
+
+/// The stored signature of `obs_id`.
+pub fn load(conn: &Connection, obs_id: i64) -> Result<Option<Vec<u32>>, NmemError> {
+    let blob: Option<Vec<u8>> = conn
+        .prepare_cached("SELECT signature FROM observation_minhash WHERE obs_id = ?1")?
+        .query_row([obs_id], |r| r.get(0))
+        .optional()?;
+    Ok(blob.filter(|b| !b.is_empty()).map(|b| from_blob(&b)))
+}
+
+/// Observations most similar to signature `sig`, at least `min_similarity`,
+/// best first, excluding `exclude`.
+pub fn similar(
+    conn: &Connection,
+    sig: &[u32],
+    exclude: i64,
+    min_similarity: f64,
+    limit: usize,
+) -> Result<Vec<(i64, f64)>, NmemError> {
+    let mut candidates: HashSet<i64> = HashSet::new();
+    let mut stmt = conn.prepare_cached(
+        "SELECT obs_id FROM observation_lsh WHERE band = ?1 AND bucket = ?2 ORDER BY obs_id DESC LIMIT ?3",
+    )?;
+    for (band, bucket) in buckets(sig) {
+        for id in stmt.query_map(params![band, bucket, BUCKET_CANDIDATES], |r| r.get::<_, i64>(0))? {
+            candidates.insert(id?);
+        }
+    }
+    candidates.remove(&exclude);
+
+    let mut scored = Vec::new();
+    for id in candidates {
+        if let Some(other) = load(conn, id)? {
+            let s = similarity(sig, &other);
+            if s >= min_similarity {
+                scored.push((id, s));
+            }
+        }
+    }
+    scored.sort_by(|a, b| b.1.total_cmp(&a.1).then(b.0.cmp(&a.0)));
+    scored.truncate(limit);
+    Ok(scored)
+}
+
+/// Sign up to `limit` observations that have no signature yet, newest
+/// first. Returns how many were looked at.
+pub fn backfill(conn: &Connection, limit: i64) -> Result<usize, NmemError> {
+    let mut stmt = conn.prepare(concat!(
+        "SELECT o.id, ",
+        crate::s1_chunk::full_content_sql!(),
+        " FROM observations o
+         WHERE NOT EXISTS (SELECT 1 FROM observation_minhash m WHERE m.obs_id = o.id)
+         ORDER BY o.id DESC LIMIT ?1"
+    ))?;
+    let rows: Vec<(i64, String)> = stmt.query_map([limit], |r| Ok((r.get(0)?, r.get(1)?)))?.collect::<Result<_, _>>()?;
+    let tx = conn.unchecked_transaction()?;
+    for (id, content) in &rows {
+        store(&tx, *id, content)?;
+    }
+    tx.commit()?;
+    Ok(rows.len())
+}
+
+/// A group of near-duplicate observations.
+#[derive(Debug, Serialize)]
+pub struct Cluster {
+    pub size: usize,
+    pub obs_type: String,
+    /// The newest member.
+    pub example_id: i64,
+    pub preview: String,
+}
+
+#[derive(Debug, Serialize)]
+pub struct NearDuplicateReport {
+    pub min_similarity: f64,
+    /// Observations with a signature.
+    pub signed: i64,
+    /// Clusters of two or more.
+    pub clusters: usize,
+    /// Members beyond the first of each cluster — what dedup could drop.
+    pub redundant: usize,
+    /// `redundant` per obs_type, most first.
+    pub redundant_by_type: Vec<(String, usize)>,
+    pub largest: Vec<Cluster>,
+}
+
+fn find(parent: &mut HashMap<i64, i64>, id: i64) -> i64 {
+    let p = *parent.get(&id).unwrap_or(&id);
+    if p == id {
+        return id;
+    }
+    let root = find(parent, p);
+    parent.insert(id, root);
+    root
+}
+
+/// Cluster signed observations whose estimated similarity to a bucket-mate
+/// reaches `min_similarity`.
+pub fn near_duplicates(conn: &Connection, min_similarity: f64, largest: usize) -> Result<NearDuplicateReport, NmemError> {
+    let signed: i64 =
+        conn.query_row("SELECT COUNT(*) FROM observation_minhash WHERE length(signature) > 0", [], |r| r.get(0))?;
+    let mut stmt = conn.prepare(
+        "SELECT group_concat(obs_id) FROM observation_lsh GROUP BY band, bucket HAVING COUNT(*) > 1",
+    )?;
+    let groups: Vec<String> = stmt.query_map([], |r| r.get(0))?.collect::<Result<_, _>>()?;
+
+    // Each bucket-mate joins the bucket's first member if they are similar
+    // enough; union-find merges across bands
+    let mut signatures: HashMap<i64, Vec<u32>> = HashMap::new();
+    let mut parent: HashMap<i64, i64> = HashMap::new();
+    for group in groups {
+        let ids: Vec<i64> = group.split(',').filter_map(|s| s.parse().ok()).collect();
+        let Some((&first, rest)) = ids.split_first() else { continue };
+        for &id in [first].iter().chain(rest) {
+            if !signatures.contains_key(&id)
+                && let Some(sig) = load(conn, id)?
+            {
+                signatures.insert(id, sig);
+            }
+        }
+        for &id in rest {
+            let (Some(a), Some(b)) = (signatures.get(&first), signatures.get(&id)) else { continue };
+            if similarity(a, b) >= min_similarity {
+                parent.entry(first).or_insert(first);
+                parent.entry(id).or_insert(id);
+                let (ra, rb) = (find(&mut parent, first), find(&mut parent, id));
+                if ra != rb {
+                    parent.insert(ra.min(rb), ra.max(rb));
+                }
+            }
+        }
+    }
+
+    let mut members: HashMap<i64, Vec<i64>> = HashMap::new();
+    let ids: Vec<i64> = parent.keys().copied().collect();
+    for id in ids {
+        let root = find(&mut parent, id);
+        members.entry(root).or_default().push(id);
+    }
+    let mut clusters: Vec<Vec<i64>> = members.into_values().filter(|m| m.len() > 1).collect();
+    clusters.sort_by(|a, b| b.len().cmp(&a.len()).then(b.iter().max().cmp(&a.iter().max())));
+
+    let mut info = conn.prepare_cached("SELECT obs_type, substr(content, 1, 120) FROM observations WHERE id = ?1")?;
+    let mut by_type: HashMap<String, usize> = HashMap::new();
+    let mut report = Vec::new();
+    for cluster in &clusters {
+        let example_id = cluster.iter().copied().max().unwrap_or_default();
+        let (obs_type, preview): (String, String) = info.query_row([example_id], |r| Ok((r.get(0)?, r.get(1)?)))?;
+        *by_type.entry(obs_type.clone()).or_default() += cluster.len() - 1;
+        if report.len() < largest {
+            report.push(Cluster { size: cluster.len(), obs_type, example_id, preview });
+        }
+    }
+    let mut redundant_by_type: Vec<(String, usize)> = by_type.into_iter().collect();
+    redundant_by_type.sort_by(|a, b| b.1.cmp(&a.1).then(a.0.cmp(&b.0)));
+
+    Ok(NearDuplicateReport {
+        min_similarity,
+        signed,
+        clusters: clusters.len(),
+        redundant: clusters.iter().map(|c| c.len() - 1).sum(),
+        redundant_by_type,
+        largest: report,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const LOG: &str = "error[E0308]: mismatched types in src/auth.rs expected String found integer at line 42 while building nmem";
+
+    #[test]
+    fn signatures_estimate_shingle_overlap() {
+        let a = signature(LOG).unwrap();
+        assert_eq!(a, signature(&LOG.to_uppercase()).unwrap(), "case-insensitive and deterministic");
+        let near = signature(&LOG.replace("line 42", "line 43")).unwrap();
+        let far = signature("cargo build finished release profile optimized target in twelve seconds").unwrap();
+        assert!(similarity(&a, &near) > 0.5, "{}", similarity(&a, &near));
+        assert!(similarity(&a, &far) < 0.2, "{}", similarity(&a, &far));
+        assert_eq!(signature("too short to sign"), None);
+        assert_eq!(from_blob(&to_blob(&a)), a);
+    }
+
+    #[test]
+    fn similar_finds_bucket_mates_and_clusters_them() {
+        let mut conn = Connection::open_in_memory().unwrap();
+        crate::schema::MIGRATIONS.to_latest(&mut conn).unwrap();
+        conn.pragma_update(None, "foreign_keys", "ON").unwrap();
+        conn.execute("INSERT INTO sessions (id, project, started_at) VALUES ('s1', 'p', 0)", []).unwrap();
+        let contents = [
+            LOG.to_string(),
+            LOG.replace("line 42", "line 43"),
+            LOG.replace("line 42", "line 44"),
+            "cargo build finished release profile optimized target in twelve seconds".into(),
+            "ls".into(),
+        ];
+        for (i, content) in contents.iter().enumerate() {
+            conn.execute(
+                "INSERT INTO observations (id, session_id, timestamp, obs_type, source_event, content)
+                 VALUES (?1, 's1', ?1, 'command', 'PostToolUse', ?2)",
+                params![i as i64 + 1, content],
+            )
+            .unwrap();
+        }
+        assert_eq!(backfill(&conn, 100).unwrap(), 5);
+        assert_eq!(backfill(&conn, 100).unwrap(), 0, "short content is remembered as skipped");
+
+        let sig = load(&conn, 1).unwrap().unwrap();
+        let hits = similar(&conn, &sig, 1, 0.5, 10).unwrap();
+        assert_eq!(hits.iter().map(|h| h.0).collect::<HashSet<_>>(), HashSet::from([2, 3]));
+
+        let report = near_duplicates(&conn, 0.5, 5).unwrap();
+        assert_eq!((report.signed, report.clusters, report.redundant), (4, 1, 2));
+        assert_eq!(report.largest[0].size, 3);
+        assert_eq!(report.largest[0].example_id, 3);
+        assert_eq!(report.redundant_by_type, vec![("command".to_string(), 2)]);
+
+        conn.execute("DELETE FROM observations WHERE id = 2", []).unwrap();
+        assert_eq!(load(&conn, 2).unwrap(), None, "signature goes with its observation");
+        assert!(!similar(&conn, &sig, 1, 0.5, 10).unwrap().iter().any(|h| h.0 == 2));
+    }
+}
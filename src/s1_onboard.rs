@@ -0,0 +1,280 @@
+//! S1 Operations — first-session project snapshot.
+//!
+//! The first SessionStart in a project nmem has never seen would inject
+//! nothing. Instead the project directory is scanned — file counts by
+//! language, build system, top-level layout, the head of the README, and
+//! recent commits — and the result is stored as a `project_onboarding`
+//! observation. Context shows it under "Project snapshot" until one of the
+//! project's sessions has been summarized. The scan is bounded
+//! (`MAX_ENTRIES`, `MAX_DEPTH`) to stay inside the hook's timeout.
+
+use crate::s5_filter::SecretFilter;
+use crate::NmemError;
+use rusqlite::{Connection, OptionalExtension, params};
+use std::collections::HashMap;
+use std::path::Path;
+
+/// `obs_type` of the snapshot observation.
+pub const OBS_TYPE: &str = "project_onboarding";
+
+/// Directory entries visited at most.
+const MAX_ENTRIES: usize = 5000;
+/// Directory levels below the root walked.
+const MAX_DEPTH: usize = 6;
+/// README lines and characters kept.
+const README_LINES: usize = 15;
+const README_CHARS: usize = 800;
+/// Recent commits listed.
+const COMMITS: usize = 5;
+
+/// Directories never worth counting.
+const SKIP_DIRS: &[&str] = &["node_modules", "target", "dist", "build", "vendor", "__pycache__", "venv"];
+
+/// Build manifests: (file, build system).
+const BUILD_FILES: &[(&str, &str)] = &[
+    ("Cargo.toml", "Cargo"),
+    ("package.json", "npm"),
+    ("pyproject.toml", "Python (pyproject)"),
+    ("setup.py", "Python (setuptools)"),
+    ("go.mod", "Go modules"),
+    ("pom.xml", "Maven"),
+    ("build.gradle", "Gradle"),
+    ("build.gradle.kts", "Gradle"),
+    ("CMakeLists.txt", "CMake"),
+    ("Makefile", "Make"),
+    ("Gemfile", "Bundler"),
+    ("mix.exs", "Mix"),
+    ("flake.nix", "Nix"),
+];
+
+fn language(ext: &str) -> Option<&'static str> {
+    Some(match ext {
+        "rs" => "Rust",
+        "py" => "Python",
+        "ts" | "tsx" => "TypeScript",
+        "js" | "jsx" | "mjs" | "cjs" => "JavaScript",
+        "go" => "Go",
+        "java" => "Java",
+        "kt" | "kts" => "Kotlin",
+        "c" | "h" => "C",
+        "cc" | "cpp" | "cxx" | "hpp" => "C++",
+        "cs" => "C#",
+        "rb" => "Ruby",
+        "ex" | "exs" => "Elixir",
+        "swift" => "Swift",
+        "php" => "PHP",
+        "sh" | "bash" => "Shell",
+        "nix" => "Nix",
+        "sql" => "SQL",
+        _ => return None,
+    })
+}
+
+/// Whether `project` has no sessions other than `session_id`.
+pub fn is_new_project(conn: &Connection, project: &str, session_id: &str) -> Result<bool, NmemError> {
+    let seen: bool = conn.query_row(
+        "SELECT EXISTS(SELECT 1 FROM sessions WHERE project = ?1 AND id != ?2)",
+        params![project, session_id],
+        |r| r.get(0),
+    )?;
+    Ok(!seen)
+}
+
+/// Describe the project rooted at `root`. `None` when it isn't a directory
+/// or holds nothing recognisable.
+pub fn scan(root: &Path) -> Option<String> {
+    if !root.is_dir() {
+        return None;
+    }
+    let mut languages: HashMap<&str, usize> = HashMap::new();
+    let (mut files, mut dirs, mut visited) = (0usize, 0usize, 0usize);
+    let mut truncated = false;
+    let mut stack = vec![(root.to_path_buf(), 0usize)];
+    while let Some((dir, depth)) = stack.pop() {
+        let Ok(entries) = std::fs::read_dir(&dir) else { continue };
+        for entry in entries.flatten() {
+            visited += 1;
+            if visited > MAX_ENTRIES {
+                truncated = true;
+                break;
+            }
+            let name = entry.file_name();
+            let name = name.to_string_lossy();
+            let Ok(kind) = entry.file_type() else { continue };
+            if kind.is_dir() {
+                if name.starts_with('.') || SKIP_DIRS.contains(&name.as_ref()) {
+                    continue;
+                }
+                dirs += 1;
+                if depth < MAX_DEPTH {
+                    stack.push((entry.path(), depth + 1));
+                }
+            } else if kind.is_file() {
+                files += 1;
+                if let Some(lang) = Path::new(name.as_ref()).extension().and_then(|e| language(&e.to_string_lossy())) {
+                    *languages.entry(lang).or_default() += 1;
+                }
+            }
+        }
+        if truncated {
+            break;
+        }
+    }
+
+    let build: Vec<String> = BUILD_FILES
+        .iter()
+        .filter(|(file, _)| root.join(file).is_file())
+        .map(|(file, system)| format!("{system} ({file})"))
+        .collect();
+    if files == 0 && build.is_empty() {
+        return None;
+    }
+
+    let mut out = String::new();
+    let mut langs: Vec<(&str, usize)> = languages.into_iter().collect();
+    langs.sort_by(|a, b| b.1.cmp(&a.1).then(a.0.cmp(b.0)));
+    if !langs.is_empty() {
+        let list: Vec<String> = langs.iter().take(5).map(|(l, n)| format!("{l} ({n})")).collect();
+        out.push_str(&format!("Languages: {}\n", list.join(", ")));
+    }
+    if !build.is_empty() {
+        out.push_str(&format!("Build: {}\n", build.join(", ")));
+    }
+    out.push_str(&format!(
+        "Files: {}{files} files in {dirs} directories\n",
+        if truncated { "over " } else { "" }
+    ));
+
+    let mut top: Vec<String> = std::fs::read_dir(root)
+        .into_iter()
+        .flatten()
+        .flatten()
+        .filter_map(|e| {
+            let name = e.file_name().to_string_lossy().into_owned();
+            if name.starts_with('.') {
+                return None;
+            }
+            Some(if e.file_type().ok()?.is_dir() { format!("{name}/") } else { name })
+        })
+        .collect();
+    top.sort();
+    if !top.is_empty() {
+        let more = top.len().saturating_sub(20);
+        top.truncate(20);
+        out.push_str(&format!("Top level: {}", top.join(", ")));
+        if more > 0 {
+            out.push_str(&format!(", … {more} more"));
+        }
+        out.push('\n');
+    }
+
+    if let Some(readme) = readme_head(root) {
+        out.push_str(&format!("README:\n{readme}\n"));
+    }
+    let commits = recent_commits(root);
+    if !commits.is_empty() {
+        out.push_str("Recent commits:\n");
+        for c in commits {
+            out.push_str(&format!("  {c}\n"));
+        }
+    }
+    Some(out.trim_end().to_string())
+}
+
+fn readme_head(root: &Path) -> Option<String> {
+    let path = ["README.md", "README", "README.rst", "README.txt", "readme.md"]
+        .iter()
+        .map(|n| root.join(n))
+        .find(|p| p.is_file())?;
+    let text = std::fs::read_to_string(path).ok()?;
+    let head: Vec<&str> = text.lines().filter(|l| !l.trim().is_empty()).take(README_LINES).collect();
+    let head: String = head.join("\n").chars().take(README_CHARS).collect();
+    (!head.is_empty()).then_some(head)
+}
+
+/// `short-oid summary` of the newest commits, if `root` is in a git repo.
+fn recent_commits(root: &Path) -> Vec<String> {
+    let Ok(repo) = git2::Repository::discover(root) else {
+        return Vec::new();
+    };
+    let Ok(mut walk) = repo.revwalk() else {
+        return Vec::new();
+    };
+    if walk.push_head().is_err() {
+        return Vec::new();
+    }
+    walk.flatten()
+        .take(COMMITS)
+        .filter_map(|oid| {
+            let commit = repo.find_commit(oid).ok()?;
+            let oid = oid.to_string();
+            Some(format!("{} {}", &oid[..7], commit.summary().unwrap_or("")))
+        })
+        .collect()
+}
+
+/// Store `snapshot` (redacted) as the onboarding observation of `session_id`.
+pub fn record(
+    conn: &Connection,
+    filter: &SecretFilter,
+    session_id: &str,
+    snapshot: &str,
+    ts: i64,
+) -> Result<i64, NmemError> {
+    let (content, _) = filter.redact(snapshot);
+    conn.execute(
+        "INSERT INTO observations (session_id, timestamp, obs_type, source_event, content)
+         VALUES (?1, ?2, ?3, 'SessionStart', ?4)",
+        params![session_id, ts, OBS_TYPE, content],
+    )?;
+    Ok(conn.last_insert_rowid())
+}
+
+/// The project's latest onboarding snapshot taken before `before`, while
+/// no session of the project has a real summary yet.
+pub fn latest(conn: &Connection, project: &str, before: Option<i64>) -> Result<Option<String>, NmemError> {
+    Ok(conn
+        .query_row(
+            "SELECT o.content FROM observations o JOIN sessions s ON s.id = o.session_id
+             WHERE s.project = ?1 AND o.obs_type = ?2 AND (?3 IS NULL OR o.timestamp < ?3)
+               AND NOT EXISTS (
+                   SELECT 1 FROM sessions p
+                   WHERE p.project = ?1 AND p.summary IS NOT NULL
+                     AND json_extract(p.summary, '$.intent') IS NOT 'empty session'
+                     AND (?3 IS NULL OR p.started_at < ?3))
+             ORDER BY o.timestamp DESC LIMIT 1",
+            params![project, OBS_TYPE, before],
+            |r| r.get(0),
+        )
+        .optional()?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn scan_describes_layout_build_and_readme() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let root = dir.path();
+        std::fs::write(root.join("Cargo.toml"), "[package]\nname = \"demo\"\n").unwrap();
+        std::fs::write(root.join("README.md"), "# Demo\n\nA tool that does things.\n").unwrap();
+        std::fs::create_dir_all(root.join("src/bin")).unwrap();
+        std::fs::write(root.join("src/main.rs"), "fn main() {}").unwrap();
+        std::fs::write(root.join("src/bin/extra.rs"), "fn main() {}").unwrap();
+        std::fs::write(root.join("build.py"), "").unwrap();
+        std::fs::create_dir_all(root.join("target/debug")).unwrap();
+        std::fs::write(root.join("target/debug/junk.rs"), "").unwrap();
+
+        let text = scan(root).unwrap();
+        assert!(text.contains("Languages: Rust (2), Python (1)"), "{text}");
+        assert!(text.contains("Build: Cargo (Cargo.toml)"), "{text}");
+        assert!(text.contains("Top level: Cargo.toml, README.md, build.py, src/, target/"), "{text}");
+        assert!(text.contains("A tool that does things."), "{text}");
+        assert!(!text.contains("Recent commits"), "not a repo");
+
+        let empty = tempfile::TempDir::new().unwrap();
+        assert_eq!(scan(empty.path()), None);
+        assert_eq!(scan(&root.join("missing")), None);
+    }
+}
@@ -0,0 +1,251 @@
+//! S1 Operations — agent plan tracking.
+//!
+//! TodoWrite rewrites the agent's whole todo list on every call, and
+//! ExitPlanMode carries the plan the agent proposed. Stored as plain
+//! observations, the churn between calls is invisible. At record time each
+//! TodoWrite is diffed against the session's latest status per item and the
+//! differences land in `plans` — new items, status transitions, items that
+//! were dropped — so `plan_history` shows what the agent believed the task
+//! was, and how that belief changed.
+
+use crate::s5_filter::SecretFilter;
+use crate::NmemError;
+use rusqlite::{Connection, params};
+use serde::Serialize;
+use std::collections::HashMap;
+
+/// `kind` of a TodoWrite item row.
+pub const TODO: &str = "todo";
+/// `kind` of an ExitPlanMode row.
+pub const PLAN: &str = "plan";
+
+/// Status recorded for an item missing from a later TodoWrite.
+const REMOVED: &str = "removed";
+/// Chars of an ExitPlanMode plan kept in `item`.
+const PLAN_CHARS: usize = 2000;
+
+#[derive(Debug, Serialize)]
+pub struct PlanEvent {
+    pub session_id: String,
+    pub timestamp: i64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub obs_id: Option<i64>,
+    pub kind: String,
+    pub item: String,
+    pub status: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub prev_status: Option<String>,
+}
+
+/// (content, status) pairs of a TodoWrite input, in list order.
+fn todos(tool_input: &serde_json::Value) -> Vec<(String, String)> {
+    tool_input
+        .get("todos")
+        .and_then(|v| v.as_array())
+        .map(|items| {
+            items
+                .iter()
+                .filter_map(|t| {
+                    let content = t.get("content").and_then(|v| v.as_str())?.trim();
+                    let status = t.get("status").and_then(|v| v.as_str()).unwrap_or("pending");
+                    (!content.is_empty()).then(|| (content.to_string(), status.to_string()))
+                })
+                .collect()
+        })
+        .unwrap_or_default()
+}
+
+/// Observation content for a TodoWrite: counts plus the item in progress.
+pub fn todo_summary(tool_input: &serde_json::Value) -> String {
+    let items = todos(tool_input);
+    let count = |s: &str| items.iter().filter(|(_, st)| st == s).count();
+    let mut summary = format!(
+        "{} todos: {} done, {} in progress, {} pending",
+        items.len(),
+        count("completed"),
+        count("in_progress"),
+        count("pending"),
+    );
+    if let Some((content, _)) = items.iter().find(|(_, st)| st == "in_progress") {
+        summary.push_str(" — now: ");
+        summary.push_str(content);
+    }
+    summary
+}
+
+/// Record the plan changes carried by a TodoWrite or ExitPlanMode call.
+/// Returns the number of `plans` rows written; other tools write none.
+pub fn record(
+    conn: &Connection,
+    filter: &SecretFilter,
+    session_id: &str,
+    obs_id: i64,
+    tool_name: &str,
+    tool_input: &serde_json::Value,
+    now: i64,
+) -> Result<usize, NmemError> {
+    let mut insert = conn.prepare_cached(
+        "INSERT INTO plans (session_id, obs_id, timestamp, kind, item, status, prev_status)
+         VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)",
+    )?;
+
+    if tool_name == "ExitPlanMode" {
+        let plan: String = tool_input
+            .get("plan")
+            .and_then(|v| v.as_str())
+            .unwrap_or("")
+            .trim()
+            .chars()
+            .take(PLAN_CHARS)
+            .collect();
+        if plan.is_empty() {
+            return Ok(0);
+        }
+        let (plan, _) = filter.redact(&plan);
+        insert.execute(params![session_id, obs_id, now, PLAN, plan, "proposed", None::<String>])?;
+        return Ok(1);
+    }
+    if tool_name != "TodoWrite" {
+        return Ok(0);
+    }
+
+    // Latest status per todo item this session, dropped items included
+    let mut latest: HashMap<String, String> = {
+        let mut stmt = conn.prepare(
+            "SELECT item, status FROM plans
+             WHERE id IN (SELECT MAX(id) FROM plans WHERE session_id = ?1 AND kind = ?2 GROUP BY item)",
+        )?;
+        stmt.query_map(params![session_id, TODO], |r| Ok((r.get(0)?, r.get(1)?)))?
+            .collect::<Result<_, _>>()?
+    };
+
+    let mut written = 0;
+    for (content, status) in todos(tool_input) {
+        let (item, _) = filter.redact(&content);
+        let prev = latest.remove(&item);
+        if prev.as_deref() == Some(status.as_str()) {
+            continue;
+        }
+        insert.execute(params![session_id, obs_id, now, TODO, item, status, prev])?;
+        written += 1;
+    }
+    let mut dropped: Vec<(String, String)> = latest.into_iter().filter(|(_, s)| s != REMOVED).collect();
+    dropped.sort();
+    for (item, prev) in dropped {
+        insert.execute(params![session_id, obs_id, now, TODO, item, REMOVED, prev])?;
+        written += 1;
+    }
+    Ok(written)
+}
+
+/// The last `limit` plan events, oldest first. With neither filter, the most
+/// recent session that has any.
+pub fn plan_history(
+    conn: &Connection,
+    session_id: Option<&str>,
+    project: Option<&str>,
+    limit: i64,
+) -> Result<Vec<PlanEvent>, NmemError> {
+    let session_id: Option<String> = match (session_id, project) {
+        (Some(s), _) => Some(s.to_string()),
+        (None, Some(_)) => None,
+        (None, None) => {
+            let mut stmt = conn.prepare("SELECT session_id FROM plans ORDER BY id DESC LIMIT 1")?;
+            let mut rows = stmt.query([])?;
+            match rows.next()? {
+                Some(r) => Some(r.get(0)?),
+                None => return Ok(Vec::new()),
+            }
+        }
+    };
+
+    let mut stmt = conn.prepare(
+        "SELECT * FROM (
+             SELECT p.id, p.session_id, p.timestamp, p.obs_id, p.kind, p.item, p.status, p.prev_status
+             FROM plans p JOIN sessions s ON s.id = p.session_id
+             WHERE (?1 IS NULL OR p.session_id = ?1) AND (?2 IS NULL OR s.project = ?2)
+             ORDER BY p.id DESC LIMIT ?3
+         ) ORDER BY id",
+    )?;
+    let events = stmt
+        .query_map(params![session_id, project, limit], |r| {
+            Ok(PlanEvent {
+                session_id: r.get(1)?,
+                timestamp: r.get(2)?,
+                obs_id: r.get(3)?,
+                kind: r.get(4)?,
+                item: r.get(5)?,
+                status: r.get(6)?,
+                prev_status: r.get(7)?,
+            })
+        })?
+        .collect::<Result<_, _>>()?;
+    Ok(events)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn records_transitions_and_dropped_items() {
+        let mut conn = Connection::open_in_memory().unwrap();
+        crate::schema::MIGRATIONS.to_latest(&mut conn).unwrap();
+        conn.execute_batch("INSERT INTO sessions (id, project, started_at) VALUES ('s', 'p', 1), ('t', 'q', 2);")
+            .unwrap();
+        for id in 1..=6 {
+            conn.execute(
+                "INSERT INTO observations (id, session_id, timestamp, obs_type, source_event, content)
+                 VALUES (?1, 's', ?1, 'plan', 'PostToolUse', '')",
+                params![id],
+            )
+            .unwrap();
+        }
+        let filter = SecretFilter::with_params(Default::default());
+
+        let first = json!({"todos": [
+            {"content": "Read the parser", "status": "in_progress", "activeForm": "Reading"},
+            {"content": "Fix the bug", "status": "pending", "activeForm": "Fixing"},
+        ]});
+        assert_eq!(todo_summary(&first), "2 todos: 0 done, 1 in progress, 1 pending — now: Read the parser");
+        assert_eq!(record(&conn, &filter, "s", 1, "TodoWrite", &first, 10).unwrap(), 2);
+        // Unchanged list writes nothing
+        assert_eq!(record(&conn, &filter, "s", 2, "TodoWrite", &first, 11).unwrap(), 0);
+
+        let second = json!({"todos": [
+            {"content": "Read the parser", "status": "completed"},
+            {"content": "Rewrite the lexer", "status": "in_progress"},
+        ]});
+        assert_eq!(record(&conn, &filter, "s", 3, "TodoWrite", &second, 12).unwrap(), 3);
+        let plan = json!({"plan": "1. Swap the lexer\n2. Keep the parser"});
+        assert_eq!(record(&conn, &filter, "s", 4, "ExitPlanMode", &plan, 13).unwrap(), 1);
+        assert_eq!(record(&conn, &filter, "s", 5, "Bash", &json!({"command": "ls"}), 14).unwrap(), 0);
+        record(&conn, &filter, "t", 6, "TodoWrite", &first, 15).unwrap();
+
+        let events = plan_history(&conn, Some("s"), None, 50).unwrap();
+        let rows: Vec<(&str, &str, Option<&str>)> = events
+            .iter()
+            .map(|e| (e.item.as_str(), e.status.as_str(), e.prev_status.as_deref()))
+            .collect();
+        assert_eq!(
+            rows,
+            vec![
+                ("Read the parser", "in_progress", None),
+                ("Fix the bug", "pending", None),
+                ("Read the parser", "completed", Some("in_progress")),
+                ("Rewrite the lexer", "in_progress", None),
+                ("Fix the bug", "removed", Some("pending")),
+                ("1. Swap the lexer\n2. Keep the parser", "proposed", None),
+            ]
+        );
+        assert_eq!(events[5].kind, PLAN);
+
+        // Default is the latest session with plans; limit keeps the newest
+        assert!(plan_history(&conn, None, None, 50).unwrap().iter().all(|e| e.session_id == "t"));
+        assert_eq!(plan_history(&conn, None, Some("p"), 50).unwrap().len(), 6);
+        let last = plan_history(&conn, Some("s"), None, 2).unwrap();
+        assert_eq!(last[1].status, "proposed");
+        assert_eq!(last.len(), 2);
+    }
+}
@@ -0,0 +1,134 @@
+//! S1 Operations — read-only SQL over the store.
+//!
+//! `nmem query` runs user SQL on a read-only connection with the scoring
+//! functions registered (`exp_decay`, `bm25_norm`, `type_weight`,
+//! `search_score`, `recent_score`, `jaccard`, `time_bucket` — see
+//! `db::register_udfs_with`), so custom ranked views can be built without
+//! forking the SQL inside serve. Rows print as JSON lines.
+//! With no SQL argument, statements are read from stdin, each ending in `;`.
+
+#[cfg(feature = "cli")]
+use crate::cli::QueryArgs;
+use crate::NmemError;
+use rusqlite::Connection;
+use rusqlite::types::ValueRef;
+#[cfg(feature = "cli")]
+use std::path::Path;
+
+fn json_value(value: ValueRef<'_>) -> serde_json::Value {
+    match value {
+        ValueRef::Null => serde_json::Value::Null,
+        ValueRef::Integer(n) => n.into(),
+        ValueRef::Real(f) => serde_json::Number::from_f64(f).map_or(serde_json::Value::Null, Into::into),
+        ValueRef::Text(t) => String::from_utf8_lossy(t).into_owned().into(),
+        ValueRef::Blob(b) => format!("<{} bytes>", b.len()).into(),
+    }
+}
+
+/// Run one read-only statement; at most `limit` rows, as column → value.
+pub fn run_query(
+    conn: &Connection,
+    sql: &str,
+    limit: usize,
+) -> Result<Vec<serde_json::Map<String, serde_json::Value>>, NmemError> {
+    if sql.to_ascii_lowercase().contains(crate::s1_correct::ORIGINALS_TABLE) {
+        return Err(NmemError::Config(format!(
+            "{} holds redacted originals and can't be queried",
+            crate::s1_correct::ORIGINALS_TABLE
+        )));
+    }
+    let mut stmt = conn.prepare(sql)?;
+    if !stmt.readonly() {
+        return Err(NmemError::Config("nmem query only runs read-only statements".into()));
+    }
+    let columns: Vec<String> = stmt.column_names().into_iter().map(String::from).collect();
+    let mut rows = stmt.query([])?;
+    let mut out = Vec::new();
+    while out.len() < limit
+        && let Some(row) = rows.next()?
+    {
+        let mut obj = serde_json::Map::new();
+        for (i, name) in columns.iter().enumerate() {
+            obj.insert(name.clone(), json_value(row.get_ref(i)?));
+        }
+        out.push(obj);
+    }
+    Ok(out)
+}
+
+#[cfg(feature = "cli")]
+fn print_rows(conn: &Connection, sql: &str, limit: usize) -> Result<(), NmemError> {
+    use std::io::Write;
+    let rows = run_query(conn, sql, limit)?;
+    let mut out = std::io::stdout().lock();
+    for row in &rows {
+        writeln!(out, "{}", serde_json::to_string(row)?)?;
+    }
+    out.flush()?;
+    Ok(())
+}
+
+#[cfg(feature = "cli")]
+pub fn handle_query(db_path: &Path, args: &QueryArgs) -> Result<(), NmemError> {
+    let config = crate::s5_config::load_config().unwrap_or_default();
+    let conn = crate::db::open_db_readonly(db_path)?;
+    crate::db::register_udfs_with(&conn, crate::s5_config::resolve_importance(&config))?;
+    let limit = args.limit.max(1);
+
+    if let Some(sql) = &args.sql {
+        return print_rows(&conn, sql, limit);
+    }
+
+    // Statement per `;`-terminated chunk; an error doesn't end the session
+    let mut buf = String::new();
+    for line in std::io::stdin().lines() {
+        let line = line?;
+        buf.push_str(&line);
+        buf.push('\n');
+        if !line.trim_end().ends_with(';') {
+            continue;
+        }
+        let sql = std::mem::take(&mut buf);
+        if let Err(e) = print_rows(&conn, sql.trim(), limit) {
+            log::error!("{e}");
+        }
+    }
+    if !buf.trim().is_empty() {
+        print_rows(&conn, buf.trim(), limit)?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn ranks_with_udfs_and_refuses_writes() {
+        let mut conn = Connection::open_in_memory().unwrap();
+        crate::schema::MIGRATIONS.to_latest(&mut conn).unwrap();
+        crate::db::register_udfs(&conn).unwrap();
+        conn.execute_batch(
+            "INSERT INTO sessions (id, project, started_at) VALUES ('s', 'p', 1);
+             INSERT INTO observations (session_id, timestamp, obs_type, source_event, content) VALUES
+                 ('s', 100, 'file_read', 'PostToolUse', 'r'),
+                 ('s', 200, 'file_edit', 'PostToolUse', 'e');",
+        )
+        .unwrap();
+
+        let rows = run_query(
+            &conn,
+            "SELECT obs_type, time_bucket(timestamp, 60) AS bucket, type_weight(obs_type) AS w
+             FROM observations ORDER BY w DESC",
+            10,
+        )
+        .unwrap();
+        assert_eq!(rows.len(), 2);
+        assert_eq!(rows[0]["obs_type"], "file_edit");
+        assert_eq!(rows[0]["bucket"], 180);
+        assert_eq!(rows[1]["w"], 0.17);
+        assert_eq!(run_query(&conn, "SELECT id FROM observations", 1).unwrap().len(), 1);
+        assert!(run_query(&conn, "DELETE FROM observations", 10).is_err());
+        assert!(run_query(&conn, "SELECT * FROM Observation_Originals", 10).is_err());
+    }
+}
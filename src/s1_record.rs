@@ -1,11 +1,12 @@
 use crate::s4_context;
-use crate::s1_extract::{classify_tool, extract_content, extract_file_path, extract_git_metadata};
-use crate::s1_4_transcript::{get_current_prompt_id, scan_transcript};
+use crate::s1_extract::{classify_tool, extract_content, extract_file_path, extract_git_metadata, normalize_cwd, resolve_path};
+use crate::s1_4_transcript::{capture_response, get_current_prompt_id, scan_transcript};
 use crate::s2_classify;
+use crate::s2_inference::ClassificationResult;
 use crate::s2_locus;
 use crate::s2_novelty;
 use crate::s2_scope;
-use crate::s5_config::{load_config, resolve_filter_params, NmemConfig};
+use crate::s5_config::{load_config, resolve_capture, resolve_filter_params, NmemConfig};
 use crate::s5_filter::{SecretFilter, redact_json_value_with};
 use crate::s5_project::derive_project_with_strategy;
 use crate::db::{open_db, retry_on_busy};
@@ -14,7 +15,10 @@ use rusqlite::{Connection, params};
 use serde::Deserialize;
 use std::io::Read;
 use std::path::Path;
-use std::time::{SystemTime, UNIX_EPOCH};
+use std::time::{Instant, SystemTime, UNIX_EPOCH};
+
+/// Ancestors followed by `session_lineage`.
+const MAX_LINEAGE: i64 = 20;
 
 #[derive(Deserialize)]
 struct HookPayload {
@@ -30,13 +34,20 @@ struct HookPayload {
     #[serde(default)]
     tool_response: Option<serde_json::Value>,
     #[serde(default)]
+    tool_use_id: Option<String>,
+    #[serde(default)]
     transcript_path: Option<String>,
     // SessionStart specific
     #[serde(default)]
     source: Option<String>,
+    #[serde(default)]
+    model: Option<serde_json::Value>,
     // UserPromptSubmit specific
     #[serde(default)]
     prompt: Option<String>,
+    // Heartbeat specific
+    #[serde(default)]
+    message: Option<String>,
 }
 
 fn now_ts() -> i64 {
@@ -46,7 +57,71 @@ fn now_ts() -> i64 {
         .as_secs() as i64
 }
 
-fn ensure_session(conn: &Connection, session_id: &str, project: &str, ts: i64) -> Result<(), NmemError> {
+/// Raw classifier outputs for an observation's four text dimensions.
+pub(crate) struct Classified {
+    phase: Option<ClassificationResult>,
+    scope: Option<ClassificationResult>,
+    locus: Option<ClassificationResult>,
+    novelty: Option<ClassificationResult>,
+}
+
+/// Classifier labels with their `classifier_runs` ids and confidences, as
+/// stored.
+#[derive(Default)]
+pub(crate) struct Labels {
+    pub phase: Option<&'static str>,
+    pub classifier_run_id: Option<i64>,
+    pub phase_confidence: Option<f32>,
+    pub scope: Option<&'static str>,
+    pub scope_run_id: Option<i64>,
+    pub scope_confidence: Option<f32>,
+    pub locus: Option<&'static str>,
+    pub locus_run_id: Option<i64>,
+    pub locus_confidence: Option<f32>,
+    pub novelty: Option<&'static str>,
+    pub novelty_run_id: Option<i64>,
+    pub novelty_confidence: Option<f32>,
+}
+
+/// Run the phase, scope, locus, and novelty classifiers. Each is `None` when
+/// its model isn't available. Pure computation — safe on a worker thread.
+pub(crate) fn classify_text(text: &str) -> Classified {
+    Classified {
+        phase: s2_classify::classify(text),
+        scope: s2_scope::classify_scope(text),
+        locus: s2_locus::classify_locus(text),
+        novelty: s2_novelty::classify_novelty(text),
+    }
+}
+
+impl Classified {
+    /// Register classifier runs for provenance and return storable labels.
+    pub(crate) fn register(&self, conn: &Connection) -> Labels {
+        let run = |result: &Option<ClassificationResult>, name: &str| {
+            result.as_ref().and_then(|r| {
+                s2_classify::ensure_classifier_run(conn, name, r.model_hash, None, None, None).ok()
+            })
+        };
+        let confidence = |result: &Option<ClassificationResult>| result.as_ref().map(|r| r.confidence);
+        Labels {
+            phase: self.phase.as_ref().map(|r| r.label),
+            classifier_run_id: run(&self.phase, "think-act"),
+            phase_confidence: confidence(&self.phase),
+            scope: self.scope.as_ref().map(|r| r.label),
+            scope_run_id: run(&self.scope, "converge-diverge"),
+            scope_confidence: confidence(&self.scope),
+            locus: self.locus.as_ref().map(|r| r.label),
+            locus_run_id: run(&self.locus, "internal-external"),
+            locus_confidence: confidence(&self.locus),
+            novelty: self.novelty.as_ref().map(|r| r.label),
+            novelty_run_id: run(&self.novelty, "routine-novel"),
+            novelty_confidence: confidence(&self.novelty),
+        }
+    }
+}
+
+/// Insert the session row if it's new. Returns whether it was created.
+pub(crate) fn ensure_session(conn: &Connection, session_id: &str, project: &str, ts: i64) -> Result<bool, NmemError> {
     let exists: bool = conn.query_row(
         "SELECT EXISTS(SELECT 1 FROM sessions WHERE id = ?1)",
         params![session_id],
@@ -60,7 +135,27 @@ fn ensure_session(conn: &Connection, session_id: &str, project: &str, ts: i64) -
         )?;
     }
 
-    Ok(())
+    Ok(!exists)
+}
+
+/// Sessions `session_id` continues, nearest first, itself included. A
+/// session started by resume, clear, or compact under a new id has the
+/// project's previous agent session as its parent.
+pub fn session_lineage(conn: &Connection, session_id: &str) -> Result<Vec<String>, NmemError> {
+    let mut stmt = conn.prepare(
+        "WITH RECURSIVE chain(id, depth) AS (
+             SELECT ?1, 0
+             UNION
+             SELECT s.parent_id, c.depth + 1
+             FROM chain c JOIN sessions s ON s.id = c.id
+             WHERE s.parent_id IS NOT NULL AND c.depth < ?2
+         )
+         SELECT id FROM chain ORDER BY depth",
+    )?;
+    let ids = stmt
+        .query_map(params![session_id, MAX_LINEAGE], |r| r.get(0))?
+        .collect::<Result<_, _>>()?;
+    Ok(ids)
 }
 
 fn handle_session_start(
@@ -68,18 +163,42 @@ fn handle_session_start(
     payload: &HookPayload,
     config: &NmemConfig,
     project: &str,
-) -> Result<(), NmemError> {
+) -> Result<Option<String>, NmemError> {
     let ts = now_ts();
     let tx = conn.unchecked_transaction()?;
 
-    ensure_session(&tx, &payload.session_id, project, ts)?;
+    let created = ensure_session(&tx, &payload.session_id, project, ts)?;
+    // The dispatcher routes tasks back to the pane a project last ran in
+    if let Ok(pane) = std::env::var("TMUX_PANE")
+        && !pane.is_empty()
+    {
+        tx.execute("UPDATE sessions SET tmux_pane = ?1 WHERE id = ?2", params![pane, payload.session_id])?;
+    }
+
+    let model = payload
+        .model
+        .as_ref()
+        .and_then(crate::s3_model::model_id)
+        .or_else(|| std::env::var("ANTHROPIC_MODEL").ok().filter(|m| !m.trim().is_empty()));
+    crate::s3_model::set_identity(&tx, &payload.session_id, model.as_deref(), None)?;
 
     let source = payload.source.as_deref().unwrap_or("startup");
+    if created && matches!(source, "compact" | "resume" | "clear") {
+        tx.execute(
+            "UPDATE sessions SET parent_id = (
+                 SELECT id FROM sessions
+                 WHERE project = ?2 AND kind = 'agent' AND id != ?1 AND started_at <= ?3
+                 ORDER BY started_at DESC, rowid DESC LIMIT 1
+             )
+             WHERE id = ?1",
+            params![payload.session_id, project, ts],
+        )?;
+    }
     if matches!(source, "compact" | "resume" | "clear") {
         let prompt_id = get_current_prompt_id(&tx, &payload.session_id)?;
         tx.execute(
-            "INSERT INTO observations (session_id, prompt_id, timestamp, obs_type, source_event, content)
-             VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
+            "INSERT INTO observations (session_id, prompt_id, timestamp, obs_type, source_event, content, cwd)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)",
             params![
                 payload.session_id,
                 prompt_id,
@@ -87,33 +206,54 @@ fn handle_session_start(
                 format!("session_{source}"),
                 "SessionStart",
                 source,
+                normalize_cwd(&payload.cwd),
             ],
         )?;
     }
 
+    // A project never seen before starts from a scan of its directory
+    let cwd = Path::new(&payload.cwd);
+    if created
+        && config.record.onboarding
+        && cwd.is_absolute()
+        && crate::s1_onboard::is_new_project(&tx, project, &payload.session_id)?
+        && let Some(snapshot) = crate::s1_onboard::scan(cwd)
+    {
+        let params = resolve_filter_params(config, Some(project));
+        crate::s1_onboard::record(&tx, &SecretFilter::with_params(params), &payload.session_id, &snapshot, ts)?;
+    }
+
     tx.commit()?;
 
-    // Context injection — non-fatal, errors logged to stderr
-    let is_recovery = matches!(source, "compact" | "clear");
-    let (local_limit, cross_limit) = crate::s5_config::resolve_context_limits(config, project, is_recovery);
-    match s4_context::generate_context(conn, project, local_limit, cross_limit, None) {
-        Ok(ctx) if !ctx.is_empty() => print!("{ctx}"),
-        Ok(_) => {}
-        Err(_) => {}
-    }
+    session_start_context(conn, payload, config, project)
+}
 
-    Ok(())
+/// Context injection, shaped per event (`[context.profiles]`) — non-fatal.
+fn session_start_context(
+    conn: &Connection,
+    payload: &HookPayload,
+    config: &NmemConfig,
+    project: &str,
+) -> Result<Option<String>, NmemError> {
+    let source = payload.source.as_deref().unwrap_or("startup");
+    let profile = crate::s5_config::resolve_context_profile(config, project, source);
+    let cwd = Some(std::path::Path::new(&payload.cwd)).filter(|p| p.is_absolute());
+    match s4_context::generate_profiled_context(conn, project, &profile, Some(&payload.session_id), None, cwd) {
+        Ok(ctx) if !ctx.is_empty() => Ok(Some(ctx)),
+        _ => Ok(None),
+    }
 }
 
 fn handle_user_prompt(
     conn: &Connection,
     payload: &HookPayload,
     filter: &SecretFilter,
+    config: &NmemConfig,
     project: &str,
-) -> Result<(), NmemError> {
+) -> Result<Option<String>, NmemError> {
     let prompt = match payload.prompt.as_deref() {
         Some(p) if !p.is_empty() && !p.starts_with("<system-reminder>") => p,
-        _ => return Ok(()),
+        _ => return Ok(None),
     };
 
     let ts = now_ts();
@@ -123,13 +263,155 @@ fn handle_user_prompt(
 
     // Truncate and filter secrets
     let truncated: String = prompt.chars().take(2000).collect();
-    let (filtered, _redacted) = filter.redact(&truncated);
+    let (filtered, redactions) = filter.redact_collect(&truncated);
 
+    let metadata = serde_json::json!({ "lang": crate::s1_lang::detect(&filtered) }).to_string();
     tx.execute(
-        "INSERT INTO prompts (session_id, timestamp, source, content) VALUES (?1, ?2, ?3, ?4)",
-        params![payload.session_id, ts, "user", filtered],
+        "INSERT INTO prompts (session_id, timestamp, source, content, metadata) VALUES (?1, ?2, ?3, ?4, ?5)",
+        params![payload.session_id, ts, "user", filtered, metadata],
     )?;
+    let prompt_id = tx.last_insert_rowid();
+    account_capture(&tx, config, &payload.session_id, crate::s3_capture::PROMPT, prompt.len(), filtered.len())?;
+
+    tx.commit()?;
+    if config.filter.quarantine && !redactions.is_empty() {
+        quarantine(conn, config, crate::s5_quarantine::PROMPT, prompt_id, &payload.session_id, &truncated, &redactions);
+    }
+
+    // Same work done before in this project — non-fatal
+    match crate::s3_learn::similar_episode(conn, project, &payload.session_id, &filtered, config.record.similar_prompt) {
+        Ok(found) => Ok(found.map(|episode| similar_work_note(&episode))),
+        Err(e) => {
+            log::debug!("similar episode check failed: {e}");
+            Ok(None)
+        }
+    }
+}
+
+/// `similar work done in session abc12345 (Oct 02): <summary> (succeeded)`
+fn similar_work_note(episode: &crate::s3_learn::SimilarEpisode) -> String {
+    let short_id: String = episode.session_id.chars().take(8).collect();
+    let summary: String = episode.summary.chars().take(120).collect();
+    let outcome = episode.outcome.as_deref().map(|o| format!(" ({o})")).unwrap_or_default();
+    format!(
+        "similar work done in session {short_id} ({}): {summary}{outcome}",
+        crate::timefmt::relative(episode.started_at)
+    )
+}
 
+/// Count an entry's size toward the session's capture totals. A session past
+/// `[record] session_alert_mb` is flagged by `nmem status --sessions`; the
+/// hook only notes the crossing at debug, since it must not write to stderr.
+fn account_capture(
+    conn: &Connection,
+    config: &NmemConfig,
+    session_id: &str,
+    obs_type: &str,
+    raw_bytes: usize,
+    stored_bytes: usize,
+) -> Result<(), NmemError> {
+    let alert_bytes = config.record.session_alert_mb * 1024 * 1024;
+    if crate::s3_capture::record(conn, session_id, obs_type, raw_bytes, stored_bytes, alert_bytes)? {
+        log::debug!(
+            "session {session_id} has stored over {} MB of observations; see nmem status --sessions",
+            config.record.session_alert_mb
+        );
+    }
+    Ok(())
+}
+
+/// Keep the pre-redaction text for review (`[filter] quarantine`). Called
+/// once the record is committed, so no entry points at a rolled-back row.
+fn quarantine(
+    conn: &Connection,
+    config: &NmemConfig,
+    target: &str,
+    target_id: i64,
+    session_id: &str,
+    original: &str,
+    redactions: &[crate::s5_filter::Redaction],
+) {
+    let days = config.filter.quarantine_days.unwrap_or(7);
+    crate::s5_quarantine::stash_for(conn, target, target_id, session_id, original, redactions, days);
+}
+
+/// Observation type a tool call is recorded as.
+fn obs_type_of(tool_name: &str, content: &str) -> &'static str {
+    if tool_name == "Bash" {
+        crate::s1_extract::classify_bash(content)
+    } else {
+        classify_tool(tool_name)
+    }
+}
+
+/// Note a tool call before it runs, so denied or blocked calls leave a trace
+/// (S1 intents). No classification or transcript scan — PreToolUse sits in
+/// front of every tool call.
+fn handle_pre_tool_use(
+    conn: &Connection,
+    payload: &HookPayload,
+    filter: &SecretFilter,
+    config: &NmemConfig,
+    project: &str,
+) -> Result<(), NmemError> {
+    let Some(tool_name) = payload.tool_name.as_deref() else {
+        return Ok(());
+    };
+    let empty = serde_json::Value::Object(serde_json::Map::new());
+    let tool_input = payload.tool_input.as_ref().unwrap_or(&empty);
+    let content = extract_content(tool_name, tool_input);
+    if !resolve_capture(config, project).records_type(obs_type_of(tool_name, &content)) {
+        return Ok(());
+    }
+    let ts = now_ts();
+    let tx = conn.unchecked_transaction()?;
+    ensure_session(&tx, &payload.session_id, project, ts)?;
+    let prompt_id = get_current_prompt_id(&tx, &payload.session_id)?;
+    let (content, _) = filter.redact(&content);
+    crate::s1_intent::record(
+        &tx,
+        &payload.session_id,
+        prompt_id,
+        payload.tool_use_id.as_deref(),
+        tool_name,
+        extract_file_path(tool_name, tool_input).map(|p| resolve_path(&p, &payload.cwd)).as_deref(),
+        &content,
+        ts,
+    )?;
+    tx.commit()?;
+    Ok(())
+}
+
+fn handle_heartbeat(
+    conn: &Connection,
+    payload: &HookPayload,
+    filter: &SecretFilter,
+    config: &NmemConfig,
+    project: &str,
+) -> Result<(), NmemError> {
+    if !resolve_capture(config, project).records_type(crate::s1_heartbeat::OBS_TYPE) {
+        return Ok(());
+    }
+    let ts = now_ts();
+    let tx = conn.unchecked_transaction()?;
+    ensure_session(&tx, &payload.session_id, project, ts)?;
+    let prompt_id = get_current_prompt_id(&tx, &payload.session_id)?;
+    let message = payload.message.as_deref().map(|m| filter.redact(m).0);
+    let obs_id = crate::s1_heartbeat::record(
+        &tx,
+        &payload.session_id,
+        prompt_id,
+        payload.tool_use_id.as_deref(),
+        message.as_deref(),
+        normalize_cwd(&payload.cwd).as_deref(),
+        ts,
+    )?;
+    let stored: usize =
+        tx.query_row("SELECT length(content) + COALESCE(length(metadata), 0) FROM observations WHERE id = ?1", [obs_id], |r| {
+            r.get::<_, i64>(0)
+        })? as usize;
+    let raw = payload.message.as_ref().map_or(0, String::len);
+    account_capture(&tx, config, &payload.session_id, crate::s1_heartbeat::OBS_TYPE, raw, stored)?;
     tx.commit()?;
     Ok(())
 }
@@ -138,8 +420,10 @@ fn handle_post_tool_use(
     conn: &Connection,
     payload: &HookPayload,
     filter: &SecretFilter,
+    config: &NmemConfig,
     source_event: &str,
     project: &str,
+    deadline: Option<Instant>,
 ) -> Result<(), NmemError> {
     let tool_name = match payload.tool_name.as_deref() {
         Some(n) => n,
@@ -151,6 +435,12 @@ fn handle_post_tool_use(
         .cloned()
         .unwrap_or(serde_json::Value::Object(serde_json::Map::new()));
 
+    let content = extract_content(tool_name, &tool_input);
+    let obs_type = obs_type_of(tool_name, &content);
+    if !resolve_capture(config, project).records_type(obs_type) {
+        return Ok(());
+    }
+
     let ts = now_ts();
     let tx = conn.unchecked_transaction()?;
 
@@ -163,16 +453,13 @@ fn handle_post_tool_use(
         get_current_prompt_id(&tx, &payload.session_id)?
     };
 
-    let content = extract_content(tool_name, &tool_input);
-    let obs_type = if tool_name == "Bash" {
-        crate::s1_extract::classify_bash(&content)
-    } else {
-        classify_tool(tool_name)
-    };
-    let file_path = extract_file_path(tool_name, &tool_input);
+    // Relative paths in tool input resolve against the hook's cwd
+    let file_path = extract_file_path(tool_name, &tool_input).map(|p| resolve_path(&p, &payload.cwd));
+    let cwd = normalize_cwd(&payload.cwd);
 
     // Filter secrets from content
-    let (filtered_content, content_redacted) = filter.redact(&content);
+    let (filtered_content, redactions) = filter.redact_collect(&content);
+    let content_redacted = !redactions.is_empty();
 
     // Build metadata and filter it
     let is_failure = source_event == "PostToolUseFailure";
@@ -188,6 +475,11 @@ fn handle_post_tool_use(
         other => other.to_string(),
     });
 
+    // Failures get a taxonomy label (S2) for cross-session grouping
+    let error_class = is_failure.then(|| {
+        crate::s2_error::classify_error(&filtered_content, response_str.as_deref().unwrap_or(""))
+    });
+
     if is_failure {
         meta_obj.insert("failed".into(), serde_json::Value::Bool(true));
         if let Some(ref resp) = response_str {
@@ -223,46 +515,71 @@ fn handle_post_tool_use(
         Some(serde_json::to_string(&metadata)?)
     };
 
-    // Classify phase (think/act) — non-fatal, None if model not loaded
-    let phase_result = s2_classify::classify(&filtered_content);
-    let phase = phase_result.as_ref().map(|p| p.label);
-
-    // Register classifier run for provenance tracking
-    let classifier_run_id = phase_result
-        .as_ref()
-        .and_then(|p| {
-            s2_classify::ensure_classifier_run(&tx, "think-act", p.model_hash, None, None, None).ok()
-        });
-
-    // Classify scope (converge/diverge) — non-fatal, None if model not loaded
-    let scope_result = s2_scope::classify_scope(&filtered_content);
-    let scope = scope_result.as_ref().map(|s| s.label);
-    let scope_run_id = scope_result.as_ref().and_then(|s| {
-        s2_classify::ensure_classifier_run(&tx, "converge-diverge", s.model_hash, None, None, None)
-            .ok()
-    });
+    // Failing test names (S2) for the flaky/recurring test memory
+    let failing_tests: Vec<(String, &str)> = if is_failure && obs_type == "command" {
+        crate::s2_test_failure::parse_failing_tests(response_str.as_deref().unwrap_or(""))
+            .into_iter()
+            .map(|(name, framework)| (filter.redact(&name).0, framework))
+            .collect()
+    } else {
+        Vec::new()
+    };
 
-    // Classify locus (internal/external) — non-fatal
-    let locus_result = s2_locus::classify_locus(&filtered_content);
-    let locus = locus_result.as_ref().map(|r| r.label);
-    let locus_run_id = locus_result.as_ref().and_then(|r| {
-        s2_classify::ensure_classifier_run(&tx, "internal-external", r.model_hash, None, None, None).ok()
-    });
+    // A command run again back to back only bumps the previous one's count
+    if config.record.collapse_repeats
+        && obs_type == "command"
+        && let Some(obs_id) = crate::s1_repeat::collapse(
+            &tx,
+            &payload.session_id,
+            prompt_id,
+            &filtered_content,
+            metadata_str.as_deref(),
+            is_failure,
+            ts,
+        )?
+    {
+        let intent = crate::s1_intent::resolve(&tx, &payload.session_id, payload.tool_use_id.as_deref(), tool_name, obs_id, ts)?;
+        crate::s1_heartbeat::link(&tx, &payload.session_id, payload.tool_use_id.as_deref(), intent, obs_id)?;
+        let raw_bytes = tool_input.to_string().len() + response_str.as_ref().map_or(0, String::len);
+        account_capture(&tx, config, &payload.session_id, obs_type, raw_bytes, 0)?;
+        crate::s2_test_failure::record(&tx, project, &payload.session_id, obs_id, ts, &failing_tests)?;
+        tx.commit()?;
+        return Ok(());
+    }
 
-    // Classify novelty (routine/novel) — non-fatal
-    let novelty_result = s2_novelty::classify_novelty(&filtered_content);
-    let novelty = novelty_result.as_ref().map(|r| r.label);
-    let novelty_run_id = novelty_result.as_ref().and_then(|r| {
-        s2_classify::ensure_classifier_run(&tx, "routine-novel", r.model_hash, None, None, None).ok()
-    });
+    // Classify all four dimensions within the hook's latency budget; if the
+    // models don't answer in time the observation is stored unlabelled and
+    // classification is queued (S3 pending work).
+    let classified = {
+        let text = filtered_content.clone();
+        crate::s3_pending::within_budget(deadline, move || classify_text(&text))
+    };
+    let spilled = classified.is_none();
+    let Labels {
+        phase,
+        classifier_run_id,
+        phase_confidence,
+        scope,
+        scope_run_id,
+        scope_confidence,
+        locus,
+        locus_run_id,
+        locus_confidence,
+        novelty,
+        novelty_run_id,
+        novelty_confidence,
+    } = classified.map(|c| c.register(&tx)).unwrap_or_default();
 
     // Friction is now computed at episode level (S4), not per-observation
     let friction: Option<&str> = None;
     let friction_run_id: Option<i64> = None;
 
+    let (mcp_server, mcp_tool) = crate::s1_extract::parse_mcp_tool(tool_name).unzip();
+    let (head, chunks) = crate::s1_chunk::split(&filtered_content, config.record.chunk_chars);
+
     tx.execute(
-        "INSERT INTO observations (session_id, prompt_id, timestamp, obs_type, source_event, tool_name, file_path, content, metadata, phase, classifier_run_id, scope, scope_run_id, locus, locus_run_id, novelty, novelty_run_id, friction, friction_run_id)
-         VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12, ?13, ?14, ?15, ?16, ?17, ?18, ?19)",
+        "INSERT INTO observations (session_id, prompt_id, timestamp, obs_type, source_event, tool_name, file_path, content, metadata, phase, classifier_run_id, scope, scope_run_id, locus, locus_run_id, novelty, novelty_run_id, friction, friction_run_id, error_class, mcp_server, mcp_tool, cwd, phase_confidence, scope_confidence, locus_confidence, novelty_confidence)
+         VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12, ?13, ?14, ?15, ?16, ?17, ?18, ?19, ?20, ?21, ?22, ?23, ?24, ?25, ?26, ?27)",
         params![
             payload.session_id,
             prompt_id,
@@ -271,7 +588,7 @@ fn handle_post_tool_use(
             source_event,
             tool_name,
             file_path,
-            filtered_content,
+            head,
             metadata_str,
             phase,
             classifier_run_id,
@@ -283,10 +600,87 @@ fn handle_post_tool_use(
             novelty_run_id,
             friction,
             friction_run_id,
+            error_class,
+            mcp_server,
+            mcp_tool,
+            cwd,
+            phase_confidence,
+            scope_confidence,
+            locus_confidence,
+            novelty_confidence,
         ],
     )?;
 
+    let obs_id = tx.last_insert_rowid();
+    crate::s1_chunk::store(&tx, obs_id, &chunks)?;
+    crate::s1_minhash::store(&tx, obs_id, &filtered_content)?;
+    let intent = crate::s1_intent::resolve(&tx, &payload.session_id, payload.tool_use_id.as_deref(), tool_name, obs_id, ts)?;
+    crate::s1_heartbeat::link(&tx, &payload.session_id, payload.tool_use_id.as_deref(), intent, obs_id)?;
+    let raw_bytes = tool_input.to_string().len() + response_str.as_ref().map_or(0, String::len);
+    let stored_bytes = filtered_content.len() + metadata_str.as_ref().map_or(0, String::len);
+    account_capture(&tx, config, &payload.session_id, obs_type, raw_bytes, stored_bytes)?;
+    if spilled {
+        crate::s3_pending::enqueue(&tx, crate::s3_pending::CLASSIFY, Some(obs_id), None, ts)?;
+    }
+    crate::s2_test_failure::record(&tx, project, &payload.session_id, obs_id, ts, &failing_tests)?;
+
+    // What the file looked like the first time this session read it
+    if obs_type == "file_read" && !is_failure
+        && let Some(ref path) = file_path
+        && let Err(e) = crate::s1_snapshot::capture_first_read(
+            &tx, filter, &payload.session_id, obs_id, path, &payload.cwd, ts,
+        )
+    {
+        log::debug!("file snapshot failed (non-fatal): {e}");
+    }
+
+    if obs_type == "plan" && !is_failure {
+        crate::s1_plan::record(&tx, filter, &payload.session_id, obs_id, tool_name, &tool_input, ts)?;
+    }
+
+    // A file removed and a same-named one written next is a rename
+    if !is_failure && (obs_type == "file_write" || tool_name == "Bash") {
+        crate::s1_alias::record_delete_create(&tx, &payload.session_id, obs_id, ts)?;
+    }
+
+    // A success after an unchanged failure means the failure was flaky
+    if !is_failure && tool_name == "Bash" {
+        crate::s2_error::mark_flaky(&tx, &payload.session_id, &filtered_content, obs_id)?;
+
+        // Rename tracking — explicit moves, then git's similarity index on commit
+        for (old, new) in crate::s1_alias::parse_move_command(&filtered_content, &payload.cwd) {
+            crate::s1_alias::record_alias(&tx, &old, &new, "command", ts)?;
+        }
+        if obs_type == "git_commit" {
+            let cwd = payload.cwd.clone();
+            let renames = crate::s3_pending::within_budget(deadline, move || {
+                crate::s1_git::head_renames(Path::new(&cwd))
+            });
+            match renames {
+                Some(Ok(renames)) => {
+                    for (old, new) in renames {
+                        crate::s1_alias::record_alias(&tx, &old, &new, "git", ts)?;
+                    }
+                }
+                Some(Err(e)) => log::debug!("rename detection skipped: {e}"),
+                None => crate::s3_pending::enqueue(
+                    &tx,
+                    crate::s3_pending::HEAD_RENAMES,
+                    Some(obs_id),
+                    Some(&payload.cwd),
+                    ts,
+                )?,
+            }
+        }
+    }
+
+    // Auto-pin after flaky relabelling, so a flaky retry is not a "first success"
+    crate::s3_autopin::evaluate_observation(&tx, &config.auto_pin, obs_id)?;
+
     tx.commit()?;
+    if config.filter.quarantine && content_redacted {
+        quarantine(conn, config, crate::s5_quarantine::OBSERVATION, obs_id, &payload.session_id, &content, &redactions);
+    }
 
     // Stream to VictoriaLogs — non-fatal, fire-and-forget
     stream_observation_to_logs(
@@ -416,13 +810,28 @@ fn build_log_message(
     }
 }
 
-fn handle_stop(conn: &Connection, payload: &HookPayload, _config: &NmemConfig, db_path: &Path) -> Result<(), NmemError> {
+fn handle_stop(
+    conn: &Connection,
+    payload: &HookPayload,
+    config: &NmemConfig,
+    filter: &SecretFilter,
+    db_path: &Path,
+    defer_maintain: bool,
+) -> Result<(), NmemError> {
     let ts = now_ts();
     let tx = conn.unchecked_transaction()?;
 
-    // Final transcript scan
+    // Final transcript scan, then what the assistant reported for the turn
     if let Some(tp) = payload.transcript_path.as_deref() {
         scan_transcript(&tx, &payload.session_id, tp, ts)?;
+        let known: bool = tx.query_row(
+            "SELECT EXISTS(SELECT 1 FROM sessions WHERE id = ?1)",
+            params![payload.session_id],
+            |r| r.get(0),
+        )?;
+        if known {
+            capture_response(&tx, filter, &payload.session_id, tp, config.record.response_chars, ts)?;
+        }
     }
 
     // Compute session signature and obs count
@@ -451,6 +860,9 @@ fn handle_stop(conn: &Connection, payload: &HookPayload, _config: &NmemConfig, d
         params![ts, sig_json, payload.session_id],
     )?;
 
+    // Calls announced but never completed: denied, blocked, or interrupted
+    crate::s1_intent::close_session(&tx, &payload.session_id, ts)?;
+
     // Sentinel summary for empty sessions (< 3 observations) — unblocks S3 sweep
     if obs_count < 3 {
         crate::s1_4_summarize::write_sentinel_summary(&tx, &payload.session_id)?;
@@ -460,7 +872,7 @@ fn handle_stop(conn: &Connection, payload: &HookPayload, _config: &NmemConfig, d
 
     // Spawn deferred maintenance as a detached background process
     // (only if there's enough data to summarize)
-    if obs_count >= 3 {
+    if defer_maintain && obs_count >= 3 {
         spawn_deferred_maintain(&payload.session_id, db_path);
     }
 
@@ -491,45 +903,158 @@ fn spawn_deferred_maintain(session_id: &str, db_path: &Path) {
 
 /// Hook entry point. IMPORTANT: this function and everything it calls must
 /// never write to stderr — Claude Code treats any stderr from hooks as an error.
-pub fn handle_record(db_path: &Path) -> Result<(), NmemError> {
-    let start = std::time::Instant::now();
-
+/// With `heartbeat`, the payload is recorded as a `Heartbeat` event whatever
+/// its `hook_event_name`.
+pub fn handle_record(db_path: &Path, heartbeat: bool) -> Result<(), NmemError> {
     let mut input = String::new();
     std::io::stdin().read_to_string(&mut input)?;
+    if heartbeat {
+        let mut payload: serde_json::Value = serde_json::from_str(&input)?;
+        if let Some(obj) = payload.as_object_mut() {
+            obj.insert("hook_event_name".into(), crate::s1_heartbeat::EVENT.into());
+        }
+        input = payload.to_string();
+    }
+    let config = load_config().unwrap_or_default();
+    if let Some(ctx) = record_event(db_path, &input, &config, true)? {
+        print!("{ctx}");
+    }
+    Ok(())
+}
+
+/// Check the event against the configured context packs' triggers and hand
+/// over what is queued for the session: plain text for UserPromptSubmit, a
+/// `hookSpecificOutput` JSON for PostToolUse.
+fn pack_advisory(
+    conn: &Connection,
+    payload: &HookPayload,
+    config: &NmemConfig,
+    project: &str,
+) -> Result<Option<String>, NmemError> {
+    let now = now_ts();
+    let event = payload.hook_event_name.as_str();
+    match event {
+        "UserPromptSubmit" => {
+            if let Some(prompt) = &payload.prompt {
+                crate::s4_pack::check_prompt(conn, config, &payload.session_id, project, prompt, now)?;
+            }
+        }
+        "PostToolUse" | "PostToolUseFailure" => {
+            let file_path = payload
+                .tool_name
+                .as_deref()
+                .zip(payload.tool_input.as_ref())
+                .and_then(|(name, input)| extract_file_path(name, input))
+                .map(|p| resolve_path(&p, &payload.cwd));
+            if let Some(path) = file_path {
+                crate::s4_pack::check_file(conn, config, &payload.session_id, project, &path, now)?;
+            }
+        }
+        _ => return Ok(None),
+    }
+    let delivered = crate::s4_pack::take_queued(conn, config, &payload.session_id, Some(project), now)?;
+    if delivered.is_empty() {
+        return Ok(None);
+    }
+    let text = delivered.iter().map(|d| d.markdown.as_str()).collect::<Vec<_>>().join("\n");
+    if event == "UserPromptSubmit" {
+        return Ok(Some(text));
+    }
+    Ok(Some(
+        serde_json::json!({
+            "hookSpecificOutput": { "hookEventName": event, "additionalContext": text }
+        })
+        .to_string(),
+    ))
+}
 
-    let payload: HookPayload = serde_json::from_str(&input)?;
+/// Record one hook event (its JSON payload) under `config`. Returns the
+/// context SessionStart injects, or context packs a prompt or tool call
+/// triggered. With `defer_maintain`, Stop spawns the
+/// post-session maintenance in the background; otherwise the caller runs it.
+pub fn record_event(
+    db_path: &Path,
+    input: &str,
+    config: &NmemConfig,
+    defer_maintain: bool,
+) -> Result<Option<String>, NmemError> {
+    let start = Instant::now();
+    let payload: HookPayload = serde_json::from_str(input)?;
 
     if payload.session_id.is_empty() {
-        return Ok(());
+        return Ok(None);
     }
 
-    // Load config and create project-aware filter
-    let config = load_config().unwrap_or_default();
+    // Project-aware filter
     let project = derive_project_with_strategy(&payload.cwd, config.project.strategy);
-    let params = resolve_filter_params(&config, Some(&project));
+    let params = resolve_filter_params(config, Some(&project));
     let filter = SecretFilter::with_params(params);
+    let capture = resolve_capture(config, &project);
+    let budget = config.record.latency_budget_ms;
+    let deadline = (budget > 0).then(|| start + std::time::Duration::from_millis(budget));
 
     // Fresh connection on each retry — avoids stale transaction state after BUSY
     let result = retry_on_busy(|| {
         let conn = open_db(db_path)?;
-        match payload.hook_event_name.as_str() {
-            "SessionStart" => handle_session_start(&conn, &payload, &config, &project),
-            "UserPromptSubmit" => handle_user_prompt(&conn, &payload, &filter, &project),
-            "PostToolUse" => handle_post_tool_use(&conn, &payload, &filter, "PostToolUse", &project),
-            "PostToolUseFailure" => handle_post_tool_use(&conn, &payload, &filter, "PostToolUseFailure", &project),
-            "Stop" => handle_stop(&conn, &payload, &config, db_path),
-            _ => Ok(()),
+        // A capture pause (`nmem pause`) or a disabled event drops it; hooks
+        // still succeed
+        if !capture.records_event(&payload.hook_event_name)
+            || crate::s3_pause::active(&conn, &project, now_ts())?.is_some()
+        {
+            return match payload.hook_event_name.as_str() {
+                "SessionStart" => session_start_context(&conn, &payload, config, &project),
+                _ => Ok(None),
+            };
+        }
+        let result = match payload.hook_event_name.as_str() {
+            "SessionStart" => handle_session_start(&conn, &payload, config, &project),
+            "UserPromptSubmit" => handle_user_prompt(&conn, &payload, &filter, config, &project),
+            "PreToolUse" => handle_pre_tool_use(&conn, &payload, &filter, config, &project).map(|()| None),
+            "PostToolUse" => handle_post_tool_use(&conn, &payload, &filter, config, "PostToolUse", &project, deadline)
+                .map(|()| None),
+            "PostToolUseFailure" => {
+                handle_post_tool_use(&conn, &payload, &filter, config, "PostToolUseFailure", &project, deadline)
+                    .map(|()| None)
+            }
+            "Stop" => handle_stop(&conn, &payload, config, &filter, db_path, defer_maintain).map(|()| None),
+            crate::s1_heartbeat::EVENT => handle_heartbeat(&conn, &payload, &filter, config, &project).map(|()| None),
+            _ => Ok(None),
+        };
+        // Context packs triggered by this event — non-fatal
+        let result = match result {
+            Ok(context) if !config.packs.is_empty() && payload.hook_event_name != "SessionStart" => {
+                let packs = pack_advisory(&conn, &payload, config, &project).unwrap_or_else(|e| {
+                    log::debug!("context pack check failed: {e}");
+                    None
+                });
+                // Only UserPromptSubmit has both, and both are plain text
+                Ok(match (context, packs) {
+                    (Some(note), Some(packs)) => Some(format!("{note}\n{packs}")),
+                    (note, packs) => note.or(packs),
+                })
+            }
+            other => other,
+        };
+        // Spend what's left of the budget on earlier spilled work — non-fatal
+        if result.is_ok()
+            && deadline.is_some()
+            && let Err(e) = crate::s3_pending::drain(&conn, deadline)
+        {
+            log::debug!("pending work drain failed: {e}");
         }
+        result
     });
 
     // Metrics export — non-fatal
+    #[cfg(feature = "cli")]
     if config.metrics.enabled {
-        record_metrics(&config, &payload, &project, result.is_ok(), start);
+        record_metrics(config, &payload, &project, result.is_ok(), start);
     }
 
     result
 }
 
+#[cfg(feature = "cli")]
 fn record_metrics(
     config: &NmemConfig,
     payload: &HookPayload,
@@ -537,20 +1062,8 @@ fn record_metrics(
     success: bool,
     start: std::time::Instant,
 ) {
-    let rt = match tokio::runtime::Builder::new_multi_thread()
-        .worker_threads(1)
-        .enable_all()
-        .build()
-    {
-        Ok(rt) => rt,
-        Err(_) => return,
-    };
-
-    let provider = match rt.block_on(async {
-        crate::metrics::init_meter_provider(&config.metrics)
-    }) {
-        Some(p) => p,
-        None => return,
+    let Some(_exporter) = crate::metrics::Exporter::start(&config.metrics) else {
+        return;
     };
 
     let meter = opentelemetry::global::meter("nmem");
@@ -596,6 +1109,4 @@ fn record_metrics(
         .f64_histogram("nmem_record_duration_seconds")
         .build()
         .record(start.elapsed().as_secs_f64(), &[]);
-
-    let _ = provider.shutdown();
 }
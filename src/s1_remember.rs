@@ -0,0 +1,160 @@
+//! S1 Operations — memories an agent writes on purpose.
+//!
+//! Observations are what nmem saw; markers are waypoints in a session's
+//! stream. A memory is a keyed slot the agent fills deliberately ("the
+//! staging DB is read-only", "run tests with --test-threads=1") and expects
+//! back: `remember` writes or overwrites `key`, `forget` clears it. Scope is
+//! one project or global (`project IS NULL`); a `ttl` lets a note lapse on
+//! its own. Context injection lists live memories first, ahead of every
+//! other section, within `[context] memory_chars`.
+
+use crate::NmemError;
+use rusqlite::{Connection, params};
+use serde::Serialize;
+
+/// Longest memory key.
+pub const MAX_KEY_CHARS: usize = 100;
+
+#[derive(Debug, Serialize, PartialEq)]
+pub struct Memory {
+    pub key: String,
+    /// `None` for a global memory.
+    pub project: Option<String>,
+    pub content: String,
+    pub created_at: i64,
+    pub updated_at: i64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub expires_at: Option<i64>,
+}
+
+/// What `remember` did.
+#[derive(Debug, Serialize)]
+pub struct Remembered {
+    #[serde(flatten)]
+    pub memory: Memory,
+    /// `created` or `updated`.
+    pub status: &'static str,
+}
+
+/// Write `content` under `key` in `project` (global when `None`),
+/// replacing what was there. Expired memories are dropped on the way.
+pub fn remember(
+    conn: &Connection,
+    project: Option<&str>,
+    key: &str,
+    content: &str,
+    ttl_secs: Option<i64>,
+    now: i64,
+) -> Result<Remembered, NmemError> {
+    let key = key.trim();
+    if key.is_empty() {
+        return Err(NmemError::Config("memory key must not be empty".into()));
+    }
+    let expires_at = ttl_secs.map(|ttl| now.saturating_add(ttl));
+    let tx = conn.unchecked_transaction()?;
+    tx.execute("DELETE FROM memories WHERE expires_at IS NOT NULL AND expires_at <= ?1", params![now])?;
+    let updated = tx.execute(
+        "UPDATE memories SET content = ?3, updated_at = ?4, expires_at = ?5
+         WHERE project IS ?1 AND key = ?2",
+        params![project, key, content, now, expires_at],
+    )?;
+    if updated == 0 {
+        tx.execute(
+            "INSERT INTO memories (project, key, content, created_at, updated_at, expires_at)
+             VALUES (?1, ?2, ?3, ?4, ?4, ?5)",
+            params![project, key, content, now, expires_at],
+        )?;
+    }
+    let created_at: i64 = tx.query_row(
+        "SELECT created_at FROM memories WHERE project IS ?1 AND key = ?2",
+        params![project, key],
+        |r| r.get(0),
+    )?;
+    tx.commit()?;
+    Ok(Remembered {
+        memory: Memory {
+            key: key.to_string(),
+            project: project.map(String::from),
+            content: content.to_string(),
+            created_at,
+            updated_at: now,
+            expires_at,
+        },
+        status: if updated == 0 { "created" } else { "updated" },
+    })
+}
+
+/// Clear `key` in `project` (global when `None`). Returns whether it existed.
+pub fn forget(conn: &Connection, project: Option<&str>, key: &str) -> Result<bool, NmemError> {
+    let removed = conn.execute("DELETE FROM memories WHERE project IS ?1 AND key = ?2", params![project, key.trim()])?;
+    Ok(removed > 0)
+}
+
+/// Memories live at `at` for `project`: its own, then global ones, most
+/// recently written first within each.
+pub fn active(conn: &Connection, project: &str, at: i64) -> Result<Vec<Memory>, NmemError> {
+    let mut stmt = conn.prepare_cached(
+        "SELECT key, project, content, created_at, updated_at, expires_at FROM memories
+         WHERE (project = ?1 OR project IS NULL)
+           AND created_at <= ?2 AND (expires_at IS NULL OR expires_at > ?2)
+         ORDER BY project IS NULL, updated_at DESC, id DESC",
+    )?;
+    let rows = stmt
+        .query_map(params![project, at], |r| {
+            Ok(Memory {
+                key: r.get(0)?,
+                project: r.get(1)?,
+                content: r.get(2)?,
+                created_at: r.get(3)?,
+                updated_at: r.get(4)?,
+                expires_at: r.get(5)?,
+            })
+        })?
+        .collect::<Result<_, _>>()?;
+    Ok(rows)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn setup() -> Connection {
+        let mut conn = Connection::open_in_memory().unwrap();
+        crate::schema::MIGRATIONS.to_latest(&mut conn).unwrap();
+        conn
+    }
+
+    #[test]
+    fn keys_are_scoped_and_overwritten() {
+        let conn = setup();
+        let first = remember(&conn, Some("api"), "test-cmd", "cargo test -- --test-threads=1", None, 100).unwrap();
+        assert_eq!(first.status, "created");
+        let again = remember(&conn, Some("api"), " test-cmd ", "cargo nextest run", None, 200).unwrap();
+        assert_eq!((again.status, again.memory.created_at), ("updated", 100));
+        remember(&conn, None, "test-cmd", "make test", None, 150).unwrap();
+        remember(&conn, Some("web"), "staging", "read-only", None, 150).unwrap();
+
+        let live = active(&conn, "api", 300).unwrap();
+        let seen: Vec<(Option<&str>, &str)> = live.iter().map(|m| (m.project.as_deref(), m.content.as_str())).collect();
+        assert_eq!(seen, vec![(Some("api"), "cargo nextest run"), (None, "make test")], "project first, then global");
+
+        assert!(forget(&conn, None, "test-cmd").unwrap());
+        assert!(!forget(&conn, None, "test-cmd").unwrap());
+        assert_eq!(active(&conn, "api", 300).unwrap().len(), 1);
+        assert!(remember(&conn, None, "  ", "x", None, 300).is_err());
+    }
+
+    #[test]
+    fn ttl_lapses() {
+        let conn = setup();
+        remember(&conn, Some("api"), "deploy-freeze", "no deploys until Friday", Some(60), 100).unwrap();
+        assert_eq!(active(&conn, "api", 159).unwrap().len(), 1);
+        assert!(active(&conn, "api", 160).unwrap().is_empty());
+        assert!(active(&conn, "api", 99).unwrap().is_empty(), "not yet written");
+
+        // Writing drops expired slots
+        remember(&conn, Some("api"), "other", "x", None, 200).unwrap();
+        let left: i64 = conn.query_row("SELECT COUNT(*) FROM memories", [], |r| r.get(0)).unwrap();
+        assert_eq!(left, 1);
+    }
+}
@@ -0,0 +1,142 @@
+//! S1 Operations — run-length compression of repeated commands.
+//!
+//! Iterating on a fix, an agent runs `cargo test` ten times in a row; ten
+//! identical observations bury the rest of the session in `session_trace`
+//! and inflate the episode's `obs_count`. A command identical to the
+//! session's previous observation — same prompt, same content, same
+//! outcome — is folded into it instead: `repeat_count` goes up,
+//! `last_timestamp` moves to the newest run, whose metadata (a failure's
+//! output) is merged over the kept one's so pins survive, and the run's
+//! own time lands in `observation_repeats`, so `timeline` can expand the
+//! runs again (`expand_repeats`).
+//! `[record] collapse_repeats = false` stores every run.
+
+use crate::NmemError;
+use rusqlite::{Connection, OptionalExtension, params};
+use serde::Serialize;
+
+/// How often an observation ran back to back.
+#[derive(Debug, Clone, Serialize, PartialEq)]
+pub struct Repeats {
+    pub count: i64,
+    pub first_timestamp: i64,
+    pub last_timestamp: i64,
+    /// Which run this entry is (1-based), when `timeline` expands them.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub run: Option<i64>,
+}
+
+/// Fold a command run into the session's previous observation when that
+/// is the same command, under the same prompt, with the same outcome.
+/// Returns the observation it was folded into.
+pub fn collapse(
+    conn: &Connection,
+    session_id: &str,
+    prompt_id: Option<i64>,
+    content: &str,
+    metadata: Option<&str>,
+    failed: bool,
+    ts: i64,
+) -> Result<Option<i64>, NmemError> {
+    let previous: Option<(i64, String, Option<i64>, String, bool)> = conn
+        .query_row(
+            concat!(
+                "SELECT o.id, o.obs_type, o.prompt_id, ",
+                crate::s1_chunk::full_content_sql!(),
+                ", COALESCE(json_extract(o.metadata, '$.failed'), 0) = 1
+                 FROM observations o WHERE o.session_id = ?1 ORDER BY o.id DESC LIMIT 1"
+            ),
+            params![session_id],
+            |r| Ok((r.get(0)?, r.get(1)?, r.get(2)?, r.get(3)?, r.get(4)?)),
+        )
+        .optional()?;
+    let Some((id, obs_type, previous_prompt, previous_content, previous_failed)) = previous else {
+        return Ok(None);
+    };
+    if obs_type != "command" || previous_prompt != prompt_id || previous_content != content || previous_failed != failed {
+        return Ok(None);
+    }
+    conn.execute(
+        "UPDATE observations SET repeat_count = repeat_count + 1, last_timestamp = ?2,
+                metadata = CASE WHEN ?3 IS NULL THEN metadata ELSE json_patch(COALESCE(metadata, '{}'), ?3) END
+         WHERE id = ?1",
+        params![id, ts, metadata],
+    )?;
+    conn.execute("INSERT INTO observation_repeats (obs_id, timestamp) VALUES (?1, ?2)", params![id, ts])?;
+    Ok(Some(id))
+}
+
+/// The runs folded into `obs_id`, or `None` if it ran once.
+pub fn repeats(conn: &Connection, obs_id: i64) -> Result<Option<Repeats>, NmemError> {
+    let row: Option<(i64, i64, Option<i64>)> = conn
+        .prepare_cached("SELECT repeat_count, timestamp, last_timestamp FROM observations WHERE id = ?1")?
+        .query_row([obs_id], |r| Ok((r.get(0)?, r.get(1)?, r.get(2)?)))
+        .optional()?;
+    Ok(row.filter(|(count, _, _)| *count > 1).map(|(count, first, last)| Repeats {
+        count,
+        first_timestamp: first,
+        last_timestamp: last.unwrap_or(first),
+        run: None,
+    }))
+}
+
+/// When each run of `obs_id` happened, the first included.
+pub fn run_timestamps(conn: &Connection, obs_id: i64) -> Result<Vec<i64>, NmemError> {
+    let mut stmt = conn.prepare_cached(
+        "SELECT timestamp FROM observations WHERE id = ?1
+         UNION ALL
+         SELECT timestamp FROM (SELECT timestamp FROM observation_repeats WHERE obs_id = ?1 ORDER BY timestamp, rowid)",
+    )?;
+    let rows = stmt.query_map([obs_id], |r| r.get(0))?.collect::<Result<_, _>>()?;
+    Ok(rows)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn setup() -> Connection {
+        let mut conn = Connection::open_in_memory().unwrap();
+        crate::schema::MIGRATIONS.to_latest(&mut conn).unwrap();
+        conn.execute_batch(
+            "INSERT INTO sessions (id, project, started_at) VALUES ('s1', 'p', 0);
+             INSERT INTO prompts (id, session_id, timestamp, source, content) VALUES (1, 's1', 0, 'user', 'fix it'), (2, 's1', 50, 'user', 'again');
+             INSERT INTO observations (id, session_id, prompt_id, timestamp, obs_type, source_event, content, metadata)
+             VALUES (1, 's1', 1, 10, 'command', 'PostToolUse', 'cargo test', '{\"failed\":true}');",
+        )
+        .unwrap();
+        conn
+    }
+
+    #[test]
+    fn folds_identical_runs_under_one_prompt() {
+        let conn = setup();
+        let failed = Some(r#"{"failed":true,"response":"2 failed"}"#);
+        assert_eq!(collapse(&conn, "s1", Some(1), "cargo test", failed, true, 20).unwrap(), Some(1));
+        assert_eq!(collapse(&conn, "s1", Some(1), "cargo test", failed, true, 30).unwrap(), Some(1));
+        // A pass, another command, or another prompt breaks the run
+        assert_eq!(collapse(&conn, "s1", Some(1), "cargo test", None, false, 40).unwrap(), None);
+        assert_eq!(collapse(&conn, "s1", Some(1), "cargo build", failed, true, 40).unwrap(), None);
+        assert_eq!(collapse(&conn, "s1", Some(2), "cargo test", failed, true, 60).unwrap(), None);
+
+        let r = repeats(&conn, 1).unwrap().unwrap();
+        assert_eq!((r.count, r.first_timestamp, r.last_timestamp), (3, 10, 30));
+        assert_eq!(run_timestamps(&conn, 1).unwrap(), vec![10, 20, 30]);
+        let metadata: String = conn.query_row("SELECT metadata FROM observations WHERE id = 1", [], |r| r.get(0)).unwrap();
+        assert!(metadata.contains("2 failed"), "newest run's output is kept");
+    }
+
+    #[test]
+    fn only_the_latest_observation_can_absorb_a_run() {
+        let conn = setup();
+        conn.execute(
+            "INSERT INTO observations (session_id, prompt_id, timestamp, obs_type, source_event, content)
+             VALUES ('s1', 1, 15, 'file_edit', 'PostToolUse', 'Edit src/lib.rs')",
+            [],
+        )
+        .unwrap();
+        assert_eq!(collapse(&conn, "s1", Some(1), "cargo test", Some(r#"{"failed":true}"#), true, 20).unwrap(), None);
+        assert_eq!(repeats(&conn, 1).unwrap(), None);
+        assert_eq!(run_timestamps(&conn, 1).unwrap(), vec![10]);
+    }
+}
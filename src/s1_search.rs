@@ -1,18 +1,25 @@
+#[cfg(feature = "cli")]
 use crate::cli::SearchArgs;
 use crate::db::open_db_readonly;
+use crate::s5_config::{load_config, resolve_recency_half_life};
 use crate::NmemError;
 use serde::Serialize;
 use std::path::Path;
 
-#[derive(Serialize)]
-struct SearchResult {
-    id: i64,
-    timestamp: i64,
-    obs_type: String,
-    content_preview: String,
-    file_path: Option<String>,
-    session_id: String,
-    is_pinned: bool,
+/// One full-text match, as listed by `nmem search` and `NmemStore::search`.
+#[derive(Debug, Serialize)]
+pub struct SearchResult {
+    pub id: i64,
+    pub timestamp: i64,
+    pub obs_type: String,
+    pub content_preview: String,
+    pub file_path: Option<String>,
+    pub session_id: String,
+    pub is_pinned: bool,
+    /// Set when the best match was in a stored chunk of long content; the
+    /// preview is then a snippet of that chunk.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub chunk: Option<crate::s1_chunk::ChunkMatch>,
 }
 
 #[derive(Serialize)]
@@ -29,6 +36,7 @@ struct FullObservation {
     is_pinned: bool,
 }
 
+#[cfg(feature = "cli")]
 pub fn handle_search(db_path: &Path, args: &SearchArgs) -> Result<(), NmemError> {
     let query = match crate::sanitize_fts_query(&args.query) {
         Some(q) => q,
@@ -52,32 +60,88 @@ pub fn handle_search(db_path: &Path, args: &SearchArgs) -> Result<(), NmemError>
         }
     };
 
-    if blended {
-        crate::db::register_udfs(&conn)?;
-    }
+    // Blended ranking decays by age; the half-life is per-project configurable
+    let half_life = if blended {
+        let config = load_config().unwrap_or_default();
+        crate::db::register_udfs_with(&conn, crate::s5_config::resolve_importance(&config))?;
+        Some(resolve_recency_half_life(&config, args.project.as_deref()))
+    } else {
+        None
+    };
 
-    if args.ids {
-        print_ids(&conn, &query, args.project.as_deref(), args.obs_type.as_deref(), limit, blended)?;
+    let cwd = match args.cwd.as_deref() {
+        Some(dir) => Some(
+            crate::s1_extract::cwd_filter(dir)
+                .ok_or_else(|| NmemError::Config(format!("invalid --cwd: {dir:?}")))?,
+        ),
+        None => None,
+    };
+    let filters = Filters {
+        project: args.project.as_deref(),
+        obs_type: args.obs_type.as_deref(),
+        mcp_server: args.mcp_server.as_deref(),
+        mcp_tool: args.mcp_tool.as_deref(),
+        include_archived: args.include_archived,
+        cwd: cwd.as_deref(),
+    };
+    let printed = if args.ids {
+        print_ids(&conn, &query, &filters, limit, half_life)
     } else if args.full {
-        print_full(&conn, &query, args.project.as_deref(), args.obs_type.as_deref(), limit, blended)?;
+        print_full(&conn, &query, &filters, limit, half_life)
     } else {
-        print_index(&conn, &query, args.project.as_deref(), args.obs_type.as_deref(), limit, blended)?;
+        print_index(&conn, &query, &filters, limit, half_life)
+    };
+
+    // A broken index degrades to a scan (index rows, or ids) and queues a rebuild
+    match printed {
+        Err(NmemError::Database(ref e)) if is_fts_failure(e) => {
+            log::warn!(
+                "full-text index unreadable ({e}) — scanning the newest {SCAN_FALLBACK_ROWS} observations; rebuilding in the background"
+            );
+            crate::s3_maintain::spawn_fts_repair(db_path);
+            let results = scan_search(&conn, &query, &filters, limit)?;
+            if args.ids {
+                for r in &results {
+                    println!("{}", r.id);
+                }
+            } else {
+                println!("{}", serde_json::to_string(&results)?);
+            }
+            log::info!("{} results for {:?} (degraded)", results.len(), query);
+            Ok(())
+        }
+        other => other,
     }
+}
 
-    Ok(())
+/// SQL condition: observation `o` was recorded in directory `$p` — a bind
+/// holding a cwd from `normalize_cwd` — or below it. True when `$p` is NULL.
+macro_rules! cwd_under_sql {
+    ($p:literal) => {
+        concat!(
+            "(", $p, " IS NULL OR o.cwd = ", $p,
+            " OR substr(o.cwd, 1, length(rtrim(", $p, ", '/')) + 1) = rtrim(", $p, ", '/') || '/')"
+        )
+    };
 }
+pub(crate) use cwd_under_sql;
 
-const BLENDED_INDEX_SQL: &str = "WITH fts_matches AS (
+const BLENDED_INDEX_SQL: &str = concat!("WITH ", crate::s1_chunk::fts_best_cte!(), ",
+fts_matches AS (
     SELECT o.id, o.timestamp, o.obs_type,
-           SUBSTR(o.content, 1, 120) AS content_preview,
+           COALESCE(f.snippet, SUBSTR(o.content, 1, 120)) AS content_preview,
            o.file_path, o.session_id, o.is_pinned,
+           f.chunk_seq, f.chunk_start, f.chunk_end,
            f.rank AS raw_rank
     FROM observations o
     JOIN sessions s ON o.session_id = s.id
-    JOIN observations_fts f ON o.id = f.rowid
-    WHERE observations_fts MATCH ?1
-      AND (?2 IS NULL OR s.project = ?2)
+    JOIN fts_best f ON o.id = f.obs_id
+    WHERE (?2 IS NULL OR s.project = ?2)
       AND (?3 IS NULL OR o.obs_type = ?3)
+      AND (?6 IS NULL OR o.mcp_server = ?6)
+      AND (?7 IS NULL OR o.mcp_tool = ?7)
+      AND ", cwd_under_sql!("?9"), "
+      AND (?8 OR ?2 IS NOT NULL OR NOT EXISTS (SELECT 1 FROM archived_projects a WHERE a.project = s.project))
 ),
 rank_bounds AS (
     SELECT MIN(raw_rank) AS min_r, MAX(raw_rank) AS max_r FROM fts_matches
@@ -87,29 +151,30 @@ scored AS (
            CASE WHEN b.max_r = b.min_r THEN 1.0
                 ELSE (m.raw_rank - b.max_r) / (b.min_r - b.max_r)
            END AS bm25_norm,
-           exp_decay((unixepoch('now') - m.timestamp) / 86400.0, 7.0) AS recency,
-           CASE m.obs_type
-               WHEN 'file_edit' THEN 1.0 WHEN 'command' THEN 0.67
-               WHEN 'session_compact' THEN 0.5 WHEN 'mcp_call' THEN 0.33
-               ELSE 0.17
-           END AS type_w
+           exp_decay((unixepoch('now') - m.timestamp) / 86400.0, ?5) AS recency
     FROM fts_matches m, rank_bounds b
 )
-SELECT id, timestamp, obs_type, content_preview, file_path, session_id, is_pinned
+SELECT id, timestamp, obs_type, content_preview, file_path, session_id, is_pinned,
+       chunk_seq, chunk_start, chunk_end
 FROM scored
-ORDER BY (bm25_norm * 0.5 + recency * 0.3 + type_w * 0.2) DESC
-LIMIT ?4";
+ORDER BY search_score(bm25_norm, recency, obs_type, is_pinned) DESC
+LIMIT ?4");
 
-const BLENDED_FULL_SQL: &str = "WITH fts_matches AS (
+const BLENDED_FULL_SQL: &str = concat!("WITH ", crate::s1_chunk::fts_best_cte!(), ",
+fts_matches AS (
     SELECT o.id, o.timestamp, o.session_id, o.obs_type, o.source_event,
-           o.tool_name, o.file_path, o.content, o.metadata, o.is_pinned,
+           o.tool_name, o.file_path, ", crate::s1_chunk::full_content_sql!(), " AS content,
+           o.metadata, o.is_pinned,
            f.rank AS raw_rank
     FROM observations o
     JOIN sessions s ON o.session_id = s.id
-    JOIN observations_fts f ON o.id = f.rowid
-    WHERE observations_fts MATCH ?1
-      AND (?2 IS NULL OR s.project = ?2)
+    JOIN fts_best f ON o.id = f.obs_id
+    WHERE (?2 IS NULL OR s.project = ?2)
       AND (?3 IS NULL OR o.obs_type = ?3)
+      AND (?6 IS NULL OR o.mcp_server = ?6)
+      AND (?7 IS NULL OR o.mcp_tool = ?7)
+      AND ", cwd_under_sql!("?9"), "
+      AND (?8 OR ?2 IS NOT NULL OR NOT EXISTS (SELECT 1 FROM archived_projects a WHERE a.project = s.project))
 ),
 rank_bounds AS (
     SELECT MIN(raw_rank) AS min_r, MAX(raw_rank) AS max_r FROM fts_matches
@@ -119,29 +184,28 @@ scored AS (
            CASE WHEN b.max_r = b.min_r THEN 1.0
                 ELSE (m.raw_rank - b.max_r) / (b.min_r - b.max_r)
            END AS bm25_norm,
-           exp_decay((unixepoch('now') - m.timestamp) / 86400.0, 7.0) AS recency,
-           CASE m.obs_type
-               WHEN 'file_edit' THEN 1.0 WHEN 'command' THEN 0.67
-               WHEN 'session_compact' THEN 0.5 WHEN 'mcp_call' THEN 0.33
-               ELSE 0.17
-           END AS type_w
+           exp_decay((unixepoch('now') - m.timestamp) / 86400.0, ?5) AS recency
     FROM fts_matches m, rank_bounds b
 )
 SELECT id, timestamp, session_id, obs_type, source_event,
        tool_name, file_path, content, metadata, is_pinned
 FROM scored
-ORDER BY (bm25_norm * 0.5 + recency * 0.3 + type_w * 0.2) DESC
-LIMIT ?4";
+ORDER BY search_score(bm25_norm, recency, obs_type, is_pinned) DESC
+LIMIT ?4");
 
-const BLENDED_IDS_SQL: &str = "WITH fts_matches AS (
-    SELECT o.id, o.timestamp, o.obs_type,
+const BLENDED_IDS_SQL: &str = concat!("WITH ", crate::s1_chunk::fts_best_cte!(), ",
+fts_matches AS (
+    SELECT o.id, o.timestamp, o.obs_type, o.is_pinned,
            f.rank AS raw_rank
     FROM observations o
     JOIN sessions s ON o.session_id = s.id
-    JOIN observations_fts f ON o.id = f.rowid
-    WHERE observations_fts MATCH ?1
-      AND (?2 IS NULL OR s.project = ?2)
+    JOIN fts_best f ON o.id = f.obs_id
+    WHERE (?2 IS NULL OR s.project = ?2)
       AND (?3 IS NULL OR o.obs_type = ?3)
+      AND (?6 IS NULL OR o.mcp_server = ?6)
+      AND (?7 IS NULL OR o.mcp_tool = ?7)
+      AND ", cwd_under_sql!("?9"), "
+      AND (?8 OR ?2 IS NOT NULL OR NOT EXISTS (SELECT 1 FROM archived_projects a WHERE a.project = s.project))
 ),
 rank_bounds AS (
     SELECT MIN(raw_rank) AS min_r, MAX(raw_rank) AS max_r FROM fts_matches
@@ -151,47 +215,167 @@ scored AS (
            CASE WHEN b.max_r = b.min_r THEN 1.0
                 ELSE (m.raw_rank - b.max_r) / (b.min_r - b.max_r)
            END AS bm25_norm,
-           exp_decay((unixepoch('now') - m.timestamp) / 86400.0, 7.0) AS recency,
-           CASE m.obs_type
-               WHEN 'file_edit' THEN 1.0 WHEN 'command' THEN 0.67
-               WHEN 'session_compact' THEN 0.5 WHEN 'mcp_call' THEN 0.33
-               ELSE 0.17
-           END AS type_w
+           exp_decay((unixepoch('now') - m.timestamp) / 86400.0, ?5) AS recency
     FROM fts_matches m, rank_bounds b
 )
 SELECT id
 FROM scored
-ORDER BY (bm25_norm * 0.5 + recency * 0.3 + type_w * 0.2) DESC
-LIMIT ?4";
+ORDER BY search_score(bm25_norm, recency, obs_type, is_pinned) DESC
+LIMIT ?4");
 
-fn print_index(
+/// Row filters shared by every search mode.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct Filters<'a> {
+    pub project: Option<&'a str>,
+    pub obs_type: Option<&'a str>,
+    pub mcp_server: Option<&'a str>,
+    pub mcp_tool: Option<&'a str>,
+    /// Include archived projects. Filtering by `project` always does.
+    pub include_archived: bool,
+    /// Only observations recorded in this directory or below it, normalized
+    /// with `s1_extract::normalize_cwd`.
+    pub cwd: Option<&'a str>,
+}
+
+/// Positional binds for the search queries; `?5` (half-life) only appears in
+/// blended SQL, and is bound as NULL otherwise. `?8` admits archived projects;
+/// `?9` is the cwd filter.
+fn bind_params<'a>(
+    query: &'a &'a str,
+    filters: &'a Filters<'a>,
+    limit: &'a i64,
+    half_life: &'a Option<f64>,
+) -> [&'a dyn rusqlite::ToSql; 9] {
+    [
+        query,
+        &filters.project,
+        &filters.obs_type,
+        limit,
+        half_life,
+        &filters.mcp_server,
+        &filters.mcp_tool,
+        &filters.include_archived,
+        &filters.cwd,
+    ]
+}
+
+/// Run an FTS5 query (already sanitized) and return index rows. With a
+/// `half_life`, ranking is the importance model's search blend;
+/// the scoring functions must be registered on `conn` for that.
+pub fn search(
     conn: &rusqlite::Connection,
     query: &str,
-    project: Option<&str>,
-    obs_type: Option<&str>,
+    filters: &Filters,
     limit: i64,
-    blended: bool,
-) -> Result<(), NmemError> {
-    let sql = if blended {
+    half_life: Option<f64>,
+) -> Result<Vec<SearchResult>, NmemError> {
+    let sql = if half_life.is_some() {
         BLENDED_INDEX_SQL
     } else {
-        "SELECT o.id, o.timestamp, o.obs_type,
-                SUBSTR(o.content, 1, 120) AS content_preview,
-                o.file_path, o.session_id, o.is_pinned
+        concat!("WITH ", crate::s1_chunk::fts_best_cte!(), "
+         SELECT o.id, o.timestamp, o.obs_type,
+                COALESCE(f.snippet, SUBSTR(o.content, 1, 120)) AS content_preview,
+                o.file_path, o.session_id, o.is_pinned,
+                f.chunk_seq, f.chunk_start, f.chunk_end
          FROM observations o
          JOIN sessions s ON o.session_id = s.id
-         JOIN observations_fts f ON o.id = f.rowid
-         WHERE observations_fts MATCH ?1
-           AND (?2 IS NULL OR s.project = ?2)
+         JOIN fts_best f ON o.id = f.obs_id
+         WHERE (?2 IS NULL OR s.project = ?2)
            AND (?3 IS NULL OR o.obs_type = ?3)
+           AND (?6 IS NULL OR o.mcp_server = ?6)
+           AND (?7 IS NULL OR o.mcp_tool = ?7)
+           AND ", cwd_under_sql!("?9"), "
+           AND (?8 OR ?2 IS NOT NULL OR NOT EXISTS (SELECT 1 FROM archived_projects a WHERE a.project = s.project))
          ORDER BY f.rank
-         LIMIT ?4"
+         LIMIT ?4")
     };
     let mut stmt = conn.prepare(sql)?;
 
     let results: Vec<SearchResult> = stmt
         .query_map(
-            rusqlite::params![query, project, obs_type, limit],
+            bind_params(&query, filters, &limit, &half_life).as_slice(),
+            |row| {
+                Ok(SearchResult {
+                    id: row.get(0)?,
+                    timestamp: row.get(1)?,
+                    obs_type: row.get(2)?,
+                    content_preview: row.get(3)?,
+                    file_path: row.get(4)?,
+                    session_id: row.get(5)?,
+                    is_pinned: row.get::<_, i64>(6)? != 0,
+                    chunk: crate::s1_chunk::ChunkMatch::from_row(row, 7)?,
+                })
+            },
+        )?
+        .collect::<Result<_, _>>()?;
+    Ok(results)
+}
+
+/// Observations scanned, newest first, when the FTS index is unreadable.
+pub const SCAN_FALLBACK_ROWS: i64 = 20_000;
+
+/// Whether `e` means the full-text index itself is broken — corrupt pages or
+/// missing shadow tables — as opposed to a bad query.
+pub fn is_fts_failure(e: &rusqlite::Error) -> bool {
+    let rusqlite::Error::SqliteFailure(err, msg) = e else {
+        return false;
+    };
+    err.code == rusqlite::ErrorCode::DatabaseCorrupt
+        || msg.as_deref().is_some_and(|m| {
+            m.contains("_fts") && (m.contains("no such table") || m.contains("vtable constructor failed"))
+        })
+}
+
+/// Lowercased terms of an FTS query for the substring scan, as a JSON array.
+/// Operators and FTS syntax are dropped; every remaining term must match.
+pub fn scan_terms(query: &str) -> String {
+    let terms: Vec<String> = query
+        .split_whitespace()
+        .filter(|w| !matches!(*w, "AND" | "OR" | "NOT" | "NEAR"))
+        .map(|w| w.trim_matches(|c| matches!(c, '"' | '*' | '(' | ')')).to_lowercase())
+        .filter(|w| !w.is_empty())
+        .collect();
+    serde_json::Value::from(terms).to_string()
+}
+
+/// Degraded search for when `observations_fts` can't be read: a substring
+/// scan of the newest `SCAN_FALLBACK_ROWS` observations, newest first.
+pub fn scan_search(
+    conn: &rusqlite::Connection,
+    query: &str,
+    filters: &Filters,
+    limit: i64,
+) -> Result<Vec<SearchResult>, NmemError> {
+    let mut stmt = conn.prepare(concat!(
+        "SELECT o.id, o.timestamp, o.obs_type,
+                SUBSTR(o.content, 1, 120) AS content_preview,
+                o.file_path, o.session_id, o.is_pinned
+         FROM (SELECT * FROM observations ORDER BY id DESC LIMIT ?10) o
+         JOIN sessions s ON o.session_id = s.id
+         WHERE NOT EXISTS (SELECT 1 FROM json_each(?1) t WHERE instr(lower(o.content), t.value) = 0)
+           AND (?2 IS NULL OR s.project = ?2)
+           AND (?3 IS NULL OR o.obs_type = ?3)
+           AND (?6 IS NULL OR o.mcp_server = ?6)
+           AND (?7 IS NULL OR o.mcp_tool = ?7)
+           AND ", cwd_under_sql!("?9"), "
+           AND (?8 OR ?2 IS NOT NULL OR NOT EXISTS (SELECT 1 FROM archived_projects a WHERE a.project = s.project))
+         ORDER BY o.id DESC
+         LIMIT ?4",
+    ))?;
+    let results = stmt
+        .query_map(
+            rusqlite::params![
+                scan_terms(query),
+                filters.project,
+                filters.obs_type,
+                limit,
+                None::<f64>,
+                filters.mcp_server,
+                filters.mcp_tool,
+                filters.include_archived,
+                filters.cwd,
+                SCAN_FALLBACK_ROWS,
+            ],
             |row| {
                 Ok(SearchResult {
                     id: row.get(0)?,
@@ -201,11 +385,22 @@ fn print_index(
                     file_path: row.get(4)?,
                     session_id: row.get(5)?,
                     is_pinned: row.get::<_, i64>(6)? != 0,
+                    chunk: None,
                 })
             },
         )?
         .collect::<Result<_, _>>()?;
+    Ok(results)
+}
 
+fn print_index(
+    conn: &rusqlite::Connection,
+    query: &str,
+    filters: &Filters,
+    limit: i64,
+    half_life: Option<f64>,
+) -> Result<(), NmemError> {
+    let results = search(conn, query, filters, limit, half_life)?;
     let json = serde_json::to_string(&results)?;
     println!("{json}");
     log::info!("{} results for {:?}", results.len(), query);
@@ -215,30 +410,34 @@ fn print_index(
 fn print_full(
     conn: &rusqlite::Connection,
     query: &str,
-    project: Option<&str>,
-    obs_type: Option<&str>,
+    filters: &Filters,
     limit: i64,
-    blended: bool,
+    half_life: Option<f64>,
 ) -> Result<(), NmemError> {
-    let sql = if blended {
+    let sql = if half_life.is_some() {
         BLENDED_FULL_SQL
     } else {
-        "SELECT o.id, o.timestamp, o.session_id, o.obs_type, o.source_event,
-                o.tool_name, o.file_path, o.content, o.metadata, o.is_pinned
+        concat!("WITH ", crate::s1_chunk::fts_best_cte!(), "
+         SELECT o.id, o.timestamp, o.session_id, o.obs_type, o.source_event,
+                o.tool_name, o.file_path, ", crate::s1_chunk::full_content_sql!(), " AS content,
+                o.metadata, o.is_pinned
          FROM observations o
          JOIN sessions s ON o.session_id = s.id
-         JOIN observations_fts f ON o.id = f.rowid
-         WHERE observations_fts MATCH ?1
-           AND (?2 IS NULL OR s.project = ?2)
+         JOIN fts_best f ON o.id = f.obs_id
+         WHERE (?2 IS NULL OR s.project = ?2)
            AND (?3 IS NULL OR o.obs_type = ?3)
+           AND (?6 IS NULL OR o.mcp_server = ?6)
+           AND (?7 IS NULL OR o.mcp_tool = ?7)
+           AND ", cwd_under_sql!("?9"), "
+           AND (?8 OR ?2 IS NOT NULL OR NOT EXISTS (SELECT 1 FROM archived_projects a WHERE a.project = s.project))
          ORDER BY f.rank
-         LIMIT ?4"
+         LIMIT ?4")
     };
     let mut stmt = conn.prepare(sql)?;
 
     let results: Vec<FullObservation> = stmt
         .query_map(
-            rusqlite::params![query, project, obs_type, limit],
+            bind_params(&query, filters, &limit, &half_life).as_slice(),
             |row| {
                 let metadata_str: Option<String> = row.get(8)?;
                 let metadata = metadata_str.and_then(|s| serde_json::from_str(&s).ok());
@@ -267,29 +466,32 @@ fn print_full(
 fn print_ids(
     conn: &rusqlite::Connection,
     query: &str,
-    project: Option<&str>,
-    obs_type: Option<&str>,
+    filters: &Filters,
     limit: i64,
-    blended: bool,
+    half_life: Option<f64>,
 ) -> Result<(), NmemError> {
-    let sql = if blended {
+    let sql = if half_life.is_some() {
         BLENDED_IDS_SQL
     } else {
-        "SELECT o.id
+        concat!("WITH ", crate::s1_chunk::fts_best_cte!(), "
+         SELECT o.id
          FROM observations o
          JOIN sessions s ON o.session_id = s.id
-         JOIN observations_fts f ON o.id = f.rowid
-         WHERE observations_fts MATCH ?1
-           AND (?2 IS NULL OR s.project = ?2)
+         JOIN fts_best f ON o.id = f.obs_id
+         WHERE (?2 IS NULL OR s.project = ?2)
            AND (?3 IS NULL OR o.obs_type = ?3)
+           AND (?6 IS NULL OR o.mcp_server = ?6)
+           AND (?7 IS NULL OR o.mcp_tool = ?7)
+           AND ", cwd_under_sql!("?9"), "
+           AND (?8 OR ?2 IS NOT NULL OR NOT EXISTS (SELECT 1 FROM archived_projects a WHERE a.project = s.project))
          ORDER BY f.rank
-         LIMIT ?4"
+         LIMIT ?4")
     };
     let mut stmt = conn.prepare(sql)?;
 
     let ids: Vec<i64> = stmt
         .query_map(
-            rusqlite::params![query, project, obs_type, limit],
+            bind_params(&query, filters, &limit, &half_life).as_slice(),
             |row| row.get(0),
         )?
         .collect::<Result<_, _>>()?;
@@ -300,3 +502,37 @@ fn print_ids(
     log::info!("{} results for {:?}", ids.len(), query);
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn detects_corrupt_index_and_scans() {
+        let mut conn = rusqlite::Connection::open_in_memory().unwrap();
+        crate::schema::MIGRATIONS.to_latest(&mut conn).unwrap();
+        conn.execute_batch(
+            "INSERT INTO sessions (id, project, started_at) VALUES ('s', 'p', 1);
+             INSERT INTO observations (session_id, timestamp, obs_type, source_event, content) VALUES
+                 ('s', 1, 'command', 'PostToolUse', 'cargo build --release'),
+                 ('s', 2, 'command', 'PostToolUse', 'Cargo test failed'),
+                 ('s', 3, 'file_read', 'PostToolUse', 'read src/lib.rs');
+             UPDATE observations_fts_data SET block = x'0102030405060708' WHERE id > 10;",
+        )
+        .unwrap();
+
+        let Err(NmemError::Database(e)) = search(&conn, "cargo", &Filters::default(), 10, None) else {
+            panic!("corrupt index should fail the MATCH");
+        };
+        assert!(is_fts_failure(&e));
+        let syntax = conn.prepare("SELECT * FROM observations_fts WHERE observations_fts MATCH '\"a'");
+        assert!(!syntax.and_then(|mut s| s.query_row([], |_| Ok(()))).is_err_and(|e| is_fts_failure(&e)));
+
+        assert_eq!(scan_terms(r#"cargo AND "test" fail*"#), r#"["cargo","test","fail"]"#);
+        let hits = scan_search(&conn, "CARGO", &Filters::default(), 10).unwrap();
+        assert_eq!(hits.iter().map(|h| h.id).collect::<Vec<_>>(), vec![2, 1]);
+        assert_eq!(scan_search(&conn, "cargo test", &Filters::default(), 10).unwrap().len(), 1);
+        let reads = Filters { obs_type: Some("file_read"), ..Default::default() };
+        assert!(scan_search(&conn, "cargo", &reads, 10).unwrap().is_empty());
+    }
+}
@@ -1,4 +1,7 @@
 use crate::db::open_db_readonly;
+use crate::s1_input::Validate;
+use crate::s5_config::LOCAL_ORIGIN;
+use crate::s5_filter::SecretFilter;
 use crate::NmemError;
 use rmcp::{
     ErrorData, ServerHandler, ServiceExt,
@@ -7,10 +10,11 @@ use rmcp::{
     tool, tool_handler, tool_router,
     transport::stdio,
 };
-use rusqlite::Connection;
+use rusqlite::{Connection, OptionalExtension};
 use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
 use std::path::Path;
+use std::sync::atomic::{AtomicBool, Ordering};
 use std::sync::{Arc, Mutex};
 
 type DbHandle = Arc<Mutex<Connection>>;
@@ -18,13 +22,98 @@ type DbHandle = Arc<Mutex<Connection>>;
 #[derive(Clone)]
 pub struct NmemServer {
     db: DbHandle,
+    /// Writable connection for the write tools (queue_task, create_marker,
+    /// start/conclude_investigation); `None` makes them fail. Every other
+    /// tool reads through `db`, which is opened read-only.
+    writer: Option<DbHandle>,
+    /// Writable connection for `query_log` only; `None` disables the audit log.
+    query_log: Option<DbHandle>,
+    /// Read-only stores federated into search, recent_context, and
+    /// session_summaries, by origin name.
+    attached: Vec<(String, DbHandle)>,
+    /// Set once search has spawned an FTS rebuild, so it spawns only one.
+    fts_repair: Arc<AtomicBool>,
+    /// Lowest log level the client asked for (`logging/setLevel`); stance
+    /// notifications go out only once it has.
+    log_level: Arc<Mutex<Option<LoggingLevel>>>,
+    /// Scoring functions deferred by `--lean`; `None` means every connection
+    /// had them registered up front.
+    lazy_udfs: Option<Arc<LazyUdfs>>,
+    /// Ordered results of paged searches, sliced by later pages.
+    search_pages: Arc<Mutex<SearchSnapshots>>,
     #[allow(dead_code)]
     tool_router: ToolRouter<Self>,
 }
 
+/// The importance model to register on each connection the first time a
+/// scoring query reaches it, keyed by connection address (connections live
+/// behind `Arc<Mutex<_>>` and never move).
+struct LazyUdfs {
+    model: crate::s3_importance::ImportanceModel,
+    registered: Mutex<std::collections::HashSet<usize>>,
+}
+
+/// Paged searches in progress, oldest first. BM25 ranks depend on corpus-wide
+/// statistics, so every insert re-ranks older rows and a (rank, id) keyset
+/// would skip or repeat results; instead the first page ranks up to
+/// `SEARCH_SNAPSHOT_ROWS` results once and later pages slice that ordering.
+#[derive(Default)]
+struct SearchSnapshots {
+    next_token: u64,
+    entries: std::collections::VecDeque<SearchSnapshot>,
+}
+
+struct SearchSnapshot {
+    token: u64,
+    /// Hash of the query and filters it ranked (`search_filters`); a cursor
+    /// for one search can't page another.
+    filters: String,
+    results: Vec<SearchResult>,
+}
+
+/// Results one paged search ranks up front.
+const SEARCH_SNAPSHOT_ROWS: i64 = 1000;
+/// Paged searches held at once; the oldest is dropped and its cursor expires.
+const SEARCH_SNAPSHOTS: usize = 16;
+
+impl SearchSnapshots {
+    fn store(&mut self, filters: &str, results: Vec<SearchResult>) -> u64 {
+        if self.next_token == 0 {
+            // Start past any token a previous server process handed out
+            self.next_token = std::time::SystemTime::now()
+                .duration_since(std::time::UNIX_EPOCH)
+                .map(|d| d.as_nanos() as u64)
+                .unwrap_or(1);
+        }
+        let token = self.next_token;
+        self.next_token += 1;
+        if self.entries.len() >= SEARCH_SNAPSHOTS {
+            self.entries.pop_front();
+        }
+        self.entries.push_back(SearchSnapshot { token, filters: filters.to_string(), results });
+        token
+    }
+
+    fn get(&self, token: u64) -> Option<&SearchSnapshot> {
+        self.entries.iter().find(|e| e.token == token)
+    }
+}
+
+/// Hash of everything in `params` that decides a search's result set — all
+/// but the cursor and how much of a page to return or how to render it.
+fn search_filters(params: &SearchParams) -> String {
+    let mut value = serde_json::to_value(params).unwrap_or_default();
+    if let Some(fields) = value.as_object_mut() {
+        for key in ["cursor", "limit", "compact", "max_bytes"] {
+            fields.remove(key);
+        }
+    }
+    crate::s3_audit::params_hash(&value)
+}
+
 // --- Parameter types ---
 
-#[derive(Deserialize, JsonSchema)]
+#[derive(Deserialize, Serialize, JsonSchema)]
 pub struct SearchParams {
     /// FTS5 query. Supports AND/OR/NOT, "phrase", prefix*.
     pub query: String,
@@ -34,7 +123,7 @@ pub struct SearchParams {
     /// Filter by observation type (file_read, file_edit, command, etc).
     #[serde(default)]
     pub obs_type: Option<String>,
-    /// Max results (default 20, max 100).
+    /// Max results (default 20, raised up to 50 for busy projects; max 100).
     #[serde(default)]
     pub limit: Option<i64>,
     /// Pagination offset (default 0).
@@ -49,15 +138,73 @@ pub struct SearchParams {
     /// Only include observations after this Unix timestamp.
     #[serde(default)]
     pub after: Option<i64>,
+    /// Named time window instead of raw timestamps: "today", "this-week",
+    /// "last-month", or one from `[windows]` config such as "sprint-42".
+    /// Explicit before/after narrow it further.
+    #[serde(default)]
+    pub window: Option<String>,
+    /// Filter failures by error class: compile_error, test_failure, network, permission, missing_dependency, flaky, other.
+    #[serde(default)]
+    pub error_class: Option<String>,
+    /// Filter MCP calls by server, e.g. "context7" for mcp__context7__query-docs.
+    #[serde(default)]
+    pub mcp_server: Option<String>,
+    /// Filter MCP calls by tool name within the server, e.g. "query-docs".
+    #[serde(default)]
+    pub mcp_tool: Option<String>,
+    /// Only observations recorded with this working directory or one below
+    /// it (absolute), e.g. one crate of a monorepo.
+    #[serde(default)]
+    pub cwd: Option<String>,
+    /// Also count the full match set by obs_type, project, and session. The
+    /// response becomes `{results, facets}`.
+    #[serde(default)]
+    pub facets: Option<bool>,
+    /// Without `project`, scope to the project that file paths in the query
+    /// (e.g. "src/db.rs") belong to; the response becomes
+    /// `{results, inferred_project}`. Default true.
+    #[serde(default)]
+    pub infer_project: Option<bool>,
+    /// Include archived projects (default false; a `project` filter always
+    /// includes its project).
+    #[serde(default)]
+    pub include_archived: Option<bool>,
+    /// Opaque pagination cursor. Pass "" to start paging; the response becomes
+    /// `{results, next_cursor}`. Pass the returned `next_cursor` for the next page.
+    #[serde(default)]
+    pub cursor: Option<String>,
+    /// Tighter response for browsing: short keys, 120-char previews, and
+    /// session ids deduplicated into a `sessions` header. The response
+    /// becomes `{keys, sessions, results}`.
+    #[serde(default)]
+    pub compact: Option<bool>,
+    /// Hard cap on the response size in bytes (min 256). Lists are cut from
+    /// the end and the response is flagged `truncated: true`.
+    #[serde(default)]
+    pub max_bytes: Option<usize>,
 }
 
-#[derive(Deserialize, JsonSchema)]
+#[derive(Deserialize, Serialize, JsonSchema)]
 pub struct GetObservationsParams {
     /// Observation IDs to fetch. Max 50.
     pub ids: Vec<i64>,
+    /// Add a `related` array to each observation: the rest of a marker's
+    /// thread, the same file within an hour, the same error class, and the
+    /// same prompt. Default false.
+    #[serde(default)]
+    pub related: Option<bool>,
+    /// Tighter response for browsing: short keys, 120-char previews, and
+    /// session ids deduplicated into a `sessions` header. The response
+    /// becomes `{keys, sessions, results}`.
+    #[serde(default)]
+    pub compact: Option<bool>,
+    /// Hard cap on the response size in bytes (min 256). Lists are cut from
+    /// the end and the response is flagged `truncated: true`.
+    #[serde(default)]
+    pub max_bytes: Option<usize>,
 }
 
-#[derive(Deserialize, JsonSchema)]
+#[derive(Deserialize, Serialize, JsonSchema)]
 pub struct TimelineParams {
     /// Observation ID to center on.
     pub anchor: i64,
@@ -67,9 +214,17 @@ pub struct TimelineParams {
     /// Observations after anchor (default 5).
     #[serde(default)]
     pub after: Option<i64>,
+    /// List each run of a repeated command as its own entry (default
+    /// false: one entry with a `repeats` count).
+    #[serde(default)]
+    pub expand_repeats: Option<bool>,
+    /// Hard cap on the response size in bytes (min 256). Lists are cut from
+    /// the end and the response is flagged `truncated: true`.
+    #[serde(default)]
+    pub max_bytes: Option<usize>,
 }
 
-#[derive(Deserialize, JsonSchema)]
+#[derive(Deserialize, Serialize, JsonSchema)]
 pub struct SessionSummariesParams {
     /// Filter by project name. Omit for all projects.
     #[serde(default)]
@@ -83,14 +238,30 @@ pub struct SessionSummariesParams {
     /// Only include sessions started after this Unix timestamp.
     #[serde(default)]
     pub after: Option<i64>,
+    /// Named time window instead of raw timestamps: "today", "this-week",
+    /// "last-month", or one from `[windows]` config such as "sprint-42".
+    /// Explicit before/after narrow it further.
+    #[serde(default)]
+    pub window: Option<String>,
+    /// Only sessions run on a model whose id contains this (e.g. "opus").
+    #[serde(default)]
+    pub model: Option<String>,
+    /// Opaque pagination cursor. Pass "" to start paging; the response becomes
+    /// `{results, next_cursor}`. Pass the returned `next_cursor` for the next page.
+    #[serde(default)]
+    pub cursor: Option<String>,
+    /// Hard cap on the response size in bytes (min 256). Lists are cut from
+    /// the end and the response is flagged `truncated: true`.
+    #[serde(default)]
+    pub max_bytes: Option<usize>,
 }
 
-#[derive(Deserialize, JsonSchema)]
+#[derive(Deserialize, Serialize, JsonSchema)]
 pub struct RecentContextParams {
     /// Project scope. Omit for all projects.
     #[serde(default)]
     pub project: Option<String>,
-    /// Max observations (default 30, max 100).
+    /// Max observations (default 30, raised up to 100 for busy projects; max 100).
     #[serde(default)]
     pub limit: Option<i64>,
     /// Only include observations before this Unix timestamp.
@@ -99,18 +270,44 @@ pub struct RecentContextParams {
     /// Only include observations after this Unix timestamp.
     #[serde(default)]
     pub after: Option<i64>,
+    /// Named time window instead of raw timestamps: "today", "this-week",
+    /// "last-month", or one from `[windows]` config such as "sprint-42".
+    /// Explicit before/after narrow it further.
+    #[serde(default)]
+    pub window: Option<String>,
+    /// Opaque pagination cursor. Pass "" to start paging; the response becomes
+    /// `{results, next_cursor}`. Pass the returned `next_cursor` for the next page.
+    #[serde(default)]
+    pub cursor: Option<String>,
+    /// The calling session. Its observations, and those of the sessions it
+    /// resumed or continued, outrank equally recent ones from other sessions.
+    #[serde(default)]
+    pub session_id: Option<String>,
+    /// Tighter response for browsing: short keys, 120-char previews, and
+    /// session ids deduplicated into a `sessions` header. The response
+    /// becomes `{keys, sessions, results}`.
+    #[serde(default)]
+    pub compact: Option<bool>,
+    /// Hard cap on the response size in bytes (min 256). Lists are cut from
+    /// the end and the response is flagged `truncated: true`.
+    #[serde(default)]
+    pub max_bytes: Option<usize>,
 }
 
-#[derive(Deserialize, JsonSchema)]
+#[derive(Deserialize, Serialize, JsonSchema)]
 pub struct RegenerateContextParams {
     /// Project name (required). Use the project name from session start.
     pub project: String,
     /// Only include data before this Unix timestamp. Produces "context as of time T".
     #[serde(default)]
     pub before: Option<i64>,
+    /// Hard cap on the response size in bytes (min 256). Lists are cut from
+    /// the end and the response is flagged `truncated: true`.
+    #[serde(default)]
+    pub max_bytes: Option<usize>,
 }
 
-#[derive(Deserialize, JsonSchema)]
+#[derive(Deserialize, Serialize, JsonSchema)]
 pub struct SessionTraceParams {
     /// Session ID to trace.
     pub session_id: String,
@@ -120,11 +317,21 @@ pub struct SessionTraceParams {
     /// Only include prompts after this Unix timestamp.
     #[serde(default)]
     pub after: Option<i64>,
+    /// Named time window instead of raw timestamps: "today", "this-week",
+    /// "last-month", or one from `[windows]` config such as "sprint-42".
+    /// Explicit before/after narrow it further.
+    #[serde(default)]
+    pub window: Option<String>,
+    /// Hard cap on the response size in bytes (min 256). Lists are cut from
+    /// the end and the response is flagged `truncated: true`.
+    #[serde(default)]
+    pub max_bytes: Option<usize>,
 }
 
-#[derive(Deserialize, JsonSchema)]
+#[derive(Deserialize, Serialize, JsonSchema)]
 pub struct FileHistoryParams {
-    /// File path to trace history for.
+    /// File path to trace history for. Absolute paths match exactly;
+    /// relative paths and bare file names resolve to a recorded path.
     pub file_path: String,
     /// Only include touches before this Unix timestamp.
     #[serde(default)]
@@ -132,12 +339,62 @@ pub struct FileHistoryParams {
     /// Only include touches after this Unix timestamp.
     #[serde(default)]
     pub after: Option<i64>,
+    /// Named time window instead of raw timestamps: "today", "this-week",
+    /// "last-month", or one from `[windows]` config such as "sprint-42".
+    /// Explicit before/after narrow it further.
+    #[serde(default)]
+    pub window: Option<String>,
     /// Max sessions to return (default 10, max 50).
     #[serde(default)]
     pub limit: Option<i64>,
+    /// Project you are working in. Other projects that touched the file are
+    /// listed under `shared_with` with their last decision about it.
+    /// Defaults to the project of the most recent touch.
+    #[serde(default)]
+    pub project: Option<String>,
+    /// Hard cap on the response size in bytes (min 256). Lists are cut from
+    /// the end and the response is flagged `truncated: true`.
+    #[serde(default)]
+    pub max_bytes: Option<usize>,
+}
+
+#[derive(Deserialize, Serialize, JsonSchema)]
+pub struct FileSnapshotParams {
+    /// File path as recorded by the Read tool.
+    pub file_path: String,
+    /// Only snapshots captured before this Unix timestamp — "as of" a decision.
+    #[serde(default)]
+    pub before: Option<i64>,
+    /// Only the snapshot taken in this session.
+    #[serde(default)]
+    pub session_id: Option<String>,
+    /// Max snapshots to return (default 5, max 20).
+    #[serde(default)]
+    pub limit: Option<i64>,
+    /// Hard cap on the response size in bytes (min 256). Lists are cut from
+    /// the end and the response is flagged `truncated: true`.
+    #[serde(default)]
+    pub max_bytes: Option<usize>,
+}
+
+#[derive(Deserialize, Serialize, JsonSchema)]
+pub struct PlanHistoryParams {
+    /// Session whose plan to show. Defaults to the latest session with one.
+    #[serde(default)]
+    pub session_id: Option<String>,
+    /// Plan events across this project's sessions instead.
+    #[serde(default)]
+    pub project: Option<String>,
+    /// Max events, newest kept, returned oldest first (default 50, max 200).
+    #[serde(default)]
+    pub limit: Option<i64>,
+    /// Hard cap on the response size in bytes (min 256). Lists are cut from
+    /// the end and the response is flagged `truncated: true`.
+    #[serde(default)]
+    pub max_bytes: Option<usize>,
 }
 
-#[derive(Deserialize, JsonSchema)]
+#[derive(Deserialize, Serialize, JsonSchema)]
 pub struct QueueTaskParams {
     /// The task prompt to queue for later execution.
     pub prompt: String,
@@ -151,20 +408,78 @@ pub struct QueueTaskParams {
     pub after: String,
 }
 
-#[derive(Deserialize, JsonSchema)]
+#[derive(Deserialize, Serialize, JsonSchema)]
 pub struct CreateMarkerParams {
     /// The marker text (conclusion, decision, waypoint).
     pub text: String,
     /// Project scope. Defaults to current project.
     #[serde(default)]
     pub project: Option<String>,
+    /// Marker this one replies to, continuing its thread — for a decision
+    /// that changed or a follow-up on a waypoint.
+    #[serde(default)]
+    pub reply_to: Option<i64>,
+    /// The thread's status as of this marker (e.g. decided, superseded, done).
+    #[serde(default)]
+    pub status: Option<String>,
+}
+
+#[derive(Deserialize, Serialize, JsonSchema)]
+pub struct RememberParams {
+    /// Slot name, e.g. "test-command" or "staging-db". Writing an existing
+    /// key in the same scope replaces its content.
+    pub key: String,
+    /// What to remember, in a sentence or two.
+    pub content: String,
+    /// "project" (default) or "global" (every project).
+    #[serde(default)]
+    pub scope: Option<String>,
+    /// Project scope. Defaults to current project.
+    #[serde(default)]
+    pub project: Option<String>,
+    /// Forget on its own after this long: "30m", "2h", "7d", "2w". Default: never.
+    #[serde(default)]
+    pub ttl: Option<String>,
+}
+
+#[derive(Deserialize, Serialize, JsonSchema)]
+pub struct ForgetParams {
+    /// Slot name given to remember.
+    pub key: String,
+    /// "project" (default) or "global".
+    #[serde(default)]
+    pub scope: Option<String>,
+    /// Project scope. Defaults to current project.
+    #[serde(default)]
+    pub project: Option<String>,
+}
+
+#[derive(Deserialize, Serialize, JsonSchema)]
+pub struct StartInvestigationParams {
+    /// The question being investigated.
+    pub question: String,
+    /// Project scope. Defaults to current project.
+    #[serde(default)]
+    pub project: Option<String>,
+}
+
+#[derive(Deserialize, Serialize, JsonSchema)]
+pub struct ConcludeInvestigationParams {
+    /// What was found: the answer, root cause, or decision.
+    pub conclusion: String,
+    /// Investigation id from start_investigation. Defaults to the project's newest open one.
+    #[serde(default)]
+    pub investigation_id: Option<i64>,
+    /// Project scope. Defaults to current project.
+    #[serde(default)]
+    pub project: Option<String>,
 }
 
 fn default_50() -> usize {
     50
 }
 
-#[derive(Deserialize, JsonSchema)]
+#[derive(Deserialize, Serialize, JsonSchema)]
 pub struct GitFileSummaryParams {
     /// File path relative to repo root.
     pub file_path: String,
@@ -174,9 +489,13 @@ pub struct GitFileSummaryParams {
     /// Include full commit list (default: summary only).
     #[serde(default)]
     pub full: bool,
+    /// Hard cap on the response size in bytes (min 256). Lists are cut from
+    /// the end and the response is flagged `truncated: true`.
+    #[serde(default)]
+    pub max_bytes: Option<usize>,
 }
 
-#[derive(Deserialize, JsonSchema)]
+#[derive(Deserialize, Serialize, JsonSchema)]
 pub struct CurrentStanceParams {
     /// Optional session ID. Defaults to the most recent session.
     #[serde(default)]
@@ -184,11 +503,188 @@ pub struct CurrentStanceParams {
     /// EMA alpha for smoothing (default 0.08). Lower = smoother.
     #[serde(default)]
     pub alpha: Option<f64>,
+    /// Hard cap on the response size in bytes (min 256). Lists are cut from
+    /// the end and the response is flagged `truncated: true`.
+    #[serde(default)]
+    pub max_bytes: Option<usize>,
+}
+
+#[derive(Deserialize, Serialize, JsonSchema)]
+pub struct WorkingSetParams {
+    /// Optional session ID. Defaults to the most recent session.
+    #[serde(default)]
+    pub session_id: Option<String>,
+    /// Max hot files, failing commands, and related episodes each (default 10, max 50).
+    #[serde(default)]
+    pub limit: Option<i64>,
+    /// Hard cap on the response size in bytes (min 256). Lists are cut from
+    /// the end and the response is flagged `truncated: true`.
+    #[serde(default)]
+    pub max_bytes: Option<usize>,
+}
+
+#[derive(Deserialize, Serialize, JsonSchema)]
+pub struct GrepParams {
+    /// Regular expression (Rust regex syntax, max 500 chars), e.g. `E0(5|6)\d\d`.
+    pub pattern: String,
+    /// Filter by project name. Omit for all projects.
+    #[serde(default)]
+    pub project: Option<String>,
+    /// Filter by observation type (e.g. command, file_edit).
+    #[serde(default)]
+    pub obs_type: Option<String>,
+    /// FTS5 query the observations must also match; narrows the scan.
+    #[serde(default)]
+    pub fts: Option<String>,
+    /// Case-insensitive match.
+    #[serde(default)]
+    pub ignore_case: Option<bool>,
+    /// Only observations before this Unix timestamp.
+    #[serde(default)]
+    pub before: Option<i64>,
+    /// Only observations after this Unix timestamp.
+    #[serde(default)]
+    pub after: Option<i64>,
+    /// Named time window instead of raw timestamps: "today", "this-week",
+    /// "last-month", or one from `[windows]` config such as "sprint-42".
+    /// Explicit before/after narrow it further.
+    #[serde(default)]
+    pub window: Option<String>,
+    /// Max matches (default 20, max 50).
+    #[serde(default)]
+    pub limit: Option<i64>,
+    /// Hard cap on the response size in bytes (min 256). Lists are cut from
+    /// the end and the response is flagged `truncated: true`.
+    #[serde(default)]
+    pub max_bytes: Option<usize>,
+}
+
+#[derive(Deserialize, Serialize, JsonSchema)]
+pub struct SimilarObservationsParams {
+    /// Observation to find lookalikes of.
+    pub id: i64,
+    /// Max results (default 10, max 50).
+    #[serde(default)]
+    pub limit: Option<i64>,
+    /// Lowest estimated similarity returned, 0 to 1 (default 0.5).
+    #[serde(default)]
+    pub min_similarity: Option<f64>,
+    /// Hard cap on the response size in bytes (min 256). Lists are cut from
+    /// the end and the response is flagged `truncated: true`.
+    #[serde(default)]
+    pub max_bytes: Option<usize>,
+}
+
+#[derive(Deserialize, Serialize, JsonSchema)]
+pub struct FlakyTestsParams {
+    /// Restrict to one project.
+    #[serde(default)]
+    pub project: Option<String>,
+    /// Fewest sessions a test must have failed in (default 2). Tests with a
+    /// run relabelled flaky are listed regardless.
+    #[serde(default)]
+    pub min_sessions: Option<i64>,
+    /// Max results (default 20, max 100).
+    #[serde(default)]
+    pub limit: Option<i64>,
+    /// Hard cap on the response size in bytes (min 256). Lists are cut from
+    /// the end and the response is flagged `truncated: true`.
+    #[serde(default)]
+    pub max_bytes: Option<usize>,
+}
+
+#[derive(Deserialize, Serialize, JsonSchema)]
+pub struct TestHistoryParams {
+    /// Test name as the runner printed it, or its tail on a `::`, `.`, `/`
+    /// boundary (`refresh` finds `auth::tests::refresh`).
+    pub name: String,
+    /// Restrict to one project.
+    #[serde(default)]
+    pub project: Option<String>,
+    /// Max failing runs returned, newest first (default 20, max 100).
+    #[serde(default)]
+    pub limit: Option<i64>,
+    /// Hard cap on the response size in bytes (min 256). Lists are cut from
+    /// the end and the response is flagged `truncated: true`.
+    #[serde(default)]
+    pub max_bytes: Option<usize>,
+}
+
+#[derive(Deserialize, Serialize, JsonSchema)]
+pub struct WindowSummaryParams {
+    /// Named time window: "today", "yesterday", "this-week", "last-week",
+    /// "this-month", "last-month", or one from `[windows]` config such as
+    /// "sprint-42" or "release-3".
+    pub window: String,
+    /// Restrict to one project. Omit for all projects.
+    #[serde(default)]
+    pub project: Option<String>,
+    /// Max episodes and max decisions listed, oldest first (default 30, max 100).
+    #[serde(default)]
+    pub limit: Option<i64>,
+    /// Hard cap on the response size in bytes (min 256). Lists are cut from
+    /// the end and the response is flagged `truncated: true`.
+    #[serde(default)]
+    pub max_bytes: Option<usize>,
+}
+
+#[derive(Deserialize, Serialize, JsonSchema)]
+pub struct MemoryUsageReportParams {
+    /// Look back this many days (default 30, max 365).
+    #[serde(default)]
+    pub days: Option<i64>,
+    /// Max entries in the most-retrieved observations list (default 20, max 100).
+    #[serde(default)]
+    pub limit: Option<i64>,
+    /// Hard cap on the response size in bytes (min 256). Lists are cut from
+    /// the end and the response is flagged `truncated: true`.
+    #[serde(default)]
+    pub max_bytes: Option<usize>,
+}
+
+#[derive(Deserialize, Serialize, JsonSchema)]
+pub struct ContextPackParams {
+    /// Pack to render (a `[packs.<name>]` config section). Omit to take the
+    /// packs queued for the session by their triggers.
+    #[serde(default)]
+    pub name: Option<String>,
+    /// Project to render a named pack for. Omit for all projects.
+    #[serde(default)]
+    pub project: Option<String>,
+    /// Session whose queued packs to take. Defaults to the most recent session.
+    #[serde(default)]
+    pub session_id: Option<String>,
+    /// Hard cap on the response size in bytes (min 256). Lists are cut from
+    /// the end and the response is flagged `truncated: true`.
+    #[serde(default)]
+    pub max_bytes: Option<usize>,
+}
+
+#[derive(Deserialize, Serialize, JsonSchema)]
+pub struct BundleParams {
+    /// Sub-requests to execute in order. Max 10.
+    pub requests: Vec<BundleRequest>,
+    /// Hard cap on the response size in bytes (min 256). Lists are cut from
+    /// the end and the response is flagged `truncated: true`.
+    #[serde(default)]
+    pub max_bytes: Option<usize>,
+}
+
+#[derive(Deserialize, Serialize, JsonSchema)]
+pub struct BundleRequest {
+    /// Key for this result in the response. Defaults to the tool name, suffixed with the index on collision.
+    #[serde(default)]
+    pub key: Option<String>,
+    /// Read tool to run: search, get_observations, timeline, recent_context, session_summaries, session_trace, file_history, working_set.
+    pub tool: String,
+    /// Parameters for the tool — same shape as calling it directly.
+    #[serde(default)]
+    pub params: serde_json::Value,
 }
 
 // --- Response types ---
 
-#[derive(Serialize)]
+#[derive(Clone, Serialize)]
 struct SearchResult {
     id: i64,
     timestamp: i64,
@@ -197,9 +693,22 @@ struct SearchResult {
     file_path: Option<String>,
     session_id: String,
     is_pinned: bool,
+    /// Recency half-life (days) applied when results are blended-ranked.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    half_life_days: Option<f64>,
+    /// Ordering key (BM25 rank or blended score), for merging attached stores.
+    #[serde(skip)]
+    sort_key: f64,
+    /// Database the row came from; set only when stores are attached.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    origin: Option<String>,
+    /// Best match was in a chunk of long content: where it sits. The preview
+    /// is a snippet of that chunk.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    chunk: Option<crate::s1_chunk::ChunkMatch>,
 }
 
-#[derive(Serialize)]
+#[derive(Clone, Serialize)]
 struct FullObservation {
     id: i64,
     timestamp: i64,
@@ -211,6 +720,30 @@ struct FullObservation {
     content: String,
     metadata: Option<serde_json::Value>,
     is_pinned: bool,
+    /// Redactions and annotations, oldest first.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    corrections: Vec<crate::s1_correct::Correction>,
+    /// Back-to-back runs of the same command folded into this one.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    repeats: Option<crate::s1_repeat::Repeats>,
+}
+
+#[derive(Serialize)]
+struct ObservationWithRelated {
+    #[serde(flatten)]
+    observation: FullObservation,
+    related: Vec<RelatedObservation>,
+}
+
+#[derive(Serialize)]
+struct RelatedObservation {
+    id: i64,
+    /// `thread`, `same_file`, `same_error`, or `same_prompt`.
+    relation: &'static str,
+    timestamp: i64,
+    obs_type: String,
+    file_path: Option<String>,
+    content_preview: String,
 }
 
 #[derive(Serialize)]
@@ -225,7 +758,18 @@ struct SessionSummaryResult {
     session_id: String,
     project: String,
     started_at: i64,
+    /// Model the session ran on, when recorded.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    model: Option<String>,
     summary: serde_json::Value,
+    /// Observations the summary was generated from; every field draws on them.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    sources: Vec<i64>,
+    /// File references in the summary that no source observation mentions.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    unverified_files: Vec<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    origin: Option<String>,
 }
 
 #[derive(Serialize)]
@@ -236,6 +780,10 @@ struct SessionTraceResult {
     ended_at: Option<i64>,
     summary: Option<serde_json::Value>,
     prompts: Vec<PromptTrace>,
+    /// Tool calls announced by PreToolUse that produced no observation —
+    /// denied, blocked, or interrupted (or still running).
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    unmatched_calls: Vec<crate::s1_intent::ToolIntent>,
 }
 
 #[derive(Serialize)]
@@ -256,12 +804,55 @@ struct ObservationSummary {
     file_path: Option<String>,
     content_preview: String,
     is_pinned: bool,
+    /// Back-to-back runs of this command, when it ran more than once.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    repeat_count: Option<i64>,
 }
 
 #[derive(Serialize)]
 struct FileHistoryResult {
     file_path: String,
+    /// The recorded path `file_path` resolved to; absent when nothing or
+    /// several paths matched.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    resolved_path: Option<String>,
+    /// How a non-exact query matched: `suffix` or `basename`.
+    #[serde(rename = "match", skip_serializing_if = "Option::is_none")]
+    path_match: Option<crate::s1_alias::PathMatch>,
+    /// Recorded paths an ambiguous query could mean; query again with one.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    candidates: Vec<crate::s1_alias::PathCandidate>,
+    /// Earlier names of the file whose touches are included (nearest first).
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    aliases: Vec<String>,
     sessions: Vec<FileSessionEntry>,
+    /// Touches held back by `nmem suppress` rules.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    suppressed: Option<usize>,
+    /// How often work on this file went wrong: failures after edits,
+    /// friction episodes, reverts.
+    risk: crate::s4_risk::FileRisk,
+    /// Other projects that also govern this file.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    shared_with: Vec<crate::s4_overlap::SharedFile>,
+}
+
+#[derive(Serialize)]
+struct FileSnapshotResult {
+    file_path: String,
+    /// Git blob hash of the file on disk now; absent if it is gone or too large.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    current_blob_hash: Option<String>,
+    snapshots: Vec<SnapshotEntry>,
+}
+
+#[derive(Serialize)]
+struct SnapshotEntry {
+    #[serde(flatten)]
+    snapshot: crate::s1_snapshot::Snapshot,
+    /// Whether the file still has this content; absent when either hash is.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    unchanged: Option<bool>,
 }
 
 #[derive(Serialize)]
@@ -277,6 +868,9 @@ struct FileSessionEntry {
 struct FileTouch {
     observation_id: i64,
     timestamp: i64,
+    /// Set when the touch was recorded under an earlier name.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    file_path: Option<String>,
     obs_type: String,
     content_preview: String,
     prompt_content: Option<String>,
@@ -355,13 +949,92 @@ struct StanceResult {
     guidance: String,
 }
 
+#[derive(Serialize)]
+struct WorkingSetResult {
+    session_id: String,
+    /// Intent of the open episode (its first substantive prompt).
+    #[serde(skip_serializing_if = "Option::is_none")]
+    intent: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    started_at: Option<i64>,
+    obs_count: i64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    phase_signature: Option<serde_json::Value>,
+    hot_files: Vec<HotFile>,
+    failing_commands: Vec<FailingCommand>,
+    related_episodes: Vec<RelatedEpisode>,
+}
+
+#[derive(Serialize)]
+struct HotFile {
+    file_path: String,
+    reads: i64,
+    edits: i64,
+    last_touched: i64,
+}
+
+#[derive(Serialize)]
+struct FailingCommand {
+    command: String,
+    failures: i64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    error_class: Option<String>,
+    last_failed_at: i64,
+    /// A later run of the same command succeeded.
+    resolved: bool,
+}
+
+#[derive(Serialize)]
+struct SimilarObservationsResult {
+    id: i64,
+    /// False when the observation is too short to compare.
+    comparable: bool,
+    results: Vec<SimilarObservation>,
+}
+
+#[derive(Serialize)]
+struct SimilarObservation {
+    id: i64,
+    /// Estimated Jaccard similarity of word shingles, 0 to 1.
+    similarity: f64,
+    timestamp: i64,
+    session_id: String,
+    project: String,
+    obs_type: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    file_path: Option<String>,
+    content_preview: String,
+}
+
+#[derive(Serialize)]
+struct RelatedEpisode {
+    session_id: String,
+    project: String,
+    intent: String,
+    started_at: i64,
+    /// Hot files this episode shares with the open one (under any earlier name).
+    shared_files: Vec<String>,
+    /// From a session the open one resumed or continued; these rank first.
+    lineage: bool,
+    /// `succeeded`, `blocked`, or `abandoned`, once labeled at Stop.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    outcome: Option<String>,
+}
+
 // --- Helpers ---
 
-fn db_err(e: &impl std::fmt::Display) -> ErrorData {
+/// Internal error for `e`, with its diagnostic (code, hint) as `data` and
+/// the hint appended to the message.
+fn db_err(e: &(impl std::fmt::Display + crate::diagnostic::Diagnose)) -> ErrorData {
+    let diagnostic = e.diagnostic();
+    let mut message = format!("db: {e}");
+    if let Some(hint) = diagnostic.as_ref().and_then(|d| d.hint.as_deref()) {
+        message.push_str(&format!(" (hint: {hint})"));
+    }
     ErrorData::new(
         ErrorCode::INTERNAL_ERROR,
-        format!("db: {e}"),
-        None,
+        message,
+        diagnostic.and_then(|d| serde_json::to_value(d).ok()),
     )
 }
 
@@ -369,14 +1042,530 @@ fn clamp(val: Option<i64>, default: i64, max: i64) -> i64 {
     val.unwrap_or(default).max(1).min(max)
 }
 
-fn record_query_metrics(tool: &str, start: std::time::Instant) {
-    let meter = opentelemetry::global::meter("nmem");
-    meter
-        .u64_counter("nmem_queries_total")
-        .build()
-        .add(1, &[opentelemetry::KeyValue::new("tool", tool.to_string())]);
-    meter
-        .f64_histogram("nmem_query_duration_seconds")
+/// Keyset pagination state, hex-encoded so callers treat it as opaque.
+/// `as_of` pins the observation set (max id at first page) and `now` pins the
+/// recency clock, so later pages rank exactly the rows the first page saw.
+/// `key`/`id` are the sort key and tiebreaker of the last row returned;
+/// search, whose ranks aren't stable, puts `token:position` in `id` instead.
+struct PageCursor {
+    tool: String,
+    as_of: i64,
+    now: i64,
+    key: f64,
+    id: String,
+}
+
+impl PageCursor {
+    fn encode(&self) -> String {
+        let raw = format!(
+            "v1|{}|{}|{}|{:016x}|{}",
+            self.tool, self.as_of, self.now, self.key.to_bits(), self.id
+        );
+        raw.bytes().map(|b| format!("{b:02x}")).collect()
+    }
+
+    fn decode(tool: &str, cursor: &str) -> Result<Self, ErrorData> {
+        let invalid = || {
+            ErrorData::new(
+                ErrorCode::INVALID_PARAMS,
+                format!("invalid cursor for {tool}"),
+                None,
+            )
+        };
+        if !cursor.len().is_multiple_of(2) || !cursor.is_ascii() {
+            return Err(invalid());
+        }
+        let bytes = (0..cursor.len())
+            .step_by(2)
+            .map(|i| u8::from_str_radix(&cursor[i..i + 2], 16))
+            .collect::<Result<Vec<u8>, _>>()
+            .map_err(|_| invalid())?;
+        let raw = String::from_utf8(bytes).map_err(|_| invalid())?;
+        let parts: Vec<&str> = raw.splitn(6, '|').collect();
+        let ["v1", t, as_of, now, key, id] = parts.as_slice() else {
+            return Err(invalid());
+        };
+        if *t != tool {
+            return Err(invalid());
+        }
+        Ok(PageCursor {
+            tool: tool.to_string(),
+            as_of: as_of.parse().map_err(|_| invalid())?,
+            now: now.parse().map_err(|_| invalid())?,
+            key: f64::from_bits(u64::from_str_radix(key, 16).map_err(|_| invalid())?),
+            id: id.to_string(),
+        })
+    }
+
+    /// Search cursors carry `token:position` into a `SearchSnapshots` entry.
+    fn snapshot_position(&self) -> Result<(u64, usize), ErrorData> {
+        self.id
+            .split_once(':')
+            .and_then(|(t, p)| Some((t.parse().ok()?, p.parse().ok()?)))
+            .ok_or_else(|| {
+                ErrorData::new(
+                    ErrorCode::INVALID_PARAMS,
+                    format!("invalid cursor for {}", self.tool),
+                    None,
+                )
+            })
+    }
+
+    fn id_i64(&self) -> Result<i64, ErrorData> {
+        self.id.parse().map_err(|_| {
+            ErrorData::new(
+                ErrorCode::INVALID_PARAMS,
+                format!("invalid cursor for {}", self.tool),
+                None,
+            )
+        })
+    }
+}
+
+/// Paging request derived from the `cursor` param.
+/// `None` → legacy unpaged array response; `Some(None)` → first page.
+fn page_request(tool: &str, cursor: Option<&str>) -> Result<Option<Option<PageCursor>>, ErrorData> {
+    match cursor {
+        None => Ok(None),
+        Some("") => Ok(Some(None)),
+        Some(c) => PageCursor::decode(tool, c).map(|c| Some(Some(c))),
+    }
+}
+
+#[derive(Serialize)]
+struct Page<T> {
+    results: Vec<T>,
+    next_cursor: Option<String>,
+}
+
+/// Serialize a result set: a bare array when unpaged, else `{results, next_cursor}`.
+/// Paged queries fetch `limit + 1` rows; the extra row only signals another page.
+fn paged_json<T: Serialize>(
+    mut results: Vec<T>,
+    paged: bool,
+    limit: i64,
+    next: impl Fn(&T) -> PageCursor,
+) -> Result<String, ErrorData> {
+    if !paged {
+        return serde_json::to_string(&results).map_err(|e| db_err(&e));
+    }
+    let has_more = results.len() as i64 > limit;
+    results.truncate(limit as usize);
+    let next_cursor = if has_more { results.last().map(|r| next(r).encode()) } else { None };
+    serde_json::to_string(&Page { results, next_cursor }).map_err(|e| db_err(&e))
+}
+
+/// Counts over a search's full match set, not just the returned page.
+#[derive(Serialize, Default)]
+struct SearchFacets {
+    total: i64,
+    /// Distinct sessions among the matches.
+    sessions: i64,
+    obs_type: Vec<FacetCount>,
+    project: Vec<FacetCount>,
+    /// The busiest sessions, up to `MAX_SESSION_FACETS`.
+    session: Vec<FacetCount>,
+}
+
+#[derive(Serialize)]
+struct FacetCount {
+    value: String,
+    count: i64,
+}
+
+const MAX_SESSION_FACETS: usize = 10;
+
+impl SearchFacets {
+    /// Build from per-store `(dimension, value, count)` rows; the same value
+    /// from several stores is summed.
+    fn from_counts(rows: impl IntoIterator<Item = (String, String, i64)>) -> Self {
+        let mut dims: std::collections::BTreeMap<String, std::collections::BTreeMap<String, i64>> =
+            Default::default();
+        for (dim, value, count) in rows {
+            *dims.entry(dim).or_default().entry(value).or_default() += count;
+        }
+        let mut take = |dim: &str| -> Vec<FacetCount> {
+            let mut counts: Vec<FacetCount> = dims
+                .remove(dim)
+                .unwrap_or_default()
+                .into_iter()
+                .map(|(value, count)| FacetCount { value, count })
+                .collect();
+            counts.sort_by(|a, b| b.count.cmp(&a.count).then_with(|| a.value.cmp(&b.value)));
+            counts
+        };
+        let obs_type = take("obs_type");
+        let project = take("project");
+        let mut session = take("session");
+        let sessions = session.len() as i64;
+        session.truncate(MAX_SESSION_FACETS);
+        SearchFacets {
+            total: obs_type.iter().map(|f| f.count).sum(),
+            sessions,
+            obs_type,
+            project,
+            session,
+        }
+    }
+}
+
+/// Wrap a search response (bare array or `{results, next_cursor}`) with facets.
+fn with_facets(json: String, facets: &SearchFacets) -> Result<String, ErrorData> {
+    let mut value: serde_json::Value = serde_json::from_str(&json).map_err(|e| db_err(&e))?;
+    if value.is_array() {
+        value = serde_json::json!({ "results": value });
+    }
+    value["facets"] = serde_json::to_value(facets).map_err(|e| db_err(&e))?;
+    serde_json::to_string(&value).map_err(|e| db_err(&e))
+}
+
+/// Wrap a search response (bare array or object) with the inferred project.
+fn with_inferred_project(json: String, inferred: &crate::s1_infer::InferredProject) -> Result<String, ErrorData> {
+    let mut value: serde_json::Value = serde_json::from_str(&json).map_err(|e| db_err(&e))?;
+    if value.is_array() {
+        value = serde_json::json!({ "results": value });
+    }
+    value["inferred_project"] = serde_json::to_value(inferred).map_err(|e| db_err(&e))?;
+    serde_json::to_string(&value).map_err(|e| db_err(&e))
+}
+
+/// Chars of content kept per result in a compact response.
+const COMPACT_PREVIEW_CHARS: usize = 120;
+
+/// Legend for the short keys of a compact response.
+const COMPACT_KEYS: &str =
+    "i=id t=timestamp k=obs_type s=index into sessions f=file_path p=content preview pin=pinned o=origin c=chunk";
+
+/// Rewrite an observation list response (bare array or `{results, ...}`) in
+/// compact form: short keys, previews only, and each session listed once in
+/// a `sessions` header that results point into. Projects are looked up in
+/// `db`; sessions from attached stores carry only their id.
+fn compact_json(db: &Connection, json: String) -> Result<String, ErrorData> {
+    let value: serde_json::Value = serde_json::from_str(&json).map_err(|e| db_err(&e))?;
+    let (items, mut rest) = match value {
+        serde_json::Value::Array(items) => (items, serde_json::Map::new()),
+        serde_json::Value::Object(mut obj) => match obj.remove("results") {
+            Some(serde_json::Value::Array(items)) => (items, obj),
+            _ => return serde_json::to_string(&obj).map_err(|e| db_err(&e)),
+        },
+        other => return serde_json::to_string(&other).map_err(|e| db_err(&e)),
+    };
+
+    let mut sessions: Vec<String> = Vec::new();
+    let results: Vec<serde_json::Value> = items
+        .into_iter()
+        .map(|item| {
+            let mut out = serde_json::Map::new();
+            out.insert("i".into(), item["id"].clone());
+            out.insert("t".into(), item["timestamp"].clone());
+            out.insert("k".into(), item["obs_type"].clone());
+            if let Some(sid) = item["session_id"].as_str() {
+                let idx = sessions.iter().position(|s| s == sid).unwrap_or_else(|| {
+                    sessions.push(sid.to_string());
+                    sessions.len() - 1
+                });
+                out.insert("s".into(), idx.into());
+            }
+            if !item["file_path"].is_null() {
+                out.insert("f".into(), item["file_path"].clone());
+            }
+            let text = item["content_preview"].as_str().or_else(|| item["content"].as_str()).unwrap_or("");
+            out.insert("p".into(), text.chars().take(COMPACT_PREVIEW_CHARS).collect::<String>().into());
+            if item["is_pinned"].as_bool() == Some(true) {
+                out.insert("pin".into(), 1.into());
+            }
+            for (long, short) in [("origin", "o"), ("chunk", "c")] {
+                if !item[long].is_null() {
+                    out.insert(short.into(), item[long].clone());
+                }
+            }
+            serde_json::Value::Object(out)
+        })
+        .collect();
+
+    let mut stmt = db.prepare_cached("SELECT project FROM sessions WHERE id = ?1").map_err(|e| db_err(&e))?;
+    let sessions = sessions
+        .into_iter()
+        .map(|id| {
+            let project: Option<String> = stmt
+                .query_row([&id], |r| r.get(0))
+                .optional()
+                .map_err(|e| db_err(&e))?;
+            Ok(match project {
+                Some(project) => serde_json::json!({ "id": id, "project": project }),
+                None => serde_json::json!({ "id": id }),
+            })
+        })
+        .collect::<Result<Vec<_>, ErrorData>>()?;
+
+    let mut out = serde_json::Map::new();
+    out.insert("keys".into(), COMPACT_KEYS.into());
+    out.insert("sessions".into(), sessions.into());
+    out.insert("results".into(), results.into());
+    out.append(&mut rest);
+    serde_json::to_string(&out).map_err(|e| db_err(&e))
+}
+
+/// Smallest `max_bytes` honored.
+const MIN_MAX_BYTES: usize = 256;
+
+/// Bytes kept free for the `truncated`/`omitted` fields added to a cut response.
+const TRUNCATION_FLAG_BYTES: usize = 48;
+
+/// Path (keys and indices) to the value in `root` that `measure` rates
+/// highest, with its rating. Values `measure` skips are still descended.
+fn largest_by(
+    root: &serde_json::Value,
+    measure: fn(&serde_json::Value) -> Option<usize>,
+) -> Option<(usize, Vec<serde_json::Value>)> {
+    fn walk(
+        value: &serde_json::Value,
+        measure: fn(&serde_json::Value) -> Option<usize>,
+        path: &mut Vec<serde_json::Value>,
+        best: &mut Option<(usize, Vec<serde_json::Value>)>,
+    ) {
+        if let Some(n) = measure(value)
+            && best.as_ref().is_none_or(|(m, _)| n > *m)
+        {
+            *best = Some((n, path.clone()));
+        }
+        match value {
+            serde_json::Value::Array(items) => {
+                for (i, item) in items.iter().enumerate() {
+                    path.push(i.into());
+                    walk(item, measure, path, best);
+                    path.pop();
+                }
+            }
+            serde_json::Value::Object(obj) => {
+                for (k, v) in obj {
+                    path.push(k.as_str().into());
+                    walk(v, measure, path, best);
+                    path.pop();
+                }
+            }
+            _ => {}
+        }
+    }
+    let mut best = None;
+    walk(root, measure, &mut Vec::new(), &mut best);
+    best
+}
+
+fn at_path<'a>(value: &'a mut serde_json::Value, path: &[serde_json::Value]) -> &'a mut serde_json::Value {
+    path.iter().fold(value, |v, step| match step {
+        serde_json::Value::String(k) => &mut v[k.as_str()],
+        step => &mut v[step.as_u64().unwrap_or(0) as usize],
+    })
+}
+
+/// Cut `text` to at most `max_bytes`. JSON loses trailing elements of its
+/// longest arrays, then long strings are shortened, and the top level gains
+/// `truncated: true` and `omitted` (elements dropped); a bare array is
+/// wrapped as `results`. Other text is cut and ends with a
+/// `[truncated: true …]` line.
+fn cap_text(text: String, max_bytes: usize) -> String {
+    let max_bytes = max_bytes.max(MIN_MAX_BYTES);
+    if text.len() <= max_bytes {
+        return text;
+    }
+    let Ok(mut value) = serde_json::from_str::<serde_json::Value>(&text) else {
+        let marker = format!("\n[truncated: true — {max_bytes} of {} bytes shown]", text.len());
+        let mut end = max_bytes.saturating_sub(marker.len());
+        while !text.is_char_boundary(end) {
+            end -= 1;
+        }
+        return format!("{}{marker}", &text[..end]);
+    };
+
+    let budget = max_bytes - TRUNCATION_FLAG_BYTES;
+    let mut size = text.len();
+    let mut omitted = 0usize;
+    while size > budget {
+        let longest_array = largest_by(&value, |v| v.as_array().map(Vec::len).filter(|n| *n > 0));
+        let Some((_, path)) = longest_array else { break };
+        let serde_json::Value::Array(items) = at_path(&mut value, &path) else { break };
+        // Pop until the estimate fits, then re-measure for real
+        while size > budget {
+            let Some(item) = items.pop() else { break };
+            size = size.saturating_sub(item.to_string().len() + 1);
+            omitted += 1;
+        }
+        size = value.to_string().len();
+    }
+    while size > budget {
+        let longest = largest_by(&value, |v| v.as_str().map(str::len));
+        let Some((len, path)) = longest.filter(|(len, _)| *len > 64) else { break };
+        // At most three quarters, so the "…" never makes it longer
+        let target = len.saturating_sub(size - budget + 3).min(len * 3 / 4).max(32);
+        let slot = at_path(&mut value, &path);
+        let text = slot.as_str().unwrap_or_default();
+        let mut end = target;
+        while !text.is_char_boundary(end) {
+            end -= 1;
+        }
+        *slot = format!("{}…", &text[..end]).into();
+        size = value.to_string().len();
+    }
+
+    if !value.is_object() {
+        value = serde_json::json!({ "results": value });
+    }
+    value["truncated"] = true.into();
+    value["omitted"] = omitted.into();
+    let out = value.to_string();
+    if out.len() <= max_bytes {
+        out
+    } else {
+        serde_json::json!({ "truncated": true, "error": format!("response does not fit in {max_bytes} bytes") }).to_string()
+    }
+}
+
+/// Apply a tool's `max_bytes` to its successful text response.
+fn cap_response(result: Result<CallToolResult, ErrorData>, max_bytes: Option<usize>) -> Result<CallToolResult, ErrorData> {
+    let Some(max_bytes) = max_bytes else {
+        return result;
+    };
+    let mut result = result?;
+    if result.is_error.unwrap_or(false) {
+        return Ok(result);
+    }
+    if let Some(text) = result.content.first().and_then(|c| c.as_text()).map(|t| t.text.clone()) {
+        result.content[0] = Content::text(cap_text(text, max_bytes));
+    }
+    Ok(result)
+}
+
+/// Flag a search response served by the scan fallback.
+/// `limit`, clamped, when the caller gave one; else the configured or
+/// adaptive default for `project`, returned too when the response should
+/// report it.
+fn default_limit(
+    db: &Connection,
+    limit: Option<i64>,
+    config: &crate::s5_config::NmemConfig,
+    project: Option<&str>,
+    search: bool,
+) -> Result<(i64, Option<crate::s3_limits::EffectiveLimit>), ErrorData> {
+    if limit.is_some() {
+        return Ok((clamp(limit, 1, 100), None));
+    }
+    let limits = &config.serve.limits;
+    let effective = if search {
+        crate::s3_limits::search_limit(db, limits, project, unix_now())
+    } else {
+        crate::s3_limits::recent_context_limit(db, limits, project, unix_now())
+    }
+    .map_err(|e| db_err(&e))?;
+    Ok((effective.limit, effective.is_reported().then_some(effective)))
+}
+
+/// Wrap a response (bare array or object) with the default limit it used.
+fn with_limits(json: String, effective: &crate::s3_limits::EffectiveLimit) -> Result<String, ErrorData> {
+    let mut value: serde_json::Value = serde_json::from_str(&json).map_err(|e| db_err(&e))?;
+    if value.is_array() {
+        value = serde_json::json!({ "results": value });
+    }
+    value["limits"] = serde_json::to_value(effective).map_err(|e| db_err(&e))?;
+    serde_json::to_string(&value).map_err(|e| db_err(&e))
+}
+
+fn with_degraded(json: String) -> Result<String, ErrorData> {
+    let mut value: serde_json::Value = serde_json::from_str(&json).map_err(|e| db_err(&e))?;
+    if value.is_array() {
+        value = serde_json::json!({ "results": value });
+    }
+    value["degraded"] = serde_json::json!({
+        "reason": "fts_unavailable",
+        "scanned": crate::s1_search::SCAN_FALLBACK_ROWS,
+    });
+    serde_json::to_string(&value).map_err(|e| db_err(&e))
+}
+
+/// Observations the `grep` tool examines at most per call.
+const GREP_MAX_SCAN: i64 = 20_000;
+
+/// Score added to observations from the calling session's lineage — enough
+/// to lift them over equally recent observations, not over much newer ones.
+const LINEAGE_BOOST: f64 = 0.2;
+
+/// `session_id` and the sessions it continues, as a JSON array for `json_each`.
+fn lineage_json(db: &Connection, session_id: &str) -> Result<String, ErrorData> {
+    let lineage = crate::s1_record::session_lineage(db, session_id).map_err(|e| db_err(&e))?;
+    serde_json::to_string(&lineage).map_err(|e| db_err(&e))
+}
+
+fn max_observation_id(db: &Connection) -> Result<i64, ErrorData> {
+    db.query_row("SELECT COALESCE(MAX(id), 0) FROM observations", [], |r| r.get(0))
+        .map_err(|e| db_err(&e))
+}
+
+/// The caller's project: `project` if given, else derived from `cwd` (the
+/// server's working directory when `None`), as the CLI does.
+fn resolve_project(project: Option<&str>, cwd: Option<&str>, config: &crate::s5_config::NmemConfig) -> String {
+    if let Some(p) = project {
+        return p.to_string();
+    }
+    let cwd = cwd.map(String::from).unwrap_or_else(|| {
+        std::env::current_dir()
+            .map(|p| p.to_string_lossy().into_owned())
+            .unwrap_or_default()
+    });
+    crate::s5_project::derive_project_with_strategy(&cwd, config.project.strategy)
+}
+
+fn write_err(e: &NmemError) -> ErrorData {
+    match e {
+        NmemError::Config(msg) => ErrorData::new(ErrorCode::INVALID_PARAMS, msg.clone(), None),
+        other => db_err(other),
+    }
+}
+
+fn unix_now() -> i64 {
+    std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map(|d| d.as_secs() as i64)
+        .unwrap_or(0)
+}
+
+/// The window `name` refers to now, or `INVALID_PARAMS` naming the known ones.
+fn resolve_window(name: &str) -> Result<crate::s4_window::Window, ErrorData> {
+    let config = crate::s5_config::load_config().unwrap_or_default();
+    crate::s4_window::resolve(&config, name, unix_now()).map_err(|e| {
+        let message = match e {
+            NmemError::Config(msg) => msg,
+            other => other.to_string(),
+        };
+        crate::s1_input::invalid("window", "invalid_value", message, serde_json::json!(crate::s4_window::names(&config)))
+    })
+}
+
+/// Narrow `after`/`before` to the named `window`, if one was given.
+fn apply_window(window: Option<&str>, after: &mut Option<i64>, before: &mut Option<i64>) -> Result<(), ErrorData> {
+    let Some(name) = window else {
+        return Ok(());
+    };
+    let window = resolve_window(name)?;
+    (*after, *before) = window.bounds(*after, *before);
+    if after >= before {
+        return Err(crate::s1_input::invalid(
+            "window",
+            "empty_range",
+            format!("{name} lies outside the after/before range"),
+            serde_json::json!([window.start, window.end]),
+        ));
+    }
+    Ok(())
+}
+
+fn record_query_metrics(tool: &str, start: std::time::Instant) {
+    let meter = opentelemetry::global::meter("nmem");
+    meter
+        .u64_counter("nmem_queries_total")
+        .build()
+        .add(1, &[opentelemetry::KeyValue::new("tool", tool.to_string())]);
+    meter
+        .f64_histogram("nmem_query_duration_seconds")
         .build()
         .record(
             start.elapsed().as_secs_f64(),
@@ -384,6 +1573,109 @@ fn record_query_metrics(tool: &str, start: std::time::Instant) {
         );
 }
 
+/// Same-file neighbours are looked for within this many seconds.
+const RELATED_FILE_WINDOW_SECS: i64 = 3600;
+/// Related observations returned per relation.
+const RELATED_PER_RELATION: i64 = 5;
+
+/// Observations a drill-down usually wants next, each found through an
+/// indexed column: a marker's thread (obs_type), file_path, error_class,
+/// prompt_id.
+fn related_observations(db: &Connection, obs: &FullObservation) -> rusqlite::Result<Vec<RelatedObservation>> {
+    use rusqlite::types::Value;
+
+    let (prompt_id, error_class): (Option<i64>, Option<String>) = db.query_row(
+        "SELECT prompt_id, error_class FROM observations WHERE id = ?1",
+        rusqlite::params![obs.id],
+        |r| Ok((r.get(0)?, r.get(1)?)),
+    )?;
+
+    let columns = "SELECT id, timestamp, obs_type, file_path, SUBSTR(content, 1, 120) FROM observations";
+    let mut lookups: Vec<(&'static str, String, Vec<Value>)> = Vec::new();
+    if obs.obs_type == "marker" {
+        let root = obs.metadata.as_ref().and_then(|m| m.get("thread")).and_then(|t| t.as_i64()).unwrap_or(obs.id);
+        lookups.push((
+            "thread",
+            format!(
+                "{columns} WHERE obs_type = 'marker' AND (id = ?1 OR json_extract(metadata, '$.thread') = ?1)
+                 AND id != ?2 ORDER BY timestamp DESC, id DESC"
+            ),
+            vec![Value::Integer(root), Value::Integer(obs.id)],
+        ));
+    }
+    if let Some(ref path) = obs.file_path {
+        lookups.push((
+            "same_file",
+            format!(
+                "{columns} WHERE file_path = ?1 AND timestamp BETWEEN ?2 AND ?3 AND id != ?4
+                 ORDER BY ABS(timestamp - ?5), id LIMIT ?6"
+            ),
+            vec![
+                Value::Text(path.clone()),
+                Value::Integer(obs.timestamp - RELATED_FILE_WINDOW_SECS),
+                Value::Integer(obs.timestamp + RELATED_FILE_WINDOW_SECS),
+                Value::Integer(obs.id),
+                Value::Integer(obs.timestamp),
+                Value::Integer(RELATED_PER_RELATION),
+            ],
+        ));
+    }
+    if let Some(class) = error_class {
+        lookups.push((
+            "same_error",
+            format!("{columns} WHERE error_class = ?1 AND id != ?2 ORDER BY timestamp DESC LIMIT ?3"),
+            vec![Value::Text(class), Value::Integer(obs.id), Value::Integer(RELATED_PER_RELATION)],
+        ));
+    }
+    if let Some(prompt_id) = prompt_id {
+        lookups.push((
+            "same_prompt",
+            format!("{columns} WHERE prompt_id = ?1 AND id != ?2 ORDER BY id LIMIT ?3"),
+            vec![Value::Integer(prompt_id), Value::Integer(obs.id), Value::Integer(RELATED_PER_RELATION)],
+        ));
+    }
+
+    let mut seen = std::collections::HashSet::new();
+    let mut related = Vec::new();
+    for (relation, sql, params) in lookups {
+        let mut stmt = db.prepare_cached(&sql)?;
+        let rows = stmt.query_map(rusqlite::params_from_iter(params), |r| {
+            Ok(RelatedObservation {
+                id: r.get(0)?,
+                relation,
+                timestamp: r.get(1)?,
+                obs_type: r.get(2)?,
+                file_path: r.get(3)?,
+                content_preview: r.get(4)?,
+            })
+        })?;
+        for row in rows {
+            let row = row?;
+            if seen.insert(row.id) {
+                related.push(row);
+            }
+        }
+    }
+    Ok(related)
+}
+
+/// One entry per run of a repeated command, each at its own time.
+fn expand_runs(db: &Connection, observation: &FullObservation) -> Result<Vec<FullObservation>, NmemError> {
+    let Some(ref repeats) = observation.repeats else {
+        return Ok(vec![observation.clone()]);
+    };
+    let runs = crate::s1_repeat::run_timestamps(db, observation.id)?;
+    Ok(runs
+        .into_iter()
+        .zip(1..)
+        .map(|(timestamp, run)| FullObservation {
+            timestamp,
+            repeats: Some(crate::s1_repeat::Repeats { run: Some(run), ..repeats.clone() }),
+            ..observation.clone()
+        })
+        .collect())
+}
+
 fn row_to_full_obs(row: &rusqlite::Row) -> rusqlite::Result<FullObservation> {
     let metadata_str: Option<String> = row.get(8)?;
     let metadata = metadata_str.and_then(|s| serde_json::from_str(&s).ok());
@@ -398,6 +1690,8 @@ fn row_to_full_obs(row: &rusqlite::Row) -> rusqlite::Result<FullObservation> {
         content: row.get(7)?,
         metadata,
         is_pinned: row.get::<_, i64>(9)? != 0,
+        corrections: Vec::new(),
+        repeats: None,
     })
 }
 
@@ -414,6 +1708,10 @@ struct ScoredObservation {
     metadata: Option<serde_json::Value>,
     is_pinned: bool,
     score: f64,
+    /// Recency half-life (days) that produced `score`.
+    half_life_days: f64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    origin: Option<String>,
 }
 
 fn row_to_scored_obs(row: &rusqlite::Row) -> rusqlite::Result<ScoredObservation> {
@@ -431,14 +1729,168 @@ fn row_to_scored_obs(row: &rusqlite::Row) -> rusqlite::Result<ScoredObservation>
         metadata,
         is_pinned: row.get::<_, i64>(9)? != 0,
         score: row.get(10)?,
+        half_life_days: row.get(11)?,
+        origin: None,
     })
 }
 
+/// Rows that can be tagged with the database they came from.
+/// Order search rows from several stores. BM25 depends on each store's own
+/// corpus statistics, so raw keys from different stores don't compare: each
+/// store's keys are rescaled to 0..1 (its best row 1) and rows merge on that,
+/// ties keeping store order. `higher_is_better` for blended scores; BM25
+/// ranks are better when lower.
+fn merge_by_store(rows: &mut [SearchResult], higher_is_better: bool) {
+    let mut bounds: std::collections::HashMap<Option<String>, (f64, f64)> = std::collections::HashMap::new();
+    for r in rows.iter() {
+        let b = bounds.entry(r.origin.clone()).or_insert((r.sort_key, r.sort_key));
+        b.0 = b.0.min(r.sort_key);
+        b.1 = b.1.max(r.sort_key);
+    }
+    let normalized = |r: &SearchResult| {
+        let (min, max) = bounds[&r.origin];
+        if max <= min {
+            return 1.0;
+        }
+        let t = (r.sort_key - min) / (max - min);
+        if higher_is_better { t } else { 1.0 - t }
+    };
+    rows.sort_by(|a, b| normalized(b).total_cmp(&normalized(a)));
+}
+
+trait Origin {
+    fn set_origin(&mut self, origin: &str);
+}
+
+impl Origin for SearchResult {
+    fn set_origin(&mut self, origin: &str) {
+        self.origin = Some(origin.to_string());
+    }
+}
+
+impl Origin for ScoredObservation {
+    fn set_origin(&mut self, origin: &str) {
+        self.origin = Some(origin.to_string());
+    }
+}
+
+impl Origin for SessionSummaryResult {
+    fn set_origin(&mut self, origin: &str) {
+        self.origin = Some(origin.to_string());
+    }
+}
+
 // --- Core query logic (pub for testing) ---
 
 impl NmemServer {
-    pub fn do_search(&self, params: SearchParams) -> Result<CallToolResult, ErrorData> {
-        let limit = clamp(params.limit, 20, 100);
+    /// Append rows from every attached store, tagging all rows (local ones as
+    /// `local`) with their origin. A store whose query fails — typically an
+    /// export from an older schema — is skipped with a warning.
+    fn federate<T: Origin>(
+        &self,
+        mut rows: Vec<T>,
+        query: impl Fn(&Connection) -> Result<Vec<T>, ErrorData>,
+    ) -> Result<Vec<T>, ErrorData> {
+        if self.attached.is_empty() {
+            return Ok(rows);
+        }
+        rows.iter_mut().for_each(|r| r.set_origin(LOCAL_ORIGIN));
+        for (name, handle) in &self.attached {
+            let db = handle.lock().map_err(|e| db_err(&e))?;
+            match query(&db) {
+                Ok(mut more) => {
+                    more.iter_mut().for_each(|r| r.set_origin(name));
+                    rows.extend(more);
+                }
+                Err(e) => log::warn!("attached db {name}: {}", e.message),
+            }
+        }
+        Ok(rows)
+    }
+
+    /// Search's fallback when `observations_fts` is unreadable. Filters the
+    /// scan doesn't take as `Filters` apply to its rows here.
+    fn scan_search(
+        &self,
+        db: &Connection,
+        query: &str,
+        params: &SearchParams,
+        fetch: i64,
+    ) -> Result<Vec<SearchResult>, ErrorData> {
+        let filters = crate::s1_search::Filters {
+            project: params.project.as_deref(),
+            obs_type: params.obs_type.as_deref(),
+            mcp_server: params.mcp_server.as_deref(),
+            mcp_tool: params.mcp_tool.as_deref(),
+            include_archived: params.include_archived.unwrap_or(false),
+            cwd: params.cwd.as_deref(),
+        };
+        let rows = crate::s1_search::scan_search(db, query, &filters, crate::s1_search::SCAN_FALLBACK_ROWS)
+            .map_err(|e| db_err(&e))?;
+        let mut error_class = params
+            .error_class
+            .as_ref()
+            .map(|_| db.prepare("SELECT error_class FROM observations WHERE id = ?1"))
+            .transpose()
+            .map_err(|e| db_err(&e))?;
+        let mut out = Vec::new();
+        for r in rows {
+            if out.len() as i64 >= fetch {
+                break;
+            }
+            if params.before.is_some_and(|b| r.timestamp >= b) || params.after.is_some_and(|a| r.timestamp <= a) {
+                continue;
+            }
+            if let Some(stmt) = error_class.as_mut() {
+                let class: Option<String> = stmt.query_row([r.id], |row| row.get(0)).map_err(|e| db_err(&e))?;
+                if class != params.error_class {
+                    continue;
+                }
+            }
+            out.push(SearchResult {
+                id: r.id,
+                timestamp: r.timestamp,
+                obs_type: r.obs_type,
+                content_preview: r.content_preview,
+                file_path: r.file_path,
+                session_id: r.session_id,
+                is_pinned: r.is_pinned,
+                half_life_days: None,
+                sort_key: 0.0,
+                origin: None,
+                chunk: None,
+            });
+        }
+        Ok(out)
+    }
+
+    /// Start a background FTS rebuild of the primary store, once per server.
+    /// In-memory stores have nothing to repair from another process.
+    fn repair_fts(&self, db: &Connection) {
+        let Some(path) = db.path().filter(|p| !p.is_empty()) else {
+            return;
+        };
+        if !self.fts_repair.swap(true, Ordering::SeqCst) {
+            crate::s3_maintain::spawn_fts_repair(Path::new(path));
+        }
+    }
+
+    /// Cursors snapshot one database's ids, so they can't page a merge.
+    fn check_federated_paging(&self, paged: bool) -> Result<(), ErrorData> {
+        if paged && !self.attached.is_empty() {
+            return Err(ErrorData::new(
+                ErrorCode::INVALID_PARAMS,
+                "cursor paging is not supported with attached databases; use limit/offset",
+                None,
+            ));
+        }
+        Ok(())
+    }
+
+    pub fn do_search(&self, mut params: SearchParams) -> Result<CallToolResult, ErrorData> {
+        params.validate()?;
+        let filters = search_filters(&params);
+        apply_window(params.window.as_deref(), &mut params.after, &mut params.before)?;
         let offset = params.offset.unwrap_or(0).max(0);
 
         let query = match crate::sanitize_fts_query(&params.query) {
@@ -458,22 +1910,86 @@ impl NmemServer {
             }
         };
 
+        if let Some(ref class) = params.error_class
+            && !crate::s2_error::ERROR_CLASSES.contains(&class.as_str())
+        {
+            return Err(ErrorData::new(
+                ErrorCode::INVALID_PARAMS,
+                format!("invalid error_class: {class:?} (expected one of {})", crate::s2_error::ERROR_CLASSES.join(", ")),
+                None,
+            ));
+        }
+
+        if let Some(ref cwd) = params.cwd {
+            params.cwd = Some(crate::s1_extract::normalize_cwd(cwd).ok_or_else(|| {
+                ErrorData::new(ErrorCode::INVALID_PARAMS, format!("cwd must be an absolute path, got {cwd:?}"), None)
+            })?);
+        }
+
+        let page = page_request("search", params.cursor.as_deref())?;
+        if matches!(page, Some(Some(_))) && offset > 0 {
+            return Err(ErrorData::new(
+                ErrorCode::INVALID_PARAMS,
+                "offset and cursor are mutually exclusive",
+                None,
+            ));
+        }
+
         let db = self.db.lock().map_err(|e| db_err(&e))?;
 
+        let inferred = if params.project.is_none() && params.infer_project.unwrap_or(true) {
+            self.infer_project(&db, &params.query)?
+        } else {
+            None
+        };
+        if let Some(ref inferred) = inferred {
+            params.project = Some(inferred.project.clone());
+        }
+
+        let config = crate::s5_config::load_config().unwrap_or_default();
+        let half_life = crate::s5_config::resolve_recency_half_life(&config, params.project.as_deref());
+        let (limit, effective) = default_limit(&db, params.limit, &config, params.project.as_deref(), true)?;
+
+        let paged = page.is_some();
+        self.check_federated_paging(paged)?;
+        let (as_of, now, resume) = match page {
+            Some(Some(ref c)) => (Some(c.as_of), c.now, Some(c.snapshot_position()?)),
+            Some(None) => (Some(max_observation_id(&db)?), unix_now(), None),
+            None => (None, unix_now(), None),
+        };
+        // Federated, each store returns its top offset + limit and the merge
+        // pages; a first page ranks the whole snapshot later pages slice.
+        let federated = !self.attached.is_empty();
+        let (fetch, sql_offset) = if federated {
+            (limit + offset, 0)
+        } else if paged {
+            (SEARCH_SNAPSHOT_ROWS, offset)
+        } else {
+            (limit, offset)
+        };
+
+        // Relevance is (rank ASC, id ASC), blended is (score DESC, id DESC).
         let sql = if blended {
-            "WITH fts_matches AS (
+            concat!("WITH ", crate::s1_chunk::fts_best_cte!(), ",
+            fts_matches AS (
                 SELECT o.id, o.timestamp, o.obs_type,
-                       SUBSTR(o.content, 1, 120) AS content_preview,
+                       COALESCE(f.snippet, SUBSTR(o.content, 1, 120)) AS content_preview,
                        o.file_path, o.session_id, o.is_pinned,
+                       f.chunk_seq, f.chunk_start, f.chunk_end,
                        f.rank AS raw_rank
                 FROM observations o
                 JOIN sessions s ON o.session_id = s.id
-                JOIN observations_fts f ON o.id = f.rowid
-                WHERE observations_fts MATCH ?1
-                  AND (?2 IS NULL OR s.project = ?2)
+                JOIN fts_best f ON o.id = f.obs_id
+                WHERE (?2 IS NULL OR s.project = ?2)
                   AND (?3 IS NULL OR o.obs_type = ?3)
                   AND (?4 IS NULL OR o.timestamp < ?4)
                   AND (?5 IS NULL OR o.timestamp > ?5)
+                  AND (?8 IS NULL OR o.error_class = ?8)
+                  AND (?12 IS NULL OR o.mcp_server = ?12)
+                  AND (?13 IS NULL OR o.mcp_tool = ?13)
+                  AND ", crate::s1_search::cwd_under_sql!("?15"), "
+                  AND (?14 OR ?2 IS NOT NULL OR NOT EXISTS (SELECT 1 FROM archived_projects a WHERE a.project = s.project))
+                  AND (?11 IS NULL OR o.id <= ?11)
             ),
             rank_bounds AS (
                 SELECT MIN(raw_rank) AS min_r, MAX(raw_rank) AS max_r FROM fts_matches
@@ -483,39 +1999,57 @@ impl NmemServer {
                        CASE WHEN b.max_r = b.min_r THEN 1.0
                             ELSE (m.raw_rank - b.max_r) / (b.min_r - b.max_r)
                        END AS bm25_norm,
-                       exp_decay((unixepoch('now') - m.timestamp) / 86400.0, 7.0) AS recency,
-                       CASE m.obs_type
-                           WHEN 'file_edit' THEN 1.0 WHEN 'command' THEN 0.67
-                           WHEN 'session_compact' THEN 0.5 WHEN 'mcp_call' THEN 0.33
-                           ELSE 0.17
-                       END AS type_w
+                       exp_decay((?10 - m.timestamp) / 86400.0, ?9) AS recency
                 FROM fts_matches m, rank_bounds b
+            ),
+            blended AS (
+                SELECT *, search_score(bm25_norm, recency, obs_type, is_pinned) AS score FROM scored
             )
-            SELECT id, timestamp, obs_type, content_preview, file_path, session_id, is_pinned
-            FROM scored
-            ORDER BY (bm25_norm * 0.5 + recency * 0.3 + type_w * 0.2) DESC
-            LIMIT ?6 OFFSET ?7"
+            SELECT id, timestamp, obs_type, content_preview, file_path, session_id, is_pinned, score,
+                   chunk_seq, chunk_start, chunk_end
+            FROM blended
+            ORDER BY score DESC, id DESC
+            LIMIT ?6 OFFSET ?7")
         } else {
-            "SELECT o.id, o.timestamp, o.obs_type,
-                    SUBSTR(o.content, 1, 120) AS content_preview,
-                    o.file_path, o.session_id, o.is_pinned
-             FROM observations o
-             JOIN sessions s ON o.session_id = s.id
-             JOIN observations_fts f ON o.id = f.rowid
-             WHERE observations_fts MATCH ?1
-               AND (?2 IS NULL OR s.project = ?2)
-               AND (?3 IS NULL OR o.obs_type = ?3)
-               AND (?4 IS NULL OR o.timestamp < ?4)
-               AND (?5 IS NULL OR o.timestamp > ?5)
-             ORDER BY f.rank
-             LIMIT ?6 OFFSET ?7"
+            concat!("WITH ", crate::s1_chunk::fts_best_cte!(), ",
+            fts_matches AS (
+                SELECT o.id, o.timestamp, o.obs_type,
+                       COALESCE(f.snippet, SUBSTR(o.content, 1, 120)) AS content_preview,
+                       o.file_path, o.session_id, o.is_pinned,
+                       f.chunk_seq, f.chunk_start, f.chunk_end,
+                       f.rank AS raw_rank
+                FROM observations o
+                JOIN sessions s ON o.session_id = s.id
+                JOIN fts_best f ON o.id = f.obs_id
+                WHERE (?2 IS NULL OR s.project = ?2)
+                  AND (?3 IS NULL OR o.obs_type = ?3)
+                  AND (?4 IS NULL OR o.timestamp < ?4)
+                  AND (?5 IS NULL OR o.timestamp > ?5)
+                  AND (?8 IS NULL OR o.error_class = ?8)
+                  AND (?12 IS NULL OR o.mcp_server = ?12)
+                  AND (?13 IS NULL OR o.mcp_tool = ?13)
+                  AND ", crate::s1_search::cwd_under_sql!("?15"), "
+                  AND (?14 OR ?2 IS NOT NULL OR NOT EXISTS (SELECT 1 FROM archived_projects a WHERE a.project = s.project))
+                  AND (?11 IS NULL OR o.id <= ?11)
+            )
+            SELECT id, timestamp, obs_type, content_preview, file_path, session_id, is_pinned, raw_rank,
+                   chunk_seq, chunk_start, chunk_end
+            FROM fts_matches
+            ORDER BY raw_rank, id
+            LIMIT ?6 OFFSET ?7")
         };
 
-        let mut stmt = db.prepare(sql).map_err(|e| db_err(&e))?;
-
-        let results: Vec<SearchResult> = stmt
-            .query_map(
-                rusqlite::params![query, params.project, params.obs_type, params.before, params.after, limit, offset],
+        let include_archived = params.include_archived.unwrap_or(false);
+        let result_half_life = blended.then_some(half_life);
+        let query_fts = |db: &Connection| -> rusqlite::Result<Vec<SearchResult>> {
+            let mut stmt = db.prepare(sql)?;
+            let bind: [&dyn rusqlite::ToSql; 15] = [
+                &query, &params.project, &params.obs_type, &params.before, &params.after, &fetch, &sql_offset,
+                &params.error_class, &half_life, &now, &as_of,
+                &params.mcp_server, &params.mcp_tool, &include_archived, &params.cwd,
+            ];
+            stmt.query_map(
+                bind.as_slice(),
                 |row| {
                     Ok(SearchResult {
                         id: row.get(0)?,
@@ -525,31 +2059,204 @@ impl NmemServer {
                         file_path: row.get(4)?,
                         session_id: row.get(5)?,
                         is_pinned: row.get::<_, i64>(6)? != 0,
+                        half_life_days: result_half_life,
+                        sort_key: row.get(7)?,
+                        origin: None,
+                        chunk: crate::s1_chunk::ChunkMatch::from_row(row, 8)?,
                     })
                 },
+            )?
+            .collect()
+        };
+        let fts_err = |e: rusqlite::Error| {
+            let msg = e.to_string();
+            if msg.contains("fts5") || msg.contains("syntax") {
+                return ErrorData::new(
+                    ErrorCode::INVALID_PARAMS,
+                    format!("FTS5 query error: {msg}"),
+                    None,
+                );
+            }
+            db_err(&e)
+        };
+        let run = |db: &Connection| {
+            self.ensure_udfs(db)?;
+            query_fts(db).map_err(fts_err)
+        };
+
+        // Facets ignore paging but keep the cursor's as_of snapshot
+        let count_facets = |db: &Connection| -> Result<Vec<(String, String, i64)>, ErrorData> {
+            let mut stmt = db
+                .prepare(
+                    concat!("WITH ", crate::s1_chunk::fts_best_cte!(), ",
+                    m AS (
+                        SELECT o.obs_type, s.project, o.session_id
+                        FROM observations o
+                        JOIN sessions s ON o.session_id = s.id
+                        JOIN fts_best f ON o.id = f.obs_id
+                        WHERE (?2 IS NULL OR s.project = ?2)
+                          AND (?3 IS NULL OR o.obs_type = ?3)
+                          AND (?4 IS NULL OR o.timestamp < ?4)
+                          AND (?5 IS NULL OR o.timestamp > ?5)
+                          AND (?6 IS NULL OR o.error_class = ?6)
+                          AND (?7 IS NULL OR o.mcp_server = ?7)
+                          AND (?8 IS NULL OR o.mcp_tool = ?8)
+                          AND ", crate::s1_search::cwd_under_sql!("?11"), "
+                          AND (?9 IS NULL OR o.id <= ?9)
+                          AND (?10 OR ?2 IS NOT NULL OR NOT EXISTS (SELECT 1 FROM archived_projects a WHERE a.project = s.project))
+                    )
+                    SELECT 'obs_type', obs_type, COUNT(*) FROM m GROUP BY obs_type
+                    UNION ALL SELECT 'project', project, COUNT(*) FROM m GROUP BY project
+                    UNION ALL SELECT 'session', session_id, COUNT(*) FROM m GROUP BY session_id"),
+                )
+                .map_err(|e| db_err(&e))?;
+            stmt.query_map(
+                rusqlite::params![
+                    query, params.project, params.obs_type, params.before, params.after,
+                    params.error_class, params.mcp_server, params.mcp_tool, as_of, include_archived,
+                    params.cwd,
+                ],
+                |r| Ok((r.get(0)?, r.get(1)?, r.get(2)?)),
             )
-            .map_err(|e| {
-                let msg = e.to_string();
-                if msg.contains("fts5") || msg.contains("syntax") {
-                    return ErrorData::new(
-                        ErrorCode::INVALID_PARAMS,
-                        format!("FTS5 query error: {msg}"),
-                        None,
-                    );
-                }
-                db_err(&e)
-            })?
+            .map_err(|e| db_err(&e))?
             .collect::<Result<_, _>>()
-            .map_err(|e| db_err(&e))?;
+            .map_err(|e| db_err(&e))
+        };
+        // An unreadable index degrades to a bounded scan of this store
+        // instead of failing; facets and paging are skipped.
+        let snapshot = match resume {
+            Some((token, _)) => {
+                let pages = self.search_pages.lock().map_err(|e| db_err(&e))?;
+                match pages.get(token) {
+                    Some(snapshot) if snapshot.filters == filters => Some(snapshot.results.clone()),
+                    Some(_) => {
+                        return Err(ErrorData::new(
+                            ErrorCode::INVALID_PARAMS,
+                            "search cursor belongs to a search with a different query or filters; \
+                             start again with cursor=\"\"",
+                            None,
+                        ));
+                    }
+                    None => {
+                        return Err(ErrorData::new(
+                            ErrorCode::INVALID_PARAMS,
+                            "search cursor expired; start again with cursor=\"\"",
+                            None,
+                        ));
+                    }
+                }
+            }
+            None => None,
+        };
+        let (local, degraded) = match snapshot.map_or_else(|| query_fts(&db), Ok) {
+            Ok(rows) => (rows, false),
+            Err(e) if crate::s1_search::is_fts_failure(&e) => {
+                log::warn!("search: full-text index unreadable, scanning instead: {e}");
+                self.repair_fts(&db);
+                (self.scan_search(&db, &query, &params, limit + offset)?, true)
+            }
+            Err(e) => return Err(fts_err(e)),
+        };
+        let paged = paged && !degraded;
+
+        let facets = if params.facets.unwrap_or(false) && !degraded {
+            let mut counts = count_facets(&db)?;
+            for (name, handle) in &self.attached {
+                let attached = handle.lock().map_err(|e| db_err(&e))?;
+                match count_facets(&attached) {
+                    Ok(more) => counts.extend(more),
+                    Err(e) => log::warn!("attached db {name}: {}", e.message),
+                }
+            }
+            Some(SearchFacets::from_counts(counts))
+        } else {
+            None
+        };
 
-        let json = serde_json::to_string(&results).map_err(|e| db_err(&e))?;
+        drop(db);
+        let mut results = self.federate(local, run)?;
+        if degraded {
+            // Scan rows have no rank to merge on: local newest first, then attached
+            results = results.into_iter().skip(offset as usize).take(limit as usize).collect();
+        } else if federated {
+            merge_by_store(&mut results, blended);
+            results = results.into_iter().skip(offset as usize).take(limit as usize).collect();
+        }
+
+        let json = if paged {
+            let (token, start) = match resume {
+                Some(position) => position,
+                None if results.len() as i64 > limit => {
+                    let mut pages = self.search_pages.lock().map_err(|e| db_err(&e))?;
+                    (pages.store(&filters, results.clone()), 0)
+                }
+                None => (0, 0),
+            };
+            let end = (start + limit as usize).min(results.len());
+            let next_cursor = (end < results.len()).then(|| {
+                PageCursor {
+                    tool: "search".into(),
+                    as_of: as_of.unwrap_or(0),
+                    now,
+                    key: 0.0,
+                    id: format!("{token}:{end}"),
+                }
+                .encode()
+            });
+            let page = results.get(start..end).unwrap_or_default().to_vec();
+            serde_json::to_string(&Page { results: page, next_cursor }).map_err(|e| db_err(&e))?
+        } else {
+            serde_json::to_string(&results).map_err(|e| db_err(&e))?
+        };
+        let json = if params.compact.unwrap_or(false) {
+            let db = self.db.lock().map_err(|e| db_err(&e))?;
+            compact_json(&db, json)?
+        } else {
+            json
+        };
+        let json = match facets {
+            Some(ref f) => with_facets(json, f)?,
+            None => json,
+        };
+        let json = match inferred {
+            Some(ref p) => with_inferred_project(json, p)?,
+            None => json,
+        };
+        let json = if degraded { with_degraded(json)? } else { json };
+        let json = match effective {
+            Some(ref e) => with_limits(json, e)?,
+            None => json,
+        };
         Ok(CallToolResult::success(vec![Content::text(json)]))
     }
 
+    /// Project inferred from paths in `query`, counting matches in the
+    /// primary and every attached store.
+    fn infer_project(
+        &self,
+        db: &Connection,
+        query: &str,
+    ) -> Result<Option<crate::s1_infer::InferredProject>, ErrorData> {
+        let paths = crate::s1_infer::mentioned_paths(query);
+        if paths.is_empty() {
+            return Ok(None);
+        }
+        let mut counts = crate::s1_infer::path_project_counts(db, &paths).map_err(|e| db_err(&e))?;
+        for (name, handle) in &self.attached {
+            let attached = handle.lock().map_err(|e| db_err(&e))?;
+            match crate::s1_infer::path_project_counts(&attached, &paths) {
+                Ok(more) => counts.extend(more),
+                Err(e) => log::warn!("attached db {name}: {e}"),
+            }
+        }
+        Ok(crate::s1_infer::pick_project(&counts))
+    }
+
     pub fn do_get_observations(
         &self,
         params: GetObservationsParams,
     ) -> Result<CallToolResult, ErrorData> {
+        params.validate()?;
         let ids = &params.ids;
 
         if ids.is_empty() {
@@ -557,11 +2264,6 @@ impl NmemServer {
                 "ids array must not be empty",
             )]));
         }
-        if ids.len() > 50 {
-            return Ok(CallToolResult::error(vec![Content::text(
-                "ids array must not exceed 50 elements",
-            )]));
-        }
 
         let db = self.db.lock().map_err(|e| db_err(&e))?;
 
@@ -570,12 +2272,14 @@ impl NmemServer {
             .enumerate()
             .map(|(i, _)| format!("?{}", i + 1))
             .collect();
+        // Chunked content is stitched back together
         let sql = format!(
             "SELECT o.id, o.timestamp, o.session_id, o.obs_type, o.source_event,
-                    o.tool_name, o.file_path, o.content, o.metadata, o.is_pinned
+                    o.tool_name, o.file_path, {} AS content, o.metadata, o.is_pinned
              FROM observations o
              WHERE o.id IN ({})
              ORDER BY CASE o.id {} END",
+            crate::s1_chunk::full_content_sql!(),
             placeholders.join(", "),
             ids.iter()
                 .enumerate()
@@ -593,17 +2297,36 @@ impl NmemServer {
         let param_refs: Vec<&dyn rusqlite::types::ToSql> =
             sql_params.iter().map(|b| b.as_ref()).collect();
 
-        let results: Vec<FullObservation> = stmt
+        let mut results: Vec<FullObservation> = stmt
             .query_map(param_refs.as_slice(), row_to_full_obs)
             .map_err(|e| db_err(&e))?
             .collect::<Result<_, _>>()
             .map_err(|e| db_err(&e))?;
+        for observation in &mut results {
+            observation.corrections = crate::s1_correct::corrections(&db, observation.id).map_err(|e| db_err(&e))?;
+            observation.repeats = crate::s1_repeat::repeats(&db, observation.id).map_err(|e| db_err(&e))?;
+        }
 
-        let json = serde_json::to_string(&results).map_err(|e| db_err(&e))?;
+        let json = if params.related.unwrap_or(false) {
+            let with_related = results
+                .into_iter()
+                .map(|observation| {
+                    let related = related_observations(&db, &observation)?;
+                    Ok(ObservationWithRelated { observation, related })
+                })
+                .collect::<rusqlite::Result<Vec<_>>>()
+                .map_err(|e| db_err(&e))?;
+            serde_json::to_string(&with_related)
+        } else {
+            serde_json::to_string(&results)
+        }
+        .map_err(|e| db_err(&e))?;
+        let json = if params.compact.unwrap_or(false) { compact_json(&db, json)? } else { json };
         Ok(CallToolResult::success(vec![Content::text(json)]))
     }
 
     pub fn do_timeline(&self, params: TimelineParams) -> Result<CallToolResult, ErrorData> {
+        params.validate()?;
         let before_count = clamp(params.before, 5, 50);
         let after_count = clamp(params.after, 5, 50);
 
@@ -660,7 +2383,7 @@ impl NmemServer {
             )
             .map_err(|e| db_err(&e))?;
 
-        let after: Vec<FullObservation> = after_stmt
+        let mut after: Vec<FullObservation> = after_stmt
             .query_map(
                 rusqlite::params![session_id, params.anchor, after_count],
                 row_to_full_obs,
@@ -669,6 +2392,29 @@ impl NmemServer {
             .collect::<Result<_, _>>()
             .map_err(|e| db_err(&e))?;
 
+        let mut anchor = anchor;
+        anchor.repeats = crate::s1_repeat::repeats(&db, anchor.id).map_err(|e| db_err(&e))?;
+        for observation in before.iter_mut().chain(after.iter_mut()) {
+            observation.repeats = crate::s1_repeat::repeats(&db, observation.id).map_err(|e| db_err(&e))?;
+        }
+
+        // Expanded, the anchor stands for its first run; the rest lead `after`
+        let (before, after) = if params.expand_repeats.unwrap_or(false) {
+            let mut anchor_runs = expand_runs(&db, &anchor).map_err(|e| db_err(&e))?;
+            let mut expanded_after = anchor_runs.split_off(1);
+            anchor = anchor_runs.remove(0);
+            for observation in &after {
+                expanded_after.extend(expand_runs(&db, observation).map_err(|e| db_err(&e))?);
+            }
+            let mut expanded_before = Vec::new();
+            for observation in &before {
+                expanded_before.extend(expand_runs(&db, observation).map_err(|e| db_err(&e))?);
+            }
+            (expanded_before, expanded_after)
+        } else {
+            (before, after)
+        };
+
         let result = TimelineResult {
             anchor,
             before,
@@ -676,108 +2422,170 @@ impl NmemServer {
         };
 
         let json = serde_json::to_string(&result).map_err(|e| db_err(&e))?;
-        Ok(CallToolResult::success(vec![Content::text(json)]))
-    }
-
-    pub fn do_recent_context(
-        &self,
-        params: RecentContextParams,
-    ) -> Result<CallToolResult, ErrorData> {
-        let limit = clamp(params.limit, 30, 100);
-
-        let db = self.db.lock().map_err(|e| db_err(&e))?;
-
-        let results: Vec<ScoredObservation> = if params.project.is_some() {
-            let sql = "WITH scored AS (
-                SELECT o.id, o.timestamp, o.session_id, o.obs_type, o.source_event,
-                       o.tool_name, o.file_path, o.content, o.metadata, o.is_pinned,
-                       exp_decay(
-                           (unixepoch('now') - o.timestamp) / 86400.0, 7.0
-                       ) AS recency,
-                       CASE o.obs_type
-                           WHEN 'file_edit' THEN 1.0 WHEN 'command' THEN 0.67
-                           WHEN 'session_compact' THEN 0.5 WHEN 'mcp_call' THEN 0.33
-                           ELSE 0.17
-                       END AS type_w,
-                       CASE WHEN s.project = ?1 THEN 1.0 ELSE 0.3 END AS proj_w
-                FROM observations o
-                JOIN sessions s ON o.session_id = s.id
-                WHERE (?2 IS NULL OR o.timestamp < ?2)
-                  AND (?3 IS NULL OR o.timestamp > ?3)
-            ),
-            ranked AS (
-                SELECT *,
-                       (recency * 0.5 + type_w * 0.3 + proj_w * 0.2) AS score,
-                       ROW_NUMBER() OVER (
-                           PARTITION BY COALESCE(file_path, CAST(id AS TEXT))
-                           ORDER BY (recency * 0.5 + type_w * 0.3 + proj_w * 0.2) DESC
-                       ) AS rn
-                FROM scored
-            )
-            SELECT id, timestamp, session_id, obs_type, source_event,
-                   tool_name, file_path, content, metadata, is_pinned, score
-            FROM ranked WHERE rn = 1
-            ORDER BY score DESC
-            LIMIT ?4";
-
-            let mut stmt = db.prepare(sql).map_err(|e| db_err(&e))?;
-            stmt.query_map(
-                rusqlite::params![params.project, params.before, params.after, limit],
-                row_to_scored_obs,
-            )
-            .map_err(|e| db_err(&e))?
-            .collect::<Result<_, _>>()
-            .map_err(|e| db_err(&e))?
-        } else {
-            let sql = "WITH scored AS (
-                SELECT o.id, o.timestamp, o.session_id, o.obs_type, o.source_event,
-                       o.tool_name, o.file_path, o.content, o.metadata, o.is_pinned,
-                       exp_decay(
-                           (unixepoch('now') - o.timestamp) / 86400.0, 7.0
-                       ) AS recency,
-                       CASE o.obs_type
-                           WHEN 'file_edit' THEN 1.0 WHEN 'command' THEN 0.67
-                           WHEN 'session_compact' THEN 0.5 WHEN 'mcp_call' THEN 0.33
-                           ELSE 0.17
-                       END AS type_w
-                FROM observations o
-                WHERE (?1 IS NULL OR o.timestamp < ?1)
-                  AND (?2 IS NULL OR o.timestamp > ?2)
-            ),
-            ranked AS (
-                SELECT *,
-                       (recency * 0.6 + type_w * 0.4) AS score,
-                       ROW_NUMBER() OVER (
-                           PARTITION BY COALESCE(file_path, CAST(id AS TEXT))
-                           ORDER BY (recency * 0.6 + type_w * 0.4) DESC
-                       ) AS rn
-                FROM scored
-            )
-            SELECT id, timestamp, session_id, obs_type, source_event,
-                   tool_name, file_path, content, metadata, is_pinned, score
-            FROM ranked WHERE rn = 1
-            ORDER BY score DESC
-            LIMIT ?3";
+        Ok(CallToolResult::success(vec![Content::text(json)]))
+    }
 
-            let mut stmt = db.prepare(sql).map_err(|e| db_err(&e))?;
-            stmt.query_map(rusqlite::params![params.before, params.after, limit], row_to_scored_obs)
+    pub fn do_recent_context(
+        &self,
+        mut params: RecentContextParams,
+    ) -> Result<CallToolResult, ErrorData> {
+        params.validate()?;
+        apply_window(params.window.as_deref(), &mut params.after, &mut params.before)?;
+        let page = page_request("recent_context", params.cursor.as_deref())?;
+        let config = crate::s5_config::load_config().unwrap_or_default();
+        let half_life = crate::s5_config::resolve_recency_half_life(&config, params.project.as_deref());
+
+        let db = self.db.lock().map_err(|e| db_err(&e))?;
+        let (limit, effective) = default_limit(&db, params.limit, &config, params.project.as_deref(), false)?;
+
+        let paged = page.is_some();
+        self.check_federated_paging(paged)?;
+        let (as_of, now, after_key, after_id) = match page {
+            Some(Some(ref c)) => (Some(c.as_of), c.now, Some(c.key), Some(c.id_i64()?)),
+            Some(None) => (Some(max_observation_id(&db)?), unix_now(), None, None),
+            None => (None, unix_now(), None, None),
+        };
+        let fetch = if paged { limit + 1 } else { limit };
+        let lineage = match params.session_id {
+            Some(ref sid) => Some(lineage_json(&db, sid)?),
+            None => None,
+        };
+
+        let run = |db: &Connection| -> Result<Vec<ScoredObservation>, ErrorData> {
+            self.ensure_udfs(db)?;
+            let rows = if params.project.is_some() {
+                let sql = "WITH scored AS (
+                    SELECT o.id, o.timestamp, o.session_id, o.obs_type, o.source_event,
+                           o.tool_name, o.file_path, o.content, o.metadata, o.is_pinned,
+                           exp_decay(
+                               (?6 - o.timestamp) / 86400.0, ?5
+                           ) AS recency,
+                           COALESCE(s.project = ?1, 0) AS in_project,
+                           CASE WHEN o.session_id IN (SELECT value FROM json_each(?10)) THEN ?11 ELSE 0.0 END AS lineage_w
+                    FROM observations o
+                    JOIN sessions s ON o.session_id = s.id
+                    WHERE (?2 IS NULL OR o.timestamp < ?2)
+                      AND (?3 IS NULL OR o.timestamp > ?3)
+                      AND (?7 IS NULL OR o.id <= ?7)
+                ),
+                blended AS (
+                    SELECT *, recent_score(recency, obs_type, is_pinned, in_project) + lineage_w AS score
+                    FROM scored
+                ),
+                ranked AS (
+                    SELECT *,
+                           ROW_NUMBER() OVER (
+                               PARTITION BY COALESCE(file_path, CAST(id AS TEXT))
+                               ORDER BY score DESC, id DESC
+                           ) AS rn
+                    FROM blended
+                )
+                SELECT id, timestamp, session_id, obs_type, source_event,
+                       tool_name, file_path, content, metadata, is_pinned, score, ?5
+                FROM ranked
+                WHERE rn = 1
+                  AND (?8 IS NULL OR score < ?8 OR (score = ?8 AND id < ?9))
+                ORDER BY score DESC, id DESC
+                LIMIT ?4";
+
+                let mut stmt = db.prepare(sql).map_err(|e| db_err(&e))?;
+                stmt.query_map(
+                    rusqlite::params![
+                        params.project, params.before, params.after, fetch, half_life,
+                        now, as_of, after_key, after_id, lineage, LINEAGE_BOOST
+                    ],
+                    row_to_scored_obs,
+                )
                 .map_err(|e| db_err(&e))?
                 .collect::<Result<_, _>>()
                 .map_err(|e| db_err(&e))?
+            } else {
+                let sql = "WITH scored AS (
+                    SELECT o.id, o.timestamp, o.session_id, o.obs_type, o.source_event,
+                           o.tool_name, o.file_path, o.content, o.metadata, o.is_pinned,
+                           exp_decay(
+                               (?5 - o.timestamp) / 86400.0, ?4
+                           ) AS recency,
+                           CASE WHEN o.session_id IN (SELECT value FROM json_each(?9)) THEN ?10 ELSE 0.0 END AS lineage_w
+                    FROM observations o
+                    WHERE (?1 IS NULL OR o.timestamp < ?1)
+                      AND (?2 IS NULL OR o.timestamp > ?2)
+                      AND (?6 IS NULL OR o.id <= ?6)
+                ),
+                blended AS (
+                    SELECT *, recent_score(recency, obs_type, is_pinned, NULL) + lineage_w AS score
+                    FROM scored
+                ),
+                ranked AS (
+                    SELECT *,
+                           ROW_NUMBER() OVER (
+                               PARTITION BY COALESCE(file_path, CAST(id AS TEXT))
+                               ORDER BY score DESC, id DESC
+                           ) AS rn
+                    FROM blended
+                )
+                SELECT id, timestamp, session_id, obs_type, source_event,
+                       tool_name, file_path, content, metadata, is_pinned, score, ?4
+                FROM ranked
+                WHERE rn = 1
+                  AND (?7 IS NULL OR score < ?7 OR (score = ?7 AND id < ?8))
+                ORDER BY score DESC, id DESC
+                LIMIT ?3";
+
+                let mut stmt = db.prepare(sql).map_err(|e| db_err(&e))?;
+                stmt.query_map(
+                    rusqlite::params![
+                        params.before, params.after, fetch, half_life, now, as_of, after_key, after_id,
+                        lineage, LINEAGE_BOOST
+                    ],
+                    row_to_scored_obs,
+                )
+                .map_err(|e| db_err(&e))?
+                .collect::<Result<_, _>>()
+                .map_err(|e| db_err(&e))?
+            };
+            Ok(rows)
         };
 
-        let json = serde_json::to_string(&results).map_err(|e| db_err(&e))?;
+        let local = run(&db)?;
+        drop(db);
+        let mut results = self.federate(local, run)?;
+        if !self.attached.is_empty() {
+            results.sort_by(|a, b| b.score.total_cmp(&a.score));
+            results.truncate(limit as usize);
+        }
+
+        let as_of = as_of.unwrap_or(0);
+        let json = paged_json(results, paged, limit, |r| PageCursor {
+            tool: "recent_context".into(),
+            as_of,
+            now,
+            key: r.score,
+            id: r.id.to_string(),
+        })?;
+        let json = if params.compact.unwrap_or(false) {
+            let db = self.db.lock().map_err(|e| db_err(&e))?;
+            compact_json(&db, json)?
+        } else {
+            json
+        };
+        let json = match effective {
+            Some(ref e) => with_limits(json, e)?,
+            None => json,
+        };
         Ok(CallToolResult::success(vec![Content::text(json)]))
     }
     pub fn do_regenerate_context(
         &self,
         params: RegenerateContextParams,
     ) -> Result<CallToolResult, ErrorData> {
+        params.validate()?;
         let db = self.db.lock().map_err(|e| db_err(&e))?;
+        self.ensure_udfs(&db)?;
         let config = crate::s5_config::load_config().unwrap_or_default();
         let (local_limit, cross_limit) =
             crate::s5_config::resolve_context_limits(&config, &params.project, false);
-        let ctx = crate::s4_context::generate_context(&db, &params.project, local_limit, cross_limit, params.before)
+        let ctx = crate::s4_context::generate_context(&db, &params.project, local_limit, cross_limit, params.before, None)
             .map_err(|e| db_err(&e))?;
         if ctx.is_empty() {
             Ok(CallToolResult::success(vec![Content::text(format!(
@@ -791,52 +2599,95 @@ impl NmemServer {
 
     pub fn do_session_summaries(
         &self,
-        params: SessionSummariesParams,
+        mut params: SessionSummariesParams,
     ) -> Result<CallToolResult, ErrorData> {
+        params.validate()?;
+        apply_window(params.window.as_deref(), &mut params.after, &mut params.before)?;
         let limit = clamp(params.limit, 10, 50);
+        let page = page_request("session_summaries", params.cursor.as_deref())?;
+        let paged = page.is_some();
+        let fetch = if paged { limit + 1 } else { limit };
+        // Sessions page on (started_at DESC, id DESC); new sessions sort ahead of
+        // any cursor, so no snapshot is needed.
+        let (after_started, after_id) = match page {
+            Some(Some(ref c)) => (Some(c.key as i64), Some(c.id.clone())),
+            _ => (None, None),
+        };
+        self.check_federated_paging(paged)?;
         let db = self.db.lock().map_err(|e| db_err(&e))?;
 
-        let sql = "SELECT id, project, started_at, summary FROM sessions
+        let sql = "SELECT id, project, started_at, summary, summary_sources, model FROM sessions
                    WHERE summary IS NOT NULL
                      AND (?1 IS NULL OR project = ?1)
                      AND (?2 IS NULL OR started_at < ?2)
                      AND (?3 IS NULL OR started_at > ?3)
-                   ORDER BY started_at DESC LIMIT ?4";
-        let sql_params: Vec<Box<dyn rusqlite::types::ToSql>> = vec![
-            Box::new(params.project.clone()) as Box<dyn rusqlite::types::ToSql>,
-            Box::new(params.before),
-            Box::new(params.after),
-            Box::new(limit),
-        ];
-
-        let mut stmt = db.prepare(sql).map_err(|e| db_err(&e))?;
-        let param_refs: Vec<&dyn rusqlite::types::ToSql> =
-            sql_params.iter().map(|b| b.as_ref()).collect();
+                     AND (?5 IS NULL OR started_at < ?5 OR (started_at = ?5 AND id < ?6))
+                     AND (?7 IS NULL OR instr(model, ?7) > 0)
+                   ORDER BY started_at DESC, id DESC LIMIT ?4";
+        let run = |db: &Connection| -> Result<Vec<SessionSummaryResult>, ErrorData> {
+            let mut stmt = db.prepare(sql).map_err(|e| db_err(&e))?;
+            let mut rows: Vec<SessionSummaryResult> = stmt
+                .query_map(
+                    rusqlite::params![
+                        params.project,
+                        params.before,
+                        params.after,
+                        fetch,
+                        after_started,
+                        after_id,
+                        params.model
+                    ],
+                    |row| {
+                        let summary_str: String = row.get(3)?;
+                        let summary: serde_json::Value =
+                            serde_json::from_str(&summary_str).unwrap_or(serde_json::Value::Null);
+                        let sources: Option<String> = row.get(4)?;
+                        Ok(SessionSummaryResult {
+                            session_id: row.get(0)?,
+                            project: row.get(1)?,
+                            started_at: row.get(2)?,
+                            model: row.get(5)?,
+                            summary,
+                            sources: sources.and_then(|s| serde_json::from_str(&s).ok()).unwrap_or_default(),
+                            unverified_files: Vec::new(),
+                            origin: None,
+                        })
+                    },
+                )
+                .map_err(|e| db_err(&e))?
+                .collect::<Result<_, _>>()
+                .map_err(|e| db_err(&e))?;
+            for r in rows.iter_mut().filter(|r| !r.sources.is_empty()) {
+                r.unverified_files = crate::s1_4_summarize::verify_summary(db, &r.summary, &r.sources)
+                    .map_err(|e| db_err(&e))?;
+            }
+            Ok(rows)
+        };
 
-        let results: Vec<SessionSummaryResult> = stmt
-            .query_map(param_refs.as_slice(), |row| {
-                let summary_str: String = row.get(3)?;
-                let summary: serde_json::Value =
-                    serde_json::from_str(&summary_str).unwrap_or(serde_json::Value::Null);
-                Ok(SessionSummaryResult {
-                    session_id: row.get(0)?,
-                    project: row.get(1)?,
-                    started_at: row.get(2)?,
-                    summary,
-                })
-            })
-            .map_err(|e| db_err(&e))?
-            .collect::<Result<_, _>>()
-            .map_err(|e| db_err(&e))?;
+        let local = run(&db)?;
+        drop(db);
+        let mut results = self.federate(local, run)?;
+        if !self.attached.is_empty() {
+            results.sort_by_key(|r| std::cmp::Reverse(r.started_at));
+            results.truncate(limit as usize);
+        }
 
-        let json = serde_json::to_string(&results).map_err(|e| db_err(&e))?;
+        let json = paged_json(results, paged, limit, |r| PageCursor {
+            tool: "session_summaries".into(),
+            as_of: 0,
+            now: 0,
+            key: r.started_at as f64,
+            id: r.session_id.clone(),
+        })?;
         Ok(CallToolResult::success(vec![Content::text(json)]))
     }
 
     pub fn do_session_trace(
         &self,
-        params: SessionTraceParams,
+        mut params: SessionTraceParams,
     ) -> Result<CallToolResult, ErrorData> {
+        params.validate()?;
+        apply_window(params.window.as_deref(), &mut params.after, &mut params.before)?;
         let db = self.db.lock().map_err(|e| db_err(&e))?;
 
         // 1. Session metadata
@@ -861,7 +2712,7 @@ impl NmemServer {
         // 2. Prompts + observations via LEFT JOIN, plus orphan observations (NULL prompt_id)
         let sql = "SELECT p.id AS prompt_id, p.timestamp AS prompt_ts, p.source, p.content AS prompt_content,
                           o.id AS obs_id, o.timestamp AS obs_ts, o.obs_type, o.file_path,
-                          SUBSTR(o.content, 1, 120) AS obs_preview, o.is_pinned
+                          SUBSTR(o.content, 1, 120) AS obs_preview, o.is_pinned, o.repeat_count
                    FROM prompts p
                    LEFT JOIN observations o ON o.prompt_id = p.id
                      AND (?2 IS NULL OR o.timestamp < ?2)
@@ -872,7 +2723,7 @@ impl NmemServer {
                    UNION ALL
                    SELECT NULL, o.timestamp, 'system', NULL,
                           o.id, o.timestamp, o.obs_type, o.file_path,
-                          SUBSTR(o.content, 1, 120), o.is_pinned
+                          SUBSTR(o.content, 1, 120), o.is_pinned, o.repeat_count
                    FROM observations o
                    WHERE o.session_id = ?1 AND o.prompt_id IS NULL
                      AND (?2 IS NULL OR o.timestamp < ?2)
@@ -900,6 +2751,7 @@ impl NmemServer {
                     let file_path: Option<String> = row.get(7)?;
                     let obs_preview: Option<String> = row.get(8)?;
                     let is_pinned: Option<i64> = row.get(9)?;
+                    let repeat_count: Option<i64> = row.get(10)?;
                     Ok((
                         prompt_id,
                         prompt_ts,
@@ -911,6 +2763,7 @@ impl NmemServer {
                         file_path,
                         obs_preview,
                         is_pinned,
+                        repeat_count,
                     ))
                 },
             )
@@ -928,6 +2781,7 @@ impl NmemServer {
                 file_path,
                 obs_preview,
                 is_pinned,
+                repeat_count,
             ) = row_result.map_err(|e| db_err(&e))?;
 
             let key = Some(prompt_id);
@@ -954,6 +2808,7 @@ impl NmemServer {
                     file_path,
                     content_preview: preview,
                     is_pinned: is_pinned.unwrap_or(0) != 0,
+                    repeat_count: repeat_count.filter(|&n| n > 1),
                 });
             }
         }
@@ -963,6 +2818,9 @@ impl NmemServer {
             p.observation_count = p.observations.len();
         }
 
+        let unmatched_calls = crate::s1_intent::unresolved(&db, &params.session_id, params.before, params.after)
+            .map_err(|e| db_err(&e))?;
+
         let result = SessionTraceResult {
             session_id: session.0,
             project: session.1,
@@ -970,6 +2828,7 @@ impl NmemServer {
             ended_at: session.3,
             summary,
             prompts,
+            unmatched_calls,
         };
 
         let json = serde_json::to_string(&result).map_err(|e| db_err(&e))?;
@@ -978,20 +2837,31 @@ impl NmemServer {
 
     pub fn do_file_history(
         &self,
-        params: FileHistoryParams,
+        mut params: FileHistoryParams,
     ) -> Result<CallToolResult, ErrorData> {
+        params.validate()?;
+        apply_window(params.window.as_deref(), &mut params.after, &mut params.before)?;
         let limit = clamp(params.limit, 10, 50);
         let db = self.db.lock().map_err(|e| db_err(&e))?;
 
+        // Relative paths and bare file names resolve to the recorded path
+        let resolution = crate::s1_alias::resolve_path_query(&db, &params.file_path, params.project.as_deref())
+            .map_err(|e| db_err(&e))?;
+        let path = resolution.resolved.clone().unwrap_or_else(|| params.file_path.clone());
+
+        // Follow renames: touches under any earlier name belong to this file
+        let chain = crate::s1_alias::alias_chain(&db, &path).map_err(|e| db_err(&e))?;
+        let chain_json = serde_json::to_string(&chain).map_err(|e| db_err(&e))?;
+
         let sql = "SELECT o.id AS obs_id, o.timestamp, o.obs_type,
                           SUBSTR(o.content, 1, 120) AS content_preview,
                           o.is_pinned, o.session_id,
                           s.project, s.started_at, s.summary,
-                          p.content AS prompt_content
+                          p.content AS prompt_content, o.file_path
                    FROM observations o
                    JOIN sessions s ON o.session_id = s.id
                    LEFT JOIN prompts p ON o.prompt_id = p.id AND p.source = 'user'
-                   WHERE o.file_path = ?1
+                   WHERE o.file_path IN (SELECT value FROM json_each(?1))
                      AND (?2 IS NULL OR o.timestamp < ?2)
                      AND (?3 IS NULL OR o.timestamp > ?3)
                    ORDER BY o.timestamp DESC
@@ -1010,11 +2880,12 @@ impl NmemServer {
             started_at: i64,
             summary_json: Option<String>,
             prompt_content: Option<String>,
+            file_path: String,
         }
 
         let touches: Vec<RawTouch> = stmt
             .query_map(
-                rusqlite::params![params.file_path, params.before, params.after, limit],
+                rusqlite::params![chain_json, params.before, params.after, limit],
                 |row| {
                     Ok(RawTouch {
                         obs_id: row.get(0)?,
@@ -1027,6 +2898,7 @@ impl NmemServer {
                         started_at: row.get(7)?,
                         summary_json: row.get(8)?,
                         prompt_content: row.get(9)?,
+                        file_path: row.get(10)?,
                     })
                 },
             )
@@ -1034,6 +2906,23 @@ impl NmemServer {
             .collect::<Result<_, _>>()
             .map_err(|e| db_err(&e))?;
 
+        // Touches under a suppression rule (`nmem suppress`) are held back
+        let rules = crate::s3_suppress::list_active(&db, unix_now()).unwrap_or_default();
+        let before_rules = touches.len();
+        let touches: Vec<RawTouch> = touches
+            .into_iter()
+            .filter(|t| {
+                let rules = crate::s3_suppress::Suppressions(
+                    rules.iter().filter(|r| r.project.as_deref().is_none_or(|p| p == t.project)).cloned().collect(),
+                );
+                !rules.hides(
+                    [Some(t.content_preview.as_str()), t.prompt_content.as_deref()].into_iter().flatten(),
+                    [t.file_path.as_str(), path.as_str()],
+                )
+            })
+            .collect();
+        let suppressed = Some(before_rules - touches.len()).filter(|&n| n > 0);
+
         // Group by session_id, preserving encounter order
         let mut sessions: Vec<FileSessionEntry> = Vec::new();
         let mut session_index: std::collections::HashMap<String, usize> =
@@ -1063,6 +2952,7 @@ impl NmemServer {
             sessions[idx].touches.push(FileTouch {
                 observation_id: t.obs_id,
                 timestamp: t.timestamp,
+                file_path: (t.file_path != path).then_some(t.file_path),
                 obs_type: t.obs_type,
                 content_preview: t.content_preview,
                 prompt_content: t.prompt_content,
@@ -1070,19 +2960,91 @@ impl NmemServer {
             });
         }
 
+        let project = params.project.as_deref().or(sessions.first().map(|s| s.project.as_str()));
+        let shared_with = match project {
+            Some(project) => {
+                let paths: Vec<&str> = chain.iter().map(String::as_str).collect();
+                crate::s4_overlap::shared_files(&db, project, &paths, params.before, 10).map_err(|e| db_err(&e))?
+            }
+            None => Vec::new(),
+        };
+
+        let risk = crate::s4_risk::file_risk(&db, &chain, params.before).map_err(|e| db_err(&e))?;
+
         let result = FileHistoryResult {
             file_path: params.file_path,
+            path_match: resolution.matched.filter(|m| *m != crate::s1_alias::PathMatch::Exact),
+            resolved_path: resolution.resolved,
+            candidates: resolution.candidates,
+            aliases: chain.into_iter().skip(1).collect(),
             sessions,
+            suppressed,
+            risk,
+            shared_with,
+        };
+
+        let json = serde_json::to_string(&result).map_err(|e| db_err(&e))?;
+        Ok(CallToolResult::success(vec![Content::text(json)]))
+    }
+
+    pub fn do_file_snapshot(
+        &self,
+        params: FileSnapshotParams,
+    ) -> Result<CallToolResult, ErrorData> {
+        params.validate()?;
+        let limit = clamp(params.limit, 5, 20);
+        let snapshots = {
+            let db = self.db.lock().map_err(|e| db_err(&e))?;
+            crate::s1_snapshot::snapshots(
+                &db,
+                &params.file_path,
+                params.before,
+                params.session_id.as_deref(),
+                limit,
+            )
+            .map_err(|e| db_err(&e))?
+        };
+
+        let current = crate::s1_snapshot::current_hash(&params.file_path);
+        let result = FileSnapshotResult {
+            snapshots: snapshots
+                .into_iter()
+                .map(|s| SnapshotEntry {
+                    unchanged: current
+                        .as_ref()
+                        .zip(s.blob_hash.as_ref())
+                        .map(|(now, then)| now == then),
+                    snapshot: s,
+                })
+                .collect(),
+            current_blob_hash: current,
+            file_path: params.file_path,
         };
 
         let json = serde_json::to_string(&result).map_err(|e| db_err(&e))?;
         Ok(CallToolResult::success(vec![Content::text(json)]))
     }
 
+    pub fn do_plan_history(
+        &self,
+        params: PlanHistoryParams,
+    ) -> Result<CallToolResult, ErrorData> {
+        params.validate()?;
+        let limit = clamp(params.limit, 50, 200);
+        let events = {
+            let db = self.db.lock().map_err(|e| db_err(&e))?;
+            crate::s1_plan::plan_history(&db, params.session_id.as_deref(), params.project.as_deref(), limit)
+                .map_err(|e| db_err(&e))?
+        };
+        let json = serde_json::to_string(&events).map_err(|e| db_err(&e))?;
+        Ok(CallToolResult::success(vec![Content::text(json)]))
+    }
+
     pub fn do_git_file_summary(
         &self,
         params: GitFileSummaryParams,
     ) -> Result<CallToolResult, ErrorData> {
+        params.validate()?;
         let cwd = std::env::current_dir().map_err(|e| db_err(&e))?;
         let opts = crate::s1_git::QueryOpts {
             max_commits: params.max_commits,
@@ -1101,42 +3063,78 @@ impl NmemServer {
         }
     }
 
-    pub fn do_queue_task(&self, params: QueueTaskParams) -> Result<CallToolResult, ErrorData> {
-        // Shell out to `nmem queue` to keep MCP server read-only.
-        // Same pattern as hooks calling `nmem record`.
-        let nmem_bin = std::env::current_exe().unwrap_or_else(|_| "nmem".into());
-
-        let mut cmd = std::process::Command::new(&nmem_bin);
-        cmd.arg("queue").arg(&params.prompt);
-
-        if let Some(ref project) = params.project {
-            cmd.arg("--project").arg(project);
+    pub fn do_context_pack(&self, params: ContextPackParams) -> Result<CallToolResult, ErrorData> {
+        params.validate()?;
+        let config = crate::s5_config::load_config().unwrap_or_default();
+        let configured = || config.packs.keys().cloned().collect::<Vec<_>>().join(", ");
+        if let Some(name) = &params.name {
+            let pack = config.packs.get(name).ok_or_else(|| {
+                ErrorData::new(
+                    ErrorCode::INVALID_PARAMS,
+                    format!("unknown pack {name:?}; configured: [{}]", configured()),
+                    None,
+                )
+            })?;
+            let db = self.db.lock().map_err(|e| db_err(&e))?;
+            let markdown = crate::s4_pack::render(&db, name, pack, params.project.as_deref(), None)
+                .map_err(|e| db_err(&e))?;
+            return Ok(CallToolResult::success(vec![Content::text(markdown)]));
         }
-        if let Some(ref cwd) = params.cwd {
-            cmd.arg("--cwd").arg(cwd);
+
+        let conn = self.writer()?;
+        let session: Option<(String, String)> = match &params.session_id {
+            Some(sid) => conn.query_row("SELECT id, project FROM sessions WHERE id = ?1", [sid], |r| {
+                Ok((r.get(0)?, r.get(1)?))
+            }),
+            None => conn.query_row(
+                "SELECT id, project FROM sessions WHERE kind = 'agent' ORDER BY started_at DESC LIMIT 1",
+                [],
+                |r| Ok((r.get(0)?, r.get(1)?)),
+            ),
         }
-        cmd.arg("--after").arg(&params.after);
+        .optional()
+        .map_err(|e| db_err(&e))?;
+        let Some((session_id, project)) = session else {
+            return Err(ErrorData::new(ErrorCode::INVALID_PARAMS, "no sessions found", None));
+        };
+        let delivered = crate::s4_pack::take_queued(&conn, &config, &session_id, Some(&project), unix_now())
+            .map_err(|e| write_err(&e))?;
+        let text = if delivered.is_empty() {
+            format!("No context packs queued for session {session_id}. Configured packs: [{}]", configured())
+        } else {
+            delivered.iter().map(|d| d.markdown.as_str()).collect::<Vec<_>>().join("\n")
+        };
+        Ok(CallToolResult::success(vec![Content::text(text)]))
+    }
 
-        let output = cmd.output().map_err(|e| {
+    /// Lock the writable connection for a write tool.
+    fn writer(&self) -> Result<std::sync::MutexGuard<'_, Connection>, ErrorData> {
+        let writer = self.writer.as_ref().ok_or_else(|| {
             ErrorData::new(
                 ErrorCode::INTERNAL_ERROR,
-                format!("failed to run nmem queue: {e}"),
+                "write tools unavailable: the store could not be opened for writing",
                 None,
             )
         })?;
+        writer.lock().map_err(|e| db_err(&e))
+    }
 
-        if !output.status.success() {
-            let stderr = String::from_utf8_lossy(&output.stderr);
-            return Err(ErrorData::new(
-                ErrorCode::INTERNAL_ERROR,
-                format!("nmem queue failed: {stderr}"),
-                None,
-            ));
-        }
+    pub fn do_queue_task(&self, params: QueueTaskParams) -> Result<CallToolResult, ErrorData> {
+        params.validate()?;
+        let config = crate::s5_config::load_config().unwrap_or_default();
+        let cwd = params.cwd.clone().or_else(|| {
+            std::env::current_dir().ok().map(|p| p.to_string_lossy().into_owned())
+        });
+        let project = resolve_project(params.project.as_deref(), cwd.as_deref(), &config);
+        let run_after = crate::s4_dispatch::parse_schedule(&params.after).map_err(|e| write_err(&e))?;
 
-        let task_id = String::from_utf8_lossy(&output.stdout).trim().to_string();
+        let task_id = {
+            let conn = self.writer()?;
+            crate::s4_dispatch::queue_task(&conn, &params.prompt, Some(&project), cwd.as_deref(), run_after)
+                .map_err(|e| write_err(&e))?
+        };
         let response = serde_json::json!({
-            "task_id": task_id.parse::<i64>().unwrap_or(0),
+            "task_id": task_id,
             "status": "pending",
             "prompt": params.prompt,
         });
@@ -1145,63 +3143,204 @@ impl NmemServer {
             serde_json::to_string(&response).map_err(|e| db_err(&e))?,
         )]))
     }
-    pub fn do_create_marker(&self, params: CreateMarkerParams) -> Result<CallToolResult, ErrorData> {
-        // Find nmem binary: current_exe (if it still exists on disk), then PATH, then ~/.local/bin
-        let nmem_bin = std::env::current_exe()
-            .ok()
-            .filter(|p| p.exists())
-            .unwrap_or_else(|| {
-                // current_exe may point to a deleted binary after rebuild
-                let local = std::path::PathBuf::from(
-                    format!("{}/.local/bin/nmem", std::env::var("HOME").unwrap_or_default()),
-                );
-                if local.exists() {
-                    local
-                } else {
-                    "nmem".into()
-                }
-            });
-
-        let mut cmd = std::process::Command::new(&nmem_bin);
-        cmd.arg("mark").arg(&params.text);
 
-        if let Some(ref project) = params.project {
-            cmd.arg("--project").arg(project);
+    pub fn do_create_marker(&self, params: CreateMarkerParams) -> Result<CallToolResult, ErrorData> {
+        params.validate()?;
+        let config = crate::s5_config::load_config().unwrap_or_default();
+        let project = resolve_project(params.project.as_deref(), None, &config);
+        let obs_id = {
+            let conn = self.writer()?;
+            let reply = crate::s1_mark::Reply { to: params.reply_to, status: params.status.as_deref() };
+            crate::s1_mark::create(&conn, &config, &project, &params.text, reply, unix_now())
+                .map_err(|e| write_err(&e))?
+        };
+        let mut response = serde_json::json!({
+            "observation_id": obs_id,
+            "status": "created",
+            "text": params.text,
+        });
+        if let Some(parent) = params.reply_to {
+            response["reply_to"] = parent.into();
         }
 
-        let output = cmd.output().map_err(|e| {
-            ErrorData::new(
-                ErrorCode::INTERNAL_ERROR,
-                format!("failed to run nmem mark: {e}"),
-                None,
-            )
-        })?;
+        Ok(CallToolResult::success(vec![Content::text(
+            serde_json::to_string(&response).map_err(|e| db_err(&e))?,
+        )]))
+    }
 
-        if !output.status.success() {
-            let stderr = String::from_utf8_lossy(&output.stderr);
-            return Err(ErrorData::new(
-                ErrorCode::INTERNAL_ERROR,
-                format!("nmem mark failed: {stderr}"),
-                None,
-            ));
-        }
+    pub fn do_remember(&self, params: RememberParams) -> Result<CallToolResult, ErrorData> {
+        params.validate()?;
+        let config = crate::s5_config::load_config().unwrap_or_default();
+        let project = resolve_project(params.project.as_deref(), None, &config);
+        let filter = SecretFilter::with_params(crate::s5_config::resolve_filter_params(&config, Some(&project)));
+        let (content, _) = filter.redact(&params.content);
+        let scope = (params.scope.as_deref() != Some("global")).then_some(project.as_str());
+        let ttl = params.ttl.as_deref().and_then(|t| crate::s4_dispatch::parse_relative(&t.trim().to_lowercase()));
+        let remembered = {
+            let conn = self.writer()?;
+            crate::s1_remember::remember(&conn, scope, &params.key, &content, ttl, unix_now())
+                .map_err(|e| write_err(&e))?
+        };
+        Ok(CallToolResult::success(vec![Content::text(
+            serde_json::to_string(&remembered).map_err(|e| db_err(&e))?,
+        )]))
+    }
 
-        let obs_id = String::from_utf8_lossy(&output.stdout).trim().to_string();
+    pub fn do_forget(&self, params: ForgetParams) -> Result<CallToolResult, ErrorData> {
+        params.validate()?;
+        let config = crate::s5_config::load_config().unwrap_or_default();
+        let project = resolve_project(params.project.as_deref(), None, &config);
+        let scope = (params.scope.as_deref() != Some("global")).then_some(project.as_str());
+        let forgotten = {
+            let conn = self.writer()?;
+            crate::s1_remember::forget(&conn, scope, &params.key).map_err(|e| write_err(&e))?
+        };
         let response = serde_json::json!({
-            "observation_id": obs_id.parse::<i64>().unwrap_or(0),
-            "status": "created",
-            "text": params.text,
+            "key": params.key.trim(),
+            "project": scope,
+            "status": if forgotten { "forgotten" } else { "not_found" },
         });
+        Ok(CallToolResult::success(vec![Content::text(
+            serde_json::to_string(&response).map_err(|e| db_err(&e))?,
+        )]))
+    }
+
+    pub fn do_start_investigation(&self, params: StartInvestigationParams) -> Result<CallToolResult, ErrorData> {
+        params.validate()?;
+        let config = crate::s5_config::load_config().unwrap_or_default();
+        let project = resolve_project(params.project.as_deref(), None, &config);
+        let filter = SecretFilter::with_params(crate::s5_config::resolve_filter_params(&config, Some(&project)));
+        let (question, _) = filter.redact(&params.question);
+        let id = {
+            let conn = self.writer()?;
+            crate::s1_investigate::start(&conn, &project, &question, unix_now()).map_err(|e| write_err(&e))?
+        };
 
+        let response = serde_json::json!({
+            "investigation_id": id,
+            "status": "open",
+            "question": question,
+        });
         Ok(CallToolResult::success(vec![Content::text(
             serde_json::to_string(&response).map_err(|e| db_err(&e))?,
         )]))
     }
 
+    pub fn do_conclude_investigation(&self, params: ConcludeInvestigationParams) -> Result<CallToolResult, ErrorData> {
+        params.validate()?;
+        let config = crate::s5_config::load_config().unwrap_or_default();
+        let project = resolve_project(params.project.as_deref(), None, &config);
+        let filter = SecretFilter::with_params(crate::s5_config::resolve_filter_params(&config, Some(&project)));
+        let (conclusion, _) = filter.redact(&params.conclusion);
+        let done = {
+            let conn = self.writer()?;
+            crate::s1_investigate::conclude(&conn, &project, params.investigation_id, &conclusion, unix_now())
+                .map_err(|e| write_err(&e))?
+        };
+        Ok(CallToolResult::success(vec![Content::text(
+            serde_json::to_string(&done).map_err(|e| db_err(&e))?,
+        )]))
+    }
+
+    pub fn do_bundle(&self, params: BundleParams) -> Result<CallToolResult, ErrorData> {
+        params.validate()?;
+
+        // Keys are settled before anything runs, so a duplicate rejects the
+        // whole bundle rather than half of it
+        let mut keys: Vec<String> = Vec::with_capacity(params.requests.len());
+        for (i, req) in params.requests.iter().enumerate() {
+            let key = match &req.key {
+                Some(k) => k.clone(),
+                None if keys.contains(&req.tool) => format!("{}_{i}", req.tool),
+                None => req.tool.clone(),
+            };
+            if keys.contains(&key) {
+                return Err(crate::s1_input::invalid(
+                    "requests[].key",
+                    "duplicate",
+                    format!("duplicate bundle key {key:?}"),
+                    serde_json::Value::Null,
+                ));
+            }
+            keys.push(key);
+        }
+
+        // Each sub-request runs through the same do_* path as a direct call.
+        // A failing sub-request yields {"error": ...} under its key instead of
+        // failing the whole bundle.
+        let mut results = serde_json::Map::new();
+        for (key, req) in keys.into_iter().zip(params.requests) {
+            let start = std::time::Instant::now();
+            let params_hash = crate::s3_audit::params_hash(&req.params);
+            let outcome = self.run_bundle_request(&req.tool, req.params);
+            self.finish_query(&format!("bundle.{}", req.tool), &params_hash, &outcome, start);
+
+            let value = match outcome {
+                Ok(result) => {
+                    let text = result
+                        .content
+                        .first()
+                        .and_then(|c| c.as_text())
+                        .map(|t| t.text.clone())
+                        .unwrap_or_default();
+                    if result.is_error.unwrap_or(false) {
+                        serde_json::json!({ "error": text })
+                    } else {
+                        serde_json::from_str(&text).unwrap_or(serde_json::Value::String(text))
+                    }
+                }
+                Err(e) => serde_json::json!({ "error": e.message }),
+            };
+            results.insert(key, value);
+        }
+
+        let json = serde_json::to_string(&results).map_err(|e| db_err(&e))?;
+        Ok(CallToolResult::success(vec![Content::text(json)]))
+    }
+
+    fn run_bundle_request(
+        &self,
+        tool: &str,
+        params: serde_json::Value,
+    ) -> Result<CallToolResult, ErrorData> {
+        fn parse<T: serde::de::DeserializeOwned>(
+            tool: &str,
+            params: serde_json::Value,
+        ) -> Result<T, ErrorData> {
+            let params = if params.is_null() { serde_json::json!({}) } else { params };
+            serde_json::from_value(params).map_err(|e| {
+                ErrorData::new(
+                    ErrorCode::INVALID_PARAMS,
+                    format!("invalid params for {tool}: {e}"),
+                    None,
+                )
+            })
+        }
+
+        let max_bytes = params.get("max_bytes").and_then(|v| v.as_u64()).map(|n| n as usize);
+        let result = match tool {
+            "search" => self.do_search(parse(tool, params)?),
+            "get_observations" => self.do_get_observations(parse(tool, params)?),
+            "timeline" => self.do_timeline(parse(tool, params)?),
+            "recent_context" => self.do_recent_context(parse(tool, params)?),
+            "session_summaries" => self.do_session_summaries(parse(tool, params)?),
+            "session_trace" => self.do_session_trace(parse(tool, params)?),
+            "file_history" => self.do_file_history(parse(tool, params)?),
+            "working_set" => self.do_working_set(parse(tool, params)?),
+            other => Err(ErrorData::new(
+                ErrorCode::INVALID_PARAMS,
+                format!("unsupported bundle tool: {other:?}"),
+                None,
+            )),
+        };
+        cap_response(result, max_bytes)
+    }
+
     pub fn do_current_stance(
         &self,
         params: CurrentStanceParams,
     ) -> Result<CallToolResult, ErrorData> {
+        params.validate()?;
         let alpha = params.alpha.unwrap_or(0.08).clamp(0.01, 1.0);
         let db = self.db.lock().map_err(|e| db_err(&e))?;
 
@@ -1210,7 +3349,7 @@ impl NmemServer {
             sid
         } else {
             db.query_row(
-                "SELECT id FROM sessions ORDER BY started_at DESC LIMIT 1",
+                "SELECT id FROM sessions WHERE kind = 'agent' ORDER BY started_at DESC LIMIT 1",
                 [],
                 |row| row.get(0),
             )
@@ -1269,18 +3408,20 @@ impl NmemServer {
 
         let pct = |n: i64| (n as f64 / total as f64 * 100.0 * 10.0).round() / 10.0;
 
-        // 3. Full sequence for EMA
+        // 3. Full sequence for EMA, each label scaled by its classifier's
+        // confidence so marginal calls move the stance less (unscored = 1)
         let mut seq_stmt = db
             .prepare(
-                "SELECT phase, scope, timestamp FROM observations
+                "SELECT phase, scope, timestamp, COALESCE(phase_confidence, 1.0), COALESCE(scope_confidence, 1.0)
+                 FROM observations
                  WHERE session_id = ?1 AND phase IS NOT NULL AND scope IS NOT NULL
                  ORDER BY timestamp ASC",
             )
             .map_err(|e| db_err(&e))?;
 
         struct ObsPoint {
-            phase_val: f64,  // -1 = think, +1 = act
-            scope_val: f64,  // -1 = diverge, +1 = converge
+            phase_val: f64,  // -1 = think, +1 = act, times confidence
+            scope_val: f64,  // -1 = diverge, +1 = converge, times confidence
             timestamp: i64,
         }
 
@@ -1289,9 +3430,11 @@ impl NmemServer {
                 let phase: String = row.get(0)?;
                 let scope: String = row.get(1)?;
                 let timestamp: i64 = row.get(2)?;
+                let phase_conf: f64 = row.get(3)?;
+                let scope_conf: f64 = row.get(4)?;
                 Ok(ObsPoint {
-                    phase_val: if phase == "act" { 1.0 } else { -1.0 },
-                    scope_val: if scope == "converge" { 1.0 } else { -1.0 },
+                    phase_val: if phase == "act" { phase_conf } else { -phase_conf },
+                    scope_val: if scope == "converge" { scope_conf } else { -scope_conf },
                     timestamp,
                 })
             })
@@ -1343,12 +3486,7 @@ impl NmemServer {
         let scope_direction = direction(scope_5, scope_20, "converge", "diverge");
 
         // Stance label from current EMA
-        let stance = match (ema_phase >= 0.0, ema_scope >= 0.0) {
-            (true, true) => "act+converge",
-            (true, false) => "act+diverge",
-            (false, true) => "think+converge",
-            (false, false) => "think+diverge",
-        };
+        let stance = crate::s4_stance::label(ema_phase, ema_scope);
 
         // 6. Detect recent scope zero-crossings (last 50 observations)
         let now_ts = std::time::SystemTime::now()
@@ -1365,18 +3503,8 @@ impl NmemServer {
             if (prev_scope >= 0.0) != (curr_scope >= 0.0) {
                 let prev_phase = ema_history[i - 1].0;
                 let curr_phase = ema_history[i].0;
-                let from_stance = match (prev_phase >= 0.0, prev_scope >= 0.0) {
-                    (true, true) => "act+converge",
-                    (true, false) => "act+diverge",
-                    (false, true) => "think+converge",
-                    (false, false) => "think+diverge",
-                };
-                let to_stance = match (curr_phase >= 0.0, curr_scope >= 0.0) {
-                    (true, true) => "act+converge",
-                    (true, false) => "act+diverge",
-                    (false, true) => "think+converge",
-                    (false, false) => "think+diverge",
-                };
+                let from_stance = crate::s4_stance::label(prev_phase, prev_scope);
+                let to_stance = crate::s4_stance::label(curr_phase, curr_scope);
                 let minutes_ago =
                     ((now_ts - ema_history[i].2) as f64 / 60.0 * 10.0).round() / 10.0;
                 recent_shifts.push(RecentShift {
@@ -1533,6 +3661,323 @@ impl NmemServer {
         let json = serde_json::to_string(&result).map_err(|e| db_err(&e))?;
         Ok(CallToolResult::success(vec![Content::text(json)]))
     }
+
+    pub fn do_grep(&self, mut params: GrepParams) -> Result<CallToolResult, ErrorData> {
+        params.validate()?;
+        apply_window(params.window.as_deref(), &mut params.after, &mut params.before)?;
+        let limit = clamp(params.limit, 20, 50) as usize;
+        let re = crate::s1_grep::compile(&params.pattern, params.ignore_case.unwrap_or(false))
+            .map_err(|e| ErrorData::new(ErrorCode::INVALID_PARAMS, e.to_string(), None))?;
+        let filters = crate::s1_grep::GrepFilters {
+            project: params.project.as_deref(),
+            obs_type: params.obs_type.as_deref(),
+            fts: params.fts.as_deref(),
+            before: params.before,
+            after: params.after,
+        };
+        let db = self.db.lock().map_err(|e| db_err(&e))?;
+        let result = crate::s1_grep::grep(&db, &re, &filters, limit, GREP_MAX_SCAN).map_err(|e| db_err(&e))?;
+        let json = serde_json::to_string(&result).map_err(|e| db_err(&e))?;
+        Ok(CallToolResult::success(vec![Content::text(json)]))
+    }
+
+    pub fn do_working_set(&self, params: WorkingSetParams) -> Result<CallToolResult, ErrorData> {
+        params.validate()?;
+        let limit = clamp(params.limit, 10, 50);
+        let db = self.db.lock().map_err(|e| db_err(&e))?;
+
+        let session_id: String = if let Some(sid) = params.session_id {
+            sid
+        } else {
+            db.query_row(
+                "SELECT id FROM sessions WHERE kind = 'agent' ORDER BY started_at DESC LIMIT 1",
+                [],
+                |row| row.get(0),
+            )
+            .map_err(|e| match e {
+                rusqlite::Error::QueryReturnedNoRows => ErrorData::new(
+                    ErrorCode::INVALID_PARAMS,
+                    "no sessions found",
+                    None,
+                ),
+                other => db_err(&other),
+            })?
+        };
+
+        let mut result = WorkingSetResult {
+            session_id: session_id.clone(),
+            intent: None,
+            started_at: None,
+            obs_count: 0,
+            phase_signature: None,
+            hot_files: Vec::new(),
+            failing_commands: Vec::new(),
+            related_episodes: Vec::new(),
+        };
+
+        // The open episode is the last one detected; it runs to the newest
+        // prompt so agent-side prompts after the last user turn are included.
+        let episodes = crate::s4_memory::detect_episodes(&db, &session_id).map_err(|e| db_err(&e))?;
+        let Some(mut episode) = episodes.into_iter().next_back() else {
+            let json = serde_json::to_string(&result).map_err(|e| db_err(&e))?;
+            return Ok(CallToolResult::success(vec![Content::text(json)]));
+        };
+        let newest_prompt: i64 = db
+            .query_row(
+                "SELECT COALESCE(MAX(id), ?2) FROM prompts WHERE session_id = ?1",
+                rusqlite::params![session_id, episode.last_prompt_id],
+                |r| r.get(0),
+            )
+            .map_err(|e| db_err(&e))?;
+        episode.last_prompt_id = episode.last_prompt_id.max(newest_prompt);
+
+        let annotated = crate::s4_memory::annotate_episode(&db, &episode).map_err(|e| db_err(&e))?;
+        result.obs_count = annotated.obs_count;
+        result.phase_signature = serde_json::from_str(&annotated.phase_signature).ok();
+        result.intent = Some(episode.intent.clone());
+        result.started_at = Some(episode.started_at);
+        let range = rusqlite::params![session_id, episode.first_prompt_id, episode.last_prompt_id, limit];
+
+        // 1. Hot files, most touched first
+        let mut stmt = db
+            .prepare(
+                "SELECT file_path,
+                        SUM(obs_type = 'file_read') AS reads,
+                        SUM(obs_type IN ('file_edit', 'file_write')) AS edits,
+                        MAX(timestamp) AS last_touched
+                 FROM observations
+                 WHERE session_id = ?1 AND prompt_id BETWEEN ?2 AND ?3
+                   AND file_path IS NOT NULL
+                 GROUP BY file_path
+                 ORDER BY edits DESC, COUNT(*) DESC, last_touched DESC
+                 LIMIT ?4",
+            )
+            .map_err(|e| db_err(&e))?;
+        result.hot_files = stmt
+            .query_map(range, |r| {
+                Ok(HotFile {
+                    file_path: r.get(0)?,
+                    reads: r.get(1)?,
+                    edits: r.get(2)?,
+                    last_touched: r.get(3)?,
+                })
+            })
+            .map_err(|e| db_err(&e))?
+            .collect::<Result<_, _>>()
+            .map_err(|e| db_err(&e))?;
+
+        // 2. Commands that failed in this episode, and whether a later run passed
+        let mut stmt = db
+            .prepare(
+                "WITH ep AS (
+                     SELECT id, timestamp, content, error_class,
+                            COALESCE(json_extract(metadata, '$.failed'), 0) = 1 AS failed
+                     FROM observations
+                     WHERE session_id = ?1 AND prompt_id BETWEEN ?2 AND ?3
+                       AND obs_type = 'command'
+                 ),
+                 fails AS (
+                     SELECT content, COUNT(*) AS failures, MAX(id) AS last_id, MAX(timestamp) AS last_failed_at
+                     FROM ep WHERE failed
+                     GROUP BY content
+                 )
+                 SELECT f.content, f.failures,
+                        (SELECT error_class FROM ep WHERE ep.id = f.last_id),
+                        f.last_failed_at,
+                        EXISTS(SELECT 1 FROM ep WHERE ep.content = f.content AND NOT ep.failed AND ep.id > f.last_id)
+                 FROM fails f
+                 ORDER BY f.last_id DESC
+                 LIMIT ?4",
+            )
+            .map_err(|e| db_err(&e))?;
+        result.failing_commands = stmt
+            .query_map(range, |r| {
+                Ok(FailingCommand {
+                    command: r.get(0)?,
+                    failures: r.get(1)?,
+                    error_class: r.get(2)?,
+                    last_failed_at: r.get(3)?,
+                    resolved: r.get(4)?,
+                })
+            })
+            .map_err(|e| db_err(&e))?
+            .collect::<Result<_, _>>()
+            .map_err(|e| db_err(&e))?;
+
+        // 3. Prior episodes (other sessions) whose hot files overlap ours,
+        // following renames so pre-rename episodes still match
+        let mut names: Vec<(String, String)> = Vec::new();
+        for hot in &result.hot_files {
+            for alias in crate::s1_alias::alias_chain(&db, &hot.file_path).map_err(|e| db_err(&e))? {
+                names.push((alias, hot.file_path.clone()));
+            }
+        }
+        if !names.is_empty() {
+            let names_json = serde_json::to_string(
+                &names.iter().map(|(alias, current)| serde_json::json!([alias, current])).collect::<Vec<_>>(),
+            )
+            .map_err(|e| db_err(&e))?;
+            let mut stmt = db
+                .prepare(
+                    "WITH names AS (
+                         SELECT json_extract(value, '$[0]') AS alias, json_extract(value, '$[1]') AS current
+                         FROM json_each(?2)
+                     )
+                     SELECT w.id, w.session_id, s.project, w.intent, w.started_at,
+                            json_group_array(DISTINCT n.current) AS shared,
+                            w.session_id IN (SELECT value FROM json_each(?4)) AS in_lineage, w.outcome
+                     FROM work_units w
+                     JOIN sessions s ON s.id = w.session_id
+                     JOIN json_each(w.hot_files) h
+                     JOIN names n ON n.alias = h.value
+                     WHERE w.session_id != ?1
+                     GROUP BY w.id
+                     ORDER BY in_lineage DESC, COUNT(DISTINCT n.current) DESC, w.started_at DESC
+                     LIMIT ?3",
+                )
+                .map_err(|e| db_err(&e))?;
+            let lineage = lineage_json(&db, &session_id)?;
+            result.related_episodes = stmt
+                .query_map(rusqlite::params![session_id, names_json, limit, lineage], |r| {
+                    let shared: String = r.get(5)?;
+                    Ok(RelatedEpisode {
+                        session_id: r.get(1)?,
+                        project: r.get(2)?,
+                        intent: r.get(3)?,
+                        started_at: r.get(4)?,
+                        shared_files: serde_json::from_str(&shared).unwrap_or_default(),
+                        lineage: r.get(6)?,
+                        outcome: r.get(7)?,
+                    })
+                })
+                .map_err(|e| db_err(&e))?
+                .collect::<Result<_, _>>()
+                .map_err(|e| db_err(&e))?;
+        }
+
+        let json = serde_json::to_string(&result).map_err(|e| db_err(&e))?;
+        Ok(CallToolResult::success(vec![Content::text(json)]))
+    }
+
+    pub fn do_similar_observations(&self, params: SimilarObservationsParams) -> Result<CallToolResult, ErrorData> {
+        params.validate()?;
+        let limit = clamp(params.limit, 10, 50) as usize;
+        let min_similarity = params.min_similarity.unwrap_or(0.5);
+        let db = self.db.lock().map_err(|e| db_err(&e))?;
+
+        // Observations older than their signature table are signed on the fly
+        let sig = match crate::s1_minhash::load(&db, params.id).map_err(|e| db_err(&e))? {
+            Some(sig) => Some(sig),
+            None => {
+                let content: String = db
+                    .query_row(
+                        concat!("SELECT ", crate::s1_chunk::full_content_sql!(), " FROM observations o WHERE o.id = ?1"),
+                        [params.id],
+                        |r| r.get(0),
+                    )
+                    .map_err(|e| match e {
+                        rusqlite::Error::QueryReturnedNoRows => ErrorData::new(
+                            ErrorCode::INVALID_PARAMS,
+                            format!("observation not found: {}", params.id),
+                            None,
+                        ),
+                        other => db_err(&other),
+                    })?;
+                crate::s1_minhash::signature(&content)
+            }
+        };
+
+        let mut result = SimilarObservationsResult { id: params.id, comparable: sig.is_some(), results: Vec::new() };
+        if let Some(sig) = sig {
+            let hits = crate::s1_minhash::similar(&db, &sig, params.id, min_similarity, limit).map_err(|e| db_err(&e))?;
+            let mut stmt = db
+                .prepare_cached(
+                    "SELECT o.timestamp, o.session_id, s.project, o.obs_type, o.file_path, SUBSTR(o.content, 1, 120)
+                     FROM observations o JOIN sessions s ON s.id = o.session_id WHERE o.id = ?1",
+                )
+                .map_err(|e| db_err(&e))?;
+            for (id, similarity) in hits {
+                let row = stmt
+                    .query_row([id], |r| {
+                        Ok(SimilarObservation {
+                            id,
+                            similarity: (similarity * 100.0).round() / 100.0,
+                            timestamp: r.get(0)?,
+                            session_id: r.get(1)?,
+                            project: r.get(2)?,
+                            obs_type: r.get(3)?,
+                            file_path: r.get(4)?,
+                            content_preview: r.get(5)?,
+                        })
+                    })
+                    .map_err(|e| db_err(&e))?;
+                result.results.push(row);
+            }
+        }
+
+        let json = serde_json::to_string(&result).map_err(|e| db_err(&e))?;
+        Ok(CallToolResult::success(vec![Content::text(json)]))
+    }
+
+    pub fn do_flaky_tests(&self, params: FlakyTestsParams) -> Result<CallToolResult, ErrorData> {
+        params.validate()?;
+        let limit = clamp(params.limit, 20, 100);
+        let min_sessions = params.min_sessions.unwrap_or(2).max(1);
+        let db = self.db.lock().map_err(|e| db_err(&e))?;
+        let tests = crate::s2_test_failure::flaky(&db, params.project.as_deref(), min_sessions, limit)
+            .map_err(|e| db_err(&e))?;
+        let json = serde_json::to_string(&tests).map_err(|e| db_err(&e))?;
+        Ok(CallToolResult::success(vec![Content::text(json)]))
+    }
+
+    pub fn do_test_history(&self, params: TestHistoryParams) -> Result<CallToolResult, ErrorData> {
+        params.validate()?;
+        let limit = clamp(params.limit, 20, 100);
+        let db = self.db.lock().map_err(|e| db_err(&e))?;
+        let mut found = crate::s2_test_failure::lookup(&db, &params.name, params.project.as_deref())
+            .map_err(|e| db_err(&e))?;
+        let json = match found.len() {
+            0 => {
+                return Err(ErrorData::new(
+                    ErrorCode::INVALID_PARAMS,
+                    format!("no recorded failures of test: {}", params.name),
+                    None,
+                ));
+            }
+            1 => {
+                let history = crate::s2_test_failure::history(&db, found.remove(0), limit).map_err(|e| db_err(&e))?;
+                serde_json::to_string(&history)
+            }
+            // Several tests share the tail: let the caller pick one
+            _ => serde_json::to_string(&serde_json::json!({ "ambiguous": true, "candidates": found })),
+        }
+        .map_err(|e| db_err(&e))?;
+        Ok(CallToolResult::success(vec![Content::text(json)]))
+    }
+
+    pub fn do_window_summary(&self, params: WindowSummaryParams) -> Result<CallToolResult, ErrorData> {
+        params.validate()?;
+        let limit = clamp(params.limit, 30, 100);
+        let window = resolve_window(&params.window)?;
+        let db = self.db.lock().map_err(|e| db_err(&e))?;
+        let summary = crate::s4_window::summarize(&db, window, params.project.as_deref(), limit)
+            .map_err(|e| db_err(&e))?;
+        let json = serde_json::to_string(&summary).map_err(|e| db_err(&e))?;
+        Ok(CallToolResult::success(vec![Content::text(json)]))
+    }
+
+    pub fn do_memory_usage_report(
+        &self,
+        params: MemoryUsageReportParams,
+    ) -> Result<CallToolResult, ErrorData> {
+        let days = clamp(params.days, 30, 365);
+        let limit = clamp(params.limit, 20, 100);
+        let db = self.db.lock().map_err(|e| db_err(&e))?;
+        let since = unix_now() - days * 86400;
+        let report = crate::s3_audit::usage_report(&db, since, limit).map_err(|e| db_err(&e))?;
+        let json = serde_json::to_string(&report).map_err(|e| db_err(&e))?;
+        Ok(CallToolResult::success(vec![Content::text(json)]))
+    }
 }
 
 // --- MCP tool wrappers (delegate to do_* methods) ---
@@ -1542,16 +3987,104 @@ impl NmemServer {
     pub fn new(db: DbHandle) -> Self {
         Self {
             db,
+            writer: None,
+            query_log: None,
+            attached: Vec::new(),
+            fts_repair: Arc::new(AtomicBool::new(false)),
+            log_level: Arc::new(Mutex::new(None)),
+            lazy_udfs: None,
+            search_pages: Arc::new(Mutex::new(SearchSnapshots::default())),
             tool_router: Self::tool_router(),
         }
     }
 
+    /// Register the SQL scoring functions with `model` on first use instead
+    /// of at startup; connections handed to the server come without them.
+    pub fn with_lazy_udfs(mut self, model: crate::s3_importance::ImportanceModel) -> Self {
+        self.lazy_udfs = Some(Arc::new(LazyUdfs {
+            model,
+            registered: Mutex::new(std::collections::HashSet::new()),
+        }));
+        self
+    }
+
+    /// Make sure `conn` has the scoring functions before a query that calls them.
+    fn ensure_udfs(&self, conn: &Connection) -> Result<(), ErrorData> {
+        let Some(lazy) = &self.lazy_udfs else {
+            return Ok(());
+        };
+        let mut registered = lazy.registered.lock().map_err(|e| db_err(&e))?;
+        if registered.insert(conn as *const Connection as usize) {
+            crate::db::register_udfs_with(conn, lazy.model.clone()).map_err(|e| db_err(&e))?;
+        }
+        Ok(())
+    }
+
+    /// Serve the write tools through `conn`.
+    pub fn with_writer(mut self, conn: DbHandle) -> Self {
+        self.writer = Some(conn);
+        self
+    }
+
+    /// Record every tool call into `query_log` through `conn`.
+    pub fn with_query_log(mut self, conn: DbHandle) -> Self {
+        self.query_log = Some(conn);
+        self
+    }
+
+    /// Federate a read-only store into search, recent_context, and
+    /// session_summaries; its results are tagged `origin: name`.
+    pub fn with_attached(mut self, name: impl Into<String>, conn: DbHandle) -> Self {
+        self.attached.push((name.into(), conn));
+        self
+    }
+
+    /// Per-call bookkeeping: OTel metrics, then the `query_log` row. Logging
+    /// failures are warned about, never surfaced to the caller.
+    fn finish_query(
+        &self,
+        tool: &str,
+        params_hash: &str,
+        result: &Result<CallToolResult, ErrorData>,
+        start: std::time::Instant,
+    ) {
+        record_query_metrics(tool, start);
+        let Some(log) = &self.query_log else {
+            return;
+        };
+        let (is_error, (result_count, obs_ids)) = match result {
+            Ok(r) if !r.is_error.unwrap_or(false) => {
+                let text = r.content.first().and_then(|c| c.as_text()).map(|t| t.text.as_str());
+                (false, crate::s3_audit::summarize_result(text.unwrap_or_default()))
+            }
+            _ => (true, (None, Vec::new())),
+        };
+        let entry = crate::s3_audit::QueryLogEntry {
+            tool,
+            params_hash,
+            result_count,
+            duration_ms: start.elapsed().as_millis() as i64,
+            is_error,
+            obs_ids,
+        };
+        let logged = log
+            .lock()
+            .map_err(|e| NmemError::Config(format!("query_log lock: {e}")))
+            .and_then(|conn| {
+                crate::s3_audit::log_query(&conn, &entry, unix_now())?;
+                crate::s3_slow_query::flush(&conn, unix_now()).map(|_| ())
+            });
+        if let Err(e) = logged {
+            log::warn!("query_log: {e}");
+        }
+    }
+
     pub fn db_handle(&self) -> &DbHandle {
         &self.db
     }
 
     #[tool(
-        description = "Search past agent actions (file reads, edits, commands, searches) by full-text query. Only contains records of what the agent did in prior sessions — not external data. Returns ranked index with IDs and previews. Use optional before/after Unix timestamps to scope results to a time range.",
+        description = "Search past agent actions (file reads, edits, commands, searches) by full-text query. Only contains records of what the agent did in prior sessions — not external data. Returns ranked index with IDs and previews. Use optional before/after Unix timestamps to scope results to a time range, and error_class to find failures by kind. Pass cursor=\"\" to page through up to 1000 results in the order the first page ranked them; follow next_cursor until it is null. Set facets=true to also get counts by obs_type, project, and session over the full match set, to refine a broad query instead of paging through it. Size: ~150 B per result, ~3 KB at the default limit of 20 (busy projects get up to 50, reported as `limits`); facets add ~1 KB.",
         annotations(read_only_hint = true, open_world_hint = false)
     )]
     async fn search(
@@ -1559,13 +4092,15 @@ impl NmemServer {
         p: Parameters<SearchParams>,
     ) -> Result<CallToolResult, ErrorData> {
         let start = std::time::Instant::now();
-        let result = self.do_search(p.0);
-        record_query_metrics("search", start);
+        let params_hash = crate::s3_audit::params_hash(&p.0);
+        let max_bytes = p.0.max_bytes;
+        let result = cap_response(self.do_search(p.0), max_bytes);
+        self.finish_query("search", &params_hash, &result, start);
         result
     }
 
     #[tool(
-        description = "Fetch full observation details by IDs. Returns complete observation objects.",
+        description = "Fetch full observation details by IDs. Returns complete observation objects; redactions and annotations made since capture are listed as `corrections`. Set related=true to also get, per observation, ids and previews of the rest of a marker's thread, observations on the same file within an hour, with the same error class, or from the same prompt. Size: ~0.5–2 KB per observation; long commands and edits run larger.",
         annotations(read_only_hint = true, open_world_hint = false)
     )]
     async fn get_observations(
@@ -1573,13 +4108,15 @@ impl NmemServer {
         p: Parameters<GetObservationsParams>,
     ) -> Result<CallToolResult, ErrorData> {
         let start = std::time::Instant::now();
-        let result = self.do_get_observations(p.0);
-        record_query_metrics("get_observations", start);
+        let params_hash = crate::s3_audit::params_hash(&p.0);
+        let max_bytes = p.0.max_bytes;
+        let result = cap_response(self.do_get_observations(p.0), max_bytes);
+        self.finish_query("get_observations", &params_hash, &result, start);
         result
     }
 
     #[tool(
-        description = "Get observations surrounding an anchor point within the same session.",
+        description = "Get observations surrounding an anchor point within the same session. A command run several times back to back is one observation with `repeats` (count, first and last timestamp); set expand_repeats=true to list each run. Size: ~0.5–2 KB per observation, ~5–20 KB at the default 5 either side.",
         annotations(read_only_hint = true, open_world_hint = false)
     )]
     async fn timeline(
@@ -1587,13 +4124,15 @@ impl NmemServer {
         p: Parameters<TimelineParams>,
     ) -> Result<CallToolResult, ErrorData> {
         let start = std::time::Instant::now();
-        let result = self.do_timeline(p.0);
-        record_query_metrics("timeline", start);
+        let params_hash = crate::s3_audit::params_hash(&p.0);
+        let max_bytes = p.0.max_bytes;
+        let result = cap_response(self.do_timeline(p.0), max_bytes);
+        self.finish_query("timeline", &params_hash, &result, start);
         result
     }
 
     #[tool(
-        description = "Session summaries generated by local LLM. Returns structured JSON with intent, completed work, files changed, and next steps. Use optional before/after Unix timestamps to filter by session start time.",
+        description = "Session summaries generated by local LLM. Returns structured JSON with intent, completed work, files changed, and next steps, plus `sources` (the observation ids it was generated from — fetch them with get_observations to check a claim) and `unverified_files` (file references no source mentions). Use optional before/after Unix timestamps to filter by session start time. Pass cursor=\"\" to page with stable ordering; follow next_cursor until it is null. Size: ~1 KB per session, ~10 KB at the default limit of 10.",
         annotations(read_only_hint = true, open_world_hint = false)
     )]
     async fn session_summaries(
@@ -1601,13 +4140,15 @@ impl NmemServer {
         p: Parameters<SessionSummariesParams>,
     ) -> Result<CallToolResult, ErrorData> {
         let start = std::time::Instant::now();
-        let result = self.do_session_summaries(p.0);
-        record_query_metrics("session_summaries", start);
+        let params_hash = crate::s3_audit::params_hash(&p.0);
+        let max_bytes = p.0.max_bytes;
+        let result = cap_response(self.do_session_summaries(p.0), max_bytes);
+        self.finish_query("session_summaries", &params_hash, &result, start);
         result
     }
 
     #[tool(
-        description = "Regenerate the full context injection (intents, session summaries, recent observations, cross-project pins) as markdown. Same output as SessionStart but with current data. Use optional before Unix timestamp to produce context as of a past point in time.",
+        description = "Regenerate the full context injection (intents, session summaries, recent observations, cross-project pins) as markdown. Same output as SessionStart but with current data. Use optional before Unix timestamp to produce context as of a past point in time. Size: ~2–8 KB of markdown.",
         annotations(read_only_hint = true, open_world_hint = false)
     )]
     async fn regenerate_context(
@@ -1615,13 +4156,15 @@ impl NmemServer {
         p: Parameters<RegenerateContextParams>,
     ) -> Result<CallToolResult, ErrorData> {
         let start = std::time::Instant::now();
-        let result = self.do_regenerate_context(p.0);
-        record_query_metrics("regenerate_context", start);
+        let params_hash = crate::s3_audit::params_hash(&p.0);
+        let max_bytes = p.0.max_bytes;
+        let result = cap_response(self.do_regenerate_context(p.0), max_bytes);
+        self.finish_query("regenerate_context", &params_hash, &result, start);
         result
     }
 
     #[tool(
-        description = "Recent observations ranked by composite score (recency decay + type weight + project match). Deduped by file_path, keeping highest-scored entry per file. Use optional before/after Unix timestamps to window the results.",
+        description = "Recent observations ranked by composite score (recency decay + type weight + project match). Deduped by file_path, keeping highest-scored entry per file. Use optional before/after Unix timestamps to window the results. Pass your session_id to boost observations from this session and the sessions it resumed or continued. Pass cursor=\"\" to page with stable ordering; follow next_cursor until it is null. Without a limit, busy projects get enough results to cover their last few working days (reported as `limits`). Size: ~200 B per result.",
         annotations(read_only_hint = true, open_world_hint = false)
     )]
     async fn recent_context(
@@ -1629,13 +4172,15 @@ impl NmemServer {
         p: Parameters<RecentContextParams>,
     ) -> Result<CallToolResult, ErrorData> {
         let start = std::time::Instant::now();
-        let result = self.do_recent_context(p.0);
-        record_query_metrics("recent_context", start);
+        let params_hash = crate::s3_audit::params_hash(&p.0);
+        let max_bytes = p.0.max_bytes;
+        let result = cap_response(self.do_recent_context(p.0), max_bytes);
+        self.finish_query("recent_context", &params_hash, &result, start);
         result
     }
 
     #[tool(
-        description = "Drill into a session's structure. Returns the session's prompts in order, each with its observations. Use to understand what happened step-by-step within a session.",
+        description = "Drill into a session's structure. Returns the session's prompts in order, each with its observations (a command run back to back is one entry with `repeat_count`), plus tool calls that never completed (denied or blocked). Use to understand what happened step-by-step within a session. Size: grows with the session — ~5 KB for a short one, 50 KB+ for a long one; set max_bytes.",
         annotations(read_only_hint = true, open_world_hint = false)
     )]
     async fn session_trace(
@@ -1643,13 +4188,15 @@ impl NmemServer {
         p: Parameters<SessionTraceParams>,
     ) -> Result<CallToolResult, ErrorData> {
         let start = std::time::Instant::now();
-        let result = self.do_session_trace(p.0);
-        record_query_metrics("session_trace", start);
+        let params_hash = crate::s3_audit::params_hash(&p.0);
+        let max_bytes = p.0.max_bytes;
+        let result = cap_response(self.do_session_trace(p.0), max_bytes);
+        self.finish_query("session_trace", &params_hash, &result, start);
         result
     }
 
     #[tool(
-        description = "Trace a file's history across sessions. Returns every session that touched this file, with the intent behind each touch. Use to understand why a file was read or modified over time.",
+        description = "Trace a file's history across sessions. Returns every session that touched this file, with the intent behind each touch. Follows renames, so touches under earlier names are included. A relative path or bare file name resolves to the recorded path (`resolved_path`, `match`: suffix or basename); if it could mean several files, `candidates` lists them. `risk` scores how often work on the file went wrong — failed commands right after edits, friction episodes, reverts — with a `warning` when it is elevated or high. Other projects that also touched the file are listed under `shared_with` with their last decision about it. Use to understand why a file was read or modified over time. Size: ~150 B per touching session.",
         annotations(read_only_hint = true, open_world_hint = false)
     )]
     async fn file_history(
@@ -1657,13 +4204,47 @@ impl NmemServer {
         p: Parameters<FileHistoryParams>,
     ) -> Result<CallToolResult, ErrorData> {
         let start = std::time::Instant::now();
-        let result = self.do_file_history(p.0);
-        record_query_metrics("file_history", start);
+        let params_hash = crate::s3_audit::params_hash(&p.0);
+        let max_bytes = p.0.max_bytes;
+        let result = cap_response(self.do_file_history(p.0), max_bytes);
+        self.finish_query("file_history", &params_hash, &result, start);
+        result
+    }
+
+    #[tool(
+        description = "What a file looked like when a past session first read it: size, git blob hash, and the first 2 KB (secrets redacted). Use before/session_id to see the version a decision was made against; `unchanged` tells whether the file on disk still matches. Snapshots are kept for a short retention window. Size: ~2.5 KB.",
+        annotations(read_only_hint = true, open_world_hint = false)
+    )]
+    async fn file_snapshot(
+        &self,
+        p: Parameters<FileSnapshotParams>,
+    ) -> Result<CallToolResult, ErrorData> {
+        let start = std::time::Instant::now();
+        let params_hash = crate::s3_audit::params_hash(&p.0);
+        let max_bytes = p.0.max_bytes;
+        let result = cap_response(self.do_file_snapshot(p.0), max_bytes);
+        self.finish_query("file_snapshot", &params_hash, &result, start);
+        result
+    }
+
+    #[tool(
+        description = "How the agent's plan evolved: TodoWrite items as they were added, changed status (pending → in_progress → completed), or were dropped (`removed`), plus plans proposed via ExitPlanMode. Oldest first. Defaults to the latest session with a plan; pass session_id or project to pick another. Size: ~1–5 KB.",
+        annotations(read_only_hint = true, open_world_hint = false)
+    )]
+    async fn plan_history(
+        &self,
+        p: Parameters<PlanHistoryParams>,
+    ) -> Result<CallToolResult, ErrorData> {
+        let start = std::time::Instant::now();
+        let params_hash = crate::s3_audit::params_hash(&p.0);
+        let max_bytes = p.0.max_bytes;
+        let result = cap_response(self.do_plan_history(p.0), max_bytes);
+        self.finish_query("plan_history", &params_hash, &result, start);
         result
     }
 
     #[tool(
-        description = "Get git history summary for a file: commits, churn, co-changes, recent messages. Returns ~40 tokens by default. Set full=true for complete commit list as JSON.",
+        description = "Get git history summary for a file: commits, churn, co-changes, recent messages. Returns ~40 tokens by default. Set full=true for complete commit list as JSON. Size with full=true: ~200 B per commit.",
         annotations(read_only_hint = true, open_world_hint = false)
     )]
     async fn git_file_summary(
@@ -1671,8 +4252,10 @@ impl NmemServer {
         p: Parameters<GitFileSummaryParams>,
     ) -> Result<CallToolResult, ErrorData> {
         let start = std::time::Instant::now();
-        let result = self.do_git_file_summary(p.0);
-        record_query_metrics("git_file_summary", start);
+        let params_hash = crate::s3_audit::params_hash(&p.0);
+        let max_bytes = p.0.max_bytes;
+        let result = cap_response(self.do_git_file_summary(p.0), max_bytes);
+        self.finish_query("git_file_summary", &params_hash, &result, start);
         result
     }
 
@@ -1685,13 +4268,14 @@ impl NmemServer {
         p: Parameters<QueueTaskParams>,
     ) -> Result<CallToolResult, ErrorData> {
         let start = std::time::Instant::now();
+        let params_hash = crate::s3_audit::params_hash(&p.0);
         let result = self.do_queue_task(p.0);
-        record_query_metrics("queue_task", start);
+        self.finish_query("queue_task", &params_hash, &result, start);
         result
     }
 
     #[tool(
-        description = "Create an agent-authored marker observation. Use to record conclusions, decisions, or waypoints not tied to a tool use. Markers are classified on all 5 dimensions and attached to the most recent session.",
+        description = "Create an agent-authored marker observation. Use to record conclusions, decisions, or waypoints not tied to a tool use. Markers are classified on all 5 dimensions and attached to the most recent session. Pass reply_to (a marker id) to continue its thread when a decision changes or a waypoint is followed up, and status to record where it stands; session context shows a thread as one item, newest first.",
         annotations(read_only_hint = false, open_world_hint = false)
     )]
     async fn create_marker(
@@ -1699,13 +4283,90 @@ impl NmemServer {
         p: Parameters<CreateMarkerParams>,
     ) -> Result<CallToolResult, ErrorData> {
         let start = std::time::Instant::now();
+        let params_hash = crate::s3_audit::params_hash(&p.0);
         let result = self.do_create_marker(p.0);
-        record_query_metrics("create_marker", start);
+        self.finish_query("create_marker", &params_hash, &result, start);
+        result
+    }
+
+    #[tool(
+        description = "Remember something on purpose: write `content` under `key`, for this project (default) or every project (`scope: \"global\"`). Memories are listed first in every future session's context, ahead of everything nmem captured on its own, so use this for what must not be lost — a required test command, a constraint, a decision the user stated. Writing an existing key replaces it; `ttl` (\"2h\", \"7d\") lets it lapse. Prefer create_marker for a waypoint in the current session's story.",
+        annotations(read_only_hint = false, open_world_hint = false)
+    )]
+    async fn remember(
+        &self,
+        p: Parameters<RememberParams>,
+    ) -> Result<CallToolResult, ErrorData> {
+        let start = std::time::Instant::now();
+        let params_hash = crate::s3_audit::params_hash(&p.0);
+        let result = self.do_remember(p.0);
+        self.finish_query("remember", &params_hash, &result, start);
+        result
+    }
+
+    #[tool(
+        description = "Forget a memory written with remember, by key and scope. Use when it is no longer true, so it stops appearing in session context. Returns status `forgotten` or `not_found`.",
+        annotations(read_only_hint = false, destructive_hint = true, open_world_hint = false)
+    )]
+    async fn forget(
+        &self,
+        p: Parameters<ForgetParams>,
+    ) -> Result<CallToolResult, ErrorData> {
+        let start = std::time::Instant::now();
+        let params_hash = crate::s3_audit::params_hash(&p.0);
+        let result = self.do_forget(p.0);
+        self.finish_query("forget", &params_hash, &result, start);
+        result
+    }
+
+    #[tool(
+        description = "Open an investigation: a bracketed line of inquiry. Call when starting to dig into a question (why something fails, where something lives). Observations recorded until conclude_investigation are attached to it. One open investigation per session.",
+        annotations(read_only_hint = false, open_world_hint = false)
+    )]
+    async fn start_investigation(
+        &self,
+        p: Parameters<StartInvestigationParams>,
+    ) -> Result<CallToolResult, ErrorData> {
+        let start = std::time::Instant::now();
+        let params_hash = crate::s3_audit::params_hash(&p.0);
+        let result = self.do_start_investigation(p.0);
+        self.finish_query("start_investigation", &params_hash, &result, start);
+        result
+    }
+
+    #[tool(
+        description = "Conclude an investigation with what was found. Captures the files touched since start_investigation; concluded investigations are shown prominently in future session context when related files or terms come up.",
+        annotations(read_only_hint = false, open_world_hint = false)
+    )]
+    async fn conclude_investigation(
+        &self,
+        p: Parameters<ConcludeInvestigationParams>,
+    ) -> Result<CallToolResult, ErrorData> {
+        let start = std::time::Instant::now();
+        let params_hash = crate::s3_audit::params_hash(&p.0);
+        let result = self.do_conclude_investigation(p.0);
+        self.finish_query("conclude_investigation", &params_hash, &result, start);
+        result
+    }
+
+    #[tool(
+        description = "Run several read tools in one call. Each request names a tool (search, get_observations, timeline, recent_context, session_summaries, session_trace, file_history, working_set) and its params; results are returned as a JSON object keyed by request key. Use at the start of a task to replace several sequential retrieval calls. Size: the sum of its requests; max_bytes caps the whole bundle, and each request may carry its own.",
+        annotations(read_only_hint = true, open_world_hint = false)
+    )]
+    async fn bundle(
+        &self,
+        p: Parameters<BundleParams>,
+    ) -> Result<CallToolResult, ErrorData> {
+        let start = std::time::Instant::now();
+        let params_hash = crate::s3_audit::params_hash(&p.0);
+        let max_bytes = p.0.max_bytes;
+        let result = cap_response(self.do_bundle(p.0), max_bytes);
+        self.finish_query("bundle", &params_hash, &result, start);
         result
     }
 
     #[tool(
-        description = "Returns the current session's stance (phase × scope) with trend analysis and retrieval guidance. Call this periodically to orient your retrieval strategy. The `guidance` field tells you what nmem tools to use based on your current cognitive trajectory. When scope trends toward diverge, prior sessions' next_steps become relevant. When in deep think, search for prior conclusions. When in sustained act+converge, no retrieval action needed unless encountering new files.",
+        description = "Returns the current session's stance (phase × scope) with trend analysis and retrieval guidance. Call this periodically to orient your retrieval strategy. The `guidance` field tells you what nmem tools to use based on your current cognitive trajectory. When scope trends toward diverge, prior sessions' next_steps become relevant. When in deep think, search for prior conclusions. When in sustained act+converge, no retrieval action needed unless encountering new files. Size: ~1 KB.",
         annotations(read_only_hint = true, open_world_hint = false)
     )]
     async fn current_stance(
@@ -1713,8 +4374,129 @@ impl NmemServer {
         p: Parameters<CurrentStanceParams>,
     ) -> Result<CallToolResult, ErrorData> {
         let start = std::time::Instant::now();
-        let result = self.do_current_stance(p.0);
-        record_query_metrics("current_stance", start);
+        let params_hash = crate::s3_audit::params_hash(&p.0);
+        let max_bytes = p.0.max_bytes;
+        let result = cap_response(self.do_current_stance(p.0), max_bytes);
+        self.finish_query("current_stance", &params_hash, &result, start);
+        result
+    }
+
+    #[tool(
+        description = "Returns the working set of the task in progress: the current episode's hot files (read/edit counts), commands that failed in it (and whether they have since passed), and prior episodes from other sessions that touched the same files — those from sessions this one resumed or continued first (`lineage`), each with its `outcome` (succeeded, blocked, or abandoned) once labeled. Cheaper and more focused than session-level history when you are mid-task. Size: ~2–4 KB.",
+        annotations(read_only_hint = true, open_world_hint = false)
+    )]
+    async fn working_set(
+        &self,
+        p: Parameters<WorkingSetParams>,
+    ) -> Result<CallToolResult, ErrorData> {
+        let start = std::time::Instant::now();
+        let params_hash = crate::s3_audit::params_hash(&p.0);
+        let max_bytes = p.0.max_bytes;
+        let result = cap_response(self.do_working_set(p.0), max_bytes);
+        self.finish_query("working_set", &params_hash, &result, start);
+        result
+    }
+
+    #[tool(
+        description = "Find observations whose content reads like a given one — the same error output, a repeated command, an edit made again elsewhere — by MinHash similarity of word shingles, no embeddings involved. Returns the closest matches with their estimated `similarity` (0–1, default floor 0.5), session, project, and a preview; `comparable: false` means the observation is too short to compare. Use after search or get_observations to ask \"where else did this happen?\" Size: ~250 B per match.",
+        annotations(read_only_hint = true, open_world_hint = false)
+    )]
+    async fn similar_observations(
+        &self,
+        p: Parameters<SimilarObservationsParams>,
+    ) -> Result<CallToolResult, ErrorData> {
+        let start = std::time::Instant::now();
+        let params_hash = crate::s3_audit::params_hash(&p.0);
+        let max_bytes = p.0.max_bytes;
+        let result = cap_response(self.do_similar_observations(p.0), max_bytes);
+        self.finish_query("similar_observations", &params_hash, &result, start);
+        result
+    }
+
+    #[tool(
+        description = "Tests that keep failing: individual test names parsed from failed cargo, pytest, jest, and go test output, with first/last seen, total failures, how many sessions they failed in, and `flaky_runs` (failing runs whose command later passed unchanged). Lists tests that failed in at least `min_sessions` sessions (default 2) or ever looked flaky, flakiest first. Use before chasing a failure to learn whether it is new or a known offender. Size: ~200 B per test.",
+        annotations(read_only_hint = true, open_world_hint = false)
+    )]
+    async fn flaky_tests(&self, p: Parameters<FlakyTestsParams>) -> Result<CallToolResult, ErrorData> {
+        let start = std::time::Instant::now();
+        let params_hash = crate::s3_audit::params_hash(&p.0);
+        let max_bytes = p.0.max_bytes;
+        let result = cap_response(self.do_flaky_tests(p.0), max_bytes);
+        self.finish_query("flaky_tests", &params_hash, &result, start);
+        result
+    }
+
+    #[tool(
+        description = "Every recorded failure of one test: its summary (project, framework, first/last seen, failures, sessions) and the failing runs newest first, each with the observation id, session, command, and whether that run was later relabelled flaky. `name` may be the full name or its tail (`refresh` for `auth::tests::refresh`); when several tests match, returns `ambiguous: true` with the candidates. Follow up with get_observations on a run's obs_id for the output. Size: ~150 B per run.",
+        annotations(read_only_hint = true, open_world_hint = false)
+    )]
+    async fn test_history(&self, p: Parameters<TestHistoryParams>) -> Result<CallToolResult, ErrorData> {
+        let start = std::time::Instant::now();
+        let params_hash = crate::s3_audit::params_hash(&p.0);
+        let max_bytes = p.0.max_bytes;
+        let result = cap_response(self.do_test_history(p.0), max_bytes);
+        self.finish_query("test_history", &params_hash, &result, start);
+        result
+    }
+
+    #[tool(
+        description = "Roll up a named time window — \"this-week\", \"last-month\", or a sprint or release from `[windows]` config (\"sprint-42\") — into what happened inside it: session and observation counts, the episodes started (intent, outcome), decisions (markers and what episode narratives learned), and failures by error class with the most repeated ones. Use for a standup, retro, or sprint review. Every tool that takes before/after also takes `window`. Size: ~150 B per episode or decision, ~3-8 KB typical.",
+        annotations(read_only_hint = true, open_world_hint = false)
+    )]
+    async fn window_summary(&self, p: Parameters<WindowSummaryParams>) -> Result<CallToolResult, ErrorData> {
+        let start = std::time::Instant::now();
+        let params_hash = crate::s3_audit::params_hash(&p.0);
+        let max_bytes = p.0.max_bytes;
+        let result = cap_response(self.do_window_summary(p.0), max_bytes);
+        self.finish_query("window_summary", &params_hash, &result, start);
+        result
+    }
+
+    #[tool(
+        description = "Regex search over stored observation content and metadata (error output of failed commands), for precision queries full-text search can't express, e.g. error codes `E0(5|6)\\d\\d` or `timeout=\\d{4,}`. Scans newest first and stops after 20,000 observations; `scan_limited: true` means older matches may exist — narrow with project, obs_type, a time range, or an fts pre-filter query. Prefer search for words and phrases. Size: ~300 B per match, ~6 KB at the default limit of 20.",
+        annotations(read_only_hint = true, open_world_hint = false)
+    )]
+    async fn grep(
+        &self,
+        p: Parameters<GrepParams>,
+    ) -> Result<CallToolResult, ErrorData> {
+        let start = std::time::Instant::now();
+        let params_hash = crate::s3_audit::params_hash(&p.0);
+        let max_bytes = p.0.max_bytes;
+        let result = cap_response(self.do_grep(p.0), max_bytes);
+        self.finish_query("grep", &params_hash, &result, start);
+        result
+    }
+
+    #[tool(
+        description = "Reports how nmem memory is actually used: calls per tool (errors, empty results, latency, repeated lookups), the most frequently retrieved observations, and per obs_type the share of stored observations ever retrieved. Built from the query audit log. Size: ~2–5 KB.",
+        annotations(read_only_hint = true, open_world_hint = false)
+    )]
+    async fn memory_usage_report(
+        &self,
+        p: Parameters<MemoryUsageReportParams>,
+    ) -> Result<CallToolResult, ErrorData> {
+        let start = std::time::Instant::now();
+        let params_hash = crate::s3_audit::params_hash(&p.0);
+        let max_bytes = p.0.max_bytes;
+        let result = cap_response(self.do_memory_usage_report(p.0), max_bytes);
+        self.finish_query("memory_usage_report", &params_hash, &result, start);
+        result
+    }
+
+    #[tool(
+        description = "Returns a context pack: a curated bundle of saved searches, pinned observations and markers, and recently touched files, configured as `[packs.<name>]`, for a recurring situation such as a release or a schema migration. Packs are queued when a prompt mentions one of their intent words or a tool touches one of their files, and handed over once per session with the next hook; pass `name` to render a pack on demand, or omit it to take the packs queued for the session. Size: ~1–4 KB per pack.",
+        annotations(read_only_hint = false, open_world_hint = false)
+    )]
+    async fn context_pack(
+        &self,
+        p: Parameters<ContextPackParams>,
+    ) -> Result<CallToolResult, ErrorData> {
+        let start = std::time::Instant::now();
+        let params_hash = crate::s3_audit::params_hash(&p.0);
+        let max_bytes = p.0.max_bytes;
+        let result = cap_response(self.do_context_pack(p.0), max_bytes);
+        self.finish_query("context_pack", &params_hash, &result, start);
         result
     }
 }
@@ -1722,31 +4504,206 @@ impl NmemServer {
 #[tool_handler]
 impl ServerHandler for NmemServer {
     fn get_info(&self) -> ServerInfo {
-        ServerInfo::new(ServerCapabilities::builder().enable_tools().build())
+        ServerInfo::new(ServerCapabilities::builder().enable_tools().enable_logging().build())
             .with_instructions("nmem: cross-session memory for AI coding agents. Stores records of what the agent did in prior sessions (files read/edited, commands run, searches performed). NOT a general-purpose database — only contains the agent's own past actions and their context.")
     }
+
+    fn set_level(
+        &self,
+        request: SetLevelRequestParams,
+        _context: rmcp::service::RequestContext<rmcp::RoleServer>,
+    ) -> impl std::future::Future<Output = Result<(), ErrorData>> + Send + '_ {
+        if let Ok(mut level) = self.log_level.lock() {
+            *level = Some(request.level);
+        }
+        std::future::ready(Ok(()))
+    }
 }
 
-pub fn handle_serve(db_path: &Path) -> Result<(), NmemError> {
-    let conn = open_db_readonly(db_path)?;
-    crate::db::register_udfs(&conn)?;
-    let db: DbHandle = Arc::new(Mutex::new(conn));
-    let server = NmemServer::new(db);
+/// Rank of an MCP log level, lowest first.
+fn log_severity(level: LoggingLevel) -> u8 {
+    match level {
+        LoggingLevel::Debug => 0,
+        LoggingLevel::Info => 1,
+        LoggingLevel::Notice => 2,
+        LoggingLevel::Warning => 3,
+        LoggingLevel::Error => 4,
+        LoggingLevel::Critical => 5,
+        LoggingLevel::Alert => 6,
+        LoggingLevel::Emergency => 7,
+    }
+}
 
-    let rt = tokio::runtime::Builder::new_multi_thread()
-        .enable_all()
-        .build()
-        .map_err(NmemError::Io)?;
+impl NmemServer {
+    /// Whether the client asked for log messages at `level` or below.
+    fn wants_log(&self, level: LoggingLevel) -> bool {
+        self.log_level
+            .lock()
+            .ok()
+            .and_then(|l| *l)
+            .is_some_and(|min| log_severity(level) >= log_severity(min))
+    }
+
+    /// Stance events (`s4_stance`) since the last call, if a hook has
+    /// committed since then.
+    fn stance_events(
+        &self,
+        watch: &mut crate::s4_stance::StanceWatch,
+        data_version: &mut Option<i64>,
+    ) -> Result<Vec<crate::s4_stance::StanceEvent>, NmemError> {
+        let db = self.db.lock().map_err(|e| NmemError::Config(format!("db lock: {e}")))?;
+        let version: i64 = db.query_row("PRAGMA data_version", [], |r| r.get(0))?;
+        if *data_version == Some(version) {
+            return Ok(Vec::new());
+        }
+        *data_version = Some(version);
+        watch.check(&db)
+    }
+
+    /// Poll the database every `STANCE_POLL` and push stance changes to the
+    /// client as `notifications/message` until it goes away.
+    fn watch_stance(self, peer: rmcp::service::Peer<rmcp::RoleServer>) {
+        tokio::spawn(async move {
+            let mut watch = crate::s4_stance::StanceWatch::default();
+            let mut data_version = None;
+            let mut tick = tokio::time::interval(STANCE_POLL);
+            loop {
+                tick.tick().await;
+                let events = match self.stance_events(&mut watch, &mut data_version) {
+                    Ok(events) => events,
+                    Err(e) => {
+                        log::debug!("stance watch: {e}");
+                        continue;
+                    }
+                };
+                for event in events {
+                    let level = match event.urgency {
+                        crate::s4_stance::Urgency::Info => LoggingLevel::Info,
+                        crate::s4_stance::Urgency::Notice => LoggingLevel::Notice,
+                        crate::s4_stance::Urgency::Warning => LoggingLevel::Warning,
+                    };
+                    if !self.wants_log(level) {
+                        continue;
+                    }
+                    let data = serde_json::to_value(&event).unwrap_or_default();
+                    let message = LoggingMessageNotificationParam::new(level, data).with_logger("nmem.stance");
+                    if let Err(e) = peer.notify_logging_message(message).await {
+                        log::debug!("stance watch stopped: {e}");
+                        return;
+                    }
+                }
+            }
+        });
+    }
+}
+
+/// How often `serve` checks whether a hook has written.
+const STANCE_POLL: std::time::Duration = std::time::Duration::from_secs(1);
+
+/// Prepared statements kept per connection in lean mode (rusqlite's default is 16).
+const LEAN_STATEMENT_CACHE: usize = 4;
+/// SQLite page cache per read connection in lean mode, as a negative KiB
+/// count (SQLite's default is -2000).
+const LEAN_PAGE_CACHE_KIB: i64 = -512;
+
+pub fn handle_serve(db_path: &Path, attach: &[std::path::PathBuf], lean: bool) -> Result<(), NmemError> {
+    let config = crate::s5_config::load_config().unwrap_or_default();
+    let lean = lean || config.serve.lean;
+    let model = crate::s5_config::resolve_importance(&config);
+    // Lean mode trades first-call latency for footprint: connections start
+    // with a small statement cache and no scoring functions, which the
+    // server registers on the first query that needs them.
+    let open_reader = |path: &Path| -> Result<Connection, NmemError> {
+        let conn = open_db_readonly(path)?;
+        if lean {
+            conn.set_prepared_statement_cache_capacity(LEAN_STATEMENT_CACHE);
+            conn.pragma_update(None, "cache_size", LEAN_PAGE_CACHE_KIB)?;
+        } else {
+            crate::db::register_udfs_with(&conn, model.clone())?;
+        }
+        Ok(conn)
+    };
+    // Open the writer first: its migrations must land before the read-only
+    // connection looks for query_log. It serves the write tools and, when
+    // enabled, the audit log.
+    let writer: Option<DbHandle> = if db_path.exists() {
+        match crate::db::open_db(db_path) {
+            Ok(conn) => {
+                if lean {
+                    conn.set_prepared_statement_cache_capacity(LEAN_STATEMENT_CACHE);
+                }
+                Some(Arc::new(Mutex::new(conn)))
+            }
+            Err(e) => {
+                log::warn!("write tools disabled: {e}");
+                None
+            }
+        }
+    } else {
+        None
+    };
+    let query_log = match &writer {
+        Some(w) if config.query_log.enabled => {
+            let pruned = w
+                .lock()
+                .map_err(|e| NmemError::Config(format!("query_log lock: {e}")))
+                .and_then(|conn| crate::s3_audit::prune(&conn, config.query_log.retention_days, unix_now()));
+            match pruned {
+                Ok(_) => Some(w.clone()),
+                Err(e) => {
+                    log::warn!("query_log disabled: {e}");
+                    None
+                }
+            }
+        }
+        _ => None,
+    };
+
+    let db: DbHandle = Arc::new(Mutex::new(open_reader(db_path)?));
+    let mut server = NmemServer::new(db);
+    if lean {
+        server = server.with_lazy_udfs(model.clone());
+    }
+    if let Some(w) = writer {
+        server = server.with_writer(w);
+    }
+    if let Some(log) = query_log {
+        server = server.with_query_log(log);
+    }
+    for (name, path) in attached_stores(&config.serve.attach, attach) {
+        match open_reader(&path) {
+            Ok(conn) => {
+                log::info!("attached {name}: {}", path.display());
+                server = server.with_attached(name, Arc::new(Mutex::new(conn)));
+            }
+            Err(e) => log::warn!("attached db {name} ({}) skipped: {e}", path.display()),
+        }
+    }
+
+    let mut builder = if lean {
+        tokio::runtime::Builder::new_current_thread()
+    } else {
+        tokio::runtime::Builder::new_multi_thread()
+    };
+    let rt = builder.enable_all().build().map_err(NmemError::Io)?;
 
     rt.block_on(async {
-        let config = crate::s5_config::load_config().unwrap_or_default();
-        let provider = crate::metrics::init_meter_provider(&config.metrics);
+        // A meter provider runs its own export loop; lean mode goes without.
+        let provider = if lean {
+            None
+        } else {
+            crate::metrics::init_meter_provider(&config.metrics)
+        };
 
-        log::info!("serve starting");
+        log::info!("serve starting{}", if lean { " (lean)" } else { "" });
+        let watcher = config.serve.stance_notifications.then(|| server.clone());
         let service = server
             .serve(stdio())
             .await
             .map_err(|e| NmemError::Config(format!("mcp: {e}")))?;
+        if let Some(watcher) = watcher {
+            watcher.watch_stance(service.peer().clone());
+        }
         service
             .waiting()
             .await
@@ -1760,3 +4717,28 @@ pub fn handle_serve(db_path: &Path) -> Result<(), NmemError> {
         Ok(())
     })
 }
+
+/// Configured `[serve.attach]` stores, then extra `--db` paths named by file
+/// stem (suffixed `-2`, `-3`, ... on collision).
+fn attached_stores(
+    configured: &std::collections::BTreeMap<String, std::path::PathBuf>,
+    extra: &[std::path::PathBuf],
+) -> Vec<(String, std::path::PathBuf)> {
+    let mut stores: Vec<(String, std::path::PathBuf)> =
+        configured.iter().map(|(n, p)| (n.clone(), p.clone())).collect();
+    for path in extra {
+        let stem = path
+            .file_stem()
+            .map(|s| s.to_string_lossy().into_owned())
+            .unwrap_or_else(|| "attached".into());
+        let taken = |name: &str| name == LOCAL_ORIGIN || stores.iter().any(|(n, _)| n == name);
+        let mut name = stem.clone();
+        let mut n = 2;
+        while taken(&name) {
+            name = format!("{stem}-{n}");
+            n += 1;
+        }
+        stores.push((name, path.clone()));
+    }
+    stores
+}
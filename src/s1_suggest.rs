@@ -0,0 +1,263 @@
+//! S1 Operations — intent autocomplete for shells and editors.
+//!
+//! `nmem suggest --prefix "fix the" --project X` completes what the user is
+//! typing from what they asked the agent before: past user prompts (through
+//! `prompts_fts`, every typed word a term and the last one a prefix) and
+//! episode intents (`work_units.intent`, matched the same way). Candidates
+//! are grouped by their normalized first line and ranked by how often they
+//! were asked (log-scaled) times how recently (the project's recency
+//! half-life), with completions that start with the typed text first.
+//!
+//! Plain output is one completion per line, ready for `fzf`, a zsh widget,
+//! or an editor's completion source; `--json` adds source, count, last use,
+//! and score.
+
+use crate::NmemError;
+use rusqlite::Connection;
+use serde::Serialize;
+use std::collections::HashMap;
+
+/// Longest completion offered; longer intents are cut at a word boundary.
+const MAX_CHARS: usize = 120;
+/// Prompts shorter than this are acknowledgements, not intents.
+const MIN_WORDS: usize = 3;
+/// Candidate rows read per source before grouping.
+const CANDIDATES: i64 = 500;
+/// Ranking boost for completions that start with the typed text.
+const PREFIX_BOOST: f64 = 2.0;
+
+#[derive(Debug, Serialize)]
+pub struct Suggestion {
+    pub text: String,
+    /// `prompt`, `episode`, or `prompt+episode`.
+    pub source: String,
+    /// Times it was asked.
+    pub count: usize,
+    pub last_used: i64,
+    pub score: f64,
+}
+
+/// Lowercased words of the typed text.
+fn words(text: &str) -> Vec<String> {
+    text.to_lowercase()
+        .split(|c: char| !c.is_alphanumeric() && c != '_')
+        .filter(|w| !w.is_empty())
+        .map(str::to_string)
+        .collect()
+}
+
+/// FTS5 query for `prefix`: every word quoted, the last one as a prefix
+/// unless the text ends in a space. `None` when nothing is typed.
+fn fts_query(prefix: &str) -> Option<String> {
+    let terms = words(prefix);
+    let (last, rest) = terms.split_last()?;
+    let mut query: Vec<String> = rest.iter().map(|w| format!("\"{w}\"")).collect();
+    let open = !prefix.ends_with(char::is_whitespace);
+    query.push(if open { format!("\"{last}\"*") } else { format!("\"{last}\"") });
+    Some(query.join(" "))
+}
+
+/// Whether `text` contains every typed word, the last as a word prefix.
+fn matches(text: &str, typed: &[String], open: bool) -> bool {
+    let have = words(text);
+    typed.iter().enumerate().all(|(i, w)| {
+        if open && i + 1 == typed.len() {
+            have.iter().any(|h| h.starts_with(w.as_str()))
+        } else {
+            have.contains(w)
+        }
+    })
+}
+
+/// First line, whitespace collapsed, cut to `MAX_CHARS` at a word boundary.
+fn completion_text(text: &str) -> String {
+    let line = text.lines().map(str::trim).find(|l| !l.is_empty()).unwrap_or_default();
+    let collapsed = line.split_whitespace().collect::<Vec<_>>().join(" ");
+    if collapsed.chars().count() <= MAX_CHARS {
+        return collapsed;
+    }
+    let cut: String = collapsed.chars().take(MAX_CHARS).collect();
+    match cut.rfind(' ') {
+        Some(space) => cut[..space].to_string(),
+        None => cut,
+    }
+}
+
+/// Ranked completions for `prefix` from `project`'s past prompts and
+/// episode intents (every project when `None`).
+pub fn suggest(
+    conn: &Connection,
+    prefix: &str,
+    project: Option<&str>,
+    half_life_days: f64,
+    limit: usize,
+    now: i64,
+) -> Result<Vec<Suggestion>, NmemError> {
+    let typed = words(prefix);
+    let open = !prefix.ends_with(char::is_whitespace);
+    let mut rows: Vec<(&'static str, String, i64)> = Vec::new();
+
+    let mut read = |source: &'static str, sql: &str, params: &[&dyn rusqlite::ToSql]| -> Result<(), NmemError> {
+        let mut stmt = conn.prepare(sql)?;
+        let found = stmt.query_map(params, |r| Ok((r.get::<_, String>(0)?, r.get::<_, i64>(1)?)))?;
+        for row in found {
+            let (text, ts) = row?;
+            rows.push((source, text, ts));
+        }
+        Ok(())
+    };
+    match fts_query(prefix) {
+        Some(query) => read(
+            "prompt",
+            "SELECT p.content, p.timestamp FROM prompts_fts f
+             JOIN prompts p ON p.id = f.rowid
+             JOIN sessions s ON s.id = p.session_id
+             WHERE prompts_fts MATCH ?1 AND p.source = 'user' AND (?2 IS NULL OR s.project = ?2)
+             ORDER BY p.timestamp DESC LIMIT ?3",
+            &[&query, &project, &CANDIDATES],
+        )?,
+        None => read(
+            "prompt",
+            "SELECT p.content, p.timestamp FROM prompts p
+             JOIN sessions s ON s.id = p.session_id
+             WHERE p.source = 'user' AND (?1 IS NULL OR s.project = ?1)
+             ORDER BY p.timestamp DESC LIMIT ?2",
+            &[&project, &CANDIDATES],
+        )?,
+    }
+    // Episode intents have no FTS index; scan the project's recent ones
+    read(
+        "episode",
+        "SELECT w.intent, w.started_at FROM work_units w
+         JOIN sessions s ON s.id = w.session_id
+         WHERE w.intent IS NOT NULL AND (?1 IS NULL OR s.project = ?1)
+         ORDER BY w.started_at DESC LIMIT ?2",
+        &[&project, &(CANDIDATES * 4)],
+    )?;
+
+    let mut grouped: HashMap<String, Suggestion> = HashMap::new();
+    for (source, content, ts) in rows {
+        if content.starts_with("<system-reminder>") || content.split_whitespace().count() < MIN_WORDS {
+            continue;
+        }
+        let text = completion_text(&content);
+        if !matches(&text, &typed, open) {
+            continue;
+        }
+        let entry = grouped.entry(text.to_lowercase()).or_insert_with(|| Suggestion {
+            text: text.clone(),
+            source: source.to_string(),
+            count: 0,
+            last_used: ts,
+            score: 0.0,
+        });
+        entry.count += 1;
+        if !entry.source.split('+').any(|s| s == source) {
+            entry.source = "prompt+episode".to_string();
+        }
+        if ts > entry.last_used {
+            entry.last_used = ts;
+            entry.text = text;
+        }
+    }
+
+    let typed_lower = prefix.trim_start().to_lowercase();
+    let mut suggestions: Vec<Suggestion> = grouped
+        .into_values()
+        .map(|mut s| {
+            let recency = crate::s3_importance::recency(now - s.last_used, half_life_days);
+            let boost = if !typed_lower.is_empty() && s.text.to_lowercase().starts_with(&typed_lower) {
+                PREFIX_BOOST
+            } else {
+                1.0
+            };
+            s.score = (1.0 + (s.count as f64).ln()) * (0.1 + recency) * boost;
+            s
+        })
+        .collect();
+    suggestions.sort_by(|a, b| b.score.total_cmp(&a.score).then(b.last_used.cmp(&a.last_used)));
+    suggestions.truncate(limit);
+    Ok(suggestions)
+}
+
+#[cfg(feature = "cli")]
+pub fn handle_suggest(db_path: &std::path::Path, args: &crate::cli::SuggestArgs) -> Result<(), NmemError> {
+    let config = crate::s5_config::load_config().unwrap_or_default();
+    let project = if args.all_projects {
+        None
+    } else {
+        Some(args.project.clone().unwrap_or_else(|| {
+            let cwd = std::env::current_dir()
+                .map(|p| p.to_string_lossy().to_string())
+                .unwrap_or_default();
+            crate::s5_project::derive_project_with_strategy(&cwd, config.project.strategy)
+        }))
+    };
+    let conn = crate::db::open_db_readonly(db_path)?;
+    let half_life = crate::s5_config::resolve_recency_half_life(&config, project.as_deref());
+    let now = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs() as i64;
+    let suggestions = suggest(&conn, &args.prefix, project.as_deref(), half_life, args.limit.clamp(1, 100), now)?;
+    for s in &suggestions {
+        if args.json {
+            println!("{}", serde_json::to_string(s)?);
+        } else {
+            println!("{}", s.text);
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn setup() -> Connection {
+        let mut conn = Connection::open_in_memory().unwrap();
+        crate::schema::MIGRATIONS.to_latest(&mut conn).unwrap();
+        conn.execute_batch(
+            "INSERT INTO sessions (id, project, started_at) VALUES ('a', 'p', 0), ('b', 'p', 0), ('c', 'other', 0);
+             INSERT INTO prompts (session_id, timestamp, source, content) VALUES
+                 ('a', 1000, 'user', 'fix the flaky login test'),
+                 ('b', 5000, 'user', 'fix the flaky login test'),
+                 ('b', 6000, 'user', 'fix the migration ordering bug'),
+                 ('b', 6100, 'user', 'please fix it'),
+                 ('b', 6200, 'user', 'we should fix the flaky login test soon'),
+                 ('c', 7000, 'user', 'fix the other project build');
+             INSERT INTO work_units (session_id, started_at, intent) VALUES
+                 ('a', 900, 'fix the flaky login test'),
+                 ('b', 4000, 'refactor the config loader');",
+        )
+        .unwrap();
+        conn
+    }
+
+    #[test]
+    fn ranks_frequent_prefix_matches_first_within_project() {
+        let conn = setup();
+        let got = suggest(&conn, "fix the fl", Some("p"), 30.0, 10, 7000).unwrap();
+        let texts: Vec<&str> = got.iter().map(|s| s.text.as_str()).collect();
+        assert_eq!(texts, ["fix the flaky login test", "we should fix the flaky login test soon"]);
+        assert_eq!(got[0].count, 3);
+        assert_eq!(got[0].source, "prompt+episode");
+
+        let got = suggest(&conn, "fix the ", Some("p"), 30.0, 10, 7000).unwrap();
+        assert!(got.iter().any(|s| s.text == "fix the migration ordering bug"));
+        assert!(!got.iter().any(|s| s.text.contains("other project")), "scoped to the project");
+        assert!(!got.iter().any(|s| s.text == "please fix it"), "the typed words must all appear");
+
+        let got = suggest(&conn, "conf", Some("p"), 30.0, 10, 7000).unwrap();
+        assert_eq!(got[0].text, "refactor the config loader");
+        assert_eq!(got[0].source, "episode");
+    }
+
+    #[test]
+    fn completion_text_takes_first_line_and_cuts_at_word() {
+        assert_eq!(completion_text("\n  fix   the bug\nmore detail"), "fix the bug");
+        let long = "word ".repeat(40);
+        let cut = completion_text(&long);
+        assert!(cut.chars().count() <= MAX_CHARS && cut.ends_with("word"));
+    }
+}
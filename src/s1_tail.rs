@@ -0,0 +1,282 @@
+//! S1 Operations — live tail of what the hooks record.
+//!
+//! `nmem tail` prints the newest observations and prompts as JSON lines,
+//! with the classifier labels and redaction flag they were stored with.
+//! `--follow` keeps polling: `PRAGMA data_version` changes whenever another
+//! connection commits, and only then are rows past the last seen ids read.
+
+#[cfg(feature = "cli")]
+use crate::cli::TailArgs;
+#[cfg(feature = "cli")]
+use crate::db::open_db_readonly;
+use crate::NmemError;
+use rusqlite::{Connection, params};
+use serde::Serialize;
+#[cfg(feature = "cli")]
+use std::path::Path;
+
+/// Characters of content shown per entry.
+const CONTENT_CHARS: usize = 200;
+
+#[cfg(feature = "cli")]
+const POLL_INTERVAL: std::time::Duration = std::time::Duration::from_millis(500);
+
+// Entries are printed and dropped one batch at a time; boxing buys nothing
+#[allow(clippy::large_enum_variant)]
+#[derive(Debug, Serialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum TailEntry {
+    Observation {
+        id: i64,
+        timestamp: i64,
+        session_id: String,
+        obs_type: String,
+        source_event: String,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        tool_name: Option<String>,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        file_path: Option<String>,
+        content: String,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        phase: Option<String>,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        scope: Option<String>,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        locus: Option<String>,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        novelty: Option<String>,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        friction: Option<String>,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        error_class: Option<String>,
+        redacted: bool,
+        is_pinned: bool,
+    },
+    Prompt {
+        id: i64,
+        timestamp: i64,
+        session_id: String,
+        source: String,
+        content: String,
+    },
+}
+
+impl TailEntry {
+    /// Time order; within a second a prompt comes before the tool calls it led to.
+    fn sort_key(&self) -> (i64, u8, i64) {
+        match self {
+            TailEntry::Prompt { timestamp, id, .. } => (*timestamp, 0, *id),
+            TailEntry::Observation { timestamp, id, .. } => (*timestamp, 1, *id),
+        }
+    }
+}
+
+/// Highest observation and prompt ids already printed.
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub struct TailCursor {
+    pub obs_id: i64,
+    pub prompt_id: i64,
+}
+
+fn truncate(s: String) -> String {
+    match s.char_indices().nth(CONTENT_CHARS) {
+        Some((i, _)) => format!("{}…", &s[..i]),
+        None => s,
+    }
+}
+
+fn query_observations(
+    conn: &Connection,
+    session: Option<&str>,
+    after_id: i64,
+    newest: Option<i64>,
+) -> Result<Vec<TailEntry>, NmemError> {
+    // With `newest`, the last N rows; otherwise everything past `after_id`.
+    let mut stmt = conn.prepare_cached(
+        "SELECT id, timestamp, session_id, obs_type, source_event, tool_name, file_path, content,
+                phase, scope, locus, novelty, friction, error_class,
+                COALESCE(json_extract(metadata, '$.redacted'), 0), is_pinned
+         FROM observations
+         WHERE id > ?1 AND (?2 IS NULL OR session_id = ?2)
+         ORDER BY CASE WHEN ?3 IS NULL THEN id ELSE -id END
+         LIMIT COALESCE(?3, -1)",
+    )?;
+    let rows = stmt
+        .query_map(params![after_id, session, newest], |r| {
+            Ok(TailEntry::Observation {
+                id: r.get(0)?,
+                timestamp: r.get(1)?,
+                session_id: r.get(2)?,
+                obs_type: r.get(3)?,
+                source_event: r.get(4)?,
+                tool_name: r.get(5)?,
+                file_path: r.get(6)?,
+                content: truncate(r.get(7)?),
+                phase: r.get(8)?,
+                scope: r.get(9)?,
+                locus: r.get(10)?,
+                novelty: r.get(11)?,
+                friction: r.get(12)?,
+                error_class: r.get(13)?,
+                redacted: r.get::<_, i64>(14)? != 0,
+                is_pinned: r.get::<_, i64>(15)? != 0,
+            })
+        })?
+        .collect::<Result<_, _>>()?;
+    Ok(rows)
+}
+
+fn query_prompts(
+    conn: &Connection,
+    session: Option<&str>,
+    after_id: i64,
+    newest: Option<i64>,
+) -> Result<Vec<TailEntry>, NmemError> {
+    let mut stmt = conn.prepare_cached(
+        "SELECT id, timestamp, session_id, source, content
+         FROM prompts
+         WHERE id > ?1 AND (?2 IS NULL OR session_id = ?2)
+         ORDER BY CASE WHEN ?3 IS NULL THEN id ELSE -id END
+         LIMIT COALESCE(?3, -1)",
+    )?;
+    let rows = stmt
+        .query_map(params![after_id, session, newest], |r| {
+            Ok(TailEntry::Prompt {
+                id: r.get(0)?,
+                timestamp: r.get(1)?,
+                session_id: r.get(2)?,
+                source: r.get(3)?,
+                content: truncate(r.get(4)?),
+            })
+        })?
+        .collect::<Result<_, _>>()?;
+    Ok(rows)
+}
+
+/// Merge, order by time, and advance the cursor past what is returned.
+fn merge(mut entries: Vec<TailEntry>, cursor: &mut TailCursor) -> Vec<TailEntry> {
+    entries.sort_by_key(TailEntry::sort_key);
+    for e in &entries {
+        match e {
+            TailEntry::Observation { id, .. } => cursor.obs_id = cursor.obs_id.max(*id),
+            TailEntry::Prompt { id, .. } => cursor.prompt_id = cursor.prompt_id.max(*id),
+        }
+    }
+    entries
+}
+
+/// The newest `lines` entries, oldest first, and a cursor past them.
+pub fn latest(conn: &Connection, session: Option<&str>, lines: i64) -> Result<(Vec<TailEntry>, TailCursor), NmemError> {
+    let mut entries = query_observations(conn, session, 0, Some(lines))?;
+    entries.extend(query_prompts(conn, session, 0, Some(lines))?);
+    entries.sort_by_key(|e| std::cmp::Reverse(e.sort_key()));
+    entries.truncate(lines.max(0) as usize);
+
+    // Start following from the newest row, even one that didn't make the cut
+    let mut cursor = TailCursor {
+        obs_id: conn.query_row(
+            "SELECT COALESCE(MAX(id), 0) FROM observations WHERE ?1 IS NULL OR session_id = ?1",
+            params![session],
+            |r| r.get(0),
+        )?,
+        prompt_id: conn.query_row(
+            "SELECT COALESCE(MAX(id), 0) FROM prompts WHERE ?1 IS NULL OR session_id = ?1",
+            params![session],
+            |r| r.get(0),
+        )?,
+    };
+    let entries = merge(entries, &mut cursor);
+    Ok((entries, cursor))
+}
+
+/// Entries recorded after `cursor`, oldest first; advances `cursor`.
+pub fn since(conn: &Connection, session: Option<&str>, cursor: &mut TailCursor) -> Result<Vec<TailEntry>, NmemError> {
+    let mut entries = query_observations(conn, session, cursor.obs_id, None)?;
+    entries.extend(query_prompts(conn, session, cursor.prompt_id, None)?);
+    Ok(merge(entries, cursor))
+}
+
+#[cfg(feature = "cli")]
+fn print_entries(entries: &[TailEntry]) -> Result<(), NmemError> {
+    use std::io::Write;
+    let mut out = std::io::stdout().lock();
+    for entry in entries {
+        writeln!(out, "{}", serde_json::to_string(entry)?)?;
+    }
+    out.flush()?;
+    Ok(())
+}
+
+#[cfg(feature = "cli")]
+pub fn handle_tail(db_path: &Path, args: &TailArgs) -> Result<(), NmemError> {
+    let conn = open_db_readonly(db_path)?;
+    let session = args.session.as_deref();
+
+    let (entries, mut cursor) = latest(&conn, session, args.lines.clamp(0, 1000))?;
+    print_entries(&entries)?;
+    if !args.follow {
+        return Ok(());
+    }
+
+    let data_version = |conn: &Connection| -> Result<i64, NmemError> {
+        Ok(conn.query_row("PRAGMA data_version", [], |r| r.get(0))?)
+    };
+    let mut seen = data_version(&conn)?;
+    loop {
+        std::thread::sleep(POLL_INTERVAL);
+        let version = data_version(&conn)?;
+        if version == seen {
+            continue;
+        }
+        seen = version;
+        print_entries(&since(&conn, session, &mut cursor)?)?;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn latest_then_since() {
+        let mut conn = Connection::open_in_memory().unwrap();
+        crate::schema::MIGRATIONS.to_latest(&mut conn).unwrap();
+        conn.execute_batch(
+            "INSERT INTO sessions (id, project, started_at) VALUES ('a', 'p', 1), ('b', 'p', 1);
+             INSERT INTO prompts (id, session_id, timestamp, source, content) VALUES (1, 'a', 10, 'user', 'fix it');
+             INSERT INTO observations (id, session_id, timestamp, obs_type, source_event, content, metadata, phase)
+                 VALUES (1, 'a', 11, 'file_read', 'PostToolUse', 'Read a.rs', '{\"redacted\":true}', 'think'),
+                        (2, 'b', 12, 'command', 'PostToolUse', 'ls', NULL, NULL),
+                        (3, 'a', 13, 'file_edit', 'PostToolUse', 'Edit a.rs', NULL, 'act');",
+        )
+        .unwrap();
+
+        let (entries, mut cursor) = latest(&conn, Some("a"), 2).unwrap();
+        let json: Vec<serde_json::Value> = entries.iter().map(|e| serde_json::to_value(e).unwrap()).collect();
+        assert_eq!(json.len(), 2);
+        assert_eq!(json[0]["id"], 1);
+        assert_eq!(json[0]["redacted"], true);
+        assert_eq!(json[0]["phase"], "think");
+        assert_eq!(json[1]["id"], 3);
+        assert_eq!(cursor, TailCursor { obs_id: 3, prompt_id: 1 });
+
+        assert!(since(&conn, Some("a"), &mut cursor).unwrap().is_empty());
+        conn.execute_batch(
+            "INSERT INTO prompts (id, session_id, timestamp, source, content) VALUES (2, 'a', 20, 'user', 'now test');
+             INSERT INTO observations (id, session_id, timestamp, obs_type, source_event, content)
+                 VALUES (4, 'a', 21, 'command', 'PostToolUse', 'cargo test'),
+                        (5, 'b', 22, 'command', 'PostToolUse', 'other session');",
+        )
+        .unwrap();
+        let json: Vec<serde_json::Value> = since(&conn, Some("a"), &mut cursor)
+            .unwrap()
+            .iter()
+            .map(|e| serde_json::to_value(e).unwrap())
+            .collect();
+        assert_eq!(json.len(), 2);
+        assert_eq!(json[0]["kind"], "prompt");
+        assert_eq!(json[1]["kind"], "observation");
+        assert_eq!(json[1]["id"], 4);
+        assert_eq!(cursor, TailCursor { obs_id: 4, prompt_id: 2 });
+    }
+}
@@ -0,0 +1,327 @@
+//! S2 Coordination — every backfill in one dependency-ordered pass.
+//!
+//! `nmem backfill --dimension all` replaces five separate invocations. The
+//! four text classifiers (phase, scope, locus, novelty) share one scan:
+//! each batch of observations missing any label is read once and every
+//! missing label filled. Then `error_class` for failed observations,
+//! episode friction (which reads episodes, not labels), and last
+//! `obs_trace`, which snapshots the labels and friction into each episode.
+//!
+//! Each batch commits together with its stage's checkpoint in
+//! `backfill_progress`, so an interrupted run resumes where it stopped;
+//! a completed run clears the checkpoints. Narratives (LLM) stay a
+//! separate `--dimension narrative`.
+
+use crate::s2_inference::{ClassificationResult, ensure_classifier_run};
+use crate::NmemError;
+use rusqlite::{Connection, OptionalExtension, params};
+use serde::Serialize;
+use std::collections::BTreeMap;
+
+/// A text classifier dimension: label column, run id column, classifier
+/// name, and the model.
+struct Classifier {
+    column: &'static str,
+    run_id_column: &'static str,
+    name: &'static str,
+    classify: fn(&str) -> Option<ClassificationResult>,
+    model_hash: fn() -> Option<&'static str>,
+}
+
+const CLASSIFIERS: [Classifier; 4] = [
+    Classifier {
+        column: "phase",
+        run_id_column: "classifier_run_id",
+        name: "think-act",
+        classify: crate::s2_classify::classify,
+        model_hash: crate::s2_classify::current_model_hash,
+    },
+    Classifier {
+        column: "scope",
+        run_id_column: "scope_run_id",
+        name: "converge-diverge",
+        classify: crate::s2_scope::classify_scope,
+        model_hash: crate::s2_scope::current_scope_model_hash,
+    },
+    Classifier {
+        column: "locus",
+        run_id_column: "locus_run_id",
+        name: "internal-external",
+        classify: crate::s2_locus::classify_locus,
+        model_hash: crate::s2_locus::current_model_hash,
+    },
+    Classifier {
+        column: "novelty",
+        run_id_column: "novelty_run_id",
+        name: "routine-novel",
+        classify: crate::s2_novelty::classify_novelty,
+        model_hash: crate::s2_novelty::current_model_hash,
+    },
+];
+
+/// Stages in dependency order.
+pub const STAGES: [&str; 4] = ["classify", "error_class", "friction", "obs_trace"];
+
+/// What one stage did.
+#[derive(Debug, Default, Serialize)]
+pub struct StageReport {
+    pub stage: &'static str,
+    /// Rows written (observations labeled, episodes filled).
+    pub updated: u64,
+    /// Per-label counts, for the classifiers.
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    pub labels: BTreeMap<String, u64>,
+    /// Finished by an earlier, interrupted run.
+    pub resumed: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub note: Option<String>,
+}
+
+/// Rows each stage would touch, for `--dry-run`.
+pub fn pending(conn: &Connection) -> Result<Vec<(String, i64)>, NmemError> {
+    let mut out = Vec::new();
+    for c in &CLASSIFIERS {
+        let n: i64 =
+            conn.query_row(&format!("SELECT COUNT(*) FROM observations WHERE {} IS NULL", c.column), [], |r| r.get(0))?;
+        out.push((c.column.to_string(), n));
+    }
+    out.push(("error_class".into(), crate::s2_error::unlabeled(conn)?));
+    let episodes: i64 = conn.query_row("SELECT COUNT(*) FROM work_units", [], |r| r.get(0))?;
+    out.push(("friction".into(), episodes));
+    let traceless: i64 = conn.query_row("SELECT COUNT(*) FROM work_units WHERE obs_trace IS NULL", [], |r| r.get(0))?;
+    out.push(("obs_trace".into(), traceless));
+    Ok(out)
+}
+
+/// Where `stage` stopped: `None` if it never ran, `Some((last_id, done))`.
+fn checkpoint(conn: &Connection, stage: &str) -> Result<Option<(i64, bool)>, NmemError> {
+    Ok(conn
+        .query_row("SELECT last_id, done FROM backfill_progress WHERE stage = ?1", params![stage], |r| {
+            Ok((r.get(0)?, r.get(1)?))
+        })
+        .optional()?)
+}
+
+fn save_checkpoint(conn: &Connection, stage: &str, last_id: i64, done: bool, now: i64) -> Result<(), NmemError> {
+    conn.execute(
+        "INSERT INTO backfill_progress (stage, last_id, done, updated_at) VALUES (?1, ?2, ?3, ?4)
+         ON CONFLICT (stage) DO UPDATE SET last_id = excluded.last_id, done = excluded.done, updated_at = excluded.updated_at",
+        params![stage, last_id, done, now],
+    )?;
+    Ok(())
+}
+
+/// Run every stage not already done, in order. `run_metadata` goes on any
+/// classifier run created.
+pub fn run(
+    conn: &Connection,
+    batch_size: usize,
+    run_metadata: Option<&str>,
+    now: i64,
+) -> Result<Vec<StageReport>, NmemError> {
+    let batch_size = batch_size.max(1);
+    let mut reports = Vec::new();
+    for stage in STAGES {
+        let mut report = StageReport { stage, ..Default::default() };
+        let (last_id, done) = checkpoint(conn, stage)?.unwrap_or((0, false));
+        if done {
+            report.resumed = true;
+            reports.push(report);
+            continue;
+        }
+        match stage {
+            "classify" => classify_all(conn, &mut report, last_id, batch_size, run_metadata, now)?,
+            "error_class" => report.updated = crate::s2_error::label_unlabeled(conn, batch_size)? as u64,
+            "friction" => {
+                let (episodes, updated, orphaned) = crate::s4_memory::relabel_friction(conn)?;
+                report.updated = updated;
+                report.note = Some(format!("{episodes} episodes, {orphaned} orphans cleared"));
+            }
+            _ => {
+                let (filled, _) = crate::s4_memory::fill_obs_traces(conn)?;
+                report.updated = filled;
+            }
+        }
+        save_checkpoint(conn, stage, 0, true, now)?;
+        reports.push(report);
+    }
+    conn.execute("DELETE FROM backfill_progress", [])?;
+    Ok(reports)
+}
+
+/// Fill every missing classifier label in one scan, from `after` on.
+fn classify_all(
+    conn: &Connection,
+    report: &mut StageReport,
+    mut after: i64,
+    batch_size: usize,
+    run_metadata: Option<&str>,
+    now: i64,
+) -> Result<(), NmemError> {
+    let mut active = Vec::new();
+    let mut missing = Vec::new();
+    for c in &CLASSIFIERS {
+        match (c.model_hash)() {
+            Some(hash) => {
+                let run_id = ensure_classifier_run(conn, c.name, hash, None, None, run_metadata)?;
+                active.push((c, run_id));
+            }
+            None => missing.push(c.column),
+        }
+    }
+    if !missing.is_empty() {
+        report.note = Some(format!("no model for {}", missing.join(", ")));
+    }
+    if active.is_empty() {
+        return Ok(());
+    }
+
+    let null_any = active.iter().map(|(c, _)| format!("{} IS NULL", c.column)).collect::<Vec<_>>().join(" OR ");
+    let null_each = active.iter().map(|(c, _)| format!("{} IS NULL", c.column)).collect::<Vec<_>>().join(", ");
+    let select = format!("SELECT id, content, {null_each} FROM observations WHERE id > ?1 AND ({null_any}) ORDER BY id LIMIT ?2");
+    loop {
+        let rows: Vec<(i64, String, Vec<bool>)> = {
+            let mut stmt = conn.prepare_cached(&select)?;
+            stmt.query_map(params![after, batch_size as i64], |r| {
+                let needs = (0..active.len()).map(|i| r.get(2 + i)).collect::<Result<_, _>>()?;
+                Ok((r.get(0)?, r.get(1)?, needs))
+            })?
+            .collect::<Result<_, _>>()?
+        };
+        let Some(&(last, _, _)) = rows.last() else { break };
+
+        let tx = conn.unchecked_transaction()?;
+        for (id, content, needs) in &rows {
+            let mut labeled = false;
+            for ((c, run_id), needed) in active.iter().zip(needs) {
+                if !needed {
+                    continue;
+                }
+                if let Some(result) = (c.classify)(content) {
+                    tx.prepare_cached(&format!(
+                        "UPDATE observations SET {col} = ?1, {run} = ?2, {col}_confidence = ?3 WHERE id = ?4",
+                        col = c.column,
+                        run = c.run_id_column,
+                    ))?
+                    .execute(params![result.label, run_id, result.confidence, id])?;
+                    *report.labels.entry(format!("{}={}", c.column, result.label)).or_default() += 1;
+                    labeled = true;
+                }
+            }
+            report.updated += labeled as u64;
+        }
+        save_checkpoint(&tx, "classify", last, false, now)?;
+        tx.commit()?;
+        after = last;
+    }
+    Ok(())
+}
+
+/// CLI handler for `nmem backfill --dimension all`.
+#[cfg(feature = "cli")]
+pub fn handle_backfill_all(db_path: &std::path::Path, args: &crate::cli::BackfillArgs) -> Result<(), NmemError> {
+    let conn = crate::db::open_db(db_path)?;
+    if args.dry_run {
+        for (stage, n) in pending(&conn)? {
+            println!("{stage:<12} {n}");
+        }
+        log::info!("dry run — no changes made");
+        return Ok(());
+    }
+    let now = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs() as i64;
+    let reports = run(&conn, args.batch_size, args.metadata_json().as_deref(), now)?;
+    for r in &reports {
+        let mut line = format!("{:<12} {:>7} updated", r.stage, r.updated);
+        if r.resumed {
+            line.push_str("  (done in an earlier run)");
+        }
+        if !r.labels.is_empty() {
+            let labels: Vec<String> = r.labels.iter().map(|(k, v)| format!("{k} {v}")).collect();
+            line.push_str(&format!("  [{}]", labels.join(", ")));
+        }
+        if let Some(note) = &r.note {
+            line.push_str(&format!("  — {note}"));
+        }
+        println!("{line}");
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn setup() -> Connection {
+        let mut conn = Connection::open_in_memory().unwrap();
+        crate::schema::MIGRATIONS.to_latest(&mut conn).unwrap();
+        conn.execute_batch(
+            "INSERT INTO sessions (id, project, started_at) VALUES ('s1', 'p', 0);
+             INSERT INTO prompts (id, session_id, timestamp, source, content) VALUES (1, 's1', 0, 'user', 'fix the build');
+             INSERT INTO work_units (id, session_id, started_at, first_prompt_id, last_prompt_id, phase_signature)
+             VALUES (1, 's1', 0, 1, 1, '{\"failures\":1}');",
+        )
+        .unwrap();
+        for (id, content, failed) in [
+            (1, "Read src/main.rs", false),
+            (2, "cargo build", true),
+            (3, "Edit src/main.rs", false),
+            (4, "cargo build", false),
+        ] {
+            conn.execute(
+                "INSERT INTO observations (id, session_id, prompt_id, timestamp, obs_type, source_event, content, metadata)
+                 VALUES (?1, 's1', 1, ?1, 'command', 'PostToolUse', ?2, ?3)",
+                params![id, content, failed.then_some(r#"{"failed":true,"response":"error[E0425]: cannot find value"}"#)],
+            )
+            .unwrap();
+        }
+        conn
+    }
+
+    fn unlabeled(conn: &Connection) -> i64 {
+        conn.query_row(
+            "SELECT COUNT(*) FROM observations WHERE phase IS NULL OR scope IS NULL OR locus IS NULL OR novelty IS NULL",
+            [],
+            |r| r.get(0),
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn runs_every_stage_in_order() {
+        let conn = setup();
+        let reports = run(&conn, 3, None, 100).unwrap();
+        let stages: Vec<&str> = reports.iter().map(|r| r.stage).collect();
+        assert_eq!(stages, STAGES);
+        assert_eq!(reports[0].updated, 4);
+        assert_eq!(reports[0].labels.values().sum::<u64>(), 16, "four labels each");
+        assert_eq!(unlabeled(&conn), 0);
+        assert_eq!(reports[1].updated, 1);
+
+        // The trace carries the labels and friction written before it
+        let trace: String = conn.query_row("SELECT obs_trace FROM work_units WHERE id = 1", [], |r| r.get(0)).unwrap();
+        let trace: serde_json::Value = serde_json::from_str(&trace).unwrap();
+        assert_eq!(trace.as_array().unwrap().len(), 4);
+        assert!(trace[0]["p"].is_string() && trace[0]["f"].is_string(), "{trace}");
+
+        let left: i64 = conn.query_row("SELECT COUNT(*) FROM backfill_progress", [], |r| r.get(0)).unwrap();
+        assert_eq!(left, 0, "a finished run clears its checkpoints");
+    }
+
+    #[test]
+    fn resumes_from_the_checkpoint() {
+        let conn = setup();
+        // An earlier run classified through #2 and finished nothing else
+        save_checkpoint(&conn, "classify", 2, false, 50).unwrap();
+        let reports = run(&conn, 10, None, 100).unwrap();
+        assert_eq!(reports[0].updated, 2, "starts after #2");
+        assert_eq!(unlabeled(&conn), 2);
+
+        save_checkpoint(&conn, "classify", 0, true, 150).unwrap();
+        let reports = run(&conn, 10, None, 200).unwrap();
+        assert!(reports[0].resumed);
+        assert!(!reports[1].resumed);
+    }
+}
@@ -47,6 +47,7 @@ pub fn current_model_hash() -> Option<&'static str> {
 }
 
 /// Backfill phase labels for all observations with NULL phase.
+#[cfg(feature = "cli")]
 pub fn handle_backfill(db_path: &std::path::Path, args: &crate::cli::BackfillArgs) -> Result<(), crate::NmemError> {
     s2_inference::generic_backfill(
         db_path,
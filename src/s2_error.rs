@@ -0,0 +1,253 @@
+//! S2 Coordination — error taxonomy for failed tool calls.
+//!
+//! Rule-based: assigns each failure one label from a fixed taxonomy so errors
+//! can be grouped across sessions without relying on raw message text.
+//! `flaky` is never assigned from text — it is applied retroactively when the
+//! same command later succeeds with no file edit in between.
+
+use crate::NmemError;
+use rusqlite::{Connection, params};
+
+/// All labels, in precedence order for reporting.
+pub const ERROR_CLASSES: &[&str] = &[
+    "compile_error",
+    "test_failure",
+    "network",
+    "permission",
+    "missing_dependency",
+    "flaky",
+    "other",
+];
+
+/// Classify a failure from its response text (falls back to the command itself).
+pub fn classify_error(command: &str, response: &str) -> &'static str {
+    let text = if response.trim().is_empty() { command } else { response };
+    let lower = text.to_lowercase();
+
+    // errno names only as whole words, as Node/libuv print them
+    if lower.contains("permission denied")
+        || lower.contains("operation not permitted")
+        || has_code(&lower, "eacces")
+        || has_code(&lower, "eperm")
+    {
+        return "permission";
+    }
+
+    if lower.contains(": command not found")
+        || lower.contains("no module named")
+        || lower.contains("cannot find module")
+        || lower.contains("no matching package")
+        || lower.contains("modulenotfounderror")
+        || lower.lines().any(|l| {
+            let l = l.trim_start();
+            (l.starts_with("error") || l.starts_with("fatal")) && l.contains("is not installed")
+        })
+    {
+        return "missing_dependency";
+    }
+
+    // Timeouts only at the socket: a test or build that "timed out" is not a
+    // network failure
+    if lower.contains("connection refused")
+        || lower.contains("connection reset")
+        || lower.contains("connection timed out")
+        || lower.contains("could not resolve host")
+        || lower.contains("name or service not known")
+        || lower.contains("network is unreachable")
+        || lower.contains("temporary failure in name resolution")
+        || lower.contains("curl: (28)")
+        || has_code(&lower, "econnrefused")
+        || has_code(&lower, "etimedout")
+    {
+        return "network";
+    }
+
+    if lower.contains("test result: failed")
+        || lower.contains("tests failed")
+        || lower.contains("assertion failed")
+        || lower.contains("assertionerror")
+        || lower.contains("panicked at")
+        || lower.contains("failures:")
+        || lower.lines().any(|l| l.trim_start().starts_with("failed ") || l.contains("... failed"))
+    {
+        return "test_failure";
+    }
+
+    if text.contains("error[E")
+        || lower.contains("could not compile")
+        || lower.contains("syntaxerror")
+        || lower.contains("compilation failed")
+        || lower.contains("type error")
+        || lower.lines().any(|l| l.trim_start().starts_with("error:"))
+    {
+        return "compile_error";
+    }
+
+    "other"
+}
+
+/// `code` as a whole word, not inside a longer identifier.
+fn has_code(lower: &str, code: &str) -> bool {
+    let word = |c: Option<char>| c.is_some_and(|c| c.is_alphanumeric() || c == '_');
+    lower
+        .match_indices(code)
+        .any(|(i, _)| !word(lower[..i].chars().next_back()) && !word(lower[i + code.len()..].chars().next()))
+}
+
+/// Relabel earlier failures of `content` in this session as `flaky` if nothing
+/// was edited between the failure and this success (observation `success_id`).
+pub fn mark_flaky(conn: &Connection, session_id: &str, content: &str, success_id: i64) -> Result<usize, NmemError> {
+    // Runs on every successful Bash call; most have no earlier failure to relabel
+    let failed_before: bool = conn
+        .prepare_cached(
+            "SELECT EXISTS (SELECT 1 FROM observations
+             WHERE session_id = ?1 AND id < ?3 AND content = ?2
+               AND error_class IS NOT NULL AND error_class != 'flaky')",
+        )?
+        .query_row(params![session_id, content, success_id], |r| r.get(0))?;
+    if !failed_before {
+        return Ok(0);
+    }
+    let n = conn.execute(
+        "UPDATE observations SET error_class = 'flaky'
+         WHERE session_id = ?1 AND content = ?2 AND id < ?3
+           AND error_class IS NOT NULL AND error_class != 'flaky'
+           AND NOT EXISTS (
+               SELECT 1 FROM observations e
+               WHERE e.session_id = ?1 AND e.obs_type = 'file_edit'
+                 AND e.id > observations.id AND e.id < ?3
+           )",
+        params![session_id, content, success_id],
+    )?;
+    Ok(n)
+}
+
+/// Backfill error_class for failed observations recorded before classification existed.
+#[cfg(feature = "cli")]
+pub fn handle_backfill_error_class(
+    db_path: &std::path::Path,
+    args: &crate::cli::BackfillArgs,
+) -> Result<(), NmemError> {
+    let conn = crate::db::open_db(db_path)?;
+
+    if args.dry_run {
+        log::info!("error_class backfill (dry run) — {} failed observations unlabeled", unlabeled(&conn)?);
+        return Ok(());
+    }
+
+    let labeled = label_unlabeled(&conn, args.batch_size)?;
+    log::info!("error_class backfill complete — {labeled} observations labeled");
+    Ok(())
+}
+
+/// Failed observations without an `error_class`.
+pub fn unlabeled(conn: &Connection) -> Result<i64, NmemError> {
+    Ok(conn.query_row(
+        "SELECT COUNT(*) FROM observations WHERE error_class IS NULL AND json_extract(metadata, '$.failed') = 1",
+        [],
+        |r| r.get(0),
+    )?)
+}
+
+/// Label every failed observation without an `error_class`, committing
+/// every `batch_size`. Returns how many were labeled.
+pub fn label_unlabeled(conn: &Connection, batch_size: usize) -> Result<usize, NmemError> {
+    let mut stmt = conn.prepare(
+        "SELECT id, content, COALESCE(json_extract(metadata, '$.response'), '')
+         FROM observations
+         WHERE error_class IS NULL AND json_extract(metadata, '$.failed') = 1",
+    )?;
+    let rows: Vec<(i64, String, String)> = stmt
+        .query_map([], |r| Ok((r.get(0)?, r.get(1)?, r.get(2)?)))?
+        .collect::<Result<_, _>>()?;
+
+    for chunk in rows.chunks(batch_size.max(1)) {
+        let tx = conn.unchecked_transaction()?;
+        for (id, content, response) in chunk {
+            tx.execute(
+                "UPDATE observations SET error_class = ?1 WHERE id = ?2",
+                params![classify_error(content, response), id],
+            )?;
+        }
+        tx.commit()?;
+    }
+    Ok(rows.len())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn classifies_compile_errors() {
+        assert_eq!(classify_error("cargo build", "error[E0433]: failed to resolve: use of undeclared crate"), "compile_error");
+        assert_eq!(classify_error("cargo build", "error: could not compile `nmem` due to 2 previous errors"), "compile_error");
+    }
+
+    #[test]
+    fn classifies_test_failures() {
+        let resp = "running 3 tests\nthread 'x' panicked at src/lib.rs:10:5\ntest result: FAILED. 2 passed; 1 failed";
+        assert_eq!(classify_error("cargo test", resp), "test_failure");
+        assert_eq!(classify_error("pytest", "E   AssertionError: 1 != 2"), "test_failure");
+    }
+
+    #[test]
+    fn classifies_environment_failures() {
+        assert_eq!(classify_error("ls /root", "ls: cannot open directory '/root': Permission denied"), "permission");
+        assert_eq!(classify_error("foo", "bash: foo: command not found"), "missing_dependency");
+        assert_eq!(classify_error("python x.py", "ModuleNotFoundError: No module named 'requests'"), "missing_dependency");
+        assert_eq!(classify_error("curl localhost", "curl: (7) Failed to connect: Connection refused"), "network");
+    }
+
+    #[test]
+    fn environment_patterns_are_anchored_to_tool_output() {
+        assert_eq!(classify_error("npm i", "Error: EACCES: permission denied, mkdir '/usr/lib'"), "permission");
+        assert_eq!(classify_error("node x.js", "Error: EPERM: operation failed, rename 'a'"), "permission");
+        assert_eq!(classify_error("npm i", "Error: connect ECONNREFUSED 127.0.0.1:5432"), "network");
+        assert_eq!(classify_error("npm i", "Error: connect ETIMEDOUT: 10.0.0.1"), "network");
+        assert_eq!(classify_error("ssh host", "ssh: connect to host h port 22: Connection timed out"), "network");
+        assert_eq!(classify_error("curl x", "curl: (28) Operation timed out after 5000 ms"), "network");
+        assert_eq!(classify_error("rustup", "error: toolchain 'nightly' is not installed"), "missing_dependency");
+
+        assert_eq!(classify_error("cargo test", "test slow_io has been running for over 60 seconds, timed out"), "other");
+        assert_eq!(classify_error("grep", "src/keeperm.rs: no match"), "other");
+        assert_eq!(classify_error("make", "hook not installed, skipping\nmake: *** [all] Error 2"), "other");
+    }
+
+    #[test]
+    fn falls_back_to_command_and_other() {
+        assert_eq!(classify_error("bash: x: command not found", ""), "missing_dependency");
+        assert_eq!(classify_error("false", "exit code 1"), "other");
+    }
+
+    #[test]
+    fn flaky_requires_no_intervening_edit() {
+        let mut conn = Connection::open_in_memory().unwrap();
+        crate::schema::MIGRATIONS.to_latest(&mut conn).unwrap();
+        conn.execute("INSERT INTO sessions (id, project, started_at) VALUES ('s', 'p', 0)", []).unwrap();
+        let insert = |obs_type: &str, content: &str, class: Option<&str>| {
+            conn.execute(
+                "INSERT INTO observations (session_id, timestamp, obs_type, source_event, content, error_class)
+                 VALUES ('s', 0, ?1, 'PostToolUse', ?2, ?3)",
+                params![obs_type, content, class],
+            )
+            .unwrap();
+            conn.last_insert_rowid()
+        };
+
+        let a = insert("command", "cargo test", Some("test_failure"));
+        let ok = insert("command", "cargo test", None);
+        assert_eq!(mark_flaky(&conn, "s", "cargo test", ok).unwrap(), 1);
+
+        let b = insert("command", "cargo build", Some("compile_error"));
+        insert("file_edit", "/src/lib.rs", None);
+        let ok = insert("command", "cargo build", None);
+        assert_eq!(mark_flaky(&conn, "s", "cargo build", ok).unwrap(), 0);
+
+        let class = |id: i64| -> String {
+            conn.query_row("SELECT error_class FROM observations WHERE id = ?1", [id], |r| r.get(0)).unwrap()
+        };
+        assert_eq!(class(a), "flaky");
+        assert_eq!(class(b), "compile_error");
+    }
+}
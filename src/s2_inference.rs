@@ -5,8 +5,8 @@
 //! (s2_classify, s2_scope, s2_locus, s2_novelty, s2_friction) delegate
 //! to these shared types and functions.
 
-use serde::Deserialize;
-use std::collections::HashMap;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
 use std::path::Path;
 use std::sync::OnceLock;
 
@@ -18,6 +18,78 @@ pub struct ClassificationResult {
     pub model_hash: &'static str,
 }
 
+/// Dimensions whose confidence is stored, in `{dimension}_confidence`.
+pub const CONFIDENCE_DIMENSIONS: [&str; 4] = ["phase", "scope", "locus", "novelty"];
+
+/// Confidence under which a label counts as marginal.
+pub const LOW_CONFIDENCE: f64 = 0.6;
+
+/// Low-confidence observations listed by a dry-run backfill.
+#[cfg(feature = "cli")]
+const LOW_CONFIDENCE_LISTED: i64 = 20;
+
+/// How sure the classifiers were on one dimension, for `nmem stats --confidence`.
+#[derive(Debug, Serialize, PartialEq)]
+pub struct DimensionConfidence {
+    pub dimension: &'static str,
+    pub labeled: i64,
+    /// Labeled rows with a stored confidence; older rows have none.
+    pub scored: i64,
+    pub mean_confidence: Option<f64>,
+    /// Scored rows below the threshold.
+    pub low_confidence: i64,
+    pub labels: BTreeMap<String, i64>,
+    /// Label counts weighted by confidence; unscored rows weigh 1.
+    pub weighted: BTreeMap<String, f64>,
+}
+
+/// Per-dimension confidence summary; `threshold` marks a label as marginal.
+pub fn confidence_report(
+    conn: &rusqlite::Connection,
+    threshold: f64,
+) -> Result<Vec<DimensionConfidence>, crate::NmemError> {
+    let mut report = Vec::new();
+    for dimension in CONFIDENCE_DIMENSIONS {
+        let mut stmt = conn.prepare(&format!(
+            "SELECT {dimension}, COUNT(*), COUNT({dimension}_confidence), SUM({dimension}_confidence),
+                    COALESCE(SUM({dimension}_confidence < ?1), 0), SUM(COALESCE({dimension}_confidence, 1.0))
+             FROM observations WHERE {dimension} IS NOT NULL GROUP BY {dimension}"
+        ))?;
+        let mut d = DimensionConfidence {
+            dimension,
+            labeled: 0,
+            scored: 0,
+            mean_confidence: None,
+            low_confidence: 0,
+            labels: BTreeMap::new(),
+            weighted: BTreeMap::new(),
+        };
+        let mut sum = 0.0;
+        let rows = stmt.query_map([threshold], |r| {
+            Ok((
+                r.get::<_, String>(0)?,
+                r.get::<_, i64>(1)?,
+                r.get::<_, i64>(2)?,
+                r.get::<_, Option<f64>>(3)?,
+                r.get::<_, i64>(4)?,
+                r.get::<_, f64>(5)?,
+            ))
+        })?;
+        for row in rows {
+            let (label, n, scored, confidence_sum, low, weighted) = row?;
+            d.labeled += n;
+            d.scored += scored;
+            d.low_confidence += low;
+            sum += confidence_sum.unwrap_or(0.0);
+            d.labels.insert(label.clone(), n);
+            d.weighted.insert(label, (weighted * 10.0).round() / 10.0);
+        }
+        d.mean_confidence = (d.scored > 0).then(|| (sum / d.scored as f64 * 1000.0).round() / 1000.0);
+        report.push(d);
+    }
+    Ok(report)
+}
+
 /// Exported TF-IDF vectorizer weights for one feature set (word or char).
 #[derive(Deserialize)]
 pub(crate) struct VectorizerWeights {
@@ -310,7 +382,12 @@ pub fn ensure_classifier_run(
     Ok(conn.last_insert_rowid())
 }
 
-/// Generic backfill for any binary classifier dimension.
+/// Generic backfill for any binary classifier dimension. Labels go to
+/// `column`, confidences to `{column}_confidence`. With
+/// `--low-confidence-only`, labeled rows whose confidence is below
+/// `--below` (or was never stored) are re-classified instead of unlabeled
+/// ones; with `--dry-run` they are listed.
+#[cfg(feature = "cli")]
 pub fn generic_backfill(
     db_path: &std::path::Path,
     args: &crate::cli::BackfillArgs,
@@ -322,22 +399,52 @@ pub fn generic_backfill(
 ) -> Result<(), crate::NmemError> {
     use rusqlite::params;
 
+    if !(0.0..=1.0).contains(&args.below) {
+        return Err(crate::NmemError::Config(format!("--below must be within 0..=1, got {}", args.below)));
+    }
     let conn = crate::db::open_db(db_path)?;
+    let confidence_column = format!("{column}_confidence");
+    let (filter, what) = if args.low_confidence_only {
+        (
+            format!("{column} IS NOT NULL AND ({confidence_column} IS NULL OR {confidence_column} < {})", args.below),
+            format!("{column} confidence below {}", args.below),
+        )
+    } else {
+        (format!("{column} IS NULL"), format!("NULL {column}"))
+    };
 
-    let null_count: i64 = conn.query_row(
-        &format!("SELECT COUNT(*) FROM observations WHERE {column} IS NULL"),
+    let pending: i64 = conn.query_row(
+        &format!("SELECT COUNT(*) FROM observations WHERE {filter}"),
         [],
         |r| r.get(0),
     )?;
 
-    if null_count == 0 {
-        log::info!("no observations with NULL {column} — nothing to backfill");
+    if pending == 0 {
+        log::info!("no observations with {what} — nothing to backfill");
         return Ok(());
     }
 
-    log::info!("found {null_count} observations with NULL {column}");
+    log::info!("found {pending} observations with {what}");
 
     if args.dry_run {
+        if args.low_confidence_only {
+            let mut stmt = conn.prepare(&format!(
+                "SELECT id, {column}, {confidence_column}, content FROM observations WHERE {filter}
+                 ORDER BY {confidence_column} IS NOT NULL, {confidence_column}, id LIMIT {LOW_CONFIDENCE_LISTED}"
+            ))?;
+            let rows = stmt.query_map([], |r| {
+                Ok((r.get::<_, i64>(0)?, r.get::<_, String>(1)?, r.get::<_, Option<f64>>(2)?, r.get::<_, String>(3)?))
+            })?;
+            for row in rows {
+                let (id, label, confidence, content) = row?;
+                let confidence = confidence.map_or("?".into(), |c| format!("{c:.2}"));
+                let preview: String = content.split_whitespace().collect::<Vec<_>>().join(" ").chars().take(80).collect();
+                log::info!("  #{id} {label} ({confidence}) {preview}");
+            }
+            if pending > LOW_CONFIDENCE_LISTED {
+                log::info!("  ...and {} more", pending - LOW_CONFIDENCE_LISTED);
+            }
+        }
         log::info!("dry run — no changes made");
         return Ok(());
     }
@@ -363,7 +470,7 @@ pub fn generic_backfill(
     };
 
     let mut stmt = conn.prepare(
-        &format!("SELECT id, content FROM observations WHERE {column} IS NULL"),
+        &format!("SELECT id, content FROM observations WHERE {filter}"),
     )?;
     let rows: Vec<(i64, String)> = stmt
         .query_map([], |r| Ok((r.get(0)?, r.get(1)?)))?
@@ -375,7 +482,7 @@ pub fn generic_backfill(
     let mut skipped = 0i64;
 
     let update_sql = format!(
-        "UPDATE observations SET {column} = ?1, {run_id_column} = ?2 WHERE id = ?3"
+        "UPDATE observations SET {column} = ?1, {run_id_column} = ?2, {confidence_column} = ?3 WHERE id = ?4"
     );
 
     for chunk in rows.chunks(args.batch_size) {
@@ -384,7 +491,7 @@ pub fn generic_backfill(
 
         for (id, content) in chunk {
             if let Some(result) = classify_fn(content) {
-                update.execute(params![result.label, run_id, id])?;
+                update.execute(params![result.label, run_id, result.confidence, id])?;
                 classified += 1;
                 *counts.entry(result.label.to_string()).or_insert(0) += 1;
             } else {
@@ -395,7 +502,7 @@ pub fn generic_backfill(
         tx.commit()?;
 
         if classified % 500 == 0 && classified > 0 {
-            log::info!("  ...{classified}/{null_count}");
+            log::info!("  ...{classified}/{pending}");
         }
     }
 
@@ -532,4 +639,29 @@ mod tests {
         assert_eq!(nmem_dir(), Some(crate::install_dir()));
     }
 
+    #[test]
+    fn confidence_report_weights_labels() {
+        let mut conn = rusqlite::Connection::open_in_memory().unwrap();
+        crate::schema::MIGRATIONS.to_latest(&mut conn).unwrap();
+        conn.execute_batch(
+            "INSERT INTO sessions (id, project, started_at) VALUES ('s', 'p', 1);
+             INSERT INTO observations (session_id, timestamp, obs_type, source_event, content, phase, phase_confidence) VALUES
+               ('s', 1, 'command', 'PostToolUse', 'a', 'act', 0.9),
+               ('s', 2, 'command', 'PostToolUse', 'b', 'act', 0.55),
+               ('s', 3, 'file_read', 'PostToolUse', 'c', 'think', NULL),
+               ('s', 4, 'file_read', 'PostToolUse', 'd', NULL, NULL);",
+        )
+        .unwrap();
+
+        let report = confidence_report(&conn, LOW_CONFIDENCE).unwrap();
+        assert_eq!(report.len(), CONFIDENCE_DIMENSIONS.len());
+        let phase = &report[0];
+        assert_eq!((phase.labeled, phase.scored, phase.low_confidence), (3, 2, 1));
+        assert_eq!(phase.mean_confidence, Some(0.725));
+        assert_eq!(phase.labels.get("act"), Some(&2));
+        assert_eq!(phase.weighted.get("act"), Some(&1.5));
+        assert_eq!(phase.weighted.get("think"), Some(&1.0), "unscored rows weigh 1");
+        assert_eq!(report[1].labeled, 0);
+        assert_eq!(report[1].mean_confidence, None);
+    }
 }
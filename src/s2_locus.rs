@@ -30,6 +30,7 @@ pub fn current_model_hash() -> Option<&'static str> {
 }
 
 /// Backfill locus labels for all observations with NULL locus.
+#[cfg(feature = "cli")]
 pub fn handle_backfill_locus(
     db_path: &std::path::Path,
     args: &crate::cli::BackfillArgs,
@@ -30,6 +30,7 @@ pub fn current_model_hash() -> Option<&'static str> {
 }
 
 /// Backfill novelty labels for all observations with NULL novelty.
+#[cfg(feature = "cli")]
 pub fn handle_backfill_novelty(
     db_path: &std::path::Path,
     args: &crate::cli::BackfillArgs,
@@ -35,6 +35,7 @@ pub fn current_scope_model_hash() -> Option<&'static str> {
 }
 
 /// Backfill scope labels for all observations with NULL scope.
+#[cfg(feature = "cli")]
 pub fn handle_backfill_scope(
     db_path: &std::path::Path,
     args: &crate::cli::BackfillArgs,
@@ -0,0 +1,289 @@
+//! S2 Coordination — failing tests remembered by name.
+//!
+//! `error_class = 'test_failure'` says a command's tests failed, not which.
+//! When a command fails, its output is scanned for the failing test names
+//! of the common runners — `cargo test` (`test x ... FAILED`), pytest
+//! (`FAILED path::test`), jest (`● Suite › test`), `go test`
+//! (`--- FAIL: TestX`) — and each lands in `test_failures`, one row per
+//! project and name with first/last seen and how many sessions it failed
+//! in; `test_failure_runs` links each failure to its observation.
+//!
+//! `flaky_tests` ranks tests that keep failing across sessions (and how
+//! often a failing run was later relabelled `flaky`); `test_history` lists
+//! one test's failures. Context calls out tests that failed in at least
+//! `[learn] recurring_test_sessions` sessions.
+
+use crate::NmemError;
+use regex::Regex;
+use rusqlite::{Connection, OptionalExtension, params};
+use serde::Serialize;
+use std::sync::LazyLock;
+
+/// Test names kept from one command's output.
+const MAX_PER_RUN: usize = 50;
+/// Longest test name stored.
+pub const MAX_NAME_CHARS: usize = 300;
+
+static CARGO: LazyLock<Regex> = LazyLock::new(|| Regex::new(r"^test (\S+) \.\.\. FAILED\s*$").unwrap());
+static PYTEST: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"^(?:FAILED (\S+::\S+)(?: - .*)?|(\S+::\S+) FAILED\b.*)$").unwrap());
+static JEST: LazyLock<Regex> = LazyLock::new(|| Regex::new(r"^\s*● (.+ › .+?)\s*$").unwrap());
+static GO: LazyLock<Regex> = LazyLock::new(|| Regex::new(r"^\s*--- FAIL: (\S+)").unwrap());
+
+/// Failing test names and their runner (`cargo`, `pytest`, `jest`, `go`)
+/// found in a failed command's output, in order, once each.
+pub fn parse_failing_tests(output: &str) -> Vec<(String, &'static str)> {
+    let mut found: Vec<(String, &'static str)> = Vec::new();
+    for line in output.lines() {
+        let hit = if let Some(c) = CARGO.captures(line) {
+            Some((c[1].to_string(), "cargo"))
+        } else if let Some(c) = PYTEST.captures(line) {
+            c.get(1).or(c.get(2)).map(|m| (m.as_str().to_string(), "pytest"))
+        } else if let Some(c) = JEST.captures(line) {
+            Some((c[1].to_string(), "jest"))
+        } else {
+            GO.captures(line).map(|c| (c[1].to_string(), "go"))
+        };
+        if let Some((name, framework)) = hit
+            && name.chars().count() <= MAX_NAME_CHARS
+            && !found.iter().any(|(n, _)| *n == name)
+        {
+            found.push((name, framework));
+            if found.len() == MAX_PER_RUN {
+                break;
+            }
+        }
+    }
+    found
+}
+
+/// Record the tests that failed in observation `obs_id`. Returns how many.
+pub fn record(
+    conn: &Connection,
+    project: &str,
+    session_id: &str,
+    obs_id: i64,
+    ts: i64,
+    failing: &[(String, &str)],
+) -> Result<usize, NmemError> {
+    for (name, framework) in failing {
+        let seen_in_session: bool = conn.query_row(
+            "SELECT EXISTS (SELECT 1 FROM test_failure_runs r JOIN test_failures t ON t.id = r.test_id
+                            WHERE t.project = ?1 AND t.name = ?2 AND r.session_id = ?3)",
+            params![project, name, session_id],
+            |r| r.get(0),
+        )?;
+        conn.execute(
+            "INSERT INTO test_failures (project, name, framework, first_seen, last_seen, failures, sessions)
+             VALUES (?1, ?2, ?3, ?4, ?4, 1, 1)
+             ON CONFLICT (project, name) DO UPDATE SET
+                 last_seen = MAX(last_seen, excluded.last_seen),
+                 failures = failures + 1,
+                 sessions = sessions + ?5",
+            params![project, name, framework, ts, !seen_in_session as i64],
+        )?;
+        let test_id: i64 = conn.query_row(
+            "SELECT id FROM test_failures WHERE project = ?1 AND name = ?2",
+            params![project, name],
+            |r| r.get(0),
+        )?;
+        conn.execute(
+            "INSERT INTO test_failure_runs (test_id, obs_id, session_id, timestamp) VALUES (?1, ?2, ?3, ?4)",
+            params![test_id, obs_id, session_id, ts],
+        )?;
+    }
+    Ok(failing.len())
+}
+
+#[derive(Debug, Serialize)]
+pub struct TestSummary {
+    pub project: String,
+    pub name: String,
+    pub framework: String,
+    pub first_seen: i64,
+    pub last_seen: i64,
+    pub failures: i64,
+    pub sessions: i64,
+    /// Failing runs whose command later passed unchanged (`error_class = 'flaky'`).
+    pub flaky_runs: i64,
+}
+
+const SUMMARY_SQL: &str = "
+    SELECT t.project, t.name, t.framework, t.first_seen, t.last_seen, t.failures, t.sessions,
+           (SELECT COUNT(*) FROM test_failure_runs r JOIN observations o ON o.id = r.obs_id
+            WHERE r.test_id = t.id AND o.error_class = 'flaky') AS flaky_runs
+    FROM test_failures t";
+
+fn summary_from_row(r: &rusqlite::Row) -> rusqlite::Result<TestSummary> {
+    Ok(TestSummary {
+        project: r.get(0)?,
+        name: r.get(1)?,
+        framework: r.get(2)?,
+        first_seen: r.get(3)?,
+        last_seen: r.get(4)?,
+        failures: r.get(5)?,
+        sessions: r.get(6)?,
+        flaky_runs: r.get(7)?,
+    })
+}
+
+/// Tests that failed in at least `min_sessions` sessions, or had a run
+/// relabelled flaky; flakiest, then most widespread, then latest first.
+pub fn flaky(
+    conn: &Connection,
+    project: Option<&str>,
+    min_sessions: i64,
+    limit: i64,
+) -> Result<Vec<TestSummary>, NmemError> {
+    let sql = format!(
+        "SELECT * FROM ({SUMMARY_SQL} WHERE ?1 IS NULL OR t.project = ?1)
+         WHERE sessions >= ?2 OR flaky_runs > 0
+         ORDER BY flaky_runs DESC, sessions DESC, last_seen DESC
+         LIMIT ?3"
+    );
+    let mut stmt = conn.prepare(&sql)?;
+    let rows = stmt
+        .query_map(params![project, min_sessions, limit], summary_from_row)?
+        .collect::<Result<_, _>>()?;
+    Ok(rows)
+}
+
+/// Tests of `project` failing in at least `min_sessions` sessions, latest
+/// first — the ones context calls out.
+pub fn recurring(conn: &Connection, project: &str, min_sessions: i64, limit: i64) -> Result<Vec<TestSummary>, NmemError> {
+    let sql = format!("{SUMMARY_SQL} WHERE t.project = ?1 AND t.sessions >= ?2 ORDER BY t.last_seen DESC LIMIT ?3");
+    let mut stmt = conn.prepare(&sql)?;
+    let rows = stmt
+        .query_map(params![project, min_sessions, limit], summary_from_row)?
+        .collect::<Result<_, _>>()?;
+    Ok(rows)
+}
+
+#[derive(Debug, Serialize)]
+pub struct TestRun {
+    pub obs_id: i64,
+    pub session_id: String,
+    pub timestamp: i64,
+    pub command: String,
+    pub flaky: bool,
+}
+
+#[derive(Debug, Serialize)]
+pub struct TestHistory {
+    #[serde(flatten)]
+    pub test: TestSummary,
+    /// Failing runs, newest first.
+    pub runs: Vec<TestRun>,
+}
+
+/// Tests named `name` exactly or ending in it (`refresh` finds
+/// `auth::tests::refresh`), most recently failed first.
+pub fn lookup(conn: &Connection, name: &str, project: Option<&str>) -> Result<Vec<TestSummary>, NmemError> {
+    let query = |condition: &str| -> Result<Vec<TestSummary>, NmemError> {
+        let sql = format!(
+            "{SUMMARY_SQL} WHERE (?2 IS NULL OR t.project = ?2) AND {condition} ORDER BY t.last_seen DESC LIMIT 20"
+        );
+        let mut stmt = conn.prepare(&sql)?;
+        let rows = stmt.query_map(params![name, project], summary_from_row)?.collect::<Result<_, _>>()?;
+        Ok(rows)
+    };
+    let exact = query("t.name = ?1")?;
+    if !exact.is_empty() {
+        return Ok(exact);
+    }
+    // A suffix starting at a path or module separator
+    query(
+        "length(t.name) > length(?1) AND substr(t.name, -length(?1)) = ?1
+         AND substr(t.name, -length(?1) - 1, 1) IN (':', '.', '/', ' ')",
+    )
+}
+
+/// One test's failing runs, newest first, at most `limit`.
+pub fn history(conn: &Connection, test: TestSummary, limit: i64) -> Result<TestHistory, NmemError> {
+    let test_id: Option<i64> = conn
+        .query_row(
+            "SELECT id FROM test_failures WHERE project = ?1 AND name = ?2",
+            params![test.project, test.name],
+            |r| r.get(0),
+        )
+        .optional()?;
+    let mut stmt = conn.prepare(
+        "SELECT r.obs_id, r.session_id, r.timestamp, SUBSTR(o.content, 1, 200), COALESCE(o.error_class = 'flaky', 0)
+         FROM test_failure_runs r JOIN observations o ON o.id = r.obs_id
+         WHERE r.test_id = ?1
+         ORDER BY r.timestamp DESC, r.rowid DESC
+         LIMIT ?2",
+    )?;
+    let runs = stmt
+        .query_map(params![test_id, limit], |r| {
+            Ok(TestRun {
+                obs_id: r.get(0)?,
+                session_id: r.get(1)?,
+                timestamp: r.get(2)?,
+                command: r.get(3)?,
+                flaky: r.get(4)?,
+            })
+        })?
+        .collect::<Result<_, _>>()?;
+    Ok(TestHistory { test, runs })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_each_runner_once_per_test() {
+        let cargo = "running 3 tests\ntest auth::tests::refresh ... FAILED\ntest auth::tests::login ... ok\n\nfailures:\n\n---- auth::tests::refresh stdout ----\ntest auth::tests::refresh ... FAILED\n";
+        assert_eq!(parse_failing_tests(cargo), vec![("auth::tests::refresh".to_string(), "cargo")]);
+
+        let pytest = "tests/test_api.py::test_create FAILED [ 50%]\n=== short test summary info ===\nFAILED tests/test_api.py::test_create - AssertionError: 1 != 2\nFAILED tests/test_db.py::TestPool::test_reuse\n";
+        assert_eq!(
+            parse_failing_tests(pytest),
+            vec![
+                ("tests/test_api.py::test_create".to_string(), "pytest"),
+                ("tests/test_db.py::TestPool::test_reuse".to_string(), "pytest"),
+            ]
+        );
+
+        let jest = "FAIL src/cart.test.ts\n  ● Cart › adds an item\n\n    expect(received).toBe(expected)\n  ● Test suite failed to run\n";
+        assert_eq!(parse_failing_tests(jest), vec![("Cart › adds an item".to_string(), "jest")]);
+
+        let go = "=== RUN   TestParse\n--- FAIL: TestParse (0.00s)\nFAIL\n";
+        assert_eq!(parse_failing_tests(go), vec![("TestParse".to_string(), "go")]);
+        assert!(parse_failing_tests("error[E0425]: cannot find value `x`").is_empty());
+    }
+
+    #[test]
+    fn counts_sessions_and_ranks_flaky_tests() {
+        let mut conn = Connection::open_in_memory().unwrap();
+        crate::schema::MIGRATIONS.to_latest(&mut conn).unwrap();
+        conn.execute_batch(
+            "INSERT INTO sessions (id, project, started_at) VALUES ('s1', 'p', 0), ('s2', 'p', 100);
+             INSERT INTO observations (id, session_id, timestamp, obs_type, source_event, content, error_class) VALUES
+                 (1, 's1', 10, 'command', 'PostToolUse', 'cargo test', 'test_failure'),
+                 (2, 's1', 20, 'command', 'PostToolUse', 'cargo test', 'test_failure'),
+                 (3, 's2', 110, 'command', 'PostToolUse', 'cargo test', 'flaky');",
+        )
+        .unwrap();
+        let refresh = [("auth::tests::refresh".to_string(), "cargo")];
+        let login = [("auth::tests::login".to_string(), "cargo")];
+        record(&conn, "p", "s1", 1, 10, &refresh).unwrap();
+        record(&conn, "p", "s1", 2, 20, &[refresh[0].clone(), login[0].clone()]).unwrap();
+        record(&conn, "p", "s2", 3, 110, &refresh).unwrap();
+
+        let ranked = flaky(&conn, Some("p"), 2, 10).unwrap();
+        assert_eq!(ranked.len(), 1, "login failed in one session, never flaky");
+        let t = &ranked[0];
+        assert_eq!((t.name.as_str(), t.failures, t.sessions, t.flaky_runs), ("auth::tests::refresh", 3, 2, 1));
+        assert_eq!((t.first_seen, t.last_seen), (10, 110));
+        assert_eq!(recurring(&conn, "p", 2, 5).unwrap().len(), 1);
+
+        let found = lookup(&conn, "refresh", None).unwrap();
+        assert_eq!(found.len(), 1);
+        assert!(lookup(&conn, "fresh", None).unwrap().is_empty(), "suffix on a name boundary only");
+        let h = history(&conn, found.into_iter().next().unwrap(), 10).unwrap();
+        let runs: Vec<(i64, bool)> = h.runs.iter().map(|r| (r.obs_id, r.flaky)).collect();
+        assert_eq!(runs, vec![(3, true), (2, false), (1, false)]);
+    }
+}
@@ -0,0 +1,136 @@
+//! S3 Control — project archival.
+//!
+//! One-off projects otherwise keep surfacing in cross-project context and
+//! learn reports long after they stop mattering. Archiving keeps the data but
+//! takes the project out of those, out of default search (`include_archived`
+//! or an explicit project filter brings it back), and shortens its retention
+//! to `retention.archived_retention_pct` of the usual days.
+
+#[cfg(feature = "cli")]
+use crate::cli::{ProjectArgs, ProjectCommand};
+#[cfg(feature = "cli")]
+use crate::db::open_db;
+use crate::NmemError;
+use rusqlite::{Connection, OptionalExtension, params};
+use serde::Serialize;
+#[cfg(feature = "cli")]
+use std::path::Path;
+
+#[derive(Debug, Serialize)]
+pub struct ProjectRow {
+    pub project: String,
+    pub sessions: i64,
+    pub last_active: i64,
+    pub archived_at: Option<i64>,
+}
+
+/// Archive `project`. Returns false when it already was; errors when no
+/// session belongs to it.
+pub fn archive(conn: &Connection, project: &str, now: i64) -> Result<bool, NmemError> {
+    let known: bool = conn.query_row(
+        "SELECT EXISTS (SELECT 1 FROM sessions WHERE project = ?1)",
+        params![project],
+        |r| r.get(0),
+    )?;
+    if !known {
+        return Err(NmemError::Config(format!("no sessions for project {project:?}")));
+    }
+    let inserted = conn.execute(
+        "INSERT OR IGNORE INTO archived_projects (project, archived_at) VALUES (?1, ?2)",
+        params![project, now],
+    )?;
+    Ok(inserted > 0)
+}
+
+/// Restore `project`. Returns false when it was not archived.
+pub fn unarchive(conn: &Connection, project: &str) -> Result<bool, NmemError> {
+    Ok(conn.execute("DELETE FROM archived_projects WHERE project = ?1", params![project])? > 0)
+}
+
+pub fn is_archived(conn: &Connection, project: &str) -> Result<bool, NmemError> {
+    Ok(conn
+        .query_row("SELECT 1 FROM archived_projects WHERE project = ?1", params![project], |_| Ok(()))
+        .optional()?
+        .is_some())
+}
+
+/// Every project with sessions, most recently active first.
+pub fn list_projects(conn: &Connection) -> Result<Vec<ProjectRow>, NmemError> {
+    let mut stmt = conn.prepare(
+        "SELECT s.project, COUNT(*), MAX(COALESCE(s.ended_at, s.started_at)), a.archived_at
+         FROM sessions s
+         LEFT JOIN archived_projects a ON a.project = s.project
+         WHERE s.project IS NOT NULL
+         GROUP BY s.project
+         ORDER BY 3 DESC",
+    )?;
+    let rows = stmt
+        .query_map([], |r| {
+            Ok(ProjectRow {
+                project: r.get(0)?,
+                sessions: r.get(1)?,
+                last_active: r.get(2)?,
+                archived_at: r.get(3)?,
+            })
+        })?
+        .collect::<Result<_, _>>()?;
+    Ok(rows)
+}
+
+#[cfg(feature = "cli")]
+pub fn handle_project(db_path: &Path, args: &ProjectArgs) -> Result<(), NmemError> {
+    let conn = open_db(db_path)?;
+    match &args.command {
+        ProjectCommand::List => {
+            println!("{}", serde_json::to_string_pretty(&list_projects(&conn)?)?);
+        }
+        ProjectCommand::Archive(p) => {
+            let now = std::time::SystemTime::now()
+                .duration_since(std::time::UNIX_EPOCH)
+                .unwrap_or_default()
+                .as_secs() as i64;
+            if archive(&conn, &p.name, now)? {
+                log::info!("archived {}", p.name);
+            } else {
+                log::info!("{} is already archived", p.name);
+            }
+        }
+        ProjectCommand::Unarchive(p) => {
+            if unarchive(&conn, &p.name)? {
+                log::info!("unarchived {}", p.name);
+            } else {
+                log::info!("{} is not archived", p.name);
+            }
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn archive_round_trip() {
+        let mut conn = Connection::open_in_memory().unwrap();
+        crate::schema::MIGRATIONS.to_latest(&mut conn).unwrap();
+        conn.execute_batch(
+            "INSERT INTO sessions (id, project, started_at, ended_at) VALUES ('a', 'keep', 100, 200), ('b', 'old', 50, 60);",
+        )
+        .unwrap();
+
+        assert!(archive(&conn, "old", 1000).unwrap());
+        assert!(!archive(&conn, "old", 2000).unwrap());
+        assert!(archive(&conn, "missing", 1000).is_err());
+        assert!(is_archived(&conn, "old").unwrap());
+
+        let rows = list_projects(&conn).unwrap();
+        assert_eq!(rows[0].project, "keep");
+        assert_eq!(rows[0].archived_at, None);
+        assert_eq!(rows[1].archived_at, Some(1000));
+
+        assert!(unarchive(&conn, "old").unwrap());
+        assert!(!unarchive(&conn, "old").unwrap());
+        assert!(!is_archived(&conn, "old").unwrap());
+    }
+}
@@ -0,0 +1,388 @@
+//! S3 Control — audit log of MCP reads.
+//!
+//! Every MCP tool call is recorded in `query_log` (tool, params hash, result
+//! count, duration, session when derivable). Which memories actually get
+//! retrieved is the feedback signal retention and ranking decisions lack:
+//! an obs_type that is never read back is a candidate for shorter retention.
+//!
+//! The MCP server stays read-only for memory data; it logs through a separate
+//! writable connection that only ever touches `query_log`.
+
+#[cfg(feature = "cli")]
+use crate::cli::{AuditArgs, AuditCommand};
+use crate::db::open_db;
+use crate::NmemError;
+use rusqlite::{Connection, params};
+use serde::Serialize;
+use std::path::Path;
+
+/// Max observation ids recorded per call — enough for any tool's page size.
+const MAX_LOGGED_IDS: usize = 100;
+
+pub struct QueryLogEntry<'a> {
+    pub tool: &'a str,
+    pub params_hash: &'a str,
+    pub result_count: Option<i64>,
+    pub duration_ms: i64,
+    pub is_error: bool,
+    pub obs_ids: Vec<i64>,
+}
+
+/// Stable FNV-1a hash of a tool's serialized params. Identical calls hash
+/// identically across processes and releases, so repeats can be grouped.
+pub fn params_hash(params: &impl Serialize) -> String {
+    let bytes = serde_json::to_vec(params).unwrap_or_default();
+    let mut hash: u64 = 0xcbf2_9ce4_8422_2325;
+    for b in bytes {
+        hash ^= u64::from(b);
+        hash = hash.wrapping_mul(0x0100_0000_01b3);
+    }
+    format!("{hash:016x}")
+}
+
+/// Result count and retrieved observation ids from a tool's JSON output.
+/// Lists count their items (bare or under `results`); objects with a
+/// `sessions` list count sessions. Other shapes have no count. Items from
+/// attached databases (`origin` other than local) don't contribute ids.
+pub fn summarize_result(text: &str) -> (Option<i64>, Vec<i64>) {
+    let Ok(value) = serde_json::from_str::<serde_json::Value>(text) else {
+        return (None, Vec::new());
+    };
+    let items = match &value {
+        serde_json::Value::Array(a) => Some(a),
+        serde_json::Value::Object(o) => o
+            .get("results")
+            .or_else(|| o.get("sessions"))
+            .and_then(|v| v.as_array()),
+        _ => None,
+    };
+    let Some(items) = items else {
+        return (None, Vec::new());
+    };
+    let ids = items
+        .iter()
+        .filter(|item| {
+            item.get("origin")
+                .and_then(|o| o.as_str())
+                .is_none_or(|o| o == crate::s5_config::LOCAL_ORIGIN)
+        })
+        .filter_map(|item| item.get("id").or_else(|| item.get("obs_id")))
+        .filter_map(|id| id.as_i64())
+        .take(MAX_LOGGED_IDS)
+        .collect();
+    (Some(items.len() as i64), ids)
+}
+
+/// Append one call to `query_log`. The session is the newest open agent
+/// session — the MCP server isn't told which session spawned it.
+pub fn log_query(conn: &Connection, entry: &QueryLogEntry, now: i64) -> Result<(), NmemError> {
+    let obs_ids = if entry.obs_ids.is_empty() {
+        None
+    } else {
+        Some(serde_json::to_string(&entry.obs_ids)?)
+    };
+    conn.execute(
+        "INSERT INTO query_log (timestamp, tool, params_hash, session_id, result_count, duration_ms, is_error, obs_ids)
+         VALUES (?1, ?2, ?3,
+                 (SELECT id FROM sessions WHERE kind = 'agent' AND ended_at IS NULL
+                  ORDER BY started_at DESC LIMIT 1),
+                 ?4, ?5, ?6, ?7)",
+        params![
+            now,
+            entry.tool,
+            entry.params_hash,
+            entry.result_count,
+            entry.duration_ms,
+            entry.is_error,
+            obs_ids
+        ],
+    )?;
+    Ok(())
+}
+
+/// Delete log rows older than `retention_days`.
+pub fn prune(conn: &Connection, retention_days: u32, now: i64) -> Result<usize, NmemError> {
+    let cutoff = now - i64::from(retention_days) * 86400;
+    Ok(conn.execute("DELETE FROM query_log WHERE timestamp < ?1", params![cutoff])?)
+}
+
+#[derive(Serialize)]
+pub struct QueryLogRow {
+    pub id: i64,
+    pub timestamp: i64,
+    pub tool: String,
+    pub params_hash: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub session_id: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub result_count: Option<i64>,
+    pub duration_ms: i64,
+    pub is_error: bool,
+}
+
+/// Most recent calls first, optionally for one tool.
+pub fn recent_queries(
+    conn: &Connection,
+    tool: Option<&str>,
+    since: i64,
+    limit: i64,
+) -> Result<Vec<QueryLogRow>, NmemError> {
+    let mut stmt = conn.prepare(
+        "SELECT id, timestamp, tool, params_hash, session_id, result_count, duration_ms, is_error
+         FROM query_log
+         WHERE timestamp >= ?1 AND (?2 IS NULL OR tool = ?2)
+         ORDER BY timestamp DESC, id DESC
+         LIMIT ?3",
+    )?;
+    let rows = stmt
+        .query_map(params![since, tool, limit], |r| {
+            Ok(QueryLogRow {
+                id: r.get(0)?,
+                timestamp: r.get(1)?,
+                tool: r.get(2)?,
+                params_hash: r.get(3)?,
+                session_id: r.get(4)?,
+                result_count: r.get(5)?,
+                duration_ms: r.get(6)?,
+                is_error: r.get(7)?,
+            })
+        })?
+        .collect::<Result<_, _>>()?;
+    Ok(rows)
+}
+
+#[derive(Serialize)]
+pub struct UsageReport {
+    pub since: i64,
+    pub total_calls: i64,
+    pub tools: Vec<ToolUsage>,
+    /// Observations returned most often.
+    pub top_observations: Vec<RetrievedObservation>,
+    /// Per obs_type: how many stored observations were retrieved at least once.
+    pub obs_types: Vec<ObsTypeUsage>,
+}
+
+#[derive(Serialize)]
+pub struct ToolUsage {
+    pub tool: String,
+    pub calls: i64,
+    pub errors: i64,
+    /// Calls that returned an empty list.
+    pub empty: i64,
+    pub avg_ms: f64,
+    /// Distinct params hashes — low relative to `calls` means repeated lookups.
+    pub distinct_params: i64,
+}
+
+#[derive(Serialize)]
+pub struct RetrievedObservation {
+    pub id: i64,
+    pub obs_type: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub file_path: Option<String>,
+    pub content_preview: String,
+    pub hits: i64,
+}
+
+#[derive(Serialize)]
+pub struct ObsTypeUsage {
+    pub obs_type: String,
+    pub stored: i64,
+    pub retrieved: i64,
+    pub retrieved_pct: f64,
+}
+
+/// Aggregate the log since `since` into a per-tool and per-memory report.
+pub fn usage_report(conn: &Connection, since: i64, limit: i64) -> Result<UsageReport, NmemError> {
+    let total_calls: i64 = conn.query_row(
+        "SELECT COUNT(*) FROM query_log WHERE timestamp >= ?1",
+        params![since],
+        |r| r.get(0),
+    )?;
+
+    let mut stmt = conn.prepare(
+        "SELECT tool, COUNT(*), SUM(is_error), SUM(result_count = 0),
+                AVG(duration_ms), COUNT(DISTINCT params_hash)
+         FROM query_log
+         WHERE timestamp >= ?1
+         GROUP BY tool
+         ORDER BY COUNT(*) DESC, tool",
+    )?;
+    let tools = stmt
+        .query_map(params![since], |r| {
+            Ok(ToolUsage {
+                tool: r.get(0)?,
+                calls: r.get(1)?,
+                errors: r.get(2)?,
+                empty: r.get::<_, Option<i64>>(3)?.unwrap_or(0),
+                avg_ms: (r.get::<_, f64>(4)? * 10.0).round() / 10.0,
+                distinct_params: r.get(5)?,
+            })
+        })?
+        .collect::<Result<_, _>>()?;
+
+    let hits_cte = "WITH hits AS (
+                        SELECT CAST(j.value AS INTEGER) AS obs_id, COUNT(*) AS n
+                        FROM query_log q, json_each(q.obs_ids) j
+                        WHERE q.timestamp >= ?1 AND q.obs_ids IS NOT NULL
+                        GROUP BY obs_id
+                    )";
+
+    let mut stmt = conn.prepare(&format!(
+        "{hits_cte}
+         SELECT o.id, o.obs_type, o.file_path, SUBSTR(o.content, 1, 120), h.n
+         FROM hits h JOIN observations o ON o.id = h.obs_id
+         ORDER BY h.n DESC, o.id DESC
+         LIMIT ?2"
+    ))?;
+    let top_observations = stmt
+        .query_map(params![since, limit], |r| {
+            Ok(RetrievedObservation {
+                id: r.get(0)?,
+                obs_type: r.get(1)?,
+                file_path: r.get(2)?,
+                content_preview: r.get(3)?,
+                hits: r.get(4)?,
+            })
+        })?
+        .collect::<Result<_, _>>()?;
+
+    let mut stmt = conn.prepare(&format!(
+        "{hits_cte}
+         SELECT o.obs_type, COUNT(*), COUNT(h.obs_id)
+         FROM observations o LEFT JOIN hits h ON h.obs_id = o.id
+         GROUP BY o.obs_type
+         ORDER BY COUNT(*) DESC, o.obs_type"
+    ))?;
+    let obs_types = stmt
+        .query_map(params![since], |r| {
+            let stored: i64 = r.get(1)?;
+            let retrieved: i64 = r.get(2)?;
+            Ok(ObsTypeUsage {
+                obs_type: r.get(0)?,
+                stored,
+                retrieved,
+                retrieved_pct: if stored > 0 {
+                    (retrieved as f64 * 1000.0 / stored as f64).round() / 10.0
+                } else {
+                    0.0
+                },
+            })
+        })?
+        .collect::<Result<_, _>>()?;
+
+    Ok(UsageReport {
+        since,
+        total_calls,
+        tools,
+        top_observations,
+        obs_types,
+    })
+}
+
+#[cfg(feature = "cli")]
+pub fn handle_audit(db_path: &Path, args: &AuditArgs) -> Result<(), NmemError> {
+    let now = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs() as i64;
+    // Writable open runs migrations, so query_log exists on older databases
+    let conn = open_db(db_path)?;
+
+    let json = match &args.command {
+        AuditCommand::Queries(q) => {
+            let since = now - q.days * 86400;
+            let rows = recent_queries(&conn, q.tool.as_deref(), since, q.limit.clamp(1, 1000))?;
+            serde_json::to_string_pretty(&rows)?
+        }
+        AuditCommand::Usage(u) => {
+            let since = now - u.days * 86400;
+            let report = usage_report(&conn, since, u.limit.clamp(1, 100))?;
+            serde_json::to_string_pretty(&report)?
+        }
+        AuditCommand::Deletions(d) => crate::s3_deletions::handle_deletions(&conn, d, now)?,
+    };
+    println!("{json}");
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn setup() -> Connection {
+        let mut conn = Connection::open_in_memory().unwrap();
+        crate::schema::MIGRATIONS.to_latest(&mut conn).unwrap();
+        conn.execute_batch(
+            "INSERT INTO sessions (id, project, started_at, ended_at) VALUES ('old', 'p', 0, 10);
+             INSERT INTO sessions (id, project, started_at) VALUES ('live', 'p', 100);
+             INSERT INTO observations (id, session_id, timestamp, obs_type, source_event, content)
+                 VALUES (1, 'live', 100, 'file_edit', 'PostToolUse', 'Edit a.rs'),
+                        (2, 'live', 100, 'file_read', 'PostToolUse', 'Read a.rs'),
+                        (3, 'live', 100, 'file_read', 'PostToolUse', 'Read b.rs');",
+        )
+        .unwrap();
+        conn
+    }
+
+    fn entry<'a>(tool: &'a str, text: &str) -> QueryLogEntry<'a> {
+        let (result_count, obs_ids) = summarize_result(text);
+        QueryLogEntry { tool, params_hash: "h", result_count, duration_ms: 4, is_error: false, obs_ids }
+    }
+
+    #[test]
+    fn params_hash_is_stable_and_param_sensitive() {
+        let a = params_hash(&serde_json::json!({"query": "auth"}));
+        assert_eq!(a, params_hash(&serde_json::json!({"query": "auth"})));
+        assert_ne!(a, params_hash(&serde_json::json!({"query": "login"})));
+        assert_eq!(a.len(), 16);
+    }
+
+    #[test]
+    fn summarizes_result_shapes() {
+        assert_eq!(summarize_result(r#"[{"id":1},{"id":2}]"#), (Some(2), vec![1, 2]));
+        assert_eq!(summarize_result(r#"{"results":[{"id":7}],"next_cursor":null}"#), (Some(1), vec![7]));
+        assert_eq!(summarize_result(r#"{"sessions":[{"session_id":"s"}]}"#), (Some(1), vec![]));
+        assert_eq!(summarize_result(r#"{"stance":"think"}"#), (None, vec![]));
+        assert_eq!(summarize_result("not json"), (None, vec![]));
+        assert_eq!(
+            summarize_result(r#"[{"id":1,"origin":"local"},{"id":1,"origin":"team"}]"#),
+            (Some(2), vec![1])
+        );
+    }
+
+    #[test]
+    fn logs_with_open_session_and_prunes() {
+        let conn = setup();
+        log_query(&conn, &entry("search", r#"[{"id":1}]"#), 1000).unwrap();
+        log_query(&conn, &entry("search", "[]"), 90_000).unwrap();
+
+        let rows = recent_queries(&conn, Some("search"), 0, 10).unwrap();
+        assert_eq!(rows.len(), 2);
+        assert_eq!(rows[0].result_count, Some(0));
+        assert_eq!(rows[1].session_id.as_deref(), Some("live"));
+
+        assert_eq!(prune(&conn, 1, 90_000).unwrap(), 1);
+        assert_eq!(recent_queries(&conn, None, 0, 10).unwrap().len(), 1);
+    }
+
+    #[test]
+    fn usage_report_counts_retrievals() {
+        let conn = setup();
+        log_query(&conn, &entry("search", r#"[{"id":1},{"id":2}]"#), 100).unwrap();
+        log_query(&conn, &entry("get_observations", r#"[{"id":1}]"#), 100).unwrap();
+        log_query(&conn, &entry("search", "[]"), 100).unwrap();
+
+        let report = usage_report(&conn, 0, 10).unwrap();
+        assert_eq!(report.total_calls, 3);
+        assert_eq!(report.tools[0].tool, "search");
+        assert_eq!(report.tools[0].calls, 2);
+        assert_eq!(report.tools[0].empty, 1);
+        assert_eq!(report.top_observations[0].id, 1);
+        assert_eq!(report.top_observations[0].hits, 2);
+
+        let reads = report.obs_types.iter().find(|t| t.obs_type == "file_read").unwrap();
+        assert_eq!((reads.stored, reads.retrieved), (2, 1));
+        assert_eq!(reads.retrieved_pct, 50.0);
+    }
+}
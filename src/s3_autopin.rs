@@ -0,0 +1,268 @@
+//! S3 Control — auto-pin rules for high-value observations.
+//!
+//! Manual pinning is easy to forget, and unpinned moments are eventually swept.
+//! These rules pin what is almost always worth keeping: pushes, decision
+//! markers, the first success of a command that had been failing, and the edit
+//! that ended a friction episode. Each auto-pin records its rule in
+//! `metadata.auto_pin` so it can be audited (and `nmem unpin`ned).
+//!
+//! Pinning exempts rows from retention, so the rules are opt-in
+//! (`[auto_pin] enabled = true`). Per-observation rules run at record time;
+//! `friction_fix` needs episode boundaries and runs in session maintenance
+//! after the Stop hook.
+
+use crate::s5_config::AutoPinConfig;
+use crate::NmemError;
+use rusqlite::{Connection, OptionalExtension, params};
+
+/// Pin `id` and tag it with the rule that fired. No-op if already pinned.
+fn pin(conn: &Connection, id: i64, rule: &str) -> Result<bool, NmemError> {
+    let n = conn.execute(
+        "UPDATE observations
+         SET is_pinned = 1, metadata = json_set(COALESCE(metadata, '{}'), '$.auto_pin', ?2)
+         WHERE id = ?1 AND is_pinned = 0",
+        params![id, rule],
+    )?;
+    Ok(n > 0)
+}
+
+/// Evaluate record-time rules for a freshly inserted observation.
+/// Returns the rule that pinned it, if any.
+pub fn evaluate_observation(
+    conn: &Connection,
+    config: &AutoPinConfig,
+    id: i64,
+) -> Result<Option<&'static str>, NmemError> {
+    if !config.enabled {
+        return Ok(None);
+    }
+    let row: Option<(String, String, String, bool)> = conn
+        .query_row(
+            "SELECT session_id, obs_type, content,
+                    COALESCE(json_extract(metadata, '$.failed'), 0) = 1 OR error_class IS NOT NULL
+             FROM observations WHERE id = ?1",
+            params![id],
+            |r| Ok((r.get(0)?, r.get(1)?, r.get(2)?, r.get(3)?)),
+        )
+        .optional()?;
+    let Some((session_id, obs_type, content, failed)) = row else {
+        return Ok(None);
+    };
+    if failed {
+        return Ok(None);
+    }
+
+    let rule = if obs_type == "git_push" && config.git_push {
+        Some("git_push")
+    } else if obs_type == "marker" && is_decision_marker(&content, &config.marker_keywords) {
+        Some("decision_marker")
+    } else if config.first_success && is_first_success(conn, &session_id, &content, id)? {
+        Some("first_success")
+    } else {
+        None
+    };
+
+    match rule {
+        Some(r) if pin(conn, id, r)? => Ok(Some(r)),
+        _ => Ok(None),
+    }
+}
+
+/// Whether `content` contains any keyword as a whole word: "decided" matches
+/// "Decided: ..." but not "undecided".
+fn is_decision_marker(content: &str, keywords: &[String]) -> bool {
+    let lower = content.to_lowercase();
+    let is_word = |c: Option<char>| c.is_some_and(char::is_alphanumeric);
+    keywords.iter().filter(|k| !k.is_empty()).any(|k| {
+        let k = k.to_lowercase();
+        lower.match_indices(&k).any(|(at, _)| {
+            !is_word(lower[..at].chars().next_back()) && !is_word(lower[at + k.len()..].chars().next())
+        })
+    })
+}
+
+/// First success of a command whose last run genuinely failed. Flaky failures
+/// (relabelled by S2 when nothing changed in between) don't count.
+fn is_first_success(conn: &Connection, session_id: &str, content: &str, id: i64) -> Result<bool, NmemError> {
+    let last_failure: Option<i64> = conn.query_row(
+        "SELECT MAX(id) FROM observations
+         WHERE session_id = ?1 AND content = ?2 AND id < ?3
+           AND error_class IS NOT NULL AND error_class != 'flaky'",
+        params![session_id, content, id],
+        |r| r.get(0),
+    )?;
+    let Some(failure_id) = last_failure else {
+        return Ok(false);
+    };
+    let earlier_success: bool = conn.query_row(
+        "SELECT EXISTS(
+             SELECT 1 FROM observations
+             WHERE session_id = ?1 AND content = ?2 AND id > ?3 AND id < ?4
+               AND error_class IS NULL
+         )",
+        params![session_id, content, failure_id, id],
+        |r| r.get(0),
+    )?;
+    Ok(!earlier_success)
+}
+
+/// Stop-time rule: in each friction episode that ended in a passing command,
+/// pin the last edit between the final failure and that success — the fix.
+pub fn evaluate_session(conn: &Connection, config: &AutoPinConfig, session_id: &str) -> Result<usize, NmemError> {
+    if !config.enabled || !config.friction_fix {
+        return Ok(0);
+    }
+    let mut stmt = conn.prepare(
+        "SELECT first_prompt_id, last_prompt_id FROM work_units
+         WHERE session_id = ?1
+           AND COALESCE(json_extract(phase_signature, '$.failures'), 0) > 0",
+    )?;
+    let units: Vec<(i64, i64)> = stmt
+        .query_map(params![session_id], |r| Ok((r.get(0)?, r.get(1)?)))?
+        .collect::<Result<_, _>>()?;
+
+    let mut pinned = 0;
+    for (first, last) in units {
+        let fix: Option<i64> = conn
+            .query_row(
+                "WITH ep AS (
+                     SELECT id, obs_type, tool_name,
+                            COALESCE(json_extract(metadata, '$.failed'), 0) = 1 AS failed
+                     FROM observations
+                     WHERE session_id = ?1 AND prompt_id BETWEEN ?2 AND ?3
+                 ),
+                 last_fail AS (SELECT MAX(id) AS id FROM ep WHERE failed),
+                 recovery AS (
+                     SELECT MIN(ep.id) AS id FROM ep, last_fail
+                     WHERE ep.id > last_fail.id AND NOT ep.failed AND ep.tool_name = 'Bash'
+                 )
+                 SELECT MAX(ep.id) FROM ep, last_fail, recovery
+                 WHERE ep.obs_type IN ('file_edit', 'file_write')
+                   AND ep.id > last_fail.id AND ep.id < recovery.id",
+                params![session_id, first, last],
+                |r| r.get(0),
+            )?;
+        if let Some(id) = fix
+            && pin(conn, id, "friction_fix")?
+        {
+            pinned += 1;
+        }
+    }
+    Ok(pinned)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn setup() -> Connection {
+        let mut conn = Connection::open_in_memory().unwrap();
+        crate::schema::MIGRATIONS.to_latest(&mut conn).unwrap();
+        conn.execute_batch(
+            "INSERT INTO sessions (id, project, started_at) VALUES ('s', 'p', 0);
+             INSERT INTO prompts (id, session_id, timestamp, source, content) VALUES (1, 's', 0, 'user', 'go');",
+        )
+        .unwrap();
+        conn
+    }
+
+    fn insert(conn: &Connection, obs_type: &str, content: &str, failed: bool) -> i64 {
+        conn.execute(
+            "INSERT INTO observations (session_id, prompt_id, timestamp, obs_type, source_event, tool_name, content, metadata, error_class)
+             VALUES ('s', 1, 0, ?1, 'PostToolUse', CASE WHEN ?1 = 'file_edit' THEN 'Edit' ELSE 'Bash' END, ?2,
+                     CASE WHEN ?3 THEN '{\"failed\":true}' END, CASE WHEN ?3 THEN 'test_failure' END)",
+            params![obs_type, content, failed],
+        )
+        .unwrap();
+        conn.last_insert_rowid()
+    }
+
+    fn auto_pin(conn: &Connection, id: i64) -> Option<String> {
+        conn.query_row(
+            "SELECT json_extract(metadata, '$.auto_pin') FROM observations WHERE id = ?1 AND is_pinned = 1",
+            params![id],
+            |r| r.get(0),
+        )
+        .optional()
+        .unwrap()
+        .flatten()
+    }
+
+    fn enabled() -> AutoPinConfig {
+        AutoPinConfig { enabled: true, ..Default::default() }
+    }
+
+    #[test]
+    fn pins_pushes_and_decision_markers() {
+        let conn = setup();
+        let config = enabled();
+        let push = insert(&conn, "git_push", "git push origin main", false);
+        let marker = insert(&conn, "marker", "Decided: keep FTS5 over tantivy", false);
+        let note = insert(&conn, "marker", "looked at the parser", false);
+        let undecided = insert(&conn, "marker", "still undecided between the two", false);
+        let conclusions = insert(&conn, "marker", "no conclusions yet", false);
+
+        assert_eq!(evaluate_observation(&conn, &config, push).unwrap(), Some("git_push"));
+        assert_eq!(evaluate_observation(&conn, &config, marker).unwrap(), Some("decision_marker"));
+        assert_eq!(evaluate_observation(&conn, &config, note).unwrap(), None);
+        assert_eq!(evaluate_observation(&conn, &config, undecided).unwrap(), None);
+        assert_eq!(evaluate_observation(&conn, &config, conclusions).unwrap(), None);
+        assert_eq!(auto_pin(&conn, push).as_deref(), Some("git_push"));
+    }
+
+    #[test]
+    fn pins_only_first_success_after_real_failure() {
+        let conn = setup();
+        let config = enabled();
+        insert(&conn, "command", "cargo test", true);
+        insert(&conn, "file_edit", "Edit src/lib.rs", false);
+        let first = insert(&conn, "command", "cargo test", false);
+        let second = insert(&conn, "command", "cargo test", false);
+
+        assert_eq!(evaluate_observation(&conn, &config, first).unwrap(), Some("first_success"));
+        assert_eq!(evaluate_observation(&conn, &config, second).unwrap(), None);
+
+        // A flaky failure is not a real failure
+        conn.execute("UPDATE observations SET error_class = 'flaky' WHERE error_class IS NOT NULL", []).unwrap();
+        conn.execute("UPDATE observations SET is_pinned = 0", []).unwrap();
+        assert_eq!(evaluate_observation(&conn, &config, first).unwrap(), None);
+    }
+
+    #[test]
+    fn disabled_rules_do_not_pin() {
+        let conn = setup();
+        let config = AutoPinConfig { git_push: false, marker_keywords: vec![], ..enabled() };
+        let push = insert(&conn, "git_push", "git push", false);
+        let marker = insert(&conn, "marker", "conclusion: done", false);
+        assert_eq!(evaluate_observation(&conn, &config, push).unwrap(), None);
+        assert_eq!(evaluate_observation(&conn, &config, marker).unwrap(), None);
+
+        let off = AutoPinConfig { enabled: false, ..enabled() };
+        let push = insert(&conn, "git_push", "git push", false);
+        assert_eq!(evaluate_observation(&conn, &off, push).unwrap(), None);
+        // Opt-in: the default pins nothing
+        assert_eq!(evaluate_observation(&conn, &AutoPinConfig::default(), push).unwrap(), None);
+    }
+
+    #[test]
+    fn pins_edit_that_ended_friction_episode() {
+        let conn = setup();
+        conn.execute(
+            "INSERT INTO work_units (session_id, started_at, intent, first_prompt_id, last_prompt_id, phase_signature, obs_count)
+             VALUES ('s', 0, 'fix tests', 1, 1, '{\"failures\":2}', 6)",
+            [],
+        )
+        .unwrap();
+        insert(&conn, "command", "cargo test", true);
+        let early_edit = insert(&conn, "file_edit", "Edit a.rs", false);
+        insert(&conn, "command", "cargo test", true);
+        let fix = insert(&conn, "file_edit", "Edit b.rs", false);
+        insert(&conn, "command", "cargo test", false);
+        let later = insert(&conn, "file_edit", "Edit c.rs", false);
+
+        assert_eq!(evaluate_session(&conn, &enabled(), "s").unwrap(), 1);
+        assert_eq!(auto_pin(&conn, fix).as_deref(), Some("friction_fix"));
+        assert_eq!(auto_pin(&conn, early_edit), None);
+        assert_eq!(auto_pin(&conn, later), None);
+    }
+}
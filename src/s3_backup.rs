@@ -0,0 +1,304 @@
+//! S3 Control — restore drills for backups.
+//!
+//! A backup that has never been restored is only assumed to work. `nmem
+//! backup verify <snapshot>` restores a pre-migration snapshot
+//! (`backups/*.db`, see `s3_migrate`) or a `purge --export-before` JSONL
+//! export into a scratch directory, migrates the copy to this binary's
+//! schema, and runs a battery of checks on it:
+//!
+//! - `open`: the copy reads, with the store key when one is configured
+//!   (a snapshot taken before `nmem encrypt` reads as plaintext)
+//! - `restore` (exports): every exported row went back in
+//! - `migrate`: pending migrations apply
+//! - `integrity`: `PRAGMA quick_check`
+//! - `counts` (snapshots): sessions, prompts, observations, and episodes are
+//!   within `--tolerance` of the live store's counts
+//! - `fts`: the FTS5 indexes pass their integrity check and find a sampled
+//!   observation by one of its words
+//! - `sample`: random observations and prompts read back as text, with
+//!   valid JSON metadata
+//!
+//! The snapshot and the live store are only read; the scratch copy is
+//! removed afterwards.
+
+use crate::schema::MIGRATIONS;
+use crate::NmemError;
+use rusqlite::{Connection, OptionalExtension};
+use serde::Serialize;
+use std::path::{Path, PathBuf};
+
+/// Tables whose row counts are compared against the live store.
+const COUNTED: [&str; 4] = ["sessions", "prompts", "observations", "work_units"];
+
+#[derive(Debug, Serialize)]
+pub struct Check {
+    pub name: &'static str,
+    pub passed: bool,
+    pub detail: String,
+}
+
+#[derive(Debug, Serialize)]
+pub struct Report {
+    pub snapshot: String,
+    /// `database` or `export`.
+    pub kind: &'static str,
+    /// Schema version the snapshot was taken at (databases only).
+    pub schema_from: Option<usize>,
+    pub schema_to: usize,
+    pub passed: bool,
+    pub checks: Vec<Check>,
+}
+
+impl Report {
+    fn check(&mut self, name: &'static str, result: Result<String, String>) -> bool {
+        let passed = result.is_ok();
+        let detail = result.unwrap_or_else(|e| e);
+        self.checks.push(Check { name, passed, detail });
+        self.passed &= passed;
+        passed
+    }
+}
+
+/// Scratch directory, removed on drop.
+struct Scratch(PathBuf);
+
+impl Drop for Scratch {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_dir_all(&self.0);
+    }
+}
+
+fn scratch_dir() -> Result<Scratch, NmemError> {
+    let nanos = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_nanos();
+    let dir = std::env::temp_dir().join(format!("nmem-verify-{}-{nanos}", std::process::id()));
+    std::fs::create_dir_all(&dir)?;
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        std::fs::set_permissions(&dir, std::fs::Permissions::from_mode(0o700))?;
+    }
+    Ok(Scratch(dir))
+}
+
+/// An export is JSONL: its first non-blank byte opens an object.
+fn is_export(path: &Path) -> Result<bool, NmemError> {
+    use std::io::Read;
+    let mut head = [0u8; 64];
+    let n = std::fs::File::open(path)?.read(&mut head)?;
+    Ok(head[..n].iter().find(|b| !b.is_ascii_whitespace()) == Some(&b'{'))
+}
+
+/// Open `path` with the store key, falling back to plaintext. Returns the
+/// connection and how it was read.
+fn open_copy(path: &Path) -> Result<(Connection, &'static str), String> {
+    if let Some(key) = crate::db::load_key() {
+        let conn = Connection::open(path).map_err(|e| e.to_string())?;
+        if crate::db::apply_key(&conn, &key).is_ok() {
+            return Ok((conn, "decrypted with the store key"));
+        }
+    }
+    let conn = Connection::open(path).map_err(|e| e.to_string())?;
+    match conn.query_row("SELECT count(*) FROM sqlite_master", [], |_| Ok(())) {
+        Ok(()) => Ok((conn, "plaintext")),
+        Err(e) => Err(format!("unreadable (wrong key, not a database, or corrupt): {e}")),
+    }
+}
+
+fn count(conn: &Connection, table: &str) -> rusqlite::Result<i64> {
+    conn.query_row(&format!("SELECT COUNT(*) FROM {table}"), [], |r| r.get(0))
+}
+
+/// Whether `restored` is within `tolerance` (a fraction) of `live`.
+fn within(restored: i64, live: i64, tolerance: f64) -> bool {
+    let scale = restored.max(live).max(1) as f64;
+    (restored - live).abs() as f64 / scale <= tolerance
+}
+
+fn check_counts(conn: &Connection, live: &Path, tolerance: f64) -> Result<String, String> {
+    let live_conn = crate::db::open_db_readonly(live).map_err(|e| format!("live store unreadable: {e}"))?;
+    let mut parts = Vec::new();
+    let mut off = Vec::new();
+    for table in COUNTED {
+        let restored = count(conn, table).map_err(|e| format!("{table}: {e}"))?;
+        let current = count(&live_conn, table).map_err(|e| format!("live {table}: {e}"))?;
+        parts.push(format!("{table} {restored} (live {current})"));
+        if !within(restored, current, tolerance) {
+            off.push(table);
+        }
+    }
+    if off.is_empty() {
+        Ok(parts.join(", "))
+    } else {
+        Err(format!("{} off by more than {:.0}%: {}", off.join(", "), tolerance * 100.0, parts.join(", ")))
+    }
+}
+
+fn check_fts(conn: &Connection) -> Result<String, String> {
+    for table in ["observations_fts", "observation_chunks_fts", "prompts_fts"] {
+        conn.execute_batch(&format!("INSERT INTO {table}({table}) VALUES('integrity-check')"))
+            .map_err(|e| format!("{table}: {e}"))?;
+    }
+    let sampled: Option<(i64, String)> = conn
+        .query_row("SELECT id, content FROM observations ORDER BY random() LIMIT 1", [], |r| {
+            Ok((r.get(0)?, r.get(1)?))
+        })
+        .optional()
+        .map_err(|e| e.to_string())?;
+    let Some((id, content)) = sampled else {
+        return Ok("indexes intact; no observations to search".into());
+    };
+    let Some(word) = content
+        .split(|c: char| !c.is_alphanumeric())
+        .filter(|w| w.chars().count() >= 3)
+        .max_by_key(|w| w.len())
+    else {
+        return Ok(format!("indexes intact; observation {id} has no searchable word"));
+    };
+    let found: bool = conn
+        .query_row(
+            "SELECT EXISTS (SELECT 1 FROM observations_fts WHERE observations_fts MATCH ?1 AND rowid = ?2)",
+            rusqlite::params![format!("\"{word}\""), id],
+            |r| r.get(0),
+        )
+        .map_err(|e| e.to_string())?;
+    if found {
+        Ok(format!("indexes intact; \"{word}\" finds observation {id}"))
+    } else {
+        Err(format!("searching \"{word}\" does not find observation {id}"))
+    }
+}
+
+fn check_samples(conn: &Connection, samples: usize) -> Result<String, String> {
+    let read = |sql: &str| -> Result<usize, String> {
+        let mut stmt = conn.prepare(sql).map_err(|e| e.to_string())?;
+        let rows = stmt
+            .query_map([samples as i64], |r| Ok((r.get::<_, i64>(0)?, r.get::<_, String>(1)?, r.get::<_, bool>(2)?)))
+            .map_err(|e| e.to_string())?;
+        let mut n = 0;
+        for row in rows {
+            let (id, _, metadata_ok) = row.map_err(|e| format!("unreadable row: {e}"))?;
+            if !metadata_ok {
+                return Err(format!("row {id} has invalid JSON metadata"));
+            }
+            n += 1;
+        }
+        Ok(n)
+    };
+    let obs = read(
+        "SELECT id, content, metadata IS NULL OR json_valid(metadata) FROM observations ORDER BY random() LIMIT ?1",
+    )
+    .map_err(|e| format!("observations: {e}"))?;
+    let prompts = read(
+        "SELECT id, content, metadata IS NULL OR json_valid(metadata) FROM prompts ORDER BY random() LIMIT ?1",
+    )
+    .map_err(|e| format!("prompts: {e}"))?;
+    Ok(format!("read {obs} observations, {prompts} prompts"))
+}
+
+/// Restore `snapshot` into a scratch directory and check it, comparing row
+/// counts with the store at `live` when it exists. `tolerance` is the
+/// allowed relative count difference; `samples` how many random rows to
+/// read back.
+pub fn verify(snapshot: &Path, live: &Path, tolerance: f64, samples: usize) -> Result<Report, NmemError> {
+    if !snapshot.is_file() {
+        return Err(NmemError::Config(format!("snapshot not found: {}", snapshot.display())));
+    }
+    let export = is_export(snapshot)?;
+    let scratch = scratch_dir()?;
+    let copy = scratch.0.join("restore.db");
+    let mut report = Report {
+        snapshot: snapshot.display().to_string(),
+        kind: if export { "export" } else { "database" },
+        schema_from: None,
+        schema_to: 0,
+        passed: true,
+        checks: Vec::new(),
+    };
+
+    let conn = if export {
+        let mut conn = Connection::open(&copy)?;
+        MIGRATIONS.to_latest(&mut conn)?;
+        report.check("open", Ok("fresh store at the current schema".into()));
+        let text = std::fs::read_to_string(snapshot)?;
+        let lines = text.lines().filter(|l| !l.trim().is_empty()).count();
+        let restored = crate::s3_purge::restore_from(&conn, snapshot).map_err(|e| e.to_string());
+        let restored = restored.and_then(|tables| {
+            let total: usize = tables.iter().map(|(_, n)| n).sum();
+            let parts: Vec<String> = tables.iter().filter(|(_, n)| *n > 0).map(|(t, n)| format!("{n} {t}")).collect();
+            if total == lines {
+                Ok(format!("restored {}", if parts.is_empty() { "nothing".into() } else { parts.join(", ") }))
+            } else {
+                Err(format!("restored {total} of {lines} exported rows"))
+            }
+        });
+        if !report.check("restore", restored) {
+            return Ok(report);
+        }
+        conn
+    } else {
+        std::fs::copy(snapshot, &copy)?;
+        let opened = open_copy(&copy);
+        let detail = opened.as_ref().map(|(_, how)| how.to_string()).map_err(Clone::clone);
+        if !report.check("open", detail) {
+            return Ok(report);
+        }
+        let (mut conn, _) = opened.expect("checked above");
+        let from = crate::s3_migrate::schema_version(&conn)?;
+        report.schema_from = Some(from);
+        let migrated = MIGRATIONS.to_latest(&mut conn).map_err(|e| e.to_string()).and_then(|_| {
+            let to = crate::s3_migrate::schema_version(&conn).map_err(|e| e.to_string())?;
+            Ok(if to == from { format!("already at v{to}") } else { format!("v{from} → v{to}") })
+        });
+        if !report.check("migrate", migrated) {
+            return Ok(report);
+        }
+        conn
+    };
+    report.schema_to = crate::s3_migrate::schema_version(&conn)?;
+
+    let integrity: String = conn.query_row("PRAGMA quick_check", [], |r| r.get(0))?;
+    report.check("integrity", if integrity == "ok" { Ok("ok".into()) } else { Err(integrity) });
+    if !export && live.is_file() && !same_file(snapshot, live) {
+        report.check("counts", check_counts(&conn, live, tolerance));
+    }
+    report.check("fts", check_fts(&conn));
+    report.check("sample", check_samples(&conn, samples));
+    Ok(report)
+}
+
+fn same_file(a: &Path, b: &Path) -> bool {
+    matches!((a.canonicalize(), b.canonicalize()), (Ok(a), Ok(b)) if a == b)
+}
+
+#[cfg(feature = "cli")]
+pub fn handle_backup(db_path: &Path, args: &crate::cli::BackupArgs) -> Result<(), NmemError> {
+    match &args.command {
+        crate::cli::BackupCommand::Verify(v) => {
+            let report = verify(&v.snapshot, db_path, v.tolerance.max(0.0), v.samples.max(1))?;
+            println!("{}", serde_json::to_string_pretty(&report)?);
+            if !report.passed {
+                let failed: Vec<&str> = report.checks.iter().filter(|c| !c.passed).map(|c| c.name).collect();
+                return Err(NmemError::Config(format!("backup verification failed: {}", failed.join(", "))));
+            }
+            log::info!("{}: all {} checks passed", v.snapshot.display(), report.checks.len());
+            Ok(())
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn counts_compare_within_tolerance() {
+        assert!(within(100, 100, 0.0));
+        assert!(within(80, 100, 0.25));
+        assert!(!within(70, 100, 0.25));
+        assert!(within(0, 0, 0.0));
+        assert!(!within(0, 5, 0.5));
+    }
+}
@@ -0,0 +1,265 @@
+//! S3 Control — capture size accounting.
+//!
+//! Every hook write adds its size to `capture_sizes`, per session and
+//! obs_type (`prompt` for user prompts): `raw_bytes` is what the hook was
+//! handed (tool input and response), `stored_bytes` what was written after
+//! extraction, truncation, and redaction. The totals survive retention
+//! sweeps, so `nmem status --sessions` and `nmem stats --capture` show which
+//! sessions bloated the database before retention settings are chosen.
+//!
+//! `nmem stats --dirs` counts observations by the working directory they
+//! were recorded in instead, so activity in a monorepo can be told apart by
+//! subdirectory.
+
+#[cfg(feature = "cli")]
+use crate::cli::StatusArgs;
+use crate::NmemError;
+use rusqlite::{Connection, params};
+use serde::Serialize;
+use std::collections::BTreeMap;
+
+/// Obs_type under which user prompts are counted.
+pub const PROMPT: &str = "prompt";
+
+#[derive(Debug, Serialize, PartialEq)]
+pub struct TypeCapture {
+    pub obs_type: String,
+    pub entries: i64,
+    pub raw_bytes: i64,
+    pub stored_bytes: i64,
+}
+
+#[derive(Debug, Serialize)]
+pub struct SessionCapture {
+    pub session_id: String,
+    pub project: String,
+    pub started_at: i64,
+    pub entries: i64,
+    pub raw_bytes: i64,
+    pub stored_bytes: i64,
+    /// Stored bytes passed `[record] session_alert_mb`.
+    pub over_alert: bool,
+    /// Largest types first.
+    pub by_type: Vec<TypeCapture>,
+}
+
+#[derive(Debug, Serialize, PartialEq)]
+pub struct DirCapture {
+    pub cwd: String,
+    pub observations: i64,
+    /// Failed tool calls.
+    pub failed: i64,
+    pub last_at: i64,
+    pub by_type: BTreeMap<String, i64>,
+}
+
+/// Add one captured entry. Returns true when this write took the session's
+/// stored total past `alert_bytes` (never when `alert_bytes` is 0).
+pub fn record(
+    conn: &Connection,
+    session_id: &str,
+    obs_type: &str,
+    raw_bytes: usize,
+    stored_bytes: usize,
+    alert_bytes: u64,
+) -> Result<bool, NmemError> {
+    conn.execute(
+        "INSERT INTO capture_sizes (session_id, obs_type, entries, raw_bytes, stored_bytes)
+         VALUES (?1, ?2, 1, ?3, ?4)
+         ON CONFLICT (session_id, obs_type) DO UPDATE SET
+             entries = entries + 1,
+             raw_bytes = raw_bytes + excluded.raw_bytes,
+             stored_bytes = stored_bytes + excluded.stored_bytes",
+        params![session_id, obs_type, raw_bytes as i64, stored_bytes as i64],
+    )?;
+    if alert_bytes == 0 {
+        return Ok(false);
+    }
+    let total: i64 = conn.query_row(
+        "SELECT SUM(stored_bytes) FROM capture_sizes WHERE session_id = ?1",
+        params![session_id],
+        |r| r.get(0),
+    )?;
+    let alert = alert_bytes as i64;
+    Ok(total >= alert && total - (stored_bytes as i64) < alert)
+}
+
+/// Sessions with the most stored bytes, largest first.
+pub fn largest_sessions(conn: &Connection, limit: i64, alert_bytes: u64) -> Result<Vec<SessionCapture>, NmemError> {
+    let mut stmt = conn.prepare(
+        "SELECT c.session_id, s.project, s.started_at,
+                SUM(c.entries), SUM(c.raw_bytes), SUM(c.stored_bytes)
+         FROM capture_sizes c JOIN sessions s ON s.id = c.session_id
+         GROUP BY c.session_id
+         ORDER BY SUM(c.stored_bytes) DESC
+         LIMIT ?1",
+    )?;
+    let mut sessions: Vec<SessionCapture> = stmt
+        .query_map(params![limit], |r| {
+            let stored_bytes: i64 = r.get(5)?;
+            Ok(SessionCapture {
+                session_id: r.get(0)?,
+                project: r.get(1)?,
+                started_at: r.get(2)?,
+                entries: r.get(3)?,
+                raw_bytes: r.get(4)?,
+                stored_bytes,
+                over_alert: alert_bytes > 0 && stored_bytes >= alert_bytes as i64,
+                by_type: Vec::new(),
+            })
+        })?
+        .collect::<Result<_, _>>()?;
+
+    let mut by_type = conn.prepare(
+        "SELECT obs_type, entries, raw_bytes, stored_bytes FROM capture_sizes
+         WHERE session_id = ?1
+         ORDER BY stored_bytes DESC, obs_type",
+    )?;
+    for session in &mut sessions {
+        session.by_type = by_type
+            .query_map(params![session.session_id], |r| {
+                Ok(TypeCapture {
+                    obs_type: r.get(0)?,
+                    entries: r.get(1)?,
+                    raw_bytes: r.get(2)?,
+                    stored_bytes: r.get(3)?,
+                })
+            })?
+            .collect::<Result<_, _>>()?;
+    }
+    Ok(sessions)
+}
+
+/// Observations per recorded working directory, busiest first; with
+/// `under` (a normalized cwd), only that directory and those below it.
+pub fn by_directory(conn: &Connection, under: Option<&str>) -> Result<Vec<DirCapture>, NmemError> {
+    let mut stmt = conn.prepare(concat!(
+        "SELECT o.cwd, o.obs_type, COUNT(*),
+                COALESCE(SUM(COALESCE(json_extract(o.metadata, '$.failed'), 0) = 1), 0), MAX(o.timestamp)
+         FROM observations o
+         WHERE o.cwd IS NOT NULL AND ",
+        crate::s1_search::cwd_under_sql!("?1"),
+        "
+         GROUP BY o.cwd, o.obs_type"
+    ))?;
+    let mut dirs: BTreeMap<String, DirCapture> = BTreeMap::new();
+    let rows = stmt.query_map(params![under], |r| {
+        Ok((r.get::<_, String>(0)?, r.get::<_, String>(1)?, r.get::<_, i64>(2)?, r.get::<_, i64>(3)?, r.get::<_, i64>(4)?))
+    })?;
+    for row in rows {
+        let (cwd, obs_type, n, failed, last_at) = row?;
+        let dir = dirs.entry(cwd.clone()).or_insert_with(|| DirCapture {
+            cwd,
+            observations: 0,
+            failed: 0,
+            last_at,
+            by_type: BTreeMap::new(),
+        });
+        dir.observations += n;
+        dir.failed += failed;
+        dir.last_at = dir.last_at.max(last_at);
+        dir.by_type.insert(obs_type, n);
+    }
+    let mut dirs: Vec<DirCapture> = dirs.into_values().collect();
+    dirs.sort_by(|a, b| b.observations.cmp(&a.observations).then_with(|| a.cwd.cmp(&b.cwd)));
+    Ok(dirs)
+}
+
+/// Capture totals across all sessions: (raw_bytes, stored_bytes).
+pub fn totals(conn: &Connection) -> Result<(i64, i64), NmemError> {
+    Ok(conn.query_row(
+        "SELECT COALESCE(SUM(raw_bytes), 0), COALESCE(SUM(stored_bytes), 0) FROM capture_sizes",
+        [],
+        |r| Ok((r.get(0)?, r.get(1)?)),
+    )?)
+}
+
+/// Print the largest sessions for `nmem status --sessions`.
+#[cfg(feature = "cli")]
+pub fn print_sessions(conn: &Connection, args: &StatusArgs, alert_bytes: u64) -> Result<(), NmemError> {
+    let sessions = largest_sessions(conn, args.limit.clamp(1, 200), alert_bytes)?;
+    if sessions.is_empty() {
+        log::info!("no captured sessions");
+        return Ok(());
+    }
+    for s in &sessions {
+        let flag = if s.over_alert { " [over alert]" } else { "" };
+        log::info!(
+            "{} ({}) — {} stored of {} raw, {} entries{flag}",
+            s.session_id,
+            s.project,
+            crate::status::fmt_size(s.stored_bytes.max(0) as u64),
+            crate::status::fmt_size(s.raw_bytes.max(0) as u64),
+            s.entries,
+        );
+        let parts: Vec<String> = s
+            .by_type
+            .iter()
+            .take(5)
+            .map(|t| format!("{} {}", t.obs_type, crate::status::fmt_size(t.stored_bytes.max(0) as u64)))
+            .collect();
+        log::info!("  {}", parts.join(", "));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn accumulates_and_alerts_once() {
+        let mut conn = Connection::open_in_memory().unwrap();
+        crate::schema::MIGRATIONS.to_latest(&mut conn).unwrap();
+        conn.execute_batch("INSERT INTO sessions (id, project, started_at) VALUES ('a', 'p', 1), ('b', 'p', 2);")
+            .unwrap();
+
+        assert!(!record(&conn, "a", "command", 5000, 400, 1000).unwrap());
+        assert!(record(&conn, "a", "file_read", 9000, 700, 1000).unwrap());
+        assert!(!record(&conn, "a", "command", 100, 100, 1000).unwrap());
+        assert!(!record(&conn, "b", PROMPT, 50, 50, 0).unwrap());
+
+        let sessions = largest_sessions(&conn, 10, 1000).unwrap();
+        assert_eq!(sessions.len(), 2);
+        let a = &sessions[0];
+        assert_eq!(a.session_id, "a");
+        assert_eq!((a.entries, a.raw_bytes, a.stored_bytes), (3, 14100, 1200));
+        assert!(a.over_alert);
+        assert_eq!(
+            a.by_type[0],
+            TypeCapture { obs_type: "file_read".into(), entries: 1, raw_bytes: 9000, stored_bytes: 700 }
+        );
+        assert!(!sessions[1].over_alert);
+        assert_eq!(totals(&conn).unwrap(), (14150, 1250));
+    }
+
+    #[test]
+    fn counts_observations_by_directory() {
+        let mut conn = Connection::open_in_memory().unwrap();
+        crate::schema::MIGRATIONS.to_latest(&mut conn).unwrap();
+        conn.execute_batch(
+            r#"INSERT INTO sessions (id, project, started_at) VALUES ('s', 'mono', 1);
+               INSERT INTO observations (session_id, timestamp, obs_type, source_event, content, metadata, cwd) VALUES
+                 ('s', 10, 'command', 'PostToolUse', 'cargo test', '{"failed":true}', '/mono/crates/api'),
+                 ('s', 20, 'command', 'PostToolUse', 'cargo test', NULL, '/mono/crates/api'),
+                 ('s', 30, 'file_edit', 'PostToolUse', 'lib.rs', NULL, '/mono/crates/api'),
+                 ('s', 40, 'command', 'PostToolUse', 'cargo fmt', NULL, '/mono/crates/apiary'),
+                 ('s', 50, 'command', 'PostToolUse', 'ls', NULL, '/mono'),
+                 ('s', 60, 'command', 'PostToolUse', 'ls', NULL, NULL);"#,
+        )
+        .unwrap();
+
+        let all = by_directory(&conn, None).unwrap();
+        let cwds: Vec<&str> = all.iter().map(|d| d.cwd.as_str()).collect();
+        assert_eq!(cwds, vec!["/mono/crates/api", "/mono", "/mono/crates/apiary"]);
+        let api = &all[0];
+        assert_eq!((api.observations, api.failed, api.last_at), (3, 1, 30));
+        assert_eq!(api.by_type.get("command"), Some(&2));
+
+        // A directory filter takes the directory and those below, not siblings sharing a prefix
+        let under = by_directory(&conn, Some("/mono/crates/api")).unwrap();
+        assert_eq!(under.len(), 1);
+        assert_eq!(by_directory(&conn, Some("/mono")).unwrap().len(), 3);
+        assert_eq!(by_directory(&conn, Some("/")).unwrap().len(), 3);
+    }
+}
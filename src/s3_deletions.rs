@@ -0,0 +1,271 @@
+//! S3 Control — audit trail of deletions.
+//!
+//! nmem deletes memory on its own (retention sweeps, quota eviction) and on
+//! request (`nmem purge`); what was deleted, when, by whom, and under which
+//! filter is itself worth remembering. Every such operation appends one row
+//! to `deletions_log`, in the same transaction as the deletion. Triggers
+//! make the table append-only.
+//!
+//! With `[deletions_log] sign = true`, each row also carries
+//! `HMAC-SHA256(audit.key, prev_hash | row)`, chaining it to the row before,
+//! so `nmem audit deletions --verify` detects an edited or removed entry
+//! (dropping rows off the end is only detectable against a copy of the last
+//! hash).
+
+use crate::NmemError;
+use rusqlite::{Connection, OptionalExtension, params};
+use serde::Serialize;
+
+/// One deletion, as recorded.
+pub struct Deletion<'a> {
+    /// `purge`, `sweep`, or `quota`.
+    pub operation: &'a str,
+    /// What selected the rows: purge flags, retention tiers, quota projects.
+    pub filter: serde_json::Value,
+    /// Rows deleted, all tables together.
+    pub deleted: i64,
+    /// Per-table or per-type counts.
+    pub details: serde_json::Value,
+}
+
+#[derive(Debug, Serialize)]
+pub struct DeletionEntry {
+    pub id: i64,
+    pub timestamp: i64,
+    pub operation: String,
+    pub actor: String,
+    pub filter: serde_json::Value,
+    pub deleted: i64,
+    pub details: serde_json::Value,
+    pub signed: bool,
+}
+
+/// The key for signed entries, when `[deletions_log] sign` is on.
+pub fn signing_key(config: &crate::s5_config::NmemConfig) -> Option<Vec<u8>> {
+    if !config.deletions_log.sign {
+        return None;
+    }
+    match crate::db::load_or_create_side_key("audit.key") {
+        Ok(key) => Some(key.into_bytes()),
+        Err(e) => {
+            log::warn!("audit key unavailable, deletion logged unsigned: {e}");
+            None
+        }
+    }
+}
+
+/// Who ran the deletion: the OS user.
+fn actor() -> String {
+    std::env::var("USER")
+        .or_else(|_| std::env::var("USERNAME"))
+        .unwrap_or_else(|_| "unknown".into())
+}
+
+/// The bytes an entry's HMAC covers.
+#[allow(clippy::too_many_arguments)]
+fn signed_bytes(
+    id: i64,
+    timestamp: i64,
+    operation: &str,
+    actor: &str,
+    filter: &str,
+    deleted: i64,
+    details: &str,
+    prev_hash: &str,
+) -> Vec<u8> {
+    format!("{id}\x1f{timestamp}\x1f{operation}\x1f{actor}\x1f{filter}\x1f{deleted}\x1f{details}\x1f{prev_hash}").into_bytes()
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{b:02x}")).collect()
+}
+
+/// Append `deletion`, signed when `key` is given. Returns the entry's id.
+pub fn record(conn: &Connection, deletion: &Deletion, key: Option<&[u8]>, now: i64) -> Result<i64, NmemError> {
+    let (last_id, prev_hash): (i64, Option<String>) = conn
+        .query_row("SELECT id, hash FROM deletions_log ORDER BY id DESC LIMIT 1", [], |r| {
+            Ok((r.get(0)?, r.get(1)?))
+        })
+        .optional()?
+        .unwrap_or((0, None));
+    let id = last_id + 1;
+    let actor = actor();
+    let filter = deletion.filter.to_string();
+    let details = deletion.details.to_string();
+    let prev_hash = prev_hash.unwrap_or_default();
+    let hash = key.map(|key| {
+        let bytes = signed_bytes(id, now, deletion.operation, &actor, &filter, deletion.deleted, &details, &prev_hash);
+        hex(&crate::s5_filter::hmac_sha256(key, &bytes))
+    });
+    conn.execute(
+        "INSERT INTO deletions_log (id, timestamp, operation, actor, filter, deleted, details, hash)
+         VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8)",
+        params![id, now, deletion.operation, actor, filter, deletion.deleted, details, hash],
+    )?;
+    Ok(id)
+}
+
+/// Entries since `since`, newest first.
+pub fn recent(
+    conn: &Connection,
+    operation: Option<&str>,
+    since: i64,
+    limit: i64,
+) -> Result<Vec<DeletionEntry>, NmemError> {
+    let mut stmt = conn.prepare(
+        "SELECT id, timestamp, operation, actor, filter, deleted, details, hash IS NOT NULL
+         FROM deletions_log
+         WHERE timestamp >= ?1 AND (?2 IS NULL OR operation = ?2)
+         ORDER BY id DESC LIMIT ?3",
+    )?;
+    let rows = stmt
+        .query_map(params![since, operation, limit], |r| {
+            let json = |i: usize| -> rusqlite::Result<serde_json::Value> {
+                let text: String = r.get(i)?;
+                Ok(serde_json::from_str(&text).unwrap_or(serde_json::Value::String(text)))
+            };
+            Ok(DeletionEntry {
+                id: r.get(0)?,
+                timestamp: r.get(1)?,
+                operation: r.get(2)?,
+                actor: r.get(3)?,
+                filter: json(4)?,
+                deleted: r.get(5)?,
+                details: json(6)?,
+                signed: r.get(7)?,
+            })
+        })?
+        .collect::<Result<_, _>>()?;
+    Ok(rows)
+}
+
+#[derive(Debug, Serialize, PartialEq)]
+pub struct Problem {
+    pub id: i64,
+    pub problem: String,
+}
+
+#[derive(Debug, Serialize)]
+pub struct Verification {
+    pub entries: i64,
+    pub signed: i64,
+    pub problems: Vec<Problem>,
+    /// The newest hash; keep a copy elsewhere to detect truncation later.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub head: Option<String>,
+}
+
+/// Walk the whole log, recomputing every signed entry's HMAC and checking
+/// that ids run without gaps.
+pub fn verify(conn: &Connection, key: &[u8]) -> Result<Verification, NmemError> {
+    type Row = (i64, i64, String, String, String, i64, String, Option<String>);
+    let mut stmt = conn.prepare(
+        "SELECT id, timestamp, operation, actor, filter, deleted, details, hash FROM deletions_log ORDER BY id",
+    )?;
+    let rows: Vec<Row> = stmt
+        .query_map([], |r| Ok((r.get(0)?, r.get(1)?, r.get(2)?, r.get(3)?, r.get(4)?, r.get(5)?, r.get(6)?, r.get(7)?)))?
+        .collect::<Result<_, _>>()?;
+
+    let mut report = Verification { entries: rows.len() as i64, signed: 0, problems: Vec::new(), head: None };
+    let mut expected_id = 1;
+    let mut prev_hash = String::new();
+    for (id, timestamp, operation, actor, filter, deleted, details, hash) in rows {
+        if id != expected_id {
+            report.problems.push(Problem { id, problem: format!("entries {expected_id}..{} missing", id - 1) });
+        }
+        expected_id = id + 1;
+        if let Some(ref hash) = hash {
+            report.signed += 1;
+            let bytes = signed_bytes(id, timestamp, &operation, &actor, &filter, deleted, &details, &prev_hash);
+            if hex(&crate::s5_filter::hmac_sha256(key, &bytes)) != *hash {
+                report.problems.push(Problem { id, problem: "hash mismatch: entry or its predecessor was altered".into() });
+            }
+        }
+        prev_hash = hash.clone().unwrap_or_default();
+        if hash.is_some() {
+            report.head = hash;
+        }
+    }
+    Ok(report)
+}
+
+/// CLI handler: `nmem audit deletions`.
+#[cfg(feature = "cli")]
+pub fn handle_deletions(conn: &Connection, args: &crate::cli::AuditDeletionsArgs, now: i64) -> Result<String, NmemError> {
+    if args.verify {
+        let key = crate::db::load_or_create_side_key("audit.key")?;
+        let report = verify(conn, key.as_bytes())?;
+        if !report.problems.is_empty() {
+            log::warn!("deletions_log: {} problems found", report.problems.len());
+        }
+        return Ok(serde_json::to_string_pretty(&report)?);
+    }
+    let since = now - args.days * 86400;
+    let rows = recent(conn, args.operation.as_deref(), since, args.limit.clamp(1, 1000))?;
+    Ok(serde_json::to_string_pretty(&rows)?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn setup() -> Connection {
+        let mut conn = Connection::open_in_memory().unwrap();
+        crate::schema::MIGRATIONS.to_latest(&mut conn).unwrap();
+        conn
+    }
+
+    fn purge(deleted: i64) -> Deletion<'static> {
+        Deletion {
+            operation: "purge",
+            filter: serde_json::json!({"project": "api"}),
+            deleted,
+            details: serde_json::json!({"observations": deleted}),
+        }
+    }
+
+    #[test]
+    fn log_is_append_only_and_queryable() {
+        let conn = setup();
+        record(&conn, &purge(3), None, 100).unwrap();
+        record(&conn, &Deletion { operation: "sweep", ..purge(7) }, None, 200).unwrap();
+
+        let rows = recent(&conn, None, 0, 10).unwrap();
+        assert_eq!(rows.iter().map(|r| r.id).collect::<Vec<_>>(), vec![2, 1]);
+        assert_eq!(rows[1].filter["project"], "api");
+        assert!(!rows[0].signed);
+        assert_eq!(recent(&conn, Some("purge"), 0, 10).unwrap().len(), 1);
+        assert_eq!(recent(&conn, None, 150, 10).unwrap().len(), 1);
+
+        assert!(conn.execute("UPDATE deletions_log SET deleted = 0", []).is_err());
+        assert!(conn.execute("DELETE FROM deletions_log", []).is_err());
+    }
+
+    #[test]
+    fn signed_chain_detects_tampering() {
+        let conn = setup();
+        let key = b"test-key";
+        for (i, n) in [3, 7, 11].into_iter().enumerate() {
+            record(&conn, &purge(n), Some(key), 100 + i as i64).unwrap();
+        }
+        let clean = verify(&conn, key).unwrap();
+        assert_eq!((clean.entries, clean.signed), (3, 3));
+        assert!(clean.problems.is_empty(), "{:?}", clean.problems);
+        assert_eq!(verify(&conn, b"other-key").unwrap().problems.len(), 3, "wrong key fails every entry");
+
+        // Someone drops the triggers and edits the middle entry
+        conn.execute_batch(
+            "DROP TRIGGER deletions_log_no_update; DROP TRIGGER deletions_log_no_delete;
+             UPDATE deletions_log SET deleted = 1 WHERE id = 2;",
+        )
+        .unwrap();
+        let problems = verify(&conn, key).unwrap().problems;
+        assert_eq!(problems.iter().map(|p| p.id).collect::<Vec<_>>(), vec![2]);
+
+        // ...or removes it: the gap and the broken link both show
+        conn.execute("DELETE FROM deletions_log WHERE id = 2", []).unwrap();
+        let problems = verify(&conn, key).unwrap().problems;
+        assert_eq!(problems.len(), 2, "{problems:?}");
+        assert!(problems[0].problem.contains("2..2 missing"), "{problems:?}");
+    }
+}
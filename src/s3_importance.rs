@@ -0,0 +1,164 @@
+//! S3 Control — observation importance.
+//!
+//! One model decides which observations matter most wherever they compete
+//! for limited room: blended `search`, `recent_context`, the SessionStart
+//! context tables, and quota eviction in sweep. Its signals, each in 0–1:
+//!
+//! - relevance — BM25 rank scaled across the result set (search only);
+//! - recency — `exp_decay` of age in days, with the project's half-life;
+//! - type — the obs_type's weight;
+//! - project — 1 in the project asked about, `other_project` outside it;
+//!
+//! plus `pin_bonus` for pinned observations. Weights come from `[scoring]`.
+//! The default profile:
+//!
+//! | blend | weights |
+//! |---|---|
+//! | search | 0.5 relevance + 0.3 recency + 0.2 type |
+//! | recent | 0.5 recency + 0.3 type + 0.2 project |
+//!
+//! with type weights `file_edit` 1.0, `command` 0.67, `session_compact`
+//! 0.5, `mcp_call` 0.33, anything else 0.17; `other_project` 0.3; and
+//! `pin_bonus` 0, since context already keeps pins ahead of everything.
+//! Without a project to match (an unscoped `recent_context`, or eviction
+//! within one project) every observation counts as matching.
+//!
+//! SQL reaches the model through `search_score()` and `recent_score()`,
+//! registered by `db::register_udfs_with`.
+
+use crate::s5_config::{RecentWeights, SearchWeights};
+use std::collections::HashMap;
+
+/// Observation-type weights behind the `type_weight()` SQL function.
+#[derive(Debug, Clone, PartialEq)]
+pub struct TypeWeights {
+    pub weights: HashMap<String, f64>,
+    /// Weight for types not in `weights` (and NULL).
+    pub other: f64,
+}
+
+impl Default for TypeWeights {
+    fn default() -> Self {
+        Self {
+            weights: [("file_edit", 1.0), ("command", 0.67), ("session_compact", 0.5), ("doc_knowledge", 0.5), ("mcp_call", 0.33)]
+                .into_iter()
+                .map(|(t, w)| (t.to_string(), w))
+                .collect(),
+            other: 0.17,
+        }
+    }
+}
+
+impl TypeWeights {
+    pub fn get(&self, obs_type: Option<&str>) -> f64 {
+        obs_type.and_then(|t| self.weights.get(t)).copied().unwrap_or(self.other)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct ImportanceModel {
+    pub types: TypeWeights,
+    pub search: SearchWeights,
+    pub recent: RecentWeights,
+    /// Project signal of observations outside the project asked about.
+    pub other_project: f64,
+    pub pin_bonus: f64,
+}
+
+impl Default for ImportanceModel {
+    fn default() -> Self {
+        Self {
+            types: TypeWeights::default(),
+            search: SearchWeights::default(),
+            recent: RecentWeights::default(),
+            other_project: 0.3,
+            pin_bonus: 0.0,
+        }
+    }
+}
+
+impl ImportanceModel {
+    fn pin(&self, pinned: bool) -> f64 {
+        if pinned { self.pin_bonus } else { 0.0 }
+    }
+
+    /// Blended search score of a match.
+    pub fn search_score(&self, relevance: f64, recency: f64, obs_type: Option<&str>, pinned: bool) -> f64 {
+        let w = &self.search;
+        w.relevance * relevance + w.recency * recency + w.obs_type * self.types.get(obs_type) + self.pin(pinned)
+    }
+
+    /// Score of an observation competing on recency. `in_project` is
+    /// `None` when there is no project to match.
+    pub fn recent_score(&self, recency: f64, obs_type: Option<&str>, pinned: bool, in_project: Option<bool>) -> f64 {
+        let w = &self.recent;
+        let project = if in_project == Some(false) { self.other_project } else { 1.0 };
+        w.recency * recency + w.obs_type * self.types.get(obs_type) + w.project * project + self.pin(pinned)
+    }
+}
+
+/// Recency signal of an observation `age_secs` old: 1 now, halving every
+/// `half_life_days`. Matches the `exp_decay()` SQL function.
+pub fn recency(age_secs: i64, half_life_days: f64) -> f64 {
+    if half_life_days <= 0.0 {
+        return 0.0;
+    }
+    (-std::f64::consts::LN_2 * (age_secs as f64 / 86400.0) / half_life_days).exp()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const DAY: i64 = 86400;
+
+    #[test]
+    fn default_profile_orders_recent_observations() {
+        let model = ImportanceModel::default();
+        let score = |age_days: i64, obs_type: &str, pinned: bool, in_project: Option<bool>| {
+            model.recent_score(recency(age_days * DAY, 7.0), Some(obs_type), pinned, in_project)
+        };
+
+        // Same age: type decides
+        assert!(score(0, "file_edit", false, None) > score(0, "command", false, None));
+        assert!(score(0, "command", false, None) > score(0, "mcp_call", false, None));
+        assert!(score(0, "mcp_call", false, None) > score(0, "file_read", false, None));
+        // A five-day-old edit still outranks a fresh read, not a fresh command
+        assert!(score(5, "file_edit", false, None) > score(0, "file_read", false, None));
+        assert!(score(5, "file_edit", false, None) < score(0, "command", false, None));
+        // The project asked about beats another project's identical observation
+        assert!(score(1, "command", false, Some(true)) > score(1, "command", false, Some(false)));
+        assert_eq!(score(1, "command", false, None), score(1, "command", false, Some(true)));
+        // No pin bonus by default
+        assert_eq!(score(3, "command", true, None), score(3, "command", false, None));
+    }
+
+    #[test]
+    fn search_blend_weighs_relevance_first() {
+        let model = ImportanceModel::default();
+        let fresh = recency(0, 7.0);
+        let month = recency(30 * DAY, 7.0);
+        // Best match from a month ago beats a weak fresh one of the same type
+        assert!(model.search_score(1.0, month, Some("command"), false) > model.search_score(0.2, fresh, Some("command"), false));
+        // Equal relevance and age: type decides
+        assert!(model.search_score(0.5, fresh, Some("file_edit"), false) > model.search_score(0.5, fresh, Some("command"), false));
+        assert!((model.search_score(1.0, 1.0, Some("file_edit"), false) - 1.0).abs() < 1e-9);
+    }
+
+    #[test]
+    fn configured_weights_change_the_order() {
+        let model = ImportanceModel {
+            pin_bonus: 1.0,
+            recent: RecentWeights { recency: 1.0, obs_type: 0.0, project: 0.0 },
+            ..ImportanceModel::default()
+        };
+        let fresh_read = model.recent_score(recency(0, 7.0), Some("file_read"), false, None);
+        let old_edit = model.recent_score(recency(2 * DAY, 7.0), Some("file_edit"), false, None);
+        assert!(fresh_read > old_edit, "recency only: newest first");
+        let old_pinned = model.recent_score(recency(60 * DAY, 7.0), Some("file_read"), true, None);
+        assert!(old_pinned > fresh_read, "a full pin bonus lifts pins above everything");
+
+        assert_eq!(recency(7 * DAY, 7.0), 0.5);
+        assert_eq!(recency(DAY, 0.0), 0.0);
+    }
+}
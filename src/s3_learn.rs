@@ -1,10 +1,31 @@
+#[cfg(feature = "cli")]
 use crate::cli::LearnArgs;
 use crate::db::open_db_readonly;
 use crate::NmemError;
 use rusqlite::Connection;
+use serde::Serialize;
+use sha2::{Digest, Sha256};
 use std::collections::HashMap;
 use std::path::{Path, PathBuf};
 
+/// Kind of an intent confirmed as a stuck loop by failures or errors in the
+/// same sessions.
+pub const STUCK_LOOP: &str = "stuck_loop";
+
+/// Kind of an error failing in several projects at once — the machine or
+/// network (DNS, docker daemon, registry), not any one project.
+pub const ENVIRONMENT_ISSUE: &str = "environment_issue";
+
+/// Every kind a report can contain.
+pub const PATTERN_KINDS: [&str; 6] =
+    [STUCK_LOOP, ENVIRONMENT_ISSUE, "failed_command", "recurring_error", "repeated_intent", "unresolved_read"];
+
+/// Error classes that are about a project's own code, never its environment.
+const PROJECT_ERROR_CLASSES: [&str; 2] = ["compile_error", "test_failure"];
+
+/// Report severities, lowest first — SARIF's result levels.
+pub const SEVERITIES: [&str; 3] = ["note", "warning", "error"];
+
 pub struct Pattern {
     pub kind: &'static str,
     pub description: String,
@@ -118,6 +139,7 @@ fn detect_failed_commands(
          FROM observations
          WHERE obs_type = 'command'
            AND json_extract(metadata, '$.failed') = 1
+           AND session_id NOT IN (SELECT s.id FROM sessions s JOIN archived_projects a ON a.project = s.project)
          GROUP BY content, session_id",
     )?;
 
@@ -205,6 +227,7 @@ fn detect_unresolved_reads(
          FROM observations o
          WHERE o.obs_type = 'file_read'
            AND o.file_path IS NOT NULL
+           AND o.session_id NOT IN (SELECT s.id FROM sessions s JOIN archived_projects a ON a.project = s.project)
            AND NOT EXISTS (
                SELECT 1 FROM observations e
                WHERE e.file_path = o.file_path
@@ -286,11 +309,13 @@ fn detect_error_patterns(
 
     // Fetch failed commands that have error responses in metadata.
     let mut stmt = conn.prepare(
-        "SELECT json_extract(metadata, '$.response'), session_id, MAX(timestamp) as latest_ts
+        "SELECT json_extract(metadata, '$.response'), session_id, MAX(timestamp) as latest_ts,
+                COALESCE(error_class, 'other')
          FROM observations
          WHERE obs_type = 'command'
            AND json_extract(metadata, '$.failed') = 1
            AND json_extract(metadata, '$.response') IS NOT NULL
+           AND session_id NOT IN (SELECT s.id FROM sessions s JOIN archived_projects a ON a.project = s.project)
          GROUP BY json_extract(metadata, '$.response'), session_id",
     )?;
 
@@ -298,6 +323,7 @@ fn detect_error_patterns(
         response: String,
         session_id: String,
         timestamp: i64,
+        error_class: String,
     }
 
     let rows: Vec<Row> = stmt
@@ -306,18 +332,22 @@ fn detect_error_patterns(
                 response: row.get(0)?,
                 session_id: row.get(1)?,
                 timestamp: row.get(2)?,
+                error_class: row.get(3)?,
             })
         })?
         .collect::<Result<_, _>>()?;
 
-    // Extract error signature from response text — first meaningful error line.
+    // Group by error class + signature (first meaningful error line). The class
+    // keeps differently-worded lines of one kind apart from unrelated failures.
     let mut groups: HashMap<String, HashMap<String, i64>> = HashMap::new();
     let mut examples: HashMap<String, String> = HashMap::new();
+    let mut classes: HashMap<String, String> = HashMap::new();
     for row in &rows {
         let sig = extract_error_signature(&row.response);
         if sig.is_empty() {
             continue;
         }
+        classes.entry(sig.clone()).or_insert_with(|| row.error_class.clone());
         examples.entry(sig.clone()).or_insert_with(|| {
             row.response.chars().take(200).collect()
         });
@@ -343,9 +373,10 @@ fn detect_error_patterns(
             let session_count = sessions.len() as i64;
             let session_ids: Vec<String> = sessions.into_keys().collect();
             let example = examples.get(&sig).cloned().unwrap_or_default();
+            let class = classes.get(&sig).map(String::as_str).unwrap_or("other");
             Pattern {
                 kind: "recurring_error",
-                description: format!("`{}` across {session_count} sessions", short_cmd(&sig)),
+                description: format!("`{}` ({class}) across {session_count} sessions", short_cmd(&sig)),
                 normalized: sig,
                 session_count,
                 heat,
@@ -360,6 +391,117 @@ fn detect_error_patterns(
     Ok(patterns)
 }
 
+/// One error signature failing across projects within the window.
+#[derive(Debug, Serialize)]
+pub struct EnvironmentIssue {
+    pub signature: String,
+    pub error_class: String,
+    pub projects: Vec<String>,
+    pub sessions: Vec<String>,
+    pub first_seen: i64,
+    pub last_seen: i64,
+    pub example: String,
+}
+
+/// Failures since `now - window_secs` whose error signature shows up in at
+/// least `min_projects` projects, most recent first. Compile and test
+/// failures are left out: those belong to the project.
+pub fn detect_environment_issues(
+    conn: &Connection,
+    min_projects: usize,
+    window_secs: i64,
+    now: i64,
+) -> Result<Vec<EnvironmentIssue>, NmemError> {
+    use std::collections::{BTreeMap, BTreeSet};
+
+    let mut stmt = conn.prepare(
+        "SELECT json_extract(o.metadata, '$.response'), o.session_id, s.project, o.timestamp,
+                COALESCE(o.error_class, 'other')
+         FROM observations o JOIN sessions s ON s.id = o.session_id
+         WHERE json_extract(o.metadata, '$.failed') = 1
+           AND json_extract(o.metadata, '$.response') IS NOT NULL
+           AND o.timestamp > ?1 AND o.timestamp <= ?2
+           AND NOT EXISTS (SELECT 1 FROM archived_projects a WHERE a.project = s.project)
+         ORDER BY o.timestamp",
+    )?;
+    let rows = stmt.query_map(rusqlite::params![now - window_secs, now], |r| {
+        Ok((
+            r.get::<_, String>(0)?,
+            r.get::<_, String>(1)?,
+            r.get::<_, String>(2)?,
+            r.get::<_, i64>(3)?,
+            r.get::<_, String>(4)?,
+        ))
+    })?;
+
+    #[derive(Default)]
+    struct Group {
+        class: String,
+        projects: BTreeSet<String>,
+        sessions: BTreeSet<String>,
+        first: i64,
+        last: i64,
+        example: String,
+    }
+    let mut groups: BTreeMap<String, Group> = BTreeMap::new();
+    for row in rows {
+        let (response, session_id, project, ts, class) = row?;
+        if PROJECT_ERROR_CLASSES.contains(&class.as_str()) {
+            continue;
+        }
+        let sig = extract_error_signature(&response);
+        if sig.is_empty() {
+            continue;
+        }
+        let g = groups.entry(sig).or_insert_with(|| Group {
+            class,
+            first: ts,
+            example: response.chars().take(200).collect(),
+            ..Default::default()
+        });
+        g.projects.insert(project);
+        g.sessions.insert(session_id);
+        g.last = ts;
+    }
+
+    let mut issues: Vec<EnvironmentIssue> = groups
+        .into_iter()
+        .filter(|(_, g)| g.projects.len() >= min_projects)
+        .map(|(signature, g)| EnvironmentIssue {
+            signature,
+            error_class: g.class,
+            projects: g.projects.into_iter().collect(),
+            sessions: g.sessions.into_iter().collect(),
+            first_seen: g.first,
+            last_seen: g.last,
+            example: g.example,
+        })
+        .collect();
+    issues.sort_by(|a, b| b.last_seen.cmp(&a.last_seen).then_with(|| a.signature.cmp(&b.signature)));
+    Ok(issues)
+}
+
+/// Report `issues` as their own patterns, heat 100 while current and decaying
+/// from the last failure, and drop the recurring errors they explain.
+pub fn separate_environment(patterns: &mut Vec<Pattern>, issues: &[EnvironmentIssue], half_life: f64, now: i64) {
+    patterns.retain(|p| p.kind != "recurring_error" || !issues.iter().any(|i| i.signature == p.normalized));
+    patterns.extend(issues.iter().map(|i| Pattern {
+        kind: ENVIRONMENT_ISSUE,
+        description: format!(
+            "`{}` ({}) in {} projects: {}",
+            short_cmd(&i.signature),
+            i.error_class,
+            i.projects.len(),
+            i.projects.join(", ")
+        ),
+        normalized: i.signature.clone(),
+        session_count: i.sessions.len() as i64,
+        heat: (exp_decay((now - i.last_seen) as f64 / 3600.0, half_life) * 100.0).round(),
+        sessions: i.sessions.clone(),
+        example: i.example.clone(),
+    }));
+}
+
 /// Extract a normalized error signature from a response string.
 /// Looks for common error patterns and returns a short canonical form.
 fn extract_error_signature(response: &str) -> String {
@@ -414,8 +556,9 @@ fn detect_repeated_intents(
     let mut stmt = conn.prepare(
         "SELECT id, started_at, json_extract(summary, '$.intent') as intent
          FROM sessions
-         WHERE summary IS NOT NULL
-           AND json_extract(summary, '$.intent') IS NOT NULL",
+         WHERE summary IS NOT NULL AND kind = 'agent'
+           AND json_extract(summary, '$.intent') IS NOT NULL
+           AND NOT EXISTS (SELECT 1 FROM archived_projects a WHERE a.project = sessions.project)",
     )?;
 
     struct Row {
@@ -506,21 +649,66 @@ fn detect_repeated_intents(
     Ok(patterns)
 }
 
-pub const STOPWORDS: &[&str] = &[
-    "a", "an", "the", "and", "or", "to", "of", "in", "for", "with", "on", "at", "by", "from",
-    "is", "it", "this", "that", "be", "as", "are", "was", "were", "been", "do", "does", "did",
-    "will", "would", "could", "should", "may", "might", "can", "has", "have", "had", "not", "no",
-    "up", "out", "about", "into", "over", "after", "before",
-];
+/// Fewest prompt keywords worth comparing — "yes", "go on" match anything.
+const MIN_PROMPT_KEYWORDS: usize = 3;
+
+/// An earlier episode a new prompt repeats.
+#[derive(Debug, PartialEq)]
+pub struct SimilarEpisode {
+    pub session_id: String,
+    pub started_at: i64,
+    /// The narrative's intent when summarized, else the episode's own.
+    pub summary: String,
+    pub outcome: Option<String>,
+}
+
+/// The episode in `project`, outside `session_id`, whose intent's keywords
+/// are closest to `prompt`'s, when at least `threshold` (Jaccard) — the
+/// repeated-intent check, run on one prompt as it is recorded. Ties go to
+/// the most recent.
+pub fn similar_episode(
+    conn: &Connection,
+    project: &str,
+    session_id: &str,
+    prompt: &str,
+    threshold: f64,
+) -> Result<Option<SimilarEpisode>, NmemError> {
+    let keywords = intent_keywords(prompt);
+    if threshold <= 0.0 || keywords.len() < MIN_PROMPT_KEYWORDS {
+        return Ok(None);
+    }
+    let mut stmt = conn.prepare_cached(
+        "SELECT w.session_id, w.started_at, w.intent,
+                CASE WHEN json_valid(w.summary) THEN json_extract(w.summary, '$.intent') END, w.outcome
+         FROM work_units w JOIN sessions s ON s.id = w.session_id
+         WHERE s.project = ?1 AND w.session_id != ?2 AND w.intent IS NOT NULL
+         ORDER BY w.started_at DESC LIMIT 500",
+    )?;
+    let mut best: Option<(f64, SimilarEpisode)> = None;
+    let rows = stmt.query_map(rusqlite::params![project, session_id], |r| {
+        Ok((
+            r.get::<_, String>(0)?,
+            r.get::<_, i64>(1)?,
+            r.get::<_, String>(2)?,
+            r.get::<_, Option<String>>(3)?,
+            r.get::<_, Option<String>>(4)?,
+        ))
+    })?;
+    for row in rows {
+        let (session_id, started_at, intent, narrated, outcome) = row?;
+        let score = jaccard(&keywords, &intent_keywords(&intent));
+        if score >= threshold && best.as_ref().is_none_or(|(b, _)| score > *b) {
+            let summary = narrated.filter(|n| !n.trim().is_empty()).unwrap_or(intent);
+            best = Some((score, SimilarEpisode { session_id, started_at, summary, outcome }));
+        }
+    }
+    Ok(best.map(|(_, episode)| episode))
+}
 
-/// Extract meaningful keywords from an intent string.
+/// Extract meaningful keywords from an intent string, in the language it
+/// reads as (see `s1_lang`).
 pub fn intent_keywords(intent: &str) -> Vec<String> {
-    intent
-        .to_lowercase()
-        .split(|c: char| !c.is_alphanumeric() && c != '_')
-        .filter(|w| w.len() > 2 && !STOPWORDS.contains(w))
-        .map(|w| w.to_string())
-        .collect()
+    crate::s1_lang::keywords(intent, &crate::s1_lang::detect(intent))
 }
 
 /// Jaccard similarity between two keyword bags (as sorted unique sets).
@@ -614,17 +802,18 @@ fn normalize_heat(patterns: &mut [Pattern]) {
 pub fn write_report(patterns: &[Pattern], output: &Path) -> Result<(), NmemError> {
     use std::fmt::Write;
 
-    let now = chrono_date();
+    let now = crate::timefmt::iso(now_secs());
     let failed: Vec<&Pattern> = patterns.iter().filter(|p| p.kind == "failed_command").collect();
     let unresolved: Vec<&Pattern> = patterns.iter().filter(|p| p.kind == "unresolved_read").collect();
     let errors: Vec<&Pattern> = patterns.iter().filter(|p| p.kind == "recurring_error").collect();
     let intents: Vec<&Pattern> = patterns.iter().filter(|p| p.kind == "repeated_intent").collect();
+    let environment: Vec<&Pattern> = patterns.iter().filter(|p| p.kind == ENVIRONMENT_ISSUE).collect();
 
     let mut md = String::new();
     writeln!(md, "# nmem learnings — detected {now}").unwrap();
     writeln!(md).unwrap();
 
-    if failed.is_empty() && unresolved.is_empty() && errors.is_empty() && intents.is_empty() {
+    if failed.is_empty() && unresolved.is_empty() && errors.is_empty() && intents.is_empty() && environment.is_empty() {
         writeln!(md, "No patterns detected above threshold.").unwrap();
     }
 
@@ -644,6 +833,19 @@ pub fn write_report(patterns: &[Pattern], output: &Path) -> Result<(), NmemError
         }
     }
 
+    if !environment.is_empty() {
+        writeln!(md, "## Environment issues ({} patterns)", environment.len()).unwrap();
+        writeln!(md).unwrap();
+        writeln!(md, "Failing the same way across projects — check the machine or network, not the code:").unwrap();
+        writeln!(md).unwrap();
+        for p in &environment {
+            writeln!(md, "### {} (heat: {})", p.description, p.heat as u32).unwrap();
+            writeln!(md, "Sessions: {}", format_sessions(&p.sessions)).unwrap();
+            writeln!(md, "Example: `{}`", p.example).unwrap();
+            writeln!(md).unwrap();
+        }
+    }
+
     if !failed.is_empty() {
         writeln!(md, "## Repeated failures ({} patterns)", failed.len()).unwrap();
         writeln!(md).unwrap();
@@ -698,6 +900,165 @@ pub fn write_report(patterns: &[Pattern], output: &Path) -> Result<(), NmemError
     Ok(())
 }
 
+/// Short id stable across runs while the pattern persists: hash of kind and
+/// normalized target.
+pub fn pattern_id(kind: &str, target: &str) -> String {
+    let digest = Sha256::new().chain_update(kind).chain_update([0]).chain_update(target).finalize();
+    digest[..4].iter().map(|b| format!("{b:02x}")).collect()
+}
+
+fn default_severity(kind: &str) -> &'static str {
+    match kind {
+        STUCK_LOOP => "error",
+        ENVIRONMENT_ISSUE | "failed_command" | "recurring_error" => "warning",
+        _ => "note",
+    }
+}
+
+fn severity_rank(level: &str) -> usize {
+    SEVERITIES.iter().position(|s| *s == level).unwrap_or(0)
+}
+
+/// One report entry for CI: a pattern with its severity.
+#[derive(Debug, Serialize)]
+pub struct Finding {
+    pub id: String,
+    pub kind: &'static str,
+    pub severity: String,
+    pub description: String,
+    pub normalized: String,
+    pub session_count: i64,
+    pub heat: f64,
+    pub sessions: Vec<String>,
+    pub example: String,
+    /// Stuck loops only: the failures and errors seen in the same sessions.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub corroborated_by: Vec<String>,
+}
+
+impl Finding {
+    fn new(p: &Pattern, kind: &'static str, severities: &HashMap<String, String>) -> Self {
+        Finding {
+            id: pattern_id(kind, &p.normalized),
+            kind,
+            severity: severities.get(kind).cloned().unwrap_or_else(|| default_severity(kind).into()),
+            description: p.description.clone(),
+            normalized: p.normalized.clone(),
+            session_count: p.session_count,
+            heat: p.heat,
+            sessions: p.sessions.clone(),
+            example: p.example.clone(),
+            corroborated_by: Vec::new(),
+        }
+    }
+}
+
+/// Patterns plus confirmed stuck loops, most severe then hottest first.
+/// `severities` overrides the built-in severity per kind.
+pub fn findings(patterns: &[Pattern], severities: &HashMap<String, String>) -> Vec<Finding> {
+    let of_kind = |kind: &str| patterns.iter().filter(|p| p.kind == kind).collect::<Vec<_>>();
+    let intents = of_kind("repeated_intent");
+    let mut out: Vec<Finding> = find_confirmed(&intents, &of_kind("failed_command"), &of_kind("recurring_error"))
+        .into_iter()
+        .map(|(intent, corroborating)| Finding {
+            corroborated_by: corroborating,
+            ..Finding::new(intent, STUCK_LOOP, severities)
+        })
+        .collect();
+    out.extend(patterns.iter().map(|p| Finding::new(p, p.kind, severities)));
+    out.sort_by(|a, b| {
+        severity_rank(&b.severity)
+            .cmp(&severity_rank(&a.severity))
+            .then(b.heat.total_cmp(&a.heat))
+            .then_with(|| a.id.cmp(&b.id))
+    });
+    out
+}
+
+/// Findings at or above `level`.
+pub fn count_at_or_above(findings: &[Finding], level: &str) -> usize {
+    let min = severity_rank(level);
+    findings.iter().filter(|f| severity_rank(&f.severity) >= min).count()
+}
+
+/// The `--format json` report.
+pub fn json_report(findings: &[Finding], threshold: i64, half_life: f64) -> serde_json::Value {
+    serde_json::json!({
+        "generated_at": now_secs(),
+        "threshold": threshold,
+        "half_life_hours": half_life,
+        "findings": findings,
+    })
+}
+
+/// The `--format sarif` report (SARIF 2.1.0), one rule per pattern kind.
+/// Unresolved reads carry their file as the location, relative to `root`
+/// when under it, so code scanning can annotate it.
+pub fn sarif_report(findings: &[Finding], root: Option<&Path>) -> serde_json::Value {
+    let rules: Vec<serde_json::Value> = PATTERN_KINDS
+        .iter()
+        .map(|kind| {
+            serde_json::json!({
+                "id": kind,
+                "shortDescription": {"text": rule_text(kind)},
+                "defaultConfiguration": {"level": default_severity(kind)},
+            })
+        })
+        .collect();
+    let results: Vec<serde_json::Value> = findings
+        .iter()
+        .map(|f| {
+            let mut text = format!("{} ({} sessions, heat {})", f.description, f.session_count, f.heat as u32);
+            if !f.corroborated_by.is_empty() {
+                text.push_str(&format!("; corroborated by {}", f.corroborated_by.join(", ")));
+            }
+            let mut result = serde_json::json!({
+                "ruleId": f.kind,
+                "level": f.severity,
+                "message": {"text": text},
+                "partialFingerprints": {"nmemPattern/v1": f.id},
+                "properties": {
+                    "heat": f.heat,
+                    "sessionCount": f.session_count,
+                    "sessions": f.sessions,
+                    "example": f.example,
+                },
+            });
+            if f.kind == "unresolved_read" {
+                let path = Path::new(&f.normalized);
+                let uri = root.and_then(|r| path.strip_prefix(r).ok()).unwrap_or(path);
+                result["locations"] = serde_json::json!([{
+                    "physicalLocation": {"artifactLocation": {"uri": uri.to_string_lossy()}},
+                }]);
+            }
+            result
+        })
+        .collect();
+    serde_json::json!({
+        "$schema": "https://json.schemastore.org/sarif-2.1.0.json",
+        "version": "2.1.0",
+        "runs": [{
+            "tool": {"driver": {
+                "name": "nmem",
+                "version": env!("CARGO_PKG_VERSION"),
+                "rules": rules,
+            }},
+            "results": results,
+        }],
+    })
+}
+
+fn rule_text(kind: &str) -> &'static str {
+    match kind {
+        STUCK_LOOP => "Repeated intent with failures in the same sessions",
+        ENVIRONMENT_ISSUE => "Same error failing across projects",
+        "failed_command" => "Command failing across sessions",
+        "recurring_error" => "Error recurring across sessions",
+        "repeated_intent" => "Same task started across sessions",
+        _ => "File read across sessions but never edited",
+    }
+}
+
 /// Find intents that share sessions with failures or errors — confirmed stuck loops.
 fn find_confirmed<'a>(
     intents: &[&'a Pattern],
@@ -747,68 +1108,85 @@ fn format_sessions(sessions: &[String]) -> String {
         .join(", ")
 }
 
-fn chrono_date() -> String {
-    // Simple date without pulling in chrono — just use the system
-    let now = std::time::SystemTime::now()
-        .duration_since(std::time::UNIX_EPOCH)
-        .unwrap()
-        .as_secs();
-    // Convert unix timestamp to YYYY-MM-DD
-    let days = now / 86400;
-    let mut y = 1970i64;
-    let mut remaining = days as i64;
-    loop {
-        let days_in_year = if is_leap(y) { 366 } else { 365 };
-        if remaining < days_in_year {
-            break;
-        }
-        remaining -= days_in_year;
-        y += 1;
-    }
-    let months = if is_leap(y) {
-        [31, 29, 31, 30, 31, 30, 31, 31, 30, 31, 30, 31]
-    } else {
-        [31, 28, 31, 30, 31, 30, 31, 31, 30, 31, 30, 31]
-    };
-    let mut m = 1;
-    for &days_in_month in &months {
-        if remaining < days_in_month {
-            break;
-        }
-        remaining -= days_in_month;
-        m += 1;
-    }
-    let d = remaining + 1;
-    format!("{y:04}-{m:02}-{d:02}")
-}
-
-fn is_leap(y: i64) -> bool {
-    (y % 4 == 0 && y % 100 != 0) || y % 400 == 0
-}
-
 fn default_output() -> PathBuf {
     let home = std::env::var("HOME").unwrap_or_else(|_| "/tmp".into());
     PathBuf::from(home).join(".nmem").join("learnings.md")
 }
 
+#[cfg(feature = "cli")]
 pub fn handle_learn(db_path: &Path, args: &LearnArgs) -> Result<(), NmemError> {
+    if !matches!(args.format.as_str(), "markdown" | "json" | "sarif") {
+        return Err(NmemError::Config(format!(
+            "invalid --format: {:?} (expected markdown, json, or sarif)",
+            args.format
+        )));
+    }
+    let config = crate::s5_config::load_config().unwrap_or_default();
+    let fail_on = args.fail_on.as_ref().or(config.learn.fail_on.as_ref());
+    if let Some(level) = fail_on
+        && !SEVERITIES.contains(&level.as_str())
+    {
+        return Err(NmemError::Config(format!(
+            "invalid --fail-on: {level:?} (expected {})",
+            SEVERITIES.join(", ")
+        )));
+    }
+
     let conn = open_db_readonly(db_path)?;
-    let patterns = detect_patterns(&conn, args.threshold, args.half_life)?;
-    let output = args.output.clone().unwrap_or_else(default_output);
+    let mut patterns = detect_patterns(&conn, args.threshold, args.half_life)?;
+    let now = now_secs();
+    let issues = detect_environment_issues(
+        &conn,
+        config.learn.environment_projects,
+        config.learn.environment_window_hours as i64 * 3600,
+        now,
+    )?;
+    separate_environment(&mut patterns, &issues, args.half_life, now);
+    let findings = findings(&patterns, &config.learn.severities);
 
-    write_report(&patterns, &output)?;
+    if args.format == "markdown" {
+        let output = args.output.clone().unwrap_or_else(default_output);
+        write_report(&patterns, &output)?;
+        log_counts(&patterns, &output.display().to_string());
+    } else {
+        let report = if args.format == "json" {
+            json_report(&findings, args.threshold, args.half_life)
+        } else {
+            sarif_report(&findings, std::env::current_dir().ok().as_deref())
+        };
+        let text = serde_json::to_string_pretty(&report)?;
+        match &args.output {
+            Some(path) => {
+                std::fs::write(path, text)?;
+                log_counts(&patterns, &path.display().to_string());
+            }
+            None => {
+                println!("{text}");
+                log_counts(&patterns, "stdout");
+            }
+        }
+    }
 
+    if let Some(level) = fail_on {
+        let failing = count_at_or_above(&findings, level);
+        if failing > 0 {
+            return Err(NmemError::Config(format!("{failing} patterns at or above {level}")));
+        }
+    }
+    Ok(())
+}
+
+#[cfg(feature = "cli")]
+fn log_counts(patterns: &[Pattern], destination: &str) {
     let failed_count = patterns.iter().filter(|p| p.kind == "failed_command").count();
     let error_count = patterns.iter().filter(|p| p.kind == "recurring_error").count();
     let intent_count = patterns.iter().filter(|p| p.kind == "repeated_intent").count();
     let unresolved_count = patterns.iter().filter(|p| p.kind == "unresolved_read").count();
+    let environment_count = patterns.iter().filter(|p| p.kind == ENVIRONMENT_ISSUE).count();
 
     log::info!(
-        "{failed_count} failures, {error_count} errors, {intent_count} intents, {unresolved_count} unresolved → {}",
-        output.display()
+        "{failed_count} failures, {error_count} errors, {intent_count} intents, {unresolved_count} unresolved, {environment_count} environment → {destination}"
     );
-
-    Ok(())
 }
 
 #[cfg(test)]
@@ -868,6 +1246,19 @@ mod tests {
         assert_eq!(patterns[0].session_count, 4);
     }
 
+    #[test]
+    fn archived_projects_are_left_out() {
+        let conn = setup_db();
+        for i in 0..4 {
+            let sid = format!("session-{i}");
+            insert_session(&conn, &sid);
+            insert_obs(&conn, &sid, "command", "cargo test", None, Some(r#"{"failed": true}"#));
+        }
+        crate::s3_archive::archive(&conn, "test", 2000).unwrap();
+
+        assert!(detect_patterns(&conn, 3, 168.0).unwrap().is_empty());
+    }
+
     #[test]
     fn below_threshold_returns_empty() {
         let conn = setup_db();
@@ -1082,6 +1473,53 @@ mod tests {
         assert!(patterns[0].normalized.contains("not found"));
     }
 
+    #[test]
+    fn environment_issues_cluster_across_projects() {
+        let conn = setup_db();
+        let fail = |sid: &str, project: &str, ts: i64, class: &str, response: &str| {
+            conn.execute(
+                "INSERT OR IGNORE INTO sessions (id, project, started_at) VALUES (?1, ?2, ?3)",
+                rusqlite::params![sid, project, ts],
+            )
+            .unwrap();
+            conn.execute(
+                "INSERT INTO observations (session_id, timestamp, obs_type, source_event, tool_name, content, metadata, error_class)
+                 VALUES (?1, ?2, 'command', 'PostToolUse', 'Bash', 'docker ps', ?3, ?4)",
+                rusqlite::params![sid, ts, serde_json::json!({"failed": true, "response": response}).to_string(), class],
+            )
+            .unwrap();
+        };
+        let daemon = "Cannot connect to the Docker daemon. Is the docker daemon running? exit code 1";
+        fail("a1", "web", 9_000, "network", daemon);
+        fail("b1", "api", 9_500, "network", daemon);
+        // Same project twice is not environmental; nor is a shared compile error
+        fail("c1", "cli", 9_100, "network", "Could not resolve host: example.com");
+        fail("c2", "cli", 9_200, "network", "Could not resolve host: example.com");
+        fail("a2", "web", 9_300, "compile_error", "error: linker `cc` not found");
+        fail("b2", "api", 9_300, "compile_error", "error: linker `cc` not found");
+        // Outside the window
+        fail("d1", "old", 1_000, "network", "Could not resolve host: example.com");
+
+        let issues = detect_environment_issues(&conn, 2, 3600, 10_000).unwrap();
+        assert_eq!(issues.len(), 1);
+        assert_eq!(issues[0].projects, vec!["api", "web"]);
+        assert_eq!((issues[0].first_seen, issues[0].last_seen), (9_000, 9_500));
+
+        let mut patterns = vec![Pattern {
+            kind: "recurring_error",
+            description: String::new(),
+            normalized: issues[0].signature.clone(),
+            session_count: 3,
+            heat: 50.0,
+            sessions: vec![],
+            example: String::new(),
+        }];
+        separate_environment(&mut patterns, &issues, 168.0, 9_500);
+        assert_eq!(patterns.len(), 1, "the recurring error is reported as environmental instead");
+        assert_eq!((patterns[0].kind, patterns[0].heat), (ENVIRONMENT_ISSUE, 100.0));
+        assert!(patterns[0].description.contains("2 projects: api, web"), "{}", patterns[0].description);
+    }
+
     #[test]
     fn error_patterns_below_threshold_empty() {
         let conn = setup_db();
@@ -1144,6 +1582,36 @@ mod tests {
         assert_eq!(patterns[0].session_count, 3);
     }
 
+    #[test]
+    fn prompt_finds_similar_episode_in_project() {
+        let conn = setup_db();
+        conn.execute_batch(
+            "INSERT INTO sessions (id, project, started_at) VALUES
+                ('abc123456789', 'api', 1000), ('other-proj', 'web', 1000), ('current', 'api', 5000);
+             INSERT INTO work_units (session_id, started_at, intent, summary, outcome) VALUES
+                ('abc123456789', 1000, 'fix flaky login test timeout',
+                 '{\"intent\":\"Stabilized the login test by raising its timeout\"}', 'succeeded'),
+                ('abc123456789', 2000, 'update billing export schema', 'not json', NULL),
+                ('other-proj', 3000, 'fix flaky login test timeout', NULL, NULL),
+                ('current', 5000, 'fix flaky login test timeout', NULL, NULL);",
+        )
+        .unwrap();
+
+        let found = similar_episode(&conn, "api", "current", "Fix the flaky login test timeout again", 0.6)
+            .unwrap()
+            .unwrap();
+        assert_eq!(found.session_id, "abc123456789");
+        assert_eq!(found.summary, "Stabilized the login test by raising its timeout");
+        assert_eq!(found.outcome.as_deref(), Some("succeeded"));
+
+        let billing = similar_episode(&conn, "api", "current", "update the billing export schema", 0.6).unwrap();
+        assert_eq!(billing.unwrap().summary, "update billing export schema", "unparsable summary falls back");
+        assert!(similar_episode(&conn, "api", "current", "write release notes for v2", 0.6).unwrap().is_none());
+        assert!(similar_episode(&conn, "api", "current", "login test", 0.6).unwrap().is_none(), "too few keywords");
+        assert!(similar_episode(&conn, "web", "other-proj", "fix flaky login test timeout", 0.6).unwrap().is_none());
+        assert!(similar_episode(&conn, "api", "current", "fix flaky login test timeout", 0.0).unwrap().is_none());
+    }
+
     #[test]
     fn dissimilar_intents_stay_separate() {
         let conn = setup_db();
@@ -1228,4 +1696,45 @@ mod tests {
         assert!(!confirmed.is_empty(), "should detect confirmed stuck loop");
         assert!(!confirmed[0].1.is_empty(), "should have corroborating evidence");
     }
+
+    #[test]
+    fn findings_rank_by_severity_and_export_sarif() {
+        let conn = setup_db();
+        for sid in ["s1", "s2", "s3"] {
+            conn.execute(
+                "INSERT INTO sessions (id, project, started_at, summary) VALUES (?1, 'test', 1000, ?2)",
+                rusqlite::params![sid, r#"{"intent": "fix cargo test PATH issue"}"#],
+            ).unwrap();
+            insert_obs(&conn, sid, "command", "cargo test", None,
+                Some(r#"{"failed": true, "response": "cargo: command not found"}"#));
+            insert_obs(&conn, sid, "file_read", "read", Some("/repo/src/pool.rs"), None);
+        }
+        let patterns = detect_patterns(&conn, 3, 168.0).unwrap();
+
+        let found = findings(&patterns, &HashMap::new());
+        assert_eq!(found[0].kind, STUCK_LOOP);
+        assert_eq!(found[0].severity, "error");
+        assert!(!found[0].corroborated_by.is_empty());
+        assert_eq!(found.last().unwrap().severity, "note");
+        assert_eq!(count_at_or_above(&found, "error"), 1);
+        assert!(count_at_or_above(&found, "warning") >= 2);
+        assert_eq!(count_at_or_above(&found, "note"), found.len());
+
+        let overrides = HashMap::from([(STUCK_LOOP.to_string(), "note".to_string())]);
+        assert_eq!(count_at_or_above(&findings(&patterns, &overrides), "error"), 0);
+
+        let json = json_report(&found, 3, 168.0);
+        assert_eq!(json["findings"].as_array().unwrap().len(), found.len());
+
+        let sarif = sarif_report(&found, Some(Path::new("/repo")));
+        assert_eq!(sarif["version"], "2.1.0");
+        let run = &sarif["runs"][0];
+        assert_eq!(run["tool"]["driver"]["rules"].as_array().unwrap().len(), PATTERN_KINDS.len());
+        let results = run["results"].as_array().unwrap();
+        assert_eq!(results.len(), found.len());
+        assert_eq!(results[0]["level"], "error");
+        let read = results.iter().find(|r| r["ruleId"] == "unresolved_read").unwrap();
+        assert_eq!(read["locations"][0]["physicalLocation"]["artifactLocation"]["uri"], "src/pool.rs");
+        assert_eq!(read["partialFingerprints"]["nmemPattern/v1"], pattern_id("unresolved_read", "/repo/src/pool.rs"));
+    }
 }
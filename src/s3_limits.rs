@@ -0,0 +1,167 @@
+//! S3 Control — default result limits.
+//!
+//! `search` returns 20 results and `recent_context` 30 when the caller
+//! passes no `limit`. That covers a quiet project's last few days but only a
+//! few hours of a busy one. With `[serve.limits] adaptive` on (the default),
+//! each default is raised to cover the project's last `window_days` distinct
+//! days with activity: `recent_context` to the number of distinct files and
+//! observations it would rank in that window, `search` by one result per
+//! `SEARCH_OBS_PER_RESULT` observations there. Both stay within a cap, and
+//! never drop below the fixed defaults. A fixed `search` or
+//! `recent_context` in config replaces the adaptive value. Responses report
+//! the limit chosen whenever it isn't the fixed default.
+
+use crate::s5_config::LimitsConfig;
+use crate::NmemError;
+use rusqlite::{Connection, params};
+use serde::Serialize;
+
+/// Fixed defaults, and the caps adaptive values stay under.
+pub const SEARCH_DEFAULT: i64 = 20;
+pub const SEARCH_CAP: i64 = 50;
+pub const RECENT_DEFAULT: i64 = 30;
+pub const RECENT_CAP: i64 = 100;
+/// Observations in the window per extra search result.
+const SEARCH_OBS_PER_RESULT: i64 = 25;
+/// Days searched back for the window's working days.
+const LOOKBACK_DAYS: i64 = 90;
+
+#[derive(Debug, Serialize, PartialEq)]
+pub struct EffectiveLimit {
+    pub limit: i64,
+    /// `default`, `adaptive`, or `config`.
+    pub source: &'static str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub window_days: Option<u32>,
+    /// Observations in the window the adaptive value was derived from.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub window_observations: Option<i64>,
+}
+
+impl EffectiveLimit {
+    fn fixed(limit: i64, source: &'static str) -> Self {
+        Self { limit, source, window_days: None, window_observations: None }
+    }
+
+    /// Whether a response should say which limit it used.
+    pub fn is_reported(&self) -> bool {
+        self.source != "default"
+    }
+}
+
+/// Observations, and distinct `recent_context` keys (file, else
+/// observation), since the start of the `days`-th most recent day with
+/// activity in `project` (all projects when `None`).
+fn window(conn: &Connection, project: Option<&str>, days: u32, now: i64) -> Result<(i64, i64), NmemError> {
+    let start: Option<String> = conn.query_row(
+        "SELECT MIN(d) FROM (
+             SELECT DISTINCT date(o.timestamp, 'unixepoch') AS d
+             FROM observations o JOIN sessions s ON s.id = o.session_id
+             WHERE (?1 IS NULL OR s.project = ?1) AND o.timestamp > ?3
+             ORDER BY d DESC LIMIT ?2)",
+        params![project, days, now - LOOKBACK_DAYS * 86400],
+        |r| r.get(0),
+    )?;
+    let Some(start) = start else {
+        return Ok((0, 0));
+    };
+    Ok(conn.query_row(
+        "SELECT COUNT(*), COUNT(DISTINCT COALESCE(o.file_path, CAST(o.id AS TEXT)))
+         FROM observations o JOIN sessions s ON s.id = o.session_id
+         WHERE (?1 IS NULL OR s.project = ?1) AND o.timestamp >= unixepoch(?2)",
+        params![project, start],
+        |r| Ok((r.get(0)?, r.get(1)?)),
+    )?)
+}
+
+fn resolve(
+    conn: &Connection,
+    config: &LimitsConfig,
+    configured: Option<i64>,
+    default: i64,
+    project: Option<&str>,
+    now: i64,
+    derive: impl Fn(i64, i64) -> i64,
+) -> Result<EffectiveLimit, NmemError> {
+    if let Some(limit) = configured {
+        return Ok(EffectiveLimit::fixed(limit, "config"));
+    }
+    if !config.adaptive {
+        return Ok(EffectiveLimit::fixed(default, "default"));
+    }
+    let (observations, keys) = window(conn, project, config.window_days, now)?;
+    let limit = derive(observations, keys);
+    if limit <= default {
+        return Ok(EffectiveLimit::fixed(default, "default"));
+    }
+    Ok(EffectiveLimit {
+        limit,
+        source: "adaptive",
+        window_days: Some(config.window_days),
+        window_observations: Some(observations),
+    })
+}
+
+/// Default `search` limit for `project`.
+pub fn search_limit(conn: &Connection, config: &LimitsConfig, project: Option<&str>, now: i64) -> Result<EffectiveLimit, NmemError> {
+    resolve(conn, config, config.search, SEARCH_DEFAULT, project, now, |obs, _| {
+        (obs / SEARCH_OBS_PER_RESULT).clamp(SEARCH_DEFAULT, SEARCH_CAP)
+    })
+}
+
+/// Default `recent_context` limit for `project`.
+pub fn recent_context_limit(conn: &Connection, config: &LimitsConfig, project: Option<&str>, now: i64) -> Result<EffectiveLimit, NmemError> {
+    resolve(conn, config, config.recent_context, RECENT_DEFAULT, project, now, |_, keys| {
+        keys.clamp(RECENT_DEFAULT, RECENT_CAP)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn busy_projects_get_larger_defaults() {
+        let mut conn = Connection::open_in_memory().unwrap();
+        crate::schema::MIGRATIONS.to_latest(&mut conn).unwrap();
+        let day = 86400;
+        let now = 1_000 * day;
+        conn.execute_batch(
+            "INSERT INTO sessions (id, project, started_at) VALUES ('busy', 'busy', 0), ('quiet', 'quiet', 0);",
+        )
+        .unwrap();
+        // busy: 400 edits of 60 files on each of the last 3 working days,
+        // with a gap, and more a month ago outside the window
+        for (offset, n) in [(0, 400), (2, 400), (5, 400), (30, 900)] {
+            for i in 0..n {
+                conn.execute(
+                    "INSERT INTO observations (session_id, timestamp, obs_type, source_event, file_path, content)
+                     VALUES ('busy', ?1, 'file_edit', 'PostToolUse', ?2, 'edit')",
+                    params![now - offset * day - 3600 - i, format!("/src/f{}.rs", i % 60 + offset)],
+                )
+                .unwrap();
+            }
+        }
+        conn.execute(
+            "INSERT INTO observations (session_id, timestamp, obs_type, source_event, content)
+             VALUES ('quiet', ?1, 'command', 'PostToolUse', 'ls')",
+            params![now - day],
+        )
+        .unwrap();
+
+        let config = LimitsConfig::default();
+        let search = search_limit(&conn, &config, Some("busy"), now).unwrap();
+        assert_eq!((search.limit, search.source, search.window_observations), (48, "adaptive", Some(1200)));
+        let recent = recent_context_limit(&conn, &config, Some("busy"), now).unwrap();
+        assert_eq!((recent.limit, recent.source), (65, "adaptive"));
+
+        let quiet = search_limit(&conn, &config, Some("quiet"), now).unwrap();
+        assert_eq!(quiet, EffectiveLimit::fixed(SEARCH_DEFAULT, "default"));
+        assert!(!quiet.is_reported());
+
+        let fixed = LimitsConfig { recent_context: Some(12), ..LimitsConfig::default() };
+        assert_eq!(recent_context_limit(&conn, &fixed, Some("busy"), now).unwrap(), EffectiveLimit::fixed(12, "config"));
+        let off = LimitsConfig { adaptive: false, ..LimitsConfig::default() };
+        assert_eq!(search_limit(&conn, &off, Some("busy"), now).unwrap().limit, SEARCH_DEFAULT);
+    }
+}
@@ -0,0 +1,261 @@
+//! S3 Control — LLM token accounting and the monthly budget.
+//!
+//! Every generation call (session summaries, episode narratives, topics,
+//! rollups) records its token counts in `llm_usage`, and its latency as
+//! `nmem_llm_duration_seconds` when metrics are exported. When
+//! `[summarization] monthly_token_budget` is set and this calendar month's
+//! total reaches it, episode narratives, topic consolidation, and rollups
+//! pause; session summaries keep running since retention sweeps wait on them.
+
+#[cfg(feature = "cli")]
+use crate::cli::StatsArgs;
+use crate::db::open_db;
+use crate::s1_4_inference::GenerateResult;
+use crate::s5_config::{SummarizationConfig, load_config};
+use crate::NmemError;
+use rusqlite::{Connection, params};
+use serde::Serialize;
+use std::path::Path;
+
+pub const SESSION_SUMMARY: &str = "session_summary";
+pub const EPISODE_NARRATIVE: &str = "episode_narrative";
+pub const TOPIC_CONSOLIDATION: &str = "topic_consolidation";
+pub const ROLLUP: &str = "rollup";
+
+#[derive(Debug, Serialize, PartialEq)]
+pub struct MonthUsage {
+    pub month: String,
+    pub task: String,
+    pub calls: i64,
+    pub prompt_tokens: i64,
+    pub generated_tokens: i64,
+    pub total_ms: i64,
+}
+
+#[derive(Debug, Serialize)]
+pub struct LlmReport {
+    /// Tokens (prompt + generated) used since the start of this UTC month.
+    pub month_to_date: i64,
+    pub monthly_budget: Option<u64>,
+    pub narratives_paused: bool,
+    pub months: Vec<MonthUsage>,
+}
+
+/// Record one generation call. `work_unit_first_prompt` identifies the
+/// episode within `session_id` for narratives.
+pub fn record(
+    conn: &Connection,
+    task: &str,
+    session_id: &str,
+    work_unit_first_prompt: Option<i64>,
+    result: &GenerateResult,
+    now: i64,
+) -> Result<(), NmemError> {
+    conn.execute(
+        "INSERT INTO llm_usage (task, session_id, work_unit_id, prompt_tokens, generated_tokens,
+                                total_ms, created_at)
+         VALUES (?1, ?2,
+                 (SELECT id FROM work_units WHERE session_id = ?2 AND first_prompt_id = ?3),
+                 ?4, ?5, ?6, ?7)",
+        params![
+            task,
+            session_id,
+            work_unit_first_prompt,
+            result.prompt_tokens as i64,
+            result.generated_tokens as i64,
+            result.total_ms as i64,
+            now,
+        ],
+    )?;
+    #[cfg(feature = "cli")]
+    opentelemetry::global::meter("nmem")
+        .f64_histogram("nmem_llm_duration_seconds")
+        .build()
+        .record(result.total_ms as f64 / 1000.0, &[opentelemetry::KeyValue::new("task", task.to_string())]);
+    Ok(())
+}
+
+/// Tokens used since the start of the UTC calendar month containing `now`.
+pub fn month_to_date(conn: &Connection, now: i64) -> Result<i64, NmemError> {
+    Ok(conn.query_row(
+        "SELECT COALESCE(SUM(prompt_tokens + generated_tokens), 0) FROM llm_usage
+         WHERE created_at >= CAST(strftime('%s', ?1, 'unixepoch', 'start of month') AS INTEGER)",
+        params![now],
+        |r| r.get(0),
+    )?)
+}
+
+/// Whether this month's usage has reached the configured budget. Without a
+/// budget nothing is ever paused.
+pub fn over_budget(conn: &Connection, config: &SummarizationConfig, now: i64) -> Result<bool, NmemError> {
+    if config.monthly_token_budget == 0 {
+        return Ok(false);
+    }
+    Ok(month_to_date(conn, now)? >= config.monthly_token_budget as i64)
+}
+
+/// Per-month, per-task totals for the last `months` months, newest first.
+pub fn monthly_usage(conn: &Connection, now: i64, months: i64) -> Result<Vec<MonthUsage>, NmemError> {
+    let mut stmt = conn.prepare(
+        "SELECT strftime('%Y-%m', created_at, 'unixepoch') AS month, task, COUNT(*),
+                SUM(prompt_tokens), SUM(generated_tokens), SUM(total_ms)
+         FROM llm_usage
+         WHERE created_at >= CAST(strftime('%s', ?1, 'unixepoch', 'start of month',
+                                           printf('-%d months', ?2 - 1)) AS INTEGER)
+         GROUP BY month, task
+         ORDER BY month DESC, task",
+    )?;
+    let rows = stmt
+        .query_map(params![now, months], |r| {
+            Ok(MonthUsage {
+                month: r.get(0)?,
+                task: r.get(1)?,
+                calls: r.get(2)?,
+                prompt_tokens: r.get(3)?,
+                generated_tokens: r.get(4)?,
+                total_ms: r.get(5)?,
+            })
+        })?
+        .collect::<Result<_, _>>()?;
+    Ok(rows)
+}
+
+pub fn llm_report(
+    conn: &Connection,
+    config: &SummarizationConfig,
+    now: i64,
+    months: i64,
+) -> Result<LlmReport, NmemError> {
+    Ok(LlmReport {
+        month_to_date: month_to_date(conn, now)?,
+        monthly_budget: (config.monthly_token_budget > 0).then_some(config.monthly_token_budget),
+        narratives_paused: over_budget(conn, config, now)?,
+        months: monthly_usage(conn, now, months)?,
+    })
+}
+
+#[cfg(feature = "cli")]
+pub fn handle_stats(db_path: &Path, args: &StatsArgs) -> Result<(), NmemError> {
+    let now = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs() as i64;
+    let config = load_config().unwrap_or_default();
+    // Writable open runs migrations, so llm_usage exists on older databases
+    let conn = open_db(db_path)?;
+
+    if args.dirs {
+        let under = match args.cwd.as_deref() {
+            Some(dir) => Some(
+                crate::s1_extract::cwd_filter(dir)
+                    .ok_or_else(|| NmemError::Config(format!("invalid --cwd: {dir:?}")))?,
+            ),
+            None => None,
+        };
+        let dirs = crate::s3_capture::by_directory(&conn, under.as_deref())?;
+        println!("{}", serde_json::to_string_pretty(&dirs)?);
+        return Ok(());
+    }
+
+    if args.models {
+        let models = crate::s3_model::by_model(&conn, args.project.as_deref(), args.model.as_deref())?;
+        println!("{}", serde_json::to_string_pretty(&models)?);
+        return Ok(());
+    }
+
+    if args.near_duplicates {
+        let report = crate::s1_minhash::near_duplicates(&conn, args.min_similarity.clamp(0.0, 1.0), 10)?;
+        println!("{}", serde_json::to_string_pretty(&report)?);
+        return Ok(());
+    }
+
+    if args.confidence {
+        let report = crate::s2_inference::confidence_report(&conn, crate::s2_inference::LOW_CONFIDENCE)?;
+        println!("{}", serde_json::to_string_pretty(&report)?);
+        return Ok(());
+    }
+
+    if args.capture {
+        let alert_bytes = config.record.session_alert_mb * 1024 * 1024;
+        let sessions = crate::s3_capture::largest_sessions(&conn, 50, alert_bytes)?;
+        println!("{}", serde_json::to_string_pretty(&sessions)?);
+        return Ok(());
+    }
+
+    let report = llm_report(&conn, &config.summarization, now, args.months.clamp(1, 120))?;
+    println!("{}", serde_json::to_string_pretty(&report)?);
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // 2026-10-16T12:00:00Z
+    const NOW: i64 = 1_792_152_000;
+
+    fn setup() -> Connection {
+        let mut conn = Connection::open_in_memory().unwrap();
+        crate::schema::MIGRATIONS.to_latest(&mut conn).unwrap();
+        conn.execute_batch(
+            "INSERT INTO sessions (id, project, started_at) VALUES ('s1', 'p', 100);
+             INSERT INTO work_units (session_id, started_at, ended_at, intent, first_prompt_id,
+                                     last_prompt_id, obs_count)
+                 VALUES ('s1', 100, 200, 'fix auth', 7, 9, 5);",
+        )
+        .unwrap();
+        conn
+    }
+
+    fn result(prompt: usize, generated: usize) -> GenerateResult {
+        GenerateResult {
+            text: String::new(),
+            total_ms: 1000,
+            prompt_tokens: prompt,
+            generated_tokens: generated,
+        }
+    }
+
+    #[test]
+    fn usage_is_attributed_and_summed_per_month() {
+        let conn = setup();
+        // Previous month
+        record(&conn, SESSION_SUMMARY, "s1", None, &result(5000, 500), NOW - 40 * 86400).unwrap();
+        record(&conn, SESSION_SUMMARY, "s1", None, &result(800, 200), NOW).unwrap();
+        record(&conn, EPISODE_NARRATIVE, "s1", Some(7), &result(300, 100), NOW).unwrap();
+
+        let wu: Option<i64> = conn
+            .query_row(
+                "SELECT work_unit_id FROM llm_usage WHERE task = 'episode_narrative'",
+                [],
+                |r| r.get(0),
+            )
+            .unwrap();
+        assert!(wu.is_some());
+
+        assert_eq!(month_to_date(&conn, NOW).unwrap(), 1400);
+
+        let months = monthly_usage(&conn, NOW, 3).unwrap();
+        assert_eq!(months.len(), 3);
+        assert_eq!(months[0].month, "2026-10");
+        assert_eq!(months[0].task, EPISODE_NARRATIVE);
+        assert_eq!(months[2].month, "2026-09");
+        assert_eq!(months[2].prompt_tokens, 5000);
+        assert_eq!(monthly_usage(&conn, NOW, 1).unwrap().len(), 2);
+    }
+
+    #[test]
+    fn budget_pauses_once_reached() {
+        let conn = setup();
+        let mut config = SummarizationConfig::default();
+        record(&conn, SESSION_SUMMARY, "s1", None, &result(900, 100), NOW).unwrap();
+        assert!(!over_budget(&conn, &config, NOW).unwrap(), "no budget, never paused");
+
+        config.monthly_token_budget = 2000;
+        assert!(!over_budget(&conn, &config, NOW).unwrap());
+        record(&conn, SESSION_SUMMARY, "s1", None, &result(900, 100), NOW).unwrap();
+        assert!(over_budget(&conn, &config, NOW).unwrap());
+        // A new month starts fresh
+        assert!(!over_budget(&conn, &config, NOW + 20 * 86400).unwrap());
+    }
+}
@@ -1,58 +1,107 @@
+#[cfg(feature = "cli")]
 use crate::cli::MaintainArgs;
-use crate::s5_config::load_config;
-use crate::s3_sweep::run_sweep;
+use crate::s5_config::{NmemConfig, load_config};
+use crate::s3_schedule::defer_reason;
+use crate::s3_sweep::{enforce_project_quotas, in_sweep_window, run_sweep};
 use crate::db::open_db;
 use crate::NmemError;
 use std::path::Path;
 
+#[cfg(feature = "cli")]
 pub fn handle_maintain(db_path: &Path, args: &MaintainArgs) -> Result<(), NmemError> {
-    // Session-scoped maintenance: episodes → summarize → sweep → checkpoint
+    // Session-scoped maintenance: episodes → auto-pin → summarize → sweep → checkpoint
     if let Some(ref session_id) = args.session {
-        return handle_session_maintain(db_path, session_id);
+        return handle_session_maintain(db_path, session_id, args.now);
+    }
+    // Before open_db, which would migrate the store forward
+    if let Some(version) = args.rollback_to {
+        return crate::s3_migrate::handle_rollback(db_path, version);
+    }
+    if args.advise_indexes {
+        return crate::s3_slow_query::handle_advise(db_path, args);
     }
 
     let conn = open_db(db_path)?;
+    let metrics = load_config().unwrap_or_default().metrics;
+    let deferred = if args.now { None } else { defer_reason(&load_config().unwrap_or_default().schedule, now_unix()) };
+    let exporter = crate::metrics::Exporter::start(&metrics);
+    let meter = opentelemetry::global::meter("nmem");
 
     let size_before = std::fs::metadata(db_path)?.len();
 
+    let drained = crate::s3_pending::drain(&conn, None)?;
+    log::info!("pending work — {drained} completed");
+
+    // Sign observations recorded before MinHash signatures existed
+    let signed = crate::s1_minhash::backfill(&conn, crate::s1_minhash::BACKFILL_BATCH)?;
+    if signed > 0 {
+        log::info!("minhash — {signed} observations signed");
+    }
+
     // Incremental vacuum — reclaim freed pages
     let free_before: i64 = conn.pragma_query_value(None, "freelist_count", |r| r.get(0))?;
     conn.pragma_update(None, "incremental_vacuum", 0)?;
     let free_after: i64 = conn.pragma_query_value(None, "freelist_count", |r| r.get(0))?;
     let reclaimed = free_before - free_after;
     log::info!("incremental vacuum — reclaimed {reclaimed} pages");
+    if exporter.is_some() {
+        let page_size: i64 = conn.pragma_query_value(None, "page_size", |r| r.get(0))?;
+        meter
+            .u64_counter("nmem_vacuum_reclaimed_bytes_total")
+            .build()
+            .add((reclaimed * page_size).max(0) as u64, &[]);
+    }
 
     // WAL checkpoint (TRUNCATE folds WAL into main file, then deletes WAL)
     conn.execute_batch("PRAGMA wal_checkpoint(TRUNCATE)")?;
     log::info!("WAL checkpoint — ok");
 
+    // Optional FTS rebuild — first, so a corrupt index doesn't fail the check
+    if args.rebuild_fts {
+        rebuild_fts(&conn)?;
+        log::info!("FTS rebuild (observations, chunks, prompts) — ok");
+    }
+
     // FTS integrity check
     conn.execute_batch(
         "INSERT INTO observations_fts(observations_fts) VALUES('integrity-check')",
     )?;
     log::info!("FTS integrity (observations) — ok");
 
+    conn.execute_batch(
+        "INSERT INTO observation_chunks_fts(observation_chunks_fts) VALUES('integrity-check')",
+    )?;
+    log::info!("FTS integrity (observation chunks) — ok");
+
     conn.execute_batch("INSERT INTO prompts_fts(prompts_fts) VALUES('integrity-check')")?;
     log::info!("FTS integrity (prompts) — ok");
 
-    // Optional FTS rebuild
-    if args.rebuild_fts {
-        conn.execute_batch(
-            "INSERT INTO observations_fts(observations_fts) VALUES('rebuild')",
-        )?;
-        log::info!("FTS rebuild (observations) — ok");
-
-        conn.execute_batch("INSERT INTO prompts_fts(prompts_fts) VALUES('rebuild')")?;
-        log::info!("FTS rebuild (prompts) — ok");
+    // Heavy steps wait for an allowed window; deferred Stop-time
+    // maintenance catches up when they may run
+    if let Some(ref reason) = deferred {
+        if args.sweep || args.resummarize || args.catch_up {
+            log::info!("sweep and summarization deferred ({reason}) — pass --now to override");
+        }
+    } else {
+        let config = load_config().unwrap_or_default();
+        for sid in crate::s3_schedule::take_deferred(&conn)? {
+            log::info!("running deferred maintenance for {sid}");
+            if let Err(e) = maintain_session(db_path, &sid, &config) {
+                log::warn!("{sid}: deferred maintenance failed: {e}");
+            }
+        }
     }
 
     // Retention sweep
-    if args.sweep {
+    if args.sweep && deferred.is_none() {
         let config = load_config().unwrap_or_default();
         if !config.retention.enabled {
             log::info!("retention sweep skipped (not enabled in config)");
+        } else if !args.now && !in_sweep_window(&config.retention, now_unix())? {
+            log::info!("retention sweep skipped (outside sweep_window) — pass --now to override");
         } else {
-            let result = run_sweep(&conn, &config.retention)?;
+            let key = crate::s3_deletions::signing_key(&config);
+            let result = run_sweep(&conn, &config.retention, args.force, key.as_deref())?;
             if result.deleted > 0 {
                 for (obs_type, count) in &result.by_type {
                     log::info!("sweep — {obs_type}: {count} deleted");
@@ -62,11 +111,41 @@ pub fn handle_maintain(db_path: &Path, args: &MaintainArgs) -> Result<(), NmemEr
             } else {
                 log::info!("sweep — nothing to delete");
             }
+            if result.snapshots_deleted > 0 {
+                log::info!("sweep — {} file snapshots expired", result.snapshots_deleted);
+            }
+            if exporter.is_some() {
+                crate::metrics::record_sweep(&meter, &result);
+            }
+        }
+
+        for q in enforce_project_quotas(&conn, &config)? {
+            log::info!(
+                "quota — {}: {} evicted, {:.1} → {:.1} MB (limit {:.0} MB)",
+                q.project,
+                q.evicted,
+                q.before_bytes as f64 / 1048576.0,
+                q.after_bytes as f64 / 1048576.0,
+                q.quota_bytes as f64 / 1048576.0
+            );
+            if q.after_bytes > q.quota_bytes {
+                log::warn!("quota — {}: still over limit, nothing else evictable", q.project);
+            }
+        }
+
+        let now = now_unix();
+        let pruned = crate::s3_audit::prune(&conn, config.query_log.retention_days, now)?;
+        if pruned > 0 {
+            log::info!("sweep — query_log: {pruned} rows pruned");
+        }
+        let pruned = crate::s1_changes::prune(&conn, now - crate::s1_changes::RETENTION_DAYS * 86400)?;
+        if pruned > 0 {
+            log::info!("sweep — changelog: {pruned} entries pruned");
         }
     }
 
     // Resummarize all sessions
-    if args.resummarize {
+    if args.resummarize && deferred.is_none() {
         let config = load_config().unwrap_or_default();
         if !config.summarization.enabled {
             log::info!("resummarize skipped (summarization not enabled)");
@@ -76,7 +155,7 @@ pub fn handle_maintain(db_path: &Path, args: &MaintainArgs) -> Result<(), NmemEr
     }
 
     // Catch up: summarize missed sessions
-    if args.catch_up {
+    if args.catch_up && deferred.is_none() {
         let config = load_config().unwrap_or_default();
         if !config.summarization.enabled {
             log::info!("catch-up skipped (summarization not enabled)");
@@ -85,8 +164,28 @@ pub fn handle_maintain(db_path: &Path, args: &MaintainArgs) -> Result<(), NmemEr
         }
     }
 
+    // Topic consolidation rides the periodic run when enabled — non-fatal
+    let config = load_config().unwrap_or_default();
+    if config.consolidate.enabled && deferred.is_none() {
+        let now = now_unix();
+        match crate::s4_consolidate::consolidate(&conn, None, &config.consolidate, &config.summarization, now) {
+            Ok(done) => log::info!("consolidate — {} topics written", done.len()),
+            Err(e) => log::warn!("consolidation failed (non-fatal): {e}"),
+        }
+    }
+    // After consolidation: both spend the same monthly token budget
+    if config.rollup.enabled && deferred.is_none() {
+        match crate::s4_rollup::rollup(&conn, None, &config.rollup, &config.summarization, now_unix()) {
+            Ok(done) => log::info!("rollup — {} periods written", done.len()),
+            Err(e) => log::warn!("rollups failed (non-fatal): {e}"),
+        }
+    }
+
     let size_after = std::fs::metadata(db_path)?.len();
     log::info!("database: {} → {}", fmt_size(size_before), fmt_size(size_after));
+    if exporter.is_some() {
+        crate::metrics::export_db_stats(&conn, db_path, &config);
+    }
 
     Ok(())
 }
@@ -106,12 +205,13 @@ fn resummarize_all(
     log::info!("resummarizing {total} sessions...");
 
     let inference_params = crate::s1_4_inference::params_from_config(config)?;
+    let _slot = crate::s3_schedule::llm_slot()?;
     let engine = crate::s1_4_inference::InferenceEngine::new(inference_params)?;
 
     let mut success = 0u64;
     let mut failed = 0u64;
     for (i, sid) in session_ids.iter().enumerate() {
-        match crate::s1_4_summarize::summarize_session_with_engine(conn, sid, &engine) {
+        match crate::s1_4_summarize::summarize_session_with_engine(conn, sid, &engine, config) {
             Ok(()) => {
                 success += 1;
                 eprint!("\r[{}/{}] {} ok, {} failed", i + 1, total, success, failed);
@@ -183,12 +283,13 @@ fn catch_up_unsummarized(
     log::info!("catch-up — {total} sessions to summarize");
 
     let inference_params = crate::s1_4_inference::params_from_config(config)?;
+    let _slot = crate::s3_schedule::llm_slot()?;
     let engine = crate::s1_4_inference::InferenceEngine::new(inference_params)?;
 
     let mut success = 0u64;
     let mut failed = 0u64;
     for (i, sid) in session_ids.iter().enumerate() {
-        match crate::s1_4_summarize::summarize_session_with_engine(conn, sid, &engine) {
+        match crate::s1_4_summarize::summarize_session_with_engine(conn, sid, &engine, config) {
             Ok(()) => {
                 success += 1;
                 eprint!("\r[{}/{}] {} ok, {} failed", i + 1, total, success, failed);
@@ -206,9 +307,49 @@ fn catch_up_unsummarized(
     Ok(())
 }
 
-fn handle_session_maintain(db_path: &Path, session_id: &str) -> Result<(), NmemError> {
-    let conn = open_db(db_path)?;
+/// Post-session maintenance as Stop spawns it: queued for later in quiet
+/// hours or on battery (unless `now`), otherwise run for this session and
+/// any deferred earlier.
+fn handle_session_maintain(db_path: &Path, session_id: &str, now: bool) -> Result<(), NmemError> {
     let config = load_config().unwrap_or_default();
+    if !now && let Some(reason) = defer_reason(&config.schedule, now_unix()) {
+        let conn = open_db(db_path)?;
+        crate::s3_schedule::defer_session(&conn, session_id, now_unix())?;
+        log::info!("maintenance deferred ({reason})");
+        return Ok(());
+    }
+    maintain_session(db_path, session_id, &config)?;
+    let earlier = crate::s3_schedule::take_deferred(&open_db(db_path)?)?;
+    for sid in earlier.iter().filter(|s| *s != session_id) {
+        log::info!("running deferred maintenance for {sid}");
+        if let Err(e) = maintain_session(db_path, sid, &config) {
+            log::warn!("{sid}: deferred maintenance failed: {e}");
+        }
+    }
+    Ok(())
+}
+
+fn now_unix() -> i64 {
+    std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs() as i64
+}
+
+/// Post-session maintenance — what Stop spawns in the background.
+pub fn maintain_session(db_path: &Path, session_id: &str, config: &NmemConfig) -> Result<(), NmemError> {
+    let conn = open_db(db_path)?;
+    // Flushes sweep, LLM latency, and store gauges on return
+    #[cfg(feature = "cli")]
+    let exporter = crate::metrics::Exporter::start(&config.metrics);
+
+    // Finish work the hooks spilled past their latency budget — episodes
+    // read the phase labels it fills in. Non-fatal.
+    match crate::s3_pending::drain(&conn, None) {
+        Ok(n) if n > 0 => log::info!("completed {n} pending items"),
+        Err(e) => log::warn!("pending work failed (non-fatal): {e}"),
+        _ => {}
+    }
 
     // Detect episodes — non-fatal
     match crate::s4_memory::detect_and_narrate_episodes(&conn, session_id, &config.summarization) {
@@ -217,31 +358,97 @@ fn handle_session_maintain(db_path: &Path, session_id: &str) -> Result<(), NmemE
         _ => {}
     }
 
+    // Auto-pin fixes that ended friction episodes — needs episodes, non-fatal
+    match crate::s3_autopin::evaluate_session(&conn, &config.auto_pin, session_id) {
+        Ok(n) if n > 0 => log::info!("auto-pinned {n} friction fixes"),
+        Err(e) => log::warn!("auto-pin failed (non-fatal): {e}"),
+        _ => {}
+    }
+
     // Summarize session — non-fatal
     match crate::s1_4_summarize::summarize_session(&conn, session_id, &config.summarization) {
         Ok(()) => log::info!("session summarized"),
         Err(e) => log::warn!("summarization failed (non-fatal): {e}"),
     }
 
-    // Retention sweep — non-fatal
-    if config.retention.enabled {
-        match run_sweep(&conn, &config.retention) {
-            Ok(r) if r.deleted > 0 => {
-                log::info!("sweep deleted {} expired observations", r.deleted);
+    // Label episode outcomes — reads the summary's completed items, non-fatal
+    if let Err(e) = crate::s4_outcome::label_session(&conn, session_id) {
+        log::warn!("episode outcomes failed (non-fatal): {e}");
+    }
+
+    // Retention sweep — non-fatal; outside `sweep_window` the expired rows
+    // wait for the next maintenance run inside it
+    let sweep_now = in_sweep_window(&config.retention, now_unix()).unwrap_or_else(|e| {
+        log::warn!("{e}");
+        false
+    });
+    if config.retention.enabled && sweep_now {
+        match run_sweep(&conn, &config.retention, false, crate::s3_deletions::signing_key(config).as_deref()) {
+            Ok(r) => {
+                if r.deleted > 0 {
+                    log::info!("sweep deleted {} expired observations", r.deleted);
+                }
+                #[cfg(feature = "cli")]
+                if exporter.is_some() {
+                    crate::metrics::record_sweep(&opentelemetry::global::meter("nmem"), &r);
+                }
             }
             Err(e) => log::warn!("sweep error (non-fatal): {e}"),
-            _ => {}
         }
     }
+    match enforce_project_quotas(&conn, config) {
+        Ok(results) => {
+            for q in results.iter().filter(|q| q.evicted > 0) {
+                log::info!("quota evicted {} observations from {}", q.evicted, q.project);
+            }
+        }
+        Err(e) => log::warn!("quota error (non-fatal): {e}"),
+    }
+
+    // Hooks only ever add to the quarantine store; expiry happens here
+    if let Err(e) = crate::s5_quarantine::purge_expired_at(db_path, now_unix()) {
+        log::warn!("quarantine purge failed (non-fatal): {e}");
+    }
 
     // WAL checkpoint
     if let Err(e) = conn.execute_batch("PRAGMA wal_checkpoint(TRUNCATE)") {
         log::warn!("WAL checkpoint failed (non-fatal): {e}");
     }
 
+    #[cfg(feature = "cli")]
+    if exporter.is_some() {
+        crate::metrics::export_db_stats(&conn, db_path, config);
+    }
+
     Ok(())
 }
 
+/// Rebuild the FTS indexes from their content tables.
+pub fn rebuild_fts(conn: &rusqlite::Connection) -> Result<(), NmemError> {
+    conn.execute_batch(
+        "INSERT INTO observations_fts(observations_fts) VALUES('rebuild');
+         INSERT INTO observation_chunks_fts(observation_chunks_fts) VALUES('rebuild');
+         INSERT INTO prompts_fts(prompts_fts) VALUES('rebuild');",
+    )?;
+    Ok(())
+}
+
+/// Run `nmem maintain --rebuild-fts` as a detached background process —
+/// search calls this when it finds the index unreadable.
+pub fn spawn_fts_repair(db_path: &Path) {
+    let Ok(exe) = std::env::current_exe() else {
+        return;
+    };
+    let mut cmd = std::process::Command::new(exe);
+    cmd.arg("maintain").arg("--rebuild-fts").arg("--db").arg(db_path);
+    cmd.stdin(std::process::Stdio::null())
+        .stdout(std::process::Stdio::null())
+        .stderr(std::process::Stdio::null());
+    if let Err(e) = cmd.spawn() {
+        log::warn!("FTS repair not started: {e}");
+    }
+}
+
 fn fmt_size(bytes: u64) -> String {
     if bytes < 1024 {
         format!("{bytes} B")
@@ -0,0 +1,286 @@
+//! S3 Control — schema migration safety.
+//!
+//! rusqlite_migration only runs forward, so `open_db` migrates through
+//! [`migrate`]: before applying pending migrations to an existing store it
+//! snapshots the database with `VACUUM INTO` to `backups/` beside it
+//! (`{stem}-v{version}-{unix}.db`), keeping the newest `[migration]
+//! keep_backups`. When a pending version is in `schema::RISKY_VERSIONS` the
+//! migrations are first rehearsed on a copy of that snapshot; if the
+//! rehearsal fails the live store is left untouched at its old version.
+//!
+//! `nmem maintain --rollback-to <version>` swaps the live store for the
+//! newest backup taken at that version, snapshotting the current store first
+//! so the rollback can itself be undone. Run it with no other nmem process
+//! holding the database, and follow it with a binary of that schema version —
+//! this one would migrate the store forward again on next open.
+
+use crate::schema::{MIGRATIONS, RISKY_VERSIONS};
+use crate::NmemError;
+use rusqlite::Connection;
+use std::path::{Path, PathBuf};
+
+/// A pre-migration snapshot in the backup directory.
+#[derive(Debug, PartialEq)]
+pub struct Backup {
+    pub path: PathBuf,
+    /// Schema version of the snapshot.
+    pub version: usize,
+    pub taken_at: i64,
+}
+
+fn now_secs() -> i64 {
+    std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs() as i64
+}
+
+/// Schema version of `conn` (the number of migrations applied).
+pub fn schema_version(conn: &Connection) -> Result<usize, NmemError> {
+    let v: i64 = conn.pragma_query_value(None, "user_version", |r| r.get(0))?;
+    Ok(v.max(0) as usize)
+}
+
+/// Directory holding the backups of `db_path`.
+pub fn backup_dir(db_path: &Path) -> PathBuf {
+    db_path.parent().unwrap_or(Path::new(".")).join("backups")
+}
+
+fn backup_prefix(db_path: &Path) -> String {
+    let stem = db_path.file_stem().map(|s| s.to_string_lossy().into_owned()).unwrap_or_else(|| "nmem".into());
+    format!("{stem}-v")
+}
+
+/// Backups of `db_path`, newest first.
+pub fn list_backups(db_path: &Path) -> Result<Vec<Backup>, NmemError> {
+    let dir = backup_dir(db_path);
+    if !dir.is_dir() {
+        return Ok(Vec::new());
+    }
+    let prefix = backup_prefix(db_path);
+    let mut backups = Vec::new();
+    for entry in std::fs::read_dir(&dir)? {
+        let path = entry?.path();
+        let Some(name) = path.file_name().and_then(|n| n.to_str()) else {
+            continue;
+        };
+        let parsed = name
+            .strip_prefix(&prefix)
+            .and_then(|rest| rest.strip_suffix(".db"))
+            .and_then(|rest| rest.split_once('-'))
+            .and_then(|(v, ts)| Some((v.parse().ok()?, ts.parse().ok()?)));
+        if let Some((version, taken_at)) = parsed {
+            backups.push(Backup { path, version, taken_at });
+        }
+    }
+    backups.sort_by(|a, b| b.taken_at.cmp(&a.taken_at).then(b.version.cmp(&a.version)));
+    Ok(backups)
+}
+
+/// Snapshot the database behind `conn` (at schema `version`) into the backup
+/// directory of `db_path`. A snapshot already taken for the same version and
+/// second — by a concurrent hook — is reused.
+pub fn snapshot(conn: &Connection, db_path: &Path, version: usize, now: i64) -> Result<PathBuf, NmemError> {
+    let dir = backup_dir(db_path);
+    if !dir.exists() {
+        std::fs::create_dir_all(&dir)?;
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            std::fs::set_permissions(&dir, std::fs::Permissions::from_mode(0o700))?;
+        }
+    }
+    let path = dir.join(format!("{}{version}-{now}.db", backup_prefix(db_path)));
+    if !path.exists() {
+        conn.execute("VACUUM INTO ?1", [path.to_string_lossy()])?;
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            std::fs::set_permissions(&path, std::fs::Permissions::from_mode(0o600))?;
+        }
+    }
+    Ok(path)
+}
+
+/// Delete all but the newest `keep` backups of `db_path`.
+fn prune(db_path: &Path, keep: usize) -> Result<(), NmemError> {
+    for old in list_backups(db_path)?.into_iter().skip(keep) {
+        std::fs::remove_file(&old.path)?;
+    }
+    Ok(())
+}
+
+/// Open `path` read-write with the store key, without migrating it.
+fn open_unmigrated(path: &Path) -> Result<Connection, NmemError> {
+    let conn = Connection::open(path)?;
+    if let Some(key) = crate::db::load_key() {
+        crate::db::apply_key(&conn, &key)?;
+    }
+    Ok(conn)
+}
+
+/// Open a copy of `backup` and run every pending migration on it.
+fn rehearse(backup: &Path) -> Result<(), NmemError> {
+    let copy = backup.with_extension("rehearsal");
+    std::fs::copy(backup, &copy)?;
+    let result = (|| {
+        let mut conn = open_unmigrated(&copy)?;
+        MIGRATIONS.to_latest(&mut conn)?;
+        Ok(())
+    })();
+    for ext in ["rehearsal", "rehearsal-journal", "rehearsal-wal", "rehearsal-shm"] {
+        let _ = std::fs::remove_file(copy.with_extension(ext));
+    }
+    result
+}
+
+/// Bring `conn` (the store at `db_path`) to the latest schema, snapshotting it
+/// first and rehearsing risky migrations on a copy. New stores migrate
+/// directly.
+pub fn migrate(conn: &mut Connection, db_path: &Path, keep_backups: usize) -> Result<(), NmemError> {
+    let current = schema_version(conn)?;
+    let latest = current as i64 + MIGRATIONS.pending_migrations(conn)? as i64;
+    if current > 0 && (current as i64) < latest {
+        let risky = RISKY_VERSIONS.iter().any(|&v| v > current && v as i64 <= latest);
+        if keep_backups > 0 || risky {
+            let backup = snapshot(conn, db_path, current, now_secs())?;
+            if risky && let Err(e) = rehearse(&backup) {
+                return Err(NmemError::Config(format!(
+                    "migration from schema v{current} failed on a copy, store left unchanged (backup: {}): {e}",
+                    backup.display()
+                )));
+            }
+            prune(db_path, keep_backups)?;
+        }
+    }
+    MIGRATIONS.to_latest(conn)?;
+    Ok(())
+}
+
+/// Replace the store at `db_path` with its newest backup at schema `version`.
+/// The current store is snapshotted first; returns that snapshot's path and
+/// the backup restored.
+pub fn rollback_to(db_path: &Path, version: usize, now: i64) -> Result<(PathBuf, Backup), NmemError> {
+    let backups = list_backups(db_path)?;
+    let Some(pos) = backups.iter().position(|b| b.version == version) else {
+        let mut versions: Vec<usize> = backups.iter().map(|b| b.version).collect();
+        versions.sort_unstable();
+        versions.dedup();
+        let available: Vec<String> = versions.iter().map(|v| format!("v{v}")).collect();
+        return Err(NmemError::Config(format!(
+            "no backup at schema v{version} in {} (available: {})",
+            backup_dir(db_path).display(),
+            if available.is_empty() { "none".into() } else { available.join(", ") }
+        )));
+    };
+    let restore = backups.into_iter().nth(pos).expect("position is in range");
+
+    if !db_path.exists() {
+        return Err(NmemError::Config(format!("database not found: {}", db_path.display())));
+    }
+    // VACUUM INTO needs a writable connection
+    let conn = open_unmigrated(db_path)?;
+    let current = schema_version(&conn)?;
+    let saved = snapshot(&conn, db_path, current, now)?;
+    drop(conn);
+
+    let staging = db_path.with_extension("db-restoring");
+    std::fs::copy(&restore.path, &staging)?;
+    // The snapshot above read through the WAL; a stale one must not be
+    // replayed onto the restored file
+    let _ = std::fs::remove_file(db_path.with_extension("db-wal"));
+    let _ = std::fs::remove_file(db_path.with_extension("db-shm"));
+    std::fs::rename(&staging, db_path)?;
+    Ok((saved, restore))
+}
+
+#[cfg(feature = "cli")]
+pub fn handle_rollback(db_path: &Path, version: usize) -> Result<(), NmemError> {
+    let (saved, restored) = rollback_to(db_path, version, now_secs())?;
+    log::info!(
+        "rolled back to schema v{version} from backup taken {} ({})",
+        crate::timefmt::datetime(restored.taken_at),
+        restored.path.display()
+    );
+    log::info!("previous store saved as {}", saved.display());
+    log::info!("run an nmem build with schema v{version} — this one migrates forward again on next open");
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn store_at(path: &Path, version: usize) -> Connection {
+        let mut conn = Connection::open(path).unwrap();
+        MIGRATIONS.to_version(&mut conn, version).unwrap();
+        conn.execute("INSERT INTO sessions (id, project, started_at) VALUES ('s1', 'p', 1)", []).unwrap();
+        conn
+    }
+
+    #[test]
+    fn migrate_snapshots_rehearses_and_prunes() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let db = dir.path().join("nmem.db");
+        let latest = schema_version(&{
+            let mut c = Connection::open_in_memory().unwrap();
+            MIGRATIONS.to_latest(&mut c).unwrap();
+            c
+        })
+        .unwrap();
+
+        // A fresh store has nothing worth backing up
+        let mut conn = Connection::open(&db).unwrap();
+        migrate(&mut conn, &db, 2).unwrap();
+        assert!(list_backups(&db).unwrap().is_empty());
+        drop(conn);
+        std::fs::remove_file(&db).unwrap();
+
+        // v20 → latest crosses risky versions: snapshot at v20, rehearsed
+        let mut conn = store_at(&db, 20);
+        migrate(&mut conn, &db, 2).unwrap();
+        assert_eq!(schema_version(&conn).unwrap(), latest);
+        let backups = list_backups(&db).unwrap();
+        assert_eq!(backups.len(), 1);
+        assert_eq!(backups[0].version, 20);
+        let snap = Connection::open(&backups[0].path).unwrap();
+        assert_eq!(schema_version(&snap).unwrap(), 20);
+        let sessions: i64 = snap.query_row("SELECT COUNT(*) FROM sessions", [], |r| r.get(0)).unwrap();
+        assert_eq!(sessions, 1);
+        assert!(!backups[0].path.with_extension("rehearsal").exists());
+
+        // Retention keeps the newest
+        for ts in [10, 20, 30] {
+            snapshot(&conn, &db, latest, ts).unwrap();
+        }
+        prune(&db, 2).unwrap();
+        let kept: Vec<i64> = list_backups(&db).unwrap().iter().map(|b| b.taken_at).collect();
+        assert_eq!(kept.len(), 2);
+        assert!(kept.iter().all(|ts| *ts != 10 && *ts != 20));
+    }
+
+    #[test]
+    fn rollback_restores_matching_backup_and_saves_current() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let db = dir.path().join("nmem.db");
+        let mut conn = store_at(&db, 28);
+        migrate(&mut conn, &db, 3).unwrap();
+        conn.execute("INSERT INTO sessions (id, project, started_at) VALUES ('s2', 'p', 2)", []).unwrap();
+        drop(conn);
+
+        let err = rollback_to(&db, 5, 100).unwrap_err();
+        assert!(err.to_string().contains("available: v28"), "{err}");
+
+        let (saved, restored) = rollback_to(&db, 28, 100).unwrap();
+        assert_eq!(restored.version, 28);
+        let conn = Connection::open(&db).unwrap();
+        assert_eq!(schema_version(&conn).unwrap(), 28);
+        let sessions: i64 = conn.query_row("SELECT COUNT(*) FROM sessions", [], |r| r.get(0)).unwrap();
+        assert_eq!(sessions, 1, "rows written after the backup are gone");
+
+        // The pre-rollback store is kept, so the rollback can be undone
+        let saved = Connection::open(saved).unwrap();
+        let sessions: i64 = saved.query_row("SELECT COUNT(*) FROM sessions", [], |r| r.get(0)).unwrap();
+        assert_eq!(sessions, 2);
+    }
+}
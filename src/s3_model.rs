@@ -0,0 +1,190 @@
+//! S3 Control — which model and agent version produced a session.
+//!
+//! `sessions.model` and `sessions.agent_version` come from the SessionStart
+//! payload's `model` (else `ANTHROPIC_MODEL`), then from the transcript as
+//! it is scanned: each assistant entry names its `message.model` and the
+//! agent's `version`, and the latest seen wins, so a mid-session `/model`
+//! switch is reflected. `nmem stats --models` breaks failures, friction, and
+//! error classes down by project and model, so models can be compared on
+//! the same work.
+
+use crate::NmemError;
+use rusqlite::{Connection, params};
+use serde::Serialize;
+use std::collections::BTreeMap;
+
+/// Model id from a hook payload's `model`: a string, or an object with `id`.
+pub fn model_id(value: &serde_json::Value) -> Option<String> {
+    let id = match value {
+        serde_json::Value::String(s) => s.as_str(),
+        serde_json::Value::Object(o) => o.get("id").and_then(|v| v.as_str())?,
+        _ => return None,
+    };
+    let id = id.trim();
+    (!id.is_empty()).then(|| id.to_string())
+}
+
+/// Model and agent version of one transcript entry. Placeholder models
+/// (`<synthetic>`, used for locally generated errors) are skipped.
+pub fn from_transcript_entry(entry: &serde_json::Value) -> (Option<String>, Option<String>) {
+    let model = entry
+        .get("message")
+        .and_then(|m| m.get("model"))
+        .and_then(|v| v.as_str())
+        .filter(|m| !m.is_empty() && !m.starts_with('<'))
+        .map(String::from);
+    let version = entry.get("version").and_then(|v| v.as_str()).filter(|v| !v.is_empty()).map(String::from);
+    (model, version)
+}
+
+/// Record what the session ran on; `None` keeps what is stored.
+pub fn set_identity(
+    conn: &Connection,
+    session_id: &str,
+    model: Option<&str>,
+    agent_version: Option<&str>,
+) -> Result<(), NmemError> {
+    if model.is_none() && agent_version.is_none() {
+        return Ok(());
+    }
+    conn.execute(
+        "UPDATE sessions SET model = COALESCE(?2, model), agent_version = COALESCE(?3, agent_version) WHERE id = ?1",
+        params![session_id, model, agent_version],
+    )?;
+    Ok(())
+}
+
+#[derive(Debug, Serialize, PartialEq)]
+pub struct ModelStats {
+    pub project: String,
+    /// `unknown` for sessions recorded before models were.
+    pub model: String,
+    pub agent_versions: Vec<String>,
+    pub sessions: i64,
+    pub observations: i64,
+    /// Failed tool calls.
+    pub failed: i64,
+    pub failure_rate: f64,
+    /// Observations in episodes labeled friction, of those labeled at all.
+    pub friction: i64,
+    pub friction_rate: Option<f64>,
+    pub error_classes: BTreeMap<String, i64>,
+}
+
+fn rate(n: i64, of: i64) -> f64 {
+    if of == 0 { 0.0 } else { (n as f64 / of as f64 * 1000.0).round() / 1000.0 }
+}
+
+/// Agent sessions per project and model. `project` narrows to one project;
+/// `model` to models whose id contains it (`opus` matches every Opus).
+pub fn by_model(conn: &Connection, project: Option<&str>, model: Option<&str>) -> Result<Vec<ModelStats>, NmemError> {
+    const FILTER: &str = "s.kind = 'agent' AND (?1 IS NULL OR s.project = ?1)
+                          AND (?2 IS NULL OR instr(s.model, ?2) > 0)";
+    let mut stmt = conn.prepare(&format!(
+        "SELECT s.project, COALESCE(s.model, 'unknown'), COUNT(DISTINCT s.id), COUNT(o.id),
+                COALESCE(SUM(COALESCE(json_extract(o.metadata, '$.failed'), 0) = 1), 0),
+                COALESCE(SUM(o.friction = 'friction'), 0), COUNT(o.friction),
+                group_concat(DISTINCT s.agent_version)
+         FROM sessions s LEFT JOIN observations o ON o.session_id = s.id
+         WHERE {FILTER}
+         GROUP BY s.project, COALESCE(s.model, 'unknown')
+         ORDER BY s.project, COUNT(o.id) DESC"
+    ))?;
+    let mut rows: Vec<ModelStats> = stmt
+        .query_map(params![project, model], |r| {
+            let observations: i64 = r.get(3)?;
+            let failed: i64 = r.get(4)?;
+            let friction: i64 = r.get(5)?;
+            let labeled: i64 = r.get(6)?;
+            let versions: Option<String> = r.get(7)?;
+            let mut agent_versions: Vec<String> =
+                versions.map(|v| v.split(',').map(String::from).collect()).unwrap_or_default();
+            agent_versions.sort();
+            Ok(ModelStats {
+                project: r.get(0)?,
+                model: r.get(1)?,
+                agent_versions,
+                sessions: r.get(2)?,
+                observations,
+                failed,
+                failure_rate: rate(failed, observations),
+                friction,
+                friction_rate: (labeled > 0).then(|| rate(friction, labeled)),
+                error_classes: BTreeMap::new(),
+            })
+        })?
+        .collect::<Result<_, _>>()?;
+
+    let mut stmt = conn.prepare(&format!(
+        "SELECT s.project, COALESCE(s.model, 'unknown'), o.error_class, COUNT(*)
+         FROM sessions s JOIN observations o ON o.session_id = s.id
+         WHERE {FILTER} AND o.error_class IS NOT NULL
+         GROUP BY s.project, COALESCE(s.model, 'unknown'), o.error_class"
+    ))?;
+    let classes = stmt.query_map(params![project, model], |r| {
+        Ok((r.get::<_, String>(0)?, r.get::<_, String>(1)?, r.get::<_, String>(2)?, r.get::<_, i64>(3)?))
+    })?;
+    for class in classes {
+        let (project, model, class, n) = class?;
+        if let Some(row) = rows.iter_mut().find(|s| s.project == project && s.model == model) {
+            row.error_classes.insert(class, n);
+        }
+    }
+    Ok(rows)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn model_comes_from_payload_or_transcript() {
+        assert_eq!(model_id(&serde_json::json!("claude-opus-4-1")), Some("claude-opus-4-1".into()));
+        assert_eq!(
+            model_id(&serde_json::json!({"id": "claude-sonnet-4-5", "display_name": "Sonnet"})),
+            Some("claude-sonnet-4-5".into())
+        );
+        assert_eq!(model_id(&serde_json::json!("")), None);
+
+        let entry = serde_json::json!({"type": "assistant", "version": "2.0.14",
+                                       "message": {"model": "claude-sonnet-4-5", "content": []}});
+        assert_eq!(from_transcript_entry(&entry), (Some("claude-sonnet-4-5".into()), Some("2.0.14".into())));
+        let synthetic = serde_json::json!({"type": "assistant", "message": {"model": "<synthetic>"}});
+        assert_eq!(from_transcript_entry(&synthetic), (None, None));
+    }
+
+    #[test]
+    fn breaks_down_failures_and_friction_by_model() {
+        let mut conn = Connection::open_in_memory().unwrap();
+        crate::schema::MIGRATIONS.to_latest(&mut conn).unwrap();
+        conn.execute_batch(
+            r#"INSERT INTO sessions (id, project, started_at, model, agent_version) VALUES
+                 ('a', 'app', 1, 'claude-opus-4-1', '2.0.1'),
+                 ('b', 'app', 2, 'claude-sonnet-4-5', '2.0.1'),
+                 ('c', 'app', 3, 'claude-sonnet-4-5', '2.0.3'),
+                 ('d', 'app', 4, NULL, NULL);
+               INSERT INTO observations (session_id, timestamp, obs_type, source_event, content, metadata, friction, error_class) VALUES
+                 ('a', 10, 'command', 'PostToolUse', 'x', NULL, 'smooth', NULL),
+                 ('b', 20, 'command', 'PostToolUse', 'x', '{"failed":true}', 'friction', 'compile'),
+                 ('c', 30, 'command', 'PostToolUse', 'x', '{"failed":true}', 'friction', 'compile'),
+                 ('c', 40, 'command', 'PostToolUse', 'x', NULL, 'smooth', NULL),
+                 ('c', 50, 'file_read', 'PostToolUse', 'x', NULL, NULL, NULL);"#,
+        )
+        .unwrap();
+
+        let all = by_model(&conn, Some("app"), None).unwrap();
+        let models: Vec<&str> = all.iter().map(|m| m.model.as_str()).collect();
+        assert_eq!(models, vec!["claude-sonnet-4-5", "claude-opus-4-1", "unknown"]);
+        let sonnet = &all[0];
+        assert_eq!((sonnet.sessions, sonnet.observations, sonnet.failed), (2, 4, 2));
+        assert_eq!(sonnet.failure_rate, 0.5);
+        assert_eq!((sonnet.friction, sonnet.friction_rate), (2, Some(0.667)));
+        assert_eq!(sonnet.agent_versions, vec!["2.0.1", "2.0.3"]);
+        assert_eq!(sonnet.error_classes.get("compile"), Some(&2));
+        assert_eq!(all[2].friction_rate, None);
+
+        let opus = by_model(&conn, None, Some("opus")).unwrap();
+        assert_eq!(opus.len(), 1);
+        assert_eq!(opus[0].failure_rate, 0.0);
+    }
+}
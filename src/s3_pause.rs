@@ -0,0 +1,167 @@
+//! S3 Control — temporary capture pause.
+//!
+//! `nmem pause` stops recording for a while — all projects, or one — for
+//! moments when sensitive material is on screen. While a pause is active the
+//! record hook still exits successfully but writes nothing: prompts, tool
+//! calls, and Stop are dropped, and SessionStart only injects context (whose
+//! header says capture is paused). Pauses with `--for` lapse on their own;
+//! `nmem resume` lifts them early. `nmem status` lists active pauses.
+
+#[cfg(feature = "cli")]
+use crate::cli::{PauseArgs, ResumeArgs};
+#[cfg(feature = "cli")]
+use crate::db::open_db;
+use crate::NmemError;
+use rusqlite::{Connection, OptionalExtension, params};
+use serde::Serialize;
+#[cfg(feature = "cli")]
+use std::path::Path;
+
+/// Scope of a pause covering every project.
+pub const ALL_PROJECTS: &str = "*";
+
+#[derive(Debug, Serialize, PartialEq)]
+pub struct Pause {
+    /// `None` for all projects.
+    pub project: Option<String>,
+    pub paused_at: i64,
+    /// `None` until `nmem resume`.
+    pub until: Option<i64>,
+}
+
+impl Pause {
+    fn from_row(r: &rusqlite::Row) -> rusqlite::Result<Self> {
+        let scope: String = r.get(0)?;
+        Ok(Pause {
+            project: (scope != ALL_PROJECTS).then_some(scope),
+            paused_at: r.get(1)?,
+            until: r.get(2)?,
+        })
+    }
+
+    /// One line for status and the context header.
+    pub fn describe(&self) -> String {
+        let scope = match &self.project {
+            Some(p) => format!("for {p}"),
+            None => "for all projects".into(),
+        };
+        match self.until {
+            Some(ts) => format!("capture paused {scope} until {}", crate::timefmt::datetime(ts)),
+            None => format!("capture paused {scope} until `nmem resume`"),
+        }
+    }
+}
+
+/// Pause capture for `project` (all projects when `None`) until `until`, or
+/// indefinitely. Replaces an existing pause of the same scope.
+pub fn pause(conn: &Connection, project: Option<&str>, until: Option<i64>, now: i64) -> Result<(), NmemError> {
+    conn.execute(
+        "INSERT OR REPLACE INTO capture_pauses (scope, paused_at, until) VALUES (?1, ?2, ?3)",
+        params![project.unwrap_or(ALL_PROJECTS), now, until],
+    )?;
+    Ok(())
+}
+
+/// Lift the pause on `project`, or every pause when `None`. Returns how many
+/// were lifted.
+pub fn resume(conn: &Connection, project: Option<&str>) -> Result<usize, NmemError> {
+    Ok(conn.execute("DELETE FROM capture_pauses WHERE ?1 IS NULL OR scope = ?1", params![project])?)
+}
+
+/// The pause covering `project` at `now`, if any — the longest-lasting when
+/// both a project and an all-projects pause apply.
+pub fn active(conn: &Connection, project: &str, now: i64) -> Result<Option<Pause>, NmemError> {
+    Ok(conn
+        .query_row(
+            "SELECT scope, paused_at, until FROM capture_pauses
+             WHERE scope IN (?1, ?2) AND (until IS NULL OR until > ?3)
+             ORDER BY until IS NULL DESC, until DESC LIMIT 1",
+            params![ALL_PROJECTS, project, now],
+            Pause::from_row,
+        )
+        .optional()?)
+}
+
+/// Every pause still in effect at `now`.
+pub fn list_active(conn: &Connection, now: i64) -> Result<Vec<Pause>, NmemError> {
+    let mut stmt = conn.prepare(
+        "SELECT scope, paused_at, until FROM capture_pauses
+         WHERE until IS NULL OR until > ?1
+         ORDER BY scope",
+    )?;
+    let rows = stmt.query_map(params![now], Pause::from_row)?.collect::<Result<_, _>>()?;
+    Ok(rows)
+}
+
+#[cfg(feature = "cli")]
+fn now_secs() -> i64 {
+    std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs() as i64
+}
+
+#[cfg(feature = "cli")]
+pub fn handle_pause(db_path: &Path, args: &PauseArgs) -> Result<(), NmemError> {
+    let now = now_secs();
+    let until = match args.duration.as_deref() {
+        Some(d) => Some(
+            now + crate::s4_dispatch::parse_relative(d).ok_or_else(|| {
+                NmemError::Config(format!("cannot parse --for: {d:?} — try \"30m\", \"2h\", or \"1d\""))
+            })?,
+        ),
+        None => None,
+    };
+    let conn = open_db(db_path)?;
+    pause(&conn, args.project.as_deref(), until, now)?;
+    if let Some(p) = active(&conn, args.project.as_deref().unwrap_or(ALL_PROJECTS), now)? {
+        log::info!("{}", p.describe());
+    }
+    Ok(())
+}
+
+#[cfg(feature = "cli")]
+pub fn handle_resume(db_path: &Path, args: &ResumeArgs) -> Result<(), NmemError> {
+    let conn = open_db(db_path)?;
+    let lifted = resume(&conn, args.project.as_deref())?;
+    if lifted == 0 {
+        log::info!("capture was not paused");
+    } else {
+        log::info!("capture resumed ({lifted} pause{} lifted)", if lifted == 1 { "" } else { "s" });
+    }
+    if let Some(ref project) = args.project
+        && let Some(p) = active(&conn, project, now_secs())?
+    {
+        log::info!("still in effect: {}", p.describe());
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn pauses_scope_expire_and_resume() {
+        let mut conn = Connection::open_in_memory().unwrap();
+        crate::schema::MIGRATIONS.to_latest(&mut conn).unwrap();
+
+        assert_eq!(active(&conn, "web", 100).unwrap(), None);
+        pause(&conn, Some("web"), Some(200), 100).unwrap();
+        assert_eq!(active(&conn, "web", 150).unwrap().unwrap().until, Some(200));
+        assert_eq!(active(&conn, "api", 150).unwrap(), None);
+        assert_eq!(active(&conn, "web", 200).unwrap(), None, "lapses at until");
+
+        // An open-ended all-projects pause outlasts the project one
+        pause(&conn, None, None, 120).unwrap();
+        let p = active(&conn, "web", 150).unwrap().unwrap();
+        assert_eq!((p.project, p.until), (None, None));
+        assert!(active(&conn, "api", 10_000).unwrap().is_some());
+        assert_eq!(list_active(&conn, 150).unwrap().len(), 2);
+
+        assert_eq!(resume(&conn, Some("web")).unwrap(), 1);
+        assert!(active(&conn, "web", 150).unwrap().is_some(), "all-projects pause remains");
+        assert_eq!(resume(&conn, None).unwrap(), 1);
+        assert!(list_active(&conn, 150).unwrap().is_empty());
+    }
+}
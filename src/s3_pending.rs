@@ -0,0 +1,218 @@
+//! S3 Control — hook latency budget and spilled work.
+//!
+//! `nmem record` runs inline with the agent's tool loop, so it gets a hard
+//! wall-clock budget (`[record] latency_budget_ms`). Work that doesn't finish
+//! in time — classification, git rename detection — is written to
+//! `pending_work` and completed by a later hook with budget to spare or by
+//! `nmem maintain`.
+
+use crate::NmemError;
+use rusqlite::{Connection, OptionalExtension, params};
+use std::path::Path;
+use std::sync::mpsc;
+use std::time::Instant;
+
+/// Classify an observation stored without labels.
+pub const CLASSIFY: &str = "classify";
+/// Record renames from the HEAD commit; payload is the repo cwd.
+pub const HEAD_RENAMES: &str = "head_renames";
+
+/// Run `f` on a worker thread and wait for it until `deadline`. `None` if it
+/// is still running then; the thread is abandoned and dies with the process.
+/// Without a deadline `f` runs inline.
+pub fn within_budget<T: Send + 'static>(
+    deadline: Option<Instant>,
+    f: impl FnOnce() -> T + Send + 'static,
+) -> Option<T> {
+    let Some(deadline) = deadline else {
+        return Some(f());
+    };
+    let (tx, rx) = mpsc::channel();
+    std::thread::spawn(move || {
+        let _ = tx.send(f());
+    });
+    rx.recv_timeout(deadline.saturating_duration_since(Instant::now())).ok()
+}
+
+pub fn enqueue(
+    conn: &Connection,
+    kind: &str,
+    obs_id: Option<i64>,
+    payload: Option<&str>,
+    now: i64,
+) -> Result<(), NmemError> {
+    conn.execute(
+        "INSERT INTO pending_work (kind, obs_id, payload, created_at) VALUES (?1, ?2, ?3, ?4)",
+        params![kind, obs_id, payload, now],
+    )?;
+    Ok(())
+}
+
+pub fn pending_count(conn: &Connection) -> Result<i64, NmemError> {
+    Ok(conn.query_row("SELECT COUNT(*) FROM pending_work", [], |r| r.get(0))?)
+}
+
+/// Complete queued work oldest first, stopping at `deadline` (`None` drains
+/// everything). Each item commits on its own. Deferred session maintenance
+/// is left to `s3_schedule`. Returns how many completed.
+pub fn drain(conn: &Connection, deadline: Option<Instant>) -> Result<usize, NmemError> {
+    let items: Vec<(i64, String, Option<i64>, Option<String>)> = conn
+        .prepare("SELECT id, kind, obs_id, payload FROM pending_work WHERE kind != ?1 ORDER BY id")?
+        .query_map([crate::s3_schedule::SESSION_MAINTAIN], |r| Ok((r.get(0)?, r.get(1)?, r.get(2)?, r.get(3)?)))?
+        .collect::<Result<_, _>>()?;
+
+    let mut done = 0;
+    for (id, kind, obs_id, payload) in items {
+        if deadline.is_some_and(|d| Instant::now() >= d) {
+            break;
+        }
+        let tx = conn.unchecked_transaction()?;
+        let finished = match kind.as_str() {
+            CLASSIFY => classify_observation(&tx, obs_id, deadline)?,
+            HEAD_RENAMES => record_renames(&tx, payload, deadline)?,
+            other => {
+                log::debug!("dropping pending work {id} of unknown kind {other:?}");
+                true
+            }
+        };
+        if !finished {
+            // Out of budget mid-item; it stays queued
+            break;
+        }
+        tx.execute("DELETE FROM pending_work WHERE id = ?1", params![id])?;
+        tx.commit()?;
+        done += 1;
+    }
+    Ok(done)
+}
+
+/// Label an observation that was stored unclassified. A deleted observation
+/// counts as done.
+fn classify_observation(
+    conn: &Connection,
+    obs_id: Option<i64>,
+    deadline: Option<Instant>,
+) -> Result<bool, NmemError> {
+    let Some(obs_id) = obs_id else {
+        return Ok(true);
+    };
+    let content: Option<String> = conn
+        .query_row("SELECT content FROM observations WHERE id = ?1", params![obs_id], |r| r.get(0))
+        .optional()?;
+    let Some(content) = content else {
+        return Ok(true);
+    };
+    let Some(classified) = within_budget(deadline, move || crate::s1_record::classify_text(&content))
+    else {
+        return Ok(false);
+    };
+    let l = classified.register(conn);
+    conn.execute(
+        "UPDATE observations SET phase = ?2, classifier_run_id = ?3, scope = ?4, scope_run_id = ?5,
+                locus = ?6, locus_run_id = ?7, novelty = ?8, novelty_run_id = ?9,
+                phase_confidence = ?10, scope_confidence = ?11, locus_confidence = ?12, novelty_confidence = ?13
+         WHERE id = ?1",
+        params![
+            obs_id,
+            l.phase,
+            l.classifier_run_id,
+            l.scope,
+            l.scope_run_id,
+            l.locus,
+            l.locus_run_id,
+            l.novelty,
+            l.novelty_run_id,
+            l.phase_confidence,
+            l.scope_confidence,
+            l.locus_confidence,
+            l.novelty_confidence,
+        ],
+    )?;
+    Ok(true)
+}
+
+/// Record git's renames for HEAD of the repo at `cwd`. A repo that's gone or
+/// has no renames counts as done.
+fn record_renames(
+    conn: &Connection,
+    cwd: Option<String>,
+    deadline: Option<Instant>,
+) -> Result<bool, NmemError> {
+    let Some(cwd) = cwd else {
+        return Ok(true);
+    };
+    let Some(renames) = within_budget(deadline, move || crate::s1_git::head_renames(Path::new(&cwd)))
+    else {
+        return Ok(false);
+    };
+    let now = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs() as i64;
+    match renames {
+        Ok(renames) => {
+            for (old, new) in renames {
+                crate::s1_alias::record_alias(conn, &old, &new, "git", now)?;
+            }
+        }
+        Err(e) => log::debug!("rename detection skipped: {e}"),
+    }
+    Ok(true)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    fn setup() -> Connection {
+        let mut conn = Connection::open_in_memory().unwrap();
+        conn.pragma_update(None, "foreign_keys", "ON").unwrap();
+        crate::schema::MIGRATIONS.to_latest(&mut conn).unwrap();
+        conn.execute_batch(
+            "INSERT INTO sessions (id, project, started_at) VALUES ('s1', 'proj', 100);
+             INSERT INTO observations (id, session_id, timestamp, obs_type, source_event, content)
+                 VALUES (1, 's1', 110, 'command', 'PostToolUse', 'grep -rn todo src');",
+        )
+        .unwrap();
+        conn
+    }
+
+    #[test]
+    fn within_budget_times_out() {
+        assert_eq!(within_budget(None, || 7), Some(7));
+        let soon = Some(Instant::now() + Duration::from_millis(20));
+        assert_eq!(within_budget(soon, || 7), Some(7));
+        let slow = within_budget(soon, || std::thread::sleep(Duration::from_secs(2)));
+        assert!(slow.is_none());
+    }
+
+    #[test]
+    fn drain_classifies_spilled_observations() {
+        let conn = setup();
+        enqueue(&conn, CLASSIFY, Some(1), None, 120).unwrap();
+        assert_eq!(pending_count(&conn).unwrap(), 1);
+
+        // An expired deadline leaves the queue alone
+        assert_eq!(drain(&conn, Some(Instant::now())).unwrap(), 0);
+        assert_eq!(pending_count(&conn).unwrap(), 1);
+
+        assert_eq!(drain(&conn, None).unwrap(), 1);
+        assert_eq!(pending_count(&conn).unwrap(), 0);
+        let phase: Option<String> = conn
+            .query_row("SELECT phase FROM observations WHERE id = 1", [], |r| r.get(0))
+            .unwrap();
+        assert!(phase.is_some());
+    }
+
+    #[test]
+    fn deleted_observation_drops_its_work() {
+        let conn = setup();
+        enqueue(&conn, CLASSIFY, Some(1), None, 120).unwrap();
+        enqueue(&conn, HEAD_RENAMES, None, Some("/nonexistent/repo"), 120).unwrap();
+        conn.execute("DELETE FROM observations WHERE id = 1", []).unwrap();
+        assert_eq!(pending_count(&conn).unwrap(), 1, "classify work cascades away");
+        assert_eq!(drain(&conn, None).unwrap(), 1);
+        assert_eq!(pending_count(&conn).unwrap(), 0);
+    }
+}
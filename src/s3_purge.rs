@@ -1,7 +1,11 @@
-use crate::cli::PurgeArgs;
+#[cfg(feature = "cli")]
+use crate::cli::{PurgeArgs, RestoreArgs};
 use crate::db::open_db;
 use crate::NmemError;
+use rusqlite::types::ValueRef;
 use rusqlite::{Connection, params};
+use std::collections::{HashMap, HashSet};
+use std::io::{BufRead, Write};
 use std::path::Path;
 use std::time::{SystemTime, UNIX_EPOCH};
 
@@ -56,6 +60,7 @@ fn days_ago_ts(days: u32) -> i64 {
     now + 1 - (days as i64 * 86400)
 }
 
+#[cfg(feature = "cli")]
 fn has_any_filter(args: &PurgeArgs) -> bool {
     args.before.is_some()
         || args.project.is_some()
@@ -66,6 +71,7 @@ fn has_any_filter(args: &PurgeArgs) -> bool {
 }
 
 /// Count matching observations, prompts, and sessions that would be purged.
+#[cfg(feature = "cli")]
 fn count_targets(conn: &Connection, args: &PurgeArgs) -> Result<PurgeCounts, NmemError> {
     let obs_count = count_observations(conn, args)?;
     let prompt_count = count_prompts(conn, args)?;
@@ -77,6 +83,7 @@ fn count_targets(conn: &Connection, args: &PurgeArgs) -> Result<PurgeCounts, Nme
     })
 }
 
+#[cfg(feature = "cli")]
 fn count_observations(conn: &Connection, args: &PurgeArgs) -> Result<usize, NmemError> {
     let (where_clause, bind_values) = build_obs_where(args)?;
     let sql = format!("SELECT COUNT(*) FROM observations WHERE {where_clause}");
@@ -84,6 +91,7 @@ fn count_observations(conn: &Connection, args: &PurgeArgs) -> Result<usize, Nmem
     Ok(count as usize)
 }
 
+#[cfg(feature = "cli")]
 fn count_prompts(conn: &Connection, args: &PurgeArgs) -> Result<usize, NmemError> {
     // Only session/project/before purge prompts directly
     if let Some(ref session) = args.session {
@@ -114,6 +122,7 @@ fn count_prompts(conn: &Connection, args: &PurgeArgs) -> Result<usize, NmemError
     Ok(0)
 }
 
+#[cfg(feature = "cli")]
 fn count_sessions(conn: &Connection, args: &PurgeArgs) -> Result<usize, NmemError> {
     if let Some(ref session) = args.session {
         let count: i64 = conn.query_row(
@@ -136,6 +145,7 @@ fn count_sessions(conn: &Connection, args: &PurgeArgs) -> Result<usize, NmemErro
 }
 
 /// Build WHERE clause for observations based on args. Returns (clause, bind_values).
+#[cfg(feature = "cli")]
 fn build_obs_where(args: &PurgeArgs) -> Result<(String, Vec<String>), NmemError> {
     let mut clauses: Vec<String> = Vec::new();
     let mut values: Vec<String> = Vec::new();
@@ -179,8 +189,11 @@ fn build_obs_where(args: &PurgeArgs) -> Result<(String, Vec<String>), NmemError>
         let sanitized = crate::sanitize_fts_query(search)
             .ok_or_else(|| NmemError::Config("search query produced no usable terms".into()))?;
         clauses.push(format!(
-            "id IN (SELECT rowid FROM observations_fts WHERE observations_fts MATCH ?{})",
-            values.len() + 1
+            "(id IN (SELECT rowid FROM observations_fts WHERE observations_fts MATCH ?{n})
+              OR id IN (SELECT c.obs_id FROM observation_chunks c
+                        JOIN observation_chunks_fts f ON c.id = f.rowid
+                        WHERE observation_chunks_fts MATCH ?{n}))",
+            n = values.len() + 1
         ));
         values.push(sanitized);
     }
@@ -192,8 +205,14 @@ fn build_obs_where(args: &PurgeArgs) -> Result<(String, Vec<String>), NmemError>
     Ok((clauses.join(" AND "), values))
 }
 
+#[cfg(feature = "cli")]
 fn delete_observations(conn: &Connection, args: &PurgeArgs) -> Result<usize, NmemError> {
     let (where_clause, bind_values) = build_obs_where(args)?;
+    // Intents carry the same tool input as the observations they produced
+    conn.execute(
+        &format!("DELETE FROM tool_intents WHERE obs_id IN (SELECT id FROM observations WHERE {where_clause})"),
+        rusqlite::params_from_iter(&bind_values),
+    )?;
     let sql = format!("DELETE FROM observations WHERE {where_clause}");
     let deleted = conn.execute(&sql, rusqlite::params_from_iter(&bind_values))?;
     Ok(deleted)
@@ -214,6 +233,8 @@ fn delete_prompts_for_project(conn: &Connection, project: &str) -> Result<usize,
 
 fn delete_prompts_before(conn: &Connection, ts: i64) -> Result<usize, NmemError> {
     let deleted = conn.execute("DELETE FROM prompts WHERE timestamp < ?1", params![ts])?;
+    // Responses answer prompts; they age out with them
+    conn.execute("DELETE FROM responses WHERE timestamp < ?1", params![ts])?;
     Ok(deleted)
 }
 
@@ -253,29 +274,186 @@ pub fn cleanup_orphans(conn: &Connection) -> Result<usize, NmemError> {
     conn.execute_batch("DELETE FROM prompts WHERE session_id NOT IN (SELECT id FROM sessions)")?;
     conn.execute_batch("DELETE FROM work_units WHERE session_id NOT IN (SELECT id FROM sessions)")?;
     conn.execute_batch("DELETE FROM _cursor WHERE session_id NOT IN (SELECT id FROM sessions)")?;
-    // Delete sessions that have no observations or prompts left
+    conn.execute_batch("DELETE FROM responses WHERE session_id NOT IN (SELECT id FROM sessions)")?;
+    // Delete sessions that have no observations, prompts, or episodes left
     let orphaned = conn.execute(
         "DELETE FROM sessions WHERE id NOT IN (
             SELECT DISTINCT session_id FROM observations
             UNION
             SELECT DISTINCT session_id FROM prompts
+            UNION
+            SELECT DISTINCT session_id FROM work_units
         )",
         [],
     )?;
     Ok(orphaned)
 }
 
-pub fn post_purge_maintenance(conn: &Connection, obs_deleted: usize) -> Result<(), NmemError> {
+/// Deletions past this many observations rebuild the FTS indexes rather than
+/// leave them to trigger-by-trigger upkeep.
+pub const FTS_REBUILD_ROWS: usize = 1000;
+
+/// Reclaim pages and checkpoint after a deletion; with `rebuild_fts`, also
+/// rebuild the observation FTS indexes.
+pub fn post_purge_maintenance(conn: &Connection, rebuild_fts: bool) -> Result<(), NmemError> {
     conn.pragma_update(None, "incremental_vacuum", 0)?;
 
-    if obs_deleted > 1000 {
-        conn.execute_batch("INSERT INTO observations_fts(observations_fts) VALUES('rebuild')")?;
+    if rebuild_fts {
+        conn.execute_batch(
+            "INSERT INTO observations_fts(observations_fts) VALUES('rebuild');
+             INSERT INTO observation_chunks_fts(observation_chunks_fts) VALUES('rebuild');",
+        )?;
     }
 
     conn.execute_batch("PRAGMA wal_checkpoint(TRUNCATE)")?;
     Ok(())
 }
 
+/// Rows captured for `--export-before`, one JSON object per line:
+/// `{"table": ..., "row": {column: value, ...}}`. Parents come first in the
+/// file so `nmem restore` can replay it top to bottom. Every row a deletion
+/// takes with it through `ON DELETE CASCADE` is captured too, found from the
+/// schema's foreign keys, and BLOBs are written as `{"$blob": "<hex>"}`.
+#[derive(Default)]
+struct Stash {
+    rows: Vec<(usize, serde_json::Value)>,
+    /// Exported lines, so a row captured twice is written once.
+    seen: HashSet<String>,
+    /// Restore order per table: one past the deepest table it references.
+    ranks: HashMap<String, usize>,
+}
+
+/// Tables whose rows are deleted along with `table`'s: (child, child column,
+/// referenced column).
+fn cascade_children(conn: &Connection, table: &str) -> Result<Vec<(String, String, String)>, NmemError> {
+    let mut stmt = conn.prepare_cached(
+        "SELECT m.name, f.\"from\", COALESCE(f.\"to\", (SELECT name FROM pragma_table_info(?1) WHERE pk = 1))
+         FROM sqlite_master m, pragma_foreign_key_list(m.name) f
+         WHERE m.type = 'table' AND f.\"table\" = ?1 AND f.on_delete = 'CASCADE' AND m.name != ?1",
+    )?;
+    let children = stmt
+        .query_map(params![table], |r| Ok((r.get(0)?, r.get(1)?, r.get(2)?)))?
+        .collect::<Result<_, _>>()?;
+    Ok(children)
+}
+
+fn is_identifier(name: &str) -> bool {
+    !name.is_empty() && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_')
+}
+
+impl Stash {
+    fn rank(&mut self, conn: &Connection, table: &str) -> Result<usize, NmemError> {
+        if let Some(&rank) = self.ranks.get(table) {
+            return Ok(rank);
+        }
+        // Placeholder first, so a cycle of references terminates
+        self.ranks.insert(table.to_string(), 0);
+        let parents: Vec<String> = conn
+            .prepare_cached("SELECT DISTINCT \"table\" FROM pragma_foreign_key_list(?1) WHERE \"table\" != ?1")?
+            .query_map(params![table], |r| r.get(0))?
+            .collect::<Result<_, _>>()?;
+        let mut rank = 0;
+        for parent in parents {
+            rank = rank.max(self.rank(conn, &parent)? + 1);
+        }
+        self.ranks.insert(table.to_string(), rank);
+        Ok(rank)
+    }
+
+    fn add<P: rusqlite::Params>(
+        &mut self,
+        conn: &Connection,
+        table: &str,
+        where_clause: &str,
+        params: P,
+    ) -> Result<(), NmemError> {
+        if !is_identifier(table) {
+            return Err(NmemError::Config(format!("cannot export table {table}")));
+        }
+        let rank = self.rank(conn, table)?;
+        let mut stmt = conn.prepare(&format!("SELECT * FROM {table} WHERE {where_clause}"))?;
+        let columns: Vec<String> = stmt.column_names().into_iter().map(String::from).collect();
+        let mut rows = stmt.query(params)?;
+        let mut added: Vec<serde_json::Map<String, serde_json::Value>> = Vec::new();
+        while let Some(row) = rows.next()? {
+            let mut obj = serde_json::Map::new();
+            for (i, col) in columns.iter().enumerate() {
+                let value = match row.get_ref(i)? {
+                    ValueRef::Null => serde_json::Value::Null,
+                    ValueRef::Integer(n) => n.into(),
+                    ValueRef::Real(f) => f.into(),
+                    ValueRef::Text(t) => String::from_utf8_lossy(t).into_owned().into(),
+                    ValueRef::Blob(b) => {
+                        let hex: String = b.iter().map(|byte| format!("{byte:02x}")).collect();
+                        serde_json::json!({ "$blob": hex })
+                    }
+                };
+                obj.insert(col.clone(), value);
+            }
+            let line = serde_json::json!({ "table": table, "row": obj });
+            if self.seen.insert(line.to_string()) {
+                self.rows.push((rank, line));
+                added.push(obj);
+            }
+        }
+        drop(rows);
+        drop(stmt);
+        if added.is_empty() {
+            return Ok(());
+        }
+        for (child, column, key) in cascade_children(conn, table)? {
+            let keys: Vec<&serde_json::Value> = added.iter().filter_map(|r| r.get(&key)).filter(|v| !v.is_null()).collect();
+            if keys.is_empty() || !is_identifier(&column) {
+                continue;
+            }
+            self.add(
+                conn,
+                &child,
+                &format!("{column} IN (SELECT value FROM json_each(?1))"),
+                params![serde_json::to_string(&keys)?],
+            )?;
+        }
+        Ok(())
+    }
+
+    fn write(mut self, path: &Path) -> Result<usize, NmemError> {
+        use std::io::Write;
+        self.rows.sort_by_key(|(rank, _)| *rank);
+        let mut file = std::fs::File::create(path)?;
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            std::fs::set_permissions(path, std::fs::Permissions::from_mode(0o600))?;
+        }
+        for (_, row) in &self.rows {
+            writeln!(file, "{row}")?;
+        }
+        file.sync_all()?;
+        Ok(self.rows.len())
+    }
+}
+
+fn stash_add<P: rusqlite::Params>(
+    stash: &mut Option<Stash>,
+    conn: &Connection,
+    table: &str,
+    where_clause: &str,
+    params: P,
+) -> Result<(), NmemError> {
+    match stash {
+        Some(s) => s.add(conn, table, where_clause, params),
+        None => Ok(()),
+    }
+}
+
+/// Sessions `cleanup_orphans` would delete.
+const ORPHAN_SESSIONS: &str = "id NOT IN (
+    SELECT DISTINCT session_id FROM observations
+    UNION
+    SELECT DISTINCT session_id FROM prompts
+)";
+
+#[cfg(feature = "cli")]
 pub fn handle_purge(db_path: &Path, args: &PurgeArgs) -> Result<(), NmemError> {
     if !has_any_filter(args) {
         return Err(NmemError::Config(
@@ -286,6 +464,12 @@ pub fn handle_purge(db_path: &Path, args: &PurgeArgs) -> Result<(), NmemError> {
     let conn = open_db(db_path)?;
     conn.pragma_update(None, "secure_delete", "ON")?;
 
+    if args.interactive {
+        let stdin = std::io::stdin();
+        let stdout = std::io::stdout();
+        return interactive_purge(&conn, args, &mut stdin.lock(), &mut stdout.lock());
+    }
+
     let counts = count_targets(&conn, args)?;
     let total = counts.observations + counts.prompts + counts.sessions;
 
@@ -306,40 +490,95 @@ pub fn handle_purge(db_path: &Path, args: &PurgeArgs) -> Result<(), NmemError> {
 
     // Execute deletion inside a transaction
     let tx = conn.unchecked_transaction()?;
+    let mut stash = args.export_before.as_ref().map(|_| Stash::default());
 
     // 1. Delete observations (leaf)
+    let (where_clause, bind_values) = build_obs_where(args)?;
+    stash_add(&mut stash, &tx, "observations", &where_clause, rusqlite::params_from_iter(&bind_values))?;
+    stash_add(
+        &mut stash,
+        &tx,
+        "tool_intents",
+        &format!("obs_id IN (SELECT id FROM observations WHERE {where_clause})"),
+        rusqlite::params_from_iter(&bind_values),
+    )?;
     let obs_deleted = delete_observations(&tx, args)?;
 
     // 2. Delete prompts for session/project/before modes
     let mut prompts_deleted = 0;
     if let Some(ref session) = args.session {
+        stash_add(&mut stash, &tx, "prompts", "session_id = ?1", params![session])?;
         prompts_deleted += delete_prompts_for_session(&tx, session)?;
     }
     if let Some(ref project) = args.project {
+        stash_add(
+            &mut stash,
+            &tx,
+            "prompts",
+            "session_id IN (SELECT id FROM sessions WHERE project = ?1)",
+            params![project],
+        )?;
         prompts_deleted += delete_prompts_for_project(&tx, project)?;
     }
     if let Some(ref before) = args.before {
         let ts = parse_date_to_ts(before)?;
+        stash_add(&mut stash, &tx, "prompts", "timestamp < ?1", params![ts])?;
+        stash_add(&mut stash, &tx, "responses", "timestamp < ?1", params![ts])?;
         prompts_deleted += delete_prompts_before(&tx, ts)?;
     }
 
     // 3. Delete sessions for session/project modes
     let mut sessions_deleted = 0;
+    // (delete_session* also drop observations other filters didn't match)
     if let Some(ref session) = args.session {
+        stash_add(&mut stash, &tx, "observations", "session_id = ?1", params![session])?;
+        stash_add(&mut stash, &tx, "work_units", "session_id = ?1", params![session])?;
+        stash_add(&mut stash, &tx, "sessions", "id = ?1", params![session])?;
         sessions_deleted += delete_session(&tx, session)?;
     } else if let Some(ref project) = args.project {
+        stash_add(
+            &mut stash,
+            &tx,
+            "observations",
+            "session_id IN (SELECT id FROM sessions WHERE project = ?1)",
+            params![project],
+        )?;
+        stash_add(
+            &mut stash,
+            &tx,
+            "work_units",
+            "session_id IN (SELECT id FROM sessions WHERE project = ?1)",
+            params![project],
+        )?;
+        stash_add(&mut stash, &tx, "sessions", "project = ?1", params![project])?;
         sessions_deleted += delete_sessions_for_project(&tx, project)?;
     }
 
     // 4. Cleanup orphans for other modes
     if args.session.is_none() && args.project.is_none() {
+        stash_add(&mut stash, &tx, "sessions", ORPHAN_SESSIONS, [])?;
         sessions_deleted += cleanup_orphans(&tx)?;
     }
 
+    // The export must be on disk before anything is committed
+    if let (Some(stash), Some(path)) = (stash, args.export_before.as_ref()) {
+        let rows = stash.write(path)?;
+        log::info!("exported {rows} rows to {}", path.display());
+    }
+
+    log_purge(
+        &tx,
+        purge_filter(args),
+        serde_json::json!({
+            "observations": obs_deleted,
+            "prompts": prompts_deleted,
+            "sessions": sessions_deleted,
+        }),
+    )?;
     tx.commit()?;
 
     // Post-deletion maintenance (outside transaction)
-    post_purge_maintenance(&conn, obs_deleted)?;
+    post_purge_maintenance(&conn, obs_deleted > FTS_REBUILD_ROWS)?;
 
     log::info!(
         "purged {} observations, {} prompts, {} sessions",
@@ -349,6 +588,323 @@ pub fn handle_purge(db_path: &Path, args: &PurgeArgs) -> Result<(), NmemError> {
     Ok(())
 }
 
+/// The flags that selected a purge, for `deletions_log`.
+#[cfg(feature = "cli")]
+fn purge_filter(args: &PurgeArgs) -> serde_json::Value {
+    serde_json::json!({
+        "before": args.before,
+        "project": args.project,
+        "session": args.session,
+        "id": args.id,
+        "type": args.obs_type,
+        "older_than": args.older_than,
+        "search": args.search,
+    })
+}
+
+/// Append the purge to `deletions_log`, inside its transaction.
+#[cfg(feature = "cli")]
+fn log_purge(conn: &Connection, filter: serde_json::Value, details: serde_json::Value) -> Result<(), NmemError> {
+    let deleted = details.as_object().map_or(0, |d| d.values().filter_map(|v| v.as_i64()).sum());
+    let key = crate::s3_deletions::signing_key(&crate::s5_config::load_config().unwrap_or_default());
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs() as i64;
+    let deletion = crate::s3_deletions::Deletion { operation: "purge", filter, deleted, details };
+    crate::s3_deletions::record(conn, &deletion, key.as_deref(), now)?;
+    Ok(())
+}
+
+/// Matches shown in interactive mode. More than this means the filters are
+/// too broad to review by eye.
+const MAX_INTERACTIVE: usize = 500;
+
+struct Candidate {
+    id: i64,
+    timestamp: i64,
+    session_id: String,
+    project: String,
+    obs_type: String,
+    preview: String,
+}
+
+#[cfg(feature = "cli")]
+fn load_candidates(conn: &Connection, args: &PurgeArgs) -> Result<Vec<Candidate>, NmemError> {
+    let (where_clause, bind_values) = build_obs_where(args)?;
+    let sql = format!(
+        "SELECT o.id, o.timestamp, o.session_id, COALESCE(s.project, '?'), o.obs_type,
+                REPLACE(SUBSTR(o.content, 1, 80), char(10), ' ')
+         FROM observations o LEFT JOIN sessions s ON s.id = o.session_id
+         WHERE o.id IN (SELECT id FROM observations WHERE {where_clause})
+         ORDER BY o.timestamp, o.id
+         LIMIT {}",
+        MAX_INTERACTIVE + 1
+    );
+    let mut stmt = conn.prepare(&sql)?;
+    let rows = stmt
+        .query_map(rusqlite::params_from_iter(&bind_values), |r| {
+            Ok(Candidate {
+                id: r.get(0)?,
+                timestamp: r.get(1)?,
+                session_id: r.get(2)?,
+                project: r.get(3)?,
+                obs_type: r.get(4)?,
+                preview: r.get(5)?,
+            })
+        })?
+        .collect::<Result<_, _>>()?;
+    Ok(rows)
+}
+
+/// Parse a selection command against `n` numbered items (1-based).
+/// Accepts `all`, `none`, numbers and ranges (`3`, `1-5`, `2,7-9`).
+/// Returns the indices and whether they are being deselected (`-` or `!` prefix).
+fn parse_selection(spec: &str, n: usize) -> Result<(Vec<usize>, bool), String> {
+    let spec = spec.trim();
+    let (spec, deselect) = match spec.strip_prefix(['-', '!']) {
+        Some(rest) => (rest.trim(), true),
+        None => (spec, false),
+    };
+    match spec {
+        "all" => return Ok(((0..n).collect(), deselect)),
+        "none" => return Ok(((0..n).collect(), !deselect)),
+        _ => {}
+    }
+    let mut picked = Vec::new();
+    for part in spec.split([',', ' ']).filter(|p| !p.is_empty()) {
+        let (lo, hi) = match part.split_once('-') {
+            Some((a, b)) => (a.trim(), b.trim()),
+            None => (part, part),
+        };
+        let parse = |s: &str| -> Result<usize, String> {
+            match s.parse::<usize>() {
+                Ok(i) if (1..=n).contains(&i) => Ok(i - 1),
+                _ => Err(format!("not a match number: {s:?} (1-{n})")),
+            }
+        };
+        let (lo, hi) = (parse(lo)?, parse(hi)?);
+        if lo > hi {
+            return Err(format!("empty range: {part:?}"));
+        }
+        picked.extend(lo..=hi);
+    }
+    if picked.is_empty() {
+        return Err("nothing selected".into());
+    }
+    Ok((picked, deselect))
+}
+
+fn print_candidates(out: &mut impl Write, candidates: &[Candidate], selected: &[bool]) -> std::io::Result<()> {
+    for (i, c) in candidates.iter().enumerate() {
+        writeln!(
+            out,
+            "[{}] {:>3}  #{}  {}  {}/{}  {}  {}",
+            if selected[i] { 'x' } else { ' ' },
+            i + 1,
+            c.id,
+            crate::timefmt::datetime(c.timestamp),
+            c.project,
+            c.session_id,
+            c.obs_type,
+            c.preview
+        )?;
+    }
+    Ok(())
+}
+
+/// Review matches, adjust the selection, confirm with per-project/session
+/// counts, then delete only the selected observations.
+#[cfg(feature = "cli")]
+fn interactive_purge(
+    conn: &Connection,
+    args: &PurgeArgs,
+    input: &mut impl BufRead,
+    out: &mut impl Write,
+) -> Result<(), NmemError> {
+    let candidates = load_candidates(conn, args)?;
+    if candidates.is_empty() {
+        writeln!(out, "nothing to purge")?;
+        return Ok(());
+    }
+    if candidates.len() > MAX_INTERACTIVE {
+        return Err(NmemError::Config(format!(
+            "more than {MAX_INTERACTIVE} matching observations — narrow the filters for --interactive"
+        )));
+    }
+
+    let mut selected = vec![true; candidates.len()];
+    print_candidates(out, &candidates, &selected)?;
+    writeln!(
+        out,
+        "\nSelect with numbers or ranges (3, 1-5), `-` to deselect (-2), `all`, `none`, `list`.\nEmpty line to continue, `q` to abort."
+    )?;
+
+    let mut line = String::new();
+    loop {
+        let count = selected.iter().filter(|s| **s).count();
+        write!(out, "{count}/{} selected> ", candidates.len())?;
+        out.flush()?;
+        line.clear();
+        if input.read_line(&mut line)? == 0 {
+            writeln!(out, "\naborted")?;
+            return Ok(());
+        }
+        match line.trim() {
+            "" => break,
+            "q" | "quit" => {
+                writeln!(out, "aborted")?;
+                return Ok(());
+            }
+            "list" => print_candidates(out, &candidates, &selected)?,
+            spec => match parse_selection(spec, candidates.len()) {
+                Ok((indices, deselect)) => {
+                    for i in indices {
+                        selected[i] = !deselect;
+                    }
+                }
+                Err(e) => writeln!(out, "{e}")?,
+            },
+        }
+    }
+
+    let ids: Vec<i64> = candidates
+        .iter()
+        .zip(&selected)
+        .filter(|(_, s)| **s)
+        .map(|(c, _)| c.id)
+        .collect();
+    if ids.is_empty() {
+        writeln!(out, "nothing selected")?;
+        return Ok(());
+    }
+
+    // Final confirmation: counts per project and session
+    let mut groups: Vec<(&str, &str, usize)> = Vec::new();
+    for c in candidates.iter().zip(&selected).filter(|(_, s)| **s).map(|(c, _)| c) {
+        match groups.iter_mut().find(|(p, s, _)| *p == c.project && *s == c.session_id) {
+            Some(g) => g.2 += 1,
+            None => groups.push((&c.project, &c.session_id, 1)),
+        }
+    }
+    writeln!(out, "\nAbout to delete {} observations:", ids.len())?;
+    for (project, session, n) in &groups {
+        writeln!(out, "  {project}  {session}  {n}")?;
+    }
+    if let Some(path) = &args.export_before {
+        writeln!(out, "Deleted rows will be exported to {}", path.display())?;
+    }
+    write!(out, "Type `yes` to delete: ")?;
+    out.flush()?;
+    line.clear();
+    input.read_line(&mut line)?;
+    if line.trim() != "yes" {
+        writeln!(out, "aborted")?;
+        return Ok(());
+    }
+
+    let ids_json = serde_json::to_string(&ids)?;
+    let tx = conn.unchecked_transaction()?;
+    let mut stash = args.export_before.as_ref().map(|_| Stash::default());
+    let by_id = "id IN (SELECT value FROM json_each(?1))";
+    stash_add(&mut stash, &tx, "observations", by_id, params![ids_json])?;
+    let obs_deleted = tx.execute(&format!("DELETE FROM observations WHERE {by_id}"), params![ids_json])?;
+    stash_add(&mut stash, &tx, "sessions", ORPHAN_SESSIONS, [])?;
+    let sessions_deleted = cleanup_orphans(&tx)?;
+    if let (Some(stash), Some(path)) = (stash, args.export_before.as_ref()) {
+        let rows = stash.write(path)?;
+        writeln!(out, "exported {rows} rows to {}", path.display())?;
+    }
+    let mut filter = purge_filter(args);
+    filter["interactive"] = true.into();
+    filter["selected"] = ids.len().into();
+    log_purge(&tx, filter, serde_json::json!({"observations": obs_deleted, "sessions": sessions_deleted}))?;
+    tx.commit()?;
+    post_purge_maintenance(conn, obs_deleted > FTS_REBUILD_ROWS)?;
+
+    writeln!(out, "purged {obs_deleted} observations, {sessions_deleted} sessions")?;
+    Ok(())
+}
+
+/// Replay a `--export-before` file. Rows that already exist are left alone,
+/// so restoring twice is harmless.
+pub fn restore_from(conn: &Connection, path: &Path) -> Result<Vec<(String, usize)>, NmemError> {
+    let text = std::fs::read_to_string(path)?;
+    let tx = conn.unchecked_transaction()?;
+    let mut restored: Vec<(String, usize)> = Vec::new();
+    for (lineno, line) in text.lines().enumerate().filter(|(_, l)| !l.trim().is_empty()) {
+        let bad = |msg: &str| NmemError::Config(format!("{}:{}: {msg}", path.display(), lineno + 1));
+        let entry: serde_json::Value = serde_json::from_str(line)?;
+        let table = entry["table"].as_str().ok_or_else(|| bad("missing table"))?;
+        let known: bool = is_identifier(table)
+            && tx.query_row(
+                "SELECT EXISTS (SELECT 1 FROM sqlite_master WHERE type = 'table' AND name = ?1)",
+                params![table],
+                |r| r.get(0),
+            )?;
+        if !known {
+            return Err(bad("unknown table"));
+        }
+        let slot = match restored.iter().position(|(t, _)| t == table) {
+            Some(i) => i,
+            None => {
+                restored.push((table.to_string(), 0));
+                restored.len() - 1
+            }
+        };
+        let row = entry["row"].as_object().ok_or_else(|| bad("missing row"))?;
+        if row.is_empty() {
+            return Err(bad("empty row"));
+        }
+
+        let columns: Vec<&str> = row.keys().map(String::as_str).collect();
+        if !columns.iter().all(|c| is_identifier(c)) {
+            return Err(bad("invalid column name"));
+        }
+        let values: Vec<rusqlite::types::Value> = row
+            .values()
+            .map(|v| match v {
+                serde_json::Value::Null => rusqlite::types::Value::Null,
+                serde_json::Value::Number(n) => match n.as_i64() {
+                    Some(i) => rusqlite::types::Value::Integer(i),
+                    None => rusqlite::types::Value::Real(n.as_f64().unwrap_or_default()),
+                },
+                serde_json::Value::String(s) => rusqlite::types::Value::Text(s.clone()),
+                serde_json::Value::Object(o) if o.len() == 1 && o.contains_key("$blob") => {
+                    o["$blob"].as_str().and_then(from_hex).map_or(rusqlite::types::Value::Null, rusqlite::types::Value::Blob)
+                }
+                other => rusqlite::types::Value::Text(other.to_string()),
+            })
+            .collect();
+        let placeholders: Vec<String> = (1..=values.len()).map(|i| format!("?{i}")).collect();
+        let sql = format!(
+            "INSERT OR IGNORE INTO {table} ({}) VALUES ({})",
+            columns.join(", "),
+            placeholders.join(", ")
+        );
+        restored[slot].1 += tx.execute(&sql, rusqlite::params_from_iter(values))?;
+    }
+    tx.commit()?;
+    Ok(restored)
+}
+
+fn from_hex(hex: &str) -> Option<Vec<u8>> {
+    if !hex.len().is_multiple_of(2) || !hex.is_ascii() {
+        return None;
+    }
+    (0..hex.len()).step_by(2).map(|i| u8::from_str_radix(&hex[i..i + 2], 16).ok()).collect()
+}
+
+#[cfg(feature = "cli")]
+pub fn handle_restore(db_path: &Path, args: &RestoreArgs) -> Result<(), NmemError> {
+    let conn = open_db(db_path)?;
+    let restored = restore_from(&conn, &args.file)?;
+    for (table, n) in &restored {
+        log::info!("restored {n} {table}");
+    }
+    Ok(())
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -466,6 +1022,7 @@ mod tests {
         assert_eq!(prompt_count, 0);
     }
 
+    #[cfg(feature = "cli")]
     #[test]
     fn purge_id_is_parameterized() {
         // If build_obs_where uses format!("id = {id}") instead of a parameter,
@@ -480,6 +1037,8 @@ mod tests {
             older_than: None,
             search: None,
             confirm: false,
+            interactive: false,
+            export_before: None,
         };
         let (clause, values) = build_obs_where(&args).unwrap();
         // Should use a bind parameter like "id = ?1", not "id = 42"
@@ -520,4 +1079,127 @@ mod tests {
             .unwrap();
         assert_eq!(remaining, 0, "orphaned observations should be deleted");
     }
+
+    #[test]
+    fn parse_selection_ranges_and_deselect() {
+        assert_eq!(parse_selection("3", 5).unwrap(), (vec![2], false));
+        assert_eq!(parse_selection("1-3, 5", 5).unwrap(), (vec![0, 1, 2, 4], false));
+        assert_eq!(parse_selection("-2-3", 5).unwrap(), (vec![1, 2], true));
+        assert_eq!(parse_selection("!4", 5).unwrap(), (vec![3], true));
+        assert_eq!(parse_selection("all", 2).unwrap(), (vec![0, 1], false));
+        assert_eq!(parse_selection("none", 2).unwrap(), (vec![0, 1], true));
+        assert!(parse_selection("6", 5).is_err());
+        assert!(parse_selection("4-2", 5).is_err());
+        assert!(parse_selection("x", 5).is_err());
+    }
+
+    #[cfg(feature = "cli")]
+    fn search_args(search: &str, export_before: Option<std::path::PathBuf>) -> PurgeArgs {
+        PurgeArgs {
+            id: None,
+            before: None,
+            project: None,
+            session: None,
+            obs_type: None,
+            older_than: None,
+            search: Some(search.into()),
+            confirm: false,
+            interactive: true,
+            export_before,
+        }
+    }
+
+    fn obs_contents(conn: &Connection) -> Vec<String> {
+        conn.prepare("SELECT content FROM observations ORDER BY id")
+            .unwrap()
+            .query_map([], |r| r.get(0))
+            .unwrap()
+            .collect::<Result<_, _>>()
+            .unwrap()
+    }
+
+    #[cfg(feature = "cli")]
+    #[test]
+    fn interactive_purge_deletes_only_selected_and_exports() {
+        let conn = setup_test_db();
+        insert_session(&conn, "s1", "alpha");
+        insert_session(&conn, "s2", "beta");
+        insert_observation(&conn, "s1", "cargo build secret");
+        insert_observation(&conn, "s1", "cargo test secret");
+        insert_observation(&conn, "s2", "npm run secret");
+        insert_observation(&conn, "s2", "unrelated");
+
+        let dir = tempfile::TempDir::new().unwrap();
+        let export = dir.path().join("purged.jsonl");
+        let args = search_args("secret", Some(export.clone()));
+        let mut input = std::io::Cursor::new("-2\n\nyes\n");
+        let mut out = Vec::new();
+        interactive_purge(&conn, &args, &mut input, &mut out).unwrap();
+
+        let out = String::from_utf8(out).unwrap();
+        assert!(out.contains("About to delete 2 observations"));
+        assert!(out.contains("alpha  s1  1"));
+        assert!(out.contains("beta  s2  1"));
+        assert_eq!(obs_contents(&conn), vec!["cargo test secret", "unrelated"]);
+
+        // Restoring puts the deleted rows back, and is idempotent
+        let restored = restore_from(&conn, &export).unwrap();
+        assert!(restored.contains(&("observations".into(), 2)));
+        restore_from(&conn, &export).unwrap();
+        assert_eq!(obs_contents(&conn).len(), 4);
+    }
+
+    #[cfg(feature = "cli")]
+    #[test]
+    fn export_captures_cascaded_rows_and_blobs() {
+        let conn = setup_test_db();
+        insert_session(&conn, "s1", "alpha");
+        insert_observation(&conn, "s1", "cargo build secret with enough words to sign");
+        let obs_id = conn.last_insert_rowid();
+        crate::s1_minhash::store(&conn, obs_id, "cargo build secret with enough words to sign").unwrap();
+        conn.execute(
+            "INSERT INTO responses (session_id, timestamp, content) VALUES ('s1', 1700000001, 'done')",
+            [],
+        )
+        .unwrap();
+        let signature: Vec<u8> =
+            conn.query_row("SELECT signature FROM observation_minhash", [], |r| r.get(0)).unwrap();
+
+        let dir = tempfile::TempDir::new().unwrap();
+        let export = dir.path().join("purged.jsonl");
+        let args = search_args("secret", Some(export.clone()));
+        interactive_purge(&conn, &args, &mut std::io::Cursor::new("\nyes\n"), &mut Vec::new()).unwrap();
+        assert_eq!(conn.query_row("SELECT COUNT(*) FROM responses", [], |r| r.get::<_, i64>(0)).unwrap(), 0);
+
+        // Cascaded children are exported after their parents, blobs as hex
+        let text = std::fs::read_to_string(&export).unwrap();
+        let tables: Vec<String> = text
+            .lines()
+            .map(|l| serde_json::from_str::<serde_json::Value>(l).unwrap()["table"].as_str().unwrap().to_string())
+            .collect();
+        assert_eq!(tables.first().map(String::as_str), Some("sessions"));
+        assert!(tables.contains(&"responses".to_string()));
+        assert!(text.contains(r#""signature":{"$blob":""#));
+
+        let restored = restore_from(&conn, &export).unwrap();
+        assert!(restored.contains(&("observation_minhash".into(), 1)));
+        assert!(restored.contains(&("responses".into(), 1)));
+        let back: Vec<u8> = conn.query_row("SELECT signature FROM observation_minhash", [], |r| r.get(0)).unwrap();
+        assert_eq!(back, signature);
+    }
+
+    #[cfg(feature = "cli")]
+    #[test]
+    fn interactive_purge_aborts_without_yes() {
+        let conn = setup_test_db();
+        insert_session(&conn, "s1", "alpha");
+        insert_observation(&conn, "s1", "cargo build secret");
+
+        let args = search_args("secret", None);
+        let mut input = std::io::Cursor::new("\nno\n");
+        let mut out = Vec::new();
+        interactive_purge(&conn, &args, &mut input, &mut out).unwrap();
+        assert!(String::from_utf8(out).unwrap().ends_with("aborted\n"));
+        assert_eq!(obs_contents(&conn).len(), 1);
+    }
 }
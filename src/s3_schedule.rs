@@ -0,0 +1,217 @@
+//! S3 Control — when heavy background work may run.
+//!
+//! Summarization, episode narratives, retention sweeps, and dispatched tasks
+//! can load a laptop during the workday. `[schedule]` sets the policy:
+//! `quiet_hours` (local windows like `"09:00-18:00"`, wrapping past
+//! midnight allowed) and `defer_on_battery` push that work out of the way;
+//! `max_llm_calls` caps how many processes hold an LLM at once.
+//!
+//! Stop-time maintenance that is deferred queues its session in
+//! `pending_work` (`session_maintain`, which hook-time draining leaves
+//! alone); the next post-session maintenance or `nmem maintain` that is
+//! allowed to run catches those sessions up. `nmem maintain` keeps its light
+//! steps (checkpoint, FTS checks) and skips sweeps and summarization;
+//! `nmem dispatch` leaves tasks queued. `--now` overrides the policy.
+
+use crate::s5_config::ScheduleConfig;
+use crate::NmemError;
+use std::fs::File;
+use std::path::Path;
+use std::time::{Duration, Instant};
+
+/// Pending-work kind of a session whose post-session maintenance was
+/// deferred; payload is the session id.
+pub const SESSION_MAINTAIN: &str = "session_maintain";
+
+/// How long a process waits for an LLM slot before giving up.
+const SLOT_WAIT: Duration = Duration::from_secs(600);
+const SLOT_POLL: Duration = Duration::from_secs(2);
+
+/// Parse a quiet-hours window, `HH:MM-HH:MM`, into seconds since midnight.
+/// The end may be `24:00`; an end before the start wraps past midnight.
+pub fn parse_window(window: &str) -> Result<(i64, i64), NmemError> {
+    let bad = || NmemError::Config(format!("schedule.quiet_hours: {window:?} — expected \"HH:MM-HH:MM\""));
+    let clock = |s: &str| -> Result<i64, NmemError> {
+        let (h, m) = s.trim().split_once(':').ok_or_else(bad)?;
+        let (h, m): (i64, i64) = (h.parse().map_err(|_| bad())?, m.parse().map_err(|_| bad())?);
+        if !(0..=24).contains(&h) || !(0..60).contains(&m) || (h == 24 && m > 0) {
+            return Err(bad());
+        }
+        Ok(h * 3600 + m * 60)
+    };
+    let (start, end) = window.split_once('-').ok_or_else(bad)?;
+    Ok((clock(start)?, clock(end)?))
+}
+
+/// Whether `secs` (since local midnight) falls in a parsed window.
+pub(crate) fn in_window((start, end): (i64, i64), secs: i64) -> bool {
+    if start <= end { (start..end).contains(&secs) } else { secs >= start || secs < end }
+}
+
+/// The quiet-hours window `time_of_day` (seconds since local midnight)
+/// falls in, if any.
+pub fn quiet_window(config: &ScheduleConfig, time_of_day: i64) -> Option<&str> {
+    config
+        .quiet_hours
+        .iter()
+        .find(|w| parse_window(w).is_ok_and(|win| in_window(win, time_of_day)))
+        .map(String::as_str)
+}
+
+/// Battery state from Linux `power_supply` entries under `root`: `Some(true)`
+/// when a battery is discharging and no mains supply is online.
+fn linux_on_battery(root: &Path) -> Option<bool> {
+    let read = |dir: &Path, file: &str| std::fs::read_to_string(dir.join(file)).map(|s| s.trim().to_string());
+    let mut discharging = None;
+    for entry in std::fs::read_dir(root).ok()?.flatten() {
+        let dir = entry.path();
+        match read(&dir, "type").ok().as_deref() {
+            Some("Mains") if read(&dir, "online").is_ok_and(|o| o == "1") => return Some(false),
+            Some("Battery") => {
+                discharging = Some(discharging.unwrap_or(false) || read(&dir, "status").is_ok_and(|s| s == "Discharging"))
+            }
+            _ => {}
+        }
+    }
+    discharging
+}
+
+/// Whether the machine runs on battery. Unknown (desktops, unsupported
+/// platforms) counts as mains power.
+pub fn on_battery() -> bool {
+    if cfg!(target_os = "linux") {
+        return linux_on_battery(Path::new("/sys/class/power_supply")).unwrap_or(false);
+    }
+    if cfg!(target_os = "macos") {
+        return std::process::Command::new("pmset")
+            .args(["-g", "batt"])
+            .output()
+            .is_ok_and(|o| String::from_utf8_lossy(&o.stdout).contains("'Battery Power'"));
+    }
+    false
+}
+
+/// Why heavy work should wait now, or `None` when it may run.
+pub fn defer_reason(config: &ScheduleConfig, now: i64) -> Option<String> {
+    if let Some(window) = quiet_window(config, crate::timefmt::time_of_day(now)) {
+        return Some(format!("quiet hours {window}"));
+    }
+    if config.defer_on_battery && on_battery() {
+        return Some("on battery".into());
+    }
+    None
+}
+
+/// Queue a session's post-session maintenance for an allowed window.
+pub fn defer_session(conn: &rusqlite::Connection, session_id: &str, now: i64) -> Result<(), NmemError> {
+    let queued: bool = conn.query_row(
+        "SELECT EXISTS(SELECT 1 FROM pending_work WHERE kind = ?1 AND payload = ?2)",
+        rusqlite::params![SESSION_MAINTAIN, session_id],
+        |r| r.get(0),
+    )?;
+    if !queued {
+        crate::s3_pending::enqueue(conn, SESSION_MAINTAIN, None, Some(session_id), now)?;
+    }
+    Ok(())
+}
+
+/// Take the sessions whose maintenance was deferred, oldest first.
+pub fn take_deferred(conn: &rusqlite::Connection) -> Result<Vec<String>, NmemError> {
+    let sessions: Vec<String> = conn
+        .prepare("SELECT payload FROM pending_work WHERE kind = ?1 AND payload IS NOT NULL ORDER BY id")?
+        .query_map([SESSION_MAINTAIN], |r| r.get(0))?
+        .collect::<Result<_, _>>()?;
+    conn.execute("DELETE FROM pending_work WHERE kind = ?1", [SESSION_MAINTAIN])?;
+    Ok(sessions)
+}
+
+/// A held LLM slot; released when dropped or when the process exits.
+pub struct LlmSlot {
+    _lock: Option<File>,
+}
+
+/// Wait for one of `[schedule] max_llm_calls` slots, shared by every nmem
+/// process through lock files beside the binary. Unlimited (0) returns at
+/// once.
+pub fn llm_slot() -> Result<LlmSlot, NmemError> {
+    let max = crate::s5_config::load_config().map(|c| c.schedule.max_llm_calls).unwrap_or(0);
+    if max == 0 {
+        return Ok(LlmSlot { _lock: None });
+    }
+    let dir = crate::install_dir().join("locks");
+    std::fs::create_dir_all(&dir)?;
+    let started = Instant::now();
+    let mut logged = false;
+    loop {
+        for i in 0..max {
+            let file = File::options().create(true).truncate(false).write(true).open(dir.join(format!("llm-{i}.lock")))?;
+            if file.try_lock().is_ok() {
+                return Ok(LlmSlot { _lock: Some(file) });
+            }
+        }
+        if started.elapsed() >= SLOT_WAIT {
+            return Err(NmemError::Config(format!(
+                "all {max} LLM slots stayed busy for {}s (schedule.max_llm_calls)",
+                SLOT_WAIT.as_secs()
+            )));
+        }
+        if !logged {
+            log::info!("waiting for an LLM slot ({max} in use)");
+            logged = true;
+        }
+        std::thread::sleep(SLOT_POLL);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn config(quiet_hours: &[&str]) -> ScheduleConfig {
+        ScheduleConfig { quiet_hours: quiet_hours.iter().map(|s| s.to_string()).collect(), ..Default::default() }
+    }
+
+    #[test]
+    fn quiet_windows_including_past_midnight() {
+        let c = config(&["09:00-12:00", "22:30-06:00"]);
+        let at = |h: i64, m: i64| h * 3600 + m * 60;
+        assert_eq!(quiet_window(&c, at(9, 0)), Some("09:00-12:00"));
+        assert_eq!(quiet_window(&c, at(12, 0)), None);
+        assert_eq!(quiet_window(&c, at(23, 15)), Some("22:30-06:00"));
+        assert_eq!(quiet_window(&c, at(5, 59)), Some("22:30-06:00"));
+        assert_eq!(quiet_window(&c, at(14, 0)), None);
+        assert!(quiet_window(&config(&["00:00-24:00"]), at(17, 0)).is_some());
+        assert!(parse_window("9-17").is_err());
+        assert!(parse_window("09:00-25:00").is_err());
+    }
+
+    #[test]
+    fn reads_linux_power_supply() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let supply = |name: &str, files: &[(&str, &str)]| {
+            let d = dir.path().join(name);
+            std::fs::create_dir(&d).unwrap();
+            for (f, v) in files {
+                std::fs::write(d.join(f), format!("{v}\n")).unwrap();
+            }
+        };
+        assert_eq!(linux_on_battery(dir.path()), None, "desktop: no supplies");
+        supply("BAT0", &[("type", "Battery"), ("status", "Discharging")]);
+        assert_eq!(linux_on_battery(dir.path()), Some(true));
+        supply("AC", &[("type", "Mains"), ("online", "1")]);
+        assert_eq!(linux_on_battery(dir.path()), Some(false));
+    }
+
+    #[test]
+    fn deferred_sessions_queue_once_and_are_taken_together() {
+        let mut conn = rusqlite::Connection::open_in_memory().unwrap();
+        crate::schema::MIGRATIONS.to_latest(&mut conn).unwrap();
+        defer_session(&conn, "a", 1).unwrap();
+        defer_session(&conn, "b", 2).unwrap();
+        defer_session(&conn, "a", 3).unwrap();
+        crate::s3_pending::enqueue(&conn, crate::s3_pending::CLASSIFY, None, None, 4).unwrap();
+        assert_eq!(take_deferred(&conn).unwrap(), vec!["a", "b"]);
+        assert!(take_deferred(&conn).unwrap().is_empty());
+        assert_eq!(crate::s3_pending::pending_count(&conn).unwrap(), 1);
+    }
+}
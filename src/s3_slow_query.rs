@@ -0,0 +1,435 @@
+//! S3 Control — slow statements and the indexes they are missing.
+//!
+//! Every connection `db::open_db` hands out is profiled: statements that run
+//! longer than `[diagnostics] slow_query_ms` are buffered in memory (the
+//! profile callback can't touch the database) and written to `slow_queries`
+//! with their `EXPLAIN QUERY PLAN` when the command finishes, or after each
+//! MCP call in the server. The `record` hook never flushes: it must not open
+//! a second connection or write to stderr. Identical SQL text folds into one
+//! row counting calls and time.
+//!
+//! `nmem maintain --advise-indexes` reads that log back. For each statement
+//! whose plan still scans a table or sorts through a temp B-tree, it builds a
+//! candidate index from the statement's own predicates — equality columns
+//! first, then one range or ORDER BY column — and keeps it only when an
+//! in-memory replica of the schema plans the statement better with it.
+//! `--apply` creates the kept indexes.
+
+#[cfg(feature = "cli")]
+use crate::cli::MaintainArgs;
+use crate::db::open_db;
+use crate::NmemError;
+use regex::Regex;
+use rusqlite::trace::{TraceEvent, TraceEventCodes};
+use rusqlite::{Connection, params};
+use serde::Serialize;
+use std::cell::Cell;
+use std::collections::{HashMap, HashSet};
+use std::path::Path;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{LazyLock, Mutex};
+
+/// Slow statements waiting to be written, at most this many per process.
+const MAX_BUFFERED: usize = 200;
+
+/// Columns in a suggested index: up to two equality columns and one range
+/// or ORDER BY column.
+const MAX_EQUALITY_COLUMNS: usize = 2;
+
+static THRESHOLD_MS: AtomicU64 = AtomicU64::new(0);
+static BUFFER: Mutex<Vec<(String, u64)>> = Mutex::new(Vec::new());
+
+thread_local! {
+    /// Set while flushing, so the flush's own statements aren't profiled.
+    static FLUSHING: Cell<bool> = const { Cell::new(false) };
+}
+
+/// Profile `conn`, buffering statements slower than `threshold_ms`
+/// (0 leaves it unprofiled).
+pub fn instrument(conn: &Connection, threshold_ms: u64) {
+    if threshold_ms == 0 {
+        return;
+    }
+    THRESHOLD_MS.store(threshold_ms, Ordering::Relaxed);
+    conn.trace_v2(TraceEventCodes::SQLITE_TRACE_PROFILE, Some(on_profile));
+}
+
+fn on_profile(event: TraceEvent<'_>) {
+    let TraceEvent::Profile(stmt, elapsed) = event else {
+        return;
+    };
+    let ms = elapsed.as_millis() as u64;
+    if ms < THRESHOLD_MS.load(Ordering::Relaxed) || FLUSHING.get() {
+        return;
+    }
+    let sql = stmt.sql().trim().to_string();
+    // Only statements an index can help; maintenance (FTS rebuilds,
+    // PRAGMAs, DDL) is slow by design
+    let verb = sql.split_whitespace().next().unwrap_or_default().to_ascii_uppercase();
+    if !matches!(verb.as_str(), "SELECT" | "WITH" | "UPDATE" | "DELETE") {
+        return;
+    }
+    record(sql, ms);
+}
+
+fn record(sql: String, ms: u64) {
+    if let Ok(mut buffer) = BUFFER.lock()
+        && buffer.len() < MAX_BUFFERED
+    {
+        buffer.push((sql, ms));
+    }
+}
+
+/// `EXPLAIN QUERY PLAN` details of `sql`, one line per plan step, or `None`
+/// when it can't be planned here. Parameters stay unbound.
+pub fn explain(conn: &Connection, sql: &str) -> Option<Vec<String>> {
+    let mut stmt = conn.prepare(&format!("EXPLAIN QUERY PLAN {sql}")).ok()?;
+    let mut rows = stmt.raw_query();
+    let mut plan = Vec::new();
+    while let Some(row) = rows.next().ok()? {
+        plan.push(row.get::<_, String>(3).ok()?);
+    }
+    Some(plan)
+}
+
+/// Write buffered slow statements to `slow_queries`. Returns how many.
+pub fn flush(conn: &Connection, now: i64) -> Result<usize, NmemError> {
+    let pending = std::mem::take(&mut *BUFFER.lock().map_err(|e| NmemError::Config(format!("slow query buffer: {e}")))?);
+    if pending.is_empty() {
+        return Ok(0);
+    }
+    FLUSHING.set(true);
+    // Plans may call the scoring functions; already registered is fine
+    let _ = crate::db::register_udfs(conn);
+    let written = pending.iter().try_for_each(|(sql, ms)| {
+        let plan = explain(conn, sql).map(|p| p.join("\n"));
+        conn.execute(
+            "INSERT INTO slow_queries (sql, calls, total_ms, max_ms, plan, first_seen, last_seen)
+             VALUES (?1, 1, ?2, ?2, ?3, ?4, ?4)
+             ON CONFLICT(sql) DO UPDATE SET calls = calls + 1, total_ms = total_ms + ?2,
+                 max_ms = max(max_ms, ?2), plan = COALESCE(?3, plan), last_seen = ?4",
+            params![sql, *ms as i64, plan, now],
+        )
+        .map(|_| ())
+    });
+    FLUSHING.set(false);
+    written?;
+    Ok(pending.len())
+}
+
+/// Flush through a fresh connection at the end of a command. Non-fatal.
+pub fn flush_pending(db_path: &Path) {
+    if BUFFER.lock().map(|b| b.is_empty()).unwrap_or(true) {
+        return;
+    }
+    let now = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs() as i64;
+    if let Err(e) = open_db(db_path).and_then(|conn| flush(&conn, now)) {
+        log::debug!("slow query log: {e}");
+    }
+}
+
+#[derive(Debug, Serialize, PartialEq)]
+pub struct IndexAdvice {
+    pub table: String,
+    pub columns: Vec<String>,
+    /// Slow calls of the statements it helps.
+    pub calls: i64,
+    pub total_ms: i64,
+    /// Slowest statement it helps.
+    pub example: String,
+}
+
+impl IndexAdvice {
+    pub fn name(&self) -> String {
+        format!("idx_advised_{}_{}", self.table, self.columns.join("_"))
+    }
+
+    pub fn create_sql(&self) -> String {
+        format!("CREATE INDEX IF NOT EXISTS {} ON {}({})", self.name(), self.table, self.columns.join(", "))
+    }
+}
+
+const SQL_KEYWORDS: &[&str] = &[
+    "where", "join", "left", "inner", "cross", "outer", "on", "using", "order", "group", "limit", "union", "set",
+    "natural", "having", "window", "as",
+];
+
+static TABLE_REF: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"(?i)\b(?:FROM|JOIN|UPDATE)\s+(\w+)(?:\s+(?:AS\s+)?(\w+))?").unwrap());
+static PREDICATE: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(r"(?i)(?:\b(\w+)\.)?\b(\w+)\s*(==|!=|<>|<=|>=|=|<|>|\bIS\s+NOT\b|\bNOT\s+IN\b|\bIN\b|\bIS\b|\bBETWEEN\b)")
+        .unwrap()
+});
+static ORDER_BY: LazyLock<Regex> = LazyLock::new(|| Regex::new(r"(?i)\bORDER\s+BY\s+(?:(\w+)\.)?(\w+)").unwrap());
+
+/// Names a statement uses for tables (the table itself and any alias),
+/// resolved to the real tables in `tables`.
+fn table_names(sql: &str, tables: &HashMap<String, HashSet<String>>) -> HashMap<String, String> {
+    let mut names = HashMap::new();
+    for c in TABLE_REF.captures_iter(sql) {
+        let table = c[1].to_string();
+        if !tables.contains_key(&table) {
+            continue;
+        }
+        names.insert(table.clone(), table.clone());
+        if let Some(alias) = c.get(2).map(|m| m.as_str())
+            && !SQL_KEYWORDS.contains(&alias.to_ascii_lowercase().as_str())
+        {
+            names.insert(alias.to_string(), table);
+        }
+    }
+    names
+}
+
+/// The candidate index on `table` for `sql`: equality columns in the order
+/// they appear, then the first range or ORDER BY column.
+fn candidate(sql: &str, table: &str, columns: &HashSet<String>, names: &HashMap<String, String>) -> Vec<String> {
+    let on_table = |qualifier: Option<&str>, column: &str| {
+        columns.contains(column) && qualifier.is_none_or(|q| names.get(q).is_some_and(|t| t == table))
+    };
+    let (mut equality, mut range) = (Vec::new(), Vec::new());
+    for c in PREDICATE.captures_iter(sql) {
+        let column = c[2].to_string();
+        if !on_table(c.get(1).map(|m| m.as_str()), &column) {
+            continue;
+        }
+        let op = c[3].to_ascii_uppercase();
+        let op = op.split_whitespace().collect::<Vec<_>>().join(" ");
+        match op.as_str() {
+            "=" | "==" | "IN" | "IS" => equality.push(column),
+            "<" | ">" | "<=" | ">=" | "BETWEEN" => range.push(column),
+            _ => {}
+        }
+    }
+    for c in ORDER_BY.captures_iter(sql) {
+        if on_table(c.get(1).map(|m| m.as_str()), &c[2]) {
+            range.push(c[2].to_string());
+        }
+    }
+    let mut index: Vec<String> = Vec::new();
+    for column in equality {
+        if index.len() < MAX_EQUALITY_COLUMNS && !index.contains(&column) {
+            index.push(column);
+        }
+    }
+    if let Some(column) = range.into_iter().find(|c| !index.contains(c)) {
+        index.push(column);
+    }
+    index
+}
+
+/// Plan steps an index could remove: full scans of real tables and temp
+/// B-tree sorts.
+fn plan_cost(plan: &[String], names: &HashMap<String, String>) -> usize {
+    plan.iter()
+        .filter(|step| {
+            step.contains("USE TEMP B-TREE")
+                || step
+                    .strip_prefix("SCAN ")
+                    .and_then(|rest| rest.split_whitespace().next())
+                    .is_some_and(|name| names.contains_key(name) && !step.contains("VIRTUAL TABLE"))
+        })
+        .count()
+}
+
+/// Tables the plan reads, by their real name.
+fn planned_tables(plan: &[String], names: &HashMap<String, String>) -> Vec<String> {
+    let mut tables = Vec::new();
+    for step in plan {
+        let name = step
+            .strip_prefix("SCAN ")
+            .or_else(|| step.strip_prefix("SEARCH "))
+            .and_then(|rest| rest.split_whitespace().next());
+        if let Some(table) = name.and_then(|n| names.get(n))
+            && !tables.contains(table)
+        {
+            tables.push(table.clone());
+        }
+    }
+    tables
+}
+
+/// The schema of `conn` in an empty in-memory database, where candidate
+/// indexes cost nothing to try.
+fn schema_replica(conn: &Connection) -> Result<Connection, NmemError> {
+    let replica = Connection::open_in_memory()?;
+    crate::db::register_udfs(&replica)?;
+    let ddl: Vec<String> = conn
+        .prepare("SELECT sql FROM sqlite_master WHERE sql IS NOT NULL AND type IN ('table', 'index', 'view') ORDER BY rowid")?
+        .query_map([], |r| r.get(0))?
+        .collect::<Result<_, _>>()?;
+    for statement in ddl {
+        // FTS shadow tables already exist once their virtual table does
+        let _ = replica.execute_batch(&statement);
+    }
+    Ok(replica)
+}
+
+/// Leading columns of each index on each table.
+fn existing_indexes(conn: &Connection) -> Result<HashMap<String, Vec<Vec<String>>>, NmemError> {
+    let mut indexes: HashMap<String, Vec<Vec<String>>> = HashMap::new();
+    let pairs: Vec<(String, String)> = conn
+        .prepare("SELECT tbl_name, name FROM sqlite_master WHERE type = 'index'")?
+        .query_map([], |r| Ok((r.get(0)?, r.get(1)?)))?
+        .collect::<Result<_, _>>()?;
+    for (table, index) in pairs {
+        let columns: Vec<String> = conn
+            .prepare("SELECT name FROM pragma_index_info(?1) ORDER BY seqno")?
+            .query_map([&index], |r| r.get::<_, Option<String>>(0))?
+            .filter_map(|c| c.ok().flatten())
+            .collect();
+        indexes.entry(table).or_default().push(columns);
+    }
+    Ok(indexes)
+}
+
+/// Indexes that would improve the plans of logged slow statements, most
+/// total slow time first.
+pub fn advise(conn: &Connection) -> Result<Vec<IndexAdvice>, NmemError> {
+    let replica = schema_replica(conn)?;
+    let mut tables: HashMap<String, HashSet<String>> = HashMap::new();
+    let names: Vec<String> = replica
+        .prepare("SELECT name FROM sqlite_master WHERE type = 'table' AND sql NOT LIKE 'CREATE VIRTUAL%'")?
+        .query_map([], |r| r.get(0))?
+        .collect::<Result<_, _>>()?;
+    for table in names {
+        let columns: HashSet<String> = replica
+            .prepare("SELECT name FROM pragma_table_info(?1)")?
+            .query_map([&table], |r| r.get(0))?
+            .collect::<Result<_, _>>()?;
+        tables.insert(table, columns);
+    }
+    let existing = existing_indexes(&replica)?;
+
+    let logged: Vec<(String, i64, i64)> = conn
+        .prepare("SELECT sql, calls, total_ms FROM slow_queries ORDER BY max_ms DESC")?
+        .query_map([], |r| Ok((r.get(0)?, r.get(1)?, r.get(2)?)))?
+        .collect::<Result<_, _>>()?;
+
+    let mut advice: Vec<IndexAdvice> = Vec::new();
+    for (sql, calls, total_ms) in logged {
+        let Some(plan) = explain(&replica, &sql) else {
+            continue;
+        };
+        let names = table_names(&sql, &tables);
+        let cost = plan_cost(&plan, &names);
+        if cost == 0 {
+            continue;
+        }
+        for table in planned_tables(&plan, &names) {
+            let columns = candidate(&sql, &table, &tables[&table], &names);
+            let covered = existing
+                .get(&table)
+                .is_some_and(|idx| idx.iter().any(|have| have.starts_with(&columns)));
+            if columns.is_empty() || covered {
+                continue;
+            }
+            if let Some(known) = advice.iter_mut().find(|a| a.table == table && a.columns == columns) {
+                known.calls += calls;
+                known.total_ms += total_ms;
+                continue;
+            }
+            let trial = IndexAdvice { table: table.clone(), columns, calls, total_ms, example: sql.clone() };
+            replica.execute_batch(&trial.create_sql())?;
+            let improved = explain(&replica, &sql).is_some_and(|p| plan_cost(&p, &names) < cost);
+            replica.execute_batch(&format!("DROP INDEX {}", trial.name()))?;
+            if improved {
+                advice.push(trial);
+            }
+        }
+    }
+    advice.sort_by_key(|a| std::cmp::Reverse(a.total_ms));
+    Ok(advice)
+}
+
+#[cfg(feature = "cli")]
+pub fn handle_advise(db_path: &Path, args: &MaintainArgs) -> Result<(), NmemError> {
+    let conn = open_db(db_path)?;
+    let logged: i64 = conn.query_row("SELECT COUNT(*) FROM slow_queries", [], |r| r.get(0))?;
+    let advice = advise(&conn)?;
+    if advice.is_empty() {
+        log::info!("index advice — none ({logged} slow statements logged)");
+        return Ok(());
+    }
+    for a in &advice {
+        let example: String = a.example.split_whitespace().collect::<Vec<_>>().join(" ").chars().take(120).collect();
+        log::info!(
+            "index advice — {}({}): {} slow calls, {} ms total — e.g. {example}",
+            a.table,
+            a.columns.join(", "),
+            a.calls,
+            a.total_ms
+        );
+        if args.apply {
+            conn.execute_batch(&a.create_sql())?;
+            log::info!("created {}", a.name());
+        }
+    }
+    if !args.apply {
+        log::info!("pass --apply to create {} index(es)", advice.len());
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// The buffer is process-wide; these tests take turns with it.
+    static SERIAL: Mutex<()> = Mutex::new(());
+
+    fn setup() -> Connection {
+        let mut conn = Connection::open_in_memory().unwrap();
+        crate::schema::MIGRATIONS.to_latest(&mut conn).unwrap();
+        conn
+    }
+
+    #[test]
+    fn slow_statements_fold_by_sql_with_their_plan() {
+        let _serial = SERIAL.lock().unwrap();
+        let conn = setup();
+        let sql = "SELECT id FROM observations WHERE tool_name = ?1";
+        record(sql.into(), 300);
+        record(sql.into(), 500);
+        assert!(flush(&conn, 100).unwrap() >= 2);
+        let (calls, total, max, plan): (i64, i64, i64, String) = conn
+            .query_row("SELECT calls, total_ms, max_ms, plan FROM slow_queries WHERE sql = ?1", [sql], |r| {
+                Ok((r.get(0)?, r.get(1)?, r.get(2)?, r.get(3)?))
+            })
+            .unwrap();
+        assert_eq!((calls, total, max), (2, 800, 500));
+        assert!(plan.starts_with("SCAN observations"), "{plan}");
+    }
+
+    #[test]
+    fn advises_indexes_that_remove_scans_and_sorts() {
+        let _serial = SERIAL.lock().unwrap();
+        let conn = setup();
+        for (sql, ms) in [
+            ("SELECT o.id FROM observations o JOIN sessions s ON s.id = o.session_id
+              WHERE o.file_path = ?1 AND s.project = ?2 ORDER BY o.timestamp DESC LIMIT 20", 900),
+            ("SELECT id, content FROM observations WHERE tool_name = ?1 AND timestamp > ?2", 400),
+            // Already served by idx_obs_session
+            ("SELECT id FROM observations WHERE session_id = ?1 ORDER BY timestamp", 300),
+        ] {
+            record(sql.into(), ms);
+        }
+        flush(&conn, 1).unwrap();
+
+        let advice = advise(&conn).unwrap();
+        let suggested: Vec<(&str, Vec<&str>)> =
+            advice.iter().map(|a| (a.table.as_str(), a.columns.iter().map(String::as_str).collect())).collect();
+        assert_eq!(
+            suggested,
+            vec![("observations", vec!["file_path", "timestamp"]), ("observations", vec!["tool_name", "timestamp"])]
+        );
+
+        for a in &advice {
+            conn.execute_batch(&a.create_sql()).unwrap();
+        }
+        assert!(advise(&conn).unwrap().is_empty(), "applied advice is not repeated");
+    }
+}
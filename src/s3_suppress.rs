@@ -0,0 +1,256 @@
+//! S3 Control — keeping past work out of context on purpose.
+//!
+//! For a clean-room rewrite the agent shouldn't be steered by how a file or
+//! subject was handled before. `nmem suppress --file <path>` or `--topic
+//! <keywords>` stores a rule (all projects, or one; `--for` makes it lapse);
+//! while active, context generation drops the episodes, summaries, tasks,
+//! investigations, topics, docs, and activity that match it and notes how
+//! many items it held back, and `file_history` leaves out matching touches.
+//! Nothing is deleted: lifting the rule (`nmem suppress --remove <id>`)
+//! brings it all back. `nmem status` lists active rules.
+
+#[cfg(feature = "cli")]
+use crate::cli::SuppressArgs;
+use crate::NmemError;
+use rusqlite::{Connection, params};
+use serde::Serialize;
+use std::collections::HashSet;
+#[cfg(feature = "cli")]
+use std::path::Path;
+
+pub const FILE: &str = "file";
+pub const TOPIC: &str = "topic";
+
+#[derive(Debug, Clone, Serialize, PartialEq)]
+pub struct Suppression {
+    pub id: i64,
+    /// `None` for all projects.
+    pub project: Option<String>,
+    /// `file` or `topic`.
+    pub kind: String,
+    /// A path (file, directory, or trailing part of either), or keywords
+    /// that must all appear.
+    pub pattern: String,
+    pub created_at: i64,
+    /// `None` until removed.
+    pub until: Option<i64>,
+}
+
+impl Suppression {
+    fn from_row(r: &rusqlite::Row) -> rusqlite::Result<Self> {
+        Ok(Suppression {
+            id: r.get(0)?,
+            project: r.get(1)?,
+            kind: r.get(2)?,
+            pattern: r.get(3)?,
+            created_at: r.get(4)?,
+            until: r.get(5)?,
+        })
+    }
+
+    /// One line for status and the CLI.
+    pub fn describe(&self) -> String {
+        let scope = match &self.project {
+            Some(p) => format!("in {p}"),
+            None => "in all projects".into(),
+        };
+        let until = match self.until {
+            Some(ts) => format!("until {}", crate::timefmt::datetime(ts)),
+            None => format!("until `nmem suppress --remove {}`", self.id),
+        };
+        format!("#{} {} {:?} {scope} {until}", self.id, self.kind, self.pattern)
+    }
+
+    /// Whether `path` is the suppressed file, lies under it, or names it
+    /// by a trailing part (`src/auth.rs` for `/repo/src/auth.rs`).
+    pub fn matches_path(&self, path: &str) -> bool {
+        if self.kind != FILE {
+            return false;
+        }
+        let rule = self.pattern.trim_end_matches('/');
+        let path = path.trim_end_matches('/');
+        if rule.is_empty() || path.is_empty() {
+            return false;
+        }
+        let under = |outer: &str, inner: &str| {
+            inner == outer
+                || inner.starts_with(&format!("{outer}/"))
+                || inner.ends_with(&format!("/{outer}"))
+                || inner.contains(&format!("/{outer}/"))
+        };
+        under(rule, path) || (!path.starts_with('/') && rule.ends_with(&format!("/{path}")))
+    }
+
+    /// Whether every keyword of a topic rule appears as a word in `text`.
+    pub fn matches_text(&self, text: &str) -> bool {
+        if self.kind != TOPIC {
+            return false;
+        }
+        let keywords = words(&self.pattern);
+        !keywords.is_empty() && keywords.is_subset(&words(text))
+    }
+}
+
+fn words(text: &str) -> HashSet<String> {
+    text.split(|c: char| !c.is_alphanumeric())
+        .filter(|w| !w.is_empty())
+        .map(str::to_lowercase)
+        .collect()
+}
+
+/// The rules in effect for one project.
+#[derive(Debug, Default)]
+pub struct Suppressions(pub Vec<Suppression>);
+
+impl Suppressions {
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    /// Whether an item about `texts` touching `paths` is held back.
+    pub fn hides<'a>(
+        &self,
+        texts: impl IntoIterator<Item = &'a str>,
+        paths: impl IntoIterator<Item = &'a str>,
+    ) -> bool {
+        if self.0.is_empty() {
+            return false;
+        }
+        paths.into_iter().any(|p| self.0.iter().any(|s| s.matches_path(p)))
+            || texts.into_iter().any(|t| self.0.iter().any(|s| s.matches_text(t)))
+    }
+}
+
+/// Add a rule; returns its id.
+pub fn add(
+    conn: &Connection,
+    project: Option<&str>,
+    kind: &str,
+    pattern: &str,
+    until: Option<i64>,
+    now: i64,
+) -> Result<i64, NmemError> {
+    let pattern = pattern.trim();
+    if pattern.is_empty() {
+        return Err(NmemError::Config(format!("suppress: empty --{kind}")));
+    }
+    conn.execute(
+        "INSERT INTO context_suppressions (project, kind, pattern, created_at, until) VALUES (?1, ?2, ?3, ?4, ?5)",
+        params![project, kind, pattern, now, until],
+    )?;
+    Ok(conn.last_insert_rowid())
+}
+
+/// Remove a rule. Returns whether it existed.
+pub fn remove(conn: &Connection, id: i64) -> Result<bool, NmemError> {
+    Ok(conn.execute("DELETE FROM context_suppressions WHERE id = ?1", [id])? > 0)
+}
+
+/// Every rule still in effect at `now`.
+pub fn list_active(conn: &Connection, now: i64) -> Result<Vec<Suppression>, NmemError> {
+    let mut stmt = conn.prepare(
+        "SELECT id, project, kind, pattern, created_at, until FROM context_suppressions
+         WHERE until IS NULL OR until > ?1
+         ORDER BY id",
+    )?;
+    let rows = stmt.query_map([now], Suppression::from_row)?.collect::<Result<_, _>>()?;
+    Ok(rows)
+}
+
+/// Rules covering `project` at `now`. Databases not yet migrated have none.
+pub fn active(conn: &Connection, project: &str, now: i64) -> Suppressions {
+    let rules = list_active(conn, now).unwrap_or_default();
+    Suppressions(rules.into_iter().filter(|s| s.project.as_deref().is_none_or(|p| p == project)).collect())
+}
+
+#[cfg(feature = "cli")]
+pub fn handle_suppress(db_path: &Path, args: &SuppressArgs) -> Result<(), NmemError> {
+    let now = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs() as i64;
+    let conn = crate::db::open_db(db_path)?;
+    if let Some(id) = args.remove {
+        if !remove(&conn, id)? {
+            return Err(NmemError::Config(format!("no suppression #{id}")));
+        }
+        log::info!("suppression #{id} removed");
+        return Ok(());
+    }
+    let rule = match (&args.file, &args.topic) {
+        (Some(path), _) => Some((FILE, path)),
+        (_, Some(keywords)) => Some((TOPIC, keywords)),
+        _ => None,
+    };
+    if let Some((kind, pattern)) = rule {
+        let until = match args.duration.as_deref() {
+            Some(d) => Some(
+                now + crate::s4_dispatch::parse_relative(d).ok_or_else(|| {
+                    NmemError::Config(format!("cannot parse --for: {d:?} — try \"2h\", \"1d\", or \"7d\""))
+                })?,
+            ),
+            None => None,
+        };
+        add(&conn, args.project.as_deref(), kind, pattern, until, now)?;
+    }
+    let rules = list_active(&conn, now)?;
+    if rules.is_empty() {
+        log::info!("no active suppressions");
+    }
+    for rule in &rules {
+        log::info!("{}", rule.describe());
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rule(kind: &str, pattern: &str) -> Suppression {
+        Suppression { id: 1, project: None, kind: kind.into(), pattern: pattern.into(), created_at: 0, until: None }
+    }
+
+    #[test]
+    fn paths_match_files_directories_and_trailing_parts() {
+        let file = rule(FILE, "src/auth.rs");
+        assert!(file.matches_path("src/auth.rs"));
+        assert!(file.matches_path("/repo/src/auth.rs"));
+        assert!(!file.matches_path("/repo/src/auth.rs.bak"));
+        assert!(!file.matches_path("/repo/othersrc/auth.rs"));
+
+        let dir = rule(FILE, "/repo/src/auth/");
+        assert!(dir.matches_path("/repo/src/auth/token.rs"));
+        assert!(dir.matches_path("src/auth"), "relative name of the directory");
+        assert!(!dir.matches_path("/repo/src/authz.rs"));
+        assert!(!rule(TOPIC, "src/auth.rs").matches_path("src/auth.rs"));
+    }
+
+    #[test]
+    fn topics_need_every_keyword() {
+        let topic = rule(TOPIC, "JWT refresh");
+        assert!(topic.matches_text("Fix jwt token refresh race"));
+        assert!(!topic.matches_text("Fix jwt signing"));
+        assert!(!topic.matches_text("jwtrefresh"));
+    }
+
+    #[test]
+    fn rules_scope_by_project_and_lapse() {
+        let mut conn = Connection::open_in_memory().unwrap();
+        crate::schema::MIGRATIONS.to_latest(&mut conn).unwrap();
+        add(&conn, Some("web"), FILE, "src/auth.rs", Some(200), 100).unwrap();
+        let all = add(&conn, None, TOPIC, "billing", None, 100).unwrap();
+        assert!(add(&conn, None, TOPIC, "  ", None, 100).is_err());
+
+        assert_eq!(active(&conn, "web", 150).0.len(), 2);
+        assert_eq!(active(&conn, "api", 150).0.len(), 1);
+        assert_eq!(active(&conn, "web", 200).0.len(), 1, "lapses at until");
+        assert!(active(&conn, "web", 150).hides(["billing export"], []));
+        assert!(active(&conn, "web", 150).hides([], ["/w/src/auth.rs"]));
+        assert!(!active(&conn, "api", 150).hides([], ["/w/src/auth.rs"]));
+
+        assert!(remove(&conn, all).unwrap());
+        assert!(!remove(&conn, all).unwrap());
+        assert_eq!(list_active(&conn, 150).unwrap().len(), 1);
+    }
+}
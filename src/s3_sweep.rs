@@ -1,5 +1,6 @@
-use crate::s5_config::RetentionConfig;
-use crate::s3_purge::{cleanup_orphans, post_purge_maintenance};
+use crate::s3_importance::recency;
+use crate::s5_config::{NmemConfig, RetentionConfig, resolve_importance, resolve_recency_half_life};
+use crate::s3_purge::{FTS_REBUILD_ROWS, cleanup_orphans, post_purge_maintenance};
 use crate::NmemError;
 use rusqlite::{Connection, params};
 use std::time::{SystemTime, UNIX_EPOCH};
@@ -8,6 +9,7 @@ pub struct SweepResult {
     pub deleted: usize,
     pub by_type: Vec<(String, usize)>,
     pub orphans_cleaned: usize,
+    pub snapshots_deleted: usize,
 }
 
 fn has_syntheses_table(conn: &Connection) -> bool {
@@ -20,12 +22,36 @@ fn has_syntheses_table(conn: &Connection) -> bool {
         > 0
 }
 
-pub fn run_sweep(conn: &Connection, config: &RetentionConfig) -> Result<SweepResult, NmemError> {
+/// Observations that may be swept: covered by a session summary written
+/// after them or by a finished episode, so something durable outlives the raw
+/// rows. Only those with neither are refused.
+const DISTILLED_SQL: &str = "
+    AND (EXISTS (SELECT 1 FROM sessions s WHERE s.id = observations.session_id
+                 AND s.summary IS NOT NULL AND observations.timestamp <= s.summarized_at)
+         OR EXISTS (SELECT 1 FROM work_units w WHERE w.session_id = observations.session_id
+                    AND observations.timestamp <= w.ended_at))";
+
+/// Without the invariants: any summarized session, no minimum age.
+const FORCED_SQL: &str = "
+    AND session_id IN (SELECT id FROM sessions WHERE summary IS NOT NULL)";
+
+/// Delete observations past their type's retention, shortened for archived
+/// projects by `archived_retention_pct`. Unless `force`, an
+/// observation is only deleted when its session has a summary written after
+/// it or an episode that ended after it, and it is older than `min_age_hours`.
+/// Each `deletions_log` entry is signed with `key` when one is given.
+pub fn run_sweep(
+    conn: &Connection,
+    config: &RetentionConfig,
+    force: bool,
+    key: Option<&[u8]>,
+) -> Result<SweepResult, NmemError> {
     if !config.enabled {
         return Ok(SweepResult {
             deleted: 0,
             by_type: Vec::new(),
             orphans_cleaned: 0,
+            snapshots_deleted: 0,
         });
     }
 
@@ -35,32 +61,62 @@ pub fn run_sweep(conn: &Connection, config: &RetentionConfig) -> Result<SweepRes
         .unwrap_or_default()
         .as_secs() as i64
         + 1;
-
-    let has_syntheses = has_syntheses_table(conn);
-    let tx = conn.unchecked_transaction()?;
+    let min_age_cutoff = now - config.min_age_hours as i64 * 3600;
+
+    let sessions_clause = if force { FORCED_SQL } else { DISTILLED_SQL };
+    let synthesis_clause = if has_syntheses_table(conn) {
+        "AND id NOT IN (SELECT value FROM syntheses, json_each(syntheses.source_obs_ids))"
+    } else {
+        ""
+    };
+    // ?3 is the shorter cutoff for archived projects, ?4 the chunk size
+    let sql = format!(
+        "DELETE FROM observations WHERE id IN (
+             SELECT id FROM observations WHERE obs_type = ?1
+             AND (timestamp < ?2
+                  OR (timestamp < ?3 AND session_id IN (
+                      SELECT s.id FROM sessions s JOIN archived_projects a ON a.project = s.project)))
+             AND is_pinned = 0 {sessions_clause} {synthesis_clause}
+             LIMIT ?4)"
+    );
+
+    let filter = serde_json::json!({
+        "force": force,
+        "days": config.days.iter().collect::<std::collections::BTreeMap<_, _>>(),
+        "snapshot_days": config.snapshot_days,
+        "min_age_hours": config.min_age_hours,
+    });
+    // Every committed chunk carries its own `deletions_log` entry, so a
+    // sweep cut short still accounts for what it deleted
+    let audit = |tx: &Connection, deleted: usize, details: serde_json::Value| {
+        let deletion = crate::s3_deletions::Deletion {
+            operation: "sweep",
+            filter: filter.clone(),
+            deleted: deleted as i64,
+            details,
+        };
+        crate::s3_deletions::record(tx, &deletion, key, now - 1)
+    };
 
     let mut total_deleted = 0usize;
     let mut by_type = Vec::new();
 
     for (obs_type, days) in &config.days {
-        let cutoff = now - (*days as i64 * 86400);
-
-        let deleted = if has_syntheses {
-            tx.execute(
-                "DELETE FROM observations WHERE obs_type = ?1 AND timestamp < ?2
-                 AND is_pinned = 0
-                 AND session_id IN (SELECT id FROM sessions WHERE summary IS NOT NULL)
-                 AND id NOT IN (SELECT value FROM syntheses, json_each(syntheses.source_obs_ids))",
-                params![obs_type, cutoff],
-            )?
-        } else {
-            tx.execute(
-                "DELETE FROM observations WHERE obs_type = ?1 AND timestamp < ?2
-                 AND is_pinned = 0
-                 AND session_id IN (SELECT id FROM sessions WHERE summary IS NOT NULL)",
-                params![obs_type, cutoff],
-            )?
-        };
+        let mut cutoff = now - (*days as i64 * 86400);
+        let archived_days = *days as i64 * config.archived_retention_pct.min(100) as i64 / 100;
+        let mut archived_cutoff = now - archived_days * 86400;
+        if !force {
+            cutoff = cutoff.min(min_age_cutoff);
+            archived_cutoff = archived_cutoff.min(min_age_cutoff);
+        }
+
+        let deleted = delete_in_chunks(conn, config, |tx, chunk| {
+            let deleted = tx.execute(&sql, params![obs_type, cutoff, archived_cutoff, chunk])?;
+            if deleted > 0 {
+                audit(tx, deleted, serde_json::json!({"by_type": {obs_type: deleted}}))?;
+            }
+            Ok(deleted)
+        })?;
 
         if deleted > 0 {
             by_type.push((obs_type.clone(), deleted));
@@ -68,22 +124,195 @@ pub fn run_sweep(conn: &Connection, config: &RetentionConfig) -> Result<SweepRes
         }
     }
 
+    // Snapshots have their own tier, independent of summarization
+    let snapshot_cutoff = now - config.snapshot_days as i64 * 86400;
+    let snapshots_deleted = delete_in_chunks(conn, config, |tx, chunk| {
+        let deleted = tx.execute(
+            "DELETE FROM file_snapshots WHERE id IN (
+                 SELECT id FROM file_snapshots WHERE captured_at < ?1 LIMIT ?2)",
+            params![snapshot_cutoff, chunk],
+        )?;
+        if deleted > 0 {
+            audit(tx, deleted, serde_json::json!({"snapshots": deleted}))?;
+        }
+        Ok(deleted)
+    })?;
+
+    let tx = conn.unchecked_transaction()?;
     let orphans_cleaned = cleanup_orphans(&tx)?;
+    if orphans_cleaned > 0 {
+        audit(&tx, orphans_cleaned, serde_json::json!({"orphan_sessions": orphans_cleaned}))?;
+    }
     tx.commit()?;
 
-    post_purge_maintenance(conn, total_deleted)?;
+    // The FTS triggers kept the indexes in step chunk by chunk; the full
+    // rebuild a large purge gets would take back the long write lock
+    post_purge_maintenance(conn, false)?;
 
     Ok(SweepResult {
         deleted: total_deleted,
         by_type,
         orphans_cleaned,
+        snapshots_deleted,
     })
 }
 
+/// Whether a sweep may run at `now`: always without a `sweep_window`,
+/// otherwise only inside it (local time).
+pub fn in_sweep_window(config: &RetentionConfig, now: i64) -> Result<bool, NmemError> {
+    let Some(window) = &config.sweep_window else {
+        return Ok(true);
+    };
+    let window = crate::s3_schedule::parse_window(window).map_err(|_| {
+        NmemError::Config(format!("retention.sweep_window: {window:?} — expected \"HH:MM-HH:MM\""))
+    })?;
+    Ok(crate::s3_schedule::in_window(window, crate::timefmt::time_of_day(now)))
+}
+
+/// Run `delete` (a DELETE bounded by the chunk size it is given) until a
+/// chunk comes back short, each chunk in a transaction of its own with
+/// `sweep_pause_ms` between them. Returns the rows deleted.
+fn delete_in_chunks(
+    conn: &Connection,
+    config: &RetentionConfig,
+    mut delete: impl FnMut(&Connection, i64) -> Result<usize, NmemError>,
+) -> Result<usize, NmemError> {
+    let chunk = config.sweep_chunk.max(1);
+    let mut total = 0;
+    loop {
+        let tx = conn.unchecked_transaction()?;
+        let deleted = delete(&tx, chunk as i64)?;
+        tx.commit()?;
+        total += deleted;
+        if deleted < chunk {
+            return Ok(total);
+        }
+        log::debug!("sweep — {total} deleted so far, pausing");
+        std::thread::sleep(std::time::Duration::from_millis(config.sweep_pause_ms));
+    }
+}
+
+/// Bytes an observation occupies: content, metadata, and its chunks.
+const OBS_BYTES_SQL: &str = "
+    length(CAST(o.content AS BLOB)) + COALESCE(length(CAST(o.metadata AS BLOB)), 0)
+    + COALESCE((SELECT SUM(length(CAST(c.content AS BLOB))) FROM observation_chunks c WHERE c.obs_id = o.id), 0)";
+
+/// Evictions for one project over its `max_size_mb`.
+pub struct QuotaResult {
+    pub project: String,
+    pub quota_bytes: i64,
+    pub before_bytes: i64,
+    pub after_bytes: i64,
+    pub evicted: usize,
+}
+
+/// Bring each project with a `max_size_mb` under it by deleting its least
+/// important observations first (the importance model's recent blend), among
+/// those evictable: unpinned, not failures, and not recorded in an episode's
+/// `obs_trace`. A project can stay over quota when nothing else is
+/// evictable — `after_bytes` says by how much.
+pub fn enforce_project_quotas(conn: &Connection, config: &NmemConfig) -> Result<Vec<QuotaResult>, NmemError> {
+    let model = resolve_importance(config);
+    let now = SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default().as_secs() as i64;
+    let mut quotas: Vec<(&String, u32)> = config
+        .projects
+        .iter()
+        .filter_map(|(name, pc)| pc.max_size_mb.map(|mb| (name, mb)))
+        .collect();
+    quotas.sort();
+
+    let tx = conn.unchecked_transaction()?;
+    let mut results = Vec::new();
+    let mut total_evicted = 0usize;
+    for (project, mb) in quotas {
+        let quota_bytes = mb as i64 * 1024 * 1024;
+        let before_bytes: i64 = tx.query_row(
+            &format!(
+                "SELECT COALESCE(SUM({OBS_BYTES_SQL}), 0) FROM observations o
+                 JOIN sessions s ON s.id = o.session_id WHERE s.project = ?1"
+            ),
+            params![project],
+            |r| r.get(0),
+        )?;
+        if before_bytes <= quota_bytes {
+            continue;
+        }
+
+        let half_life = resolve_recency_half_life(config, Some(project));
+        let mut candidates: Vec<(i64, i64, f64)> = {
+            let mut stmt = tx.prepare(&format!(
+                "SELECT o.id, {OBS_BYTES_SQL}, o.timestamp, o.obs_type FROM observations o
+                 JOIN sessions s ON s.id = o.session_id
+                 WHERE s.project = ?1 AND o.is_pinned = 0
+                   AND COALESCE(json_extract(o.metadata, '$.failed'), 0) != 1
+                   AND NOT EXISTS (
+                       SELECT 1 FROM work_units w, json_each(w.obs_trace) t
+                       WHERE w.session_id = o.session_id
+                         AND json_extract(t.value, '$.t') = o.timestamp
+                         AND json_extract(t.value, '$.type') = o.obs_type)
+                 ORDER BY o.timestamp, o.id"
+            ))?;
+            stmt.query_map(params![project], |r| {
+                let timestamp: i64 = r.get(2)?;
+                let obs_type: Option<String> = r.get(3)?;
+                let score = model.recent_score(recency(now - timestamp, half_life), obs_type.as_deref(), false, None);
+                Ok((r.get(0)?, r.get(1)?, score))
+            })?
+            .collect::<Result<_, _>>()?
+        };
+        // Least important first; the SQL order (oldest first) breaks ties
+        candidates.sort_by(|a, b| a.2.total_cmp(&b.2));
+
+        let mut after_bytes = before_bytes;
+        let mut evicted = 0usize;
+        for (id, bytes, _) in candidates {
+            if after_bytes <= quota_bytes {
+                break;
+            }
+            tx.execute("DELETE FROM observations WHERE id = ?1", params![id])?;
+            after_bytes -= bytes;
+            evicted += 1;
+        }
+        total_evicted += evicted;
+        results.push(QuotaResult {
+            project: project.clone(),
+            quota_bytes,
+            before_bytes,
+            after_bytes,
+            evicted,
+        });
+    }
+    if total_evicted > 0 {
+        let orphans = cleanup_orphans(&tx)?;
+        let key = crate::s3_deletions::signing_key(config);
+        let deletion = crate::s3_deletions::Deletion {
+            operation: "quota",
+            filter: serde_json::json!(results
+                .iter()
+                .map(|r| (r.project.clone(), r.quota_bytes))
+                .collect::<std::collections::BTreeMap<_, _>>()),
+            deleted: (total_evicted + orphans) as i64,
+            details: serde_json::json!({
+                "evicted": results
+                    .iter()
+                    .map(|r| (r.project.clone(), r.evicted))
+                    .collect::<std::collections::BTreeMap<_, _>>(),
+                "orphan_sessions": orphans,
+            }),
+        };
+        crate::s3_deletions::record(&tx, &deletion, key.as_deref(), now)?;
+    }
+    tx.commit()?;
+
+    post_purge_maintenance(conn, total_evicted > FTS_REBUILD_ROWS)?;
+    Ok(results)
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
     use crate::db::open_db;
+    use crate::s5_config::ProjectConfig;
     use std::collections::HashMap;
     use tempfile::TempDir;
 
@@ -92,10 +321,13 @@ mod tests {
         let db_path = dir.path().join("test.db");
         let conn = open_db(&db_path).unwrap();
         conn.execute(
-            "INSERT INTO sessions (id, project, started_at, summary) VALUES ('s1', 'test', 1000, '{}')",
+            "INSERT INTO sessions (id, project, started_at, summary, summarized_at)
+             VALUES ('s1', 'test', 1000, '{}', unixepoch())",
             [],
         )
         .unwrap();
+        conn.execute("INSERT INTO work_units (session_id, started_at) VALUES ('s1', 1000)", [])
+            .unwrap();
         conn.execute(
             "INSERT INTO prompts (session_id, timestamp, source, content) VALUES ('s1', 1000, 'user', 'hello')",
             [],
@@ -122,9 +354,15 @@ mod tests {
             enabled: false,
             days: HashMap::from([("file_read".into(), 1)]),
             max_db_size_mb: None,
+            snapshot_days: 7,
+            min_age_hours: 24,
+            archived_retention_pct: 25,
+            sweep_chunk: 500,
+            sweep_pause_ms: 0,
+            sweep_window: None,
         };
 
-        let result = run_sweep(&conn, &config).unwrap();
+        let result = run_sweep(&conn, &config, false, None).unwrap();
         assert_eq!(result.deleted, 0);
 
         let count: i64 = conn
@@ -151,9 +389,15 @@ mod tests {
             enabled: true,
             days: HashMap::from([("file_read".into(), 90)]),
             max_db_size_mb: None,
+            snapshot_days: 7,
+            min_age_hours: 24,
+            archived_retention_pct: 25,
+            sweep_chunk: 500,
+            sweep_pause_ms: 0,
+            sweep_window: None,
         };
 
-        let result = run_sweep(&conn, &config).unwrap();
+        let result = run_sweep(&conn, &config, false, None).unwrap();
         assert_eq!(result.deleted, 1);
         assert_eq!(result.by_type.len(), 1);
         assert_eq!(result.by_type[0].0, "file_read");
@@ -181,9 +425,15 @@ mod tests {
             enabled: true,
             days: HashMap::from([("file_read".into(), 90)]),
             max_db_size_mb: None,
+            snapshot_days: 7,
+            min_age_hours: 24,
+            archived_retention_pct: 25,
+            sweep_chunk: 500,
+            sweep_pause_ms: 0,
+            sweep_window: None,
         };
 
-        let result = run_sweep(&conn, &config).unwrap();
+        let result = run_sweep(&conn, &config, false, None).unwrap();
         assert_eq!(result.deleted, 0);
 
         let count: i64 = conn
@@ -223,9 +473,15 @@ mod tests {
             enabled: true,
             days: HashMap::from([("file_read".into(), 90)]),
             max_db_size_mb: None,
+            snapshot_days: 7,
+            min_age_hours: 24,
+            archived_retention_pct: 25,
+            sweep_chunk: 500,
+            sweep_pause_ms: 0,
+            sweep_window: None,
         };
 
-        let result = run_sweep(&conn, &config).unwrap();
+        let result = run_sweep(&conn, &config, false, None).unwrap();
         assert_eq!(result.deleted, 1);
 
         // Pinned observation survives
@@ -260,9 +516,15 @@ mod tests {
             enabled: true,
             days: HashMap::from([("file_read".into(), 90)]),
             max_db_size_mb: None,
+            snapshot_days: 7,
+            min_age_hours: 24,
+            archived_retention_pct: 25,
+            sweep_chunk: 500,
+            sweep_pause_ms: 0,
+            sweep_window: None,
         };
 
-        let result = run_sweep(&conn, &config).unwrap();
+        let result = run_sweep(&conn, &config, false, None).unwrap();
         assert_eq!(result.deleted, 0);
 
         let count: i64 = conn
@@ -306,9 +568,15 @@ mod tests {
             enabled: true,
             days: HashMap::from([("file_read".into(), 90)]),
             max_db_size_mb: None,
+            snapshot_days: 7,
+            min_age_hours: 24,
+            archived_retention_pct: 25,
+            sweep_chunk: 500,
+            sweep_pause_ms: 0,
+            sweep_window: None,
         };
 
-        let result = run_sweep(&conn, &config).unwrap();
+        let result = run_sweep(&conn, &config, false, None).unwrap();
         assert_eq!(result.deleted, 0, "unsummarized session observations should survive sweep");
 
         let count: i64 = conn
@@ -320,4 +588,274 @@ mod tests {
             .unwrap();
         assert_eq!(count, 1);
     }
+
+    #[test]
+    fn sweep_expires_snapshots_on_their_own_tier() {
+        let (_dir, conn) = setup_db();
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap()
+            .as_secs() as i64;
+
+        for (path, age_days) in [("old.rs", 10), ("fresh.rs", 2)] {
+            conn.execute(
+                "INSERT INTO file_snapshots (session_id, file_path, captured_at, size)
+                 VALUES ('s1', ?1, ?2, 10)",
+                params![path, now - age_days * 86400],
+            )
+            .unwrap();
+        }
+
+        let config = RetentionConfig {
+            enabled: true,
+            days: HashMap::new(),
+            max_db_size_mb: None,
+            snapshot_days: 7,
+            min_age_hours: 24,
+            archived_retention_pct: 25,
+            sweep_chunk: 500,
+            sweep_pause_ms: 0,
+            sweep_window: None,
+        };
+        let result = run_sweep(&conn, &config, false, None).unwrap();
+        assert_eq!(result.snapshots_deleted, 1);
+
+        let left: String = conn
+            .query_row("SELECT file_path FROM file_snapshots", [], |r| r.get(0))
+            .unwrap();
+        assert_eq!(left, "fresh.rs");
+    }
+
+    fn retain_file_reads(days: u32) -> RetentionConfig {
+        RetentionConfig {
+            enabled: true,
+            days: HashMap::from([("file_read".into(), days)]),
+            max_db_size_mb: None,
+            snapshot_days: 7,
+            min_age_hours: 24,
+            archived_retention_pct: 25,
+            sweep_chunk: 500,
+            sweep_pause_ms: 0,
+            sweep_window: None,
+        }
+    }
+
+    fn count_obs(conn: &Connection) -> i64 {
+        conn.query_row("SELECT COUNT(*) FROM observations", [], |r| r.get(0)).unwrap()
+    }
+
+    #[test]
+    fn zero_day_retention_keeps_fresh_observations() {
+        let (_dir, conn) = setup_db();
+        let now = SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_secs() as i64;
+        insert_obs(&conn, "file_read", now - 3600);
+
+        let result = run_sweep(&conn, &retain_file_reads(0), false, None).unwrap();
+        assert_eq!(result.deleted, 0, "min_age_hours must hold even with days = 0");
+        assert_eq!(count_obs(&conn), 1);
+
+        let result = run_sweep(&conn, &retain_file_reads(0), true, None).unwrap();
+        assert_eq!(result.deleted, 1, "--force overrides the minimum age");
+    }
+
+    #[test]
+    fn sweep_deletes_in_chunks_and_keeps_fts_in_step() {
+        let (_dir, conn) = setup_db();
+        let now = SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_secs() as i64;
+        for i in 0..7 {
+            insert_obs(&conn, "file_read", now - 200 * 86400 + i);
+        }
+        insert_obs(&conn, "file_read", now - 86400);
+
+        let config = RetentionConfig { sweep_chunk: 3, ..retain_file_reads(90) };
+        let result = run_sweep(&conn, &config, false, None).unwrap();
+        assert_eq!(result.deleted, 7, "three chunks: 3, 3, then a short 1");
+        assert_eq!(count_obs(&conn), 1);
+        let indexed: i64 = conn
+            .query_row("SELECT COUNT(*) FROM observations_fts WHERE observations_fts MATCH 'test'", [], |r| r.get(0))
+            .unwrap();
+        assert_eq!(indexed, 1);
+
+        // One audit entry per committed chunk, so an interrupted sweep is
+        // still accounted for row by row
+        let logged: Vec<i64> = conn
+            .prepare("SELECT deleted FROM deletions_log WHERE operation = 'sweep' ORDER BY id")
+            .unwrap()
+            .query_map([], |r| r.get(0))
+            .unwrap()
+            .collect::<Result<_, _>>()
+            .unwrap();
+        assert_eq!(logged, [3, 3, 1]);
+    }
+
+    #[test]
+    fn sweep_signs_its_audit_entries_with_the_given_key() {
+        let (_dir, conn) = setup_db();
+        let now = SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_secs() as i64;
+        for i in 0..4 {
+            insert_obs(&conn, "file_read", now - 200 * 86400 + i);
+        }
+
+        let config = RetentionConfig { sweep_chunk: 2, ..retain_file_reads(90) };
+        let result = run_sweep(&conn, &config, false, Some(b"audit-key")).unwrap();
+        assert_eq!(result.deleted, 4);
+        let report = crate::s3_deletions::verify(&conn, b"audit-key").unwrap();
+        assert_eq!(report.signed, report.entries);
+        assert!(report.signed >= 2 && report.problems.is_empty(), "{:?}", report.problems);
+        assert!(!crate::s3_deletions::verify(&conn, b"other-key").unwrap().problems.is_empty());
+    }
+
+    #[test]
+    fn sweep_window_bounds_when_sweeps_run() {
+        let mut config = retain_file_reads(90);
+        assert!(in_sweep_window(&config, 0).unwrap(), "no window: any time");
+        let now = SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_secs() as i64;
+        let secs = crate::timefmt::time_of_day(now);
+        let clock = |s: i64| format!("{:02}:{:02}", s / 3600, s % 3600 / 60);
+        config.sweep_window = Some(format!("{}-{}", clock(secs / 60 * 60), clock((secs / 60 * 60 + 120) % 86400)));
+        assert!(in_sweep_window(&config, now).unwrap());
+        assert!(!in_sweep_window(&config, now + 6 * 3600).unwrap());
+        config.sweep_window = Some("2am".into());
+        assert!(in_sweep_window(&config, now).is_err());
+    }
+
+    #[test]
+    fn sweep_keeps_observations_newer_than_summary() {
+        let (_dir, conn) = setup_db();
+        let now = SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_secs() as i64;
+        // Summary written 100 days ago; the session was resumed afterwards
+        conn.execute("UPDATE sessions SET summarized_at = ?1 WHERE id = 's1'", params![now - 100 * 86400])
+            .unwrap();
+        insert_obs(&conn, "file_read", now - 200 * 86400);
+        insert_obs(&conn, "file_read", now - 50 * 86400);
+
+        let result = run_sweep(&conn, &retain_file_reads(30), false, None).unwrap();
+        assert_eq!(result.deleted, 1);
+        let left: i64 = conn.query_row("SELECT timestamp FROM observations", [], |r| r.get(0)).unwrap();
+        assert_eq!(left, now - 50 * 86400, "observation the summary never saw survives");
+
+        // A summary without a timestamp covers nothing
+        conn.execute("UPDATE sessions SET summarized_at = NULL WHERE id = 's1'", []).unwrap();
+        insert_obs(&conn, "file_read", now - 200 * 86400);
+        assert_eq!(run_sweep(&conn, &retain_file_reads(30), false, None).unwrap().deleted, 0);
+    }
+
+    #[test]
+    fn sweep_needs_a_summary_or_an_episode() {
+        let (_dir, conn) = setup_db();
+        let now = SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_secs() as i64;
+        conn.execute("DELETE FROM work_units", []).unwrap();
+        insert_obs(&conn, "file_read", now - 200 * 86400);
+
+        let result = run_sweep(&conn, &retain_file_reads(90), false, None).unwrap();
+        assert_eq!(result.deleted, 1, "a summary is enough without episodes");
+
+        // Neither a summary nor an episode: refused unless forced
+        conn.execute("UPDATE sessions SET summary = NULL, summarized_at = NULL", []).unwrap();
+        insert_obs(&conn, "file_read", now - 200 * 86400);
+        assert_eq!(run_sweep(&conn, &retain_file_reads(90), false, None).unwrap().deleted, 0);
+
+        // An episode that ended after the observation is enough without a summary
+        conn.execute(
+            "INSERT INTO work_units (session_id, started_at, ended_at) VALUES ('s1', ?1, ?2)",
+            params![now - 201 * 86400, now - 199 * 86400],
+        )
+        .unwrap();
+        insert_obs(&conn, "file_read", now - 150 * 86400);
+        let result = run_sweep(&conn, &retain_file_reads(90), false, None).unwrap();
+        assert_eq!(result.deleted, 1, "only the observation the episode saw");
+        let left: i64 = conn.query_row("SELECT timestamp FROM observations", [], |r| r.get(0)).unwrap();
+        assert_eq!(left, now - 150 * 86400);
+    }
+
+    #[test]
+    fn archived_projects_expire_sooner() {
+        let (_dir, conn) = setup_db();
+        let now = SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_secs() as i64;
+        insert_obs(&conn, "file_read", now - 30 * 86400);
+
+        assert_eq!(run_sweep(&conn, &retain_file_reads(90), false, None).unwrap().deleted, 0);
+
+        crate::s3_archive::archive(&conn, "test", now).unwrap();
+        let result = run_sweep(&conn, &retain_file_reads(90), false, None).unwrap();
+        assert_eq!(result.deleted, 1, "archived: 25% of 90 days is about 22 days");
+    }
+
+    fn quota_config(mb: u32) -> NmemConfig {
+        let mut config = NmemConfig::default();
+        config.projects.insert("test".into(), ProjectConfig { max_size_mb: Some(mb), ..Default::default() });
+        config.projects.insert("other".into(), ProjectConfig::default());
+        config
+    }
+
+    #[test]
+    fn quota_evicts_oldest_low_value_observations() {
+        let (_dir, conn) = setup_db();
+        let body = "x".repeat(200_000);
+        for (ts, meta, pinned) in [
+            (100, None, 0),
+            (200, None, 1),
+            (300, Some(r#"{"failed":true}"#), 0),
+            (400, None, 0),
+            (500, None, 0),
+            (600, None, 0),
+            (700, None, 0),
+        ] {
+            conn.execute(
+                "INSERT INTO observations (session_id, timestamp, obs_type, source_event, content, metadata, is_pinned)
+                 VALUES ('s1', ?1, 'command', 'PostToolUse', ?2, ?3, ?4)",
+                params![ts, body, meta, pinned],
+            )
+            .unwrap();
+        }
+        conn.execute(r#"UPDATE work_units SET obs_trace = '[{"t":400,"type":"command"}]'"#, [])
+            .unwrap();
+
+        let config = quota_config(1);
+        let results = enforce_project_quotas(&conn, &config).unwrap();
+        assert_eq!(results.len(), 1);
+        assert_eq!(results[0].evicted, 2);
+        assert!(results[0].after_bytes <= results[0].quota_bytes);
+
+        let left: Vec<i64> = conn
+            .prepare("SELECT timestamp FROM observations ORDER BY timestamp")
+            .unwrap()
+            .query_map([], |r| r.get(0))
+            .unwrap()
+            .collect::<Result<_, _>>()
+            .unwrap();
+        // 100 and 500 evicted: pinned, failed, and traced rows are kept
+        assert_eq!(left, vec![200, 300, 400, 600, 700]);
+
+        // Under quota now: nothing more to do
+        let again = enforce_project_quotas(&conn, &config).unwrap();
+        assert!(again.is_empty());
+    }
+
+    #[test]
+    fn quota_evicts_low_weight_types_before_older_edits() {
+        let (_dir, conn) = setup_db();
+        let body = "x".repeat(400_000);
+        let now = SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_secs() as i64;
+        for (ts, obs_type) in [(now - 3 * 86400, "file_edit"), (now - 86400, "file_read"), (now, "command")] {
+            conn.execute(
+                "INSERT INTO observations (session_id, timestamp, obs_type, source_event, content)
+                 VALUES ('s1', ?1, ?2, 'PostToolUse', ?3)",
+                params![ts, obs_type, body],
+            )
+            .unwrap();
+        }
+
+        let results = enforce_project_quotas(&conn, &quota_config(1)).unwrap();
+        assert_eq!(results[0].evicted, 1);
+        let left: Vec<String> = conn
+            .prepare("SELECT obs_type FROM observations ORDER BY timestamp")
+            .unwrap()
+            .query_map([], |r| r.get(0))
+            .unwrap()
+            .collect::<Result<_, _>>()
+            .unwrap();
+        // The newer read goes before the three-day-old edit
+        assert_eq!(left, vec!["file_edit", "command"]);
+    }
 }
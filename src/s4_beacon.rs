@@ -18,6 +18,7 @@ use std::path::{Path, PathBuf};
 use std::time::{Duration, Instant};
 
 use crate::cli::BeaconArgs;
+use crate::s5_anonymize::{PathAnonymization, PathMap};
 use crate::NmemError;
 
 // ---------------------------------------------------------------------------
@@ -352,6 +353,7 @@ pub fn handle_beacon(db_path: &Path, args: &BeaconArgs) -> Result<(), NmemError>
 
     let limit = beacon_cfg.limit;
     let respond = beacon_cfg.respond;
+    let paths = config.filter.anonymize_paths;
     let dry_run = args.dry_run;
     let db_path = db_path.to_path_buf();
 
@@ -361,7 +363,7 @@ pub fn handle_beacon(db_path: &Path, args: &BeaconArgs) -> Result<(), NmemError>
         .map_err(NmemError::Io)?;
 
     rt.block_on(run_beacon(
-        db_path, nats_url, org, identity, limit, respond, dry_run,
+        db_path, nats_url, org, identity, limit, respond, dry_run, paths,
     ))
 }
 
@@ -369,6 +371,7 @@ pub fn handle_beacon(db_path: &Path, args: &BeaconArgs) -> Result<(), NmemError>
 // Async beacon loop
 // ---------------------------------------------------------------------------
 
+#[allow(clippy::too_many_arguments)]
 async fn run_beacon(
     db_path: PathBuf,
     nats_url: String,
@@ -377,6 +380,7 @@ async fn run_beacon(
     limit: u32,
     respond: bool,
     dry_run: bool,
+    paths: PathAnonymization,
 ) -> Result<(), NmemError> {
     log::info!("beacon: connecting to {nats_url}");
 
@@ -423,7 +427,7 @@ async fn run_beacon(
                         tokio::spawn(async move {
                             handle_search_msg(
                                 &client, msg, &db_path, &identity,
-                                limit, respond, dry_run,
+                                limit, respond, dry_run, paths,
                             ).await;
                         });
                     }
@@ -477,6 +481,7 @@ async fn run_beacon(
 // Message handlers
 // ---------------------------------------------------------------------------
 
+#[allow(clippy::too_many_arguments)]
 async fn handle_search_msg(
     client: &async_nats::Client,
     msg: async_nats::Message,
@@ -485,6 +490,7 @@ async fn handle_search_msg(
     limit: u32,
     respond: bool,
     dry_run: bool,
+    paths: PathAnonymization,
 ) {
     let reply = match &msg.reply {
         Some(r) => r.clone(),
@@ -521,7 +527,7 @@ async fn handle_search_msg(
 
     // SQLite on blocking thread (rusqlite is sync)
     let episodes = tokio::task::spawn_blocking(move || {
-        execute_search(&db_path, &query, project.as_deref(), effective_limit)
+        execute_search(&db_path, &query, project.as_deref(), effective_limit, paths)
     })
     .await
     .unwrap_or_else(|e| {
@@ -581,14 +587,30 @@ fn execute_search(
     query: &str,
     project: Option<&str>,
     limit: i64,
+    paths: PathAnonymization,
 ) -> Result<Vec<EpisodeResult>, NmemError> {
     let conn = crate::db::open_db(db_path)?;
+    let mut episodes = search_episodes(&conn, query, project, limit)?;
 
+    // Peers don't get this machine's usernames
+    let mut map = PathMap::for_db(&conn, paths)?;
+    for episode in &mut episodes {
+        anonymize_episode(&mut map, episode);
+    }
+    Ok(episodes)
+}
+
+fn search_episodes(
+    conn: &rusqlite::Connection,
+    query: &str,
+    project: Option<&str>,
+    limit: i64,
+) -> Result<Vec<EpisodeResult>, NmemError> {
     // Try tiered FTS5 queries
     let tiers = crate::query::rewrite_query(query);
     for tier_query in &tiers {
         if let Some(sanitized) = crate::query::sanitize_fts_query(tier_query) {
-            let episodes = query_episodes_fts(&conn, &sanitized, project, limit)?;
+            let episodes = query_episodes_fts(conn, &sanitized, project, limit)?;
             if !episodes.is_empty() {
                 return Ok(episodes);
             }
@@ -596,8 +618,20 @@ fn execute_search(
     }
 
     // Fallback: LIKE on intent + summary
-    let episodes = query_episodes_like(&conn, query, project, limit)?;
-    Ok(episodes)
+    query_episodes_like(conn, query, project, limit)
+}
+
+fn anonymize_episode(map: &mut PathMap, episode: &mut EpisodeResult) {
+    if map.is_off() {
+        return;
+    }
+    episode.intent = map.text(&episode.intent);
+    for file in &mut episode.hot_files {
+        *file = map.text(file);
+    }
+    for text in [&mut episode.summary, &mut episode.learned, &mut episode.notes].into_iter().flatten() {
+        *text = map.text(text);
+    }
 }
 
 fn query_episodes_fts(
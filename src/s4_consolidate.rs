@@ -0,0 +1,533 @@
+//! S4 Intelligence — memory consolidation ("dreaming").
+//!
+//! Episodes are per-session; the same subsystem gets worked on across many
+//! sessions and context injection ends up listing overlapping episodes. This
+//! job clusters episodes across sessions that share files and intent terms,
+//! asks the LLM for one topic memory per cluster (decisions, pitfalls,
+//! current state), and stores it in `topics`. Context injection shows a
+//! topic in place of the episodes it covers.
+
+#[cfg(feature = "cli")]
+use crate::cli::ConsolidateArgs;
+use crate::db::open_db;
+use crate::s5_config::{ConsolidateConfig, SummarizationConfig, load_config};
+use crate::NmemError;
+use rusqlite::{Connection, OptionalExtension, params};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeSet, HashMap, HashSet};
+use std::path::Path;
+
+/// Weight of shared files vs shared intent terms in episode similarity.
+const FILE_WEIGHT: f64 = 0.6;
+/// Episodes at least this similar are linked into one cluster.
+const LINK_THRESHOLD: f64 = 0.35;
+/// A topic needs this many episodes...
+const MIN_EPISODES: usize = 3;
+/// ...from at least this many sessions.
+const MIN_SESSIONS: usize = 2;
+/// Episodes described to the LLM per topic, newest first.
+const MAX_PROMPT_EPISODES: usize = 12;
+
+/// An episode eligible for consolidation.
+#[derive(Debug)]
+struct Candidate {
+    id: i64,
+    session_id: String,
+    files: HashSet<String>,
+    terms: HashSet<String>,
+}
+
+/// A cluster of episodes that qualifies as a topic.
+#[derive(Debug, Serialize)]
+pub struct Cluster {
+    pub project: String,
+    pub work_unit_ids: Vec<i64>,
+    pub sessions: usize,
+    /// Files shared by at least two of the episodes, most shared first.
+    pub files: Vec<String>,
+    /// Existing topic this cluster extends, if any.
+    pub topic_id: Option<i64>,
+}
+
+/// What the LLM returns for a cluster.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct TopicMemory {
+    pub title: String,
+    #[serde(default)]
+    pub decisions: Vec<String>,
+    #[serde(default)]
+    pub pitfalls: Vec<String>,
+    #[serde(default)]
+    pub current_state: String,
+}
+
+#[derive(Debug, Serialize)]
+pub struct Consolidated {
+    pub topic_id: i64,
+    pub project: String,
+    pub title: String,
+    pub episodes: usize,
+}
+
+/// A stored topic as read back for context injection.
+#[derive(Debug)]
+pub(crate) struct Topic {
+    pub memory: TopicMemory,
+    pub files: Vec<String>,
+    pub episodes: i64,
+    pub updated_at: i64,
+}
+
+/// Topics by id, most recently updated first. Unparseable summaries are
+/// skipped.
+pub(crate) fn topics_by_id(conn: &Connection, ids: &[i64]) -> Result<Vec<Topic>, NmemError> {
+    let mut stmt = conn.prepare(
+        "SELECT t.summary, t.files, t.updated_at,
+                (SELECT COUNT(*) FROM topic_work_units tw WHERE tw.topic_id = t.id)
+         FROM topics t WHERE t.id IN (SELECT value FROM json_each(?1))
+         ORDER BY t.updated_at DESC, t.id DESC",
+    )?;
+    let rows = stmt
+        .query_map(params![serde_json::to_string(ids)?], |r| {
+            Ok((
+                r.get::<_, String>(0)?,
+                r.get::<_, Option<String>>(1)?,
+                r.get::<_, i64>(2)?,
+                r.get::<_, i64>(3)?,
+            ))
+        })?
+        .filter_map(|r| {
+            let (summary, files, updated_at, episodes) = r.ok()?;
+            Some(Topic {
+                memory: serde_json::from_str(&summary).ok()?,
+                files: files.and_then(|f| serde_json::from_str(&f).ok()).unwrap_or_default(),
+                episodes,
+                updated_at,
+            })
+        })
+        .collect();
+    Ok(rows)
+}
+
+fn jaccard(a: &HashSet<String>, b: &HashSet<String>) -> f64 {
+    let union = a.union(b).count();
+    if union == 0 {
+        return 0.0;
+    }
+    a.intersection(b).count() as f64 / union as f64
+}
+
+fn similarity(a: &Candidate, b: &Candidate) -> f64 {
+    FILE_WEIGHT * jaccard(&a.files, &b.files) + (1.0 - FILE_WEIGHT) * jaccard(&a.terms, &b.terms)
+}
+
+fn find(parent: &mut [usize], i: usize) -> usize {
+    let mut root = i;
+    while parent[root] != root {
+        root = parent[root];
+    }
+    let mut i = i;
+    while parent[i] != root {
+        let next = parent[i];
+        parent[i] = root;
+        i = next;
+    }
+    root
+}
+
+/// Single-linkage clustering. Returns index groups that span enough
+/// episodes and sessions, each in input order.
+fn cluster_indices(cands: &[Candidate]) -> Vec<Vec<usize>> {
+    let mut parent: Vec<usize> = (0..cands.len()).collect();
+    for i in 0..cands.len() {
+        for j in i + 1..cands.len() {
+            if similarity(&cands[i], &cands[j]) >= LINK_THRESHOLD {
+                let (ri, rj) = (find(&mut parent, i), find(&mut parent, j));
+                if ri != rj {
+                    parent[rj] = ri;
+                }
+            }
+        }
+    }
+
+    let mut groups: HashMap<usize, Vec<usize>> = HashMap::new();
+    for i in 0..cands.len() {
+        let root = find(&mut parent, i);
+        groups.entry(root).or_default().push(i);
+    }
+    let mut out: Vec<Vec<usize>> = groups
+        .into_values()
+        .filter(|g| {
+            let sessions: HashSet<&str> = g.iter().map(|&i| cands[i].session_id.as_str()).collect();
+            g.len() >= MIN_EPISODES && sessions.len() >= MIN_SESSIONS
+        })
+        .collect();
+    out.sort_by_key(|g| g[0]);
+    out
+}
+
+fn load_candidates(conn: &Connection, project: &str, since: i64) -> Result<Vec<Candidate>, NmemError> {
+    let mut stmt = conn.prepare(
+        "SELECT w.id, w.session_id, w.intent, w.hot_files
+         FROM work_units w JOIN sessions s ON s.id = w.session_id
+         WHERE s.project = ?1 AND w.started_at >= ?2 AND w.obs_count >= 3
+         ORDER BY w.started_at DESC, w.id DESC",
+    )?;
+    let rows = stmt
+        .query_map(params![project, since], |r| {
+            let intent: String = r.get::<_, Option<String>>(2)?.unwrap_or_default();
+            let files: Vec<String> = r
+                .get::<_, Option<String>>(3)?
+                .and_then(|f| serde_json::from_str(&f).ok())
+                .unwrap_or_default();
+            Ok(Candidate {
+                id: r.get(0)?,
+                session_id: r.get(1)?,
+                files: files.into_iter().collect(),
+                terms: crate::s4_context::terms(&intent),
+            })
+        })?
+        .collect::<Result<_, _>>()?;
+    Ok(rows)
+}
+
+/// Clusters for `project` over episodes started since `since` that are new
+/// or have grown. A cluster whose episodes all already belong to one topic
+/// is left alone.
+pub fn find_clusters(conn: &Connection, project: &str, since: i64) -> Result<Vec<Cluster>, NmemError> {
+    let cands = load_candidates(conn, project, since)?;
+    let mut topic_of: HashMap<i64, i64> = HashMap::new();
+    {
+        let mut stmt = conn.prepare("SELECT work_unit_id, topic_id FROM topic_work_units")?;
+        for row in stmt.query_map([], |r| Ok((r.get(0)?, r.get(1)?)))? {
+            let (wu, topic) = row?;
+            topic_of.insert(wu, topic);
+        }
+    }
+
+    let mut clusters = Vec::new();
+    for group in cluster_indices(&cands) {
+        let ids: Vec<i64> = group.iter().map(|&i| cands[i].id).collect();
+        let topics: BTreeSet<i64> = ids.iter().filter_map(|id| topic_of.get(id).copied()).collect();
+        if topics.len() == 1 && ids.iter().all(|id| topic_of.contains_key(id)) {
+            continue;
+        }
+
+        let mut shared: HashMap<&str, usize> = HashMap::new();
+        for &i in &group {
+            for f in &cands[i].files {
+                *shared.entry(f.as_str()).or_default() += 1;
+            }
+        }
+        let mut files: Vec<(&str, usize)> = shared.into_iter().filter(|(_, n)| *n >= 2).collect();
+        files.sort_by(|a, b| b.1.cmp(&a.1).then(a.0.cmp(b.0)));
+
+        clusters.push(Cluster {
+            project: project.to_string(),
+            sessions: group.iter().map(|&i| cands[i].session_id.as_str()).collect::<HashSet<_>>().len(),
+            work_unit_ids: ids,
+            files: files.into_iter().take(10).map(|(f, _)| f.to_string()).collect(),
+            topic_id: topics.first().copied(),
+        });
+    }
+    Ok(clusters)
+}
+
+const TOPIC_SYSTEM_PROMPT: &str = "You consolidate an AI coding agent's memory. You are given several work episodes from different sessions that touched the same part of a codebase. Merge them into one topic memory the next session can rely on instead of rereading every episode.\n\nRules:\n- title: a short noun phrase naming the subsystem or concern, e.g. \"auth token refresh\".\n- decisions: choices that were made and still hold. Later episodes override earlier ones.\n- pitfalls: things that failed or surprised, so they are not repeated.\n- current_state: one or two sentences on where the work stands now.\n- All array fields MUST be JSON arrays of strings.\n\nReturn ONLY valid JSON. No markdown fences, no explanation.";
+
+fn topic_prompt(conn: &Connection, cluster: &Cluster) -> Result<String, NmemError> {
+    let mut out = format!(
+        "Return a JSON object with \"title\", \"decisions\", \"pitfalls\", \"current_state\".\n\nShared files: {}\n\nEpisodes, newest first:\n",
+        cluster.files.join(", ")
+    );
+    let mut stmt = conn.prepare(
+        "SELECT started_at, intent, hot_files, summary FROM work_units WHERE id = ?1",
+    )?;
+    for id in cluster.work_unit_ids.iter().take(MAX_PROMPT_EPISODES) {
+        let (started_at, intent, files, summary): (i64, Option<String>, Option<String>, Option<String>) =
+            stmt.query_row(params![id], |r| Ok((r.get(0)?, r.get(1)?, r.get(2)?, r.get(3)?)))?;
+        out.push_str(&format!(
+            "- [{}] {}\n  files: {}\n",
+            crate::timefmt::datetime(started_at),
+            intent.unwrap_or_default(),
+            files.unwrap_or_default(),
+        ));
+        if let Some(summary) = summary {
+            out.push_str(&format!("  narrative: {summary}\n"));
+        }
+    }
+    Ok(out)
+}
+
+/// Generate and store the topic for `cluster`. Returns the topic id.
+pub fn consolidate_cluster(
+    conn: &Connection,
+    cluster: &Cluster,
+    config: &SummarizationConfig,
+    now: i64,
+) -> Result<(i64, TopicMemory), NmemError> {
+    let user = topic_prompt(conn, cluster)?;
+    let mut inference_params = crate::s1_4_inference::params_from_config(config)?;
+    inference_params.max_tokens = 768;
+    let result = crate::s1_4_inference::generate(&inference_params, TOPIC_SYSTEM_PROMPT, &user)?;
+
+    let session_id: String = conn.query_row(
+        "SELECT session_id FROM work_units WHERE id = ?1",
+        params![cluster.work_unit_ids[0]],
+        |r| r.get(0),
+    )?;
+    if let Err(e) = crate::s3_llm_usage::record(
+        conn,
+        crate::s3_llm_usage::TOPIC_CONSOLIDATION,
+        &session_id,
+        None,
+        &result,
+        now,
+    ) {
+        log::warn!("llm usage not recorded: {e}");
+    }
+
+    let memory: TopicMemory =
+        serde_json::from_str(crate::s1_4_summarize::strip_fences(&result.text))
+            .map_err(|e| NmemError::Config(format!("topic parse: {e}")))?;
+    let id = store_topic(conn, cluster, &memory, now)?;
+    Ok((id, memory))
+}
+
+/// Write `memory` as a new topic, or over the cluster's existing one, and
+/// point the cluster's episodes at it.
+pub fn store_topic(
+    conn: &Connection,
+    cluster: &Cluster,
+    memory: &TopicMemory,
+    now: i64,
+) -> Result<i64, NmemError> {
+    let tx = conn.unchecked_transaction()?;
+    let summary = serde_json::to_string(memory)?;
+    let files = serde_json::to_string(&cluster.files)?;
+    let existing = match cluster.topic_id {
+        Some(id) => tx
+            .query_row("SELECT id FROM topics WHERE id = ?1", params![id], |r| r.get::<_, i64>(0))
+            .optional()?,
+        None => None,
+    };
+    let topic_id = match existing {
+        Some(id) => {
+            tx.execute(
+                "UPDATE topics SET title = ?2, summary = ?3, files = ?4, updated_at = ?5 WHERE id = ?1",
+                params![id, memory.title, summary, files, now],
+            )?;
+            id
+        }
+        None => {
+            tx.execute(
+                "INSERT INTO topics (project, title, summary, files, created_at, updated_at)
+                 VALUES (?1, ?2, ?3, ?4, ?5, ?5)",
+                params![cluster.project, memory.title, summary, files, now],
+            )?;
+            tx.last_insert_rowid()
+        }
+    };
+    for wu in &cluster.work_unit_ids {
+        tx.execute(
+            "INSERT INTO topic_work_units (topic_id, work_unit_id) VALUES (?1, ?2)
+             ON CONFLICT(work_unit_id) DO UPDATE SET topic_id = excluded.topic_id",
+            params![topic_id, wu],
+        )?;
+    }
+    // A topic whose episodes all moved elsewhere has nothing left to stand for
+    tx.execute(
+        "DELETE FROM topics WHERE project = ?1
+           AND id NOT IN (SELECT topic_id FROM topic_work_units)",
+        params![cluster.project],
+    )?;
+    tx.commit()?;
+    Ok(topic_id)
+}
+
+/// Consolidate every project (or just `project`). Skips LLM work when
+/// summarization is off or the monthly token budget is spent.
+pub fn consolidate(
+    conn: &Connection,
+    project: Option<&str>,
+    config: &ConsolidateConfig,
+    sum_config: &SummarizationConfig,
+    now: i64,
+) -> Result<Vec<Consolidated>, NmemError> {
+    if !sum_config.enabled {
+        log::info!("summarization disabled in config — skipping consolidation");
+        return Ok(Vec::new());
+    }
+    let since = now - config.window_days as i64 * 86400;
+    let mut done = Vec::new();
+    for project in projects(conn, project)? {
+        for cluster in find_clusters(conn, &project, since)? {
+            if crate::s3_llm_usage::over_budget(conn, sum_config, now)? {
+                log::info!("monthly LLM token budget reached — consolidation paused");
+                return Ok(done);
+            }
+            match consolidate_cluster(conn, &cluster, sum_config, now) {
+                Ok((topic_id, memory)) => done.push(Consolidated {
+                    topic_id,
+                    project: project.clone(),
+                    title: memory.title,
+                    episodes: cluster.work_unit_ids.len(),
+                }),
+                Err(e) => log::warn!("topic consolidation failed (non-fatal): {e}"),
+            }
+        }
+    }
+    Ok(done)
+}
+
+fn projects(conn: &Connection, project: Option<&str>) -> Result<Vec<String>, NmemError> {
+    if let Some(p) = project {
+        return Ok(vec![p.to_string()]);
+    }
+    let rows = conn
+        .prepare(
+            "SELECT DISTINCT s.project FROM work_units w JOIN sessions s ON s.id = w.session_id
+             ORDER BY s.project",
+        )?
+        .query_map([], |r| r.get(0))?
+        .collect::<Result<_, _>>()?;
+    Ok(rows)
+}
+
+#[cfg(feature = "cli")]
+pub fn handle_consolidate(db_path: &Path, args: &ConsolidateArgs) -> Result<(), NmemError> {
+    let conn = open_db(db_path)?;
+    let config = load_config().unwrap_or_default();
+    let now = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs() as i64;
+
+    let json = if args.dry_run {
+        let since = now - config.consolidate.window_days as i64 * 86400;
+        let mut clusters = Vec::new();
+        for project in projects(&conn, args.project.as_deref())? {
+            clusters.extend(find_clusters(&conn, &project, since)?);
+        }
+        serde_json::to_string_pretty(&clusters)?
+    } else {
+        let done = consolidate(
+            &conn,
+            args.project.as_deref(),
+            &config.consolidate,
+            &config.summarization,
+            now,
+        )?;
+        serde_json::to_string_pretty(&done)?
+    };
+    println!("{json}");
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn setup() -> Connection {
+        let mut conn = Connection::open_in_memory().unwrap();
+        conn.pragma_update(None, "foreign_keys", "ON").unwrap();
+        crate::schema::MIGRATIONS.to_latest(&mut conn).unwrap();
+        conn.execute_batch(
+            "INSERT INTO sessions (id, project, started_at) VALUES
+                 ('s1', 'proj', 1000), ('s2', 'proj', 2000), ('s3', 'proj', 3000), ('s4', 'other', 3000);",
+        )
+        .unwrap();
+        conn
+    }
+
+    fn episode(conn: &Connection, session: &str, at: i64, intent: &str, files: &[&str]) -> i64 {
+        conn.execute(
+            "INSERT INTO work_units (session_id, started_at, intent, hot_files, obs_count)
+             VALUES (?1, ?2, ?3, ?4, 5)",
+            params![session, at, intent, serde_json::to_string(files).unwrap()],
+        )
+        .unwrap();
+        conn.last_insert_rowid()
+    }
+
+    fn memory(title: &str) -> TopicMemory {
+        TopicMemory {
+            title: title.into(),
+            decisions: vec!["refresh tokens server-side".into()],
+            pitfalls: vec![],
+            current_state: "done".into(),
+        }
+    }
+
+    #[test]
+    fn clusters_span_sessions_sharing_files_and_intent() {
+        let conn = setup();
+        let a = episode(&conn, "s1", 1100, "fix token refresh", &["src/auth.rs", "src/token.rs"]);
+        let b = episode(&conn, "s2", 2100, "token refresh race", &["src/auth.rs", "src/token.rs"]);
+        let c = episode(&conn, "s3", 3100, "refresh token expiry", &["src/token.rs", "src/auth.rs", "src/clock.rs"]);
+        // Unrelated work and another project's identical episode stay out
+        episode(&conn, "s3", 3200, "render markdown tables", &["src/render.rs"]);
+        episode(&conn, "s4", 3100, "fix token refresh", &["src/auth.rs", "src/token.rs"]);
+
+        let clusters = find_clusters(&conn, "proj", 0).unwrap();
+        assert_eq!(clusters.len(), 1);
+        let mut ids = clusters[0].work_unit_ids.clone();
+        ids.sort();
+        assert_eq!(ids, vec![a, b, c]);
+        assert_eq!(clusters[0].sessions, 3);
+        assert_eq!(clusters[0].files, vec!["src/auth.rs", "src/token.rs"]);
+        assert!(clusters[0].topic_id.is_none());
+    }
+
+    #[test]
+    fn one_session_is_not_a_topic() {
+        let conn = setup();
+        for at in [1100, 1200, 1300] {
+            episode(&conn, "s1", at, "fix token refresh", &["src/auth.rs"]);
+        }
+        assert!(find_clusters(&conn, "proj", 0).unwrap().is_empty());
+    }
+
+    #[test]
+    fn stored_topics_are_skipped_until_they_grow() {
+        let conn = setup();
+        episode(&conn, "s1", 1100, "fix token refresh", &["src/auth.rs"]);
+        episode(&conn, "s2", 2100, "token refresh race", &["src/auth.rs"]);
+        episode(&conn, "s3", 3100, "token refresh expiry", &["src/auth.rs"]);
+
+        let cluster = &find_clusters(&conn, "proj", 0).unwrap()[0];
+        let id = store_topic(&conn, cluster, &memory("auth tokens"), 4000).unwrap();
+        assert!(find_clusters(&conn, "proj", 0).unwrap().is_empty());
+
+        episode(&conn, "s3", 3500, "token refresh retries", &["src/auth.rs"]);
+        let grown = find_clusters(&conn, "proj", 0).unwrap();
+        assert_eq!(grown.len(), 1);
+        assert_eq!(grown[0].topic_id, Some(id));
+        assert_eq!(grown[0].work_unit_ids.len(), 4);
+
+        assert_eq!(store_topic(&conn, &grown[0], &memory("auth token refresh"), 5000).unwrap(), id);
+        let (title, count): (String, i64) = conn
+            .query_row(
+                "SELECT title, (SELECT COUNT(*) FROM topic_work_units WHERE topic_id = t.id)
+                 FROM topics t",
+                [],
+                |r| Ok((r.get(0)?, r.get(1)?)),
+            )
+            .unwrap();
+        assert_eq!((title.as_str(), count), ("auth token refresh", 4));
+    }
+
+    #[test]
+    fn disabled_summarization_writes_nothing() {
+        let conn = setup();
+        let done = consolidate(
+            &conn,
+            None,
+            &ConsolidateConfig::default(),
+            &SummarizationConfig::default(),
+            4000,
+        )
+        .unwrap();
+        assert!(done.is_empty());
+    }
+}
@@ -1,64 +1,57 @@
-use crate::db::register_udfs;
+use crate::db::register_udfs_with;
 use crate::s1_4_summarize::SessionSummary;
+use crate::s1_investigate::Investigation;
+use crate::s4_consolidate::Topic;
+use crate::s4_outcome::Unfinished;
+use crate::s4_overlap::SharedFile;
+use crate::s5_config::{ContextSection, ResolvedContextProfile};
+use crate::timefmt;
 use crate::NmemError;
-use rusqlite::{Connection, params};
+use rusqlite::{Connection, OptionalExtension, params};
+use std::collections::HashSet;
+use std::path::Path;
 
 // --- Utility ---
 
-fn format_relative_time(ts: i64) -> String {
-    let now = std::time::SystemTime::now()
-        .duration_since(std::time::UNIX_EPOCH)
-        .unwrap_or_default()
-        .as_secs() as i64;
-    let diff = now - ts;
-
-    if diff < 3600 {
-        let mins = (diff / 60).max(1);
-        format!("{mins}m ago")
-    } else if diff < 86400 {
-        let hours = diff / 3600;
-        format!("{hours}h ago")
-    } else if diff < 604800 {
-        let days = diff / 86400;
-        format!("{days}d ago")
-    } else {
-        let days_since_epoch = ts / 86400;
-        let (year, month, day) = days_to_ymd(days_since_epoch);
-        let month_name = match month {
-            1 => "Jan", 2 => "Feb", 3 => "Mar", 4 => "Apr",
-            5 => "May", 6 => "Jun", 7 => "Jul", 8 => "Aug",
-            9 => "Sep", 10 => "Oct", 11 => "Nov", 12 => "Dec",
-            _ => "???",
-        };
-        if year == current_year() {
-            format!("{month_name} {day:02}")
-        } else {
-            format!("{month_name} {day:02}, {year}")
+/// Keyword Jaccard at or above which two rendered lines say the same thing.
+const NEAR_DUPLICATE: f64 = 0.5;
+
+/// Group near-duplicate lines (same text ignoring case, or keyword Jaccard
+/// at least `NEAR_DUPLICATE`). Each group lists indices in input order and
+/// groups are ordered by their first member, so the first phrasing wins.
+fn group_similar<'a>(lines: impl IntoIterator<Item = &'a str>) -> Vec<Vec<usize>> {
+    let mut groups: Vec<(String, Vec<String>, Vec<usize>)> = Vec::new();
+    for (i, line) in lines.into_iter().enumerate() {
+        let lower = line.trim().to_lowercase();
+        let keywords = crate::s3_learn::intent_keywords(line);
+        let similar = groups.iter_mut().find(|(text, kw, _)| {
+            *text == lower || crate::s3_learn::jaccard(kw, &keywords) >= NEAR_DUPLICATE
+        });
+        match similar {
+            Some((_, _, members)) => members.push(i),
+            None => groups.push((lower, keywords, vec![i])),
         }
     }
+    groups.into_iter().map(|(_, _, members)| members).collect()
 }
 
-fn days_to_ymd(days: i64) -> (i64, u32, u32) {
-    // Algorithm from http://howardhinnant.github.io/date_algorithms.html
-    let z = days + 719468;
-    let era = if z >= 0 { z } else { z - 146096 } / 146097;
-    let doe = (z - era * 146097) as u32;
-    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
-    let y = yoe as i64 + era * 400;
-    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
-    let mp = (5 * doy + 2) / 153;
-    let d = doy - (153 * mp + 2) / 5 + 1;
-    let m = if mp < 10 { mp + 3 } else { mp - 9 };
-    let y = if m <= 2 { y + 1 } else { y };
-    (y, m, d)
-}
-
-fn current_year() -> i64 {
-    let now = std::time::SystemTime::now()
-        .duration_since(std::time::UNIX_EPOCH)
-        .unwrap_or_default()
-        .as_secs() as i64;
-    days_to_ymd(now / 86400).0
+/// Per-section freshness annotation: the age span of the items the section shows.
+fn format_freshness(timestamps: impl IntoIterator<Item = i64>) -> String {
+    let (mut newest, mut oldest) = (i64::MIN, i64::MAX);
+    for ts in timestamps {
+        newest = newest.max(ts);
+        oldest = oldest.min(ts);
+    }
+    if newest == i64::MIN {
+        return String::new();
+    }
+    let newest_rel = timefmt::relative(newest);
+    let oldest_rel = timefmt::relative(oldest);
+    if newest_rel == oldest_rel {
+        format!("_freshness: {newest_rel}_\n")
+    } else {
+        format!("_freshness: newest {newest_rel}, oldest {oldest_rel}_\n")
+    }
 }
 
 /// Returns true if the string looks like a URL or is too short to be a useful intent.
@@ -72,14 +65,21 @@ fn is_low_quality_intent(s: &str) -> bool {
 // --- Episodes ---
 
 struct EpisodeRow {
+    session_id: String,
     started_at: i64,
     intent: String,
     obs_count: i64,
     hot_files: Vec<String>,
     phase_signature: PhaseInfo,
     summary: Option<String>,
+    /// Observations the narrative was generated from
+    sources: Vec<i64>,
     /// Fallback intent from session summary (used when raw intent is a URL or too short)
     session_intent: Option<String>,
+    /// Consolidated topic covering this episode, if one existed at the time
+    topic_id: Option<i64>,
+    /// `succeeded`, `blocked`, or `abandoned` (see `s4_outcome`).
+    outcome: Option<String>,
 }
 
 #[derive(Default)]
@@ -98,7 +98,16 @@ struct PhaseInfo {
     friction: i64,
 }
 
-fn query_episodes(conn: &Connection, project: &str, window_secs: i64, limit: i64, before: Option<i64>) -> Result<Vec<EpisodeRow>, NmemError> {
+/// Episodes for `project` within the window, newest first; with `session`,
+/// only that session's.
+fn query_episodes(
+    conn: &Connection,
+    project: &str,
+    window_secs: i64,
+    limit: i64,
+    before: Option<i64>,
+    session: Option<&str>,
+) -> Result<Vec<EpisodeRow>, NmemError> {
     let now = before.unwrap_or_else(|| {
         std::time::SystemTime::now()
             .duration_since(std::time::UNIX_EPOCH)
@@ -109,19 +118,22 @@ fn query_episodes(conn: &Connection, project: &str, window_secs: i64, limit: i64
 
     let mut stmt = conn.prepare(
         "SELECT w.started_at, w.intent, w.obs_count, w.hot_files, w.phase_signature, w.summary,
-                ss.summary AS session_summary
+                ss.summary AS session_summary, t.id, w.session_id, w.summary_sources, w.outcome
          FROM work_units w
          JOIN sessions ss ON w.session_id = ss.id
+         LEFT JOIN topic_work_units tw ON tw.work_unit_id = w.id
+         LEFT JOIN topics t ON t.id = tw.topic_id AND (?4 IS NULL OR t.updated_at < ?4)
          WHERE ss.project = ?1
            AND w.started_at >= ?2
            AND (?4 IS NULL OR w.started_at < ?4)
+           AND (?5 IS NULL OR w.session_id = ?5)
            AND w.obs_count > 0
          ORDER BY w.started_at DESC
          LIMIT ?3",
     )?;
 
     let rows = stmt
-        .query_map(params![project, cutoff, limit, before], |row| {
+        .query_map(params![project, cutoff, limit, before, session], |row| {
             let started_at: i64 = row.get(0)?;
             let intent: String = row.get::<_, Option<String>>(1)?.unwrap_or_default();
             let obs_count: i64 = row.get::<_, Option<i64>>(2)?.unwrap_or(0);
@@ -129,10 +141,14 @@ fn query_episodes(conn: &Connection, project: &str, window_secs: i64, limit: i64
             let phase_json: String = row.get::<_, Option<String>>(4)?.unwrap_or_else(|| "{}".into());
             let summary: Option<String> = row.get(5)?;
             let session_summary_json: Option<String> = row.get(6)?;
-            Ok((started_at, intent, obs_count, hot_files_json, phase_json, summary, session_summary_json))
+            let topic_id: Option<i64> = row.get(7)?;
+            let session_id: String = row.get(8)?;
+            let sources_json: Option<String> = row.get(9)?;
+            let outcome: Option<String> = row.get(10)?;
+            Ok((started_at, intent, obs_count, hot_files_json, phase_json, summary, session_summary_json, topic_id, session_id, sources_json, outcome))
         })?
         .filter_map(|r| {
-            let (started_at, intent, obs_count, hot_files_json, phase_json, summary, session_summary_json) = r.ok()?;
+            let (started_at, intent, obs_count, hot_files_json, phase_json, summary, session_summary_json, topic_id, session_id, sources_json, outcome) = r.ok()?;
             let hot_files: Vec<String> = serde_json::from_str(&hot_files_json).unwrap_or_default();
             let phase_val: serde_json::Value = serde_json::from_str(&phase_json).unwrap_or_default();
             let phase_signature = PhaseInfo {
@@ -152,13 +168,17 @@ fn query_episodes(conn: &Connection, project: &str, window_secs: i64, limit: i64
                 serde_json::from_str::<SessionSummary>(&json).ok().map(|s| s.intent)
             });
             Some(EpisodeRow {
+                session_id,
                 started_at,
                 intent,
                 obs_count,
                 hot_files,
                 phase_signature,
                 summary,
+                sources: sources_json.and_then(|s| serde_json::from_str(&s).ok()).unwrap_or_default(),
                 session_intent,
+                topic_id,
+                outcome,
             })
         })
         .collect();
@@ -198,21 +218,32 @@ fn phase_label(phase: &PhaseInfo) -> String {
 }
 
 fn format_episodes(rows: &[EpisodeRow]) -> String {
+    format_episodes_under(rows, "## Recent Episodes")
+}
+
+fn format_episodes_under(rows: &[EpisodeRow], header: &str) -> String {
     if rows.is_empty() {
         return String::new();
     }
 
-    let mut out = String::from("## Recent Episodes\n");
-    for (i, row) in rows.iter().enumerate() {
-        let time = format_relative_time(row.started_at);
-
-        // Use session summary intent as fallback when raw intent is low quality
-        let raw_intent = &row.intent;
-        let intent_source = if is_low_quality_intent(raw_intent) {
-            row.session_intent.as_deref().unwrap_or(raw_intent)
-        } else {
-            raw_intent
-        };
+    let mut out = format!("{header}\n");
+    out.push_str(&format_freshness(rows.iter().map(|r| r.started_at)));
+    // Use session summary intent as fallback when raw intent is low quality
+    let intents: Vec<&str> = rows
+        .iter()
+        .map(|row| {
+            if is_low_quality_intent(&row.intent) {
+                row.session_intent.as_deref().unwrap_or(&row.intent)
+            } else {
+                &row.intent
+            }
+        })
+        .collect();
+    // Repeats of an intent collapse into its most recent episode
+    for (i, group) in group_similar(intents.iter().copied()).iter().enumerate() {
+        let row = &rows[group[0]];
+        let time = timefmt::relative(row.started_at);
+        let intent_source = intents[group[0]];
         let intent_display: String = intent_source.chars().take(80).collect();
         let intent_display = if intent_source.chars().count() > 80 {
             format!("{intent_display}...")
@@ -220,9 +251,13 @@ fn format_episodes(rows: &[EpisodeRow]) -> String {
             intent_display
         };
 
-        let phase = phase_label(&row.phase_signature);
+        let mut phase = phase_label(&row.phase_signature);
+        if let Some(outcome) = &row.outcome {
+            phase = format!("{phase}, {outcome}");
+        }
+        let repeated = if group.len() > 1 { format!(", repeated in {} episodes", group.len()) } else { String::new() };
         out.push_str(&format!(
-            "- [{time}] **{intent_display}** ({} obs, {phase})\n",
+            "- [{time}] **{intent_display}** ({} obs, {phase}{repeated})\n",
             row.obs_count
         ));
 
@@ -248,6 +283,12 @@ fn format_episodes(rows: &[EpisodeRow]) -> String {
                         };
                         if !learned_items.is_empty() {
                             out.push_str(&format!("  - Learned: {learned_items}\n"));
+                            if let (Some(first), Some(last)) = (row.sources.iter().min(), row.sources.iter().max()) {
+                                out.push_str(&format!(
+                                    "  - Sources: {} obs, #{first}–#{last}\n",
+                                    row.sources.len()
+                                ));
+                            }
                         }
                     }
     }
@@ -259,9 +300,20 @@ fn format_episodes(rows: &[EpisodeRow]) -> String {
 struct SummaryRow {
     started_at: i64,
     summary: SessionSummary,
+    /// Per `summary.learned` entry: the "verify" note when it has gone stale.
+    stale: Vec<Option<String>>,
 }
 
-fn query_fallback_summaries(conn: &Connection, project: &str, window_secs: i64, limit: i64, before: Option<i64>) -> Result<Vec<SummaryRow>, NmemError> {
+/// Summaries of sessions outside the episode window (or without episodes);
+/// with `after`, none started earlier — a rollup covers those.
+fn query_fallback_summaries(
+    conn: &Connection,
+    project: &str,
+    window_secs: i64,
+    limit: i64,
+    before: Option<i64>,
+    after: Option<i64>,
+) -> Result<Vec<SummaryRow>, NmemError> {
     let now = before.unwrap_or_else(|| {
         std::time::SystemTime::now()
             .duration_since(std::time::UNIX_EPOCH)
@@ -273,15 +325,16 @@ fn query_fallback_summaries(conn: &Connection, project: &str, window_secs: i64,
     // Sessions older than the episode window, OR sessions without episodes
     let mut stmt = conn.prepare(
         "SELECT s.started_at, s.summary FROM sessions s
-         WHERE s.project = ?1 AND s.summary IS NOT NULL
+         WHERE s.project = ?1 AND s.summary IS NOT NULL AND s.kind = 'agent'
            AND (?4 IS NULL OR s.started_at < ?4)
+           AND (?5 IS NULL OR s.started_at >= ?5)
            AND (s.started_at < ?2
                 OR NOT EXISTS (SELECT 1 FROM work_units w WHERE w.session_id = s.id))
          ORDER BY s.started_at DESC LIMIT ?3",
     )?;
 
-    let rows = stmt
-        .query_map(params![project, cutoff, limit, before], |row| {
+    let rows: Vec<(i64, SessionSummary)> = stmt
+        .query_map(params![project, cutoff, limit, before, after], |row| {
             let started_at: i64 = row.get(0)?;
             let summary_str: String = row.get(1)?;
             Ok((started_at, summary_str))
@@ -289,10 +342,20 @@ fn query_fallback_summaries(conn: &Connection, project: &str, window_secs: i64,
         .filter_map(|r| {
             let (started_at, summary_str) = r.ok()?;
             let summary: SessionSummary = serde_json::from_str(&summary_str).ok()?;
-            Some(SummaryRow { started_at, summary })
+            Some((started_at, summary))
         })
         .collect();
-    Ok(rows)
+
+    let mut out = Vec::with_capacity(rows.len());
+    for (started_at, summary) in rows {
+        let mut stale = Vec::with_capacity(summary.learned.len());
+        for text in &summary.learned {
+            let (score, _, _) = crate::s4_decay::assess(conn, project, text, started_at, now)?;
+            stale.push((score >= crate::s4_decay::STALE_SCORE).then(|| crate::s4_decay::annotation(now - started_at)));
+        }
+        out.push(SummaryRow { started_at, summary, stale });
+    }
+    Ok(out)
 }
 
 fn format_summaries(rows: &[SummaryRow]) -> String {
@@ -301,8 +364,9 @@ fn format_summaries(rows: &[SummaryRow]) -> String {
     }
 
     let mut out = String::from("## Session Summaries\n");
+    out.push_str(&format_freshness(rows.iter().map(|r| r.started_at)));
     for (i, row) in rows.iter().enumerate() {
-        let time = format_relative_time(row.started_at);
+        let time = timefmt::relative(row.started_at);
         let intent = &row.summary.intent;
         if intent.is_empty() {
             continue;
@@ -315,8 +379,12 @@ fn format_summaries(rows: &[SummaryRow]) -> String {
                 .summary
                 .learned
                 .iter()
+                .zip(&row.stale)
                 .take(3)
-                .map(|s| s.as_str())
+                .map(|(s, stale)| match stale {
+                    Some(note) => format!("{s} {note}"),
+                    None => s.clone(),
+                })
                 .collect::<Vec<_>>()
                 .join("; ");
             out.push_str(&format!("  - Learned: {learned}\n"));
@@ -325,68 +393,140 @@ fn format_summaries(rows: &[SummaryRow]) -> String {
     out
 }
 
+fn format_rollups(rows: &[crate::s4_rollup::Rollup]) -> String {
+    if rows.is_empty() {
+        return String::new();
+    }
+
+    let mut out = String::from("## Earlier\n");
+    for (i, row) in rows.iter().enumerate() {
+        let s = &row.summary;
+        let unit = if row.period == crate::s4_rollup::MONTH { "weeks" } else { "sessions" };
+        out.push_str(&format!(
+            "- **{}** ({} {unit}): {}\n",
+            crate::s4_rollup::label(&row.period, row.period_start),
+            row.source_count,
+            s.overview
+        ));
+        if !s.learned.is_empty() {
+            out.push_str(&format!("  - Learned: {}\n", s.learned.iter().take(3).cloned().collect::<Vec<_>>().join("; ")));
+        }
+        // Older open threads were most likely picked up since
+        if i == 0 && !s.open_threads.is_empty() {
+            out.push_str(&format!("  - Open: {}\n", s.open_threads.iter().take(3).cloned().collect::<Vec<_>>().join("; ")));
+        }
+    }
+    out
+}
+
 // --- Suggested tasks ---
 
-fn query_suggested_tasks(conn: &Connection, project: &str, limit: i64) -> Result<Vec<String>, NmemError> {
-    let mut tasks = Vec::new();
+/// Older summarized sessions whose next steps count toward a suggestion's
+/// tally without adding suggestions of their own.
+const TASK_LOOKBACK: i64 = 10;
+
+/// Blocked and abandoned episodes listed ahead of suggested tasks.
+const UNFINISHED_LIMIT: usize = 3;
+
+/// A next step and how many sessions suggested it or a near-duplicate.
+struct SuggestedTask {
+    text: String,
+    sessions: usize,
+}
 
-    // Gather next_steps from the most recent session summary
+fn query_suggested_tasks(conn: &Connection, project: &str, limit: i64) -> Result<Vec<SuggestedTask>, NmemError> {
+    // (session, step, may lead a suggestion)
+    let mut candidates: Vec<(String, String, bool)> = Vec::new();
+
+    // Gather next_steps from the most recent session summary; the sessions
+    // before it only corroborate
     let mut stmt = conn.prepare(
-        "SELECT summary FROM sessions
-         WHERE project = ?1 AND summary IS NOT NULL
-         ORDER BY started_at DESC LIMIT 1",
+        "SELECT id, summary FROM sessions
+         WHERE project = ?1 AND summary IS NOT NULL AND kind = 'agent'
+         ORDER BY started_at DESC LIMIT ?2",
     )?;
-    let summary_rows: Vec<String> = stmt
-        .query_map(params![project], |row| row.get(0))?
+    let summary_rows: Vec<(String, String)> = stmt
+        .query_map(params![project, TASK_LOOKBACK + 1], |row| Ok((row.get(0)?, row.get(1)?)))?
         .collect::<Result<_, _>>()?;
+    let mut earlier = Vec::new();
 
-    for summary_str in &summary_rows {
+    for (i, (session_id, summary_str)) in summary_rows.iter().enumerate() {
         if let Ok(summary) = serde_json::from_str::<SessionSummary>(summary_str) {
-            for step in summary.next_steps.iter().take(limit as usize) {
-                tasks.push(step.clone());
+            for step in summary.next_steps {
+                if i == 0 {
+                    candidates.push((session_id.clone(), step, true));
+                } else {
+                    earlier.push((session_id.clone(), step, false));
+                }
             }
         }
     }
 
     // Also gather from recent episode narratives that have next_steps
     let mut ep_stmt = conn.prepare(
-        "SELECT w.summary FROM work_units w
+        "SELECT w.session_id, w.summary FROM work_units w
          JOIN sessions s ON w.session_id = s.id
          WHERE s.project = ?1 AND w.summary IS NOT NULL
          ORDER BY w.started_at DESC LIMIT 5",
     )?;
-    let ep_summaries: Vec<String> = ep_stmt
-        .query_map(params![project], |row| row.get(0))?
+    let ep_summaries: Vec<(String, String)> = ep_stmt
+        .query_map(params![project], |row| Ok((row.get(0)?, row.get(1)?)))?
         .collect::<Result<_, _>>()?;
 
-    for ep_summary in &ep_summaries {
-        if tasks.len() >= limit as usize {
-            break;
-        }
+    for (session_id, ep_summary) in &ep_summaries {
         if let Ok(val) = serde_json::from_str::<serde_json::Value>(ep_summary)
             && let Some(serde_json::Value::Array(steps)) = val.get("next_steps") {
                 for step in steps.iter().filter_map(|v| v.as_str()) {
-                    if tasks.len() >= limit as usize {
-                        break;
-                    }
-                    if !tasks.iter().any(|t| t == step) {
-                        tasks.push(step.to_string());
-                    }
+                    candidates.push((session_id.clone(), step.to_string(), true));
                 }
             }
     }
+    candidates.extend(earlier);
+
+    // Near-duplicates merge into one line that counts its sessions, freeing
+    // slots for different work
+    let mut tasks = Vec::new();
+    for group in group_similar(candidates.iter().map(|(_, step, _)| step.as_str())) {
+        let (_, text, leads) = &candidates[group[0]];
+        if !leads {
+            continue;
+        }
+        let sessions: HashSet<&str> = group.iter().map(|&i| candidates[i].0.as_str()).collect();
+        tasks.push(SuggestedTask { text: text.clone(), sessions: sessions.len() });
+        if tasks.len() >= limit as usize {
+            break;
+        }
+    }
 
     Ok(tasks)
 }
 
-fn format_suggested_tasks(tasks: &[String]) -> String {
-    if tasks.is_empty() {
+/// Suggested tasks, led by unfinished episodes: blocked, then abandoned.
+fn format_suggested_tasks(unfinished: &[Unfinished], tasks: &[SuggestedTask]) -> String {
+    if unfinished.is_empty() && tasks.is_empty() {
         return String::new();
     }
 
     let mut out = String::from("## Suggested Tasks\n");
+    for episode in unfinished {
+        let intent: String = episode.intent.chars().take(80).collect();
+        let why = if episode.outcome == crate::s4_outcome::BLOCKED {
+            "ended on a failing command"
+        } else {
+            "dropped, then picked up again"
+        };
+        out.push_str(&format!(
+            "- Unfinished ({}, {}): **{intent}** — {why}\n",
+            episode.outcome,
+            timefmt::relative(episode.started_at)
+        ));
+    }
     for task in tasks {
-        out.push_str(&format!("- {task}\n"));
+        if task.sessions > 1 {
+            out.push_str(&format!("- {} (suggested in {} sessions)\n", task.text, task.sessions));
+        } else {
+            out.push_str(&format!("- {}\n", task.text));
+        }
     }
     out
 }
@@ -401,37 +541,82 @@ struct ContextRow {
     content: String,
     is_pinned: bool,
     project: Option<String>,
+    /// File no longer exists under the session's cwd.
+    missing: bool,
+    /// Suffix of a marker standing for its whole thread.
+    thread: Option<String>,
 }
 
+/// Context rows compete for `?2` slots on the importance model's recent
+/// blend (`?4` is the half-life in days), and are listed newest first.
 const PROJECT_LOCAL_SQL: &str = "
-SELECT o.id, o.timestamp, o.obs_type, o.file_path, o.content, o.is_pinned,
-       NULL AS project
-FROM observations o
-JOIN sessions s ON o.session_id = s.id
-WHERE s.project = ?1
-  AND (?3 IS NULL OR o.timestamp < ?3)
-  AND (
-    o.is_pinned = 1
-    OR (o.obs_type = 'file_edit' AND o.timestamp > unixepoch('now') - 7200)
-    OR (o.obs_type IN ('git_commit', 'git_push') AND o.timestamp > unixepoch('now') - 86400)
-  )
-ORDER BY o.is_pinned DESC, o.timestamp DESC
-LIMIT ?2";
+SELECT * FROM (
+    SELECT o.id, o.timestamp, o.obs_type, o.file_path, o.content, o.is_pinned,
+           NULL AS project
+    FROM observations o
+    JOIN sessions s ON o.session_id = s.id
+    WHERE s.project = ?1
+      AND (?3 IS NULL OR o.timestamp < ?3)
+      AND (
+        o.is_pinned = 1
+        OR (o.obs_type = 'file_edit' AND o.timestamp > unixepoch('now') - 7200)
+        OR (o.obs_type IN ('git_commit', 'git_push') AND o.timestamp > unixepoch('now') - 86400)
+      )
+    ORDER BY o.is_pinned DESC,
+             recent_score(exp_decay((COALESCE(?3, unixepoch('now')) - o.timestamp) / 86400.0, ?4),
+                          o.obs_type, o.is_pinned, 1) DESC,
+             o.timestamp DESC
+    LIMIT ?2
+)
+ORDER BY is_pinned DESC, timestamp DESC";
+
+/// The current session's changes: edits, writes, commits, and failures.
+/// Binds the session id as `?1`.
+const SESSION_ACTIVITY_SQL: &str = "
+SELECT * FROM (
+    SELECT o.id, o.timestamp, o.obs_type, o.file_path, o.content, o.is_pinned,
+           NULL AS project
+    FROM observations o
+    WHERE o.session_id = ?1
+      AND (?3 IS NULL OR o.timestamp < ?3)
+      AND (
+        o.obs_type IN ('file_edit', 'file_write', 'git_commit', 'git_push')
+        OR json_extract(o.metadata, '$.failed') = 1
+      )
+    ORDER BY recent_score(exp_decay((COALESCE(?3, unixepoch('now')) - o.timestamp) / 86400.0, ?4),
+                          o.obs_type, o.is_pinned, 1) DESC,
+             o.timestamp DESC
+    LIMIT ?2
+)
+ORDER BY timestamp DESC";
 
 const CROSS_PROJECT_SQL: &str = "
-SELECT o.id, o.timestamp, o.obs_type, o.file_path, o.content, o.is_pinned,
-       s.project
-FROM observations o
-JOIN sessions s ON o.session_id = s.id
-WHERE s.project IS NOT NULL AND s.project != ?1
-  AND o.is_pinned = 1
-  AND (?3 IS NULL OR o.timestamp < ?3)
-ORDER BY o.timestamp DESC
-LIMIT ?2";
-
-fn query_rows(conn: &Connection, sql: &str, project: &str, limit: i64, before: Option<i64>) -> Result<Vec<ContextRow>, NmemError> {
+SELECT * FROM (
+    SELECT o.id, o.timestamp, o.obs_type, o.file_path, o.content, o.is_pinned,
+           s.project
+    FROM observations o
+    JOIN sessions s ON o.session_id = s.id
+    WHERE s.project IS NOT NULL AND s.project != ?1
+      AND NOT EXISTS (SELECT 1 FROM archived_projects a WHERE a.project = s.project)
+      AND o.is_pinned = 1
+      AND (?3 IS NULL OR o.timestamp < ?3)
+    ORDER BY recent_score(exp_decay((COALESCE(?3, unixepoch('now')) - o.timestamp) / 86400.0, ?4),
+                          o.obs_type, o.is_pinned, 0) DESC,
+             o.timestamp DESC
+    LIMIT ?2
+)
+ORDER BY timestamp DESC";
+
+fn query_rows(
+    conn: &Connection,
+    sql: &str,
+    project: &str,
+    limit: i64,
+    before: Option<i64>,
+    half_life: f64,
+) -> Result<Vec<ContextRow>, NmemError> {
     let mut stmt = conn.prepare(sql)?;
-    let rows = stmt.query_map(params![project, limit, before], |row| {
+    let rows = stmt.query_map(params![project, limit, before, half_life], |row| {
         Ok(ContextRow {
             id: row.get(0)?,
             timestamp: row.get(1)?,
@@ -440,12 +625,56 @@ fn query_rows(conn: &Connection, sql: &str, project: &str, limit: i64, before: O
             content: row.get(4)?,
             is_pinned: row.get::<_, i64>(5)? != 0,
             project: row.get(6)?,
+            missing: false,
+            thread: None,
         })
     })?
     .collect::<Result<Vec<_>, _>>()?;
     Ok(rows)
 }
 
+/// Show each marker thread once, as its newest marker: the thread's latest
+/// status first, then the newest text, noting how long the thread is and
+/// which marker started it. Rows are newest first, so the first member seen
+/// stands in for the rest.
+fn collapse_marker_threads(conn: &Connection, rows: Vec<ContextRow>) -> Result<Vec<ContextRow>, NmemError> {
+    let mut stmt = conn.prepare_cached(
+        "WITH root AS (
+             SELECT COALESCE(json_extract(metadata, '$.thread'), id) AS id FROM observations WHERE id = ?1
+         )
+         SELECT o.id, o.timestamp, o.content, json_extract(o.metadata, '$.status'), root.id
+         FROM observations o, root
+         WHERE o.obs_type = 'marker' AND (o.id = root.id OR json_extract(o.metadata, '$.thread') = root.id)
+         ORDER BY o.timestamp DESC, o.id DESC",
+    )?;
+    let mut roots = HashSet::new();
+    let mut out = Vec::with_capacity(rows.len());
+    for mut row in rows {
+        if row.obs_type == "marker" {
+            let thread: Vec<(i64, i64, String, Option<String>, i64)> = stmt
+                .query_map(params![row.id], |r| Ok((r.get(0)?, r.get(1)?, r.get(2)?, r.get(3)?, r.get(4)?)))?
+                .collect::<Result<_, _>>()?;
+            if thread.len() > 1 {
+                let root = thread[0].4;
+                if !roots.insert(root) {
+                    continue;
+                }
+                let (id, timestamp, content, _, _) = &thread[0];
+                let status = thread.iter().find_map(|t| t.3.as_deref());
+                row.id = *id;
+                row.timestamp = *timestamp;
+                row.content = match status {
+                    Some(status) => format!("[{status}] {content}"),
+                    None => content.clone(),
+                };
+                row.thread = Some(format!(" (thread of {} from #{root})", thread.len()));
+            }
+        }
+        out.push(row);
+    }
+    Ok(out)
+}
+
 fn title_for_row(row: &ContextRow) -> String {
     if let Some(fp) = &row.file_path {
         fp.clone()
@@ -462,6 +691,7 @@ fn title_for_row(row: &ContextRow) -> String {
 /// Compress observation rows into grouped activity lines.
 /// Pinned items and git operations stay as individual rows.
 /// File edits are grouped by path with counts.
+/// Rows whose file is missing are marked and listed after live ones.
 fn format_activity(rows: &[ContextRow], header: &str) -> String {
     if rows.is_empty() {
         return String::new();
@@ -470,16 +700,17 @@ fn format_activity(rows: &[ContextRow], header: &str) -> String {
     let mut out = String::new();
     out.push_str(header);
     out.push('\n');
+    out.push_str(&format_freshness(rows.iter().map(|r| r.timestamp)));
 
     // Separate into individual items (pinned, git ops) and grouped edits
     let mut individual: Vec<&ContextRow> = Vec::new();
-    let mut edit_groups: std::collections::BTreeMap<String, (i64, i64, String)> = std::collections::BTreeMap::new(); // path -> (count, most_recent_ts, obs_type)
+    let mut edit_groups: std::collections::BTreeMap<String, (i64, i64, bool)> = std::collections::BTreeMap::new(); // path -> (count, most_recent_ts, missing)
 
     for row in rows {
         if row.is_pinned || row.obs_type == "git_commit" || row.obs_type == "git_push" {
             individual.push(row);
         } else if let Some(fp) = &row.file_path {
-            let entry = edit_groups.entry(fp.clone()).or_insert((0, row.timestamp, row.obs_type.clone()));
+            let entry = edit_groups.entry(fp.clone()).or_insert((0, row.timestamp, row.missing));
             entry.0 += 1;
             if row.timestamp > entry.1 {
                 entry.1 = row.timestamp;
@@ -490,16 +721,20 @@ fn format_activity(rows: &[ContextRow], header: &str) -> String {
     }
 
     // Sort grouped edits by most recent timestamp descending
-    let mut grouped: Vec<(String, i64, i64, String)> = edit_groups
+    let mut grouped: Vec<(String, i64, i64, bool)> = edit_groups
         .into_iter()
-        .map(|(path, (count, ts, obs_type))| (path, count, ts, obs_type))
+        .map(|(path, (count, ts, missing))| (path, count, ts, missing))
         .collect();
-    grouped.sort_by(|a, b| b.2.cmp(&a.2));
+    grouped.sort_by_key(|g| (g.3, std::cmp::Reverse(g.2)));
+    individual.sort_by_key(|r| r.missing);
 
     // Format grouped edits
-    for (path, count, ts, _obs_type) in &grouped {
-        let time = format_relative_time(*ts);
-        let path = path.replace('|', "\\|");
+    for (path, count, ts, missing) in &grouped {
+        let time = timefmt::relative(*ts);
+        let mut path = path.replace('|', "\\|");
+        if *missing {
+            path.push_str(STALE_MARKER);
+        }
         if *count == 1 {
             out.push_str(&format!("- {path} ({time})\n"));
         } else {
@@ -509,9 +744,15 @@ fn format_activity(rows: &[ContextRow], header: &str) -> String {
 
     // Format individual items (pinned, git ops)
     for row in &individual {
-        let time = format_relative_time(row.timestamp);
+        let time = timefmt::relative(row.timestamp);
         let title = title_for_row(row);
-        let title = title.replace('|', "\\|");
+        let mut title = title.replace('|', "\\|");
+        if row.missing {
+            title.push_str(STALE_MARKER);
+        }
+        if let Some(thread) = &row.thread {
+            title.push_str(thread);
+        }
         let project_suffix = if let Some(p) = &row.project {
             format!(" [{p}]")
         } else {
@@ -528,76 +769,612 @@ fn format_activity(rows: &[ContextRow], header: &str) -> String {
     out
 }
 
+// --- Concluded investigations ---
+
+/// Lowercased words of 4+ chars, the unit for matching investigations to
+/// recent intents.
+pub(crate) fn terms(text: &str) -> HashSet<String> {
+    text.split(|c: char| !c.is_alphanumeric() && c != '_')
+        .filter(|w| w.chars().count() >= 4)
+        .map(str::to_lowercase)
+        .collect()
+}
+
+/// Pick up to `limit` investigations to surface: those sharing a file with
+/// recent work or a term with recent intents first, then any concluded
+/// since `recent_cutoff`. Input is newest first; so is each tier.
+fn select_investigations(
+    investigations: Vec<Investigation>,
+    recent_files: &HashSet<&str>,
+    recent_terms: &HashSet<String>,
+    recent_cutoff: i64,
+    limit: usize,
+) -> Vec<Investigation> {
+    let (related, rest): (Vec<_>, Vec<_>) = investigations.into_iter().partition(|inv| {
+        inv.files.iter().any(|f| recent_files.contains(f.as_str()))
+            || !terms(&inv.question).is_disjoint(recent_terms)
+    });
+    related
+        .into_iter()
+        .chain(rest.into_iter().filter(|inv| inv.concluded_at >= recent_cutoff))
+        .take(limit)
+        .collect()
+}
+
+/// Memories the agent wrote with `remember`, in order until `budget`
+/// characters are used; the rest are counted.
+fn format_memories(rows: &[crate::s1_remember::Memory], budget: usize) -> String {
+    if rows.is_empty() || budget == 0 {
+        return String::new();
+    }
+
+    let mut out = String::from("## Remembered\n");
+    let mut used = 0;
+    for (i, memory) in rows.iter().enumerate() {
+        let scope = if memory.project.is_none() { " (global)" } else { "" };
+        let line = format!("- **{}**{scope}: {}\n", memory.key, memory.content.replace('\n', " "));
+        let len = line.chars().count();
+        if used + len > budget {
+            // A first memory over budget still shows, cut short
+            if i == 0 {
+                out.extend(line.chars().take(budget.saturating_sub(4)));
+                out.push_str("...\n");
+                used = budget;
+                continue;
+            }
+            out.push_str(&format!("_{} more not shown; `forget` stale ones_\n", rows.len() - i));
+            break;
+        }
+        out.push_str(&line);
+        used += len;
+    }
+    out
+}
+
+fn format_investigations(rows: &[Investigation]) -> String {
+    if rows.is_empty() {
+        return String::new();
+    }
+
+    let mut out = String::from("## Investigations\n");
+    out.push_str(&format_freshness(rows.iter().map(|r| r.concluded_at)));
+    for inv in rows {
+        let time = timefmt::relative(inv.concluded_at);
+        let conclusion: String = inv.conclusion.chars().take(200).collect();
+        let ellipsis = if inv.conclusion.chars().count() > 200 { "..." } else { "" };
+        out.push_str(&format!(
+            "- **{}** → {conclusion}{ellipsis} ({time})\n",
+            inv.question
+        ));
+        if !inv.files.is_empty() {
+            let files = inv.files.iter().take(5).cloned().collect::<Vec<_>>().join(", ");
+            out.push_str(&format!("  - Files: {files}\n"));
+        }
+    }
+    out
+}
+
+// --- Consolidated topics ---
+
+fn format_topics(rows: &[Topic]) -> String {
+    if rows.is_empty() {
+        return String::new();
+    }
+
+    let mut out = String::from("## Topics\n");
+    out.push_str(&format_freshness(rows.iter().map(|r| r.updated_at)));
+    for topic in rows {
+        let m = &topic.memory;
+        out.push_str(&format!(
+            "- **{}** ({} episodes, updated {})\n",
+            m.title,
+            topic.episodes,
+            timefmt::relative(topic.updated_at)
+        ));
+        if !m.current_state.is_empty() {
+            out.push_str(&format!("  - State: {}\n", m.current_state));
+        }
+        if !m.decisions.is_empty() {
+            out.push_str(&format!("  - Decisions: {}\n", m.decisions.iter().take(4).cloned().collect::<Vec<_>>().join("; ")));
+        }
+        if !m.pitfalls.is_empty() {
+            out.push_str(&format!("  - Pitfalls: {}\n", m.pitfalls.iter().take(3).cloned().collect::<Vec<_>>().join("; ")));
+        }
+        if !topic.files.is_empty() {
+            out.push_str(&format!("  - Files: {}\n", topic.files.iter().take(5).cloned().collect::<Vec<_>>().join(", ")));
+        }
+    }
+    out
+}
+
+/// Drop the items `hidden` matches, counting them into `held`.
+fn hold_back<T>(rows: &mut Vec<T>, held: &mut usize, hidden: impl Fn(&T) -> bool) {
+    let before = rows.len();
+    rows.retain(|r| !hidden(r));
+    *held += before - rows.len();
+}
+
+// --- Project docs ---
+
+fn format_docs(rows: &[crate::s1_docs::DocHit]) -> String {
+    if rows.is_empty() {
+        return String::new();
+    }
+
+    let mut out = String::from("## Project Docs\n");
+    for doc in rows {
+        let text = doc.text.split_whitespace().collect::<Vec<_>>().join(" ");
+        let preview: String = text.chars().take(200).collect();
+        let ellipsis = if text.chars().count() > 200 { "..." } else { "" };
+        out.push_str(&format!("- #{} **{}** — {preview}{ellipsis}\n", doc.id, doc.heading));
+    }
+    out
+}
+
+// --- Files shared with other projects ---
+
+fn format_shared_files(rows: &[SharedFile]) -> String {
+    if rows.is_empty() {
+        return String::new();
+    }
+
+    let mut out = String::from("## Shared files
+");
+    for row in rows {
+        out.push_str(&format!(
+            "- {} is also governed by project {} ({})",
+            row.file_path,
+            row.project,
+            timefmt::relative(row.last_touched)
+        ));
+        if let Some(decision) = &row.last_decision {
+            let text: String = decision.chars().take(160).collect();
+            let ellipsis = if decision.chars().count() > 160 { "..." } else { "" };
+            out.push_str(&format!("; last decision there: {text}{ellipsis}"));
+        }
+        out.push('\n');
+    }
+    out
+}
+
+// --- Stale path detection ---
+
+const STALE_MARKER: &str = " (deleted/moved)";
+
+/// Paths from `paths` that no longer exist on disk. Relative paths resolve
+/// against `cwd`; anything that can't be checked is assumed to still exist.
+fn missing_paths<'a>(cwd: &Path, paths: impl IntoIterator<Item = &'a str>) -> HashSet<String> {
+    let mut seen = HashSet::new();
+    paths
+        .into_iter()
+        .filter(|p| !p.is_empty() && seen.insert(*p))
+        .filter(|p| !cwd.join(p).exists())
+        .map(str::to_string)
+        .collect()
+}
+
+/// Move missing hot files to the end of each episode's list and mark them.
+fn mark_stale_episode_files(rows: &mut [EpisodeRow], missing: &HashSet<String>) {
+    for row in rows {
+        row.hot_files.sort_by_key(|f| missing.contains(f));
+        for f in &mut row.hot_files {
+            if missing.contains(f.as_str()) {
+                f.push_str(STALE_MARKER);
+            }
+        }
+    }
+}
+
 // --- Main generation ---
 
-/// Generate context injection markdown for a SessionStart event.
+/// An environment issue is noted in context while its last failure is this
+/// recent.
+const ENVIRONMENT_ACTIVE_SECS: i64 = 3600;
+
+/// Generate the full (`startup`) context injection markdown.
 /// Returns empty string if no observations exist.
-pub fn generate_context(conn: &Connection, project: &str, local_limit: i64, cross_limit: i64, before: Option<i64>) -> Result<String, NmemError> {
-    register_udfs(conn)?;
+///
+/// With `cwd`, project files mentioned in episodes and recent activity are
+/// checked against the working tree; missing ones are marked
+/// "(deleted/moved)" and listed last. Historical (`before`) views skip this.
+pub fn generate_context(
+    conn: &Connection,
+    project: &str,
+    local_limit: i64,
+    cross_limit: i64,
+    before: Option<i64>,
+    cwd: Option<&Path>,
+) -> Result<String, NmemError> {
+    let profile = ResolvedContextProfile {
+        sections: ContextSection::FULL.to_vec(),
+        episodes: 15,
+        local_limit,
+        cross_limit,
+    };
+    generate_profiled_context(conn, project, &profile, None, before, cwd)
+}
 
+/// Generate context with only the sections and sizes in `profile` (see
+/// `resolve_context_profile`). `session_id` is the session being resumed or
+/// compacted; `CurrentSession` needs it and is skipped without one.
+pub fn generate_profiled_context(
+    conn: &Connection,
+    project: &str,
+    profile: &ResolvedContextProfile,
+    session_id: Option<&str>,
+    before: Option<i64>,
+    cwd: Option<&Path>,
+) -> Result<String, NmemError> {
+    use ContextSection::*;
     let config = crate::config::load_config().unwrap_or_default();
+    register_udfs_with(conn, crate::config::resolve_importance(&config))?;
+    let half_life = crate::config::resolve_recency_half_life(&config, Some(project));
     let episode_window = crate::config::resolve_episode_window(&config, project);
+    let wants = |section| profile.sections.contains(&section);
+    let session_id = session_id.filter(|_| wants(CurrentSession));
+    let now = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs() as i64;
+
+    let mut session_rows = match session_id {
+        Some(id) => query_episodes(conn, project, episode_window, profile.episodes.max(5), before, Some(id))?,
+        None => Vec::new(),
+    };
+    let mut session_activity = match session_id {
+        Some(id) => collapse_marker_threads(conn, query_rows(conn, SESSION_ACTIVITY_SQL, id, 15, before, half_life)?)?,
+        None => Vec::new(),
+    };
+    // Episodes need fetching for investigations and topics too, which key off them
+    let mut episode_rows = if wants(Episodes) || wants(Investigations) || wants(Topics) || wants(Docs) {
+        query_episodes(conn, project, episode_window, profile.episodes, before, None)?
+    } else {
+        Vec::new()
+    };
+    // Past the detail window, weekly and monthly rollups stand in for the
+    // sessions they cover; absent on databases not yet migrated by a hook
+    let mut rollups = if wants(Summaries) {
+        let cutoff = before.unwrap_or(now) - config.rollup.detail_days as i64 * 86400;
+        crate::s4_rollup::for_context(conn, project, cutoff, before).unwrap_or_default()
+    } else {
+        Vec::new()
+    };
+    let mut summary_rows = if wants(Summaries) {
+        let covered_until = rollups.iter().map(|r| r.period_end).max();
+        query_fallback_summaries(conn, project, episode_window, 5, before, covered_until)?
+    } else {
+        Vec::new()
+    };
+    let mut suggested = if wants(Tasks) {
+        query_suggested_tasks(conn, project, 5)?
+    } else {
+        Vec::new()
+    };
+    let mut unfinished = if wants(Tasks) {
+        let mut rows = crate::s4_outcome::unfinished(conn, project, before.unwrap_or(now) - episode_window, before)?;
+        rows.truncate(UNFINISHED_LIMIT);
+        rows
+    } else {
+        Vec::new()
+    };
+    let mut local_rows = if wants(Activity) {
+        collapse_marker_threads(conn, query_rows(conn, PROJECT_LOCAL_SQL, project, profile.local_limit, before, half_life)?)?
+    } else {
+        Vec::new()
+    };
+    let mut cross_rows = if wants(CrossProject) {
+        collapse_marker_threads(conn, query_rows(conn, CROSS_PROJECT_SQL, project, profile.cross_limit, before, half_life)?)?
+    } else {
+        Vec::new()
+    };
 
-    let episode_rows = query_episodes(conn, project, episode_window, 15, before)?;
-    let summary_rows = query_fallback_summaries(conn, project, episode_window, 5, before)?;
-    let suggested = query_suggested_tasks(conn, project, 5)?;
-    let local_rows = query_rows(conn, PROJECT_LOCAL_SQL, project, local_limit, before)?;
-    let cross_rows = query_rows(conn, CROSS_PROJECT_SQL, project, cross_limit, before)?;
+    // Suppression rules (`nmem suppress`) hold back matching items, counted
+    // in the header; before stale marking, which suffixes hot file names
+    let suppress = crate::s3_suppress::active(conn, project, now);
+    let mut held_back = 0usize;
+    if !suppress.is_empty() {
+        let episode_hidden = |r: &EpisodeRow| {
+            suppress.hides(
+                [Some(r.intent.as_str()), r.summary.as_deref(), r.session_intent.as_deref()].into_iter().flatten(),
+                r.hot_files.iter().map(String::as_str),
+            )
+        };
+        let row_hidden = |r: &ContextRow| suppress.hides([r.content.as_str()], r.file_path.as_deref());
+        hold_back(&mut episode_rows, &mut held_back, episode_hidden);
+        hold_back(&mut session_rows, &mut held_back, episode_hidden);
+        hold_back(&mut summary_rows, &mut held_back, |r| {
+            let s = &r.summary;
+            suppress.hides(
+                std::iter::once(s.intent.as_str()).chain(s.learned.iter().chain(&s.completed).chain(&s.next_steps).map(String::as_str)),
+                s.files_read.iter().chain(&s.files_edited).map(String::as_str),
+            )
+        });
+        hold_back(&mut rollups, &mut held_back, |r| {
+            let s = &r.summary;
+            suppress.hides(std::iter::once(s.overview.as_str()).chain(s.learned.iter().map(String::as_str)), [])
+        });
+        hold_back(&mut suggested, &mut held_back, |t| suppress.hides([t.text.as_str()], []));
+        hold_back(&mut unfinished, &mut held_back, |u| {
+            suppress.hides([u.intent.as_str()], u.hot_files.iter().map(String::as_str))
+        });
+        hold_back(&mut session_activity, &mut held_back, row_hidden);
+        hold_back(&mut local_rows, &mut held_back, row_hidden);
+        hold_back(&mut cross_rows, &mut held_back, row_hidden);
+    }
 
-    if episode_rows.is_empty() && summary_rows.is_empty()
-        && local_rows.is_empty() && cross_rows.is_empty()
-    {
-        return Ok(String::new());
+    // Other projects' rows may come from other users of a shared database
+    if !cross_rows.is_empty() {
+        let mut paths = crate::s5_anonymize::PathMap::for_db(conn, config.filter.anonymize_paths)?;
+        if !paths.is_off() {
+            for row in &mut cross_rows {
+                row.content = paths.text(&row.content);
+                row.file_path = row.file_path.as_deref().map(|p| paths.text(p));
+            }
+        }
     }
 
-    let mut out = String::from("# nmem context\n\n");
+    // Before stale marking, which suffixes hot file names
+    let mut shared = if wants(SharedFiles) {
+        let mut paths: Vec<&str> = episode_rows.iter().chain(&session_rows)
+            .flat_map(|r| r.hot_files.iter().map(String::as_str))
+            .chain(local_rows.iter().filter_map(|r| r.file_path.as_deref()))
+            .collect();
+        paths.sort_unstable();
+        paths.dedup();
+        crate::s4_overlap::shared_files(conn, project, &paths, before, 5)?
+    } else {
+        Vec::new()
+    };
 
-    let episodes = format_episodes(&episode_rows);
-    if !episodes.is_empty() {
-        out.push_str(&episodes);
-        out.push('\n');
+    // Other projects' paths aren't checked: relative ones don't resolve here
+    if let Some(cwd) = cwd.filter(|_| before.is_none()) {
+        let missing = missing_paths(
+            cwd,
+            episode_rows.iter().chain(&session_rows)
+                .flat_map(|r| r.hot_files.iter().map(String::as_str))
+                .chain(local_rows.iter().filter_map(|r| r.file_path.as_deref())),
+        );
+        mark_stale_episode_files(&mut episode_rows, &missing);
+        mark_stale_episode_files(&mut session_rows, &missing);
+        for row in &mut local_rows {
+            row.missing = row.file_path.as_ref().is_some_and(|f| missing.contains(f));
+        }
     }
 
-    let summaries = format_summaries(&summary_rows);
-    if !summaries.is_empty() {
-        out.push_str(&summaries);
-        out.push('\n');
+    let mut investigations = if wants(Investigations) {
+        let recent_files: HashSet<&str> = episode_rows.iter()
+            .flat_map(|r| r.hot_files.iter().map(String::as_str))
+            .chain(local_rows.iter().filter_map(|r| r.file_path.as_deref()))
+            .collect();
+        let recent_terms: HashSet<String> = episode_rows.iter().flat_map(|r| terms(&r.intent)).collect();
+        let cutoff = before.unwrap_or(now) - episode_window;
+        select_investigations(
+            crate::s1_investigate::concluded(conn, project, before, 20)?,
+            &recent_files,
+            &recent_terms,
+            cutoff,
+            3,
+        )
+    } else {
+        Vec::new()
+    };
+
+    let mut docs = if wants(Docs) {
+        let recent_terms: HashSet<String> =
+            episode_rows.iter().chain(&session_rows).flat_map(|r| terms(&r.intent)).collect();
+        crate::s1_docs::related(conn, project, &recent_terms, 3)?
+    } else {
+        Vec::new()
+    };
+
+    // A topic stands in for the episodes it consolidates
+    let mut topics = if wants(Topics) {
+        let mut ids: Vec<i64> = episode_rows.iter().filter_map(|r| r.topic_id).collect();
+        ids.sort_unstable();
+        ids.dedup();
+        crate::s4_consolidate::topics_by_id(conn, &ids)?
+    } else {
+        Vec::new()
+    };
+    if wants(Episodes) {
+        episode_rows.retain(|r| {
+            r.topic_id.is_none_or(|_| !wants(Topics)) && Some(r.session_id.as_str()) != session_id
+        });
+    } else {
+        episode_rows.clear();
     }
 
-    let tasks = format_suggested_tasks(&suggested);
-    if !tasks.is_empty() {
-        out.push_str(&tasks);
-        out.push('\n');
+    if !suppress.is_empty() {
+        hold_back(&mut investigations, &mut held_back, |i| {
+            suppress.hides([i.question.as_str(), i.conclusion.as_str()], i.files.iter().map(String::as_str))
+        });
+        hold_back(&mut topics, &mut held_back, |t| {
+            let m = &t.memory;
+            suppress.hides(
+                [m.title.as_str(), m.current_state.as_str()].into_iter().chain(m.decisions.iter().chain(&m.pitfalls).map(String::as_str)),
+                t.files.iter().map(String::as_str),
+            )
+        });
+        hold_back(&mut docs, &mut held_back, |d| {
+            suppress.hides([d.heading.as_str(), d.text.as_str()], d.heading.split(" > ").next())
+        });
+        hold_back(&mut shared, &mut held_back, |f| {
+            suppress.hides(f.last_decision.as_deref(), [f.file_path.as_str()])
+        });
     }
 
-    let activity = format_activity(&local_rows, &format!("## {project}"));
-    if !activity.is_empty() {
-        out.push_str(&activity);
+    let provided = if wants(Providers) {
+        crate::s4_providers::run_providers(&config.context.providers, project, now, before)
+    } else {
+        Vec::new()
+    };
+
+    // Errors failing across projects within the last hour — a DNS outage or a
+    // stopped docker daemon, not this project's code
+    let environment: Vec<_> = if before.is_none() {
+        crate::s3_learn::detect_environment_issues(
+            conn,
+            config.learn.environment_projects,
+            config.learn.environment_window_hours as i64 * 3600,
+            now,
+        )?
+        .into_iter()
+        .filter(|i| i.last_seen > now - ENVIRONMENT_ACTIVE_SECS)
+        .collect()
+    } else {
+        Vec::new()
+    };
+
+    // Tests that keep failing session after session
+    let recurring_tests = if before.is_none() && config.learn.recurring_test_sessions > 0 {
+        crate::s2_test_failure::recurring(conn, project, config.learn.recurring_test_sessions as i64, 3)?
+    } else {
+        Vec::new()
+    };
+
+    // What the agent asked to keep, written deliberately with `remember`
+    let memories = crate::s1_remember::active(conn, project, before.unwrap_or(now))?;
+
+    // A project with no summarized sessions yet starts from its snapshot
+    let onboarding = crate::s1_onboard::latest(conn, project, before)?;
+
+    // Absent on databases not yet migrated by a hook
+    let pause = before
+        .is_none()
+        .then(|| crate::s3_pause::active(conn, project, now).ok().flatten())
+        .flatten();
+
+    if session_rows.is_empty() && session_activity.is_empty()
+        && episode_rows.is_empty() && summary_rows.is_empty() && rollups.is_empty() && suggested.is_empty()
+        && local_rows.is_empty() && cross_rows.is_empty()
+        && investigations.is_empty() && topics.is_empty() && docs.is_empty() && provided.is_empty()
+        && shared.is_empty() && pause.is_none() && onboarding.is_none() && environment.is_empty()
+        && recurring_tests.is_empty() && memories.is_empty()
+    {
+        return Ok(String::new());
     }
 
-    if !cross_rows.is_empty() {
+    let mut out = String::from("# nmem context\n");
+    // Provenance: when this was generated and how recent the newest item is,
+    // so the consumer can weigh stale sections accordingly.
+    let data_through = episode_rows.iter().chain(&session_rows).map(|r| r.started_at)
+        .chain(summary_rows.iter().map(|r| r.started_at))
+        .chain(local_rows.iter().chain(&cross_rows).chain(&session_activity).map(|r| r.timestamp))
+        .chain(investigations.iter().map(|r| r.concluded_at))
+        .chain(topics.iter().map(|r| r.updated_at))
+        .max()
+        .unwrap_or(now);
+    out.push_str(&format!(
+        "_generated_at: {} · data_through: {}",
+        timefmt::datetime(now),
+        timefmt::datetime(data_through)
+    ));
+    if let Some(ts) = before {
+        out.push_str(&format!(" · as_of: {}", timefmt::datetime(ts)));
+    }
+    out.push_str("_\n");
+    if let Some(ref p) = pause {
+        out.push_str(&format!("_{} — nothing from now on is recorded_\n", p.describe()));
+    }
+    if held_back > 0 {
+        out.push_str(&format!("_{held_back} items held back by suppression rules (`nmem suppress`)_\n"));
+    }
+    for issue in &environment {
+        out.push_str(&format!(
+            "_environment issue: `{}` failing in {} ({}) — likely the machine or network, not the code_\n",
+            issue.signature.chars().take(80).collect::<String>(),
+            issue.projects.join(", "),
+            timefmt::relative(issue.last_seen)
+        ));
+    }
+    for test in &recurring_tests {
+        out.push_str(&format!(
+            "_recurring test failure: `{}` failed in {} sessions (last {}) — see test_history_\n",
+            test.name.chars().take(120).collect::<String>(),
+            test.sessions,
+            timefmt::relative(test.last_seen)
+        ));
+    }
+    out.push('\n');
+    // Ahead of every section: these were written to be read back
+    let remembered = format_memories(&memories, config.context.memory_chars);
+    if !remembered.is_empty() {
+        out.push_str(&remembered);
         out.push('\n');
-        out.push_str(&format_activity(&cross_rows, "## Other projects"));
+    }
+    if let Some(ref snapshot) = onboarding {
+        out.push_str(&format!("## Project snapshot\n{snapshot}\n\n"));
+    }
+
+    for section in &profile.sections {
+        let text = match section {
+            CurrentSession => {
+                let mut text = format_episodes_under(&session_rows, "## This Session");
+                if !session_activity.is_empty() {
+                    let header = if text.is_empty() { "## This Session" } else { "### Changes" };
+                    text.push_str(&format_activity(&session_activity, header));
+                }
+                text
+            }
+            // Concluded investigations are the distilled answers
+            Investigations => format_investigations(&investigations),
+            Topics => format_topics(&topics),
+            Docs => format_docs(&docs),
+            Episodes => format_episodes(&episode_rows),
+            Summaries => format_summaries(&summary_rows) + &format_rollups(&rollups),
+            Tasks => format_suggested_tasks(&unfinished, &suggested),
+            // External providers ([context.providers])
+            Providers => provided.join("\n"),
+            Activity => format_activity(&local_rows, &format!("## {project}")),
+            SharedFiles => format_shared_files(&shared),
+            CrossProject => format_activity(&cross_rows, "## Other projects"),
+        };
+        if !text.is_empty() {
+            out.push_str(&text);
+            out.push('\n');
+        }
+    }
+    // No trailing blank line after the last section
+    if out.ends_with("\n\n") {
+        out.pop();
     }
 
     Ok(out)
 }
 
 /// CLI handler: print context injection output for the current project.
+#[cfg(feature = "cli")]
 pub fn handle_context(db_path: &std::path::Path, args: &crate::cli::ContextArgs) -> Result<(), NmemError> {
     let conn = crate::db::open_db_readonly(db_path)?;
 
     let config = crate::config::load_config()?;
+    let cwd = std::env::current_dir().ok();
     let project = args.project.clone().unwrap_or_else(|| {
-        let cwd = std::env::current_dir()
+        let cwd = cwd.as_deref()
             .map(|p| p.to_string_lossy().into_owned())
             .unwrap_or_default();
         crate::project::derive_project_with_strategy(&cwd, config.project.strategy)
     });
-    let (local_limit, cross_limit) = crate::config::resolve_context_limits(&config, &project, false);
-
-    let ctx = generate_context(&conn, &project, local_limit, cross_limit, None)?;
-    if ctx.is_empty() {
+    let profile = crate::config::resolve_context_profile(&config, &project, &args.event);
+    let session_id: Option<String> = conn
+        .query_row(
+            "SELECT id FROM sessions WHERE project = ?1 AND kind = 'agent'
+               AND (?2 IS NULL OR started_at < ?2)
+             ORDER BY started_at DESC LIMIT 1",
+            params![project, args.before],
+            |r| r.get(0),
+        )
+        .optional()?;
+
+    // Only check file existence when the working tree is the project's own
+    let check_dir = cwd.as_deref().filter(|_| args.project.is_none());
+    let ctx = generate_profiled_context(&conn, &project, &profile, session_id.as_deref(), args.before, check_dir)?;
+    if let Some(path) = &args.file {
+        std::fs::write(path, &ctx)?;
+        if ctx.is_empty() {
+            log::info!("no context available for project \"{project}\"; wrote empty {}", path.display());
+        }
+    } else if ctx.is_empty() {
         println!("No context available for project \"{project}\".");
     } else {
         print!("{ctx}");
@@ -608,6 +1385,7 @@ pub fn handle_context(db_path: &std::path::Path, args: &crate::cli::ContextArgs)
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::db::register_udfs;
     use crate::schema::MIGRATIONS;
 
     fn mock_ts(minutes_ago: i64) -> i64 {
@@ -631,35 +1409,6 @@ mod tests {
             .as_secs() as i64
     }
 
-    #[test]
-    fn format_relative_time_minutes() {
-        let ts = mock_ts(30);
-        let result = format_relative_time(ts);
-        assert_eq!(result, "30m ago");
-    }
-
-    #[test]
-    fn format_relative_time_hours() {
-        let ts = mock_ts(5 * 60);
-        let result = format_relative_time(ts);
-        assert_eq!(result, "5h ago");
-    }
-
-    #[test]
-    fn format_relative_time_days() {
-        let ts = mock_ts(3 * 24 * 60);
-        let result = format_relative_time(ts);
-        assert_eq!(result, "3d ago");
-    }
-
-    #[test]
-    fn format_relative_time_old() {
-        let ts = mock_ts(10 * 24 * 60);
-        let result = format_relative_time(ts);
-        assert!(result.len() >= 5, "expected date format, got: {result}");
-        assert!(!result.contains("ago"), "should not use relative format for >7 days");
-    }
-
     #[test]
     fn is_low_quality_intent_detects_urls() {
         assert!(is_low_quality_intent("https://github.com/foo/bar"));
@@ -682,13 +1431,17 @@ mod tests {
     #[test]
     fn format_episodes_basic() {
         let rows = vec![EpisodeRow {
+            session_id: "s1".into(),
             started_at: mock_ts(10),
             intent: "fix the authentication bug in the login handler".into(),
             obs_count: 5,
             hot_files: vec!["src/auth.rs".into(), "src/handler.rs".into()],
             phase_signature: PhaseInfo { investigate: 2, execute: 3, ..Default::default() },
             summary: None,
+            sources: vec![],
             session_intent: None,
+            topic_id: None,
+            outcome: None,
         }];
         let result = format_episodes(&rows);
         assert!(result.contains("## Recent Episodes"));
@@ -698,16 +1451,47 @@ mod tests {
         assert!(result.contains("src/auth.rs"));
     }
 
+    #[test]
+    fn format_episodes_collapses_repeated_intents() {
+        let row = |minutes: i64, intent: &str| EpisodeRow {
+            session_id: "s1".into(),
+            started_at: mock_ts(minutes),
+            intent: intent.into(),
+            obs_count: 4,
+            hot_files: vec![],
+            phase_signature: PhaseInfo { execute: 4, ..Default::default() },
+            summary: None,
+            sources: vec![],
+            session_intent: None,
+            topic_id: None,
+            outcome: None,
+        };
+        let rows = vec![
+            row(5, "run tests after changes"),
+            row(30, "refactor the dispatch queue"),
+            row(60, "Run the tests after the changes"),
+            row(90, "run tests after changes"),
+        ];
+        let result = format_episodes(&rows);
+        assert_eq!(result.matches("run tests after changes").count(), 1, "{result}");
+        assert!(result.contains("**run tests after changes** (4 obs, execute, repeated in 3 episodes)"), "{result}");
+        assert!(result.contains("**refactor the dispatch queue** (4 obs, execute)"), "{result}");
+    }
+
     #[test]
     fn format_episodes_url_intent_falls_back() {
         let rows = vec![EpisodeRow {
+            session_id: "s1".into(),
             started_at: mock_ts(5),
             intent: "https://github.com/foo/bar/blob/main/doc.md".into(),
             obs_count: 10,
             hot_files: vec![],
             phase_signature: PhaseInfo { investigate: 5, execute: 5, ..Default::default() },
             summary: None,
+            sources: vec![],
             session_intent: Some("Implement Bayesian surprise in episodic memory".into()),
+            topic_id: None,
+            outcome: None,
         }];
         let result = format_episodes(&rows);
         assert!(result.contains("Implement Bayesian surprise"), "should use session intent fallback");
@@ -717,13 +1501,17 @@ mod tests {
     #[test]
     fn format_episodes_short_intent_falls_back() {
         let rows = vec![EpisodeRow {
+            session_id: "s1".into(),
             started_at: mock_ts(5),
             intent: "yes".into(),
             obs_count: 8,
             hot_files: vec![],
             phase_signature: PhaseInfo { execute: 8, ..Default::default() },
             summary: None,
+            sources: vec![],
             session_intent: Some("Refactor dispatch queue logic".into()),
+            topic_id: None,
+            outcome: None,
         }];
         let result = format_episodes(&rows);
         assert!(result.contains("Refactor dispatch"), "should use session intent for short prompts");
@@ -732,13 +1520,17 @@ mod tests {
     #[test]
     fn format_episodes_with_failures() {
         let rows = vec![EpisodeRow {
+            session_id: "s1".into(),
             started_at: mock_ts(5),
             intent: "debug the test".into(),
             obs_count: 3,
             hot_files: vec![],
             phase_signature: PhaseInfo { investigate: 3, execute: 1, failures: 2, ..Default::default() },
             summary: None,
+            sources: vec![],
             session_intent: None,
+            topic_id: None,
+            outcome: None,
         }];
         let result = format_episodes(&rows);
         assert!(result.contains("investigate+failures"));
@@ -747,16 +1539,21 @@ mod tests {
     #[test]
     fn format_episodes_with_learned() {
         let rows = vec![EpisodeRow {
+            session_id: "s1".into(),
             started_at: mock_ts(5),
             intent: "fix auth".into(),
             obs_count: 4,
             hot_files: vec![],
             phase_signature: PhaseInfo { investigate: 1, execute: 1, ..Default::default() },
             summary: Some(r#"{"learned":["stale mocks cause failures","update mock first"]}"#.into()),
+            sources: vec![40, 12, 15],
             session_intent: None,
+            topic_id: None,
+            outcome: None,
         }];
         let result = format_episodes(&rows);
         assert!(result.contains("Learned: stale mocks cause failures; update mock first"));
+        assert!(result.contains("Sources: 3 obs, #12–#40"));
     }
 
     #[test]
@@ -772,16 +1569,29 @@ mod tests {
 
     #[test]
     fn format_suggested_tasks_empty() {
-        assert_eq!(format_suggested_tasks(&[]), "");
+        assert_eq!(format_suggested_tasks(&[], &[]), "");
     }
 
     #[test]
     fn format_suggested_tasks_basic() {
-        let tasks = vec!["Run cargo test".into(), "Update docs".into()];
-        let result = format_suggested_tasks(&tasks);
+        let tasks = vec![
+            SuggestedTask { text: "Run cargo test".into(), sessions: 4 },
+            SuggestedTask { text: "Update docs".into(), sessions: 1 },
+        ];
+        let result = format_suggested_tasks(&[], &tasks);
         assert!(result.contains("## Suggested Tasks"));
-        assert!(result.contains("- Run cargo test"));
-        assert!(result.contains("- Update docs"));
+        assert!(result.contains("- Run cargo test (suggested in 4 sessions)\n"));
+        assert!(result.contains("- Update docs\n"));
+    }
+
+    #[test]
+    fn format_freshness_spans_newest_and_oldest() {
+        assert_eq!(format_freshness([]), "");
+        assert_eq!(format_freshness([mock_ts(120)]), "_freshness: 2h ago_\n");
+        assert_eq!(
+            format_freshness([mock_ts(3 * 1440), mock_ts(120), mock_ts(600)]),
+            "_freshness: newest 2h ago, oldest 3d ago_\n"
+        );
     }
 
     #[test]
@@ -789,23 +1599,51 @@ mod tests {
         assert_eq!(format_activity(&[], "## Test"), "");
     }
 
+    #[test]
+    fn marker_threads_render_as_one_item_latest_status_first() {
+        let conn = setup_db();
+        conn.execute_batch(
+            r#"INSERT INTO sessions (id, project, started_at) VALUES ('s1', 'test', 1);
+               INSERT INTO observations (id, session_id, timestamp, obs_type, source_event, content, metadata) VALUES
+                 (10, 's1', 100, 'marker', 'AgentMarker', 'use sqlite for the cache', '{"status":"proposed"}'),
+                 (11, 's1', 200, 'marker', 'AgentMarker', 'switched to redis', '{"reply_to":10,"thread":10,"status":"superseded"}'),
+                 (12, 's1', 300, 'marker', 'AgentMarker', 'redis needs a password', '{"reply_to":11,"thread":10}'),
+                 (13, 's1', 250, 'marker', 'AgentMarker', 'unrelated waypoint', NULL);"#,
+        )
+        .unwrap();
+        let row = |id: i64, timestamp: i64, content: &str| ContextRow {
+            id, timestamp, obs_type: "marker".into(), file_path: None, content: content.into(),
+            is_pinned: false, project: None, missing: false, thread: None,
+        };
+        let rows = vec![
+            row(11, 200, "switched to redis"),
+            row(13, 250, "unrelated waypoint"),
+            row(10, 100, "use sqlite for the cache"),
+        ];
+        let rows = collapse_marker_threads(&conn, rows).unwrap();
+        assert_eq!(rows.iter().map(|r| r.id).collect::<Vec<_>>(), vec![12, 13]);
+        let out = format_activity(&rows, "## test");
+        assert!(out.contains("- #12 marker [superseded] redis needs a password (thread of 3 from #10)"), "{out}");
+        assert!(out.contains("- #13 marker unrelated waypoint ("), "{out}");
+    }
+
     #[test]
     fn format_activity_groups_edits() {
         let rows = vec![
             ContextRow {
                 id: 1, timestamp: mock_ts(1), obs_type: "file_edit".into(),
                 file_path: Some("src/main.rs".into()), content: String::new(),
-                is_pinned: false, project: None,
+                is_pinned: false, project: None, missing: false, thread: None,
             },
             ContextRow {
                 id: 2, timestamp: mock_ts(2), obs_type: "file_edit".into(),
                 file_path: Some("src/main.rs".into()), content: String::new(),
-                is_pinned: false, project: None,
+                is_pinned: false, project: None, missing: false, thread: None,
             },
             ContextRow {
                 id: 3, timestamp: mock_ts(3), obs_type: "file_edit".into(),
                 file_path: Some("src/main.rs".into()), content: String::new(),
-                is_pinned: false, project: None,
+                is_pinned: false, project: None, missing: false, thread: None,
             },
         ];
         let result = format_activity(&rows, "## myproj");
@@ -820,7 +1658,7 @@ mod tests {
             ContextRow {
                 id: 42, timestamp: mock_ts(5), obs_type: "command".into(),
                 file_path: None, content: "important-cmd".into(),
-                is_pinned: true, project: None,
+                is_pinned: true, project: None, missing: false, thread: None,
             },
         ];
         let result = format_activity(&rows, "## myproj");
@@ -835,7 +1673,7 @@ mod tests {
             ContextRow {
                 id: 100, timestamp: mock_ts(10), obs_type: "git_commit".into(),
                 file_path: None, content: "git commit -m 'fix auth'".into(),
-                is_pinned: false, project: None,
+                is_pinned: false, project: None, missing: false, thread: None,
             },
         ];
         let result = format_activity(&rows, "## myproj");
@@ -843,13 +1681,77 @@ mod tests {
         assert!(result.contains("git_commit"), "git ops should show type");
     }
 
+    #[test]
+    fn format_activity_marks_missing_files_and_lists_them_last() {
+        let rows = vec![
+            ContextRow {
+                id: 1, timestamp: mock_ts(1), obs_type: "file_edit".into(),
+                file_path: Some("src/old.rs".into()), content: String::new(),
+                is_pinned: false, project: None, missing: true, thread: None,
+            },
+            ContextRow {
+                id: 2, timestamp: mock_ts(5), obs_type: "file_edit".into(),
+                file_path: Some("src/new.rs".into()), content: String::new(),
+                is_pinned: false, project: None, missing: false, thread: None,
+            },
+        ];
+        let result = format_activity(&rows, "## myproj");
+        let new = result.find("src/new.rs (").expect("live file listed");
+        let old = result.find("src/old.rs (deleted/moved)").expect("missing file marked");
+        assert!(new < old, "missing file should sort after live one: {result}");
+    }
+
+    #[test]
+    fn select_investigations_prefers_related_then_recent() {
+        let inv = |q: &str, files: &[&str], at: i64| Investigation {
+            question: q.into(),
+            conclusion: "answer".into(),
+            files: files.iter().map(|f| f.to_string()).collect(),
+            concluded_at: at,
+        };
+        let all = vec![
+            inv("unrelated recent question", &[], 900),
+            inv("why does login hang", &[], 500),
+            inv("old unrelated", &["src/db.rs"], 100),
+            inv("cache layout", &["src/cache.rs"], 50),
+        ];
+        let files = HashSet::from(["src/cache.rs"]);
+        let recent_terms = terms("Fix the LOGIN flow");
+        let picked = select_investigations(all, &files, &recent_terms, 800, 5);
+        let questions: Vec<_> = picked.iter().map(|i| i.question.as_str()).collect();
+        assert_eq!(questions, vec!["why does login hang", "cache layout", "unrelated recent question"]);
+
+        let out = format_investigations(&picked[2..]);
+        assert!(out.starts_with("## Investigations\n"));
+        assert!(out.contains("- **unrelated recent question** → answer ("));
+        assert!(!out.contains("Files:"));
+    }
+
+    #[test]
+    fn missing_paths_resolves_relative_to_cwd() {
+        let dir = tempfile::TempDir::new().unwrap();
+        std::fs::write(dir.path().join("here.rs"), "").unwrap();
+        let abs = dir.path().join("here.rs").to_string_lossy().into_owned();
+        let missing = missing_paths(dir.path(), ["here.rs", abs.as_str(), "gone.rs", "gone.rs", "/nonexistent/x.rs"]);
+        assert_eq!(missing, HashSet::from(["gone.rs".to_string(), "/nonexistent/x.rs".to_string()]));
+
+        let mut rows = vec![EpisodeRow {
+            session_id: "s1".into(),
+            started_at: 0, intent: String::new(), obs_count: 1,
+            hot_files: vec!["gone.rs".into(), "here.rs".into()],
+            phase_signature: PhaseInfo::default(), summary: None, sources: vec![], session_intent: None, topic_id: None, outcome: None,
+        }];
+        mark_stale_episode_files(&mut rows, &missing);
+        assert_eq!(rows[0].hot_files, vec!["here.rs", "gone.rs (deleted/moved)"]);
+    }
+
     #[test]
     fn format_activity_single_edit_no_count() {
         let rows = vec![
             ContextRow {
                 id: 1, timestamp: mock_ts(1), obs_type: "file_edit".into(),
                 file_path: Some("src/lib.rs".into()), content: String::new(),
-                is_pinned: false, project: None,
+                is_pinned: false, project: None, missing: false, thread: None,
             },
         ];
         let result = format_activity(&rows, "## myproj");
@@ -880,7 +1782,7 @@ mod tests {
         ).unwrap();
 
         register_udfs(&conn).unwrap();
-        let rows = query_episodes(&conn, "test", 48 * 3600, 15, None).unwrap();
+        let rows = query_episodes(&conn, "test", 48 * 3600, 15, None, None).unwrap();
         assert_eq!(rows.len(), 1);
         assert_eq!(rows[0].intent, "fix auth bug");
     }
@@ -901,7 +1803,7 @@ mod tests {
         ).unwrap();
 
         register_udfs(&conn).unwrap();
-        let rows = query_episodes(&conn, "test", 48 * 3600, 15, None).unwrap();
+        let rows = query_episodes(&conn, "test", 48 * 3600, 15, None, None).unwrap();
         assert_eq!(rows.len(), 1);
         assert_eq!(rows[0].intent, "fix auth bug");
         assert_eq!(rows[0].obs_count, 5);
@@ -924,7 +1826,7 @@ mod tests {
         ).unwrap();
 
         register_udfs(&conn).unwrap();
-        let rows = query_episodes(&conn, "test", 3600, 15, None).unwrap();
+        let rows = query_episodes(&conn, "test", 3600, 15, None, None).unwrap();
         assert!(rows.is_empty());
     }
 
@@ -952,12 +1854,64 @@ mod tests {
             params![ts - 200000, r#"{"intent":"old session","completed":[],"learned":[],"next_steps":[],"files_read":[],"files_edited":[],"notes":null}"#],
         ).unwrap();
 
-        let rows = query_fallback_summaries(&conn, "test", 48 * 3600, 10, None).unwrap();
+        let rows = query_fallback_summaries(&conn, "test", 48 * 3600, 10, None, None).unwrap();
         assert_eq!(rows.len(), 2);
         assert_eq!(rows[0].summary.intent, "recent no episodes");
         assert_eq!(rows[1].summary.intent, "old session");
     }
 
+    #[test]
+    fn rollups_stand_in_for_older_sessions() {
+        let conn = setup_db();
+        let ts = now_ts();
+        for (id, age_days, intent) in [("s1", 3, "recent work"), ("s2", 40, "older work")] {
+            conn.execute(
+                "INSERT INTO sessions (id, project, started_at, summary) VALUES (?1, 'test', ?2, ?3)",
+                params![id, ts - age_days * 86400, format!(r#"{{"intent":"{intent}","learned":[]}}"#)],
+            ).unwrap();
+        }
+        let start = crate::s4_rollup::week_start(ts - 40 * 86400);
+        let period = crate::s4_rollup::Period {
+            project: "test".into(),
+            period: crate::s4_rollup::WEEK,
+            period_start: start,
+            period_end: start + 7 * 86400,
+            sources: 4,
+        };
+        let summary = crate::s4_rollup::RollupSummary {
+            overview: "Moved retention to chunked deletes.".into(),
+            learned: vec!["sweep pauses between chunks".into()],
+            open_threads: vec!["tune the chunk size".into()],
+        };
+        crate::s4_rollup::store_rollup(&conn, &period, &summary, ts).unwrap();
+
+        let ctx = generate_context(&conn, "test", 20, 10, None, None).unwrap();
+        assert!(ctx.contains("recent work"), "{ctx}");
+        assert!(!ctx.contains("older work"), "covered by the rollup: {ctx}");
+        let label = crate::s4_rollup::label("week", start);
+        assert!(ctx.contains(&format!("## Earlier\n- **{label}** (4 sessions): Moved retention to chunked deletes.\n")), "{ctx}");
+        assert!(ctx.contains("  - Open: tune the chunk size\n"), "{ctx}");
+    }
+
+    #[test]
+    fn stale_learned_entries_are_annotated() {
+        let conn = setup_db();
+        let ts = now_ts();
+        conn.execute(
+            "INSERT INTO sessions (id, project, started_at, summary) VALUES ('s1', 'test', ?1, ?2)",
+            params![ts - 150 * 86400, r#"{"intent":"auth work","learned":["API needs the X-Key header"]}"#],
+        ).unwrap();
+        conn.execute(
+            "INSERT INTO sessions (id, project, started_at, summary) VALUES ('s2', 'test', ?1, ?2)",
+            params![ts - 5 * 86400, r#"{"intent":"cache work","learned":["cache is warm after boot"]}"#],
+        ).unwrap();
+
+        let rows = query_fallback_summaries(&conn, "test", 48 * 3600, 10, None, None).unwrap();
+        let out = format_summaries(&rows);
+        assert!(out.contains("Learned: cache is warm after boot\n"), "{out}");
+        assert!(out.contains("Learned: API needs the X-Key header (learned 5 months ago — verify)"), "{out}");
+    }
+
     #[test]
     fn query_suggested_tasks_from_session() {
         let conn = setup_db();
@@ -970,8 +1924,41 @@ mod tests {
 
         let tasks = query_suggested_tasks(&conn, "test", 5).unwrap();
         assert_eq!(tasks.len(), 2);
-        assert_eq!(tasks[0], "Run cargo test");
-        assert_eq!(tasks[1], "Update docs");
+        assert_eq!(tasks[0].text, "Run cargo test");
+        assert_eq!(tasks[1].text, "Update docs");
+    }
+
+    #[test]
+    fn suggested_tasks_merge_near_duplicates_across_sessions() {
+        let conn = setup_db();
+        let ts = now_ts();
+        let summary = |steps: &[&str]| {
+            serde_json::json!({"intent": "work", "completed": [], "learned": [], "next_steps": steps,
+                               "files_read": [], "files_edited": [], "notes": null})
+            .to_string()
+        };
+        for (id, age, steps) in [
+            ("s1", 3600, &["Run cargo test", "Update docs"][..]),
+            ("s2", 7200, &["run cargo test again"][..]),
+            ("s3", 9000, &["Run cargo test", "Bump the version"][..]),
+        ] {
+            conn.execute(
+                "INSERT INTO sessions (id, project, started_at, summary) VALUES (?1, 'test', ?2, ?3)",
+                params![id, ts - age, summary(steps)],
+            )
+            .unwrap();
+        }
+        conn.execute(
+            "INSERT INTO work_units (session_id, started_at, intent, obs_count, hot_files, phase_signature, summary)
+             VALUES ('s1', ?1, 'work', 3, '[]', '{}', ?2)",
+            params![ts - 3000, r#"{"next_steps":["run the cargo tests","Tag the release"]}"#],
+        )
+        .unwrap();
+
+        let tasks = query_suggested_tasks(&conn, "test", 5).unwrap();
+        let lines: Vec<(&str, usize)> = tasks.iter().map(|t| (t.text.as_str(), t.sessions)).collect();
+        // Older sessions corroborate but don't add their own steps
+        assert_eq!(lines, vec![("Run cargo test", 3), ("Update docs", 1), ("Tag the release", 1)]);
     }
 
     #[test]
@@ -1002,12 +1989,275 @@ mod tests {
             [ts - 3600],
         ).unwrap();
 
-        let ctx = generate_context(&conn, "test", 20, 10, None).unwrap();
+        let ctx = generate_context(&conn, "test", 20, 10, None, None).unwrap();
         assert!(ctx.contains("# nmem context"));
         assert!(ctx.contains("## Recent Episodes"));
         assert!(ctx.contains("fix auth bug"));
         assert!(ctx.contains("src/auth.rs"));
         // Intents section should NOT be present
         assert!(!ctx.contains("## Recent Intents"), "intents section should be removed");
+        assert!(ctx.contains("_generated_at: "), "provenance header: {ctx}");
+        assert!(ctx.contains(&format!("data_through: {}", timefmt::datetime(ts - 3500))));
+        assert!(ctx.contains("## Recent Episodes\n_freshness: 1h ago_"));
+    }
+
+    #[test]
+    fn context_notes_active_environment_issue() {
+        let conn = setup_db();
+        register_udfs(&conn).unwrap();
+        let ts = now_ts();
+        for (sid, project, age) in [("w", "web", 600), ("a", "api", 300)] {
+            conn.execute(
+                "INSERT INTO sessions (id, project, started_at) VALUES (?1, ?2, ?3)",
+                params![sid, project, ts - age],
+            ).unwrap();
+            conn.execute(
+                "INSERT INTO observations (session_id, timestamp, obs_type, source_event, content, metadata, error_class)
+                 VALUES (?1, ?2, 'command', 'PostToolUse', 'npm install',
+                         '{\"failed\":true,\"response\":\"npm ERR! getaddrinfo ENOTFOUND registry.npmjs.org\"}', 'network')",
+                params![sid, ts - age],
+            ).unwrap();
+        }
+
+        let ctx = generate_context(&conn, "cli", 20, 10, None, None).unwrap();
+        assert!(ctx.contains("_environment issue: `npm ERR! getaddrinfo ENOTFOUND registry.npmjs.org` failing in api, web (5m ago)"), "{ctx}");
+        // Historical views don't claim it is current
+        let past = generate_context(&conn, "cli", 20, 10, Some(ts + 1), None).unwrap();
+        assert!(!past.contains("environment issue"), "{past}");
+    }
+
+    #[test]
+    fn context_calls_out_recurring_test_failures() {
+        let conn = setup_db();
+        register_udfs(&conn).unwrap();
+        let ts = now_ts();
+        for (i, sid) in ["s1", "s2", "s3"].into_iter().enumerate() {
+            let at = ts - 3600 * (3 - i as i64);
+            conn.execute("INSERT INTO sessions (id, project, started_at) VALUES (?1, 'cli', ?2)", params![sid, at]).unwrap();
+            conn.execute(
+                "INSERT INTO observations (session_id, timestamp, obs_type, source_event, content)
+                 VALUES (?1, ?2, 'command', 'PostToolUseFailure', 'cargo test')",
+                params![sid, at],
+            ).unwrap();
+            let failing = [("store::tests::reopen".to_string(), "cargo")];
+            crate::s2_test_failure::record(&conn, "cli", sid, conn.last_insert_rowid(), at, &failing).unwrap();
+        }
+
+        let ctx = generate_context(&conn, "cli", 20, 10, None, None).unwrap();
+        assert!(ctx.contains("_recurring test failure: `store::tests::reopen` failed in 3 sessions (last 1h ago)"), "{ctx}");
+        let other = generate_context(&conn, "web", 20, 10, None, None).unwrap();
+        assert!(!other.contains("recurring test failure"), "{other}");
+    }
+
+    #[test]
+    fn remembered_memories_lead_the_context() {
+        let conn = setup_db();
+        let ts = now_ts();
+        conn.execute("INSERT INTO sessions (id, project, started_at) VALUES ('s1', 'cli', ?1)", params![ts - 60]).unwrap();
+        conn.execute(
+            "INSERT INTO observations (session_id, timestamp, obs_type, source_event, file_path, content)
+             VALUES ('s1', ?1, 'file_edit', 'PostToolUse', 'src/main.rs', 'Edit src/main.rs')",
+            params![ts - 60],
+        ).unwrap();
+        crate::s1_remember::remember(&conn, Some("cli"), "tests", "run with --test-threads=1", None, ts - 30).unwrap();
+        crate::s1_remember::remember(&conn, None, "style", "no emoji in commit messages", None, ts - 20).unwrap();
+        crate::s1_remember::remember(&conn, Some("web"), "deploy", "staging is frozen", None, ts - 10).unwrap();
+
+        let ctx = generate_context(&conn, "cli", 20, 10, None, None).unwrap();
+        let remembered = ctx.find("## Remembered\n- **tests**: run with --test-threads=1\n- **style** (global): no emoji in commit messages\n");
+        assert!(remembered.is_some(), "{ctx}");
+        assert!(remembered < ctx.find("## cli"), "listed before other sections: {ctx}");
+        assert!(!ctx.contains("staging is frozen"), "{ctx}");
+
+        let tight = format_memories(&crate::s1_remember::active(&conn, "cli", ts).unwrap(), 45);
+        assert_eq!(tight, "## Remembered\n- **tests**: run with --test-threads=1\n_1 more not shown; `forget` stale ones_\n");
+        // A memory-only project still gets an injection
+        let only = generate_context(&conn, "empty-project", 20, 10, None, None).unwrap();
+        assert!(only.contains("- **style** (global)"), "{only}");
+    }
+
+    #[test]
+    fn profiles_shape_the_injection() {
+        let conn = setup_db();
+        let ts = now_ts();
+        conn.execute_batch(&format!(
+            "INSERT INTO sessions (id, project, started_at) VALUES ('old', 'test', {old}), ('cur', 'test', {cur});
+             INSERT INTO work_units (session_id, started_at, intent, obs_count, hot_files, phase_signature)
+                 VALUES ('old', {old}, 'design the cache layer', 4, '[\"src/cache.rs\"]', '{{}}'),
+                        ('old', {old} + 600, 'benchmark cache eviction', 4, '[]', '{{}}'),
+                        ('cur', {cur}, 'wire cache into handlers', 6, '[\"src/handler.rs\"]', '{{}}');
+             INSERT INTO observations (session_id, timestamp, obs_type, source_event, content, file_path)
+                 VALUES ('cur', {cur} + 60, 'file_edit', 'PostToolUse', 'edit', 'src/handler.rs'),
+                        ('cur', {cur} + 90, 'file_read', 'PostToolUse', 'read', 'src/lib.rs');",
+            old = ts - 7200,
+            cur = ts - 1800,
+        ))
+        .unwrap();
+        let config = crate::s5_config::NmemConfig::default();
+
+        let compact = crate::s5_config::resolve_context_profile(&config, "test", "compact");
+        let ctx = generate_profiled_context(&conn, "test", &compact, Some("cur"), None, None).unwrap();
+        let (this, rest) = ctx.split_once("## Recent Episodes").expect(&ctx);
+        assert!(this.contains("## This Session"), "{ctx}");
+        assert!(this.contains("wire cache into handlers"));
+        assert!(this.contains("### Changes") && !this.contains("src/lib.rs"), "{ctx}");
+        assert!(!rest.contains("wire cache into handlers"), "current session not repeated");
+        assert!(rest.contains("design the cache layer"));
+
+        let resume = crate::s5_config::resolve_context_profile(&config, "test", "resume");
+        let ctx = generate_profiled_context(&conn, "test", &resume, Some("cur"), None, None).unwrap();
+        assert!(ctx.contains("wire cache into handlers"));
+        assert!(!ctx.contains("benchmark cache eviction"), "one episode only: {ctx}");
+        assert!(!ctx.contains("## test") && !ctx.contains("## This Session"));
+
+        let full = generate_context(&conn, "test", 20, 10, None, None).unwrap();
+        assert!(full.contains("benchmark cache eviction") && !full.contains("## This Session"));
+    }
+
+    #[test]
+    fn topics_replace_the_episodes_they_cover() {
+        let conn = setup_db();
+        register_udfs(&conn).unwrap();
+        let ts = now_ts();
+        conn.execute(
+            "INSERT INTO sessions (id, project, started_at) VALUES ('s1', 'test', ?1)",
+            [ts - 7200],
+        ).unwrap();
+        for (at, intent) in [(ts - 7200, "fix token refresh"), (ts - 3600, "render tables")] {
+            conn.execute(
+                "INSERT INTO work_units (session_id, started_at, intent, obs_count, hot_files)
+                 VALUES ('s1', ?1, ?2, 5, '[]')",
+                params![at, intent],
+            ).unwrap();
+        }
+        conn.execute(
+            "INSERT INTO topics (id, project, title, summary, files, created_at, updated_at)
+             VALUES (1, 'test', 'auth tokens', ?1, '[\"src/auth.rs\"]', ?2, ?2)",
+            params![
+                r#"{"title":"auth tokens","decisions":["refresh server-side"],"pitfalls":[],"current_state":"shipped"}"#,
+                ts - 60,
+            ],
+        ).unwrap();
+        conn.execute_batch("INSERT INTO topic_work_units (work_unit_id, topic_id) VALUES (1, 1);").unwrap();
+
+        let ctx = generate_context(&conn, "test", 20, 10, None, None).unwrap();
+        assert!(ctx.contains("## Topics"), "{ctx}");
+        assert!(ctx.contains("- **auth tokens** (1 episodes"));
+        assert!(ctx.contains("  - Decisions: refresh server-side"));
+        assert!(!ctx.contains("fix token refresh"), "covered episode is folded into the topic");
+        assert!(ctx.contains("render tables"));
+
+        // Before the topic existed, the episode shows on its own
+        let then = generate_context(&conn, "test", 20, 10, Some(ts - 120), None).unwrap();
+        assert!(!then.contains("## Topics"));
+        assert!(then.contains("fix token refresh"));
+    }
+
+    #[test]
+    fn project_docs_matching_recent_intents_are_surfaced() {
+        let conn = setup_db();
+        let ts = now_ts();
+        conn.execute("INSERT INTO sessions (id, project, started_at) VALUES ('s1', 'test', ?1)", [ts - 600]).unwrap();
+        conn.execute(
+            "INSERT INTO work_units (session_id, started_at, intent, obs_count, hot_files)
+             VALUES ('s1', ?1, 'publish release binaries', 5, '[]')",
+            [ts - 600],
+        ).unwrap();
+        let filter = crate::s5_filter::SecretFilter::with_params(Default::default());
+        let doc = "# Release\nTag the version, then publish binaries to the mirror.\n# Style\nNo unwrap outside tests.\n";
+        crate::s1_docs::index_file(&conn, &filter, "test", "CLAUDE.md", doc, ts - 86400).unwrap();
+
+        let ctx = generate_context(&conn, "test", 20, 10, None, None).unwrap();
+        assert!(ctx.contains("## Project Docs\n"), "{ctx}");
+        assert!(ctx.contains("**CLAUDE.md > Release** — Tag the version, then publish binaries to the mirror."));
+        assert!(!ctx.contains("No unwrap"), "unrelated sections stay out");
+    }
+
+    #[test]
+    fn unfinished_episodes_lead_suggested_tasks() {
+        let conn = setup_db();
+        let ts = now_ts();
+        conn.execute(
+            "INSERT INTO sessions (id, project, started_at, summary) VALUES ('s1', 'test', ?1, ?2)",
+            params![ts - 7200, r#"{"intent":"x","next_steps":["Write the changelog"]}"#],
+        ).unwrap();
+        for (at, intent, outcome) in [
+            (ts - 7200, "migrate billing export", Some("abandoned")),
+            (ts - 5400, "stabilize flaky login test", Some("blocked")),
+            (ts - 3600, "render tables", Some("succeeded")),
+            (ts - 1800, "tidy imports", None),
+        ] {
+            conn.execute(
+                "INSERT INTO work_units (session_id, started_at, intent, obs_count, hot_files, outcome)
+                 VALUES ('s1', ?1, ?2, 5, '[]', ?3)",
+                params![at, intent, outcome],
+            ).unwrap();
+        }
+
+        let ctx = generate_context(&conn, "test", 20, 10, None, None).unwrap();
+        let (_, tasks) = ctx.split_once("## Suggested Tasks\n").expect(&ctx);
+        let lines: Vec<&str> = tasks.lines().take(3).collect();
+        assert_eq!(lines, [
+            "- Unfinished (blocked, 1h ago): **stabilize flaky login test** — ended on a failing command",
+            "- Unfinished (abandoned, 2h ago): **migrate billing export** — dropped, then picked up again",
+            "- Write the changelog",
+        ]);
+        assert!(ctx.contains("**render tables** (5 obs, mixed, succeeded)"), "{ctx}");
+        assert!(ctx.contains("**tidy imports** (5 obs, mixed)"), "{ctx}");
+    }
+
+    #[test]
+    fn suppression_rules_hold_back_matching_episodes() {
+        let conn = setup_db();
+        let ts = now_ts();
+        conn.execute("INSERT INTO sessions (id, project, started_at) VALUES ('s1', 'test', ?1)", [ts - 7200]).unwrap();
+        for (at, intent, files) in [
+            (ts - 7200, "fix token refresh", r#"["/w/src/auth/token.rs"]"#),
+            (ts - 5400, "billing export cleanup", "[]"),
+            (ts - 3600, "render tables", r#"["/w/src/table.rs"]"#),
+        ] {
+            conn.execute(
+                "INSERT INTO work_units (session_id, started_at, intent, obs_count, hot_files)
+                 VALUES ('s1', ?1, ?2, 5, ?3)",
+                params![at, intent, files],
+            ).unwrap();
+        }
+        crate::s3_suppress::add(&conn, Some("test"), crate::s3_suppress::FILE, "src/auth/", None, ts).unwrap();
+        crate::s3_suppress::add(&conn, None, crate::s3_suppress::TOPIC, "Billing", Some(ts + 60), ts).unwrap();
+        crate::s3_suppress::add(&conn, Some("other"), crate::s3_suppress::TOPIC, "tables", None, ts).unwrap();
+
+        let ctx = generate_context(&conn, "test", 20, 10, None, None).unwrap();
+        assert!(ctx.contains("render tables"), "{ctx}");
+        assert!(!ctx.contains("fix token refresh") && !ctx.contains("billing export"), "{ctx}");
+        assert!(ctx.contains("_2 items held back by suppression rules (`nmem suppress`)_"), "{ctx}");
+    }
+
+    #[test]
+    fn context_notes_files_shared_with_other_projects() {
+        let conn = setup_db();
+        let ts = now_ts();
+        conn.execute_batch(
+            "INSERT INTO sessions (id, project, started_at) VALUES ('s1', 'app', 1), ('s2', 'infra', 1);",
+        )
+        .unwrap();
+        conn.execute(
+            "INSERT INTO sessions (id, project, started_at, summary) VALUES ('s3', 'infra', 1, ?1)",
+            params![r#"{"intent":"compose","learned":["postgres pinned to 16"]}"#],
+        )
+        .unwrap();
+        for (session, at) in [("s1", ts - 60), ("s3", ts - 3600)] {
+            conn.execute(
+                "INSERT INTO observations (session_id, timestamp, obs_type, source_event, file_path, content)
+                 VALUES (?1, ?2, 'file_edit', 'PostToolUse', '/w/docker-compose.yml', 'edit')",
+                params![session, at],
+            )
+            .unwrap();
+        }
+
+        let ctx = generate_context(&conn, "app", 20, 10, None, None).unwrap();
+        assert!(ctx.contains("## Shared files"), "{ctx}");
+        assert!(ctx.contains(
+            "- /w/docker-compose.yml is also governed by project infra (1h ago); last decision there: postgres pinned to 16"
+        ), "{ctx}");
     }
 }
@@ -0,0 +1,244 @@
+//! S4 Intelligence — knowledge decay.
+//!
+//! A `learned` entry in an old summary ("API X requires header Y") was true
+//! when it was written and may not be now. Each entry gets a staleness score
+//! from its age plus the churn since then of the files and commands it
+//! names: later edits of a referenced file, later runs of a referenced
+//! command. Entries scoring `STALE_SCORE` or more are annotated in context —
+//! "(learned 4 months ago — verify)" — and `nmem stale` lists them for
+//! review, stalest first.
+
+#[cfg(feature = "cli")]
+use crate::cli::StaleArgs;
+use crate::s1_4_summarize::SessionSummary;
+use crate::NmemError;
+use rusqlite::{Connection, params};
+use serde::Serialize;
+#[cfg(feature = "cli")]
+use std::path::Path;
+
+/// Score at which an entry is flagged.
+pub const STALE_SCORE: f64 = 1.0;
+/// Days of age alone that reach `STALE_SCORE`.
+const AGE_DAYS: f64 = 120.0;
+/// Later edits/runs of the referenced files and commands alone that reach `STALE_SCORE`.
+const CHURN_EVENTS: f64 = 20.0;
+/// References taken from one entry.
+const MAX_REFS: usize = 5;
+
+#[derive(Debug, Serialize)]
+pub struct StaleItem {
+    pub session_id: String,
+    pub project: String,
+    pub learned_at: i64,
+    pub text: String,
+    /// Files and commands the entry names.
+    pub refs: Vec<String>,
+    /// Edits and runs of `refs` since `learned_at`.
+    pub churn: i64,
+    pub score: f64,
+}
+
+/// Files and commands named in `text`: backticked spans, and bare tokens
+/// that look like paths (`src/db.rs`, `Cargo.toml`).
+pub fn references(text: &str) -> Vec<String> {
+    let mut refs: Vec<String> = Vec::new();
+    let mut push = |r: &str| {
+        let r = r.trim();
+        if r.len() > 2 && !refs.iter().any(|x| x == r) {
+            refs.push(r.to_string());
+        }
+    };
+    for (i, span) in text.split('`').enumerate() {
+        if i % 2 == 1 {
+            push(span);
+            continue;
+        }
+        for token in span.split_whitespace() {
+            let token = token.trim_matches(|c: char| "\"'(),;:!?".contains(c)).trim_end_matches('.');
+            if token.contains("://") {
+                continue;
+            }
+            let path_like = token.contains('/')
+                || token
+                    .rsplit_once('.')
+                    .is_some_and(|(stem, ext)| {
+                        stem.len() > 1
+                            && (1..=5).contains(&ext.len())
+                            && ext.chars().all(|c| c.is_ascii_alphabetic())
+                            && ext.chars().any(|c| c.is_ascii_lowercase())
+                    });
+            if path_like {
+                push(token);
+            }
+        }
+    }
+    refs.truncate(MAX_REFS);
+    refs
+}
+
+/// Edits of files and runs of commands matching `refs` in `project` after `since`.
+pub fn churn(conn: &Connection, project: &str, refs: &[String], since: i64) -> Result<i64, NmemError> {
+    let mut total = 0;
+    for r in refs {
+        let n: i64 = conn.query_row(
+            "SELECT COUNT(*) FROM observations o JOIN sessions s ON s.id = o.session_id
+             WHERE s.project = ?1 AND o.timestamp > ?2
+               AND ((o.obs_type IN ('file_edit', 'file_write')
+                     AND (o.file_path = ?3 OR o.file_path LIKE '%/' || ?3))
+                    OR (o.obs_type = 'command' AND instr(o.content, ?3) > 0))",
+            params![project, since, r],
+            |row| row.get(0),
+        )?;
+        total += n;
+    }
+    Ok(total)
+}
+
+/// Staleness of an entry `age_secs` old whose references churned `churn` times.
+pub fn score(age_secs: i64, churn: i64) -> f64 {
+    age_secs.max(0) as f64 / 86400.0 / AGE_DAYS + churn as f64 / CHURN_EVENTS
+}
+
+/// "(learned 4 months ago — verify)".
+pub fn annotation(age_secs: i64) -> String {
+    let days = age_secs.max(0) / 86400;
+    let (n, unit) = match days {
+        0 => (1, "day"),
+        1..=13 => (days, "day"),
+        14..=59 => (days / 7, "week"),
+        60..=729 => (days / 30, "month"),
+        _ => (days / 365, "year"),
+    };
+    let plural = if n == 1 { "" } else { "s" };
+    format!("(learned {n} {unit}{plural} ago — verify)")
+}
+
+/// Score one entry learned at `learned_at` in `project`.
+pub fn assess(conn: &Connection, project: &str, text: &str, learned_at: i64, now: i64) -> Result<(f64, i64, Vec<String>), NmemError> {
+    let refs = references(text);
+    let churn = churn(conn, project, &refs, learned_at)?;
+    Ok((score(now - learned_at, churn), churn, refs))
+}
+
+/// Learned entries of summarized agent sessions scoring at least
+/// `min_score`, stalest first.
+pub fn stale_items(
+    conn: &Connection,
+    project: Option<&str>,
+    min_score: f64,
+    limit: usize,
+    now: i64,
+) -> Result<Vec<StaleItem>, NmemError> {
+    let mut stmt = conn.prepare(
+        "SELECT id, project, started_at, summary FROM sessions
+         WHERE summary IS NOT NULL AND kind = 'agent' AND (?1 IS NULL OR project = ?1)
+         ORDER BY started_at",
+    )?;
+    let sessions: Vec<(String, String, i64, String)> = stmt
+        .query_map(params![project], |r| Ok((r.get(0)?, r.get(1)?, r.get(2)?, r.get(3)?)))?
+        .collect::<Result<_, _>>()?;
+
+    let mut items = Vec::new();
+    for (session_id, project, learned_at, summary) in sessions {
+        let Ok(summary) = serde_json::from_str::<SessionSummary>(&summary) else {
+            continue;
+        };
+        for text in summary.learned {
+            let (score, churn, refs) = assess(conn, &project, &text, learned_at, now)?;
+            if score >= min_score {
+                items.push(StaleItem {
+                    session_id: session_id.clone(),
+                    project: project.clone(),
+                    learned_at,
+                    text,
+                    refs,
+                    churn,
+                    score,
+                });
+            }
+        }
+    }
+    items.sort_by(|a, b| b.score.total_cmp(&a.score).then(a.learned_at.cmp(&b.learned_at)));
+    items.truncate(limit);
+    Ok(items)
+}
+
+#[cfg(feature = "cli")]
+pub fn handle_stale(db_path: &Path, args: &StaleArgs) -> Result<(), NmemError> {
+    let conn = crate::db::open_db_readonly(db_path)?;
+    let now = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs() as i64;
+    let items = stale_items(&conn, args.project.as_deref(), args.min_score, args.limit.clamp(1, 500), now)?;
+    if args.json {
+        for item in &items {
+            println!("{}", serde_json::to_string(item)?);
+        }
+        return Ok(());
+    }
+    if items.is_empty() {
+        log::info!("no learned entries at staleness {} or above", args.min_score);
+    }
+    for item in &items {
+        let churn = if item.churn > 0 {
+            format!("  [{} later changes: {}]", item.churn, item.refs.join(", "))
+        } else {
+            String::new()
+        };
+        println!(
+            "{:>4.1}  {}  {}  {}{churn}",
+            item.score,
+            crate::timefmt::relative(item.learned_at),
+            item.project,
+            item.text
+        );
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn references_pick_out_files_and_commands() {
+        assert_eq!(
+            references("Must run `cargo build --release` before editing src/db.rs, see Cargo.toml."),
+            vec!["cargo build --release", "src/db.rs", "Cargo.toml"]
+        );
+        assert!(references("Version 1.2 works, e.g. for https://x.io/a").is_empty());
+    }
+
+    #[test]
+    fn old_or_churned_entries_are_stale() {
+        let mut conn = Connection::open_in_memory().unwrap();
+        crate::schema::MIGRATIONS.to_latest(&mut conn).unwrap();
+        let now = 1_000 * 86400;
+        let day = 86400;
+        conn.execute_batch(&format!(
+            r#"INSERT INTO sessions (id, project, started_at, summary) VALUES
+                 ('old', 'p', {old}, '{{"learned":["the API needs an auth header"]}}'),
+                 ('busy', 'p', {busy}, '{{"learned":["pool size is set in src/db.rs","fresh and quiet"]}}');"#,
+            old = now - 200 * day,
+            busy = now - 10 * day,
+        ))
+        .unwrap();
+        for i in 0..25 {
+            conn.execute(
+                "INSERT INTO observations (session_id, timestamp, obs_type, source_event, file_path, content)
+                 VALUES ('busy', ?1, 'file_edit', 'PostToolUse', '/repo/src/db.rs', 'edit')",
+                params![now - 5 * day + i],
+            )
+            .unwrap();
+        }
+
+        let items = stale_items(&conn, Some("p"), STALE_SCORE, 10, now).unwrap();
+        let texts: Vec<&str> = items.iter().map(|i| i.text.as_str()).collect();
+        assert_eq!(texts, vec!["the API needs an auth header", "pool size is set in src/db.rs"]);
+        assert_eq!(items[1].churn, 25);
+        assert_eq!(annotation(now - items[0].learned_at), "(learned 6 months ago — verify)");
+        assert_eq!(annotation(3 * 86400), "(learned 3 days ago — verify)");
+    }
+}
@@ -1,6 +1,8 @@
+#[cfg(feature = "cli")]
 use crate::cli::{DispatchArgs, QueueArgs, TaskArgs};
 use crate::db::open_db;
 use crate::NmemError;
+use rusqlite::Connection;
 use std::path::{Path, PathBuf};
 use std::process::Command as ProcessCommand;
 use std::time::{SystemTime, UNIX_EPOCH};
@@ -72,6 +74,70 @@ fn tmux_pane_exists(target: &str) -> bool {
         .unwrap_or(false)
 }
 
+/// Commands a pane can be running and still take a dispatched task.
+const SHELLS: &[&str] = &["bash", "zsh", "fish", "sh", "dash"];
+
+/// Whether `pane` is at a shell prompt — nothing, Claude Code included, is
+/// running in it that typed keys would land in.
+fn tmux_pane_idle(pane: &str) -> bool {
+    ProcessCommand::new("tmux")
+        .args(["display-message", "-p", "-t", pane, "#{pane_current_command}"])
+        .stderr(std::process::Stdio::null())
+        .output()
+        .ok()
+        .filter(|o| o.status.success())
+        .is_some_and(|o| SHELLS.contains(&String::from_utf8_lossy(&o.stdout).trim()))
+}
+
+// --- Session affinity ---
+
+/// How recent a project's last session in a pane must be for the pane to
+/// count as that project's.
+const AFFINITY_SECS: i64 = 24 * 3600;
+
+/// Panes whose most recent nmem session, started after `since`, was in
+/// `project`, newest first, with that session's id. Panes are recorded from
+/// `$TMUX_PANE` at SessionStart.
+pub fn affine_panes(conn: &Connection, project: &str, since: i64) -> Result<Vec<(String, String)>, NmemError> {
+    let mut stmt = conn.prepare(
+        "SELECT s.tmux_pane, s.id FROM sessions s
+         WHERE s.tmux_pane IS NOT NULL AND s.project = ?1 AND s.started_at > ?2
+           AND NOT EXISTS (
+               SELECT 1 FROM sessions t
+               WHERE t.tmux_pane = s.tmux_pane
+                 AND (t.started_at > s.started_at OR (t.started_at = s.started_at AND t.rowid > s.rowid)))
+         ORDER BY s.started_at DESC",
+    )?;
+    let panes = stmt
+        .query_map(rusqlite::params![project, since], |r| Ok((r.get(0)?, r.get(1)?)))?
+        .collect::<Result<_, _>>()?;
+    Ok(panes)
+}
+
+/// `prompt` with `project`'s SessionStart context in front of it, so a
+/// dispatched task starts from what the project's sessions already know.
+/// The bare prompt when the project has no memory yet.
+pub fn with_context_preamble(
+    conn: &Connection,
+    config: &crate::s5_config::NmemConfig,
+    project: &str,
+    cwd: Option<&str>,
+    prompt: &str,
+) -> String {
+    let (local, cross) = crate::s5_config::resolve_context_limits(config, project, false);
+    let cwd = cwd.map(Path::new).filter(|p| p.is_absolute());
+    match crate::s4_context::generate_context(conn, project, local, cross, None, cwd) {
+        Ok(context) if !context.trim().is_empty() => {
+            format!("# Project memory (nmem)\n\n{}\n\n---\n\n# Task\n\n{prompt}", context.trim_end())
+        }
+        Ok(_) => prompt.to_string(),
+        Err(e) => {
+            log::warn!("context for {project} failed (dispatching without it): {e}");
+            prompt.to_string()
+        }
+    }
+}
+
 // --- Schedule parsing ---
 
 fn now_unix() -> i64 {
@@ -131,7 +197,7 @@ pub fn parse_schedule(input: &str) -> Result<i64, NmemError> {
     )))
 }
 
-fn parse_relative(input: &str) -> Option<i64> {
+pub(crate) fn parse_relative(input: &str) -> Option<i64> {
     // Strip leading "in " if present
     let s = input.strip_prefix("in ").unwrap_or(input).trim();
 
@@ -157,7 +223,7 @@ fn parse_relative(input: &str) -> Option<i64> {
         _ => return None,
     };
 
-    Some(n * multiplier)
+    n.checked_mul(multiplier)
 }
 
 fn today_at_hour(hour: u32) -> Option<i64> {
@@ -253,8 +319,29 @@ fn prompt_path_for_task(task_id: i64) -> PathBuf {
     tasks_dir().join(format!("task-{task_id}.prompt"))
 }
 
+/// Touched when a task's command finishes; a reused pane outlives its task.
+fn done_path_for_task(task_id: i64) -> PathBuf {
+    tasks_dir().join(format!("task-{task_id}.done"))
+}
+
 // --- Queue ---
 
+/// Schedule `prompt` to run after `run_after`. Returns the task id.
+pub fn queue_task(
+    conn: &Connection,
+    prompt: &str,
+    project: Option<&str>,
+    cwd: Option<&str>,
+    run_after: i64,
+) -> Result<i64, NmemError> {
+    conn.execute(
+        "INSERT INTO tasks (prompt, project, cwd, run_after) VALUES (?1, ?2, ?3, ?4)",
+        rusqlite::params![prompt, project, cwd, run_after],
+    )?;
+    Ok(conn.last_insert_rowid())
+}
+
+#[cfg(feature = "cli")]
 pub fn handle_queue(db_path: &Path, args: &QueueArgs) -> Result<(), NmemError> {
     let config = crate::s5_config::load_config().unwrap_or_default();
     let cwd = args
@@ -270,14 +357,8 @@ pub fn handle_queue(db_path: &Path, args: &QueueArgs) -> Result<(), NmemError> {
     let run_after = parse_schedule(&args.after)?;
 
     let conn = open_db(db_path)?;
-
-    conn.execute(
-        "INSERT INTO tasks (prompt, project, cwd, run_after) VALUES (?1, ?2, ?3, ?4)",
-        rusqlite::params![args.prompt, project, cwd, run_after],
-    )?;
-
-    let task_id = conn.last_insert_rowid();
-    log::info!("task {task_id} scheduled for {run_after}");
+    let task_id = queue_task(&conn, &args.prompt, project.as_deref(), cwd.as_deref(), run_after)?;
+    log::info!("task {task_id} scheduled for {}", crate::timefmt::datetime(run_after));
     println!("{task_id}");
     Ok(())
 }
@@ -292,9 +373,11 @@ struct ReapRow {
 struct PendingRow {
     id: i64,
     prompt: String,
+    project: Option<String>,
     cwd: Option<String>,
 }
 
+#[cfg(feature = "cli")]
 pub fn handle_dispatch(db_path: &Path, args: &DispatchArgs) -> Result<(), NmemError> {
     // If a task file was provided, parse and queue it first
     if let Some(file) = &args.file {
@@ -330,6 +413,7 @@ pub fn handle_dispatch(db_path: &Path, args: &DispatchArgs) -> Result<(), NmemEr
     }
 
     let conn = open_db(db_path)?;
+    let config = crate::s5_config::load_config().unwrap_or_default();
 
     // 1. Reap finished tasks — only need id and tmux_target
     let running: Vec<ReapRow> = {
@@ -346,21 +430,32 @@ pub fn handle_dispatch(db_path: &Path, args: &DispatchArgs) -> Result<(), NmemEr
     };
 
     let mut running_count: u32 = 0;
+    let mut busy_panes: Vec<String> = Vec::new();
     for task in &running {
         let target = task.tmux_target.as_deref().unwrap_or("");
-        if target.is_empty() || !tmux_pane_exists(target) {
-            // Pane gone — mark completed
+        let done = done_path_for_task(task.id).exists();
+        if target.is_empty() || done || !tmux_pane_exists(target) {
+            // Pane gone or command finished — mark completed
             conn.execute(
                 "UPDATE tasks SET status = 'completed', completed_at = unixepoch('now') WHERE id = ?1",
                 [task.id],
             )?;
-            log::info!("task {} reaped (pane gone)", task.id);
+            log::info!("task {} reaped ({})", task.id, if done { "finished" } else { "pane gone" });
         } else {
             running_count += 1;
+            busy_panes.push(target.to_string());
         }
     }
 
-    // 2. Check capacity
+    // 2. Leave tasks queued outside allowed windows
+    if !args.now
+        && let Some(reason) = crate::s3_schedule::defer_reason(&config.schedule, now_unix())
+    {
+        log::info!("dispatch deferred ({reason}) — pass --now to override");
+        return Ok(());
+    }
+
+    // 3. Check capacity
     if running_count >= args.max_concurrent {
         log::info!(
             "at capacity ({running_count}/{} running)",
@@ -371,11 +466,11 @@ pub fn handle_dispatch(db_path: &Path, args: &DispatchArgs) -> Result<(), NmemEr
 
     let slots = args.max_concurrent - running_count;
 
-    // 3. Find pending tasks past their run_after time.
+    // 4. Find pending tasks past their run_after time.
     // NULL run_after = immediate dispatch (no schedule specified).
     let pending: Vec<PendingRow> = {
         let mut stmt = conn.prepare(
-            "SELECT id, prompt, cwd FROM tasks \
+            "SELECT id, prompt, project, cwd FROM tasks \
              WHERE status = 'pending' AND (run_after IS NULL OR run_after <= unixepoch('now')) \
              ORDER BY created_at ASC LIMIT ?1",
         )?;
@@ -383,7 +478,8 @@ pub fn handle_dispatch(db_path: &Path, args: &DispatchArgs) -> Result<(), NmemEr
             Ok(PendingRow {
                 id: row.get(0)?,
                 prompt: row.get(1)?,
-                cwd: row.get(2)?,
+                project: row.get(2)?,
+                cwd: row.get(3)?,
             })
         })?
         .collect::<Result<_, _>>()?
@@ -394,14 +490,28 @@ pub fn handle_dispatch(db_path: &Path, args: &DispatchArgs) -> Result<(), NmemEr
         return Ok(());
     }
 
-    // 4. Dispatch each pending task
+    // 5. Dispatch each pending task — into an idle pane its project last
+    // ran in when there is one, else a new window
     for task in &pending {
+        let affine = match task.project.as_deref() {
+            Some(project) if !args.no_affinity => affine_panes(&conn, project, now_unix() - AFFINITY_SECS)?
+                .into_iter()
+                .find(|(pane, _)| !busy_panes.contains(pane) && tmux_pane_idle(pane)),
+            _ => None,
+        };
         let window_name = format!("task-{}", task.id);
-        let target = format!("{}:{}", args.tmux_session, window_name);
+        let target = match affine {
+            Some((ref pane, _)) => pane.clone(),
+            None => format!("{}:{}", args.tmux_session, window_name),
+        };
+        let via = match affine {
+            Some((_, ref session)) => format!(" (pane of session {session})"),
+            None => String::new(),
+        };
 
         if args.dry_run {
             log::info!(
-                "[dry-run] would dispatch task {} to {} — {:?}",
+                "[dry-run] would dispatch task {} to {}{via} — {:?}",
                 task.id,
                 target,
                 truncate_prompt(&task.prompt, 60)
@@ -409,13 +519,14 @@ pub fn handle_dispatch(db_path: &Path, args: &DispatchArgs) -> Result<(), NmemEr
             continue;
         }
 
-        // Ensure tmux session exists
-        if !tmux_session_exists(&args.tmux_session) {
-            tmux_create_session(&args.tmux_session)?;
+        if affine.is_none() {
+            // Ensure tmux session exists
+            if !tmux_session_exists(&args.tmux_session) {
+                tmux_create_session(&args.tmux_session)?;
+            }
+            tmux_create_window(&args.tmux_session, &window_name)?;
         }
-
-        // Create window and send commands
-        tmux_create_window(&args.tmux_session, &window_name)?;
+        busy_panes.push(target.clone());
 
         if let Some(cwd) = &task.cwd {
             tmux_send_keys(&target, &format!("cd {}", shell_escape(cwd)))?;
@@ -426,12 +537,20 @@ pub fn handle_dispatch(db_path: &Path, args: &DispatchArgs) -> Result<(), NmemEr
         std::fs::create_dir_all(&task_dir)?;
 
         // Write prompt to file — avoids shell injection via tmux send-keys
+        let prompt = match task.project.as_deref() {
+            Some(project) if !args.no_context => {
+                with_context_preamble(&conn, &config, project, task.cwd.as_deref(), &task.prompt)
+            }
+            _ => task.prompt.clone(),
+        };
         let prompt_path = prompt_path_for_task(task.id);
-        std::fs::write(&prompt_path, &task.prompt)?;
+        std::fs::write(&prompt_path, &prompt)?;
 
         let output_path = output_path_for_task(task.id);
+        let done_path = done_path_for_task(task.id);
         let prompt_path_str = prompt_path.to_string_lossy();
         let output_path_str = output_path.to_string_lossy();
+        let done_path_str = done_path.to_string_lossy();
 
         // Source user shell environment so dispatched sessions have full PATH
         // (systemd timers have minimal env; bare `cargo` etc. fail without this)
@@ -440,11 +559,13 @@ pub fn handle_dispatch(db_path: &Path, args: &DispatchArgs) -> Result<(), NmemEr
             "source ~/.cargo/env 2>/dev/null; export PATH=\"$HOME/.local/bin:$HOME/.cargo/bin:$PATH\"",
         )?;
 
-        // Read prompt from file instead of inlining it in the shell command
+        // Read prompt from file instead of inlining it in the shell command.
+        // A reused pane stays open for the user; a task window closes itself.
+        let close = if affine.is_some() { "" } else { "; sleep 5 && exit" };
         tmux_send_keys(
             &target,
             &format!(
-                "claude -p \"$(cat '{prompt_path_str}')\" | tee '{output_path_str}'; sleep 5 && exit",
+                "claude -p \"$(cat '{prompt_path_str}')\" | tee '{output_path_str}'; touch '{done_path_str}'{close}",
             ),
         )?;
 
@@ -455,7 +576,7 @@ pub fn handle_dispatch(db_path: &Path, args: &DispatchArgs) -> Result<(), NmemEr
         )?;
 
         log::info!(
-            "dispatched task {} to {} — {:?}",
+            "dispatched task {} to {}{via} — {:?}",
             task.id,
             target,
             truncate_prompt(&task.prompt, 60)
@@ -467,6 +588,7 @@ pub fn handle_dispatch(db_path: &Path, args: &DispatchArgs) -> Result<(), NmemEr
 
 // --- Task view ---
 
+#[cfg(feature = "cli")]
 pub fn handle_task(db_path: &Path, args: &TaskArgs) -> Result<(), NmemError> {
     let conn = open_db(db_path)?;
 
@@ -519,12 +641,12 @@ pub fn handle_task(db_path: &Path, args: &TaskArgs) -> Result<(), NmemError> {
     if let Some(c) = &cwd {
         println!("  cwd:     {c}");
     }
-    println!("  created: {created_at}");
+    println!("  created: {}", crate::timefmt::iso(created_at));
     if let Some(ts) = started_at {
-        println!("  started: {ts}");
+        println!("  started: {}", crate::timefmt::iso(ts));
     }
     if let Some(ts) = completed_at {
-        println!("  done:    {ts}");
+        println!("  done:    {}", crate::timefmt::iso(ts));
     }
     if let Some(e) = &error {
         println!("  error:   {e}");
@@ -580,6 +702,7 @@ mod tests {
         (dir, db_path)
     }
 
+    #[cfg(feature = "cli")]
     #[test]
     fn queue_inserts_pending_task() {
         let (_dir, db_path) = test_db_path();
@@ -642,6 +765,7 @@ mod tests {
         assert!(parse_schedule("").is_err());
     }
 
+    #[cfg(feature = "cli")]
     #[test]
     fn queue_with_schedule() {
         let (_dir, db_path) = test_db_path();
@@ -662,6 +786,7 @@ mod tests {
         assert!((run_after.unwrap() - now - 3600).abs() < 5);
     }
 
+    #[cfg(feature = "cli")]
     #[test]
     fn dispatch_skips_future_tasks() {
         let (_dir, db_path) = test_db_path();
@@ -682,6 +807,9 @@ mod tests {
             max_concurrent: 1,
             dry_run: true,
             tmux_session: "nmem-test".into(),
+            no_affinity: false,
+            no_context: false,
+            now: false,
         };
         handle_dispatch(&db_path, &dispatch_args).unwrap();
 
@@ -693,6 +821,7 @@ mod tests {
         assert_eq!(status, "pending");
     }
 
+    #[cfg(feature = "cli")]
     #[test]
     fn dispatch_skips_when_at_capacity() {
         let (_dir, db_path) = test_db_path();
@@ -723,6 +852,9 @@ mod tests {
             max_concurrent: 1,
             dry_run: true,
             tmux_session: "nmem".into(),
+            no_affinity: false,
+            no_context: false,
+            now: false,
         };
         handle_dispatch(&db_path, &dispatch_args).unwrap();
 
@@ -782,6 +914,7 @@ mod tests {
         assert!(path.to_string_lossy().ends_with("tasks/task-42.md"));
     }
 
+    #[cfg(feature = "cli")]
     #[test]
     fn file_dispatch_inserts_task() {
         let (_dir, db_path) = test_db_path();
@@ -794,6 +927,9 @@ mod tests {
             max_concurrent: 1,
             dry_run: true,
             tmux_session: "nmem-test".into(),
+            no_affinity: false,
+            no_context: false,
+            now: false,
         };
         handle_dispatch(&db_path, &args).unwrap();
 
@@ -808,4 +944,45 @@ mod tests {
         assert_eq!(prompt, "Say hello");
         assert_eq!(project.as_deref(), Some("test-proj"));
     }
+
+    #[test]
+    fn affine_panes_keep_each_panes_latest_session() {
+        let mut conn = Connection::open_in_memory().unwrap();
+        MIGRATIONS.to_latest(&mut conn).unwrap();
+        conn.execute_batch(
+            "INSERT INTO sessions (id, project, started_at, tmux_pane) VALUES
+               ('a1', 'nmem', 100, '%1'),
+               ('b1', 'other', 200, '%1'),
+               ('a2', 'nmem', 300, '%2'),
+               ('a3', 'nmem', 400, '%3'),
+               ('old', 'nmem', 10, '%4'),
+               ('bare', 'nmem', 500, NULL);",
+        )
+        .unwrap();
+        // %1 moved on to another project; %4 is outside the window
+        let panes = affine_panes(&conn, "nmem", 50).unwrap();
+        assert_eq!(
+            panes,
+            vec![("%3".to_string(), "a3".to_string()), ("%2".to_string(), "a2".to_string())]
+        );
+        assert!(affine_panes(&conn, "missing", 0).unwrap().is_empty());
+    }
+
+    #[test]
+    fn context_preamble_only_when_project_has_memory() {
+        let mut conn = Connection::open_in_memory().unwrap();
+        MIGRATIONS.to_latest(&mut conn).unwrap();
+        let config = crate::s5_config::NmemConfig::default();
+        assert_eq!(with_context_preamble(&conn, &config, "nmem", None, "fix it"), "fix it");
+
+        conn.execute_batch(
+            "INSERT INTO sessions (id, project, started_at) VALUES ('s', 'nmem', unixepoch('now') - 60);
+             INSERT INTO observations (session_id, timestamp, obs_type, source_event, file_path, content)
+               VALUES ('s', unixepoch('now') - 30, 'file_edit', 'PostToolUse', '/repo/src/lib.rs', 'edit lib');",
+        )
+        .unwrap();
+        let prompt = with_context_preamble(&conn, &config, "nmem", None, "fix it");
+        assert!(prompt.starts_with("# Project memory (nmem)"), "{prompt}");
+        assert!(prompt.ends_with("# Task\n\nfix it"));
+    }
 }
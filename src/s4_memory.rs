@@ -1,7 +1,8 @@
-use crate::s3_learn::{intent_keywords, jaccard};
+use crate::s1_4_summarize::Payload;
+use crate::s3_learn::jaccard;
 use crate::s5_config::SummarizationConfig;
 use crate::NmemError;
-use rusqlite::{params, Connection};
+use rusqlite::{params, Connection, OptionalExtension};
 
 /// Jaccard threshold for intra-session episode boundaries.
 /// Lower than s3_learn's 0.4 because intra-session prompts are shorter
@@ -25,7 +26,7 @@ pub struct Episode {
 }
 
 /// A fully annotated episode ready for storage.
-struct WorkUnitRow {
+pub(crate) struct WorkUnitRow {
     session_id: String,
     started_at: i64,
     ended_at: Option<i64>,
@@ -33,36 +34,20 @@ struct WorkUnitRow {
     first_prompt_id: i64,
     last_prompt_id: i64,
     hot_files: String,
-    phase_signature: String,
-    obs_count: i64,
+    pub(crate) phase_signature: String,
+    pub(crate) obs_count: i64,
     obs_trace: Option<String>,
 }
 
-/// Ensure the user_intent_stream view exists (idempotent).
-fn ensure_view(conn: &Connection) -> Result<(), NmemError> {
-    conn.execute_batch(
-        "CREATE VIEW IF NOT EXISTS user_intent_stream AS
-         SELECT
-             p.id as prompt_id,
-             p.session_id,
-             p.timestamp,
-             p.content,
-             LENGTH(p.content) - LENGTH(REPLACE(p.content, ' ', '')) + 1 as word_count
-         FROM prompts p
-         WHERE p.source = 'user'",
-    )?;
-    Ok(())
-}
-
 /// Detect episode boundaries from user prompts in a session.
+/// Reads `prompts` directly (no view) so it also works on read-only connections.
 pub fn detect_episodes(conn: &Connection, session_id: &str) -> Result<Vec<Episode>, NmemError> {
-    ensure_view(conn)?;
-
     let mut stmt = conn.prepare(
-        "SELECT prompt_id, timestamp, content, word_count
-         FROM user_intent_stream
-         WHERE session_id = ?1
-         ORDER BY prompt_id ASC",
+        "SELECT id, timestamp, content,
+                CASE WHEN json_valid(metadata) THEN json_extract(metadata, '$.lang') END
+         FROM prompts
+         WHERE session_id = ?1 AND source = 'user'
+         ORDER BY id ASC",
     )?;
 
     struct PromptRow {
@@ -70,15 +55,20 @@ pub fn detect_episodes(conn: &Connection, session_id: &str) -> Result<Vec<Episod
         timestamp: i64,
         content: String,
         word_count: i64,
+        /// Recorded at capture; prompts from before then are detected here
+        lang: String,
     }
 
     let rows: Vec<PromptRow> = stmt
         .query_map(params![session_id], |r| {
+            let content: String = r.get(2)?;
+            let lang = r.get::<_, Option<String>>(3)?.unwrap_or_else(|| crate::s1_lang::detect(&content));
             Ok(PromptRow {
                 id: r.get(0)?,
                 timestamp: r.get(1)?,
-                content: r.get(2)?,
-                word_count: r.get(3)?,
+                word_count: crate::s1_lang::word_count(&content) as i64,
+                content,
+                lang,
             })
         })?
         .collect::<Result<_, _>>()?;
@@ -113,7 +103,7 @@ pub fn detect_episodes(conn: &Connection, session_id: &str) -> Result<Vec<Episod
             continue;
         }
 
-        let new_keywords = intent_keywords(&row.content);
+        let new_keywords = crate::s1_lang::keywords(&row.content, &row.lang);
 
         if current_episode.is_none() {
             // First substantive prompt — start first episode
@@ -171,7 +161,7 @@ pub fn detect_episodes(conn: &Connection, session_id: &str) -> Result<Vec<Episod
 }
 
 /// Annotate an episode with observation metadata from the DB.
-fn annotate_episode(conn: &Connection, episode: &Episode) -> Result<WorkUnitRow, NmemError> {
+pub(crate) fn annotate_episode(conn: &Connection, episode: &Episode) -> Result<WorkUnitRow, NmemError> {
     // Hot files: distinct file_paths for observations in this episode's prompt range
     let hot_files: Vec<String> = {
         let mut stmt = conn.prepare(
@@ -413,11 +403,13 @@ Prompts and actions in this episode:
 
 Return ONLY the JSON object."#;
 
-/// Gather episode-scoped payload for narrative generation.
+/// Gather episode-scoped payload for narrative generation; action lines are
+/// picked by the extractive pre-pass to fit `budget_tokens`.
 fn gather_episode_payload(
     conn: &Connection,
     episode: &WorkUnitRow,
-) -> Result<Option<String>, NmemError> {
+    budget_tokens: usize,
+) -> Result<Option<Payload>, NmemError> {
     // Skip sparse episodes — not enough activity to narrate
     if episode.obs_count < 3 {
         return Ok(None);
@@ -469,50 +461,53 @@ fn gather_episode_payload(
         out.push('\n');
     }
 
-    // Observations in range — include classifier labels and failure metadata
+    // Observations in range — include classifier labels and failure metadata,
+    // cut to the most salient within the budget left after the prompts
     let mut obs_stmt = conn.prepare(
-        "SELECT obs_type, file_path, content, phase, scope, locus, novelty, metadata
+        "SELECT obs_type, file_path, content, phase, scope, locus, novelty, metadata, id
          FROM observations
          WHERE session_id = ?1
            AND prompt_id >= ?2 AND prompt_id <= ?3
-         ORDER BY timestamp ASC LIMIT 30",
+         ORDER BY timestamp ASC LIMIT ?4",
     )?;
+    let candidates: Vec<crate::s1_4_salience::Candidate> = obs_stmt
+        .query_map(
+            params![
+                episode.session_id,
+                episode.first_prompt_id,
+                episode.last_prompt_id,
+                crate::s1_4_summarize::MAX_ACTION_ROWS,
+            ],
+            crate::s1_4_summarize::action_candidate,
+        )?
+        .collect::<Result<_, _>>()?;
+    let budget = budget_tokens
+        .saturating_sub(crate::s1_4_salience::estimate_tokens(&out))
+        .max(budget_tokens / 2);
+    let selection = crate::s1_4_salience::select(candidates, budget);
 
     out.push_str("Actions:\n");
-    let mut rows = obs_stmt.query(params![
-        episode.session_id,
-        episode.first_prompt_id,
-        episode.last_prompt_id,
-    ])?;
-    while let Some(row) = rows.next()? {
-        let obs_type: String = row.get(0)?;
-        let file_path: Option<String> = row.get(1)?;
-        let content: String = row.get(2)?;
-        let phase: Option<String> = row.get(3)?;
-        let scope: Option<String> = row.get(4)?;
-        let locus: Option<String> = row.get(5)?;
-        let novelty: Option<String> = row.get(6)?;
-        let metadata_str: Option<String> = row.get(7)?;
-
-        let display = crate::s1_4_summarize::format_action_line(
-            &obs_type, file_path.as_deref(), &content,
-            phase.as_deref(), scope.as_deref(), locus.as_deref(), novelty.as_deref(),
-            metadata_str.as_deref(),
-        );
-        out.push_str(&format!("{display}\n"));
-    }
+    crate::s1_4_summarize::push_actions(&mut out, &selection);
+    let sources = selection.sources;
+
+    Ok(Some(Payload { text: out, sources }))
+}
 
-    Ok(Some(out))
+fn now_secs() -> i64 {
+    std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs() as i64
 }
 
 /// Generate narrative for a single episode via direct LLM inference.
-/// Returns (narrative_text, elapsed_ms) on success.
+/// Returns (narrative_text, elapsed_ms, source observation ids) on success.
 fn generate_narrative(
     conn: &Connection,
     episode: &WorkUnitRow,
     config: &SummarizationConfig,
-) -> Result<Option<(String, u64)>, NmemError> {
-    let payload = match gather_episode_payload(conn, episode)? {
+) -> Result<Option<(String, u64, Vec<i64>)>, NmemError> {
+    let payload = match gather_episode_payload(conn, episode, config.payload_tokens / 2)? {
         Some(p) => p,
         None => return Ok(None),
     };
@@ -521,7 +516,7 @@ fn generate_narrative(
         .replace("{HOT_FILES}", &episode.hot_files)
         .replace("{PHASE_SIG}", &episode.phase_signature)
         .replace("{OBS_COUNT}", &episode.obs_count.to_string())
-        .replace("{PAYLOAD}", &payload);
+        .replace("{PAYLOAD}", &payload.text);
 
     let mut inference_params = crate::s1_4_inference::params_from_config(config)?;
     inference_params.max_tokens = 512; // episodes need shorter output than sessions
@@ -536,15 +531,32 @@ fn generate_narrative(
         "narrative inference: {}ms, {} prompt tokens, {} generated",
         result.total_ms, result.prompt_tokens, result.generated_tokens
     );
+    if let Err(e) = crate::s3_llm_usage::record(
+        conn,
+        crate::s3_llm_usage::EPISODE_NARRATIVE,
+        &episode.session_id,
+        Some(episode.first_prompt_id),
+        &result,
+        now_secs(),
+    ) {
+        log::warn!("llm usage not recorded: {e}");
+    }
 
-    Ok(Some((result.text, result.total_ms)))
+    Ok(Some((result.text, result.total_ms, payload.sources)))
 }
 
-/// Update a work_unit row with narrative summary.
-fn store_narrative(conn: &Connection, session_id: &str, first_prompt_id: i64, narrative: &str) -> Result<(), NmemError> {
+/// Update a work_unit row with narrative summary and the observations it was
+/// generated from.
+fn store_narrative(
+    conn: &Connection,
+    session_id: &str,
+    first_prompt_id: i64,
+    narrative: &str,
+    sources: &[i64],
+) -> Result<(), NmemError> {
     conn.execute(
-        "UPDATE work_units SET summary = ?1 WHERE session_id = ?2 AND first_prompt_id = ?3",
-        params![narrative, session_id, first_prompt_id],
+        "UPDATE work_units SET summary = ?1, summary_sources = ?4 WHERE session_id = ?2 AND first_prompt_id = ?3",
+        params![narrative, session_id, first_prompt_id, serde_json::to_string(sources)?],
     )?;
     Ok(())
 }
@@ -592,7 +604,17 @@ fn friction_label_from_signature(sig_json: &str) -> &'static str {
 /// and updates observations in each episode's prompt range.
 pub fn backfill_episode_friction(db_path: &std::path::Path) -> Result<(), NmemError> {
     let conn = crate::db::open_db(db_path)?;
+    let (episodes, updated, orphaned) = relabel_friction(&conn)?;
+    log::info!(
+        "friction backfill complete — {episodes} episodes, {updated} observations updated, {orphaned} orphans cleared"
+    );
+    Ok(())
+}
 
+/// Label every episode's observations with its friction and clear it on
+/// observations outside any episode. Returns (episodes, updated, orphans
+/// cleared).
+pub fn relabel_friction(conn: &Connection) -> Result<(usize, u64, usize), NmemError> {
     let mut stmt = conn.prepare(
         "SELECT session_id, first_prompt_id, last_prompt_id, phase_signature
          FROM work_units ORDER BY session_id, first_prompt_id",
@@ -628,20 +650,20 @@ pub fn backfill_episode_friction(db_path: &std::path::Path) -> Result<(), NmemEr
         [],
     )?;
 
-    log::info!(
-        "friction backfill complete — {} episodes, {} observations updated, {} orphans cleared",
-        units.len(),
-        updated,
-        orphaned,
-    );
-
-    Ok(())
+    Ok((units.len(), updated, orphaned))
 }
 
 /// Backfill obs_trace for existing episodes that don't have one.
 pub fn backfill_obs_trace(db_path: &std::path::Path) -> Result<(), NmemError> {
     let conn = crate::db::open_db(db_path)?;
+    let (filled, episodes) = fill_obs_traces(&conn)?;
+    log::info!("obs_trace backfill complete — {filled} of {episodes} episodes filled");
+    Ok(())
+}
 
+/// Build `obs_trace` for episodes without one. Returns (filled, episodes
+/// lacking a trace).
+pub fn fill_obs_traces(conn: &Connection) -> Result<(u64, usize), NmemError> {
     let mut stmt = conn.prepare(
         "SELECT id, session_id, first_prompt_id, last_prompt_id
          FROM work_units WHERE obs_trace IS NULL
@@ -714,39 +736,36 @@ pub fn backfill_obs_trace(db_path: &std::path::Path) -> Result<(), NmemError> {
         }
     }
 
-    log::info!(
-        "obs_trace backfill complete — {} of {} episodes filled",
-        filled,
-        units.len(),
-    );
-
-    Ok(())
+    Ok((filled, units.len()))
 }
 
-/// Backfill narrative summaries for episodes that have enough observations
-/// but no summary yet. Requires LM Studio with a loaded model.
-pub fn backfill_narratives(db_path: &std::path::Path) -> Result<(), NmemError> {
-    let config = crate::s5_config::load_config()?;
-    let sum_config = &config.summarization;
-
-    if !sum_config.enabled {
-        log::info!("summarization disabled in config — skipping narrative backfill");
-        return Ok(());
-    }
-
-    let conn = crate::db::open_db(db_path)?;
+/// Which episodes `narrate_episodes` walks, and how fast.
+pub struct NarrateOptions<'a> {
+    pub project: Option<&'a str>,
+    /// Skip episodes that already have a narrative.
+    pub missing_only: bool,
+    /// Episodes generated back to back before pausing.
+    pub batch_size: usize,
+    pub pause: std::time::Duration,
+}
 
-    // Find episodes with enough obs but no narrative
+/// Episodes with enough observations to narrate, oldest first.
+fn episodes_to_narrate(
+    conn: &Connection,
+    project: Option<&str>,
+    missing_only: bool,
+) -> Result<Vec<WorkUnitRow>, NmemError> {
     let mut stmt = conn.prepare(
-        "SELECT session_id, started_at, ended_at, intent,
-                first_prompt_id, last_prompt_id, hot_files, phase_signature, obs_count, obs_trace
-         FROM work_units
-         WHERE summary IS NULL AND obs_count >= 3
-         ORDER BY started_at ASC",
+        "SELECT w.session_id, w.started_at, w.ended_at, w.intent,
+                w.first_prompt_id, w.last_prompt_id, w.hot_files, w.phase_signature, w.obs_count, w.obs_trace
+         FROM work_units w JOIN sessions s ON s.id = w.session_id
+         WHERE w.obs_count >= 3
+           AND (?1 IS NULL OR s.project = ?1)
+           AND (?2 = 0 OR w.summary IS NULL)
+         ORDER BY w.started_at ASC",
     )?;
-
-    let episodes: Vec<WorkUnitRow> = stmt
-        .query_map([], |r| {
+    let episodes = stmt
+        .query_map(params![project, missing_only], |r| {
             Ok(WorkUnitRow {
                 session_id: r.get(0)?,
                 started_at: r.get(1)?,
@@ -761,7 +780,29 @@ pub fn backfill_narratives(db_path: &std::path::Path) -> Result<(), NmemError> {
             })
         })?
         .collect::<Result<_, _>>()?;
+    Ok(episodes)
+}
+
+/// Generate narratives for past episodes — those missed because summarization
+/// was off or the endpoint was down at Stop, or all of them to regenerate.
+/// Works in batches with a pause between, and stops at the monthly token budget.
+pub fn narrate_episodes(db_path: &std::path::Path, opts: &NarrateOptions) -> Result<(), NmemError> {
+    let config = crate::s5_config::load_config()?;
+    let sum_config = &config.summarization;
+
+    if !sum_config.enabled {
+        log::info!("summarization disabled in config — skipping narrative backfill");
+        return Ok(());
+    }
+
+    let conn = crate::db::open_db(db_path)?;
 
+    if crate::s3_llm_usage::over_budget(&conn, sum_config, now_secs())? {
+        log::info!("monthly LLM token budget reached — skipping narrative backfill");
+        return Ok(());
+    }
+
+    let episodes = episodes_to_narrate(&conn, opts.project, opts.missing_only)?;
     if episodes.is_empty() {
         log::info!("no episodes need narrative backfill");
         return Ok(());
@@ -771,26 +812,35 @@ pub fn backfill_narratives(db_path: &std::path::Path) -> Result<(), NmemError> {
 
     let mut filled = 0u64;
     let mut skipped = 0u64;
-    for ep in &episodes {
-        match generate_narrative(&conn, ep, sum_config) {
-            Ok(Some((narrative, elapsed_ms))) => {
-                store_narrative(&conn, &ep.session_id, ep.first_prompt_id, &narrative)?;
-                filled += 1;
-                log::info!(
-                    "[{}/{}] {} obs, {}ms — {}",
-                    filled + skipped,
-                    episodes.len(),
-                    ep.obs_count,
-                    elapsed_ms,
-                    ep.intent.chars().take(60).collect::<String>(),
-                );
-            }
-            Ok(None) => {
-                skipped += 1;
+    'batches: for (i, batch) in episodes.chunks(opts.batch_size.max(1)).enumerate() {
+        if i > 0 && !opts.pause.is_zero() {
+            std::thread::sleep(opts.pause);
+        }
+        for ep in batch {
+            if crate::s3_llm_usage::over_budget(&conn, sum_config, now_secs())? {
+                log::info!("monthly LLM token budget reached — pausing narrative backfill");
+                break 'batches;
             }
-            Err(e) => {
-                log::warn!("narrative generation failed: {e}");
-                skipped += 1;
+            match generate_narrative(&conn, ep, sum_config) {
+                Ok(Some((narrative, elapsed_ms, sources))) => {
+                    store_narrative(&conn, &ep.session_id, ep.first_prompt_id, &narrative, &sources)?;
+                    filled += 1;
+                    log::info!(
+                        "[{}/{}] {} obs, {}ms — {}",
+                        filled + skipped,
+                        episodes.len(),
+                        ep.obs_count,
+                        elapsed_ms,
+                        ep.intent.chars().take(60).collect::<String>(),
+                    );
+                }
+                Ok(None) => {
+                    skipped += 1;
+                }
+                Err(e) => {
+                    log::warn!("narrative generation failed: {e}");
+                    skipped += 1;
+                }
             }
         }
     }
@@ -803,6 +853,238 @@ pub fn backfill_narratives(db_path: &std::path::Path) -> Result<(), NmemError> {
     Ok(())
 }
 
+/// Backfill narrative summaries for episodes that have enough observations
+/// but no summary yet. Requires LM Studio with a loaded model.
+pub fn backfill_narratives(db_path: &std::path::Path) -> Result<(), NmemError> {
+    narrate_episodes(
+        db_path,
+        &NarrateOptions {
+            project: None,
+            missing_only: true,
+            batch_size: usize::MAX,
+            pause: std::time::Duration::ZERO,
+        },
+    )
+}
+
+#[cfg(feature = "cli")]
+pub fn handle_summarize(db_path: &std::path::Path, args: &crate::cli::SummarizeArgs) -> Result<(), NmemError> {
+    narrate_episodes(
+        db_path,
+        &NarrateOptions {
+            project: args.project.as_deref(),
+            missing_only: args.missing_only,
+            batch_size: args.batch_size,
+            pause: std::time::Duration::from_secs(args.pause_secs),
+        },
+    )
+}
+
+/// A stored episode's boundaries, as `merge_episodes`/`split_episode` see them.
+fn load_episode(conn: &Connection, id: i64) -> Result<Episode, NmemError> {
+    conn.query_row(
+        "SELECT session_id, started_at, ended_at, COALESCE(intent, ''), first_prompt_id, last_prompt_id
+         FROM work_units WHERE id = ?1",
+        params![id],
+        |r| {
+            Ok(Episode {
+                session_id: r.get(0)?,
+                started_at: r.get(1)?,
+                ended_at: r.get(2)?,
+                intent: r.get(3)?,
+                first_prompt_id: r.get(4)?,
+                last_prompt_id: r.get(5)?,
+                keywords: Vec::new(),
+            })
+        },
+    )
+    .optional()?
+    .ok_or_else(|| NmemError::Config(format!("no episode {id}")))
+}
+
+/// Rewrite work_unit `id` from a re-annotated episode. The old narrative no
+/// longer describes the range, so it is cleared.
+fn rewrite_episode(conn: &Connection, id: i64, wu: &WorkUnitRow) -> Result<(), NmemError> {
+    conn.execute(
+        "UPDATE work_units SET started_at = ?1, ended_at = ?2, intent = ?3, first_prompt_id = ?4,
+                last_prompt_id = ?5, hot_files = ?6, phase_signature = ?7, obs_count = ?8, obs_trace = ?9,
+                summary = NULL, summary_sources = NULL, learned = NULL, notes = NULL
+         WHERE id = ?10",
+        params![
+            wu.started_at,
+            wu.ended_at,
+            wu.intent,
+            wu.first_prompt_id,
+            wu.last_prompt_id,
+            wu.hot_files,
+            wu.phase_signature,
+            wu.obs_count,
+            wu.obs_trace,
+            id,
+        ],
+    )?;
+    Ok(())
+}
+
+/// Regenerate narratives for corrected episodes when summarization is on and
+/// the budget allows. Failures leave the narrative empty for `nmem summarize`.
+fn renarrate(conn: &Connection, episodes: &[&WorkUnitRow], config: &SummarizationConfig) {
+    if !config.enabled {
+        return;
+    }
+    for ep in episodes {
+        match crate::s3_llm_usage::over_budget(conn, config, now_secs()) {
+            Ok(false) => {}
+            Ok(true) => {
+                log::info!("monthly LLM token budget reached — narrative left for `nmem summarize`");
+                return;
+            }
+            Err(e) => {
+                log::warn!("llm budget check failed (non-fatal): {e}");
+                return;
+            }
+        }
+        match generate_narrative(conn, ep, config) {
+            Ok(Some((narrative, _, sources))) => {
+                if let Err(e) = store_narrative(conn, &ep.session_id, ep.first_prompt_id, &narrative, &sources) {
+                    log::warn!("episode narrative store failed: {e}");
+                }
+            }
+            Ok(None) => {}
+            Err(e) => log::warn!("episode narrative failed (non-fatal): {e}"),
+        }
+    }
+}
+
+/// Merge two episodes of the same session into one, for when detection split
+/// a single piece of work. The earlier episode is kept (with its intent and
+/// topic membership) and re-annotated over both ranges; the later one is
+/// deleted. Returns the kept id.
+pub fn merge_episodes(conn: &Connection, a: i64, b: i64, config: &SummarizationConfig) -> Result<i64, NmemError> {
+    if a == b {
+        return Err(NmemError::Config("cannot merge an episode with itself".into()));
+    }
+    let (ep_a, ep_b) = (load_episode(conn, a)?, load_episode(conn, b)?);
+    if ep_a.session_id != ep_b.session_id {
+        return Err(NmemError::Config(format!(
+            "episodes {a} and {b} are from different sessions ({} and {})",
+            ep_a.session_id, ep_b.session_id
+        )));
+    }
+    let ((keep, first), (drop, second)) = if ep_a.first_prompt_id <= ep_b.first_prompt_id {
+        ((a, ep_a), (b, ep_b))
+    } else {
+        ((b, ep_b), (a, ep_a))
+    };
+
+    let merged = Episode {
+        ended_at: first.ended_at.max(second.ended_at),
+        last_prompt_id: first.last_prompt_id.max(second.last_prompt_id),
+        ..first
+    };
+    let between: i64 = conn.query_row(
+        "SELECT COUNT(*) FROM work_units
+         WHERE session_id = ?1 AND id NOT IN (?2, ?3)
+           AND first_prompt_id <= ?5 AND last_prompt_id >= ?4",
+        params![merged.session_id, keep, drop, merged.first_prompt_id, merged.last_prompt_id],
+        |r| r.get(0),
+    )?;
+    if between > 0 {
+        return Err(NmemError::Config(format!(
+            "episodes {a} and {b} are not adjacent — merge the ones in between first"
+        )));
+    }
+
+    let tx = conn.unchecked_transaction()?;
+    let wu = annotate_episode(&tx, &merged)?;
+    rewrite_episode(&tx, keep, &wu)?;
+    tx.execute("DELETE FROM work_units WHERE id = ?1", params![drop])?;
+    apply_episode_friction(&tx, &merged.session_id)?;
+    tx.commit()?;
+
+    renarrate(conn, &[&wu], config);
+    Ok(keep)
+}
+
+/// Split an episode in two at user prompt `at_prompt`, for when detection
+/// merged unrelated work. The first half keeps the id; the second half starts
+/// at `at_prompt` and takes its text as intent. Returns both ids.
+pub fn split_episode(
+    conn: &Connection,
+    id: i64,
+    at_prompt: i64,
+    config: &SummarizationConfig,
+) -> Result<(i64, i64), NmemError> {
+    let ep = load_episode(conn, id)?;
+    let (at_ts, at_content): (i64, String) = conn
+        .query_row(
+            "SELECT timestamp, content FROM prompts
+             WHERE id = ?1 AND session_id = ?2 AND source = 'user'",
+            params![at_prompt, ep.session_id],
+            |r| Ok((r.get(0)?, r.get(1)?)),
+        )
+        .optional()?
+        .ok_or_else(|| NmemError::Config(format!("prompt {at_prompt} is not a user prompt of episode {id}'s session")))?;
+    if at_prompt <= ep.first_prompt_id || at_prompt > ep.last_prompt_id {
+        return Err(NmemError::Config(format!(
+            "prompt {at_prompt} is not inside episode {id} (prompts {}..={}, split point must be after the first)",
+            ep.first_prompt_id, ep.last_prompt_id
+        )));
+    }
+    let (head_last, head_ended): (i64, i64) = conn.query_row(
+        "SELECT id, timestamp FROM prompts
+         WHERE session_id = ?1 AND id >= ?2 AND id < ?3
+         ORDER BY id DESC LIMIT 1",
+        params![ep.session_id, ep.first_prompt_id, at_prompt],
+        |r| Ok((r.get(0)?, r.get(1)?)),
+    )?;
+
+    let tail = Episode {
+        session_id: ep.session_id.clone(),
+        started_at: at_ts,
+        ended_at: ep.ended_at,
+        intent: at_content,
+        first_prompt_id: at_prompt,
+        last_prompt_id: ep.last_prompt_id,
+        keywords: Vec::new(),
+    };
+    let head = Episode {
+        ended_at: Some(head_ended),
+        last_prompt_id: head_last,
+        ..ep
+    };
+
+    let tx = conn.unchecked_transaction()?;
+    let head_wu = annotate_episode(&tx, &head)?;
+    let tail_wu = annotate_episode(&tx, &tail)?;
+    rewrite_episode(&tx, id, &head_wu)?;
+    store_episodes(&tx, std::slice::from_ref(&tail_wu))?;
+    let tail_id = tx.last_insert_rowid();
+    apply_episode_friction(&tx, &head.session_id)?;
+    tx.commit()?;
+
+    renarrate(conn, &[&head_wu, &tail_wu], config);
+    Ok((id, tail_id))
+}
+
+#[cfg(feature = "cli")]
+pub fn handle_episode(db_path: &std::path::Path, args: &crate::cli::EpisodeArgs) -> Result<(), NmemError> {
+    use crate::cli::EpisodeCommand;
+    let config = crate::s5_config::load_config()?;
+    let conn = crate::db::open_db(db_path)?;
+    match &args.command {
+        EpisodeCommand::Merge(m) => {
+            let id = merge_episodes(&conn, m.first, m.second, &config.summarization)?;
+            log::info!("merged episodes {} and {} into {id}", m.first, m.second);
+        }
+        EpisodeCommand::Split(s) => {
+            let (head, tail) = split_episode(&conn, s.id, s.at_prompt, &config.summarization)?;
+            log::info!("split episode {} into {head} and {tail}", s.id);
+        }
+    }
+    Ok(())
+}
+
 /// Orchestrator: detect episodes, annotate, and store. No narrative generation.
 /// Idempotent: skips if work_units already exist for this session.
 pub fn detect_and_store_episodes(
@@ -878,14 +1160,26 @@ pub fn detect_and_narrate_episodes(
     apply_episode_friction(conn, session_id)?;
     let count = annotated.len();
 
-    // Generate narratives if summarization is enabled
+    // Generate narratives if summarization is enabled. Narratives are the
+    // first thing the monthly token budget pauses.
     if config.enabled {
         for ep in &annotated {
+            match crate::s3_llm_usage::over_budget(conn, config, now_secs()) {
+                Ok(false) => {}
+                Ok(true) => {
+                    log::info!("monthly LLM token budget reached — episode narratives paused");
+                    break;
+                }
+                Err(e) => {
+                    log::warn!("llm budget check failed (non-fatal): {e}");
+                    break;
+                }
+            }
             match generate_narrative(conn, ep, config) {
-                Ok(Some((narrative, elapsed_ms))) => {
+                Ok(Some((narrative, elapsed_ms, sources))) => {
                     log::info!("episode narrative ({}ms): {}", elapsed_ms,
                         ep.intent.chars().take(60).collect::<String>());
-                    if let Err(e) = store_narrative(conn, &ep.session_id, ep.first_prompt_id, &narrative) {
+                    if let Err(e) = store_narrative(conn, &ep.session_id, ep.first_prompt_id, &narrative, &sources) {
                         log::warn!("episode narrative store failed: {e}");
                     }
                 }
@@ -980,6 +1274,24 @@ mod tests {
         assert!(episodes[1].intent.contains("refactor") || episodes[1].intent.contains("database"));
     }
 
+    #[test]
+    fn non_english_prompts_split_on_intent_not_function_words() {
+        let conn = setup_db();
+        insert_session(&conn, "de");
+        // Shared only in stopwords (die, der, und, nicht, bitte, das, ist)
+        insert_prompt(&conn, "de", 1000, "bitte die Anmeldung reparieren, der Login und das Token ist nicht gültig");
+        insert_prompt(&conn, "de", 1010, "bitte die Datenbank migrieren, der Index und das Schema ist nicht aktuell");
+        assert_eq!(detect_episodes(&conn, "de").unwrap().len(), 2);
+
+        insert_session(&conn, "ja");
+        insert_prompt(&conn, "ja", 1000, "ログイン画面の認証エラーを修正してください");
+        insert_prompt(&conn, "ja", 1010, "ログイン画面の認証テストも追加してください");
+        insert_prompt(&conn, "ja", 1020, "データベースのスキーマ移行を実装してください");
+        let episodes = detect_episodes(&conn, "ja").unwrap();
+        assert_eq!(episodes.len(), 2, "CJK prompts are neither terse nor one giant keyword");
+        assert!(episodes[1].intent.contains("データベース"));
+    }
+
     #[test]
     fn terse_prompts_are_continuations() {
         let conn = setup_db();
@@ -1138,7 +1450,7 @@ mod tests {
 
         let episodes = detect_episodes(&conn, "s1").unwrap();
         let annotated = annotate_episode(&conn, &episodes[0]).unwrap();
-        let payload = gather_episode_payload(&conn, &annotated).unwrap();
+        let payload = gather_episode_payload(&conn, &annotated, 1500).unwrap();
         assert!(payload.is_some(), "single-prompt episode with 3+ obs should get narrative");
     }
 
@@ -1155,7 +1467,7 @@ mod tests {
 
         let episodes = detect_episodes(&conn, "s1").unwrap();
         let annotated = annotate_episode(&conn, &episodes[0]).unwrap();
-        let payload = gather_episode_payload(&conn, &annotated).unwrap();
+        let payload = gather_episode_payload(&conn, &annotated, 1500).unwrap();
         assert!(payload.is_none(), "sparse episode should skip narrative");
     }
 
@@ -1173,14 +1485,17 @@ mod tests {
 
         let episodes = detect_episodes(&conn, "s1").unwrap();
         let annotated = annotate_episode(&conn, &episodes[0]).unwrap();
-        let payload = gather_episode_payload(&conn, &annotated).unwrap();
+        let payload = gather_episode_payload(&conn, &annotated, 1500).unwrap();
         assert!(payload.is_some());
-        let text = payload.unwrap();
+        let payload = payload.unwrap();
+        assert_eq!(payload.sources.len(), 4);
+        let text = payload.text;
         assert!(text.contains("authentication"));
         assert!(text.contains("[file_read]"));
     }
 
     /// Helper: insert observation with classifier labels and optional metadata.
+    #[allow(clippy::too_many_arguments)]
     fn insert_obs_classified(
         conn: &Connection,
         session_id: &str,
@@ -1343,8 +1658,8 @@ mod tests {
 
         let conn = setup_db();
         insert_session(&conn, "s1");
-        // Sweep requires session to be summarized
-        conn.execute("UPDATE sessions SET summary = '{}' WHERE id = 's1'", []).unwrap();
+        // Sweep requires a summary written after the observations
+        conn.execute("UPDATE sessions SET summary = '{}', summarized_at = 2000 WHERE id = 's1'", []).unwrap();
 
         let p1 = insert_prompt(&conn, "s1", 1000, "fix the authentication bug in the login handler");
         insert_obs_classified(
@@ -1381,8 +1696,14 @@ mod tests {
                 ("file_edit".into(), 0),
             ]),
             max_db_size_mb: None,
+            snapshot_days: 7,
+            min_age_hours: 24,
+            archived_retention_pct: 25,
+            sweep_chunk: 500,
+            sweep_pause_ms: 0,
+            sweep_window: None,
         };
-        let result = run_sweep(&conn, &config).unwrap();
+        let result = run_sweep(&conn, &config, false, None).unwrap();
         assert_eq!(result.deleted, 2, "sweep should delete both observations");
 
         // Observations gone
@@ -1558,6 +1879,30 @@ mod tests {
         }
     }
 
+    #[test]
+    fn episodes_to_narrate_filters_by_project_and_summary() {
+        let conn = setup_db();
+        conn.execute_batch(
+            "INSERT INTO sessions (id, project, started_at) VALUES ('a', 'p', 1), ('b', 'q', 1);
+             INSERT INTO work_units (session_id, started_at, intent, first_prompt_id, last_prompt_id, obs_count, summary)
+                 VALUES ('a', 10, 'narrated', 1, 1, 5, '{}'),
+                        ('a', 20, 'missing', 2, 2, 5, NULL),
+                        ('a', 30, 'sparse', 3, 3, 2, NULL),
+                        ('b', 5, 'other project', 4, 4, 9, NULL);",
+        )
+        .unwrap();
+        let intents = |project, missing_only| -> Vec<String> {
+            episodes_to_narrate(&conn, project, missing_only)
+                .unwrap()
+                .into_iter()
+                .map(|e| e.intent)
+                .collect()
+        };
+        assert_eq!(intents(None, true), vec!["other project", "missing"]);
+        assert_eq!(intents(Some("p"), true), vec!["missing"]);
+        assert_eq!(intents(Some("p"), false), vec!["narrated", "missing"]);
+    }
+
     #[test]
     fn obs_trace_count_matches_obs_count() {
         let conn = setup_db();
@@ -1623,4 +1968,73 @@ mod tests {
             .unwrap();
         assert_eq!(stored, 0);
     }
+
+    #[test]
+    fn merge_and_split_reannotate_episodes() {
+        let conn = setup_db();
+        insert_session(&conn, "s1");
+        insert_session(&conn, "s2");
+        let p1 = insert_prompt(&conn, "s1", 1000, "fix the authentication bug in the login handler");
+        insert_obs_with_prompt(&conn, "s1", p1, 1001, "file_edit", Some("src/auth.rs"));
+        let p2 = insert_prompt(&conn, "s1", 1100, "completely different: update deployment yaml for staging cluster");
+        insert_obs_with_prompt(&conn, "s1", p2, 1101, "file_edit", Some("deploy.yaml"));
+        insert_obs_with_prompt(&conn, "s1", p2, 1102, "command", None);
+        let p3 = insert_prompt(&conn, "s2", 2000, "something else entirely in another session here");
+        insert_obs_with_prompt(&conn, "s2", p3, 2001, "command", None);
+        assert_eq!(detect_and_store_episodes(&conn, "s1").unwrap(), 2);
+        detect_and_store_episodes(&conn, "s2").unwrap();
+        let ids: Vec<i64> = conn
+            .prepare("SELECT id FROM work_units ORDER BY first_prompt_id")
+            .unwrap()
+            .query_map([], |r| r.get(0))
+            .unwrap()
+            .collect::<Result<_, _>>()
+            .unwrap();
+        conn.execute_batch(&format!(
+            "UPDATE work_units SET summary = '{{\"intent\":\"auth\"}}' WHERE id = {};",
+            ids[0]
+        ))
+        .unwrap();
+
+        let config = SummarizationConfig::default();
+        assert!(merge_episodes(&conn, ids[0], ids[2], &config).is_err(), "different sessions");
+        let kept = merge_episodes(&conn, ids[1], ids[0], &config).unwrap();
+        assert_eq!(kept, ids[0]);
+        let (first, last, hot, count, summary): (i64, i64, String, i64, Option<String>) = conn
+            .query_row(
+                "SELECT first_prompt_id, last_prompt_id, hot_files, obs_count, summary FROM work_units WHERE id = ?1",
+                params![kept],
+                |r| Ok((r.get(0)?, r.get(1)?, r.get(2)?, r.get(3)?, r.get(4)?)),
+            )
+            .unwrap();
+        assert_eq!((first, last, count), (p1, p2, 3));
+        assert_eq!(hot, r#"["deploy.yaml","src/auth.rs"]"#);
+        assert!(summary.is_none(), "stale narrative cleared");
+        let units: i64 = conn
+            .query_row("SELECT COUNT(*) FROM work_units WHERE session_id = 's1'", [], |r| r.get(0))
+            .unwrap();
+        assert_eq!(units, 1);
+
+        assert!(split_episode(&conn, kept, p1, &config).is_err(), "split point must follow the first prompt");
+        let (head, tail) = split_episode(&conn, kept, p2, &config).unwrap();
+        assert_eq!(head, kept);
+        let (intent, first, obs_trace): (String, i64, String) = conn
+            .query_row(
+                "SELECT intent, first_prompt_id, obs_trace FROM work_units WHERE id = ?1",
+                params![tail],
+                |r| Ok((r.get(0)?, r.get(1)?, r.get(2)?)),
+            )
+            .unwrap();
+        assert!(intent.starts_with("completely different"));
+        assert_eq!(first, p2);
+        assert!(obs_trace.contains("deploy.yaml"));
+        let (last, count): (i64, i64) = conn
+            .query_row(
+                "SELECT last_prompt_id, obs_count FROM work_units WHERE id = ?1",
+                params![head],
+                |r| Ok((r.get(0)?, r.get(1)?)),
+            )
+            .unwrap();
+        assert_eq!((last, count), (p1, 1));
+    }
 }
@@ -0,0 +1,262 @@
+//! S4 Intelligence — episode outcomes.
+//!
+//! A phase signature says how an episode's work went, not whether it got
+//! anywhere. Once Stop has summarized the session, each of its episodes is
+//! labeled:
+//!
+//! - `blocked` — its last command failed, with no passing run after it;
+//! - `succeeded` — its last command passed, or an item of the session
+//!   summary's `completed` covers its intent (any item, for a session of
+//!   one episode);
+//! - `abandoned` — set later: an unlabeled episode whose intent comes back
+//!   in a later episode within `REAPPEAR_WINDOW_SECS` was dropped the first
+//!   time.
+//!
+//! Anything else stays unlabeled. Context lists blocked, then abandoned
+//! episodes first under Suggested Tasks, until a later episode with the
+//! same intent succeeds.
+
+use crate::s1_4_summarize::SessionSummary;
+use crate::NmemError;
+use rusqlite::{Connection, OptionalExtension, params};
+
+pub const SUCCEEDED: &str = "succeeded";
+pub const BLOCKED: &str = "blocked";
+pub const ABANDONED: &str = "abandoned";
+
+/// How far back an episode's intent reappearing marks it abandoned.
+const REAPPEAR_WINDOW_SECS: i64 = 14 * 86400;
+/// Keyword Jaccard at or above which two intents are the same work.
+const SAME_INTENT: f64 = 0.5;
+
+/// Whether two intents describe the same work.
+pub fn same_intent(a: &str, b: &str) -> bool {
+    let (a, b) = (crate::s3_learn::intent_keywords(a), crate::s3_learn::intent_keywords(b));
+    !a.is_empty() && crate::s3_learn::jaccard(&a, &b) >= SAME_INTENT
+}
+
+/// Whether a `completed` item shares at least half of the intent's keywords.
+fn covers(item: &str, intent: &str) -> bool {
+    let item = crate::s3_learn::intent_keywords(item);
+    let mut keywords = crate::s3_learn::intent_keywords(intent);
+    keywords.sort();
+    keywords.dedup();
+    let shared = keywords.iter().filter(|k| item.contains(k)).count();
+    shared > 0 && shared * 2 >= keywords.len()
+}
+
+/// The label for an episode from whether its last command failed, the
+/// session summary's `completed` items, and whether it was the session's
+/// only episode.
+pub fn infer(last_command_failed: Option<bool>, intent: &str, completed: &[String], only_episode: bool) -> Option<&'static str> {
+    match last_command_failed {
+        Some(true) => Some(BLOCKED),
+        Some(false) => Some(SUCCEEDED),
+        None if completed.iter().any(|c| covers(c, intent)) => Some(SUCCEEDED),
+        None if only_episode && !completed.is_empty() => Some(SUCCEEDED),
+        None => None,
+    }
+}
+
+/// Label the episodes of `session_id`, and mark earlier unlabeled episodes
+/// of its project whose intent it picked up again as abandoned. Returns how
+/// many episodes were labeled.
+pub fn label_session(conn: &Connection, session_id: &str) -> Result<usize, NmemError> {
+    let Some((project, summary)) = conn
+        .query_row(
+            "SELECT project, summary FROM sessions WHERE id = ?1",
+            params![session_id],
+            |r| Ok((r.get::<_, String>(0)?, r.get::<_, Option<String>>(1)?)),
+        )
+        .optional()?
+    else {
+        return Ok(0);
+    };
+    let completed = summary
+        .and_then(|s| serde_json::from_str::<SessionSummary>(&s).ok())
+        .map(|s| s.completed)
+        .unwrap_or_default();
+
+    let mut stmt = conn.prepare(
+        "SELECT id, intent, first_prompt_id, last_prompt_id, started_at FROM work_units
+         WHERE session_id = ?1 ORDER BY started_at",
+    )?;
+    let episodes: Vec<(i64, String, i64, i64, i64)> = stmt
+        .query_map(params![session_id], |r| {
+            Ok((r.get(0)?, r.get::<_, Option<String>>(1)?.unwrap_or_default(), r.get(2)?, r.get(3)?, r.get(4)?))
+        })?
+        .collect::<Result<_, _>>()?;
+    let Some(last_start) = episodes.last().map(|e| e.4) else {
+        return Ok(0);
+    };
+
+    let mut labeled = 0;
+    for (id, intent, first, last, _) in &episodes {
+        let last_command_failed: Option<bool> = conn
+            .query_row(
+                "SELECT COALESCE(json_extract(metadata, '$.failed'), 0) = 1 FROM observations
+                 WHERE session_id = ?1 AND prompt_id BETWEEN ?2 AND ?3 AND obs_type = 'command'
+                 ORDER BY timestamp DESC, id DESC LIMIT 1",
+                params![session_id, first, last],
+                |r| r.get(0),
+            )
+            .optional()?;
+        if let Some(outcome) = infer(last_command_failed, intent, &completed, episodes.len() == 1) {
+            conn.execute("UPDATE work_units SET outcome = ?2 WHERE id = ?1", params![id, outcome])?;
+            labeled += 1;
+        }
+    }
+
+    // Earlier episodes, this session's included, that this session came back to
+    let mut stmt = conn.prepare(
+        "SELECT w.id, w.intent, w.started_at FROM work_units w
+         JOIN sessions s ON s.id = w.session_id
+         WHERE s.project = ?1 AND w.outcome IS NULL AND w.started_at >= ?2 AND w.started_at < ?3",
+    )?;
+    let earlier: Vec<(i64, String, i64)> = stmt
+        .query_map(params![project, episodes[0].4 - REAPPEAR_WINDOW_SECS, last_start], |r| {
+            Ok((r.get(0)?, r.get::<_, Option<String>>(1)?.unwrap_or_default(), r.get(2)?))
+        })?
+        .collect::<Result<_, _>>()?;
+    for (id, intent, started_at) in earlier {
+        if episodes.iter().any(|e| e.4 > started_at && same_intent(&intent, &e.1)) {
+            conn.execute("UPDATE work_units SET outcome = ?2 WHERE id = ?1", params![id, ABANDONED])?;
+            labeled += 1;
+        }
+    }
+    Ok(labeled)
+}
+
+/// A blocked or abandoned episode no later episode finished.
+#[derive(Debug)]
+pub struct Unfinished {
+    pub intent: String,
+    pub started_at: i64,
+    /// `blocked` or `abandoned`.
+    pub outcome: String,
+    pub hot_files: Vec<String>,
+}
+
+/// Blocked, then abandoned episodes of `project` started in `[since,
+/// before)`, newest first within each label, one per intent. Episodes whose
+/// intent a later episode succeeded at are left out.
+pub fn unfinished(conn: &Connection, project: &str, since: i64, before: Option<i64>) -> Result<Vec<Unfinished>, NmemError> {
+    let mut stmt = conn.prepare(
+        "SELECT w.intent, w.started_at, w.outcome, w.hot_files FROM work_units w
+         JOIN sessions s ON s.id = w.session_id
+         WHERE s.project = ?1 AND w.outcome IS NOT NULL AND w.started_at >= ?2
+           AND (?3 IS NULL OR w.started_at < ?3)
+         ORDER BY w.started_at DESC",
+    )?;
+    let rows: Vec<Unfinished> = stmt
+        .query_map(params![project, since, before], |r| {
+            let hot_files: Option<String> = r.get(3)?;
+            Ok(Unfinished {
+                intent: r.get::<_, Option<String>>(0)?.unwrap_or_default(),
+                started_at: r.get(1)?,
+                outcome: r.get(2)?,
+                hot_files: hot_files.and_then(|h| serde_json::from_str(&h).ok()).unwrap_or_default(),
+            })
+        })?
+        .collect::<Result<_, _>>()?;
+
+    let mut open: Vec<Unfinished> = Vec::new();
+    for (i, row) in rows.iter().enumerate() {
+        if row.outcome != BLOCKED && row.outcome != ABANDONED {
+            continue;
+        }
+        // Newer rows come first: a later success or a newer listing wins
+        let finished = rows[..i].iter().any(|r| r.outcome == SUCCEEDED && same_intent(&row.intent, &r.intent));
+        if finished || open.iter().any(|o| same_intent(&row.intent, &o.intent)) {
+            continue;
+        }
+        open.push(Unfinished {
+            intent: row.intent.clone(),
+            started_at: row.started_at,
+            outcome: row.outcome.clone(),
+            hot_files: row.hot_files.clone(),
+        });
+    }
+    open.sort_by_key(|o| o.outcome != BLOCKED);
+    Ok(open)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn setup() -> Connection {
+        let mut conn = Connection::open_in_memory().unwrap();
+        crate::schema::MIGRATIONS.to_latest(&mut conn).unwrap();
+        conn
+    }
+
+    fn episode(conn: &Connection, session: &str, at: i64, prompt: i64, intent: &str) -> i64 {
+        conn.execute(
+            "INSERT INTO prompts (id, session_id, timestamp, source, content) VALUES (?1, ?2, ?3, 'user', ?4)",
+            params![prompt, session, at, intent],
+        )
+        .unwrap();
+        conn.execute(
+            "INSERT INTO work_units (session_id, started_at, intent, first_prompt_id, last_prompt_id, obs_count, hot_files)
+             VALUES (?1, ?2, ?3, ?4, ?4, 3, '[]')",
+            params![session, at, intent, prompt],
+        )
+        .unwrap();
+        conn.last_insert_rowid()
+    }
+
+    fn command(conn: &Connection, session: &str, at: i64, prompt: i64, failed: bool) {
+        conn.execute(
+            "INSERT INTO observations (session_id, prompt_id, timestamp, obs_type, source_event, content, metadata)
+             VALUES (?1, ?2, ?3, 'command', 'PostToolUse', 'cargo test', ?4)",
+            params![session, prompt, at, if failed { r#"{"failed":true}"# } else { "{}" }],
+        )
+        .unwrap();
+    }
+
+    fn outcome(conn: &Connection, id: i64) -> Option<String> {
+        conn.query_row("SELECT outcome FROM work_units WHERE id = ?1", [id], |r| r.get(0)).unwrap()
+    }
+
+    #[test]
+    fn infers_from_last_command_then_summary() {
+        let done = vec!["Fixed the token refresh race".to_string()];
+        assert_eq!(infer(Some(true), "fix token refresh", &done, true), Some(BLOCKED));
+        assert_eq!(infer(Some(false), "render tables", &[], false), Some(SUCCEEDED));
+        assert_eq!(infer(None, "fix token refresh race", &done, false), Some(SUCCEEDED));
+        assert_eq!(infer(None, "render tables", &done, false), None);
+        assert_eq!(infer(None, "render tables", &done, true), Some(SUCCEEDED));
+    }
+
+    #[test]
+    fn labels_episodes_and_marks_returned_intents_abandoned() {
+        let conn = setup();
+        conn.execute_batch(
+            "INSERT INTO sessions (id, project, started_at) VALUES ('s1', 'p', 1000);
+             INSERT INTO sessions (id, project, started_at, summary)
+             VALUES ('s2', 'p', 5000, '{\"intent\":\"x\",\"completed\":[\"Migrated the billing export\"]}');",
+        )
+        .unwrap();
+        let migrate = episode(&conn, "s1", 1000, 1, "migrate billing export to parquet");
+        let flaky = episode(&conn, "s1", 2000, 2, "stabilize flaky login test");
+        command(&conn, "s1", 2100, 2, false);
+        command(&conn, "s1", 2200, 2, true);
+        assert_eq!(label_session(&conn, "s1").unwrap(), 1);
+        assert_eq!(outcome(&conn, migrate), None);
+        assert_eq!(outcome(&conn, flaky).as_deref(), Some(BLOCKED));
+
+        let again = episode(&conn, "s2", 5000, 3, "migrate the billing export to parquet");
+        assert_eq!(label_session(&conn, "s2").unwrap(), 2);
+        assert_eq!(outcome(&conn, migrate).as_deref(), Some(ABANDONED));
+        assert_eq!(outcome(&conn, again).as_deref(), Some(SUCCEEDED));
+        assert_eq!(outcome(&conn, flaky).as_deref(), Some(BLOCKED), "a label is kept");
+
+        let open = unfinished(&conn, "p", 0, None).unwrap();
+        assert_eq!(open.len(), 1, "the migration was finished later: {open:?}");
+        assert_eq!(open[0].intent, "stabilize flaky login test");
+        assert_eq!(open[0].outcome, BLOCKED);
+        // Before the migration was picked up again and finished
+        assert_eq!(unfinished(&conn, "p", 0, Some(3000)).unwrap().len(), 2);
+    }
+}
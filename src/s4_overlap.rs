@@ -0,0 +1,130 @@
+//! S4 Intelligence — files shared between projects.
+//!
+//! A monorepo package and an infra repo can both edit `docker-compose.yml`
+//! under different project names. Context and file history are scoped to
+//! one project, so the other project's decisions about the same file go
+//! unmentioned. This finds other projects that touched the same paths and
+//! the last decision recorded there: the `learned` head (or intent) of the
+//! episode covering that touch, falling back to the session summary.
+
+use crate::NmemError;
+use rusqlite::{Connection, params};
+use serde::Serialize;
+
+#[derive(Debug, Serialize, PartialEq)]
+pub struct SharedFile {
+    pub file_path: String,
+    /// The other project that touched `file_path`.
+    pub project: String,
+    pub last_touched: i64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub last_decision: Option<String>,
+}
+
+// Decision: first `learned` entry, else intent, of the covering episode's
+// narrative, else of the session summary
+const SHARED_SQL: &str = "
+WITH touches AS (
+    SELECT o.file_path, s.project, o.session_id, o.prompt_id, o.timestamp,
+           ROW_NUMBER() OVER (PARTITION BY o.file_path, s.project ORDER BY o.timestamp DESC, o.id DESC) AS rn
+    FROM observations o JOIN sessions s ON s.id = o.session_id
+    WHERE o.file_path IN (SELECT value FROM json_each(?1))
+      AND s.project IS NOT NULL AND s.project != ?2
+      AND (?3 IS NULL OR o.timestamp < ?3)
+      AND NOT EXISTS (SELECT 1 FROM archived_projects a WHERE a.project = s.project)
+)
+SELECT t.file_path, t.project, t.timestamp,
+       COALESCE(
+           (SELECT CASE WHEN json_valid(w.summary)
+                        THEN COALESCE(json_extract(w.summary, '$.learned[0]'), json_extract(w.summary, '$.intent'))
+                   END
+            FROM work_units w
+            WHERE w.session_id = t.session_id
+              AND t.prompt_id BETWEEN w.first_prompt_id AND w.last_prompt_id
+            ORDER BY w.started_at DESC LIMIT 1),
+           (SELECT w.intent
+            FROM work_units w
+            WHERE w.session_id = t.session_id
+              AND t.prompt_id BETWEEN w.first_prompt_id AND w.last_prompt_id
+            ORDER BY w.started_at DESC LIMIT 1),
+           (SELECT CASE WHEN json_valid(s2.summary)
+                        THEN COALESCE(json_extract(s2.summary, '$.learned[0]'), json_extract(s2.summary, '$.intent'))
+                   END
+            FROM sessions s2 WHERE s2.id = t.session_id))
+FROM touches t
+WHERE t.rn = 1
+ORDER BY t.timestamp DESC
+LIMIT ?4";
+
+/// Projects other than `project` that touched any of `paths`, one row per
+/// (path, project), most recent first. Archived projects are left out.
+pub fn shared_files(
+    conn: &Connection,
+    project: &str,
+    paths: &[&str],
+    before: Option<i64>,
+    limit: i64,
+) -> Result<Vec<SharedFile>, NmemError> {
+    if paths.is_empty() {
+        return Ok(Vec::new());
+    }
+    let paths_json = serde_json::to_string(paths)?;
+    let mut stmt = conn.prepare_cached(SHARED_SQL)?;
+    let rows = stmt
+        .query_map(params![paths_json, project, before, limit], |r| {
+            Ok(SharedFile {
+                file_path: r.get(0)?,
+                project: r.get(1)?,
+                last_touched: r.get(2)?,
+                last_decision: r.get(3)?,
+            })
+        })?
+        .collect::<Result<_, _>>()?;
+    Ok(rows)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn finds_other_projects_and_their_last_decision() {
+        let mut conn = Connection::open_in_memory().unwrap();
+        crate::schema::MIGRATIONS.to_latest(&mut conn).unwrap();
+        conn.execute_batch(
+            "INSERT INTO sessions (id, project, started_at, summary) VALUES
+                 ('app', 'app', 1, NULL),
+                 ('infra1', 'infra', 1, '{\"intent\":\"old compose work\",\"learned\":[\"expose 5432 only locally\"]}'),
+                 ('infra2', 'infra', 50, NULL),
+                 ('old', 'legacy', 1, '{\"intent\":\"legacy compose\"}');
+             INSERT INTO prompts (id, session_id, timestamp, source, content) VALUES (1, 'infra2', 60, 'user', 'pin postgres');
+             INSERT INTO work_units (session_id, started_at, intent, first_prompt_id, last_prompt_id, obs_count, summary)
+                 VALUES ('infra2', 60, 'pin postgres version', 1, 1, 3, '{\"intent\":\"pin postgres\",\"learned\":[\"postgres pinned to 16\"]}');
+             INSERT INTO observations (session_id, prompt_id, timestamp, obs_type, source_event, file_path, content) VALUES
+                 ('app', NULL, 100, 'file_edit', 'PostToolUse', '/w/docker-compose.yml', 'e'),
+                 ('infra1', NULL, 10, 'file_edit', 'PostToolUse', '/w/docker-compose.yml', 'e'),
+                 ('infra2', 1, 70, 'file_edit', 'PostToolUse', '/w/docker-compose.yml', 'e'),
+                 ('old', NULL, 5, 'file_read', 'PostToolUse', '/w/docker-compose.yml', 'r'),
+                 ('infra1', NULL, 11, 'file_edit', 'PostToolUse', '/w/other.yml', 'e');
+             INSERT INTO archived_projects (project, archived_at) VALUES ('legacy', 1);",
+        )
+        .unwrap();
+
+        let shared = shared_files(&conn, "app", &["/w/docker-compose.yml"], None, 10).unwrap();
+        assert_eq!(
+            shared,
+            vec![SharedFile {
+                file_path: "/w/docker-compose.yml".into(),
+                project: "infra".into(),
+                last_touched: 70,
+                last_decision: Some("postgres pinned to 16".into()),
+            }]
+        );
+
+        // As of before the episode, the session summary is the fallback
+        let shared = shared_files(&conn, "app", &["/w/docker-compose.yml"], Some(50), 10).unwrap();
+        assert_eq!(shared[0].last_decision.as_deref(), Some("expose 5432 only locally"));
+
+        assert!(shared_files(&conn, "infra", &["/w/other.yml"], None, 10).unwrap().is_empty());
+    }
+}
@@ -0,0 +1,298 @@
+//! S4 Intelligence — context packs.
+//!
+//! A pack (`[packs.<name>]`) is a curated bundle for a recurring situation —
+//! a release, a schema migration — built from saved searches, pinned
+//! observations and markers, and the latest touch of files matching globs.
+//! Its triggers are checked as the hooks record: a user prompt containing an
+//! `on_intent` word, or a tool call on a file matching `on_files`, queues the
+//! pack for the session in `context_pack_queue`, once per session. The next
+//! UserPromptSubmit or PostToolUse hook response hands queued packs to the
+//! agent as additional context; the MCP `context_pack` tool renders a pack on
+//! demand or hands over what is queued.
+
+use crate::s5_config::{NmemConfig, PackConfig};
+use crate::NmemError;
+use regex::Regex;
+use rusqlite::{Connection, params};
+
+/// Preview length of each listed observation.
+const PREVIEW_CHARS: usize = 160;
+/// Files listed per pack.
+const MAX_FILES: usize = 10;
+
+/// A queued pack, rendered.
+#[derive(Debug)]
+pub struct Delivered {
+    pub name: String,
+    pub reason: String,
+    pub markdown: String,
+}
+
+/// Regex for a file glob: `*` and `?` stay within a path segment, `**`
+/// crosses them. A glob not starting with `/` matches at any depth, so
+/// `CHANGELOG.md` matches `/repo/CHANGELOG.md`.
+pub fn glob_regex(glob: &str) -> Result<Regex, regex::Error> {
+    let mut re = String::from(if glob.starts_with('/') { "^" } else { "(^|/)" });
+    let mut chars = glob.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '*' if chars.peek() == Some(&'*') => {
+                chars.next();
+                re.push_str(".*");
+            }
+            '*' => re.push_str("[^/]*"),
+            '?' => re.push_str("[^/]"),
+            c => re.push_str(&regex::escape(&c.to_string())),
+        }
+    }
+    re.push('$');
+    Regex::new(&re)
+}
+
+fn matches_any(globs: &[String], path: &str) -> bool {
+    globs.iter().any(|g| glob_regex(g).is_ok_and(|re| re.is_match(path)))
+}
+
+fn applies(pack: &PackConfig, project: &str) -> bool {
+    pack.project.as_deref().is_none_or(|p| p == project)
+}
+
+/// Queue `pack` for the session unless it already was. Returns whether it
+/// was queued now.
+fn enqueue(conn: &Connection, session_id: &str, pack: &str, reason: &str, now: i64) -> Result<bool, NmemError> {
+    let n = conn.execute(
+        "INSERT OR IGNORE INTO context_pack_queue (session_id, pack, reason, queued_at) VALUES (?1, ?2, ?3, ?4)",
+        params![session_id, pack, reason, now],
+    )?;
+    Ok(n > 0)
+}
+
+/// Queue the packs whose `on_intent` words appear in a user prompt.
+pub fn check_prompt(
+    conn: &Connection,
+    config: &NmemConfig,
+    session_id: &str,
+    project: &str,
+    prompt: &str,
+    now: i64,
+) -> Result<(), NmemError> {
+    let prompt = prompt.to_lowercase();
+    for (name, pack) in config.packs.iter().filter(|(_, p)| applies(p, project)) {
+        if let Some(word) = pack.on_intent.iter().find(|w| prompt.contains(&w.to_lowercase())) {
+            enqueue(conn, session_id, name, &format!("prompt mentions \"{word}\""), now)?;
+        }
+    }
+    Ok(())
+}
+
+/// Queue the packs whose `on_files` globs match a file a tool call touched.
+pub fn check_file(
+    conn: &Connection,
+    config: &NmemConfig,
+    session_id: &str,
+    project: &str,
+    path: &str,
+    now: i64,
+) -> Result<(), NmemError> {
+    for (name, pack) in config.packs.iter().filter(|(_, p)| applies(p, project)) {
+        if matches_any(&pack.on_files, path) {
+            let file = path.rsplit('/').next().unwrap_or(path);
+            enqueue(conn, session_id, name, &format!("touched {file}"), now)?;
+        }
+    }
+    Ok(())
+}
+
+/// Render the packs queued for the session and mark them delivered. Packs
+/// no longer configured are marked delivered without rendering.
+pub fn take_queued(
+    conn: &Connection,
+    config: &NmemConfig,
+    session_id: &str,
+    project: Option<&str>,
+    now: i64,
+) -> Result<Vec<Delivered>, NmemError> {
+    let queued: Vec<(String, String)> = {
+        let mut stmt = conn.prepare(
+            "SELECT pack, reason FROM context_pack_queue
+             WHERE session_id = ?1 AND delivered_at IS NULL ORDER BY queued_at, pack",
+        )?;
+        stmt.query_map(params![session_id], |r| Ok((r.get(0)?, r.get(1)?)))?
+            .collect::<Result<_, _>>()?
+    };
+    let mut delivered = Vec::new();
+    for (name, reason) in queued {
+        if let Some(pack) = config.packs.get(&name) {
+            let markdown = render(conn, &name, pack, project, Some(&reason))?;
+            delivered.push(Delivered { name: name.clone(), reason, markdown });
+        }
+        conn.execute(
+            "UPDATE context_pack_queue SET delivered_at = ?1 WHERE session_id = ?2 AND pack = ?3",
+            params![now, session_id, name],
+        )?;
+    }
+    Ok(delivered)
+}
+
+fn preview(text: &str) -> String {
+    let line = text.split_whitespace().collect::<Vec<_>>().join(" ");
+    if line.chars().count() <= PREVIEW_CHARS {
+        return line;
+    }
+    let cut: String = line.chars().take(PREVIEW_CHARS).collect();
+    format!("{cut}…")
+}
+
+/// Render pack `name` as markdown, searching within `project` (all projects
+/// when None). `reason` names the trigger that queued it.
+pub fn render(
+    conn: &Connection,
+    name: &str,
+    pack: &PackConfig,
+    project: Option<&str>,
+    reason: Option<&str>,
+) -> Result<String, NmemError> {
+    let mut out = format!("## Context pack: {name}");
+    if let Some(reason) = reason {
+        out.push_str(&format!(" ({reason})"));
+    }
+    out.push('\n');
+    let mut empty = true;
+
+    let filters = crate::s1_search::Filters { project, ..Default::default() };
+    for query in &pack.searches {
+        let Some(fts) = crate::sanitize_fts_query(query) else { continue };
+        let hits = crate::s1_search::search(conn, &fts, &filters, pack.limit.max(1) as i64, None)?;
+        if hits.is_empty() {
+            continue;
+        }
+        empty = false;
+        out.push_str(&format!("\n### {query}\n"));
+        for h in hits {
+            out.push_str(&format!(
+                "- #{} ({}) {}: {}\n",
+                h.id,
+                crate::timefmt::relative(h.timestamp),
+                h.obs_type,
+                preview(&h.content_preview)
+            ));
+        }
+    }
+
+    if !pack.markers.is_empty() {
+        let terms: Vec<String> = pack.markers.iter().map(|t| t.to_lowercase()).collect();
+        let mut stmt = conn.prepare(
+            "SELECT o.id, o.timestamp, o.obs_type, o.content FROM observations o
+             JOIN sessions s ON s.id = o.session_id
+             WHERE (o.is_pinned = 1 OR o.obs_type = 'marker') AND (?1 IS NULL OR s.project = ?1)
+             ORDER BY o.timestamp DESC LIMIT 500",
+        )?;
+        let rows = stmt.query_map(params![project], |r| {
+            Ok((r.get::<_, i64>(0)?, r.get::<_, i64>(1)?, r.get::<_, String>(2)?, r.get::<_, String>(3)?))
+        })?;
+        let mut lines = Vec::new();
+        for row in rows {
+            let (id, ts, obs_type, content) = row?;
+            let lower = content.to_lowercase();
+            if terms.iter().any(|t| lower.contains(t)) {
+                lines.push(format!("- #{id} ({}) {obs_type}: {}\n", crate::timefmt::relative(ts), preview(&content)));
+            }
+        }
+        if !lines.is_empty() {
+            empty = false;
+            out.push_str("\n### Markers\n");
+            lines.iter().for_each(|l| out.push_str(l));
+        }
+    }
+
+    if !pack.files.is_empty() {
+        let mut stmt = conn.prepare(
+            "SELECT o.file_path, MAX(o.timestamp), o.id, o.obs_type FROM observations o
+             JOIN sessions s ON s.id = o.session_id
+             WHERE o.file_path IS NOT NULL AND (?1 IS NULL OR s.project = ?1)
+             GROUP BY o.file_path ORDER BY MAX(o.timestamp) DESC LIMIT 2000",
+        )?;
+        let rows = stmt.query_map(params![project], |r| {
+            Ok((r.get::<_, String>(0)?, r.get::<_, i64>(1)?, r.get::<_, i64>(2)?, r.get::<_, String>(3)?))
+        })?;
+        let mut lines = Vec::new();
+        for row in rows {
+            let (path, ts, id, obs_type) = row?;
+            if lines.len() < MAX_FILES && matches_any(&pack.files, &path) {
+                lines.push(format!("- {path} — {obs_type} {} (#{id})\n", crate::timefmt::relative(ts)));
+            }
+        }
+        if !lines.is_empty() {
+            empty = false;
+            out.push_str("\n### Files\n");
+            lines.iter().for_each(|l| out.push_str(l));
+        }
+    }
+
+    if empty {
+        out.push_str("\nNothing recorded yet for this pack.\n");
+    }
+    Ok(out)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn globs_match_segments_and_depth() {
+        let re = glob_regex("CHANGELOG.md").unwrap();
+        assert!(re.is_match("/repo/CHANGELOG.md") && re.is_match("CHANGELOG.md"));
+        assert!(!re.is_match("/repo/OLD-CHANGELOG.md"));
+        let re = glob_regex("release/*.sh").unwrap();
+        assert!(re.is_match("/repo/release/tag.sh"));
+        assert!(!re.is_match("/repo/release/sub/tag.sh"));
+        assert!(glob_regex("/repo/**/*.toml").unwrap().is_match("/repo/crates/api/Cargo.toml"));
+        assert!(!glob_regex("/repo/*.toml").unwrap().is_match("/other/repo/x.toml"));
+    }
+
+    #[test]
+    fn triggers_queue_a_pack_once_and_delivery_renders_it() {
+        let mut conn = Connection::open_in_memory().unwrap();
+        crate::schema::MIGRATIONS.to_latest(&mut conn).unwrap();
+        conn.execute_batch(
+            "INSERT INTO sessions (id, project, started_at) VALUES ('old', 'app', 0), ('s', 'app', 100);
+             INSERT INTO observations (session_id, timestamp, obs_type, source_event, file_path, content, is_pinned) VALUES
+               ('old', 10, 'command', 'PostToolUse', NULL, 'cargo publish --dry-run', 0),
+               ('old', 20, 'marker', 'AgentMarker', NULL, 'Release: bump version, tag, then publish', 0),
+               ('old', 30, 'file_edit', 'PostToolUse', '/app/CHANGELOG.md', '/app/CHANGELOG.md', 0);",
+        )
+        .unwrap();
+        let config: NmemConfig = toml::from_str(
+            r#"
+            [packs.release]
+            searches = ["cargo publish"]
+            markers = ["release"]
+            files = ["CHANGELOG.md"]
+            on_intent = ["release"]
+            on_files = ["Cargo.toml"]
+
+            [packs.other]
+            project = "elsewhere"
+            searches = ["x"]
+            on_intent = ["release"]
+            "#,
+        )
+        .unwrap();
+
+        check_prompt(&conn, &config, "s", "app", "Let's cut the next Release", 110).unwrap();
+        check_file(&conn, &config, "s", "app", "/app/Cargo.toml", 120).unwrap();
+        let delivered = take_queued(&conn, &config, "s", Some("app"), 130).unwrap();
+        assert_eq!(delivered.len(), 1, "once per session, only packs for this project");
+        assert_eq!(delivered[0].reason, "prompt mentions \"release\"");
+        let md = &delivered[0].markdown;
+        assert!(md.starts_with("## Context pack: release (prompt mentions \"release\")"), "{md}");
+        assert!(md.contains("### cargo publish\n- #1 "), "{md}");
+        assert!(md.contains("### Markers\n- #2 "), "{md}");
+        assert!(md.contains("- /app/CHANGELOG.md — file_edit "), "{md}");
+
+        assert!(take_queued(&conn, &config, "s", Some("app"), 140).unwrap().is_empty());
+        check_prompt(&conn, &config, "s", "app", "release again", 150).unwrap();
+        assert!(take_queued(&conn, &config, "s", Some("app"), 160).unwrap().is_empty());
+    }
+}
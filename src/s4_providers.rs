@@ -0,0 +1,254 @@
+//! S4 Intelligence — external context providers.
+//!
+//! `[context.providers]` maps a name to a shell command. At injection time each
+//! command gets a JSON request on stdin and prints a markdown section on
+//! stdout, which is spliced into the context. This is how ticket systems,
+//! runbooks, and other site-specific context get in without forking
+//! `s4_context`.
+//!
+//! Providers run in parallel and are best-effort: a timeout, non-zero exit, or
+//! empty output drops that section with a warning and never fails injection.
+//! Each runs in its own process group, killed whole at the deadline, so a
+//! background job it leaves holding stdout can't stall injection either.
+
+use crate::s5_config::ContextProvider;
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::io::{Read, Write};
+use std::process::{Child, Command, Stdio};
+use std::time::{Duration, Instant};
+
+/// What a provider is told about the injection it contributes to.
+#[derive(Serialize)]
+pub struct ProviderRequest<'a> {
+    pub project: &'a str,
+    /// Max bytes of markdown the provider's section may use.
+    pub budget: usize,
+    pub generated_at: i64,
+    /// Set when context is regenerated as of a past point in time.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub as_of: Option<i64>,
+}
+
+/// Run every provider and return their sections in name order.
+pub fn run_providers(
+    providers: &BTreeMap<String, ContextProvider>,
+    project: &str,
+    generated_at: i64,
+    as_of: Option<i64>,
+) -> Vec<String> {
+    let handles: Vec<_> = providers
+        .iter()
+        .map(|(name, provider)| {
+            let request = ProviderRequest {
+                project,
+                budget: provider.max_bytes(),
+                generated_at,
+                as_of,
+            };
+            let input = serde_json::to_vec(&request).unwrap_or_default();
+            let (name, provider) = (name.clone(), provider.clone());
+            std::thread::spawn(move || {
+                let result = run_provider(&provider, &input);
+                (name, provider, result)
+            })
+        })
+        .collect();
+
+    let mut sections = Vec::new();
+    for handle in handles {
+        let Ok((name, provider, result)) = handle.join() else {
+            continue;
+        };
+        match result {
+            Ok(out) => {
+                if let Some(section) = format_section(&name, &out, provider.max_bytes()) {
+                    sections.push(section);
+                }
+            }
+            Err(e) => log::debug!("context provider {name}: {e}"),
+        }
+    }
+    sections
+}
+
+/// Spawn `sh -c command`, feed `input`, and collect stdout within the timeout.
+fn run_provider(provider: &ContextProvider, input: &[u8]) -> Result<String, String> {
+    let mut command = Command::new("sh");
+    command
+        .arg("-c")
+        .arg(provider.command())
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::null());
+    #[cfg(unix)]
+    {
+        use std::os::unix::process::CommandExt;
+        command.process_group(0);
+    }
+    let mut child = command.spawn().map_err(|e| format!("spawn: {e}"))?;
+
+    // Providers that ignore stdin may exit before reading it; a broken pipe is fine.
+    if let Some(mut stdin) = child.stdin.take() {
+        let _ = stdin.write_all(input);
+    }
+
+    // Drain stdout on a thread so a chatty provider never blocks on a full
+    // pipe; only the first max_bytes + 1 are kept, the rest is truncated anyway.
+    let limit = provider.max_bytes() + 1;
+    let mut stdout = child.stdout.take().ok_or("no stdout")?;
+    let (send, output) = std::sync::mpsc::channel();
+    std::thread::spawn(move || {
+        let mut buf = Vec::new();
+        let mut chunk = [0u8; 8192];
+        while let Ok(n) = stdout.read(&mut chunk) {
+            if n == 0 {
+                break;
+            }
+            let keep = n.min(limit.saturating_sub(buf.len()));
+            buf.extend_from_slice(&chunk[..keep]);
+        }
+        let _ = send.send(buf);
+    });
+
+    let timeout = || format!("timed out after {}ms", provider.timeout_ms());
+    let deadline = Instant::now() + Duration::from_millis(provider.timeout_ms());
+    let status = loop {
+        match child.try_wait().map_err(|e| format!("wait: {e}"))? {
+            Some(status) => break status,
+            None if Instant::now() >= deadline => {
+                kill_group(&mut child);
+                return Err(timeout());
+            }
+            None => std::thread::sleep(Duration::from_millis(10)),
+        }
+    };
+    if !status.success() {
+        kill_group(&mut child);
+        return Err(format!("exited with {status}"));
+    }
+    // The shell is done, but a job it backgrounded may still hold stdout:
+    // wait for EOF only until the deadline, then kill the group, which
+    // closes the pipe. If stdout is still open after that (the job left the
+    // group), the reader thread is abandoned rather than joined.
+    let buf = match output.recv_timeout(deadline.saturating_duration_since(Instant::now())) {
+        Ok(buf) => buf,
+        Err(_) => {
+            kill_group(&mut child);
+            output.recv_timeout(Duration::from_millis(100)).map_err(|_| timeout())?
+        }
+    };
+    Ok(String::from_utf8_lossy(&buf).into_owned())
+}
+
+/// Kill the provider and everything left in its process group.
+fn kill_group(child: &mut Child) {
+    #[cfg(unix)]
+    if let Ok(pgid) = libc::pid_t::try_from(child.id()) {
+        // SAFETY: kill(2) with a negative pid signals that process group and
+        // touches no memory of ours.
+        unsafe {
+            libc::kill(-pgid, libc::SIGKILL);
+        }
+    }
+    let _ = child.kill();
+    let _ = child.wait();
+}
+
+/// Trim, cap at `max_bytes` (on a char boundary), and give the section a
+/// heading if the provider didn't. Empty output yields no section.
+fn format_section(name: &str, output: &str, max_bytes: usize) -> Option<String> {
+    let body = output.trim();
+    if body.is_empty() {
+        return None;
+    }
+    let mut section = if body.starts_with("## ") {
+        String::new()
+    } else {
+        format!("## {name}\n")
+    };
+    if body.len() > max_bytes {
+        let mut end = max_bytes;
+        while !body.is_char_boundary(end) {
+            end -= 1;
+        }
+        section.push_str(body[..end].trim_end());
+        section.push_str("\n_(truncated)_");
+    } else {
+        section.push_str(body);
+    }
+    section.push('\n');
+    Some(section)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn providers(entries: &[(&str, ContextProvider)]) -> BTreeMap<String, ContextProvider> {
+        entries.iter().map(|(n, p)| (n.to_string(), p.clone())).collect()
+    }
+
+    fn full(command: &str, timeout_ms: u64, max_bytes: usize) -> ContextProvider {
+        ContextProvider::Full {
+            command: command.into(),
+            timeout_ms: Some(timeout_ms),
+            max_bytes: Some(max_bytes),
+        }
+    }
+
+    #[test]
+    fn format_section_adds_heading_and_truncates() {
+        assert_eq!(format_section("tickets", "  \n", 100), None);
+        assert_eq!(format_section("tickets", "- ABC-1\n", 100).unwrap(), "## tickets\n- ABC-1\n");
+        assert_eq!(format_section("x", "## Runbook\nstep", 100).unwrap(), "## Runbook\nstep\n");
+        let long = format_section("x", "é".repeat(10).as_str(), 5).unwrap();
+        assert_eq!(long, "## x\néé\n_(truncated)_\n");
+    }
+
+    #[test]
+    fn providers_receive_request_on_stdin() {
+        let p = providers(&[(
+            "echo",
+            ContextProvider::Command("grep -o '\"project\":\"[a-z]*\"'".into()),
+        )]);
+        let sections = run_providers(&p, "nmem", 1_700_000_000, None);
+        assert_eq!(sections, vec!["## echo\n\"project\":\"nmem\"\n"]);
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn background_jobs_holding_stdout_do_not_stall() {
+        let p = providers(&[
+            ("a_bg", full("sleep 5 & echo early", 300, 100)),
+            ("b_slow", full("sh -c 'sleep 5' & sleep 5", 300, 100)),
+        ]);
+        let start = Instant::now();
+        let sections = run_providers(&p, "nmem", 0, None);
+        assert!(start.elapsed() < Duration::from_secs(2), "{:?}", start.elapsed());
+        assert_eq!(sections, vec!["## a_bg\nearly\n"]);
+    }
+
+    #[test]
+    fn failing_slow_and_empty_providers_are_dropped() {
+        let p = providers(&[
+            ("a_ok", ContextProvider::Command("echo ok".into())),
+            ("b_fail", ContextProvider::Command("echo nope; exit 3".into())),
+            ("c_slow", full("sleep 5; echo late", 100, 100)),
+            ("d_empty", ContextProvider::Command("true".into())),
+        ]);
+        let start = Instant::now();
+        let sections = run_providers(&p, "nmem", 0, None);
+        assert!(start.elapsed() < Duration::from_secs(3));
+        assert_eq!(sections, vec!["## a_ok\nok\n"]);
+    }
+
+    #[test]
+    fn output_is_capped() {
+        let p = providers(&[("big", full("yes line | head -n 100000", 2000, 20))]);
+        let sections = run_providers(&p, "nmem", 0, None);
+        assert_eq!(sections.len(), 1);
+        assert!(sections[0].len() < 60);
+        assert!(sections[0].ends_with("_(truncated)_\n"));
+    }
+}
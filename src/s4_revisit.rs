@@ -0,0 +1,219 @@
+//! S4 Intelligence — "things to revisit" queue.
+//!
+//! `nmem learn` writes a report; nothing acts on it. `nmem revisit` takes the
+//! two pattern kinds that point at unfinished work — files read across
+//! sessions but never edited, and errors that keep coming back — ranks them
+//! by heat, and gives each a one-line justification and a short stable id.
+//! `nmem revisit queue <id>` turns one into a dispatcher task whose prompt
+//! carries the evidence, so the insight ends up as scheduled work.
+
+#[cfg(feature = "cli")]
+use crate::cli::{RevisitArgs, RevisitCommand};
+use crate::s3_learn::{Pattern, detect_patterns, pattern_id};
+use crate::NmemError;
+use rusqlite::{Connection, params};
+use serde::Serialize;
+#[cfg(feature = "cli")]
+use std::path::Path;
+
+/// Pattern kinds that name something left unresolved.
+const KINDS: [&str; 2] = ["unresolved_read", "recurring_error"];
+
+/// Sessions listed in a queued task's evidence.
+const EVIDENCE_SESSIONS: usize = 10;
+
+#[derive(Debug, Serialize)]
+pub struct RevisitItem {
+    /// Stable across runs while the pattern persists: hash of kind and target.
+    pub id: String,
+    pub kind: &'static str,
+    /// File path or normalized error signature.
+    pub target: String,
+    /// 0–100, relative to the hottest pattern.
+    pub heat: f64,
+    pub session_count: i64,
+    pub justification: String,
+    pub sessions: Vec<String>,
+    pub example: String,
+}
+
+fn justification(p: &Pattern) -> String {
+    match p.kind {
+        "unresolved_read" => format!("{} — an investigation that never landed a change", p.description),
+        _ => format!("{} — fixed locally each time, never at the root", p.description),
+    }
+}
+
+impl From<Pattern> for RevisitItem {
+    fn from(p: Pattern) -> Self {
+        RevisitItem {
+            id: pattern_id(p.kind, &p.normalized),
+            justification: justification(&p),
+            kind: p.kind,
+            target: p.normalized,
+            heat: p.heat,
+            session_count: p.session_count,
+            sessions: p.sessions,
+            example: p.example,
+        }
+    }
+}
+
+/// Unresolved reads and recurring errors, hottest first.
+pub fn revisit_items(
+    conn: &Connection,
+    threshold: i64,
+    half_life: f64,
+    limit: usize,
+) -> Result<Vec<RevisitItem>, NmemError> {
+    let mut items: Vec<RevisitItem> = detect_patterns(conn, threshold, half_life)?
+        .into_iter()
+        .filter(|p| KINDS.contains(&p.kind))
+        .map(RevisitItem::from)
+        .collect();
+    items.sort_by(|a, b| b.heat.total_cmp(&a.heat).then_with(|| a.id.cmp(&b.id)));
+    items.truncate(limit);
+    Ok(items)
+}
+
+/// Dispatcher prompt for an item, with the evidence behind it.
+pub fn task_prompt(item: &RevisitItem) -> String {
+    let ask = match item.kind {
+        "unresolved_read" => format!(
+            "`{}` keeps being read without being changed. Work out what those sessions were looking for, \
+             then either make the change or record why the file is reference-only.",
+            item.target
+        ),
+        _ => format!(
+            "This error keeps coming back: `{}`. Find the root cause and fix it so it stops recurring.",
+            item.target
+        ),
+    };
+    let mut sessions: Vec<&str> = item.sessions.iter().map(String::as_str).collect();
+    sessions.sort_unstable();
+    sessions.truncate(EVIDENCE_SESSIONS);
+    format!(
+        "{ask}\n\nEvidence from nmem (heat {}, {} sessions):\n- {}\n- Sessions: {}\n- Example: {}\n\n\
+         Use the nmem search and timeline tools on those sessions for detail.",
+        item.heat as u32,
+        item.session_count,
+        item.justification,
+        sessions.join(", "),
+        item.example,
+    )
+}
+
+/// The project most of the item's sessions belong to.
+pub fn item_project(conn: &Connection, item: &RevisitItem) -> Result<Option<String>, NmemError> {
+    let ids = serde_json::to_string(&item.sessions)?;
+    let mut stmt = conn.prepare(
+        "SELECT project FROM sessions WHERE id IN (SELECT value FROM json_each(?1))
+         GROUP BY project ORDER BY COUNT(*) DESC, MAX(started_at) DESC LIMIT 1",
+    )?;
+    let mut rows = stmt.query(params![ids])?;
+    Ok(match rows.next()? {
+        Some(r) => Some(r.get(0)?),
+        None => None,
+    })
+}
+
+#[cfg(feature = "cli")]
+pub fn handle_revisit(db_path: &Path, args: &RevisitArgs) -> Result<(), NmemError> {
+    let limit = match &args.command {
+        Some(_) => usize::MAX,
+        None => args.limit.clamp(1, 100),
+    };
+    let items = {
+        let conn = crate::db::open_db_readonly(db_path)?;
+        revisit_items(&conn, args.threshold, args.half_life, limit)?
+    };
+
+    let Some(RevisitCommand::Queue(q)) = &args.command else {
+        if items.is_empty() {
+            log::info!("nothing to revisit above threshold {}", args.threshold);
+        }
+        for item in &items {
+            println!("{}  heat {:>3}  {}", item.id, item.heat as u32, item.justification);
+        }
+        return Ok(());
+    };
+
+    let item = items
+        .iter()
+        .find(|i| i.id == q.id)
+        .ok_or_else(|| NmemError::Config(format!("no revisit item {} (resolved, or below threshold?)", q.id)))?;
+    let conn = crate::db::open_db(db_path)?;
+    let project = match &q.project {
+        Some(p) => Some(p.clone()),
+        None => item_project(&conn, item)?,
+    };
+    let cwd = q
+        .cwd
+        .clone()
+        .or_else(|| std::env::current_dir().ok().map(|p| p.to_string_lossy().into_owned()));
+    let run_after = crate::s4_dispatch::parse_schedule(&q.after)?;
+    conn.execute(
+        "INSERT INTO tasks (prompt, project, cwd, run_after) VALUES (?1, ?2, ?3, ?4)",
+        params![task_prompt(item), project, cwd, run_after],
+    )?;
+    let task_id = conn.last_insert_rowid();
+    log::info!("revisit {} queued as task {task_id} for {run_after}", item.id);
+    println!("{task_id}");
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn ranks_unresolved_reads_and_errors_with_evidence() {
+        let mut conn = Connection::open_in_memory().unwrap();
+        crate::schema::MIGRATIONS.to_latest(&mut conn).unwrap();
+        let now = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .unwrap()
+            .as_secs() as i64;
+        for (i, project) in ["app", "app", "app", "infra"].iter().enumerate() {
+            let sid = format!("s{i}");
+            conn.execute(
+                "INSERT INTO sessions (id, project, started_at) VALUES (?1, ?2, ?3)",
+                params![sid, project, now],
+            )
+            .unwrap();
+            conn.execute(
+                "INSERT INTO observations (session_id, timestamp, obs_type, source_event, file_path, content)
+                 VALUES (?1, ?2, 'file_read', 'PostToolUse', '/w/src/pool.rs', 'read')",
+                params![sid, now],
+            )
+            .unwrap();
+            // Same failure, same command, in every session: failed_command and
+            // recurring_error — only the latter is a revisit item
+            conn.execute(
+                "INSERT INTO observations (session_id, timestamp, obs_type, source_event, content, metadata)
+                 VALUES (?1, ?2, 'command', 'PostToolUse', 'cargo build',
+                         '{\"failed\":true,\"response\":\"error[E0432]: unresolved import `foo`\"}')",
+                params![sid, now],
+            )
+            .unwrap();
+        }
+
+        let items = revisit_items(&conn, 3, 168.0, 10).unwrap();
+        assert_eq!(items.len(), 2, "{:?}", items.iter().map(|i| i.kind).collect::<Vec<_>>());
+        assert!(items.iter().all(|i| KINDS.contains(&i.kind)));
+        let read = items.iter().find(|i| i.kind == "unresolved_read").unwrap();
+        assert_eq!(read.target, "/w/src/pool.rs");
+        assert_eq!(read.session_count, 4);
+        assert!(read.justification.contains("never edited"));
+        assert_eq!(read.id, pattern_id("unresolved_read", "/w/src/pool.rs"));
+        assert_eq!(read.id.len(), 8);
+
+        let prompt = task_prompt(read);
+        assert!(prompt.contains("`/w/src/pool.rs` keeps being read"));
+        assert!(prompt.contains("s0, s1, s2, s3"));
+        assert_eq!(item_project(&conn, read).unwrap().as_deref(), Some("app"));
+
+        assert_eq!(revisit_items(&conn, 3, 168.0, 1).unwrap().len(), 1);
+        assert!(revisit_items(&conn, 5, 168.0, 10).unwrap().is_empty());
+    }
+}
@@ -0,0 +1,229 @@
+//! S4 Intelligence — file risk.
+//!
+//! `file_history` lists every touch of a file; whether the file tends to
+//! bite is left to the reader. This scores it from three signals over its
+//! whole history (renames included):
+//!
+//! - failure density — of the commands run within `AFTER_EDIT_SECS` of an
+//!   edit to the file in the same session, the share that failed;
+//! - friction — of the episodes that touched the file, the share labeled
+//!   `friction` (they contained a failure);
+//! - reverts — `git checkout`/`git restore` of the file, and read snapshots
+//!   whose content went back to an earlier version.
+//!
+//! The weighted sum lands in 0–1; `ELEVATED` and `HIGH` turn it into a level,
+//! and anything above low carries a one-line warning naming the evidence.
+
+use crate::NmemError;
+use rusqlite::{Connection, params};
+use serde::Serialize;
+
+/// Seconds after an edit in which a command counts as testing it.
+const AFTER_EDIT_SECS: i64 = 600;
+/// Reverts at which that signal saturates.
+const REVERT_SATURATION: f64 = 3.0;
+/// Signal weights: failure density, friction share, reverts.
+const WEIGHTS: (f64, f64, f64) = (0.5, 0.3, 0.2);
+/// Post-edit commands needed before their failure rate counts.
+const MIN_COMMANDS: i64 = 3;
+/// Score thresholds for the `elevated` and `high` levels.
+pub const ELEVATED: f64 = 0.25;
+pub const HIGH: f64 = 0.5;
+
+#[derive(Debug, Serialize, PartialEq)]
+pub struct FileRisk {
+    /// 0 (no trouble seen) to 1.
+    pub score: f64,
+    /// `low`, `elevated`, or `high`.
+    pub level: &'static str,
+    pub edits: i64,
+    /// Commands run within ten minutes after an edit, and how many failed.
+    pub commands_after_edits: i64,
+    pub failed_after_edits: i64,
+    /// Episodes that touched the file, and how many were labeled friction.
+    pub episodes: i64,
+    pub friction_episodes: i64,
+    pub reverts: i64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub warning: Option<String>,
+}
+
+/// Risk of the file known by any of `paths`, from touches before `before`.
+pub fn file_risk(conn: &Connection, paths: &[String], before: Option<i64>) -> Result<FileRisk, NmemError> {
+    let paths_json = serde_json::to_string(paths)?;
+
+    let edits: i64 = conn.query_row(
+        "SELECT COUNT(*) FROM observations
+         WHERE file_path IN (SELECT value FROM json_each(?1))
+           AND obs_type IN ('file_edit', 'file_write') AND (?2 IS NULL OR timestamp < ?2)",
+        params![paths_json, before],
+        |r| r.get(0),
+    )?;
+
+    let (commands, failed): (i64, i64) = conn.query_row(
+        "SELECT COUNT(*), COALESCE(SUM(COALESCE(json_extract(c.metadata, '$.failed'), 0) = 1), 0)
+         FROM observations c
+         WHERE c.obs_type = 'command' AND (?2 IS NULL OR c.timestamp < ?2)
+           AND EXISTS (
+               SELECT 1 FROM observations e
+               WHERE e.session_id = c.session_id
+                 AND e.file_path IN (SELECT value FROM json_each(?1))
+                 AND e.obs_type IN ('file_edit', 'file_write')
+                 AND c.timestamp > e.timestamp AND c.timestamp <= e.timestamp + ?3)",
+        params![paths_json, before, AFTER_EDIT_SECS],
+        |r| Ok((r.get(0)?, r.get(1)?)),
+    )?;
+
+    let (episodes, friction_episodes): (i64, i64) = conn.query_row(
+        "SELECT COUNT(DISTINCT w.id), COUNT(DISTINCT CASE WHEN o.friction = 'friction' THEN w.id END)
+         FROM observations o
+         JOIN work_units w ON w.session_id = o.session_id
+          AND o.timestamp >= w.started_at AND o.timestamp <= COALESCE(w.ended_at, o.timestamp)
+         WHERE o.file_path IN (SELECT value FROM json_each(?1)) AND (?2 IS NULL OR o.timestamp < ?2)",
+        params![paths_json, before],
+        |r| Ok((r.get(0)?, r.get(1)?)),
+    )?;
+
+    let reverts = restore_commands(conn, paths, before)? + snapshot_reverts(conn, paths, before)?;
+
+    let failure = if commands >= MIN_COMMANDS { failed as f64 / commands as f64 } else { 0.0 };
+    let friction = if episodes > 0 { friction_episodes as f64 / episodes as f64 } else { 0.0 };
+    let revert = (reverts as f64 / REVERT_SATURATION).min(1.0);
+    let score = WEIGHTS.0 * failure + WEIGHTS.1 * friction + WEIGHTS.2 * revert;
+    let score = (score * 100.0).round() / 100.0;
+    let level = if score >= HIGH {
+        "high"
+    } else if score >= ELEVATED {
+        "elevated"
+    } else {
+        "low"
+    };
+
+    let warning = (level != "low").then(|| {
+        let mut evidence = Vec::new();
+        if failed > 0 {
+            evidence.push(format!("{failed} of {commands} commands after edits failed"));
+        }
+        if friction_episodes > 0 {
+            evidence.push(format!("{friction_episodes} of {episodes} episodes hit friction"));
+        }
+        if reverts > 0 {
+            evidence.push(format!("{reverts} revert{}", if reverts == 1 { "" } else { "s" }));
+        }
+        format!("{level} risk ({score:.2}): {}", evidence.join(", "))
+    });
+
+    Ok(FileRisk {
+        score,
+        level,
+        edits,
+        commands_after_edits: commands,
+        failed_after_edits: failed,
+        episodes,
+        friction_episodes,
+        reverts,
+        warning,
+    })
+}
+
+/// `git checkout`/`git restore` commands naming one of `paths` (by file name).
+fn restore_commands(conn: &Connection, paths: &[String], before: Option<i64>) -> Result<i64, NmemError> {
+    let mut names: Vec<&str> = paths.iter().filter_map(|p| p.rsplit('/').next()).filter(|n| !n.is_empty()).collect();
+    names.sort_unstable();
+    names.dedup();
+    let mut total = 0;
+    for name in names {
+        let n: i64 = conn.query_row(
+            "SELECT COUNT(*) FROM observations
+             WHERE obs_type = 'command' AND (?2 IS NULL OR timestamp < ?2)
+               AND (content LIKE '%git checkout %' OR content LIKE '%git restore %')
+               AND instr(content, ?1) > 0",
+            params![name, before],
+            |r| r.get(0),
+        )?;
+        total += n;
+    }
+    Ok(total)
+}
+
+/// Read snapshots whose content returned to a version seen before the
+/// previous, different one (A → B → A).
+fn snapshot_reverts(conn: &Connection, paths: &[String], before: Option<i64>) -> Result<i64, NmemError> {
+    let mut stmt = conn.prepare(
+        "SELECT blob_hash FROM file_snapshots
+         WHERE file_path IN (SELECT value FROM json_each(?1)) AND blob_hash IS NOT NULL
+           AND (?2 IS NULL OR captured_at < ?2)
+         ORDER BY captured_at",
+    )?;
+    let hashes: Vec<String> = stmt
+        .query_map(params![serde_json::to_string(paths)?, before], |r| r.get(0))?
+        .collect::<Result<_, _>>()?;
+    let mut reverts = 0;
+    for (i, hash) in hashes.iter().enumerate().skip(2) {
+        if hash != &hashes[i - 1] && hashes[..i - 1].contains(hash) {
+            reverts += 1;
+        }
+    }
+    Ok(reverts)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn obs(conn: &Connection, session: &str, ts: i64, obs_type: &str, file: Option<&str>, content: &str, failed: bool) {
+        conn.execute(
+            "INSERT INTO observations (session_id, timestamp, obs_type, source_event, file_path, content, metadata, friction)
+             VALUES (?1, ?2, ?3, 'PostToolUse', ?4, ?5, ?6, ?7)",
+            params![
+                session,
+                ts,
+                obs_type,
+                file,
+                content,
+                failed.then_some(r#"{"failed":true}"#),
+                (session == "rough").then_some("friction"),
+            ],
+        )
+        .unwrap();
+    }
+
+    #[test]
+    fn failures_friction_and_reverts_raise_risk() {
+        let mut conn = Connection::open_in_memory().unwrap();
+        crate::schema::MIGRATIONS.to_latest(&mut conn).unwrap();
+        conn.execute_batch(
+            "INSERT INTO sessions (id, project, started_at) VALUES ('rough', 'p', 0), ('calm', 'p', 0);
+             INSERT INTO work_units (session_id, started_at, ended_at) VALUES ('rough', 0, 5000), ('calm', 0, 5000);",
+        )
+        .unwrap();
+        let risky = "/repo/src/pool.rs";
+        obs(&conn, "rough", 100, "file_edit", Some(risky), risky, false);
+        obs(&conn, "rough", 200, "command", None, "cargo test", true);
+        obs(&conn, "rough", 300, "command", None, "cargo test", true);
+        obs(&conn, "rough", 400, "command", None, "cargo test", false);
+        obs(&conn, "rough", 2000, "command", None, "cargo build", true);
+        obs(&conn, "rough", 2100, "command", None, "git checkout -- src/pool.rs", false);
+        let safe = "/repo/src/util.rs";
+        obs(&conn, "calm", 100, "file_edit", Some(safe), safe, false);
+        obs(&conn, "calm", 200, "command", None, "cargo test", false);
+
+        let risk = file_risk(&conn, &[risky.to_string()], None).unwrap();
+        // 2 of 3 post-edit commands failed (the one at 2000 is too late), 1 of
+        // 1 episode had friction, 1 revert
+        assert_eq!((risk.commands_after_edits, risk.failed_after_edits), (3, 2));
+        assert_eq!((risk.episodes, risk.friction_episodes, risk.reverts), (1, 1, 1));
+        assert_eq!((risk.score, risk.level), (0.7, "high"));
+        assert_eq!(
+            risk.warning.as_deref(),
+            Some("high risk (0.70): 2 of 3 commands after edits failed, 1 of 1 episodes hit friction, 1 revert")
+        );
+
+        let calm = file_risk(&conn, &[safe.to_string()], None).unwrap();
+        assert_eq!((calm.score, calm.level, calm.warning), (0.0, "low", None));
+
+        // As of just after the edit, only the episode's friction label counts
+        let early = file_risk(&conn, &[risky.to_string()], Some(150)).unwrap();
+        assert_eq!((early.failed_after_edits, early.reverts, early.score), (0, 0, 0.3));
+    }
+}
@@ -0,0 +1,511 @@
+//! S4 Intelligence — weekly and monthly rollups ("chained compaction").
+//!
+//! A long project accumulates hundreds of session summaries and context
+//! injection can list only a handful, so everything older than the last few
+//! sessions drops out. This job composes one LLM-written summary per
+//! completed UTC week (Monday to Monday) from that week's session summaries,
+//! plus the episode intents of sessions that never got a summary, and one
+//! per calendar month from the weekly rollups of weeks starting in it. Both
+//! are stored in `rollups`.
+//!
+//! Only periods ending `[rollup] detail_days` or more ago are rolled up.
+//! Context injection shows recent sessions as before and, past them, the
+//! newest weekly rollups and then monthly ones. A rollup is recomposed when
+//! its source count changes, e.g. after a late `--catch-up` summary.
+
+#[cfg(feature = "cli")]
+use crate::cli::RollupArgs;
+use crate::db::open_db;
+use crate::s1_4_summarize::SessionSummary;
+use crate::s5_config::{RollupConfig, SummarizationConfig, load_config};
+use crate::timefmt::{civil_from_days, days_from_civil};
+use crate::NmemError;
+use rusqlite::{Connection, OptionalExtension, params};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, BTreeSet};
+use std::path::Path;
+
+pub const WEEK: &str = "week";
+pub const MONTH: &str = "month";
+/// Sessions described to the LLM per weekly rollup, newest kept.
+const MAX_PROMPT_SESSIONS: usize = 40;
+/// Weekly rollups shown in context, newest first...
+const CONTEXT_WEEKS: usize = 4;
+/// ...then monthly rollups for the months before them.
+const CONTEXT_MONTHS: usize = 3;
+
+/// What the LLM returns for a period.
+#[derive(Debug, Serialize, Deserialize, Default, Clone, PartialEq)]
+pub struct RollupSummary {
+    /// What the period was about and where it left things.
+    #[serde(default)]
+    pub overview: String,
+    #[serde(default)]
+    pub learned: Vec<String>,
+    /// Work still unfinished when the period ended.
+    #[serde(default)]
+    pub open_threads: Vec<String>,
+}
+
+/// A period due for a (re)composed rollup.
+#[derive(Debug, Serialize, Clone, PartialEq)]
+pub struct Period {
+    pub project: String,
+    /// `week` or `month`.
+    pub period: &'static str,
+    pub period_start: i64,
+    pub period_end: i64,
+    /// Sessions for a week, weekly rollups for a month.
+    pub sources: usize,
+}
+
+/// A stored rollup.
+#[derive(Debug, Clone)]
+pub struct Rollup {
+    pub period: String,
+    pub period_start: i64,
+    pub period_end: i64,
+    pub source_count: i64,
+    pub summary: RollupSummary,
+}
+
+/// Start of the UTC week (Monday 00:00) containing `ts`.
+pub fn week_start(ts: i64) -> i64 {
+    let days = ts.div_euclid(86400);
+    // 1970-01-01 was a Thursday
+    (days - (days + 3).rem_euclid(7)) * 86400
+}
+
+/// Start and end of the UTC calendar month containing `ts`.
+pub fn month_bounds(ts: i64) -> (i64, i64) {
+    let (y, m, _) = civil_from_days(ts.div_euclid(86400));
+    let (ny, nm) = if m == 12 { (y + 1, 1) } else { (y, m + 1) };
+    (days_from_civil(y, m, 1) * 86400, days_from_civil(ny, nm, 1) * 86400)
+}
+
+/// `week of 2026-09-07` or `2026-09`.
+pub fn label(period: &str, start: i64) -> String {
+    let (y, m, d) = civil_from_days(start.div_euclid(86400));
+    if period == MONTH {
+        format!("{y:04}-{m:02}")
+    } else {
+        format!("week of {y:04}-{m:02}-{d:02}")
+    }
+}
+
+/// A week's sources: session summaries and, for sessions without one, their
+/// episode intents — `(session_id, started_at, text)`, oldest first.
+fn week_sources(conn: &Connection, project: &str, start: i64, end: i64) -> Result<Vec<(String, i64, String)>, NmemError> {
+    let mut out = Vec::new();
+    let mut stmt = conn.prepare(
+        "SELECT id, started_at, summary FROM sessions
+         WHERE project = ?1 AND kind = 'agent' AND summary IS NOT NULL
+           AND started_at >= ?2 AND started_at < ?3
+         ORDER BY started_at",
+    )?;
+    let rows = stmt.query_map(params![project, start, end], |r| {
+        Ok((r.get::<_, String>(0)?, r.get::<_, i64>(1)?, r.get::<_, String>(2)?))
+    })?;
+    for row in rows {
+        let (id, at, json) = row?;
+        let Ok(s) = serde_json::from_str::<SessionSummary>(&json) else { continue };
+        // Sentinels for near-empty sessions say nothing
+        if s.intent.is_empty() || s.intent == "empty session" {
+            continue;
+        }
+        let mut text = s.intent.clone();
+        for (name, items) in [("learned", &s.learned), ("completed", &s.completed), ("next", &s.next_steps)] {
+            if !items.is_empty() {
+                text.push_str(&format!("\n  {name}: {}", items.join("; ")));
+            }
+        }
+        out.push((id, at, text));
+    }
+
+    let mut stmt = conn.prepare(
+        "SELECT s.id, s.started_at, group_concat(w.intent, '; ') FROM sessions s
+         JOIN work_units w ON w.session_id = s.id
+         WHERE s.project = ?1 AND s.kind = 'agent' AND s.summary IS NULL
+           AND s.started_at >= ?2 AND s.started_at < ?3
+           AND w.intent IS NOT NULL AND w.obs_count > 0
+         GROUP BY s.id ORDER BY s.started_at",
+    )?;
+    let rows = stmt.query_map(params![project, start, end], |r| {
+        Ok((r.get::<_, String>(0)?, r.get::<_, i64>(1)?, r.get::<_, String>(2)?))
+    })?;
+    for row in rows {
+        let (id, at, intents) = row?;
+        out.push((id, at, format!("episodes: {intents}")));
+    }
+    out.sort_by_key(|(_, at, _)| *at);
+    Ok(out)
+}
+
+fn stored_count(conn: &Connection, project: &str, period: &str, start: i64) -> Result<Option<i64>, NmemError> {
+    Ok(conn
+        .query_row(
+            "SELECT source_count FROM rollups WHERE project = ?1 AND period = ?2 AND period_start = ?3",
+            params![project, period, start],
+            |r| r.get(0),
+        )
+        .optional()?)
+}
+
+/// Completed weeks ending `detail_days` or more before `now` that have no
+/// rollup yet, or whose session count changed since it was written.
+pub fn pending_weeks(conn: &Connection, project: &str, config: &RollupConfig, now: i64) -> Result<Vec<Period>, NmemError> {
+    let cutoff = now - config.detail_days as i64 * 86400;
+    let mut weeks = BTreeSet::new();
+    let mut stmt = conn.prepare(
+        "SELECT s.started_at FROM sessions s
+         WHERE s.project = ?1 AND s.kind = 'agent' AND s.started_at < ?2
+           AND (s.summary IS NOT NULL
+                OR EXISTS (SELECT 1 FROM work_units w WHERE w.session_id = s.id AND w.intent IS NOT NULL))",
+    )?;
+    for at in stmt.query_map(params![project, week_start(cutoff)], |r| r.get::<_, i64>(0))? {
+        weeks.insert(week_start(at?));
+    }
+
+    let mut out = Vec::new();
+    for start in weeks {
+        let end = start + 7 * 86400;
+        let sources = week_sources(conn, project, start, end)?.len();
+        if sources < config.min_sessions.max(1) as usize
+            || stored_count(conn, project, WEEK, start)? == Some(sources as i64)
+        {
+            continue;
+        }
+        out.push(Period { project: project.to_string(), period: WEEK, period_start: start, period_end: end, sources });
+    }
+    Ok(out)
+}
+
+/// Completed months ending `detail_days` or more before `now` whose stored
+/// weekly rollups (by week start) changed since their own was written.
+pub fn pending_months(conn: &Connection, project: &str, config: &RollupConfig, now: i64) -> Result<Vec<Period>, NmemError> {
+    let cutoff = now - config.detail_days as i64 * 86400;
+    let mut months: BTreeMap<(i64, i64), usize> = BTreeMap::new();
+    let mut stmt = conn.prepare("SELECT period_start FROM rollups WHERE project = ?1 AND period = 'week'")?;
+    for start in stmt.query_map(params![project], |r| r.get::<_, i64>(0))? {
+        *months.entry(month_bounds(start?)).or_default() += 1;
+    }
+
+    let mut out = Vec::new();
+    for ((start, end), sources) in months {
+        if end > cutoff || stored_count(conn, project, MONTH, start)? == Some(sources as i64) {
+            continue;
+        }
+        out.push(Period { project: project.to_string(), period: MONTH, period_start: start, period_end: end, sources });
+    }
+    Ok(out)
+}
+
+const ROLLUP_SYSTEM_PROMPT: &str = "You compress an AI coding agent's memory of one project. You are given everything recorded about a period of work, oldest first. Write one summary of the period that a later session can rely on instead of the individual entries.\n\nRules:\n- overview: two or three sentences on what the period was about and where it left things.\n- learned: decisions and conclusions that still matter, most durable first, at most 6. Later entries override earlier ones.\n- open_threads: work left unfinished at the end of the period. Drop anything a later entry completed.\n- All array fields MUST be JSON arrays of strings.\n\nReturn ONLY valid JSON. No markdown fences, no explanation.";
+
+/// The user prompt for `period` and the newest session it draws on, for
+/// usage accounting.
+fn rollup_prompt(conn: &Connection, period: &Period) -> Result<(String, Option<String>), NmemError> {
+    let mut out = format!(
+        "Return a JSON object with \"overview\", \"learned\", \"open_threads\".\n\nProject: {}\nPeriod: {}\n\n",
+        period.project,
+        label(period.period, period.period_start)
+    );
+    let newest: Option<String> = conn
+        .query_row(
+            "SELECT id FROM sessions WHERE project = ?1 AND started_at >= ?2 AND started_at < ?3
+             ORDER BY started_at DESC LIMIT 1",
+            params![period.project, period.period_start, period.period_end],
+            |r| r.get(0),
+        )
+        .optional()?;
+
+    if period.period == WEEK {
+        let sources = week_sources(conn, &period.project, period.period_start, period.period_end)?;
+        out.push_str("Sessions:\n");
+        let skip = sources.len().saturating_sub(MAX_PROMPT_SESSIONS);
+        for (_, at, text) in sources.iter().skip(skip) {
+            out.push_str(&format!("- [{}] {text}\n", crate::timefmt::date(*at)));
+        }
+    } else {
+        out.push_str("Weekly summaries:\n");
+        for week in stored(conn, &period.project, WEEK, period.period_start, period.period_end)? {
+            let s = &week.summary;
+            out.push_str(&format!("- [{}] {}\n", label(WEEK, week.period_start), s.overview));
+            if !s.learned.is_empty() {
+                out.push_str(&format!("  learned: {}\n", s.learned.join("; ")));
+            }
+            if !s.open_threads.is_empty() {
+                out.push_str(&format!("  open: {}\n", s.open_threads.join("; ")));
+            }
+        }
+    }
+    Ok((out, newest))
+}
+
+/// Stored rollups of `period` kind starting within `[from, to)`, oldest first.
+fn stored(conn: &Connection, project: &str, period: &str, from: i64, to: i64) -> Result<Vec<Rollup>, NmemError> {
+    let mut stmt = conn.prepare(
+        "SELECT period, period_start, period_end, source_count, summary FROM rollups
+         WHERE project = ?1 AND period = ?2 AND period_start >= ?3 AND period_start < ?4
+         ORDER BY period_start",
+    )?;
+    let rows = stmt
+        .query_map(params![project, period, from, to], row_to_rollup)?
+        .filter_map(Result::ok)
+        .collect();
+    Ok(rows)
+}
+
+fn row_to_rollup(r: &rusqlite::Row) -> rusqlite::Result<Rollup> {
+    let summary: String = r.get(4)?;
+    Ok(Rollup {
+        period: r.get(0)?,
+        period_start: r.get(1)?,
+        period_end: r.get(2)?,
+        source_count: r.get(3)?,
+        summary: serde_json::from_str(&summary).unwrap_or_default(),
+    })
+}
+
+/// Generate and store the rollup for `period`.
+pub fn compose(
+    conn: &Connection,
+    period: &Period,
+    config: &SummarizationConfig,
+    now: i64,
+) -> Result<RollupSummary, NmemError> {
+    let (user, newest) = rollup_prompt(conn, period)?;
+    let mut inference_params = crate::s1_4_inference::params_from_config(config)?;
+    inference_params.max_tokens = 768;
+    let result = crate::s1_4_inference::generate(&inference_params, ROLLUP_SYSTEM_PROMPT, &user)?;
+    if let Some(session_id) = newest
+        && let Err(e) = crate::s3_llm_usage::record(conn, crate::s3_llm_usage::ROLLUP, &session_id, None, &result, now)
+    {
+        log::warn!("llm usage not recorded: {e}");
+    }
+
+    let summary: RollupSummary =
+        serde_json::from_str(crate::s1_4_summarize::strip_fences(&result.text))
+            .map_err(|e| NmemError::Config(format!("rollup parse: {e}")))?;
+    store_rollup(conn, period, &summary, now)?;
+    Ok(summary)
+}
+
+/// Write `summary` for `period`, replacing an earlier one.
+pub fn store_rollup(conn: &Connection, period: &Period, summary: &RollupSummary, now: i64) -> Result<(), NmemError> {
+    conn.execute(
+        "INSERT INTO rollups (project, period, period_start, period_end, summary, source_count, created_at)
+         VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)
+         ON CONFLICT(project, period, period_start) DO UPDATE SET
+             period_end = excluded.period_end, summary = excluded.summary,
+             source_count = excluded.source_count, created_at = excluded.created_at",
+        params![
+            period.project,
+            period.period,
+            period.period_start,
+            period.period_end,
+            serde_json::to_string(summary)?,
+            period.sources as i64,
+            now
+        ],
+    )?;
+    Ok(())
+}
+
+/// Roll up every project (or just `project`): weeks first, then the months
+/// they feed. Skips LLM work when summarization is off or the monthly token
+/// budget is spent.
+pub fn rollup(
+    conn: &Connection,
+    project: Option<&str>,
+    config: &RollupConfig,
+    sum_config: &SummarizationConfig,
+    now: i64,
+) -> Result<Vec<Period>, NmemError> {
+    if !sum_config.enabled {
+        log::info!("summarization disabled in config — skipping rollups");
+        return Ok(Vec::new());
+    }
+    let mut done = Vec::new();
+    for project in projects(conn, project)? {
+        for pending in [pending_weeks as fn(&Connection, &str, &RollupConfig, i64) -> _, pending_months] {
+            for period in pending(conn, &project, config, now)? {
+                if crate::s3_llm_usage::over_budget(conn, sum_config, now)? {
+                    log::info!("monthly LLM token budget reached — rollups paused");
+                    return Ok(done);
+                }
+                match compose(conn, &period, sum_config, now) {
+                    Ok(_) => done.push(period),
+                    Err(e) => log::warn!("rollup failed (non-fatal): {e}"),
+                }
+            }
+        }
+    }
+    Ok(done)
+}
+
+fn projects(conn: &Connection, project: Option<&str>) -> Result<Vec<String>, NmemError> {
+    if let Some(p) = project {
+        return Ok(vec![p.to_string()]);
+    }
+    let rows = conn
+        .prepare("SELECT DISTINCT project FROM sessions WHERE kind = 'agent' ORDER BY project")?
+        .query_map([], |r| r.get(0))?
+        .collect::<Result<_, _>>()?;
+    Ok(rows)
+}
+
+/// Rollups for context injection, newest first: up to `CONTEXT_WEEKS`
+/// weekly rollups ending by `cutoff`, then up to `CONTEXT_MONTHS` monthly
+/// ones ending by the oldest of those weeks. With `before`, only rollups
+/// written by then.
+pub fn for_context(conn: &Connection, project: &str, cutoff: i64, before: Option<i64>) -> Result<Vec<Rollup>, NmemError> {
+    let mut stmt = conn.prepare(
+        "SELECT period, period_start, period_end, source_count, summary FROM rollups
+         WHERE project = ?1 AND period = ?2 AND period_end <= ?3
+           AND (?4 IS NULL OR created_at < ?4)
+         ORDER BY period_start DESC LIMIT ?5",
+    )?;
+    let mut out: Vec<Rollup> = stmt
+        .query_map(params![project, WEEK, cutoff, before, CONTEXT_WEEKS as i64], row_to_rollup)?
+        .collect::<Result<_, _>>()?;
+    let boundary = out.last().map_or(cutoff, |w| w.period_start);
+    let months: Vec<Rollup> = stmt
+        .query_map(params![project, MONTH, boundary, before, CONTEXT_MONTHS as i64], row_to_rollup)?
+        .collect::<Result<_, _>>()?;
+    out.extend(months);
+    Ok(out)
+}
+
+#[cfg(feature = "cli")]
+pub fn handle_rollup(db_path: &Path, args: &RollupArgs) -> Result<(), NmemError> {
+    let conn = open_db(db_path)?;
+    let config = load_config().unwrap_or_default();
+    let now = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs() as i64;
+
+    let periods = if args.dry_run {
+        // Months listed are those already due; weeks rolled up now may add more
+        let mut periods = Vec::new();
+        for project in projects(&conn, args.project.as_deref())? {
+            periods.extend(pending_weeks(&conn, &project, &config.rollup, now)?);
+            periods.extend(pending_months(&conn, &project, &config.rollup, now)?);
+        }
+        periods
+    } else {
+        rollup(&conn, args.project.as_deref(), &config.rollup, &config.summarization, now)?
+    };
+    println!("{}", serde_json::to_string_pretty(&periods)?);
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Monday 2026-09-07 00:00 UTC.
+    const MON: i64 = 1_788_739_200;
+    const DAY: i64 = 86400;
+
+    fn setup() -> Connection {
+        let mut conn = Connection::open_in_memory().unwrap();
+        crate::schema::MIGRATIONS.to_latest(&mut conn).unwrap();
+        conn
+    }
+
+    fn session(conn: &Connection, id: &str, project: &str, at: i64, intent: Option<&str>) {
+        let summary = intent.map(|i| {
+            serde_json::json!({"intent": i, "learned": ["uses WAL"], "completed": [], "next_steps": [],
+                               "files_read": [], "files_edited": [], "notes": null})
+            .to_string()
+        });
+        conn.execute(
+            "INSERT INTO sessions (id, project, started_at, summary) VALUES (?1, ?2, ?3, ?4)",
+            params![id, project, at, summary],
+        )
+        .unwrap();
+    }
+
+    fn summary(overview: &str) -> RollupSummary {
+        RollupSummary { overview: overview.into(), learned: vec!["keep WAL".into()], open_threads: vec![] }
+    }
+
+    #[test]
+    fn periods_are_utc_weeks_and_months() {
+        assert_eq!(label(WEEK, MON), "week of 2026-09-07");
+        assert_eq!(week_start(MON), MON);
+        assert_eq!(week_start(MON + 6 * DAY + 3600), MON);
+        assert_eq!(week_start(MON - 1), MON - 7 * DAY);
+        let (start, end) = month_bounds(MON);
+        assert_eq!((label(MONTH, start), label(MONTH, end)), ("2026-09".into(), "2026-10".into()));
+    }
+
+    #[test]
+    fn pending_weeks_need_enough_sessions_and_age() {
+        let conn = setup();
+        let config = RollupConfig::default();
+        session(&conn, "a", "p", MON + DAY, Some("fix the sweep"));
+        session(&conn, "b", "p", MON + 2 * DAY, None);
+        conn.execute(
+            "INSERT INTO work_units (session_id, started_at, intent, obs_count) VALUES ('b', ?1, 'tune the cache', 4)",
+            params![MON + 2 * DAY],
+        )
+        .unwrap();
+        session(&conn, "c", "p", MON + 8 * DAY, Some("lone session"));
+        session(&conn, "d", "p", MON + 9 * DAY, Some("empty session"));
+        session(&conn, "e", "other", MON + DAY, Some("elsewhere"));
+
+        // The first week has only just ended: still inside the detail window
+        assert!(pending_weeks(&conn, "p", &config, MON + 14 * DAY).unwrap().is_empty());
+
+        let now = MON + 40 * DAY;
+        let weeks = pending_weeks(&conn, "p", &config, now).unwrap();
+        assert_eq!(weeks.len(), 1, "one session (plus a sentinel) is not a week: {weeks:?}");
+        assert_eq!((weeks[0].period_start, weeks[0].sources), (MON, 2));
+        let (prompt, newest) = rollup_prompt(&conn, &weeks[0]).unwrap();
+        assert!(prompt.contains("fix the sweep") && prompt.contains("learned: uses WAL"));
+        assert!(prompt.contains("episodes: tune the cache") && !prompt.contains("elsewhere"));
+        assert_eq!(newest.as_deref(), Some("b"));
+
+        store_rollup(&conn, &weeks[0], &summary("sweep and cache work"), now).unwrap();
+        assert!(pending_weeks(&conn, "p", &config, now).unwrap().is_empty());
+        // A late summary changes the source count and recomposes the week
+        session(&conn, "f", "p", MON + 3 * DAY, Some("late summary"));
+        assert_eq!(pending_weeks(&conn, "p", &config, now).unwrap()[0].sources, 3);
+    }
+
+    #[test]
+    fn months_chain_from_stored_weeks_and_context_prefers_recent_weeks() {
+        let conn = setup();
+        let config = RollupConfig::default();
+        let week = |start: i64| Period { project: "p".into(), period: WEEK, period_start: start, period_end: start + 7 * DAY, sources: 2 };
+        // Three September weeks and the first October week
+        for (i, start) in [MON, MON + 7 * DAY, MON + 14 * DAY, MON + 28 * DAY].into_iter().enumerate() {
+            store_rollup(&conn, &week(start), &summary(&format!("week {i}")), MON + 60 * DAY).unwrap();
+        }
+        let now = MON + 70 * DAY;
+        let months = pending_months(&conn, "p", &config, now).unwrap();
+        let labels: Vec<_> = months.iter().map(|m| (label(MONTH, m.period_start), m.sources)).collect();
+        assert_eq!(labels, [("2026-09".to_string(), 3), ("2026-10".to_string(), 1)]);
+        let (prompt, _) = rollup_prompt(&conn, &months[0]).unwrap();
+        assert!(prompt.contains("[week of 2026-09-07] week 0") && !prompt.contains("week 3"));
+        for month in &months {
+            store_rollup(&conn, month, &summary("a month"), now).unwrap();
+        }
+        assert!(pending_months(&conn, "p", &config, now).unwrap().is_empty());
+        let (aug, _) = month_bounds(MON - 7 * DAY);
+        let august = Period { project: "p".into(), period: MONTH, period_start: aug, period_end: MON - 6 * DAY, sources: 4 };
+        store_rollup(&conn, &august, &summary("august"), now).unwrap();
+
+        // Weeks up to the cutoff, newest first; months only before the oldest week shown
+        let shown = for_context(&conn, "p", MON + 36 * DAY, None).unwrap();
+        let got: Vec<_> = shown.iter().map(|r| label(&r.period, r.period_start)).collect();
+        assert_eq!(got, ["week of 2026-10-05", "week of 2026-09-21", "week of 2026-09-14", "week of 2026-09-07", "2026-08"]);
+        let shown = for_context(&conn, "p", MON + 13 * DAY, None).unwrap();
+        let got: Vec<_> = shown.iter().map(|r| label(&r.period, r.period_start)).collect();
+        assert_eq!(got, ["week of 2026-09-07", "2026-08"]);
+        assert!(for_context(&conn, "p", now, Some(MON + 50 * DAY)).unwrap().is_empty(), "written after as_of");
+    }
+}
@@ -0,0 +1,232 @@
+//! S4 Intelligence — stance changes pushed as they happen.
+//!
+//! `current_stance` answers when asked; an agent deep in a task rarely
+//! asks at the moment its trajectory turns. `nmem serve` watches the
+//! database (`PRAGMA data_version` moves when a hook commits) and, for the
+//! newest agent session, compares what changed since the last look:
+//!
+//! - the stance's EMA crossed zero on either axis (`think+diverge` →
+//!   `act+converge`), at `notice`;
+//! - the last `FRICTION_STREAK` commands all failed, once per streak, at
+//!   `warning`;
+//! - a new episode began (the prompt's intent moved away), at `info`.
+//!
+//! Each becomes an MCP `notifications/message` from logger `nmem.stance`,
+//! sent only to clients that asked for log messages (`logging/setLevel`)
+//! at or below its level. The first look at a session sets the baseline
+//! without notifying.
+
+use crate::NmemError;
+use rusqlite::{Connection, OptionalExtension, params};
+use serde::Serialize;
+
+/// EMA smoothing, as `current_stance` defaults to.
+pub const ALPHA: f64 = 0.08;
+/// Consecutive failed commands that count as sustained friction.
+pub const FRICTION_STREAK: usize = 3;
+
+/// How loudly an event is logged.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Urgency {
+    Info,
+    Notice,
+    Warning,
+}
+
+#[derive(Debug, Serialize, PartialEq)]
+pub struct StanceEvent {
+    /// `stance_shift`, `sustained_friction`, or `episode_boundary`.
+    pub kind: &'static str,
+    pub session_id: String,
+    pub message: String,
+    #[serde(skip)]
+    pub urgency: Urgency,
+}
+
+/// The quadrant of an EMA point.
+pub fn label(phase: f64, scope: f64) -> &'static str {
+    match (phase >= 0.0, scope >= 0.0) {
+        (true, true) => "act+converge",
+        (true, false) => "act+diverge",
+        (false, true) => "think+converge",
+        (false, false) => "think+diverge",
+    }
+}
+
+/// Confidence-weighted EMA of (phase, scope) over a session's classified
+/// observations, or `None` before the first.
+pub fn ema(conn: &Connection, session_id: &str, alpha: f64) -> Result<Option<(f64, f64)>, NmemError> {
+    let mut stmt = conn.prepare_cached(
+        "SELECT phase = 'act', scope = 'converge', COALESCE(phase_confidence, 1.0), COALESCE(scope_confidence, 1.0)
+         FROM observations
+         WHERE session_id = ?1 AND phase IS NOT NULL AND scope IS NOT NULL
+         ORDER BY timestamp ASC",
+    )?;
+    let mut rows = stmt.query(params![session_id])?;
+    let mut point: Option<(f64, f64)> = None;
+    while let Some(row) = rows.next()? {
+        let sign = |positive: bool, confidence: f64| if positive { confidence } else { -confidence };
+        let phase = sign(row.get(0)?, row.get(2)?);
+        let scope = sign(row.get(1)?, row.get(3)?);
+        point = Some(match point {
+            None => (phase, scope),
+            Some((p, s)) => (alpha * phase + (1.0 - alpha) * p, alpha * scope + (1.0 - alpha) * s),
+        });
+    }
+    Ok(point)
+}
+
+/// Failed commands at the end of a session, back to back.
+fn failing_streak(conn: &Connection, session_id: &str) -> Result<usize, NmemError> {
+    let mut stmt = conn.prepare_cached(
+        "SELECT COALESCE(json_extract(metadata, '$.failed'), 0) = 1 FROM observations
+         WHERE session_id = ?1 AND obs_type = 'command'
+         ORDER BY id DESC LIMIT ?2",
+    )?;
+    let recent: Vec<bool> = stmt
+        .query_map(params![session_id, FRICTION_STREAK as i64], |r| r.get(0))?
+        .collect::<Result<_, _>>()?;
+    Ok(recent.iter().take_while(|failed| **failed).count())
+}
+
+/// What the last look at a session saw.
+#[derive(Debug, Default)]
+pub struct StanceWatch {
+    session_id: Option<String>,
+    stance: Option<&'static str>,
+    in_friction: bool,
+    episodes: usize,
+}
+
+impl StanceWatch {
+    /// Events since the last call, for the newest agent session.
+    pub fn check(&mut self, conn: &Connection) -> Result<Vec<StanceEvent>, NmemError> {
+        let session_id: Option<String> = conn
+            .query_row(
+                "SELECT id FROM sessions WHERE kind = 'agent' ORDER BY started_at DESC LIMIT 1",
+                [],
+                |r| r.get(0),
+            )
+            .optional()?;
+        let Some(session_id) = session_id else {
+            return Ok(Vec::new());
+        };
+        let baseline = self.session_id.as_deref() != Some(session_id.as_str());
+
+        let stance = ema(conn, &session_id, ALPHA)?.map(|(phase, scope)| label(phase, scope));
+        let in_friction = failing_streak(conn, &session_id)? >= FRICTION_STREAK;
+        let episodes = crate::s4_memory::detect_episodes(conn, &session_id)?;
+
+        let mut events = Vec::new();
+        if !baseline {
+            if let (Some(from), Some(to)) = (self.stance, stance)
+                && from != to
+            {
+                events.push(StanceEvent {
+                    kind: "stance_shift",
+                    session_id: session_id.clone(),
+                    message: format!("Stance shifted {from} → {to}. Call current_stance for retrieval guidance."),
+                    urgency: Urgency::Notice,
+                });
+            }
+            if in_friction && !self.in_friction {
+                events.push(StanceEvent {
+                    kind: "sustained_friction",
+                    session_id: session_id.clone(),
+                    message: format!(
+                        "The last {FRICTION_STREAK} commands failed. Check working_set for the failing commands, or step back and re-plan."
+                    ),
+                    urgency: Urgency::Warning,
+                });
+            }
+            if episodes.len() > self.episodes
+                && let Some(episode) = episodes.last()
+            {
+                events.push(StanceEvent {
+                    kind: "episode_boundary",
+                    session_id: session_id.clone(),
+                    message: format!("New episode: {}", episode.intent),
+                    urgency: Urgency::Info,
+                });
+            }
+        }
+
+        *self = StanceWatch { session_id: Some(session_id), stance, in_friction, episodes: episodes.len() };
+        Ok(events)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn setup() -> Connection {
+        let mut conn = Connection::open_in_memory().unwrap();
+        crate::schema::MIGRATIONS.to_latest(&mut conn).unwrap();
+        conn.execute("INSERT INTO sessions (id, project, started_at) VALUES ('s1', 'p', 0)", []).unwrap();
+        conn
+    }
+
+    fn observe(conn: &Connection, ts: i64, phase: &str, scope: &str, failed: bool) {
+        conn.execute(
+            "INSERT INTO observations (session_id, timestamp, obs_type, source_event, content, phase, scope, metadata)
+             VALUES ('s1', ?1, 'command', 'PostToolUse', 'cargo test', ?2, ?3, ?4)",
+            params![ts, phase, scope, failed.then_some(r#"{"failed":true}"#)],
+        )
+        .unwrap();
+    }
+
+    fn prompt(conn: &Connection, ts: i64, text: &str) {
+        conn.execute(
+            "INSERT INTO prompts (session_id, timestamp, source, content) VALUES ('s1', ?1, 'user', ?2)",
+            params![ts, text],
+        )
+        .unwrap();
+    }
+
+    fn kinds(events: &[StanceEvent]) -> Vec<&'static str> {
+        events.iter().map(|e| e.kind).collect()
+    }
+
+    #[test]
+    fn first_look_sets_the_baseline() {
+        let conn = setup();
+        let mut watch = StanceWatch::default();
+        assert!(watch.check(&conn).unwrap().is_empty());
+        observe(&conn, 1, "think", "diverge", true);
+        observe(&conn, 2, "think", "diverge", true);
+        observe(&conn, 3, "think", "diverge", true);
+        let mut fresh = StanceWatch::default();
+        assert!(fresh.check(&conn).unwrap().is_empty(), "already failing when first seen");
+    }
+
+    #[test]
+    fn reports_crossings_friction_streaks_and_new_episodes() {
+        let conn = setup();
+        prompt(&conn, 0, "read through the session storage layer first");
+        observe(&conn, 1, "think", "diverge", false);
+        let mut watch = StanceWatch::default();
+        assert!(watch.check(&conn).unwrap().is_empty());
+
+        // A run of confident act+converge pulls both EMAs across zero
+        for ts in 2..40 {
+            observe(&conn, ts, "act", "converge", false);
+        }
+        let events = watch.check(&conn).unwrap();
+        assert_eq!(kinds(&events), vec!["stance_shift"]);
+        assert!(events[0].message.contains("think+diverge → act+converge"), "{}", events[0].message);
+        assert!(watch.check(&conn).unwrap().is_empty(), "reported once");
+
+        for ts in 40..43 {
+            observe(&conn, ts, "act", "converge", true);
+        }
+        let events = watch.check(&conn).unwrap();
+        assert_eq!(kinds(&events), vec!["sustained_friction"]);
+        assert_eq!(events[0].urgency, Urgency::Warning);
+        observe(&conn, 43, "act", "converge", true);
+        assert!(watch.check(&conn).unwrap().is_empty(), "once per streak");
+
+        prompt(&conn, 50, "now write the release notes for version two");
+        assert_eq!(kinds(&watch.check(&conn).unwrap()), vec!["episode_boundary"]);
+    }
+}
@@ -0,0 +1,426 @@
+//! S4 Intelligence — named time windows.
+//!
+//! People segment work by sprint, release, or week, not by Unix timestamp.
+//! A window name resolves to a `[start, end)` range: a built-in (`today`,
+//! `yesterday`, `this-week`, `last-week`, `this-month`, `last-month`; weeks
+//! start on Monday, days in the display timezone) or a `[windows.<name>]`
+//! from config, which may repeat (`every = "2w"`: `sprint` is the current
+//! period, `sprint-42` the 42nd). MCP tools that take `before`/`after` take
+//! `window` too; `window_summary` rolls one up into the episodes, decisions,
+//! and failures inside it.
+
+use crate::s5_config::{NmemConfig, WindowConfig};
+use crate::timefmt::{civil_from_days, days_from_civil, days_from_date};
+use crate::NmemError;
+use rusqlite::{Connection, params};
+use serde::Serialize;
+use std::collections::BTreeMap;
+
+/// Windows every config has.
+pub const BUILTIN: [&str; 6] = ["today", "yesterday", "this-week", "last-week", "this-month", "last-month"];
+
+/// Most repeated failures listed in a summary.
+const TOP_FAILURES: i64 = 10;
+
+#[derive(Debug, Serialize, PartialEq)]
+pub struct Window {
+    pub name: String,
+    /// First second inside the window.
+    pub start: i64,
+    /// First second after it.
+    pub end: i64,
+}
+
+impl Window {
+    /// Tool filter bounds (`after` is exclusive) for the window, tightened
+    /// by explicit ones.
+    pub fn bounds(&self, after: Option<i64>, before: Option<i64>) -> (Option<i64>, Option<i64>) {
+        let after = after.map_or(self.start - 1, |a| a.max(self.start - 1));
+        let before = before.map_or(self.end, |b| b.min(self.end));
+        (Some(after), Some(before))
+    }
+}
+
+/// `This Week`, `this_week`, and `this-week` are the same window.
+fn normalize(name: &str) -> String {
+    name.trim().to_lowercase().replace([' ', '_'], "-")
+}
+
+fn period_secs(every: &str) -> Option<i64> {
+    crate::s4_dispatch::parse_relative(&every.trim().to_lowercase())
+}
+
+/// Config-time check of a `[windows.<name>]` entry.
+pub fn check(window: &WindowConfig) -> Result<(), String> {
+    let start = days_from_date(&window.start).ok_or("start: expected YYYY-MM-DD")?;
+    if let Some(end) = &window.end {
+        let end = days_from_date(end).ok_or("end: expected YYYY-MM-DD")?;
+        if end < start {
+            return Err("end is before start".into());
+        }
+    }
+    if let Some(every) = &window.every {
+        period_secs(every).ok_or("every: expected a duration like \"14d\" or \"2w\"")?;
+        if window.end.is_some() {
+            return Err("end and every don't combine: a repeating window has no end".into());
+        }
+    }
+    Ok(())
+}
+
+/// Every window name `config` knows, built-ins first.
+pub fn names(config: &NmemConfig) -> Vec<String> {
+    BUILTIN.iter().map(|n| n.to_string()).chain(config.windows.keys().cloned()).collect()
+}
+
+/// The window `name` refers to at `now`.
+pub fn resolve(config: &NmemConfig, name: &str, now: i64) -> Result<Window, NmemError> {
+    resolve_at(config, name, now, crate::timefmt::offset())
+}
+
+fn resolve_at(config: &NmemConfig, name: &str, now: i64, offset: i64) -> Result<Window, NmemError> {
+    let key = normalize(name);
+    let configured = |wanted: &str| config.windows.iter().find(|(n, _)| normalize(n) == wanted);
+    // Config first, so a name can shadow a built-in
+    if let Some((_, window)) = configured(&key) {
+        return from_config(key, window, None, now, offset);
+    }
+    if let Some((base, nth)) = key.rsplit_once('-')
+        && let Ok(nth) = nth.parse::<i64>()
+        && let Some((_, window)) = configured(base).filter(|(_, w)| w.every.is_some())
+    {
+        return from_config(key, window, Some(nth), now, offset);
+    }
+    builtin(&key, now, offset).ok_or_else(|| {
+        NmemError::Config(format!("unknown window {name:?} — known: {}", names(config).join(", ")))
+    })
+}
+
+fn from_config(name: String, window: &WindowConfig, nth: Option<i64>, now: i64, offset: i64) -> Result<Window, NmemError> {
+    let day = |date: &str| {
+        days_from_date(date)
+            .map(|d| d * 86400 - offset)
+            .ok_or_else(|| NmemError::Config(format!("windows.{name}: {date:?} is not YYYY-MM-DD")))
+    };
+    let start = day(&window.start)?;
+    let Some(every) = &window.every else {
+        let end = match &window.end {
+            Some(end) => day(end)? + 86400,
+            // Still open: everything up to now
+            None => now.max(start) + 1,
+        };
+        return Ok(Window { name, start, end });
+    };
+    let period = period_secs(every)
+        .ok_or_else(|| NmemError::Config(format!("windows.{name}: every {every:?} is not a duration")))?;
+    let index = match nth {
+        Some(n) if n >= 1 => n - 1,
+        Some(_) => return Err(NmemError::Config(format!("{name}: periods count from 1"))),
+        None if now < start => {
+            return Err(NmemError::Config(format!("{name}: first period starts {}", window.start)));
+        }
+        None => (now - start) / period,
+    };
+    let start = index
+        .checked_mul(period)
+        .and_then(|skip| start.checked_add(skip))
+        .filter(|s| s.checked_add(period).is_some())
+        .ok_or_else(|| NmemError::Config(format!("{name}: period out of range")))?;
+    Ok(Window { name, start, end: start + period })
+}
+
+fn builtin(name: &str, now: i64, offset: i64) -> Option<Window> {
+    let today = (now + offset).div_euclid(86400);
+    // 1970-01-01 was a Thursday
+    let monday = today - (today + 3).rem_euclid(7);
+    let (year, month, _) = civil_from_days(today);
+    let month_start = days_from_civil(year, month, 1);
+    let (from, to) = match name {
+        "today" => (today, today + 1),
+        "yesterday" => (today - 1, today),
+        "this-week" => (monday, monday + 7),
+        "last-week" => (monday - 7, monday),
+        "this-month" => {
+            let (next_year, next_month) = if month == 12 { (year + 1, 1) } else { (year, month + 1) };
+            (month_start, days_from_civil(next_year, next_month, 1))
+        }
+        "last-month" => {
+            let (prev_year, prev_month) = if month == 1 { (year - 1, 12) } else { (year, month - 1) };
+            (days_from_civil(prev_year, prev_month, 1), month_start)
+        }
+        _ => return None,
+    };
+    Some(Window { name: name.to_string(), start: from * 86400 - offset, end: to * 86400 - offset })
+}
+
+#[derive(Debug, Serialize)]
+pub struct WindowEpisode {
+    pub session_id: String,
+    pub project: Option<String>,
+    pub started_at: i64,
+    /// The narrative's intent when summarized, else the episode's own.
+    pub intent: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub outcome: Option<String>,
+}
+
+/// A marker, or a `learned` item of an episode narrative.
+#[derive(Debug, Serialize)]
+pub struct Decision {
+    pub timestamp: i64,
+    pub session_id: String,
+    pub text: String,
+    /// `marker` or `episode`.
+    pub source: &'static str,
+    /// The marker's id.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub id: Option<i64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub status: Option<String>,
+}
+
+/// One failing command or tool call and how often it failed.
+#[derive(Debug, Serialize)]
+pub struct RepeatedFailure {
+    pub content: String,
+    pub count: i64,
+    pub last_at: i64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error_class: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct Failures {
+    pub total: i64,
+    pub by_class: BTreeMap<String, i64>,
+    pub top: Vec<RepeatedFailure>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct WindowSummary {
+    pub window: Window,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub project: Option<String>,
+    pub sessions: i64,
+    pub observations: i64,
+    pub episodes: Vec<WindowEpisode>,
+    pub decisions: Vec<Decision>,
+    pub failures: Failures,
+}
+
+/// Episodes started, decisions recorded, and failures hit inside `window`,
+/// for `project` or all projects. Episodes and decisions are listed oldest
+/// first, at most `limit` of each.
+pub fn summarize(conn: &Connection, window: Window, project: Option<&str>, limit: i64) -> Result<WindowSummary, NmemError> {
+    let (start, end) = (window.start, window.end);
+    let sessions: i64 = conn.query_row(
+        "SELECT COUNT(*) FROM sessions WHERE started_at >= ?1 AND started_at < ?2 AND (?3 IS NULL OR project = ?3)",
+        params![start, end, project],
+        |r| r.get(0),
+    )?;
+    let observations: i64 = conn.query_row(
+        "SELECT COUNT(*) FROM observations o JOIN sessions s ON s.id = o.session_id
+         WHERE o.timestamp >= ?1 AND o.timestamp < ?2 AND (?3 IS NULL OR s.project = ?3)",
+        params![start, end, project],
+        |r| r.get(0),
+    )?;
+
+    let mut stmt = conn.prepare(
+        "SELECT w.session_id, s.project, w.started_at,
+                COALESCE(CASE WHEN json_valid(w.summary) THEN json_extract(w.summary, '$.intent') END, w.intent),
+                w.outcome,
+                CASE WHEN json_valid(w.summary) THEN json_extract(w.summary, '$.learned') END
+         FROM work_units w JOIN sessions s ON s.id = w.session_id
+         WHERE w.started_at >= ?1 AND w.started_at < ?2 AND (?3 IS NULL OR s.project = ?3)
+         ORDER BY w.started_at, w.id LIMIT ?4",
+    )?;
+    let mut episodes = Vec::new();
+    let mut decisions = Vec::new();
+    let rows = stmt.query_map(params![start, end, project, limit], |r| {
+        Ok((
+            WindowEpisode {
+                session_id: r.get(0)?,
+                project: r.get(1)?,
+                started_at: r.get(2)?,
+                intent: r.get(3)?,
+                outcome: r.get(4)?,
+            },
+            r.get::<_, Option<String>>(5)?,
+        ))
+    })?;
+    for row in rows {
+        let (episode, learned) = row?;
+        let learned: Vec<String> = match learned.and_then(|l| serde_json::from_str(&l).ok()) {
+            Some(serde_json::Value::Array(items)) => {
+                items.iter().filter_map(|v| v.as_str().map(String::from)).collect()
+            }
+            Some(serde_json::Value::String(item)) => vec![item],
+            _ => Vec::new(),
+        };
+        decisions.extend(learned.into_iter().map(|text| Decision {
+            timestamp: episode.started_at,
+            session_id: episode.session_id.clone(),
+            text,
+            source: "episode",
+            id: None,
+            status: None,
+        }));
+        episodes.push(episode);
+    }
+
+    let mut stmt = conn.prepare(
+        "SELECT o.id, o.timestamp, o.session_id, o.content, json_extract(o.metadata, '$.status')
+         FROM observations o JOIN sessions s ON s.id = o.session_id
+         WHERE o.obs_type = 'marker' AND o.timestamp >= ?1 AND o.timestamp < ?2
+           AND (?3 IS NULL OR s.project = ?3)
+         ORDER BY o.timestamp, o.id LIMIT ?4",
+    )?;
+    let markers = stmt.query_map(params![start, end, project, limit], |r| {
+        Ok(Decision {
+            id: Some(r.get(0)?),
+            timestamp: r.get(1)?,
+            session_id: r.get(2)?,
+            text: r.get(3)?,
+            source: "marker",
+            status: r.get(4)?,
+        })
+    })?;
+    for marker in markers {
+        decisions.push(marker?);
+    }
+    decisions.sort_by_key(|d| d.timestamp);
+    decisions.truncate(limit.max(0) as usize);
+
+    let failed = "FROM observations o JOIN sessions s ON s.id = o.session_id
+         WHERE json_extract(o.metadata, '$.failed') = 1 AND o.timestamp >= ?1 AND o.timestamp < ?2
+           AND (?3 IS NULL OR s.project = ?3)";
+    let mut stmt = conn.prepare(&format!("SELECT COALESCE(o.error_class, 'unclassified'), COUNT(*) {failed} GROUP BY 1"))?;
+    let by_class: BTreeMap<String, i64> = stmt
+        .query_map(params![start, end, project], |r| Ok((r.get(0)?, r.get(1)?)))?
+        .collect::<Result<_, _>>()?;
+    let mut stmt = conn.prepare(&format!(
+        "SELECT substr(o.content, 1, 200), COUNT(*), MAX(o.timestamp), MAX(o.error_class) {failed}
+         GROUP BY 1 ORDER BY 2 DESC, 3 DESC LIMIT ?4"
+    ))?;
+    let top = stmt
+        .query_map(params![start, end, project, TOP_FAILURES], |r| {
+            Ok(RepeatedFailure { content: r.get(0)?, count: r.get(1)?, last_at: r.get(2)?, error_class: r.get(3)? })
+        })?
+        .collect::<Result<_, _>>()?;
+
+    Ok(WindowSummary {
+        window,
+        project: project.map(String::from),
+        sessions,
+        observations,
+        episodes,
+        decisions,
+        failures: Failures { total: by_class.values().sum(), by_class, top },
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const NOW: i64 = 1_792_159_380; // Fri 2026-10-16 14:03 UTC
+
+    fn config(toml: &str) -> NmemConfig {
+        toml::from_str(toml).unwrap()
+    }
+
+    fn span(w: &Window) -> (String, String) {
+        let date = |ts: i64| {
+            let (y, m, d) = civil_from_days(ts.div_euclid(86400));
+            format!("{y:04}-{m:02}-{d:02}")
+        };
+        (date(w.start), date(w.end))
+    }
+
+    #[test]
+    fn builtins_follow_the_calendar() {
+        let config = NmemConfig::default();
+        let at = |name: &str| span(&resolve_at(&config, name, NOW, 0).unwrap());
+        assert_eq!(at("today"), ("2026-10-16".into(), "2026-10-17".into()));
+        assert_eq!(at("This Week"), ("2026-10-12".into(), "2026-10-19".into()));
+        assert_eq!(at("last_week"), ("2026-10-05".into(), "2026-10-12".into()));
+        assert_eq!(at("this-month"), ("2026-10-01".into(), "2026-11-01".into()));
+        assert_eq!(at("last-month"), ("2026-09-01".into(), "2026-10-01".into()));
+
+        // Days begin at local midnight
+        let today = resolve_at(&config, "today", NOW, 2 * 3600).unwrap();
+        assert_eq!(today.start, days_from_civil(2026, 10, 16) * 86400 - 2 * 3600);
+        assert!(resolve_at(&config, "next-week", NOW, 0).is_err());
+    }
+
+    #[test]
+    fn configured_windows_and_repeating_periods() {
+        let config = config(
+            r#"
+            [windows.release-3]
+            start = "2026-09-01"
+            end = "2026-09-30"
+
+            [windows.sprint]
+            start = "2026-01-05"
+            every = "2w"
+            "#,
+        );
+        let at = |name: &str| span(&resolve_at(&config, name, NOW, 0).unwrap());
+        assert_eq!(at("release-3"), ("2026-09-01".into(), "2026-10-01".into()), "end day is inclusive");
+        assert_eq!(at("sprint"), ("2026-10-12".into(), "2026-10-26".into()));
+        assert_eq!(at("sprint-1"), ("2026-01-05".into(), "2026-01-19".into()));
+        assert_eq!(at("Sprint 21"), at("sprint"));
+        assert!(resolve_at(&config, "sprint-0", NOW, 0).is_err());
+        assert!(resolve_at(&config, "release-3-2", NOW, 0).is_err(), "release-3 doesn't repeat");
+
+        let window = resolve_at(&config, "release-3", NOW, 0).unwrap();
+        assert_eq!(window.bounds(None, None), (Some(window.start - 1), Some(window.end)));
+        assert_eq!(window.bounds(Some(window.start + 10), Some(NOW)), (Some(window.start + 10), Some(window.end)));
+
+        let bad = |toml: &str| check(&config_window(toml)).unwrap_err();
+        assert!(bad(r#"start = "2026-9-31""#).contains("start"));
+        assert!(bad(r#"start = "2026-10-01"
+end = "2026-09-01""#).contains("before"));
+        assert!(bad(r#"start = "2026-10-01"
+every = "fortnightly""#).contains("every"));
+    }
+
+    fn config_window(toml: &str) -> WindowConfig {
+        toml::from_str(toml).unwrap()
+    }
+
+    #[test]
+    fn summary_gathers_episodes_decisions_and_failures() {
+        let mut conn = Connection::open_in_memory().unwrap();
+        crate::schema::MIGRATIONS.to_latest(&mut conn).unwrap();
+        conn.execute_batch(
+            r#"INSERT INTO sessions (id, project, started_at) VALUES ('s1', 'api', 1000), ('s2', 'web', 1100), ('old', 'api', 10);
+             INSERT INTO work_units (session_id, started_at, intent, summary, outcome) VALUES
+                ('s1', 1000, 'fix login', '{"intent":"Fixed the login timeout","learned":["raise the timeout to 30s"]}', 'succeeded'),
+                ('s2', 1100, 'restyle header', NULL, NULL),
+                ('old', 10, 'ancient work', NULL, NULL);
+             INSERT INTO observations (session_id, timestamp, obs_type, source_event, content, metadata, error_class) VALUES
+                ('s1', 1010, 'marker', 'AgentMarker', 'keep sessions in redis', '{"status":"accepted"}', NULL),
+                ('s1', 1020, 'command', 'PostToolUse', 'cargo test', '{"failed":true}', 'test_failure'),
+                ('s1', 1030, 'command', 'PostToolUse', 'cargo test', '{"failed":true}', 'test_failure'),
+                ('s1', 1040, 'command', 'PostToolUse', 'curl localhost', '{"failed":true}', NULL),
+                ('old', 20, 'command', 'PostToolUse', 'make', '{"failed":true}', NULL);"#,
+        )
+        .unwrap();
+        let window = || Window { name: "w".into(), start: 1000, end: 2000 };
+
+        let all = summarize(&conn, window(), None, 30).unwrap();
+        assert_eq!((all.sessions, all.observations, all.episodes.len()), (2, 4, 2));
+        assert_eq!(all.episodes[0].intent.as_deref(), Some("Fixed the login timeout"));
+        let texts: Vec<(&str, &str)> = all.decisions.iter().map(|d| (d.source, d.text.as_str())).collect();
+        assert_eq!(texts, vec![("episode", "raise the timeout to 30s"), ("marker", "keep sessions in redis")]);
+        assert_eq!(all.failures.total, 3);
+        assert_eq!(all.failures.by_class.get("test_failure"), Some(&2));
+        assert_eq!(all.failures.by_class.get("unclassified"), Some(&1));
+        assert_eq!((all.failures.top[0].content.as_str(), all.failures.top[0].count), ("cargo test", 2));
+
+        let web = summarize(&conn, window(), Some("web"), 30).unwrap();
+        assert_eq!((web.sessions, web.episodes.len(), web.decisions.len(), web.failures.total), (1, 1, 0, 0));
+    }
+}
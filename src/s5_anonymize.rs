@@ -0,0 +1,175 @@
+//! S5 Policy — home paths out of what leaves the machine.
+//!
+//! Absolute paths carry usernames: `/home/alice/src/api/auth.rs`. Whatever
+//! is read by someone other than the owner — `nmem changes` feeds, beacon
+//! replies to peers, another project's activity in injected context — goes
+//! through a `PathMap` first. `[filter] anonymize_paths` picks the form:
+//!
+//! - `home` (default): the owner's home becomes `~`, other users' homes
+//!   `~user1`, `~user2`, … in order of first appearance;
+//! - `project`: a path inside a known project becomes project-relative
+//!   (`api/auth.rs`), anything else falls back to `home`;
+//! - `off`: paths go out as recorded.
+//!
+//! The mapping is stable for the life of one `PathMap` — one export, one
+//! reply, one context render — so the same path always maps the same way
+//! and rows still join on it. The owner's own views (MCP tools, this
+//! project's context) keep real paths; `nmem changes --raw-paths` opts out
+//! for a trusted consumer.
+
+use crate::NmemError;
+use regex::Regex;
+use rusqlite::Connection;
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::sync::LazyLock;
+
+/// How `[filter] anonymize_paths` rewrites absolute home paths.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum PathAnonymization {
+    #[default]
+    Home,
+    Project,
+    Off,
+}
+
+/// A home directory and the rest of an absolute path under it.
+static HOME_PATH: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(r#"(?:/home|/Users)/[A-Za-z0-9._-]+((?:/[^\s"'`<>|:;,()\[\]{}]*)?)"#).unwrap()
+});
+
+/// Rewrites home paths for one export.
+pub struct PathMap {
+    mode: PathAnonymization,
+    /// The owner's home, mapped to `~`.
+    home: Option<String>,
+    projects: Vec<String>,
+    /// Other homes seen so far, and their aliases.
+    others: HashMap<String, String>,
+}
+
+impl PathMap {
+    pub fn new(mode: PathAnonymization, home: Option<&str>, projects: Vec<String>) -> Self {
+        let home = home.map(|h| h.trim_end_matches('/').to_string()).filter(|h| !h.is_empty());
+        PathMap { mode, home, projects, others: HashMap::new() }
+    }
+
+    /// A map for the running user, knowing every project in the database.
+    pub fn for_db(conn: &Connection, mode: PathAnonymization) -> Result<Self, NmemError> {
+        let mut stmt = conn.prepare("SELECT DISTINCT project FROM sessions WHERE project IS NOT NULL")?;
+        let projects = stmt.query_map([], |r| r.get(0))?.collect::<Result<_, _>>()?;
+        Ok(PathMap::new(mode, std::env::var("HOME").ok().as_deref(), projects))
+    }
+
+    pub fn is_off(&self) -> bool {
+        self.mode == PathAnonymization::Off
+    }
+
+    /// `text` with every home path in it rewritten.
+    pub fn text(&mut self, text: &str) -> String {
+        if self.is_off() {
+            return text.to_string();
+        }
+        // The owner's home may sit outside /home and /Users (`/root`)
+        let owned;
+        let text = match &self.home {
+            Some(home) if !HOME_PATH.is_match(home) && text.contains(home.as_str()) => {
+                owned = replace_home(text, home);
+                owned.as_str()
+            }
+            _ => text,
+        };
+        HOME_PATH
+            .replace_all(text, |caps: &regex::Captures| {
+                let (whole, rest) = (&caps[0], &caps[1]);
+                let home = &whole[..whole.len() - rest.len()];
+                if self.mode == PathAnonymization::Project
+                    && let Some(relative) = self.project_relative(rest)
+                {
+                    return relative;
+                }
+                format!("{}{rest}", self.alias(home))
+            })
+            .into_owned()
+    }
+
+    /// Rewrite every string in `value`, in place.
+    pub fn json(&mut self, value: &mut serde_json::Value) {
+        if self.is_off() {
+            return;
+        }
+        match value {
+            serde_json::Value::String(s) => *s = self.text(s),
+            serde_json::Value::Array(items) => items.iter_mut().for_each(|v| self.json(v)),
+            serde_json::Value::Object(map) => map.values_mut().for_each(|v| self.json(v)),
+            _ => {}
+        }
+    }
+
+    fn alias(&mut self, home: &str) -> String {
+        if self.home.as_deref() == Some(home) {
+            return "~".into();
+        }
+        let next = self.others.len() + 1;
+        self.others.entry(home.to_string()).or_insert_with(|| format!("~user{next}")).clone()
+    }
+
+    /// `rest` (a path below a home) from its first segment naming a known
+    /// project on.
+    fn project_relative(&self, rest: &str) -> Option<String> {
+        let segments: Vec<&str> = rest.split('/').collect();
+        let start = segments.iter().position(|s| !s.is_empty() && self.projects.iter().any(|p| p == s))?;
+        Some(segments[start..].join("/"))
+    }
+}
+
+/// `text` with `home` at a path boundary replaced by `~`.
+fn replace_home(text: &str, home: &str) -> String {
+    let mut out = String::with_capacity(text.len());
+    let mut rest = text;
+    while let Some(at) = rest.find(home) {
+        let after = &rest[at + home.len()..];
+        out.push_str(&rest[..at]);
+        if after.is_empty() || after.starts_with(['/', ' ', '"', '\'', '\n']) {
+            out.push('~');
+        } else {
+            out.push_str(home);
+        }
+        rest = after;
+    }
+    out.push_str(rest);
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn homes_map_to_stable_aliases() {
+        let mut map = PathMap::new(PathAnonymization::Home, Some("/home/alice"), vec![]);
+        assert_eq!(map.text("edited /home/alice/src/api/auth.rs"), "edited ~/src/api/auth.rs");
+        assert_eq!(map.text("cd /Users/bob/work && ls /home/carol"), "cd ~user1/work && ls ~user2");
+        assert_eq!(map.text("/Users/bob/work/x.rs"), "~user1/work/x.rs", "same home, same alias");
+        assert_eq!(map.text("/homework/notes.txt"), "/homework/notes.txt");
+
+        let mut root = PathMap::new(PathAnonymization::Home, Some("/root"), vec![]);
+        assert_eq!(root.text("cat /root/.bashrc /rootfs/x"), "cat ~/.bashrc /rootfs/x");
+    }
+
+    #[test]
+    fn project_mode_keeps_paths_joinable_inside_projects() {
+        let mut map = PathMap::new(PathAnonymization::Project, Some("/home/alice"), vec!["api".into()]);
+        assert_eq!(map.text("/home/alice/work/api/src/auth.rs"), "api/src/auth.rs");
+        assert_eq!(map.text("/home/bob/api/src/auth.rs"), "api/src/auth.rs");
+        assert_eq!(map.text("/home/alice/notes.md"), "~/notes.md");
+
+        let mut value = serde_json::json!({"file_path": "/home/alice/work/api/lib.rs", "count": 2, "hot": ["/home/dave/x"]});
+        map.json(&mut value);
+        assert_eq!(value, serde_json::json!({"file_path": "api/lib.rs", "count": 2, "hot": ["~user1/x"]}));
+
+        let mut off = PathMap::new(PathAnonymization::Off, Some("/home/alice"), vec![]);
+        assert_eq!(off.text("/home/alice/x"), "/home/alice/x");
+    }
+}
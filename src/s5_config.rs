@@ -3,8 +3,8 @@ use crate::s5_project::ProjectStrategy;
 use crate::NmemError;
 use regex::Regex;
 use serde::Deserialize;
-use std::collections::HashMap;
-use std::path::PathBuf;
+use std::collections::{BTreeMap, HashMap};
+use std::path::{Path, PathBuf};
 
 #[derive(Debug, Deserialize, Default)]
 pub struct NmemConfig {
@@ -26,6 +26,694 @@ pub struct NmemConfig {
     pub lsp: LspConfig,
     #[serde(default)]
     pub beacon: BeaconConfig,
+    #[serde(default)]
+    pub scoring: ScoringConfig,
+    #[serde(default)]
+    pub auto_pin: AutoPinConfig,
+    #[serde(default)]
+    pub query_log: QueryLogConfig,
+    #[serde(default)]
+    pub deletions_log: DeletionsLogConfig,
+    #[serde(default)]
+    pub context: ContextConfig,
+    #[serde(default)]
+    pub serve: ServeConfig,
+    #[serde(default)]
+    pub record: RecordConfig,
+    #[serde(default)]
+    pub capture: CaptureConfig,
+    #[serde(default)]
+    pub consolidate: ConsolidateConfig,
+    #[serde(default)]
+    pub rollup: RollupConfig,
+    #[serde(default)]
+    pub learn: LearnConfig,
+    #[serde(default)]
+    pub display: DisplayConfig,
+    #[serde(default)]
+    pub language: LanguageConfig,
+    #[serde(default)]
+    pub migration: MigrationConfig,
+    #[serde(default)]
+    pub packs: BTreeMap<String, PackConfig>,
+    #[serde(default)]
+    pub windows: BTreeMap<String, WindowConfig>,
+    #[serde(default)]
+    pub update: UpdateConfig,
+    #[serde(default)]
+    pub schedule: ScheduleConfig,
+    #[serde(default)]
+    pub diagnostics: DiagnosticsConfig,
+}
+
+pub const DEFAULT_KEEP_BACKUPS: usize = 3;
+
+/// Schema migration safety (`s3_migrate`).
+#[derive(Debug, Deserialize)]
+pub struct MigrationConfig {
+    /// Pre-migration backups kept in `backups/` beside the database
+    /// (default: 3). 0 disables them; risky migrations are still rehearsed.
+    #[serde(default = "default_keep_backups")]
+    pub keep_backups: usize,
+}
+
+fn default_keep_backups() -> usize {
+    DEFAULT_KEEP_BACKUPS
+}
+
+impl Default for MigrationConfig {
+    fn default() -> Self {
+        Self { keep_backups: DEFAULT_KEEP_BACKUPS }
+    }
+}
+
+/// How human-facing output renders times (see `timefmt`).
+#[derive(Debug, Deserialize, Default)]
+pub struct DisplayConfig {
+    /// `local` (default), `utc`, or a fixed offset like `+05:30`.
+    pub timezone: Option<String>,
+}
+
+/// Keyword extraction for non-English prompts (see `s1_lang`).
+#[derive(Debug, Deserialize, Default)]
+pub struct LanguageConfig {
+    /// Extra stopwords per language code (`de = ["halt", "eben"]`), added to
+    /// the built-in lists; an unknown code becomes a detectable language.
+    #[serde(default)]
+    pub stopwords: BTreeMap<String, Vec<String>>,
+}
+
+/// Slow-statement log behind `nmem maintain --advise-indexes` (`s3_slow_query`).
+#[derive(Debug, Deserialize)]
+pub struct DiagnosticsConfig {
+    /// Statements slower than this are logged with their query plan; 0
+    /// disables the log.
+    #[serde(default = "default_slow_query_ms")]
+    pub slow_query_ms: u64,
+}
+
+fn default_slow_query_ms() -> u64 {
+    250
+}
+
+impl Default for DiagnosticsConfig {
+    fn default() -> Self {
+        Self { slow_query_ms: default_slow_query_ms() }
+    }
+}
+
+/// When heavy background work may run (`s3_schedule`).
+#[derive(Debug, Deserialize, Default)]
+pub struct ScheduleConfig {
+    /// Local windows (`"09:00-18:00"`, `"22:00-06:00"`) in which
+    /// summarization, sweeps, and dispatch wait.
+    #[serde(default)]
+    pub quiet_hours: Vec<String>,
+    /// Also wait while running on battery (Linux and macOS).
+    #[serde(default)]
+    pub defer_on_battery: bool,
+    /// Processes holding an LLM at once; 0 (default) is unlimited.
+    #[serde(default)]
+    pub max_llm_calls: usize,
+}
+
+/// Release channel for `nmem self-update` and `nmem version --check`.
+#[derive(Debug, Deserialize, Default)]
+pub struct UpdateConfig {
+    /// URL of the release manifest, `https://` or `file://`. Unset: no
+    /// update checks.
+    pub endpoint: Option<String>,
+}
+
+/// `nmem learn` severities for CI (json and sarif reports), and the
+/// cross-project window for environment issues.
+#[derive(Debug, Deserialize)]
+pub struct LearnConfig {
+    /// Severity per pattern kind: `error`, `warning`, or `note`. Unset kinds
+    /// keep the built-ins (stuck loops are errors, repeated failures and
+    /// recurring errors warnings, the rest notes).
+    #[serde(default)]
+    pub severities: HashMap<String, String>,
+    /// Exit non-zero when a pattern is at or above this severity. Unset: never.
+    pub fail_on: Option<String>,
+    /// An error signature failing in at least this many projects is an
+    /// environment issue (default: 2).
+    #[serde(default = "default_environment_projects")]
+    pub environment_projects: usize,
+    /// Window, in hours back from now, searched for environment issues
+    /// (default: 24).
+    #[serde(default = "default_environment_window_hours")]
+    pub environment_window_hours: u64,
+    /// A test failing in at least this many sessions is called out in
+    /// context (default: 3; 0 = never).
+    #[serde(default = "default_recurring_test_sessions")]
+    pub recurring_test_sessions: u32,
+}
+
+fn default_recurring_test_sessions() -> u32 {
+    3
+}
+
+fn default_environment_projects() -> usize {
+    2
+}
+
+fn default_environment_window_hours() -> u64 {
+    24
+}
+
+impl Default for LearnConfig {
+    fn default() -> Self {
+        Self {
+            severities: HashMap::new(),
+            fail_on: None,
+            environment_projects: default_environment_projects(),
+            environment_window_hours: default_environment_window_hours(),
+            recurring_test_sessions: default_recurring_test_sessions(),
+        }
+    }
+}
+
+/// Topic consolidation (`nmem consolidate`, S4).
+#[derive(Debug, Deserialize)]
+pub struct ConsolidateConfig {
+    /// Also consolidate on the periodic `nmem maintain` run (default: false).
+    #[serde(default)]
+    pub enabled: bool,
+    /// Only episodes started within this many days are clustered (default: 90).
+    #[serde(default = "default_consolidate_window_days")]
+    pub window_days: u32,
+}
+
+fn default_consolidate_window_days() -> u32 {
+    90
+}
+
+impl Default for ConsolidateConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            window_days: default_consolidate_window_days(),
+        }
+    }
+}
+
+/// Weekly and monthly rollups (`nmem rollup`, S4).
+#[derive(Debug, Deserialize)]
+pub struct RollupConfig {
+    /// Also roll up on the periodic `nmem maintain` run (default: false).
+    #[serde(default)]
+    pub enabled: bool,
+    /// Sessions this recent stay detailed in context; only periods ending
+    /// earlier are rolled up and shown as rollups (default: 14).
+    #[serde(default = "default_rollup_detail_days")]
+    pub detail_days: u32,
+    /// A week needs this many sessions to get a rollup (default: 2).
+    #[serde(default = "default_rollup_min_sessions")]
+    pub min_sessions: u32,
+}
+
+fn default_rollup_detail_days() -> u32 {
+    14
+}
+
+fn default_rollup_min_sessions() -> u32 {
+    2
+}
+
+impl Default for RollupConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            detail_days: default_rollup_detail_days(),
+            min_sessions: default_rollup_min_sessions(),
+        }
+    }
+}
+
+/// Hook-side capture (`nmem record`).
+#[derive(Debug, Deserialize)]
+pub struct RecordConfig {
+    /// Wall-clock budget per hook invocation in ms (default: 50; 0 = none).
+    /// Classification and rename detection that don't finish in time are
+    /// queued in `pending_work` for the next hook or `nmem maintain`.
+    #[serde(default = "default_latency_budget_ms")]
+    pub latency_budget_ms: u64,
+    /// Characters of the assistant's final response kept per turn from the
+    /// Stop hook's transcript tail (default: 2000; 0 = don't store).
+    #[serde(default = "default_response_chars")]
+    pub response_chars: usize,
+    /// Flag sessions whose stored capture passes this many MB in `nmem
+    /// status --sessions` (default: 10; 0 = never).
+    #[serde(default = "default_session_alert_mb")]
+    pub session_alert_mb: u64,
+    /// Observation content longer than this many characters is stored as
+    /// separately indexed chunks (default: 1000; 0 = never split).
+    #[serde(default = "default_chunk_chars")]
+    pub chunk_chars: usize,
+    /// Scan a project's directory on its first session and inject the
+    /// snapshot as context (default: true).
+    #[serde(default = "default_true")]
+    pub onboarding: bool,
+    /// Fold a command identical to the session's previous observation into
+    /// it, counting runs instead of storing each (default: true).
+    #[serde(default = "default_true")]
+    pub collapse_repeats: bool,
+    /// Note an earlier episode in the project when a prompt's keywords
+    /// overlap its intent at least this much (Jaccard; default: 0.6; 0 = off).
+    #[serde(default = "default_similar_prompt")]
+    pub similar_prompt: f64,
+}
+
+fn default_latency_budget_ms() -> u64 {
+    50
+}
+
+fn default_response_chars() -> usize {
+    2000
+}
+
+fn default_session_alert_mb() -> u64 {
+    10
+}
+
+fn default_chunk_chars() -> usize {
+    1000
+}
+
+fn default_similar_prompt() -> f64 {
+    0.6
+}
+
+impl Default for RecordConfig {
+    fn default() -> Self {
+        Self {
+            latency_budget_ms: default_latency_budget_ms(),
+            response_chars: default_response_chars(),
+            session_alert_mb: default_session_alert_mb(),
+            chunk_chars: default_chunk_chars(),
+            onboarding: true,
+            collapse_repeats: true,
+            similar_prompt: default_similar_prompt(),
+        }
+    }
+}
+
+/// Hook events `[capture] events` can name. `PreToolUse` and
+/// `PostToolUseFailure` follow `PostToolUse`.
+pub const CAPTURE_EVENTS: &[&str] = &["SessionStart", "UserPromptSubmit", "PostToolUse", "Stop"];
+
+/// What the hooks record (S3). Dropped events still succeed, and
+/// SessionStart still injects context.
+#[derive(Debug, Deserialize)]
+pub struct CaptureConfig {
+    /// Hook events recorded (default: all of `CAPTURE_EVENTS`).
+    #[serde(default = "default_capture_events")]
+    pub events: Vec<String>,
+    /// Observation types never recorded, e.g. `file_read`.
+    #[serde(default)]
+    pub exclude_types: Vec<String>,
+}
+
+fn default_capture_events() -> Vec<String> {
+    CAPTURE_EVENTS.iter().map(|e| e.to_string()).collect()
+}
+
+impl Default for CaptureConfig {
+    fn default() -> Self {
+        Self {
+            events: default_capture_events(),
+            exclude_types: Vec::new(),
+        }
+    }
+}
+
+/// A context pack (`[packs.<name>]`, `s4_pack`): saved searches, markers,
+/// and file globs handed to the agent as one bundle when a trigger matches
+/// mid-session.
+#[derive(Debug, Deserialize, Clone)]
+pub struct PackConfig {
+    /// Only in this project (default: every project).
+    pub project: Option<String>,
+    /// FTS queries; each contributes its best matches.
+    #[serde(default)]
+    pub searches: Vec<String>,
+    /// Pinned observations and markers whose text contains one of these
+    /// (case-insensitive).
+    #[serde(default)]
+    pub markers: Vec<String>,
+    /// Globs of files whose latest observation is listed, e.g. `CHANGELOG.md`.
+    #[serde(default)]
+    pub files: Vec<String>,
+    /// Trigger: a user prompt — the intent an episode starts from —
+    /// containing one of these words (case-insensitive).
+    #[serde(default)]
+    pub on_intent: Vec<String>,
+    /// Trigger: a tool call on a file matching one of these globs.
+    #[serde(default)]
+    pub on_files: Vec<String>,
+    /// Matches per saved search (default: 3).
+    #[serde(default = "default_pack_limit")]
+    pub limit: usize,
+}
+
+fn default_pack_limit() -> usize {
+    3
+}
+
+/// A named time window (`[windows.<name>]`, `s4_window`) — a sprint, a
+/// release cycle — accepted as `window` wherever MCP tools take
+/// `before`/`after`.
+#[derive(Debug, Deserialize, Clone)]
+pub struct WindowConfig {
+    /// First day, `YYYY-MM-DD` in the display timezone.
+    pub start: String,
+    /// Last day, inclusive. Omit for a window still open, or with `every`.
+    #[serde(default)]
+    pub end: Option<String>,
+    /// Repeat every `14d`, `2w`, ...: `<name>` is then the current period
+    /// and `<name>-N` the Nth, counting from `start`.
+    #[serde(default)]
+    pub every: Option<String>,
+}
+
+/// Origin tag for the primary database when others are attached.
+pub const LOCAL_ORIGIN: &str = "local";
+
+/// MCP server settings.
+#[derive(Debug, Deserialize)]
+pub struct ServeConfig {
+    /// Extra read-only databases (e.g. a team export) federated into
+    /// search, recent_context, and session_summaries; results carry the name
+    /// as `origin`.
+    #[serde(default)]
+    pub attach: BTreeMap<String, PathBuf>,
+    #[serde(default)]
+    pub limits: LimitsConfig,
+    /// Push stance shifts, sustained friction, and new episodes to clients
+    /// that set a log level (default: true).
+    #[serde(default = "default_true")]
+    pub stance_notifications: bool,
+    /// Run as `nmem serve --lean` for small machines (default: false).
+    #[serde(default)]
+    pub lean: bool,
+}
+
+impl Default for ServeConfig {
+    fn default() -> Self {
+        Self {
+            attach: BTreeMap::new(),
+            limits: LimitsConfig::default(),
+            stance_notifications: true,
+            lean: false,
+        }
+    }
+}
+
+/// Default result counts of `search` and `recent_context` when the caller
+/// passes no `limit` (S3).
+#[derive(Debug, Deserialize)]
+pub struct LimitsConfig {
+    /// Raise the defaults for busy projects so they cover the last
+    /// `window_days` working days.
+    #[serde(default = "default_true")]
+    pub adaptive: bool,
+    /// Distinct days with activity the adaptive defaults aim to cover.
+    #[serde(default = "default_limits_window_days")]
+    pub window_days: u32,
+    /// Fixed `search` default; replaces the adaptive one.
+    #[serde(default)]
+    pub search: Option<i64>,
+    /// Fixed `recent_context` default; replaces the adaptive one.
+    #[serde(default)]
+    pub recent_context: Option<i64>,
+}
+
+fn default_limits_window_days() -> u32 {
+    3
+}
+
+impl Default for LimitsConfig {
+    fn default() -> Self {
+        Self {
+            adaptive: true,
+            window_days: default_limits_window_days(),
+            search: None,
+            recent_context: None,
+        }
+    }
+}
+
+/// Context injection extensions (S4).
+#[derive(Debug, Deserialize)]
+pub struct ContextConfig {
+    /// External context providers, run in name order. Each receives JSON
+    /// (project, budget, timestamps) on stdin and prints a markdown section.
+    #[serde(default)]
+    pub providers: BTreeMap<String, ContextProvider>,
+    /// Per-event overrides keyed by SessionStart source: `startup`, `resume`,
+    /// `compact` (after PreCompact), `clear`.
+    #[serde(default)]
+    pub profiles: BTreeMap<String, ContextProfile>,
+    /// Characters of `remember`ed memories listed ahead of every section
+    /// (default: 2000; 0 = none).
+    #[serde(default = "default_memory_chars")]
+    pub memory_chars: usize,
+}
+
+fn default_memory_chars() -> usize {
+    2000
+}
+
+impl Default for ContextConfig {
+    fn default() -> Self {
+        Self {
+            providers: BTreeMap::new(),
+            profiles: BTreeMap::new(),
+            memory_chars: default_memory_chars(),
+        }
+    }
+}
+
+/// A section of the injected context, in output order.
+#[derive(Debug, Deserialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum ContextSection {
+    /// The current session's episodes and its edits since.
+    CurrentSession,
+    Investigations,
+    Topics,
+    /// Ingested project docs sharing terms with recent intents.
+    Docs,
+    Episodes,
+    Summaries,
+    Tasks,
+    Providers,
+    Activity,
+    /// This project's recent files that other projects also touched.
+    SharedFiles,
+    CrossProject,
+}
+
+impl ContextSection {
+    /// Everything but the current session — the `startup` profile.
+    pub const FULL: [ContextSection; 10] = [
+        ContextSection::Investigations,
+        ContextSection::Topics,
+        ContextSection::Docs,
+        ContextSection::Episodes,
+        ContextSection::Summaries,
+        ContextSection::Tasks,
+        ContextSection::Providers,
+        ContextSection::Activity,
+        ContextSection::SharedFiles,
+        ContextSection::CrossProject,
+    ];
+}
+
+/// `[context.profiles.<event>]`. Unset fields fall back to the event's
+/// built-in profile.
+#[derive(Debug, Deserialize, Default, Clone)]
+pub struct ContextProfile {
+    pub sections: Option<Vec<ContextSection>>,
+    /// Max episodes listed.
+    pub episodes: Option<u32>,
+    pub local_limit: Option<u32>,
+    pub cross_limit: Option<u32>,
+}
+
+/// A context profile with every field decided.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ResolvedContextProfile {
+    pub sections: Vec<ContextSection>,
+    pub episodes: i64,
+    pub local_limit: i64,
+    pub cross_limit: i64,
+}
+
+/// `name = "cmd"`, or a table to override the defaults.
+#[derive(Debug, Deserialize, Clone, PartialEq)]
+#[serde(untagged)]
+pub enum ContextProvider {
+    Command(String),
+    Full {
+        command: String,
+        /// Kill the command after this long (default: 2000 ms).
+        timeout_ms: Option<u64>,
+        /// Truncate the section to this many bytes (default: 4096).
+        max_bytes: Option<usize>,
+    },
+}
+
+pub const DEFAULT_PROVIDER_TIMEOUT_MS: u64 = 2000;
+pub const DEFAULT_PROVIDER_MAX_BYTES: usize = 4096;
+
+impl ContextProvider {
+    pub fn command(&self) -> &str {
+        match self {
+            ContextProvider::Command(c) | ContextProvider::Full { command: c, .. } => c,
+        }
+    }
+
+    pub fn timeout_ms(&self) -> u64 {
+        match self {
+            ContextProvider::Full { timeout_ms: Some(t), .. } => *t,
+            _ => DEFAULT_PROVIDER_TIMEOUT_MS,
+        }
+    }
+
+    pub fn max_bytes(&self) -> usize {
+        match self {
+            ContextProvider::Full { max_bytes: Some(b), .. } => *b,
+            _ => DEFAULT_PROVIDER_MAX_BYTES,
+        }
+    }
+}
+
+/// MCP query audit log (S3). Rows older than `retention_days` are pruned when
+/// the server starts and on retention sweeps.
+#[derive(Debug, Deserialize)]
+pub struct QueryLogConfig {
+    #[serde(default = "default_true")]
+    pub enabled: bool,
+    #[serde(default = "default_query_log_days")]
+    pub retention_days: u32,
+}
+
+fn default_query_log_days() -> u32 {
+    30
+}
+
+impl Default for QueryLogConfig {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            retention_days: default_query_log_days(),
+        }
+    }
+}
+
+/// Audit trail of purges and sweeps (S3). Always written; `sign` chains
+/// each entry to the previous one with an HMAC keyed by
+/// `{install_dir}/audit.key`, so `nmem audit deletions --verify` can detect
+/// edited or removed entries.
+#[derive(Debug, Deserialize, Default)]
+pub struct DeletionsLogConfig {
+    #[serde(default)]
+    pub sign: bool,
+}
+
+/// The importance model (S3): ranking knobs shared by blended search,
+/// `recent_context`, context selection, and quota eviction. See
+/// `s3_importance` for the default profile.
+#[derive(Debug, Deserialize, Default)]
+pub struct ScoringConfig {
+    /// Recency half-life in days (default: 7). Overridable per project.
+    pub recency_half_life_days: Option<f64>,
+    /// Weights for the `type_weight()` SQL function, by obs_type; `other`
+    /// covers every type not listed. Unset types keep the built-in weights.
+    #[serde(default)]
+    pub type_weights: HashMap<String, f64>,
+    /// Project weight of observations outside the project asked about
+    /// (default: 0.3; the project's own count 1).
+    pub other_project: Option<f64>,
+    /// Added to the score of pinned observations (default: 0).
+    pub pin_bonus: Option<f64>,
+    /// Blend of blended search.
+    #[serde(default)]
+    pub search: SearchWeights,
+    /// Blend of `recent_context`, context selection, and quota eviction.
+    #[serde(default)]
+    pub recent: RecentWeights,
+}
+
+/// `[scoring.search]` — weights of BM25 relevance, recency, and type.
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize)]
+#[serde(default)]
+pub struct SearchWeights {
+    pub relevance: f64,
+    pub recency: f64,
+    #[serde(rename = "type")]
+    pub obs_type: f64,
+}
+
+impl Default for SearchWeights {
+    fn default() -> Self {
+        Self { relevance: 0.5, recency: 0.3, obs_type: 0.2 }
+    }
+}
+
+/// `[scoring.recent]` — weights of recency, type, and project match.
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize)]
+#[serde(default)]
+pub struct RecentWeights {
+    pub recency: f64,
+    #[serde(rename = "type")]
+    pub obs_type: f64,
+    pub project: f64,
+}
+
+impl Default for RecentWeights {
+    fn default() -> Self {
+        Self { recency: 0.5, obs_type: 0.3, project: 0.2 }
+    }
+}
+
+/// Auto-pin rules (S3). Off by default; once enabled, every rule is on unless
+/// turned off, and an empty `marker_keywords` disables the marker rule.
+#[derive(Debug, Deserialize)]
+pub struct AutoPinConfig {
+    #[serde(default)]
+    pub enabled: bool,
+    /// Pin successful `git push` observations.
+    #[serde(default = "default_true")]
+    pub git_push: bool,
+    /// Pin markers containing any of these words (case-insensitive, whole
+    /// words only).
+    #[serde(default = "default_marker_keywords")]
+    pub marker_keywords: Vec<String>,
+    /// Pin the first success of a command whose previous run failed.
+    #[serde(default = "default_true")]
+    pub first_success: bool,
+    /// Pin the edit that ended a friction episode (evaluated at Stop).
+    #[serde(default = "default_true")]
+    pub friction_fix: bool,
+}
+
+fn default_marker_keywords() -> Vec<String> {
+    vec!["decided".into(), "conclusion".into()]
+}
+
+impl Default for AutoPinConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            git_push: true,
+            marker_keywords: default_marker_keywords(),
+            first_success: true,
+            friction_fix: true,
+        }
+    }
 }
 
 #[derive(Debug, Deserialize, Default)]
@@ -52,6 +740,15 @@ pub struct SummarizationConfig {
     pub n_gpu_layers: u32,
     #[serde(default)]
     pub lora_path: Option<String>,
+    /// Tokens (prompt + generated) per UTC calendar month; once reached,
+    /// episode narratives pause until the month rolls over. 0 = no cap.
+    #[serde(default)]
+    pub monthly_token_budget: u64,
+    /// Token budget for a session summary's payload; the extractive pre-pass
+    /// keeps the most salient action lines that fit. Episode narratives get
+    /// half.
+    #[serde(default = "default_payload_tokens")]
+    pub payload_tokens: usize,
 }
 
 impl Default for SummarizationConfig {
@@ -65,6 +762,8 @@ impl Default for SummarizationConfig {
             n_threads: 0,
             n_gpu_layers: default_n_gpu_layers(),
             lora_path: None,
+            monthly_token_budget: 0,
+            payload_tokens: default_payload_tokens(),
         }
     }
 }
@@ -139,6 +838,10 @@ fn default_n_ctx() -> u32 {
     32768
 }
 
+fn default_payload_tokens() -> usize {
+    3000
+}
+
 fn default_n_gpu_layers() -> u32 {
     999
 }
@@ -151,6 +854,17 @@ pub struct FilterConfig {
     pub entropy_min_length: Option<usize>,
     #[serde(default)]
     pub disable_entropy: bool,
+    /// Keep each redacted record's original in an encrypted quarantine store
+    /// (separate key) so false positives can be restored (default: false).
+    #[serde(default)]
+    pub quarantine: bool,
+    /// Days a quarantined original is kept before it expires (default: 7).
+    pub quarantine_days: Option<u32>,
+    /// How absolute home paths are rewritten in `nmem changes`, beacon
+    /// replies, and other projects' context: `home` (default), `project`,
+    /// or `off`.
+    #[serde(default)]
+    pub anonymize_paths: crate::s5_anonymize::PathAnonymization,
 }
 
 #[derive(Debug, Deserialize, Default)]
@@ -168,6 +882,17 @@ pub struct ProjectConfig {
     /// Episode window in hours for context injection (default: 48).
     /// Episodes within this window replace session summaries.
     pub context_episode_window_hours: Option<u32>,
+    /// Recency half-life in days for scoring (default: `scoring.recency_half_life_days`, then 7).
+    /// Fast-moving projects want a short half-life, stable ones a long one.
+    pub recency_half_life_days: Option<f64>,
+    /// Cap in MB on the project's observations. `maintain --sweep` and session
+    /// maintenance evict its oldest low-value observations beyond it.
+    pub max_size_mb: Option<u32>,
+    /// Hook events recorded in this project; replaces `capture.events`.
+    pub capture_events: Option<Vec<String>>,
+    /// Observation types never recorded in this project; replaces
+    /// `capture.exclude_types`.
+    pub capture_exclude_types: Option<Vec<String>>,
 }
 
 #[derive(Debug, Deserialize, Default, Clone, Copy, PartialEq, Eq)]
@@ -194,6 +919,52 @@ pub struct RetentionConfig {
     /// regardless of observation count. None means no size limit.
     #[serde(default)]
     pub max_db_size_mb: Option<u32>,
+    /// Days to keep file content snapshots (default: 7). Kept short: they
+    /// matter while a decision is fresh, and they are the bulkiest rows.
+    #[serde(default = "default_snapshot_days")]
+    pub snapshot_days: u32,
+    /// Hours an observation is kept no matter what `days` says (default: 24).
+    /// Guards against `days = 0` sweeping what was just recorded.
+    #[serde(default = "default_min_age_hours")]
+    pub min_age_hours: u32,
+    /// Archived projects keep this percentage of each type's `days`
+    /// (default: 25).
+    #[serde(default = "default_archived_retention_pct")]
+    pub archived_retention_pct: u32,
+    /// Rows a sweep deletes per write (default: 500). Each chunk commits on
+    /// its own, so a session recording meanwhile waits for one chunk, not
+    /// the whole sweep.
+    #[serde(default = "default_sweep_chunk")]
+    pub sweep_chunk: usize,
+    /// Pause between sweep chunks in milliseconds (default: 50), leaving the
+    /// write lock free for hooks.
+    #[serde(default = "default_sweep_pause_ms")]
+    pub sweep_pause_ms: u64,
+    /// Local maintenance window (`"02:00-06:00"`) sweeps are kept to. Unset:
+    /// any time. Post-session maintenance outside it leaves expired rows for
+    /// the next run inside it; `nmem maintain --sweep --now` overrides.
+    #[serde(default)]
+    pub sweep_window: Option<String>,
+}
+
+fn default_sweep_chunk() -> usize {
+    500
+}
+
+fn default_sweep_pause_ms() -> u64 {
+    50
+}
+
+fn default_snapshot_days() -> u32 {
+    7
+}
+
+fn default_min_age_hours() -> u32 {
+    24
+}
+
+fn default_archived_retention_pct() -> u32 {
+    25
 }
 
 fn default_true() -> bool {
@@ -206,6 +977,12 @@ impl Default for RetentionConfig {
             enabled: true,
             days: default_retention_days(),
             max_db_size_mb: None,
+            snapshot_days: default_snapshot_days(),
+            min_age_hours: default_min_age_hours(),
+            archived_retention_pct: default_archived_retention_pct(),
+            sweep_chunk: default_sweep_chunk(),
+            sweep_pause_ms: default_sweep_pause_ms(),
+            sweep_window: None,
         }
     }
 }
@@ -226,6 +1003,7 @@ fn default_retention_days() -> HashMap<String, u32> {
         // Commands and external interactions
         ("command".into(), 180),
         ("github".into(), 180),
+        ("plan".into(), 180),
         // Investigation — high volume, shorter retention
         ("file_read".into(), 90),
         ("search".into(), 90),
@@ -239,19 +1017,23 @@ fn default_retention_days() -> HashMap<String, u32> {
 
 /// Load config from NMEM_CONFIG env var, ~/.nmem/config.toml, or defaults.
 pub fn load_config() -> Result<NmemConfig, NmemError> {
-    let path = config_path();
-    match path {
-        Some(p) if p.exists() => {
-            let content = std::fs::read_to_string(&p)?;
-            let config: NmemConfig = toml::from_str(&content)
-                .map_err(|e| NmemError::Config(format!("{}: {e}", p.display())))?;
-            validate_config(&config)?;
-            Ok(config)
-        }
+    match config_path() {
+        Some(p) if p.exists() => load_config_from(&p),
         _ => Ok(NmemConfig::default()),
     }
 }
 
+/// Load and validate the config at `path`, which must exist.
+pub fn load_config_from(path: &Path) -> Result<NmemConfig, NmemError> {
+    let parse = || -> Result<NmemConfig, NmemError> {
+        let content = std::fs::read_to_string(path)?;
+        let config: NmemConfig = toml::from_str(&content).map_err(|e| NmemError::Config(e.to_string()))?;
+        validate_config(&config)?;
+        Ok(config)
+    };
+    parse().map_err(|e| e.context_path(crate::diagnostic::LOAD_CONFIG, path))
+}
+
 fn config_path() -> Option<PathBuf> {
     if let Ok(p) = std::env::var("NMEM_CONFIG") {
         return Some(PathBuf::from(p));
@@ -265,6 +1047,130 @@ fn validate_config(config: &NmemConfig) -> Result<(), NmemError> {
             NmemError::Config(format!("extra_patterns[{i}] invalid regex: {e}"))
         })?;
     }
+    let half_lives = std::iter::once(("scoring".to_string(), config.scoring.recency_half_life_days))
+        .chain(config.projects.iter().map(|(name, pc)| (format!("projects.{name}"), pc.recency_half_life_days)));
+    for (section, days) in half_lives {
+        if let Some(d) = days
+            && !(d.is_finite() && d > 0.0)
+        {
+            return Err(NmemError::Config(format!(
+                "{section}.recency_half_life_days must be a positive number of days, got {d}"
+            )));
+        }
+    }
+    for (name, pc) in &config.projects {
+        if pc.max_size_mb == Some(0) {
+            return Err(NmemError::Config(format!("projects.{name}.max_size_mb must be at least 1")));
+        }
+    }
+    let event_lists = std::iter::once(("capture.events".to_string(), &config.capture.events)).chain(
+        config
+            .projects
+            .iter()
+            .filter_map(|(name, pc)| pc.capture_events.as_ref().map(|e| (format!("projects.{name}.capture_events"), e))),
+    );
+    for (key, events) in event_lists {
+        if let Some(bad) = events.iter().find(|e| !CAPTURE_EVENTS.contains(&e.as_str())) {
+            return Err(NmemError::Config(format!(
+                "{key}: unknown hook event {bad:?} (expected one of {})",
+                CAPTURE_EVENTS.join(", ")
+            )));
+        }
+    }
+    let scoring = &config.scoring;
+    let weights = scoring
+        .type_weights
+        .iter()
+        .map(|(obs_type, w)| (format!("type_weights.{obs_type}"), Some(*w)))
+        .chain([
+            ("other_project".to_string(), scoring.other_project),
+            ("pin_bonus".to_string(), scoring.pin_bonus),
+            ("search.relevance".to_string(), Some(scoring.search.relevance)),
+            ("search.recency".to_string(), Some(scoring.search.recency)),
+            ("search.type".to_string(), Some(scoring.search.obs_type)),
+            ("recent.recency".to_string(), Some(scoring.recent.recency)),
+            ("recent.type".to_string(), Some(scoring.recent.obs_type)),
+            ("recent.project".to_string(), Some(scoring.recent.project)),
+        ]);
+    for (key, weight) in weights {
+        if let Some(w) = weight
+            && !(w.is_finite() && w >= 0.0)
+        {
+            return Err(NmemError::Config(format!("scoring.{key} must be a non-negative number, got {w}")));
+        }
+    }
+    for (kind, level) in &config.learn.severities {
+        if !crate::s3_learn::PATTERN_KINDS.contains(&kind.as_str()) {
+            return Err(NmemError::Config(format!(
+                "learn.severities.{kind}: unknown pattern kind (expected one of {})",
+                crate::s3_learn::PATTERN_KINDS.join(", ")
+            )));
+        }
+        if !crate::s3_learn::SEVERITIES.contains(&level.as_str()) {
+            return Err(NmemError::Config(format!(
+                "learn.severities.{kind} must be one of {}, got {level:?}",
+                crate::s3_learn::SEVERITIES.join(", ")
+            )));
+        }
+    }
+    if let Some(ref level) = config.learn.fail_on
+        && !crate::s3_learn::SEVERITIES.contains(&level.as_str())
+    {
+        return Err(NmemError::Config(format!(
+            "learn.fail_on must be one of {}, got {level:?}",
+            crate::s3_learn::SEVERITIES.join(", ")
+        )));
+    }
+    if config.learn.environment_projects < 2 {
+        return Err(NmemError::Config(format!(
+            "learn.environment_projects must be at least 2, got {}",
+            config.learn.environment_projects
+        )));
+    }
+    if config.learn.environment_window_hours == 0 {
+        return Err(NmemError::Config("learn.environment_window_hours must be positive".into()));
+    }
+    if let Some(ref zone) = config.display.timezone {
+        crate::timefmt::parse_zone(zone)?;
+    }
+    for window in &config.schedule.quiet_hours {
+        crate::s3_schedule::parse_window(window)?;
+    }
+    if let Some(ref url) = config.update.endpoint
+        && !(url.starts_with("https://") || url.starts_with("file://"))
+    {
+        return Err(NmemError::Config(format!("update.endpoint must be an https:// or file:// URL, got {url:?}")));
+    }
+    for (name, provider) in &config.context.providers {
+        if provider.command().trim().is_empty() {
+            return Err(NmemError::Config(format!("context.providers.{name}: empty command")));
+        }
+    }
+    if config.serve.limits.window_days == 0 {
+        return Err(NmemError::Config("serve.limits.window_days must be positive".into()));
+    }
+    for (key, limit) in [("search", config.serve.limits.search), ("recent_context", config.serve.limits.recent_context)] {
+        if limit.is_some_and(|n| !(1..=100).contains(&n)) {
+            return Err(NmemError::Config(format!("serve.limits.{key} must be between 1 and 100")));
+        }
+    }
+    if config.serve.attach.contains_key(LOCAL_ORIGIN) {
+        return Err(NmemError::Config(format!(
+            "serve.attach.{LOCAL_ORIGIN}: name is reserved for the primary database"
+        )));
+    }
+    for (name, window) in &config.windows {
+        crate::s4_window::check(window).map_err(|e| NmemError::Config(format!("windows.{name}: {e}")))?;
+    }
+    for (name, pack) in &config.packs {
+        if pack.searches.is_empty() && pack.markers.is_empty() && pack.files.is_empty() {
+            return Err(NmemError::Config(format!("packs.{name}: needs searches, markers, or files")));
+        }
+        for glob in pack.files.iter().chain(&pack.on_files) {
+            crate::s4_pack::glob_regex(glob)
+                .map_err(|e| NmemError::Config(format!("packs.{name}: invalid glob {glob:?}: {e}")))?;
+        }
+    }
     Ok(())
 }
 
@@ -287,6 +1193,46 @@ pub fn resolve_context_limits(config: &NmemConfig, project: &str, is_recovery: b
     (local, cross)
 }
 
+/// Resolve what to inject for a SessionStart `event` (its `source`).
+///
+/// Built-in profiles: `startup` and `clear` get everything (`clear` with
+/// recovery limits); `compact` leads with the current session's state and
+/// drops summaries and other projects; `resume` gets the last episode and
+/// open tasks only. `[context.profiles.<event>]` overrides field by field.
+pub fn resolve_context_profile(config: &NmemConfig, project: &str, event: &str) -> ResolvedContextProfile {
+    use ContextSection::*;
+    let (local, cross) = resolve_context_limits(config, project, matches!(event, "compact" | "clear"));
+    let builtin = match event {
+        "compact" => ResolvedContextProfile {
+            sections: vec![CurrentSession, Investigations, Topics, Episodes, Tasks, Activity],
+            episodes: 5,
+            local_limit: local,
+            cross_limit: 0,
+        },
+        "resume" => ResolvedContextProfile {
+            sections: vec![Episodes, Tasks],
+            episodes: 1,
+            local_limit: 0,
+            cross_limit: 0,
+        },
+        _ => ResolvedContextProfile {
+            sections: ContextSection::FULL.to_vec(),
+            episodes: 15,
+            local_limit: local,
+            cross_limit: cross,
+        },
+    };
+    let Some(p) = config.context.profiles.get(event) else {
+        return builtin;
+    };
+    ResolvedContextProfile {
+        sections: p.sections.clone().unwrap_or(builtin.sections),
+        episodes: p.episodes.map_or(builtin.episodes, i64::from),
+        local_limit: p.local_limit.map_or(builtin.local_limit, i64::from),
+        cross_limit: p.cross_limit.map_or(builtin.cross_limit, i64::from),
+    }
+}
+
 /// Resolve episode window in seconds from config.
 /// Project override takes precedence, otherwise default 48 hours.
 pub fn resolve_episode_window(config: &NmemConfig, project: &str) -> i64 {
@@ -298,6 +1244,76 @@ pub fn resolve_episode_window(config: &NmemConfig, project: &str) -> i64 {
     hours as i64 * 3600
 }
 
+/// The importance model: the default profile with `[scoring]` laid over it.
+pub fn resolve_importance(config: &NmemConfig) -> crate::s3_importance::ImportanceModel {
+    let mut model = crate::s3_importance::ImportanceModel::default();
+    let scoring = &config.scoring;
+    for (obs_type, weight) in &scoring.type_weights {
+        if obs_type == "other" {
+            model.types.other = *weight;
+        } else {
+            model.types.weights.insert(obs_type.clone(), *weight);
+        }
+    }
+    model.search = scoring.search;
+    model.recent = scoring.recent;
+    if let Some(w) = scoring.other_project {
+        model.other_project = w;
+    }
+    if let Some(w) = scoring.pin_bonus {
+        model.pin_bonus = w;
+    }
+    model
+}
+
+/// Resolve the recency half-life (days) used by decay scoring.
+/// Project override takes precedence, then `[scoring]`, otherwise 7 days.
+pub fn resolve_recency_half_life(config: &NmemConfig, project: Option<&str>) -> f64 {
+    project
+        .and_then(|p| config.projects.get(p))
+        .and_then(|pc| pc.recency_half_life_days)
+        .or(config.scoring.recency_half_life_days)
+        .unwrap_or(7.0)
+}
+
+/// What the hooks record in one project.
+#[derive(Debug, Clone, PartialEq)]
+pub struct CapturePolicy {
+    pub events: Vec<String>,
+    pub exclude_types: Vec<String>,
+}
+
+impl CapturePolicy {
+    /// Whether hook `event` is recorded; `PreToolUse`,
+    /// `PostToolUseFailure`, and `Heartbeat` go with `PostToolUse`, unknown
+    /// events pass.
+    pub fn records_event(&self, event: &str) -> bool {
+        let event = match event {
+            "PreToolUse" | "PostToolUseFailure" | "Heartbeat" => "PostToolUse",
+            other => other,
+        };
+        !CAPTURE_EVENTS.contains(&event) || self.events.iter().any(|e| e == event)
+    }
+
+    pub fn records_type(&self, obs_type: &str) -> bool {
+        !self.exclude_types.iter().any(|t| t == obs_type)
+    }
+}
+
+/// `[capture]` with the project's `capture_events`/`capture_exclude_types`
+/// replacing its lists.
+pub fn resolve_capture(config: &NmemConfig, project: &str) -> CapturePolicy {
+    let pc = config.projects.get(project);
+    CapturePolicy {
+        events: pc
+            .and_then(|p| p.capture_events.clone())
+            .unwrap_or_else(|| config.capture.events.clone()),
+        exclude_types: pc
+            .and_then(|p| p.capture_exclude_types.clone())
+            .unwrap_or_else(|| config.capture.exclude_types.clone()),
+    }
+}
+
 /// Merge global config + project-specific settings into FilterParams.
 pub fn resolve_filter_params(config: &NmemConfig, project: Option<&str>) -> FilterParams {
     let mut params = FilterParams {
@@ -305,6 +1321,7 @@ pub fn resolve_filter_params(config: &NmemConfig, project: Option<&str>) -> Filt
         entropy_threshold: config.filter.entropy_threshold.unwrap_or(4.0),
         entropy_min_length: config.filter.entropy_min_length.unwrap_or(20),
         entropy_enabled: !config.filter.disable_entropy,
+        redaction_key: crate::db::load_or_create_redaction_key(),
     };
 
     // Apply project-level sensitivity (only if global hasn't explicitly disabled entropy)
@@ -475,6 +1492,192 @@ command = 60
         assert!(!config.retention.days.contains_key("user_prompt"));
     }
 
+    #[test]
+    fn parse_context_providers() {
+        let toml_str = r#"
+[context.providers]
+tickets = "jira-open --mine"
+runbook = { command = "cat runbook.md", timeout_ms = 500, max_bytes = 1024 }
+"#;
+        let config: NmemConfig = toml::from_str(toml_str).unwrap();
+        let providers = &config.context.providers;
+        assert_eq!(providers.keys().collect::<Vec<_>>(), vec!["runbook", "tickets"]);
+        assert_eq!(providers["tickets"].command(), "jira-open --mine");
+        assert_eq!(providers["tickets"].timeout_ms(), DEFAULT_PROVIDER_TIMEOUT_MS);
+        assert_eq!(providers["runbook"].timeout_ms(), 500);
+        assert_eq!(providers["runbook"].max_bytes(), 1024);
+    }
+
+    #[test]
+    fn parse_serve_attach() {
+        let config: NmemConfig = toml::from_str(
+            r#"
+[serve.attach]
+team = "/shared/team-export.db"
+"#,
+        )
+        .unwrap();
+        assert_eq!(config.serve.attach["team"], PathBuf::from("/shared/team-export.db"));
+        assert!(validate_config(&config).is_ok());
+
+        let reserved: NmemConfig = toml::from_str("[serve.attach]\nlocal = \"/x.db\"\n").unwrap();
+        assert!(validate_config(&reserved).is_err());
+    }
+
+    #[test]
+    fn parse_serve_limits() {
+        let config = NmemConfig::default();
+        assert!(config.serve.limits.adaptive);
+        assert_eq!(config.serve.limits.window_days, 3);
+
+        let config: NmemConfig = toml::from_str("[serve.limits]\nadaptive = false\nsearch = 40\n").unwrap();
+        assert!(!config.serve.limits.adaptive);
+        assert_eq!(config.serve.limits.search, Some(40));
+        assert!(validate_config(&config).is_ok());
+
+        let too_big: NmemConfig = toml::from_str("[serve.limits]\nrecent_context = 500\n").unwrap();
+        assert!(validate_config(&too_big).is_err());
+    }
+
+    #[test]
+    fn recency_half_life_resolution() {
+        assert_eq!(resolve_recency_half_life(&NmemConfig::default(), Some("any")), 7.0);
+
+        let config: NmemConfig = toml::from_str(
+            r#"
+[scoring]
+recency_half_life_days = 14.0
+
+[projects.fast]
+recency_half_life_days = 2.0
+"#,
+        )
+        .unwrap();
+        assert_eq!(resolve_recency_half_life(&config, Some("fast")), 2.0);
+        assert_eq!(resolve_recency_half_life(&config, Some("other")), 14.0);
+        assert_eq!(resolve_recency_half_life(&config, None), 14.0);
+    }
+
+    #[test]
+    fn type_weights_overlay_defaults() {
+        let config: NmemConfig = toml::from_str(
+            r#"
+[scoring.type_weights]
+file_read = 0.4
+command = 0.9
+other = 0.05
+"#,
+        )
+        .unwrap();
+        assert!(validate_config(&config).is_ok());
+        let weights = resolve_importance(&config).types;
+        assert_eq!(weights.get(Some("file_read")), 0.4);
+        assert_eq!(weights.get(Some("command")), 0.9);
+        assert_eq!(weights.get(Some("file_edit")), 1.0);
+        assert_eq!(weights.get(Some("web_fetch")), 0.05);
+        assert_eq!(weights.get(None), 0.05);
+
+        let bad: NmemConfig = toml::from_str("[scoring.type_weights]\ncommand = -1.0\n").unwrap();
+        assert!(validate_config(&bad).is_err());
+    }
+
+    #[test]
+    fn capture_policy_with_project_override() {
+        let config: NmemConfig = toml::from_str(
+            r#"
+[capture]
+events = ["SessionStart", "PostToolUse", "Stop"]
+
+[projects.private]
+capture_exclude_types = ["file_read"]
+capture_events = ["SessionStart", "PostToolUse"]
+"#,
+        )
+        .unwrap();
+        assert!(validate_config(&config).is_ok());
+
+        let global = resolve_capture(&config, "other");
+        assert!(!global.records_event("UserPromptSubmit"));
+        assert!(global.records_event("PostToolUseFailure") && global.records_event("Stop"));
+        assert!(global.records_type("file_read"));
+
+        let private = resolve_capture(&config, "private");
+        assert!(!private.records_event("Stop"));
+        assert!(private.records_event("PreToolUse"));
+        assert!(!private.records_type("file_read") && private.records_type("command"));
+
+        let default = resolve_capture(&NmemConfig::default(), "any");
+        assert!(CAPTURE_EVENTS.iter().all(|e| default.records_event(e)));
+
+        let bad: NmemConfig = toml::from_str("[capture]\nevents = [\"PostToolUses\"]\n").unwrap();
+        assert!(validate_config(&bad).is_err());
+    }
+
+    #[test]
+    fn scoring_blends_overlay_defaults() {
+        let config: NmemConfig = toml::from_str(
+            r#"
+[scoring]
+pin_bonus = 0.5
+
+[scoring.search]
+relevance = 0.8
+
+[scoring.recent]
+project = 0.0
+"#,
+        )
+        .unwrap();
+        assert!(validate_config(&config).is_ok());
+        let model = resolve_importance(&config);
+        assert_eq!(model.search, SearchWeights { relevance: 0.8, ..SearchWeights::default() });
+        assert_eq!((model.recent.recency, model.recent.project), (0.5, 0.0));
+        assert_eq!((model.pin_bonus, model.other_project), (0.5, 0.3));
+
+        let bad: NmemConfig = toml::from_str("[scoring.recent]\ntype = -0.1\n").unwrap();
+        assert!(validate_config(&bad).is_err());
+    }
+
+    #[test]
+    fn recency_half_life_must_be_positive() {
+        let config: NmemConfig = toml::from_str(
+            r#"
+[projects.bad]
+recency_half_life_days = 0.0
+"#,
+        )
+        .unwrap();
+        let err = validate_config(&config).unwrap_err().to_string();
+        assert!(err.contains("projects.bad.recency_half_life_days"), "{err}");
+    }
+
+    #[test]
+    fn context_profiles_builtin_and_overridden() {
+        let config = NmemConfig::default();
+        let startup = resolve_context_profile(&config, "p", "startup");
+        assert_eq!(startup.sections, ContextSection::FULL.to_vec());
+        assert_eq!((startup.local_limit, startup.cross_limit), (20, 10));
+        let resume = resolve_context_profile(&config, "p", "resume");
+        assert_eq!(resume.sections, vec![ContextSection::Episodes, ContextSection::Tasks]);
+        assert_eq!(resume.episodes, 1);
+        let compact = resolve_context_profile(&config, "p", "compact");
+        assert_eq!(compact.sections[0], ContextSection::CurrentSession);
+        assert_eq!((compact.local_limit, compact.cross_limit), (30, 0));
+
+        let config: NmemConfig = toml::from_str(
+            r#"
+[context.profiles.resume]
+sections = ["current_session", "tasks"]
+local_limit = 5
+"#,
+        )
+        .unwrap();
+        let resume = resolve_context_profile(&config, "p", "resume");
+        assert_eq!(resume.sections, vec![ContextSection::CurrentSession, ContextSection::Tasks]);
+        assert_eq!((resume.episodes, resume.local_limit), (1, 5));
+        assert!(toml::from_str::<NmemConfig>("[context.profiles.x]\nsections = [\"bogus\"]").is_err());
+    }
+
     #[test]
     fn context_limits_defaults_normal() {
         let config = NmemConfig::default();
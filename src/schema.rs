@@ -1,6 +1,11 @@
 use rusqlite_migration::{M, Migrations};
 use std::sync::LazyLock;
 
+/// Schema versions whose migration rewrites existing rows rather than only
+/// adding tables or columns. Upgrading across one is rehearsed on a copy
+/// first (see `s3_migrate`).
+pub const RISKY_VERSIONS: &[usize] = &[17, 24, 26];
+
 pub static MIGRATIONS: LazyLock<Migrations<'static>> = LazyLock::new(|| {
     Migrations::new(vec![
         M::up(
@@ -157,6 +162,438 @@ ALTER TABLE observations ADD COLUMN friction_run_id INTEGER REFERENCES classifie
         ),
         M::up("ALTER TABLE work_units ADD COLUMN obs_trace TEXT;"),
         M::up("ALTER TABLE sessions ADD COLUMN summarization_ms INTEGER;"),
+        M::up("ALTER TABLE sessions ADD COLUMN kind TEXT NOT NULL DEFAULT 'agent';"),
+        M::up(
+            "ALTER TABLE observations ADD COLUMN error_class TEXT;
+CREATE INDEX idx_obs_error_class ON observations(error_class) WHERE error_class IS NOT NULL;",
+        ),
+        M::up(
+            "CREATE TABLE file_aliases (
+    old_path TEXT NOT NULL,
+    new_path TEXT NOT NULL,
+    source TEXT NOT NULL,
+    detected_at INTEGER NOT NULL,
+    PRIMARY KEY (old_path, new_path)
+);
+CREATE INDEX idx_file_aliases_new ON file_aliases(new_path);",
+        ),
+        M::up(
+            "CREATE TABLE query_log (
+    id           INTEGER PRIMARY KEY,
+    timestamp    INTEGER NOT NULL,
+    tool         TEXT NOT NULL,
+    params_hash  TEXT NOT NULL,
+    session_id   TEXT,
+    result_count INTEGER,
+    duration_ms  INTEGER NOT NULL,
+    is_error     INTEGER NOT NULL DEFAULT 0,
+    obs_ids      TEXT
+);
+CREATE INDEX idx_query_log_ts ON query_log(timestamp);",
+        ),
+        M::up(
+            "ALTER TABLE observations ADD COLUMN mcp_server TEXT;
+ALTER TABLE observations ADD COLUMN mcp_tool TEXT;
+UPDATE observations
+SET mcp_server = substr(tool_name, 6, instr(substr(tool_name, 6), '__') - 1),
+    mcp_tool = substr(tool_name, 6 + instr(substr(tool_name, 6), '__') + 1)
+WHERE substr(tool_name, 1, 5) = 'mcp__'
+  AND instr(substr(tool_name, 6), '__') > 1
+  AND length(tool_name) > 6 + instr(substr(tool_name, 6), '__');
+CREATE INDEX idx_obs_mcp_server ON observations(mcp_server) WHERE mcp_server IS NOT NULL;",
+        ),
+        M::up(
+            "CREATE TABLE investigations (
+    id            INTEGER PRIMARY KEY,
+    session_id    TEXT NOT NULL REFERENCES sessions(id) ON DELETE CASCADE,
+    question      TEXT NOT NULL,
+    started_at    INTEGER NOT NULL,
+    start_obs_id  INTEGER NOT NULL,
+    concluded_at  INTEGER,
+    end_obs_id    INTEGER,
+    conclusion    TEXT,
+    obs_count     INTEGER,
+    files         TEXT
+);
+CREATE INDEX idx_investigations_session ON investigations(session_id);",
+        ),
+        M::up(
+            "CREATE TABLE pending_work (
+    id         INTEGER PRIMARY KEY,
+    kind       TEXT NOT NULL,
+    obs_id     INTEGER REFERENCES observations(id) ON DELETE CASCADE,
+    payload    TEXT,
+    created_at INTEGER NOT NULL
+);",
+        ),
+        M::up(
+            "CREATE TABLE llm_usage (
+    id               INTEGER PRIMARY KEY,
+    task             TEXT NOT NULL,
+    session_id       TEXT REFERENCES sessions(id) ON DELETE SET NULL,
+    work_unit_id     INTEGER REFERENCES work_units(id) ON DELETE SET NULL,
+    prompt_tokens    INTEGER NOT NULL,
+    generated_tokens INTEGER NOT NULL,
+    total_ms         INTEGER NOT NULL,
+    created_at       INTEGER NOT NULL
+);
+CREATE INDEX idx_llm_usage_created ON llm_usage(created_at);",
+        ),
+        M::up(
+            "CREATE TABLE file_snapshots (
+    id          INTEGER PRIMARY KEY,
+    session_id  TEXT NOT NULL REFERENCES sessions(id) ON DELETE CASCADE,
+    obs_id      INTEGER REFERENCES observations(id) ON DELETE SET NULL,
+    file_path   TEXT NOT NULL,
+    captured_at INTEGER NOT NULL,
+    size        INTEGER NOT NULL,
+    blob_hash   TEXT,
+    head        TEXT,
+    truncated   INTEGER NOT NULL DEFAULT 0,
+    UNIQUE(session_id, file_path)
+);
+CREATE INDEX idx_file_snapshots_path ON file_snapshots(file_path, captured_at);",
+        ),
+        M::up(
+            "CREATE TABLE topics (
+    id         INTEGER PRIMARY KEY,
+    project    TEXT NOT NULL,
+    title      TEXT NOT NULL,
+    summary    TEXT NOT NULL,
+    files      TEXT,
+    created_at INTEGER NOT NULL,
+    updated_at INTEGER NOT NULL
+);
+CREATE INDEX idx_topics_project ON topics(project, updated_at);
+CREATE TABLE topic_work_units (
+    work_unit_id INTEGER PRIMARY KEY REFERENCES work_units(id) ON DELETE CASCADE,
+    topic_id     INTEGER NOT NULL REFERENCES topics(id) ON DELETE CASCADE
+);
+CREATE INDEX idx_topic_work_units_topic ON topic_work_units(topic_id);",
+        ),
+        M::up(
+            "CREATE TABLE responses (
+    id         INTEGER PRIMARY KEY,
+    session_id TEXT NOT NULL REFERENCES sessions(id) ON DELETE CASCADE,
+    prompt_id  INTEGER REFERENCES prompts(id) ON DELETE SET NULL,
+    timestamp  INTEGER NOT NULL,
+    content    TEXT NOT NULL,
+    truncated  INTEGER NOT NULL DEFAULT 0
+);
+CREATE INDEX idx_responses_session ON responses(session_id, timestamp);",
+        ),
+        M::up(
+            "ALTER TABLE sessions ADD COLUMN summarized_at INTEGER;
+UPDATE sessions SET summarized_at = COALESCE(
+    ended_at,
+    (SELECT MAX(timestamp) FROM observations WHERE session_id = sessions.id),
+    started_at
+) WHERE summary IS NOT NULL;",
+        ),
+        M::up(
+            "CREATE TABLE archived_projects (
+    project     TEXT PRIMARY KEY,
+    archived_at INTEGER NOT NULL
+);",
+        ),
+        M::up(
+            "CREATE TABLE capture_sizes (
+    session_id   TEXT NOT NULL REFERENCES sessions(id) ON DELETE CASCADE,
+    obs_type     TEXT NOT NULL,
+    entries      INTEGER NOT NULL,
+    raw_bytes    INTEGER NOT NULL,
+    stored_bytes INTEGER NOT NULL,
+    PRIMARY KEY (session_id, obs_type)
+) WITHOUT ROWID;
+-- Before accounting only stored sizes are known; count them as raw too
+INSERT INTO capture_sizes (session_id, obs_type, entries, raw_bytes, stored_bytes)
+SELECT session_id, obs_type, COUNT(*), SUM(n), SUM(n)
+FROM (SELECT session_id, obs_type,
+             length(CAST(content AS BLOB)) + COALESCE(length(CAST(metadata AS BLOB)), 0) AS n
+      FROM observations
+      UNION ALL
+      SELECT session_id, 'prompt', length(CAST(content AS BLOB)) FROM prompts WHERE source = 'user')
+GROUP BY session_id, obs_type;",
+        ),
+        M::up(
+            "CREATE TABLE plans (
+    id          INTEGER PRIMARY KEY,
+    session_id  TEXT NOT NULL REFERENCES sessions(id) ON DELETE CASCADE,
+    obs_id      INTEGER REFERENCES observations(id) ON DELETE SET NULL,
+    timestamp   INTEGER NOT NULL,
+    kind        TEXT NOT NULL,
+    item        TEXT NOT NULL,
+    status      TEXT NOT NULL,
+    prev_status TEXT
+);
+CREATE INDEX idx_plans_session ON plans(session_id, item, id);",
+        ),
+        M::up(
+            "ALTER TABLE sessions ADD COLUMN parent_id TEXT REFERENCES sessions(id) ON DELETE SET NULL;",
+        ),
+        M::up(
+            "CREATE TABLE observation_chunks (
+    id       INTEGER PRIMARY KEY,
+    obs_id   INTEGER NOT NULL REFERENCES observations(id) ON DELETE CASCADE,
+    seq      INTEGER NOT NULL,
+    start    INTEGER NOT NULL,
+    content  TEXT NOT NULL
+);
+CREATE INDEX idx_observation_chunks_obs ON observation_chunks(obs_id, seq);
+CREATE VIRTUAL TABLE observation_chunks_fts USING fts5(
+    content, content='observation_chunks', content_rowid='id',
+    tokenize='porter unicode61'
+);
+CREATE TRIGGER observation_chunks_ai AFTER INSERT ON observation_chunks BEGIN
+    INSERT INTO observation_chunks_fts(rowid, content) VALUES (new.id, new.content);
+END;
+CREATE TRIGGER observation_chunks_ad AFTER DELETE ON observation_chunks BEGIN
+    INSERT INTO observation_chunks_fts(observation_chunks_fts, rowid, content)
+        VALUES('delete', old.id, old.content);
+END;",
+        ),
+        M::up(
+            "CREATE TABLE capture_pauses (
+    scope     TEXT PRIMARY KEY,
+    paused_at INTEGER NOT NULL,
+    until     INTEGER
+);",
+        ),
+        M::up(
+            "ALTER TABLE sessions ADD COLUMN summary_sources TEXT;
+ALTER TABLE work_units ADD COLUMN summary_sources TEXT;",
+        ),
+        M::up(
+            "CREATE TABLE tool_intents (
+    id          INTEGER PRIMARY KEY,
+    session_id  TEXT NOT NULL REFERENCES sessions(id) ON DELETE CASCADE,
+    prompt_id   INTEGER,
+    tool_use_id TEXT,
+    timestamp   INTEGER NOT NULL,
+    tool_name   TEXT NOT NULL,
+    file_path   TEXT,
+    content     TEXT NOT NULL,
+    status      TEXT NOT NULL DEFAULT 'pending',
+    obs_id      INTEGER REFERENCES observations(id) ON DELETE SET NULL,
+    resolved_at INTEGER
+);
+CREATE INDEX idx_tool_intents_session ON tool_intents(session_id, status, tool_name);
+CREATE INDEX idx_tool_intents_tool_use ON tool_intents(tool_use_id) WHERE tool_use_id IS NOT NULL;",
+        ),
+        M::up(
+            "CREATE TABLE changelog (
+    seq        INTEGER PRIMARY KEY AUTOINCREMENT,
+    tbl        TEXT NOT NULL,
+    row_id     TEXT NOT NULL,
+    op         TEXT NOT NULL,
+    changed_at INTEGER NOT NULL
+);
+CREATE INDEX idx_changelog_changed_at ON changelog(changed_at);
+CREATE TRIGGER changelog_sessions_i AFTER INSERT ON sessions BEGIN
+    INSERT INTO changelog (tbl, row_id, op, changed_at) VALUES ('sessions', new.id, 'insert', unixepoch());
+END;
+CREATE TRIGGER changelog_sessions_u AFTER UPDATE ON sessions BEGIN
+    INSERT INTO changelog (tbl, row_id, op, changed_at) VALUES ('sessions', new.id, 'update', unixepoch());
+END;
+CREATE TRIGGER changelog_sessions_d AFTER DELETE ON sessions BEGIN
+    INSERT INTO changelog (tbl, row_id, op, changed_at) VALUES ('sessions', old.id, 'delete', unixepoch());
+END;
+CREATE TRIGGER changelog_prompts_i AFTER INSERT ON prompts BEGIN
+    INSERT INTO changelog (tbl, row_id, op, changed_at) VALUES ('prompts', new.id, 'insert', unixepoch());
+END;
+CREATE TRIGGER changelog_prompts_u AFTER UPDATE ON prompts BEGIN
+    INSERT INTO changelog (tbl, row_id, op, changed_at) VALUES ('prompts', new.id, 'update', unixepoch());
+END;
+CREATE TRIGGER changelog_prompts_d AFTER DELETE ON prompts BEGIN
+    INSERT INTO changelog (tbl, row_id, op, changed_at) VALUES ('prompts', old.id, 'delete', unixepoch());
+END;
+CREATE TRIGGER changelog_observations_i AFTER INSERT ON observations BEGIN
+    INSERT INTO changelog (tbl, row_id, op, changed_at) VALUES ('observations', new.id, 'insert', unixepoch());
+END;
+CREATE TRIGGER changelog_observations_u AFTER UPDATE ON observations BEGIN
+    INSERT INTO changelog (tbl, row_id, op, changed_at) VALUES ('observations', new.id, 'update', unixepoch());
+END;
+CREATE TRIGGER changelog_observations_d AFTER DELETE ON observations BEGIN
+    INSERT INTO changelog (tbl, row_id, op, changed_at) VALUES ('observations', old.id, 'delete', unixepoch());
+END;
+CREATE TRIGGER changelog_work_units_i AFTER INSERT ON work_units BEGIN
+    INSERT INTO changelog (tbl, row_id, op, changed_at) VALUES ('work_units', new.id, 'insert', unixepoch());
+END;
+CREATE TRIGGER changelog_work_units_u AFTER UPDATE ON work_units BEGIN
+    INSERT INTO changelog (tbl, row_id, op, changed_at) VALUES ('work_units', new.id, 'update', unixepoch());
+END;
+CREATE TRIGGER changelog_work_units_d AFTER DELETE ON work_units BEGIN
+    INSERT INTO changelog (tbl, row_id, op, changed_at) VALUES ('work_units', old.id, 'delete', unixepoch());
+END;",
+        ),
+        M::up(
+            "CREATE TABLE corrections (
+    id          INTEGER PRIMARY KEY,
+    obs_id      INTEGER NOT NULL REFERENCES observations(id) ON DELETE CASCADE,
+    kind        TEXT NOT NULL,
+    text        TEXT NOT NULL,
+    created_at  INTEGER NOT NULL
+);
+CREATE INDEX idx_corrections_obs ON corrections(obs_id, id);
+CREATE TABLE observation_originals (
+    correction_id INTEGER PRIMARY KEY REFERENCES corrections(id) ON DELETE CASCADE,
+    obs_id        INTEGER NOT NULL,
+    content       TEXT NOT NULL,
+    metadata      TEXT,
+    snapshot_head TEXT
+);",
+        ),
+        M::up("ALTER TABLE sessions ADD COLUMN tmux_pane TEXT;"),
+        M::up(
+            "ALTER TABLE observations ADD COLUMN cwd TEXT;
+CREATE INDEX idx_observations_cwd ON observations(cwd) WHERE cwd IS NOT NULL;",
+        ),
+        M::up(
+            "CREATE TABLE context_pack_queue (
+    session_id TEXT NOT NULL REFERENCES sessions(id) ON DELETE CASCADE,
+    pack TEXT NOT NULL,
+    reason TEXT NOT NULL,
+    queued_at INTEGER NOT NULL,
+    delivered_at INTEGER,
+    PRIMARY KEY (session_id, pack)
+);",
+        ),
+        M::up(
+            "ALTER TABLE observations ADD COLUMN phase_confidence REAL;
+ALTER TABLE observations ADD COLUMN scope_confidence REAL;
+ALTER TABLE observations ADD COLUMN locus_confidence REAL;
+ALTER TABLE observations ADD COLUMN novelty_confidence REAL;",
+        ),
+        M::up(
+            "ALTER TABLE sessions ADD COLUMN model TEXT;
+ALTER TABLE sessions ADD COLUMN agent_version TEXT;",
+        ),
+        M::up(
+            "CREATE TABLE slow_queries (
+    id INTEGER PRIMARY KEY,
+    sql TEXT NOT NULL UNIQUE,
+    calls INTEGER NOT NULL DEFAULT 1,
+    total_ms INTEGER NOT NULL,
+    max_ms INTEGER NOT NULL,
+    plan TEXT,
+    first_seen INTEGER NOT NULL,
+    last_seen INTEGER NOT NULL
+);",
+        ),
+        M::up(
+            "CREATE TABLE context_suppressions (
+    id         INTEGER PRIMARY KEY,
+    project    TEXT,
+    kind       TEXT NOT NULL CHECK (kind IN ('file', 'topic')),
+    pattern    TEXT NOT NULL,
+    created_at INTEGER NOT NULL,
+    until      INTEGER
+);",
+        ),
+        M::up("ALTER TABLE work_units ADD COLUMN outcome TEXT;"),
+        M::up(
+            "CREATE TABLE observation_minhash (
+    obs_id    INTEGER PRIMARY KEY REFERENCES observations(id) ON DELETE CASCADE,
+    signature BLOB NOT NULL
+);
+CREATE TABLE observation_lsh (
+    band   INTEGER NOT NULL,
+    bucket INTEGER NOT NULL,
+    obs_id INTEGER NOT NULL REFERENCES observations(id) ON DELETE CASCADE,
+    PRIMARY KEY (band, bucket, obs_id)
+) WITHOUT ROWID;
+CREATE INDEX idx_observation_lsh_obs ON observation_lsh(obs_id);",
+        ),
+        M::up(
+            "ALTER TABLE observations ADD COLUMN repeat_count INTEGER NOT NULL DEFAULT 1;
+ALTER TABLE observations ADD COLUMN last_timestamp INTEGER;
+CREATE TABLE observation_repeats (
+    obs_id    INTEGER NOT NULL REFERENCES observations(id) ON DELETE CASCADE,
+    timestamp INTEGER NOT NULL
+);
+CREATE INDEX idx_observation_repeats_obs ON observation_repeats(obs_id, timestamp);",
+        ),
+        M::up(
+            "CREATE TABLE test_failures (
+    id         INTEGER PRIMARY KEY,
+    project    TEXT NOT NULL,
+    name       TEXT NOT NULL,
+    framework  TEXT NOT NULL,
+    first_seen INTEGER NOT NULL,
+    last_seen  INTEGER NOT NULL,
+    failures   INTEGER NOT NULL DEFAULT 1,
+    sessions   INTEGER NOT NULL DEFAULT 1,
+    UNIQUE (project, name)
+);
+CREATE TABLE test_failure_runs (
+    test_id    INTEGER NOT NULL REFERENCES test_failures(id) ON DELETE CASCADE,
+    obs_id     INTEGER NOT NULL REFERENCES observations(id) ON DELETE CASCADE,
+    session_id TEXT NOT NULL,
+    timestamp  INTEGER NOT NULL
+);
+CREATE INDEX idx_test_failure_runs_test ON test_failure_runs(test_id, timestamp);
+CREATE INDEX idx_test_failure_runs_obs ON test_failure_runs(obs_id);",
+        ),
+        M::up(
+            "CREATE TABLE memories (
+    id         INTEGER PRIMARY KEY,
+    project    TEXT,
+    key        TEXT NOT NULL,
+    content    TEXT NOT NULL,
+    created_at INTEGER NOT NULL,
+    updated_at INTEGER NOT NULL,
+    expires_at INTEGER
+);
+CREATE UNIQUE INDEX idx_memories_key ON memories(COALESCE(project, ''), key);",
+        ),
+        M::up(
+            "CREATE TABLE backfill_progress (
+    stage      TEXT PRIMARY KEY,
+    last_id    INTEGER NOT NULL DEFAULT 0,
+    done       INTEGER NOT NULL DEFAULT 0,
+    updated_at INTEGER NOT NULL
+);",
+        ),
+        M::up(
+            "CREATE TABLE deletions_log (
+    id        INTEGER PRIMARY KEY,
+    timestamp INTEGER NOT NULL,
+    operation TEXT NOT NULL,
+    actor     TEXT NOT NULL,
+    filter    TEXT NOT NULL,
+    deleted   INTEGER NOT NULL,
+    details   TEXT NOT NULL,
+    hash      TEXT
+);
+CREATE INDEX idx_deletions_log_ts ON deletions_log(timestamp);
+CREATE TRIGGER deletions_log_no_update BEFORE UPDATE ON deletions_log
+BEGIN SELECT RAISE(ABORT, 'deletions_log is append-only'); END;
+CREATE TRIGGER deletions_log_no_delete BEFORE DELETE ON deletions_log
+BEGIN SELECT RAISE(ABORT, 'deletions_log is append-only'); END;",
+        ),
+        M::up("ALTER TABLE prompts ADD COLUMN metadata TEXT;"),
+        M::up(
+            "CREATE TABLE rollups (
+    id           INTEGER PRIMARY KEY,
+    project      TEXT NOT NULL,
+    period       TEXT NOT NULL CHECK (period IN ('week', 'month')),
+    period_start INTEGER NOT NULL,
+    period_end   INTEGER NOT NULL,
+    summary      TEXT NOT NULL,
+    source_count INTEGER NOT NULL,
+    created_at   INTEGER NOT NULL,
+    UNIQUE(project, period, period_start)
+);
+CREATE INDEX idx_rollups_project ON rollups(project, period_end);",
+        ),
+        M::up(
+            "CREATE TRIGGER changelog_observation_chunks_i AFTER INSERT ON observation_chunks BEGIN
+    INSERT INTO changelog (tbl, row_id, op, changed_at) VALUES ('observations', new.obs_id, 'update', unixepoch());
+END;
+CREATE TRIGGER changelog_observation_chunks_d AFTER DELETE ON observation_chunks BEGIN
+    INSERT INTO changelog (tbl, row_id, op, changed_at) VALUES ('observations', old.obs_id, 'update', unixepoch());
+END;",
+        ),
     ])
 });
 
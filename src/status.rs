@@ -1,13 +1,22 @@
+#[cfg(feature = "cli")]
+use crate::cli::StatusArgs;
 use crate::db::{is_db_encrypted, open_db_readonly};
 use crate::NmemError;
 use std::path::Path;
 
-pub fn handle_status(db_path: &Path) -> Result<(), NmemError> {
+#[cfg(feature = "cli")]
+pub fn handle_status(db_path: &Path, args: &StatusArgs) -> Result<(), NmemError> {
     if !db_path.exists() {
         log::info!("no database at {}", db_path.display());
         return Ok(());
     }
 
+    if args.sessions {
+        let config = crate::s5_config::load_config().unwrap_or_default();
+        let conn = open_db_readonly(db_path)?;
+        return crate::s3_capture::print_sessions(&conn, args, config.record.session_alert_mb * 1024 * 1024);
+    }
+
     // File sizes
     let db_size = std::fs::metadata(db_path)?.len();
     let wal_path = db_path.with_extension("db-wal");
@@ -29,6 +38,28 @@ pub fn handle_status(db_path: &Path) -> Result<(), NmemError> {
         .unwrap()
         .collect::<Result<_, _>>()?;
 
+    // Failures by error class
+    let mut stmt = conn.prepare(
+        "SELECT error_class, COUNT(*) FROM observations WHERE error_class IS NOT NULL
+         GROUP BY error_class ORDER BY COUNT(*) DESC",
+    )?;
+    let error_breakdown: Vec<(String, i64)> = stmt
+        .query_map([], |r| Ok((r.get(0)?, r.get(1)?)))?
+        .collect::<Result<_, _>>()?;
+
+    // MCP calls by server, split by project
+    let mut stmt = conn.prepare(
+        "SELECT o.mcp_server, s.project, COUNT(*) FROM observations o
+         JOIN sessions s ON s.id = o.session_id
+         WHERE o.mcp_server IS NOT NULL
+         GROUP BY o.mcp_server, s.project
+         ORDER BY o.mcp_server, COUNT(*) DESC, s.project",
+    )?;
+    let rows: Vec<(String, String, i64)> = stmt
+        .query_map([], |r| Ok((r.get(0)?, r.get(1)?, r.get(2)?)))?
+        .collect::<Result<_, _>>()?;
+    let mcp_breakdown = group_mcp_usage(rows);
+
     // Last session
     let last_session: Option<(i64, String)> = conn
         .query_row(
@@ -63,12 +94,56 @@ pub fn handle_status(db_path: &Path) -> Result<(), NmemError> {
         log::info!("pinned — {pinned}");
     }
 
+    if !error_breakdown.is_empty() {
+        let total: i64 = error_breakdown.iter().map(|(_, c)| c).sum();
+        let parts: Vec<String> = error_breakdown
+            .iter()
+            .map(|(class, c)| format!("{class}: {c}"))
+            .collect();
+        log::info!("errors — {total} ({0})", parts.join(", "));
+    }
+
+    for (server, total, projects) in &mcp_breakdown {
+        let parts: Vec<String> = projects.iter().map(|(p, c)| format!("{p} {c}")).collect();
+        log::info!("mcp — {server}: {total} ({0})", parts.join(", "));
+    }
+
+    let now = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs() as i64;
+    if let Ok(pauses) = crate::s3_pause::list_active(&conn, now) {
+        for p in &pauses {
+            log::info!("paused — {}", p.describe());
+        }
+    }
+    if let Ok(rules) = crate::s3_suppress::list_active(&conn, now) {
+        for rule in &rules {
+            log::info!("suppressed — {}", rule.describe());
+        }
+    }
+
+    let pending = crate::s3_pending::pending_count(&conn)?;
+    if pending > 0 {
+        log::info!("pending work — {pending} (spilled past the hook latency budget)");
+    }
+
+    // Absent on databases not yet migrated by a hook
+    if let Ok((raw, stored)) = crate::s3_capture::totals(&conn)
+        && raw > 0
+    {
+        log::info!(
+            "captured — {} stored of {} raw (nmem status --sessions for the largest)",
+            fmt_size(stored.max(0) as u64),
+            fmt_size(raw.max(0) as u64)
+        );
+    }
+
     log::info!("prompts — {prompt_count}");
     log::info!("sessions — {session_count}");
 
     if let Some((ts, project)) = last_session {
-        let date = format_epoch_date(ts);
-        log::info!("last session — {date} (project: {project})");
+        log::info!("last session — {} (project: {project})", crate::timefmt::relative(ts));
     }
 
     let encrypted = is_db_encrypted(db_path);
@@ -80,31 +155,26 @@ pub fn handle_status(db_path: &Path) -> Result<(), NmemError> {
     Ok(())
 }
 
-fn format_epoch_date(epoch_secs: i64) -> String {
-    // Convert epoch seconds to YYYY-MM-DD
-    // Days from unix epoch, then civil date
-    let days = epoch_secs / 86400;
-    let (y, m, d) = civil_from_days(days);
-    format!("{y:04}-{m:02}-{d:02}")
-}
-
-/// Convert days since 1970-01-01 to (year, month, day).
-/// Algorithm from Howard Hinnant's chrono-compatible date library.
-fn civil_from_days(days: i64) -> (i64, u32, u32) {
-    let z = days + 719468;
-    let era = if z >= 0 { z } else { z - 146096 } / 146097;
-    let doe = (z - era * 146097) as u32;
-    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
-    let y = yoe as i64 + era * 400;
-    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
-    let mp = (5 * doy + 2) / 153;
-    let d = doy - (153 * mp + 2) / 5 + 1;
-    let m = if mp < 10 { mp + 3 } else { mp - 9 };
-    let y = if m <= 2 { y + 1 } else { y };
-    (y, m, d)
+/// One MCP server: total calls and per-project counts.
+type McpUsage = (String, i64, Vec<(String, i64)>);
+
+/// Fold `(server, project, count)` rows into per-server totals, busiest first.
+fn group_mcp_usage(rows: Vec<(String, String, i64)>) -> Vec<McpUsage> {
+    let mut grouped: Vec<McpUsage> = Vec::new();
+    for (server, project, count) in rows {
+        match grouped.last_mut() {
+            Some((s, total, projects)) if *s == server => {
+                *total += count;
+                projects.push((project, count));
+            }
+            _ => grouped.push((server, count, vec![(project, count)])),
+        }
+    }
+    grouped.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+    grouped
 }
 
-fn fmt_size(bytes: u64) -> String {
+pub(crate) fn fmt_size(bytes: u64) -> String {
     if bytes < 1024 {
         format!("{bytes} B")
     } else if bytes < 1024 * 1024 {
@@ -0,0 +1,245 @@
+//! Human-facing timestamps.
+//!
+//! Everything nmem prints for people — status, context, reports, task and
+//! purge listings — goes through here so times read the same everywhere:
+//! relative (`2h ago`) where recency is the point, absolute local time where
+//! an exact moment is. JSON output keeps raw Unix seconds.
+//!
+//! The zone is `[display] timezone` from config: `local` (the default),
+//! `utc`, or a fixed offset such as `+05:30`. `local` asks `date +%z` once per
+//! process (so `TZ` is honored) and applies that offset to every timestamp; a
+//! time from the other side of a DST change prints an hour off.
+
+use crate::NmemError;
+use std::process::Command;
+use std::sync::LazyLock;
+
+/// Offset from UTC in seconds, resolved once per process.
+static OFFSET: LazyLock<i64> = LazyLock::new(|| {
+    let setting = crate::s5_config::load_config().ok().and_then(|c| c.display.timezone);
+    match setting.as_deref().map(parse_zone) {
+        Some(Ok(Some(offset))) => offset,
+        _ => local_offset().unwrap_or(0),
+    }
+});
+
+const MONTHS: [&str; 12] = ["Jan", "Feb", "Mar", "Apr", "May", "Jun", "Jul", "Aug", "Sep", "Oct", "Nov", "Dec"];
+
+/// Parse a `[display] timezone` value: `Ok(None)` for `local`, otherwise the
+/// fixed offset in seconds.
+pub fn parse_zone(s: &str) -> Result<Option<i64>, NmemError> {
+    let bad = || NmemError::Config(format!("display.timezone: {s:?} — expected \"local\", \"utc\", or an offset like \"+05:30\""));
+    let s = s.trim();
+    if s.eq_ignore_ascii_case("local") {
+        return Ok(None);
+    }
+    if s.eq_ignore_ascii_case("utc") || s == "Z" {
+        return Ok(Some(0));
+    }
+    let (sign, rest) = match s.as_bytes().first() {
+        Some(b'+') => (1, &s[1..]),
+        Some(b'-') => (-1, &s[1..]),
+        _ => return Err(bad()),
+    };
+    let digits: String = rest.chars().filter(|c| *c != ':').collect();
+    if digits.is_empty() || digits.len() > 4 || !digits.chars().all(|c| c.is_ascii_digit()) {
+        return Err(bad());
+    }
+    let (hours, minutes) = if digits.len() <= 2 { (&digits[..], "0") } else { digits.split_at(digits.len() - 2) };
+    let (hours, minutes): (i64, i64) = (hours.parse().map_err(|_| bad())?, minutes.parse().map_err(|_| bad())?);
+    if hours > 14 || minutes > 59 {
+        return Err(bad());
+    }
+    Ok(Some(sign * (hours * 3600 + minutes * 60)))
+}
+
+fn local_offset() -> Option<i64> {
+    let output = Command::new("date").arg("+%z").output().ok()?;
+    parse_zone(String::from_utf8_lossy(&output.stdout).trim()).ok().flatten()
+}
+
+fn now_secs() -> i64 {
+    std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs() as i64
+}
+
+/// Convert days since 1970-01-01 to (year, month, day).
+/// Algorithm from Howard Hinnant's chrono-compatible date library.
+pub(crate) fn civil_from_days(days: i64) -> (i64, u32, u32) {
+    let z = days + 719468;
+    let era = if z >= 0 { z } else { z - 146096 } / 146097;
+    let doe = (z - era * 146097) as u32;
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+    let y = yoe as i64 + era * 400;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let d = doy - (153 * mp + 2) / 5 + 1;
+    let m = if mp < 10 { mp + 3 } else { mp - 9 };
+    let y = if m <= 2 { y + 1 } else { y };
+    (y, m, d)
+}
+
+/// Days since 1970-01-01 of (year, month, day); the inverse of
+/// `civil_from_days`.
+pub(crate) fn days_from_civil(y: i64, m: u32, d: u32) -> i64 {
+    let y = if m <= 2 { y - 1 } else { y };
+    let era = if y >= 0 { y } else { y - 399 } / 400;
+    let yoe = (y - era * 400) as u32;
+    let mp = if m > 2 { m - 3 } else { m + 9 };
+    let doy = (153 * mp + 2) / 5 + d - 1;
+    let doe = yoe * 365 + yoe / 4 - yoe / 100 + doy;
+    era * 146097 + doe as i64 - 719468
+}
+
+/// Days since 1970-01-01 of a `YYYY-MM-DD` date, or `None` when it isn't
+/// one (including days a month doesn't have).
+pub(crate) fn days_from_date(s: &str) -> Option<i64> {
+    let mut parts = s.trim().splitn(3, '-');
+    let y: i64 = parts.next()?.parse().ok()?;
+    let m: u32 = parts.next()?.parse().ok()?;
+    let d: u32 = parts.next()?.parse().ok()?;
+    let days = days_from_civil(y, m, d);
+    (civil_from_days(days) == (y, m, d)).then_some(days)
+}
+
+/// The display zone's offset from UTC in seconds.
+pub fn offset() -> i64 {
+    *OFFSET
+}
+
+/// Local (year, month, day, seconds into the day) of `ts` at `offset`.
+fn civil(ts: i64, offset: i64) -> (i64, u32, u32, i64) {
+    let local = ts + offset;
+    let (y, m, d) = civil_from_days(local.div_euclid(86400));
+    (y, m, d, local.rem_euclid(86400))
+}
+
+fn offset_label(offset: i64, utc: &str) -> String {
+    if offset == 0 {
+        return utc.to_string();
+    }
+    let sign = if offset < 0 { '-' } else { '+' };
+    let abs = offset.abs();
+    format!("{sign}{:02}:{:02}", abs / 3600, (abs % 3600) / 60)
+}
+
+/// Seconds since midnight of `ts`, in the display zone.
+pub fn time_of_day(ts: i64) -> i64 {
+    civil(ts, *OFFSET).3
+}
+
+/// `2026-10-16`, in the display zone.
+pub fn date(ts: i64) -> String {
+    date_at(ts, *OFFSET)
+}
+
+fn date_at(ts: i64, offset: i64) -> String {
+    let (y, m, d, _) = civil(ts, offset);
+    format!("{y:04}-{m:02}-{d:02}")
+}
+
+/// `2026-10-16 14:03 +02:00` (or `UTC`), in the display zone.
+pub fn datetime(ts: i64) -> String {
+    datetime_at(ts, *OFFSET)
+}
+
+fn datetime_at(ts: i64, offset: i64) -> String {
+    let (y, m, d, secs) = civil(ts, offset);
+    format!("{y:04}-{m:02}-{d:02} {:02}:{:02} {}", secs / 3600, (secs % 3600) / 60, offset_label(offset, "UTC"))
+}
+
+/// ISO 8601 with the display zone's offset, e.g. `2026-10-16T14:03:00+02:00`
+/// (`Z` in UTC). For reports.
+pub fn iso(ts: i64) -> String {
+    iso_at(ts, *OFFSET)
+}
+
+fn iso_at(ts: i64, offset: i64) -> String {
+    let (y, m, d, secs) = civil(ts, offset);
+    format!(
+        "{y:04}-{m:02}-{d:02}T{:02}:{:02}:{:02}{}",
+        secs / 3600,
+        (secs % 3600) / 60,
+        secs % 60,
+        offset_label(offset, "Z")
+    )
+}
+
+/// `30m ago`, `5h ago`, `3d ago`; older than a week, the local date
+/// (`Oct 02`, with the year when it isn't this one).
+pub fn relative(ts: i64) -> String {
+    relative_at(ts, now_secs(), *OFFSET)
+}
+
+fn relative_at(ts: i64, now: i64, offset: i64) -> String {
+    let diff = now - ts;
+    if diff < 3600 {
+        format!("{}m ago", (diff / 60).max(1))
+    } else if diff < 86400 {
+        format!("{}h ago", diff / 3600)
+    } else if diff < 604800 {
+        format!("{}d ago", diff / 86400)
+    } else {
+        let (year, month, day, _) = civil(ts, offset);
+        let month_name = MONTHS[month as usize - 1];
+        if year == civil(now, offset).0 {
+            format!("{month_name} {day:02}")
+        } else {
+            format!("{month_name} {day:02}, {year}")
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const TS: i64 = 1_792_159_380; // 2026-10-16 14:03 UTC
+
+    #[test]
+    fn dates_round_trip_through_days() {
+        assert_eq!(days_from_date("1970-01-01"), Some(0));
+        assert_eq!(days_from_date("2026-10-16"), Some(TS.div_euclid(86400)));
+        assert_eq!(days_from_date("2024-02-29").map(civil_from_days), Some((2024, 2, 29)));
+        assert_eq!(days_from_date("2026-02-29"), None);
+        assert_eq!(days_from_date("2026-13-01"), None);
+        assert_eq!(days_from_date("next tuesday"), None);
+    }
+
+    #[test]
+    fn parse_zone_accepts_local_utc_and_offsets() {
+        assert_eq!(parse_zone("local").unwrap(), None);
+        assert_eq!(parse_zone("UTC").unwrap(), Some(0));
+        assert_eq!(parse_zone("+05:30").unwrap(), Some(19800));
+        assert_eq!(parse_zone("-0800").unwrap(), Some(-28800));
+        assert_eq!(parse_zone("+2").unwrap(), Some(7200));
+        for bad in ["", "Europe/Paris", "+25", "+05:75", "0530"] {
+            assert!(parse_zone(bad).is_err(), "{bad:?} should be rejected");
+        }
+    }
+
+    #[test]
+    fn absolute_forms_follow_the_offset() {
+        assert_eq!(datetime_at(0, 0), "1970-01-01 00:00 UTC");
+        assert_eq!(datetime_at(TS, 0), "2026-10-16 14:03 UTC");
+        assert_eq!(datetime_at(TS, 19800), "2026-10-16 19:33 +05:30");
+        assert_eq!(iso_at(TS, 0), "2026-10-16T14:03:00Z");
+        assert_eq!(iso_at(TS, -36000), "2026-10-16T04:03:00-10:00");
+        // Crosses midnight into the next local day
+        assert_eq!(date_at(TS, 36000), "2026-10-17");
+    }
+
+    #[test]
+    fn relative_forms() {
+        assert_eq!(relative_at(TS - 30 * 60, TS, 0), "30m ago");
+        assert_eq!(relative_at(TS - 10, TS, 0), "1m ago");
+        assert_eq!(relative_at(TS - 5 * 3600, TS, 0), "5h ago");
+        assert_eq!(relative_at(TS - 3 * 86400, TS, 0), "3d ago");
+        assert_eq!(relative_at(TS - 14 * 86400, TS, 0), "Oct 02");
+        assert_eq!(relative_at(TS - 400 * 86400, TS, 0), "Sep 11, 2025");
+        // The date is the local one
+        assert_eq!(relative_at(TS - 14 * 86400, TS, 36000), "Oct 03");
+    }
+}
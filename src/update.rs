@@ -0,0 +1,334 @@
+//! Self-update from a release manifest.
+//!
+//! Every hook runs the nmem binary, so a stale or corrupted one breaks
+//! capture everywhere at once. `[update] endpoint` names a JSON manifest:
+//!
+//! ```json
+//! {"version": "1.3.0",
+//!  "assets": {"x86_64-linux": {"url": "https://…/nmem-x86_64-linux", "sha256": "…"}}}
+//! ```
+//!
+//! keyed by `<arch>-<os>`. Beside it, `<endpoint>.sig` holds the hex
+//! ed25519 signature of the manifest's exact bytes, made with the release
+//! key whose public half is compiled in from `NMEM_RELEASE_KEY` (hex) at
+//! build time. A manifest is not read until its signature verifies, so the
+//! checksums in it — and the download they vouch for — are only trusted when
+//! the release key signed them; a build without a key refuses to update.
+//!
+//! `nmem version --check` compares the manifest with the running version;
+//! `nmem self-update` downloads this platform's asset, checks its SHA-256
+//! against the manifest, runs the download with `--version` to confirm it
+//! starts and is the version promised, and renames
+//! it over the running binary — a rename on the same filesystem, so a hook
+//! starting mid-update sees the old binary or the new one, never half of
+//! either. Only `https://` and `file://` (local mirrors) URLs are fetched.
+//! Homebrew installs are left to `brew upgrade nmem`.
+
+#[cfg(feature = "cli")]
+use crate::cli::{SelfUpdateArgs, VersionArgs};
+use crate::NmemError;
+use ed25519_dalek::{Signature, VerifyingKey};
+use serde::Deserialize;
+use sha2::{Digest, Sha256};
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+
+/// Version of the running binary.
+pub const CURRENT: &str = env!("CARGO_PKG_VERSION");
+
+/// Hex ed25519 public key release manifests are signed with, fixed at build
+/// time.
+const RELEASE_KEY: Option<&str> = option_env!("NMEM_RELEASE_KEY");
+
+/// Largest asset downloaded.
+const MAX_ASSET_BYTES: u64 = 256 * 1024 * 1024;
+
+#[derive(Debug, Deserialize)]
+pub struct Manifest {
+    pub version: String,
+    #[serde(default)]
+    pub assets: HashMap<String, Asset>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct Asset {
+    pub url: String,
+    /// Hex SHA-256 of the binary.
+    pub sha256: String,
+}
+
+/// Manifest key of the platform this binary was built for.
+pub fn target() -> String {
+    format!("{}-{}", std::env::consts::ARCH, std::env::consts::OS)
+}
+
+/// Numeric parts of a version, ignoring a leading `v` and any pre-release
+/// or build suffix: `v1.10.0-rc1` is `[1, 10, 0]`.
+fn parts(version: &str) -> Vec<u64> {
+    let version = version.trim().trim_start_matches('v');
+    let core = version.split(['-', '+']).next().unwrap_or(version);
+    core.split('.').map(|p| p.parse().unwrap_or(0)).collect()
+}
+
+/// Whether `candidate` is a newer release than `current`.
+pub fn is_newer(candidate: &str, current: &str) -> bool {
+    let (mut a, mut b) = (parts(candidate), parts(current));
+    let len = a.len().max(b.len());
+    a.resize(len, 0);
+    b.resize(len, 0);
+    a > b
+}
+
+/// Whether `exe` lives in a Homebrew Cellar.
+pub fn is_homebrew(exe: &Path) -> bool {
+    exe.components().any(|c| c.as_os_str() == "Cellar")
+}
+
+/// Fetch an `https://` or `file://` URL.
+pub fn fetch(url: &str) -> Result<Vec<u8>, NmemError> {
+    if let Some(path) = url.strip_prefix("file://") {
+        return Ok(std::fs::read(path)?);
+    }
+    if !url.starts_with("https://") {
+        return Err(NmemError::Config(format!("update: refusing non-https URL {url}")));
+    }
+    let agent = ureq::Agent::new_with_config(
+        ureq::config::Config::builder()
+            .timeout_global(Some(std::time::Duration::from_secs(120)))
+            .build(),
+    );
+    let mut response = agent
+        .get(url)
+        .call()
+        .map_err(|e| NmemError::Config(format!("update: fetching {url}: {e}")))?;
+    response
+        .body_mut()
+        .with_config()
+        .limit(MAX_ASSET_BYTES)
+        .read_to_vec()
+        .map_err(|e| NmemError::Config(format!("update: reading {url}: {e}")))
+}
+
+/// The key this binary trusts to sign release manifests.
+pub fn release_key() -> Result<VerifyingKey, NmemError> {
+    let hex = RELEASE_KEY.ok_or_else(|| {
+        NmemError::Config("update: this build has no release key (NMEM_RELEASE_KEY); update it by reinstalling".into())
+    })?;
+    from_hex(hex)
+        .and_then(|bytes| VerifyingKey::from_bytes(&bytes).ok())
+        .ok_or_else(|| NmemError::Config("update: the compiled-in release key is not a hex ed25519 key".into()))
+}
+
+fn from_hex<const N: usize>(hex: &str) -> Option<[u8; N]> {
+    let hex = hex.trim();
+    if hex.len() != N * 2 || !hex.is_ascii() {
+        return None;
+    }
+    let mut out = [0u8; N];
+    for (i, byte) in out.iter_mut().enumerate() {
+        *byte = u8::from_str_radix(&hex[i * 2..i * 2 + 2], 16).ok()?;
+    }
+    Some(out)
+}
+
+/// Check `body` against a detached hex ed25519 `signature` by `key`.
+pub fn verify_manifest(body: &[u8], signature: &[u8], key: &VerifyingKey) -> Result<(), NmemError> {
+    let signature = std::str::from_utf8(signature)
+        .ok()
+        .and_then(from_hex::<64>)
+        .map(|bytes| Signature::from_bytes(&bytes))
+        .ok_or_else(|| NmemError::Config("update: manifest signature is not 64 hex-encoded bytes".into()))?;
+    key.verify_strict(body, &signature)
+        .map_err(|_| NmemError::Config("update: manifest signature does not match the release key".into()))
+}
+
+/// Fetch the manifest at `endpoint` and its signature at `<endpoint>.sig`,
+/// and parse the manifest only once the signature verifies against `key`.
+pub fn fetch_manifest(endpoint: &str, key: &VerifyingKey) -> Result<Manifest, NmemError> {
+    let body = fetch(endpoint)?;
+    let signature = fetch(&format!("{endpoint}.sig"))?;
+    verify_manifest(&body, &signature, key)?;
+    serde_json::from_slice(&body).map_err(|e| NmemError::Config(format!("update: bad manifest at {endpoint}: {e}")))
+}
+
+/// Hex SHA-256 of `bytes`.
+pub fn sha256_hex(bytes: &[u8]) -> String {
+    Sha256::digest(bytes).iter().map(|b| format!("{b:02x}")).collect()
+}
+
+/// Check `bytes` against the manifest's checksum.
+pub fn verify(bytes: &[u8], asset: &Asset) -> Result<(), NmemError> {
+    let actual = sha256_hex(bytes);
+    if !actual.eq_ignore_ascii_case(asset.sha256.trim()) {
+        return Err(NmemError::Config(format!(
+            "update: checksum mismatch for {}: manifest says {}, download is {actual}",
+            asset.url, asset.sha256
+        )));
+    }
+    Ok(())
+}
+
+/// Write `bytes` beside `exe`, confirm it runs and reports `version`, and
+/// rename it over `exe`. The staged file is removed when any step fails.
+/// Runs the download, so call it only once [`verify`] has matched it to a
+/// manifest [`fetch_manifest`] authenticated.
+pub fn replace(exe: &Path, bytes: &[u8], version: &str) -> Result<(), NmemError> {
+    let staged = staged_path(exe);
+    let install = || -> Result<(), NmemError> {
+        std::fs::write(&staged, bytes)?;
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            std::fs::set_permissions(&staged, std::fs::Permissions::from_mode(0o755))?;
+        }
+        let out = std::process::Command::new(&staged).arg("--version").output()?;
+        let reported = String::from_utf8_lossy(&out.stdout);
+        if !out.status.success() || !reported.split_whitespace().any(|w| parts(w) == parts(version)) {
+            return Err(NmemError::Config(format!(
+                "update: downloaded binary reports {:?}, expected {version}",
+                reported.trim()
+            )));
+        }
+        std::fs::rename(&staged, exe)?;
+        Ok(())
+    };
+    install().inspect_err(|_| {
+        let _ = std::fs::remove_file(&staged);
+    })
+}
+
+fn staged_path(exe: &Path) -> PathBuf {
+    let mut name = exe.file_name().unwrap_or_default().to_os_string();
+    name.push(".update");
+    exe.with_file_name(name)
+}
+
+fn endpoint(config: &crate::s5_config::NmemConfig) -> Result<&str, NmemError> {
+    config
+        .update
+        .endpoint
+        .as_deref()
+        .ok_or_else(|| NmemError::Config("no release endpoint: set [update] endpoint in config.toml".into()))
+}
+
+#[cfg(feature = "cli")]
+pub fn handle_version(args: &VersionArgs) -> Result<(), NmemError> {
+    println!("nmem {CURRENT}");
+    if !args.check {
+        return Ok(());
+    }
+    let config = crate::s5_config::load_config()?;
+    let manifest = fetch_manifest(endpoint(&config)?, &release_key()?)?;
+    if !is_newer(&manifest.version, CURRENT) {
+        log::info!("up to date (latest {})", manifest.version);
+        return Ok(());
+    }
+    let how = match std::env::current_exe().and_then(std::fs::canonicalize) {
+        Ok(exe) if is_homebrew(&exe) => "brew upgrade nmem",
+        _ => "nmem self-update",
+    };
+    log::info!("{} available — run `{how}`", manifest.version);
+    Ok(())
+}
+
+#[cfg(feature = "cli")]
+pub fn handle_self_update(args: &SelfUpdateArgs) -> Result<(), NmemError> {
+    // Replace the file itself, not a symlink to it
+    let exe = std::env::current_exe()?;
+    let exe = std::fs::canonicalize(&exe).unwrap_or(exe);
+    if is_homebrew(&exe) {
+        return Err(NmemError::Config("nmem was installed by Homebrew: run `brew upgrade nmem`".into()));
+    }
+    let config = crate::s5_config::load_config()?;
+    let manifest = fetch_manifest(endpoint(&config)?, &release_key()?)?;
+    if !args.force && !is_newer(&manifest.version, CURRENT) {
+        log::info!("nmem {CURRENT} is up to date (latest {})", manifest.version);
+        return Ok(());
+    }
+    let target = target();
+    let asset = manifest
+        .assets
+        .get(&target)
+        .ok_or_else(|| NmemError::Config(format!("update: release {} has no build for {target}", manifest.version)))?;
+    let bytes = fetch(&asset.url)?;
+    verify(&bytes, asset)?;
+    if args.dry_run {
+        log::info!("{} for {target} verified ({} bytes) — dry run, not installed", manifest.version, bytes.len());
+        return Ok(());
+    }
+    replace(&exe, &bytes, &manifest.version).map_err(|e| e.context_path("self-update", &exe))?;
+    log::info!("updated {} from {CURRENT} to {}", exe.display(), manifest.version);
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn compares_versions_numerically() {
+        assert!(is_newer("1.10.0", "1.9.3"));
+        assert!(is_newer("v1.2.1", "1.2"));
+        assert!(!is_newer("1.2.0", "1.2.0"));
+        assert!(!is_newer("1.2.0-rc1", "1.2.0"));
+        assert!(!is_newer("0.9", "1.2.0"));
+    }
+
+    #[test]
+    fn checksum_mismatch_is_rejected() {
+        let asset = Asset { url: "file:///x".into(), sha256: sha256_hex(b"good").to_uppercase() };
+        assert!(verify(b"good", &asset).is_ok());
+        assert!(verify(b"tampered", &asset).is_err());
+    }
+
+    #[test]
+    fn manifest_is_read_only_with_a_valid_signature() {
+        use ed25519_dalek::{Signer, SigningKey};
+
+        let dir = std::env::temp_dir().join(format!("nmem-update-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let manifest = dir.join("latest.json");
+        let endpoint = format!("file://{}", manifest.display());
+        let signing = SigningKey::from_bytes(&[7; 32]);
+        let hex = |bytes: &[u8]| bytes.iter().map(|b| format!("{b:02x}")).collect::<String>();
+        let body = br#"{"version":"9.9.9","assets":{}}"#;
+        std::fs::write(&manifest, body).unwrap();
+
+        assert!(fetch_manifest(&endpoint, &signing.verifying_key()).is_err(), "unsigned manifest is refused");
+
+        std::fs::write(dir.join("latest.json.sig"), hex(&signing.sign(body).to_bytes())).unwrap();
+        assert_eq!(fetch_manifest(&endpoint, &signing.verifying_key()).unwrap().version, "9.9.9");
+
+        let other = SigningKey::from_bytes(&[8; 32]);
+        let err = fetch_manifest(&endpoint, &other.verifying_key()).unwrap_err();
+        assert!(err.to_string().contains("does not match the release key"), "{err}");
+
+        std::fs::write(&manifest, br#"{"version":"6.6.6","assets":{}}"#).unwrap();
+        assert!(fetch_manifest(&endpoint, &signing.verifying_key()).is_err(), "tampered manifest is refused");
+        std::fs::write(dir.join("latest.json.sig"), "not hex").unwrap();
+        assert!(fetch_manifest(&endpoint, &signing.verifying_key()).is_err());
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn replace_installs_only_a_binary_reporting_the_version() {
+        let dir = std::env::temp_dir().join(format!("nmem-replace-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let exe = dir.join("nmem");
+        std::fs::write(&exe, "old").unwrap();
+        let script = b"#!/bin/sh\necho 'nmem 9.9.9'\n";
+        assert!(replace(&exe, script, "9.9.8").is_err());
+        assert_eq!(std::fs::read(&exe).unwrap(), b"old");
+        assert!(!staged_path(&exe).exists());
+        replace(&exe, script, "9.9.9").unwrap();
+        assert_eq!(std::fs::read(&exe).unwrap(), script);
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn detects_homebrew_cellar() {
+        assert!(is_homebrew(Path::new("/opt/homebrew/Cellar/nmem/1.2.0/bin/nmem")));
+        assert!(!is_homebrew(Path::new("/home/me/.local/bin/nmem")));
+    }
+}
@@ -0,0 +1,97 @@
+//! Golden-file tests for SessionStart context.
+//!
+//! Each case builds the seeded fixture database, renders a context, replaces
+//! everything clock-dependent (relative ages, dates) with placeholders, and
+//! compares the result to `tests/golden/<case>.md`. A ranking or formatting
+//! change in `s4_context` then shows up as a diff of those files in review.
+//!
+//! After an intended change, regenerate with
+//! `NMEM_BLESS=1 cargo test --test context_golden` and commit the goldens.
+
+use nmem::fixtures::{FixtureSpec, generate};
+use nmem::s4_context::{generate_context, generate_profiled_context};
+use nmem::s5_config::{NmemConfig, resolve_context_profile};
+use regex::Regex;
+use rusqlite::Connection;
+use std::path::PathBuf;
+
+fn fixture() -> (Connection, i64) {
+    let mut conn = Connection::open_in_memory().unwrap();
+    nmem::schema_migrations().to_latest(&mut conn).unwrap();
+    nmem::db::register_udfs(&conn).unwrap();
+    // Ages are relative to the real clock so the recency-sensitive queries
+    // see the same data shape on every run
+    let spec = FixtureSpec::default();
+    let now = spec.now;
+    generate(&conn, &spec).unwrap();
+    (conn, now)
+}
+
+fn normalize(text: &str) -> String {
+    let rules = [
+        (r"\b\d+[mhd] ago", "<ago>"),
+        (r"\b(Jan|Feb|Mar|Apr|May|Jun|Jul|Aug|Sep|Oct|Nov|Dec) \d{2}(, \d{4})?( \d{2}:\d{2})?", "<date>"),
+        (r"\b\d{4}-\d{2}-\d{2}([ T]\d{2}:\d{2}(:\d{2})?)?", "<date>"),
+    ];
+    let mut out = text.to_string();
+    for (pattern, placeholder) in rules {
+        out = Regex::new(pattern).unwrap().replace_all(&out, placeholder).into_owned();
+    }
+    out
+}
+
+fn check(case: &str, context: &str) {
+    let path = PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("tests/golden").join(format!("{case}.md"));
+    let actual = normalize(context);
+    if std::env::var_os("NMEM_BLESS").is_some() || !path.exists() {
+        std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+        std::fs::write(&path, &actual).unwrap();
+        return;
+    }
+    let expected = std::fs::read_to_string(&path).unwrap();
+    if actual != expected {
+        let first = actual
+            .lines()
+            .zip(expected.lines())
+            .position(|(a, e)| a != e)
+            .unwrap_or_else(|| actual.lines().count().min(expected.lines().count()));
+        panic!(
+            "context for {case} differs from {} at line {}:\n  expected: {:?}\n  actual:   {:?}\n\
+             rerun with NMEM_BLESS=1 if the change is intended\n\n{actual}",
+            path.display(),
+            first + 1,
+            expected.lines().nth(first).unwrap_or("<end>"),
+            actual.lines().nth(first).unwrap_or("<end>"),
+        );
+    }
+}
+
+#[test]
+fn startup_context_matches_golden() {
+    let (conn, _) = fixture();
+    let context = generate_context(&conn, "atlas", 20, 10, None, None).unwrap();
+    assert!(!context.is_empty());
+    check("startup_atlas", &context);
+}
+
+#[test]
+fn compact_context_matches_golden() {
+    let (conn, _) = fixture();
+    let session: String = conn
+        .query_row(
+            "SELECT id FROM sessions WHERE project = 'atlas' ORDER BY started_at DESC LIMIT 1",
+            [],
+            |r| r.get(0),
+        )
+        .unwrap();
+    let profile = resolve_context_profile(&NmemConfig::default(), "atlas", "compact");
+    let context = generate_profiled_context(&conn, "atlas", &profile, Some(&session), None, None).unwrap();
+    check("compact_atlas", &context);
+}
+
+#[test]
+fn historical_context_matches_golden() {
+    let (conn, now) = fixture();
+    let context = generate_context(&conn, "beacon", 20, 10, Some(now - 10 * 86400), None).unwrap();
+    check("historical_beacon", &context);
+}
@@ -82,6 +82,9 @@ fn dispatch_dry_run_does_not_change_status() {
             max_concurrent: 1,
             dry_run: true,
             tmux_session: "nmem-test".into(),
+            no_affinity: false,
+            no_context: false,
+            now: false,
         },
     )
     .unwrap();
@@ -115,6 +118,9 @@ fn reap_marks_completed_when_pane_gone() {
             max_concurrent: 1,
             dry_run: false,
             tmux_session: "nmem-test".into(),
+            no_affinity: false,
+            no_context: false,
+            now: false,
         },
     )
     .unwrap();
@@ -141,3 +141,54 @@ fn history_serializes_to_json() {
     assert!(json.contains("\"path\":\"f.txt\""));
     assert!(json.contains("\"total_commits\":1"));
 }
+
+fn rename_commit(repo: &Repository, from: &str, to: &str, message: &str, ts: i64) {
+    let workdir = repo.workdir().unwrap();
+    fs::rename(workdir.join(from), workdir.join(to)).unwrap();
+    let mut index = repo.index().unwrap();
+    index.remove_path(Path::new(from)).unwrap();
+    index.add_path(Path::new(to)).unwrap();
+    index.write().unwrap();
+    let tree = repo.find_tree(index.write_tree().unwrap()).unwrap();
+    let parent = repo.head().unwrap().peel_to_commit().unwrap();
+    repo.commit(Some("HEAD"), &sig(ts), &sig(ts), message, &tree, &[&parent]).unwrap();
+}
+
+const BODY: &str = "fn main() {\n    println!(\"hello\");\n}\n// padding so similarity detection has enough content\n";
+
+#[test]
+fn file_history_follows_renames() {
+    let dir = TempDir::new().unwrap();
+    let repo = Repository::init(dir.path()).unwrap();
+
+    make_commit(&repo, &[("old.rs", BODY), ("peer.rs", "p1")], "initial", 1000000);
+    make_commit(&repo, &[("old.rs", &format!("{BODY}// v2\n")), ("peer.rs", "p2")], "edit before rename", 1000100);
+    rename_commit(&repo, "old.rs", "new.rs", "rename", 1000200);
+    make_commit(&repo, &[("new.rs", &format!("{BODY}// v2\n// v3\n"))], "edit after rename", 1000300);
+
+    let hist = s1_git::file_history(dir.path(), "new.rs", &QueryOpts::default()).unwrap();
+    assert_eq!(hist.renamed_from, vec!["old.rs".to_string()]);
+    assert_eq!(hist.churn.total_commits, 4);
+    // Couplings from before the rename still count
+    assert_eq!(hist.co_changes[0].path, "peer.rs");
+    assert!(hist.co_changes.iter().all(|c| c.path != "old.rs"));
+}
+
+#[test]
+fn head_renames_reports_absolute_paths() {
+    let dir = TempDir::new().unwrap();
+    let repo = Repository::init(dir.path()).unwrap();
+
+    make_commit(&repo, &[("src/a.rs", BODY)], "initial", 1000000);
+    rename_commit(&repo, "src/a.rs", "src/b.rs", "rename", 1000100);
+
+    let renames = s1_git::head_renames(dir.path()).unwrap();
+    let workdir = repo.workdir().unwrap();
+    assert_eq!(
+        renames,
+        vec![(
+            workdir.join("src/a.rs").to_string_lossy().to_string(),
+            workdir.join("src/b.rs").to_string_lossy().to_string(),
+        )]
+    );
+}
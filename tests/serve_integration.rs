@@ -1,15 +1,21 @@
 use nmem::db::register_udfs;
 use nmem::serve::{
-    FileHistoryParams, GetObservationsParams, GitFileSummaryParams, NmemServer,
-    RecentContextParams, SearchParams, SessionSummariesParams, SessionTraceParams, TimelineParams,
+    BundleParams, BundleRequest, ConcludeInvestigationParams, CreateMarkerParams, FileHistoryParams, FileSnapshotParams, GetObservationsParams, GitFileSummaryParams, GrepParams,
+    MemoryUsageReportParams, NmemServer, PlanHistoryParams, QueueTaskParams, RecentContextParams, SearchParams, SessionSummariesParams, SessionTraceParams, StartInvestigationParams, TimelineParams, WorkingSetParams,
 };
 use rusqlite::Connection;
 use std::sync::{Arc, Mutex};
 
 fn test_db() -> Arc<Mutex<Connection>> {
+    let db = test_db_without_udfs();
+    register_udfs(&db.lock().unwrap()).unwrap();
+    db
+}
+
+/// The seeded store as `serve --lean` opens it: no scoring functions yet.
+fn test_db_without_udfs() -> Arc<Mutex<Connection>> {
     let mut conn = Connection::open_in_memory().unwrap();
     nmem::schema_migrations().to_latest(&mut conn).unwrap();
-    register_udfs(&conn).unwrap();
 
     conn.execute_batch(
         "
@@ -72,6 +78,17 @@ fn search_hyphenated_term_does_not_crash() {
             order_by: None,
             before: None,
             after: None,
+            window: None,
+            error_class: None,
+            mcp_server: None,
+            mcp_tool: None,
+            cwd: None,
+            facets: None,
+            infer_project: None,
+            include_archived: None,
+            cursor: None,
+            compact: None,
+            max_bytes: None,
         })
         .unwrap();
 
@@ -94,6 +111,17 @@ fn search_fts_operators_in_query() {
             order_by: None,
             before: None,
             after: None,
+            window: None,
+            error_class: None,
+            mcp_server: None,
+            mcp_tool: None,
+            cwd: None,
+            facets: None,
+            infer_project: None,
+            include_archived: None,
+            cursor: None,
+            compact: None,
+            max_bytes: None,
         })
         .unwrap();
 
@@ -113,6 +141,17 @@ fn search_empty_query_returns_empty() {
             order_by: None,
             before: None,
             after: None,
+            window: None,
+            error_class: None,
+            mcp_server: None,
+            mcp_tool: None,
+            cwd: None,
+            facets: None,
+            infer_project: None,
+            include_archived: None,
+            cursor: None,
+            compact: None,
+            max_bytes: None,
         })
         .unwrap();
 
@@ -133,6 +172,17 @@ fn search_finds_by_content() {
             order_by: None,
             before: None,
             after: None,
+            window: None,
+            error_class: None,
+            mcp_server: None,
+            mcp_tool: None,
+            cwd: None,
+            facets: None,
+            infer_project: None,
+            include_archived: None,
+            cursor: None,
+            compact: None,
+            max_bytes: None,
         })
         .unwrap();
 
@@ -154,6 +204,17 @@ fn search_filters_by_project() {
             order_by: None,
             before: None,
             after: None,
+            window: None,
+            error_class: None,
+            mcp_server: None,
+            mcp_tool: None,
+            cwd: None,
+            facets: None,
+            infer_project: None,
+            include_archived: None,
+            cursor: None,
+            compact: None,
+            max_bytes: None,
         })
         .unwrap();
 
@@ -176,6 +237,17 @@ fn search_filters_by_obs_type() {
             order_by: None,
             before: None,
             after: None,
+            window: None,
+            error_class: None,
+            mcp_server: None,
+            mcp_tool: None,
+            cwd: None,
+            facets: None,
+            infer_project: None,
+            include_archived: None,
+            cursor: None,
+            compact: None,
+            max_bytes: None,
         })
         .unwrap();
 
@@ -187,6 +259,221 @@ fn search_filters_by_obs_type() {
     }
 }
 
+#[test]
+fn search_filters_by_error_class() {
+    let server = make_server();
+    {
+        let db = server.db_handle();
+        let db = db.lock().unwrap();
+        db.execute("UPDATE observations SET error_class = 'test_failure' WHERE id = 4", []).unwrap();
+    }
+    let params = |class: &str| SearchParams {
+        query: "cargo".into(),
+        project: None,
+        obs_type: None,
+        limit: None,
+        offset: None,
+        order_by: None,
+        before: None,
+        after: None,
+        window: None,
+        error_class: Some(class.into()),
+        mcp_server: None,
+        mcp_tool: None,
+        cwd: None,
+        facets: None,
+        infer_project: None,
+        include_archived: None,
+        cursor: None,
+        compact: None,
+        max_bytes: None,
+    };
+
+    let arr = result_json(&server.do_search(params("test_failure")).unwrap());
+    let items = arr.as_array().unwrap();
+    assert_eq!(items.len(), 1);
+    assert_eq!(items[0]["id"], 4);
+
+    assert!(server.do_search(params("bogus")).is_err());
+}
+
+#[test]
+fn search_filters_by_mcp_server_and_tool() {
+    let server = make_server();
+    {
+        let db = server.db_handle();
+        let db = db.lock().unwrap();
+        db.execute_batch(
+            "INSERT INTO observations (id, session_id, prompt_id, timestamp, obs_type, source_event, tool_name, file_path, content, metadata, mcp_server, mcp_tool)
+                VALUES (7, 'sess-a', 1, 1707400070, 'mcp_call', 'PostToolUse', 'mcp__context7__query-docs', NULL, 'routing docs lookup', NULL, 'context7', 'query-docs');
+             INSERT INTO observations (id, session_id, prompt_id, timestamp, obs_type, source_event, tool_name, file_path, content, metadata, mcp_server, mcp_tool)
+                VALUES (8, 'sess-a', 1, 1707400080, 'mcp_call', 'PostToolUse', 'mcp__github__search_code', NULL, 'routing code lookup', NULL, 'github', 'search_code');",
+        )
+        .unwrap();
+    }
+    let params = |mcp_server: Option<&str>, mcp_tool: Option<&str>| SearchParams {
+        query: "routing".into(),
+        project: None,
+        obs_type: None,
+        limit: None,
+        offset: None,
+        order_by: None,
+        before: None,
+        after: None,
+        window: None,
+        error_class: None,
+        mcp_server: mcp_server.map(Into::into),
+        mcp_tool: mcp_tool.map(Into::into),
+        cwd: None,
+        facets: None,
+        infer_project: None,
+        include_archived: None,
+        cursor: None,
+        compact: None,
+        max_bytes: None,
+    };
+    let ids = |p: SearchParams| -> Vec<i64> {
+        let arr = result_json(&server.do_search(p).unwrap());
+        arr.as_array().unwrap().iter().map(|i| i["id"].as_i64().unwrap()).collect()
+    };
+
+    assert_eq!(ids(params(None, None)).len(), 2);
+    assert_eq!(ids(params(Some("context7"), None)), vec![7]);
+    assert_eq!(ids(params(None, Some("search_code"))), vec![8]);
+    assert!(ids(params(Some("context7"), Some("search_code"))).is_empty());
+}
+
+#[test]
+fn search_facets_count_the_full_match_set() {
+    let server = make_server();
+    let search = |facets: Option<bool>| {
+        result_json(
+            &server
+                .do_search(SearchParams {
+                    query: "auth OR cargo OR main".into(),
+                    project: None,
+                    obs_type: None,
+                    limit: Some(2),
+                    offset: None,
+                    order_by: None,
+                    before: None,
+                    after: None,
+                    window: None,
+                    error_class: None,
+                    mcp_server: None,
+                    mcp_tool: None,
+                    cwd: None,
+                    facets,
+                    infer_project: None,
+                    include_archived: None,
+                    cursor: None,
+                    compact: None,
+                    max_bytes: None,
+                })
+                .unwrap(),
+        )
+    };
+
+    assert!(search(None).is_array(), "without facets the response is unchanged");
+
+    let json = search(Some(true));
+    assert_eq!(json["results"].as_array().unwrap().len(), 2);
+    let facets = &json["facets"];
+    assert_eq!(facets["total"], 6);
+    assert_eq!(facets["sessions"], 2);
+    assert_eq!(facets["obs_type"][0], serde_json::json!({"value": "file_edit", "count": 2}));
+    assert_eq!(facets["obs_type"].as_array().unwrap().len(), 4);
+    assert_eq!(facets["project"][0], serde_json::json!({"value": "myproj", "count": 5}));
+    assert_eq!(facets["session"][1], serde_json::json!({"value": "sess-b", "count": 1}));
+}
+
+#[test]
+fn search_infers_project_from_mentioned_path() {
+    let server = make_server();
+    let search = |query: &str, infer_project: Option<bool>| {
+        result_json(
+            &server
+                .do_search(SearchParams {
+                    query: query.into(),
+                    project: None,
+                    obs_type: None,
+                    limit: None,
+                    offset: None,
+                    order_by: None,
+                    before: None,
+                    after: None,
+                    window: None,
+                    error_class: None,
+                    mcp_server: None,
+                    mcp_tool: None,
+                    cwd: None,
+                    facets: None,
+                    infer_project,
+                    include_archived: None,
+                    cursor: None,
+                    compact: None,
+                    max_bytes: None,
+                })
+                .unwrap(),
+        )
+    };
+
+    // Only sess-b (project "other") touched /src/main.rs
+    let json = search("read src/main.rs", None);
+    assert_eq!(json["inferred_project"]["project"], "other");
+    assert_eq!(json["inferred_project"]["paths"][0], "src/main.rs");
+    assert_eq!(json["inferred_project"]["confidence"], 1.0);
+    let results = json["results"].as_array().unwrap();
+    assert!(!results.is_empty());
+    assert!(results.iter().all(|r| r["session_id"] == "sess-b"), "{json}");
+
+    // Opting out keeps the bare array
+    assert!(search("read src/main.rs", Some(false)).is_array());
+
+    // No path in the query: nothing inferred
+    assert!(search("auth", None).is_array());
+}
+
+#[test]
+fn search_skips_archived_projects_unless_asked() {
+    let server = make_server();
+    nmem::archive::archive(&server.db_handle().lock().unwrap(), "other", 1707500000).unwrap();
+    let search = |project: Option<&str>, include_archived: Option<bool>| {
+        result_json(
+            &server
+                .do_search(SearchParams {
+                    query: "project".into(),
+                    project: project.map(String::from),
+                    obs_type: None,
+                    limit: None,
+                    offset: None,
+                    order_by: None,
+                    before: None,
+                    after: None,
+                    window: None,
+                    error_class: None,
+                    mcp_server: None,
+                    mcp_tool: None,
+                    cwd: None,
+                    facets: None,
+                    infer_project: None,
+                    include_archived,
+                    cursor: None,
+                    compact: None,
+                    max_bytes: None,
+                })
+                .unwrap(),
+        )
+        .as_array()
+        .unwrap()
+        .len()
+    };
+
+    assert_eq!(search(None, None), 0);
+    assert_eq!(search(None, Some(true)), 1);
+    assert_eq!(search(Some("other"), None), 1, "naming the project includes it");
+}
+
 #[test]
 fn search_returns_empty_for_no_match() {
     let server = make_server();
@@ -200,6 +487,17 @@ fn search_returns_empty_for_no_match() {
             order_by: None,
             before: None,
             after: None,
+            window: None,
+            error_class: None,
+            mcp_server: None,
+            mcp_tool: None,
+            cwd: None,
+            facets: None,
+            infer_project: None,
+            include_archived: None,
+            cursor: None,
+            compact: None,
+            max_bytes: None,
         })
         .unwrap();
 
@@ -220,6 +518,17 @@ fn search_respects_limit() {
             order_by: None,
             before: None,
             after: None,
+            window: None,
+            error_class: None,
+            mcp_server: None,
+            mcp_tool: None,
+            cwd: None,
+            facets: None,
+            infer_project: None,
+            include_archived: None,
+            cursor: None,
+            compact: None,
+            max_bytes: None,
         })
         .unwrap();
 
@@ -232,7 +541,7 @@ fn search_respects_limit() {
 fn get_observations_returns_full_objects() {
     let server = make_server();
     let result = server
-        .do_get_observations(GetObservationsParams { ids: vec![1, 3] })
+        .do_get_observations(GetObservationsParams { ids: vec![1, 3], related: None, compact: None, max_bytes: None })
         .unwrap();
 
     assert!(!result.is_error.unwrap_or(false));
@@ -245,11 +554,68 @@ fn get_observations_returns_full_objects() {
     assert!(items[1]["content"].as_str().unwrap().contains("cargo test"));
 }
 
+#[test]
+fn search_matches_chunks_and_get_observations_stitches_them() {
+    let db = test_db();
+    let plan = format!("Plan the auth rewrite. {}Finally rotate the hmac keyring.", "Step detail. ".repeat(120));
+    {
+        let conn = db.lock().unwrap();
+        let (head, chunks) = nmem::s1_chunk::split(&plan, 500);
+        conn.execute(
+            "INSERT INTO observations (id, session_id, timestamp, obs_type, source_event, tool_name, content)
+             VALUES (7, 'sess-a', 1707400070, 'plan', 'PostToolUse', 'ExitPlanMode', ?1)",
+            [&head],
+        )
+        .unwrap();
+        nmem::s1_chunk::store(&conn, 7, &chunks).unwrap();
+    }
+    let server = NmemServer::new(db);
+
+    for order_by in [None, Some("blended".to_string())] {
+        let result = server
+            .do_search(SearchParams {
+                query: "keyring".into(),
+                project: None,
+                obs_type: None,
+                limit: None,
+                offset: None,
+                order_by,
+                before: None,
+                after: None,
+                window: None,
+                error_class: None,
+                mcp_server: None,
+                mcp_tool: None,
+                cwd: None,
+                facets: Some(true),
+                infer_project: None,
+                include_archived: None,
+                cursor: None,
+                compact: None,
+                max_bytes: None,
+            })
+            .unwrap();
+        let json = result_json(&result);
+        let hit = &json["results"][0];
+        assert_eq!(hit["id"], 7);
+        assert!(hit["content_preview"].as_str().unwrap().contains("keyring"));
+        let start = hit["chunk"]["start"].as_u64().unwrap() as usize;
+        assert!(start > 0);
+        assert!(plan.chars().skip(start).collect::<String>().contains("keyring"));
+        assert_eq!(json["facets"]["total"], 1);
+    }
+
+    let result = server
+        .do_get_observations(GetObservationsParams { ids: vec![7], related: None, compact: None, max_bytes: None })
+        .unwrap();
+    assert_eq!(result_json(&result)[0]["content"], plan.as_str());
+}
+
 #[test]
 fn get_observations_empty_ids_error() {
     let server = make_server();
     let result = server
-        .do_get_observations(GetObservationsParams { ids: vec![] })
+        .do_get_observations(GetObservationsParams { ids: vec![], related: None, compact: None, max_bytes: None })
         .unwrap();
 
     assert!(result.is_error.unwrap_or(false));
@@ -262,6 +628,9 @@ fn get_observations_missing_ids_returns_partial() {
     let result = server
         .do_get_observations(GetObservationsParams {
             ids: vec![1, 9999],
+            related: None,
+            compact: None,
+            max_bytes: None,
         })
         .unwrap();
 
@@ -276,7 +645,7 @@ fn get_observations_missing_ids_returns_partial() {
 fn get_observations_with_metadata() {
     let server = make_server();
     let result = server
-        .do_get_observations(GetObservationsParams { ids: vec![2] })
+        .do_get_observations(GetObservationsParams { ids: vec![2], related: None, compact: None, max_bytes: None })
         .unwrap();
 
     let arr = result_json(&result);
@@ -285,6 +654,49 @@ fn get_observations_with_metadata() {
     assert_eq!(obs["metadata"]["redacted"], false);
 }
 
+#[test]
+fn get_observations_with_related() {
+    let server = make_server();
+    server
+        .db_handle()
+        .lock()
+        .unwrap()
+        .execute_batch("UPDATE observations SET error_class = 'test_failure' WHERE id IN (4, 5)")
+        .unwrap();
+
+    let result = server
+        .do_get_observations(GetObservationsParams { ids: vec![2, 4], related: Some(true), compact: None, max_bytes: None })
+        .unwrap();
+    let arr = result_json(&result);
+    let relations = |i: usize| -> Vec<(i64, String)> {
+        arr[i]["related"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|r| (r["id"].as_i64().unwrap(), r["relation"].as_str().unwrap().to_string()))
+            .collect()
+    };
+
+    // Same file nearest first, then the rest of the prompt; no duplicates
+    assert_eq!(arr[0]["id"], 2);
+    assert_eq!(
+        relations(0),
+        vec![
+            (1, "same_file".into()),
+            (6, "same_file".into()),
+            (3, "same_prompt".into()),
+            (4, "same_prompt".into()),
+        ]
+    );
+    // No file: same error class across sessions, then the prompt
+    assert_eq!(relations(1)[0], (5, "same_error".into()));
+    assert_eq!(relations(1).len(), 5);
+
+    // Off by default
+    let plain = result_json(&server.do_get_observations(GetObservationsParams { ids: vec![2], related: None, compact: None, max_bytes: None }).unwrap());
+    assert!(plain[0].get("related").is_none());
+}
+
 // --- timeline tests ---
 
 #[test]
@@ -295,6 +707,8 @@ fn timeline_returns_surrounding_context() {
             anchor: 3,
             before: Some(2),
             after: Some(2),
+            expand_repeats: None,
+            max_bytes: None,
         })
         .unwrap();
 
@@ -312,6 +726,44 @@ fn timeline_returns_surrounding_context() {
     assert!(after[0]["id"].as_i64().unwrap() > 3);
 }
 
+#[test]
+fn timeline_expands_repeated_command_runs() {
+    let server = make_server();
+    {
+        let db = server.db_handle();
+        let db = db.lock().unwrap();
+        db.execute_batch(
+            "UPDATE observations SET repeat_count = 3, last_timestamp = 1707400046 WHERE id = 3;
+             INSERT INTO observation_repeats (obs_id, timestamp) VALUES (3, 1707400043), (3, 1707400046);",
+        )
+        .unwrap();
+    }
+    let params = |expand_repeats| TimelineParams {
+        anchor: 2,
+        before: Some(0),
+        after: Some(2),
+        expand_repeats,
+        max_bytes: None,
+    };
+
+    let json = result_json(&server.do_timeline(params(None)).unwrap());
+    let after = json["after"].as_array().unwrap();
+    assert_eq!(after.len(), 2);
+    assert_eq!(after[0]["repeats"]["count"], 3);
+    assert_eq!(after[0]["repeats"]["last_timestamp"], 1707400046);
+    assert!(after[1].get("repeats").is_none());
+
+    let json = result_json(&server.do_timeline(params(Some(true))).unwrap());
+    let runs: Vec<(i64, i64, i64)> = json["after"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .filter(|o| o["id"] == 3)
+        .map(|o| (o["timestamp"].as_i64().unwrap(), o["repeats"]["run"].as_i64().unwrap(), o["repeats"]["count"].as_i64().unwrap()))
+        .collect();
+    assert_eq!(runs, vec![(1707400040, 1, 3), (1707400043, 2, 3), (1707400046, 3, 3)]);
+}
+
 #[test]
 fn timeline_missing_anchor_error() {
     let server = make_server();
@@ -319,6 +771,8 @@ fn timeline_missing_anchor_error() {
         anchor: 9999,
         before: None,
         after: None,
+        expand_repeats: None,
+        max_bytes: None,
     });
 
     assert!(result.is_err());
@@ -334,6 +788,8 @@ fn timeline_at_session_boundary() {
             anchor: 1,
             before: Some(5),
             after: Some(5),
+            expand_repeats: None,
+            max_bytes: None,
         })
         .unwrap();
 
@@ -354,6 +810,11 @@ fn recent_context_returns_deduped_by_file_path() {
             limit: Some(100),
             before: None,
             after: None,
+            window: None,
+            cursor: None,
+            session_id: None,
+            compact: None,
+            max_bytes: None,
         })
         .unwrap();
 
@@ -374,6 +835,33 @@ fn recent_context_returns_deduped_by_file_path() {
     assert!(null_fp.len() >= 2);
 }
 
+#[test]
+fn lean_server_registers_scoring_functions_on_first_use() {
+    let params = || RecentContextParams {
+        project: Some("myproj".into()),
+        limit: Some(10),
+        before: None,
+        after: None,
+        window: None,
+        cursor: None,
+        session_id: None,
+        compact: None,
+        max_bytes: None,
+    };
+    // Without the functions, the scored query cannot run at all
+    assert!(NmemServer::new(test_db_without_udfs()).do_recent_context(params()).is_err());
+
+    let server = NmemServer::new(test_db_without_udfs())
+        .with_lazy_udfs(nmem::s3_importance::ImportanceModel::default());
+    for _ in 0..2 {
+        let result = server.do_recent_context(params()).unwrap();
+        assert!(!result_json(&result).as_array().unwrap().is_empty());
+    }
+    let found: SearchParams = serde_json::from_value(serde_json::json!({"query": "auth"})).unwrap();
+    let result = server.do_search(found).unwrap();
+    assert!(!result_json(&result).as_array().unwrap().is_empty());
+}
+
 #[test]
 fn recent_context_filters_by_project() {
     let server = make_server();
@@ -383,6 +871,11 @@ fn recent_context_filters_by_project() {
             limit: None,
             before: None,
             after: None,
+            window: None,
+            cursor: None,
+            session_id: None,
+            compact: None,
+            max_bytes: None,
         })
         .unwrap();
 
@@ -405,6 +898,11 @@ fn recent_context_all_projects() {
             limit: None,
             before: None,
             after: None,
+            window: None,
+            cursor: None,
+            session_id: None,
+            compact: None,
+            max_bytes: None,
         })
         .unwrap();
 
@@ -419,6 +917,37 @@ fn recent_context_all_projects() {
     assert!(sessions.contains("sess-b"));
 }
 
+#[test]
+fn recent_context_compact_is_smaller_and_dedupes_sessions() {
+    let server = make_server();
+    let params = |compact| RecentContextParams {
+        project: None,
+        limit: None,
+        before: None,
+        after: None,
+        window: None,
+        cursor: None,
+        session_id: None,
+        compact,
+        max_bytes: None,
+    };
+    let full = result_text(&server.do_recent_context(params(None)).unwrap());
+    let compact = result_text(&server.do_recent_context(params(Some(true))).unwrap());
+    assert!(compact.len() * 2 < full.len() + full.len() / 5, "{} vs {}", compact.len(), full.len());
+
+    let v: serde_json::Value = serde_json::from_str(&compact).unwrap();
+    let sessions = v["sessions"].as_array().unwrap();
+    assert_eq!(sessions.len(), 2);
+    let results = v["results"].as_array().unwrap();
+    assert_eq!(results.len(), serde_json::from_str::<serde_json::Value>(&full).unwrap().as_array().unwrap().len());
+    for r in results {
+        let s = &sessions[r["s"].as_u64().unwrap() as usize];
+        assert!(s["project"].is_string());
+        assert!(r["i"].is_i64() && r["k"].is_string() && r["p"].is_string());
+        assert!(r.get("metadata").is_none() && r.get("score").is_none());
+    }
+}
+
 #[test]
 fn recent_context_empty_project() {
     let server = make_server();
@@ -428,6 +957,11 @@ fn recent_context_empty_project() {
             limit: None,
             before: None,
             after: None,
+            window: None,
+            cursor: None,
+            session_id: None,
+            compact: None,
+            max_bytes: None,
         })
         .unwrap();
 
@@ -465,6 +999,17 @@ fn search_includes_is_pinned() {
             order_by: None,
             before: None,
             after: None,
+            window: None,
+            error_class: None,
+            mcp_server: None,
+            mcp_tool: None,
+            cwd: None,
+            facets: None,
+            infer_project: None,
+            include_archived: None,
+            cursor: None,
+            compact: None,
+            max_bytes: None,
         })
         .unwrap();
 
@@ -494,7 +1039,7 @@ fn get_observations_includes_is_pinned() {
     }
 
     let result = server
-        .do_get_observations(GetObservationsParams { ids: vec![1, 3] })
+        .do_get_observations(GetObservationsParams { ids: vec![1, 3], related: None, compact: None, max_bytes: None })
         .unwrap();
 
     let arr = result_json(&result);
@@ -565,6 +1110,11 @@ fn scored_context_type_weight_ordering() {
             limit: Some(10),
             before: None,
             after: None,
+            window: None,
+            cursor: None,
+            session_id: None,
+            compact: None,
+            max_bytes: None,
         })
         .unwrap();
 
@@ -576,6 +1126,34 @@ fn scored_context_type_weight_ordering() {
     assert_eq!(items[2]["obs_type"], "file_read");
 }
 
+#[test]
+fn scored_context_reports_half_life() {
+    let now = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .unwrap()
+        .as_secs() as i64;
+    let server = scored_test_db(now);
+    insert_obs(&server, 1, "s1", now, "file_edit", Some("/a.rs"), "edit a");
+
+    let result = server
+        .do_recent_context(RecentContextParams {
+            project: None,
+            limit: Some(10),
+            before: None,
+            after: None,
+            window: None,
+            cursor: None,
+            session_id: None,
+            compact: None,
+            max_bytes: None,
+        })
+        .unwrap();
+
+    let arr = result_json(&result);
+    let half_life = arr[0]["half_life_days"].as_f64().unwrap();
+    assert!(half_life > 0.0);
+}
+
 #[test]
 fn scored_context_recency_beats_type() {
     let now = std::time::SystemTime::now()
@@ -612,6 +1190,11 @@ fn scored_context_recency_beats_type() {
             limit: Some(10),
             before: None,
             after: None,
+            window: None,
+            cursor: None,
+            session_id: None,
+            compact: None,
+            max_bytes: None,
         })
         .unwrap();
 
@@ -656,6 +1239,11 @@ fn scored_context_project_boost() {
             limit: Some(10),
             before: None,
             after: None,
+            window: None,
+            cursor: None,
+            session_id: None,
+            compact: None,
+            max_bytes: None,
         })
         .unwrap();
 
@@ -701,6 +1289,11 @@ fn scored_context_dedup_keeps_highest() {
             limit: Some(10),
             before: None,
             after: None,
+            window: None,
+            cursor: None,
+            session_id: None,
+            compact: None,
+            max_bytes: None,
         })
         .unwrap();
 
@@ -731,6 +1324,11 @@ fn scored_context_has_score_field() {
             limit: Some(10),
             before: None,
             after: None,
+            window: None,
+            cursor: None,
+            session_id: None,
+            compact: None,
+            max_bytes: None,
         })
         .unwrap();
 
@@ -758,6 +1356,17 @@ fn search_with_before_filter() {
             order_by: None,
             before: Some(1707400035),
             after: None,
+            window: None,
+            error_class: None,
+            mcp_server: None,
+            mcp_tool: None,
+            cwd: None,
+            facets: None,
+            infer_project: None,
+            include_archived: None,
+            cursor: None,
+            compact: None,
+            max_bytes: None,
         })
         .unwrap();
 
@@ -788,6 +1397,17 @@ fn search_with_after_filter() {
             order_by: None,
             before: None,
             after: Some(1707400045),
+            window: None,
+            error_class: None,
+            mcp_server: None,
+            mcp_tool: None,
+            cwd: None,
+            facets: None,
+            infer_project: None,
+            include_archived: None,
+            cursor: None,
+            compact: None,
+            max_bytes: None,
         })
         .unwrap();
 
@@ -817,6 +1437,17 @@ fn search_with_before_and_after() {
             order_by: None,
             before: Some(1707400055),
             after: Some(1707400025),
+            window: None,
+            error_class: None,
+            mcp_server: None,
+            mcp_tool: None,
+            cwd: None,
+            facets: None,
+            infer_project: None,
+            include_archived: None,
+            cursor: None,
+            compact: None,
+            max_bytes: None,
         })
         .unwrap();
 
@@ -851,6 +1482,11 @@ fn recent_context_with_before_filter() {
             limit: Some(10),
             before: Some(t2 + 1),
             after: None,
+            window: None,
+            cursor: None,
+            session_id: None,
+            compact: None,
+            max_bytes: None,
         })
         .unwrap();
 
@@ -885,6 +1521,11 @@ fn recent_context_with_after_filter() {
             limit: Some(10),
             before: None,
             after: Some(t1 + 1),
+            window: None,
+            cursor: None,
+            session_id: None,
+            compact: None,
+            max_bytes: None,
         })
         .unwrap();
 
@@ -924,6 +1565,10 @@ fn session_summaries_with_before_filter() {
             limit: None,
             before: Some(2500),
             after: None,
+            window: None,
+            model: None,
+            cursor: None,
+            max_bytes: None,
         })
         .unwrap();
 
@@ -936,6 +1581,47 @@ fn session_summaries_with_before_filter() {
     }
 }
 
+#[test]
+fn session_summaries_report_sources_and_unverified_files() {
+    let mut conn = Connection::open_in_memory().unwrap();
+    nmem::schema_migrations().to_latest(&mut conn).unwrap();
+    register_udfs(&conn).unwrap();
+
+    conn.execute_batch(
+        "
+        INSERT INTO sessions (id, project, started_at, summary, summary_sources)
+            VALUES ('s1', 'proj', 1000, '{\"intent\":\"fix\",\"files_edited\":[\"src/lib.rs\",\"src/imagined.rs\"]}', '[1]');
+        INSERT INTO sessions (id, project, started_at, summary)
+            VALUES ('s0', 'proj', 500, '{\"intent\":\"older\",\"files_edited\":[\"src/any.rs\"]}');
+        INSERT INTO observations (session_id, timestamp, obs_type, source_event, content, file_path)
+            VALUES ('s1', 1001, 'file_edit', 'PostToolUse', 'edit', '/repo/src/lib.rs');
+        ",
+    )
+    .unwrap();
+
+    let server = NmemServer::new(Arc::new(Mutex::new(conn)));
+    let result = server
+        .do_session_summaries(SessionSummariesParams {
+            project: None,
+            limit: None,
+            before: None,
+            after: None,
+            window: None,
+            model: None,
+            cursor: None,
+            max_bytes: None,
+        })
+        .unwrap();
+
+    let json = result_json(&result);
+    let items = json.as_array().unwrap();
+    assert_eq!(items[0]["sources"], serde_json::json!([1]));
+    assert_eq!(items[0]["unverified_files"], serde_json::json!(["src/imagined.rs"]));
+    // Summaries from before provenance was recorded carry neither field
+    assert!(items[1].get("sources").is_none());
+    assert!(items[1].get("unverified_files").is_none());
+}
+
 #[test]
 fn session_summaries_with_after_filter() {
     let mut conn = Connection::open_in_memory().unwrap();
@@ -963,6 +1649,10 @@ fn session_summaries_with_after_filter() {
             limit: None,
             before: None,
             after: Some(1500),
+            window: None,
+            model: None,
+            cursor: None,
+            max_bytes: None,
         })
         .unwrap();
 
@@ -975,6 +1665,45 @@ fn session_summaries_with_after_filter() {
     }
 }
 
+#[test]
+fn session_summaries_filter_by_model() {
+    let mut conn = Connection::open_in_memory().unwrap();
+    nmem::schema_migrations().to_latest(&mut conn).unwrap();
+    register_udfs(&conn).unwrap();
+
+    conn.execute_batch(
+        "
+        INSERT INTO sessions (id, project, started_at, summary, model)
+            VALUES ('s1', 'proj', 1000, '{\"intent\":\"first\"}', 'claude-opus-4-1');
+        INSERT INTO sessions (id, project, started_at, summary, model)
+            VALUES ('s2', 'proj', 2000, '{\"intent\":\"second\"}', 'claude-sonnet-4-5');
+        INSERT INTO sessions (id, project, started_at, summary)
+            VALUES ('s3', 'proj', 3000, '{\"intent\":\"third\"}');
+        ",
+    )
+    .unwrap();
+
+    let server = NmemServer::new(Arc::new(Mutex::new(conn)));
+    let result = server
+        .do_session_summaries(SessionSummariesParams {
+            project: None,
+            limit: None,
+            before: None,
+            after: None,
+            window: None,
+            model: Some("sonnet".into()),
+            cursor: None,
+            max_bytes: None,
+        })
+        .unwrap();
+
+    let arr = result_json(&result);
+    let items = arr.as_array().unwrap();
+    assert_eq!(items.len(), 1);
+    assert_eq!(items[0]["session_id"], "s2");
+    assert_eq!(items[0]["model"], "claude-sonnet-4-5");
+}
+
 // --- session_trace tests ---
 
 #[test]
@@ -985,6 +1714,8 @@ fn session_trace_returns_prompts_with_observations() {
             session_id: "sess-a".into(),
             before: None,
             after: None,
+            window: None,
+            max_bytes: None,
         })
         .unwrap();
 
@@ -1032,6 +1763,8 @@ fn session_trace_unknown_session_error() {
         session_id: "nonexistent".into(),
         before: None,
         after: None,
+        window: None,
+        max_bytes: None,
     });
 
     assert!(result.is_err());
@@ -1048,6 +1781,8 @@ fn session_trace_with_temporal_filter() {
             session_id: "sess-a".into(),
             before: Some(1707400035),
             after: None,
+            window: None,
+            max_bytes: None,
         })
         .unwrap();
 
@@ -1074,6 +1809,8 @@ fn session_trace_includes_orphan_observations() {
             session_id: "sess-b".into(),
             before: None,
             after: None,
+            window: None,
+            max_bytes: None,
         })
         .unwrap();
 
@@ -1109,6 +1846,8 @@ fn session_trace_includes_summary() {
             session_id: "s1".into(),
             before: None,
             after: None,
+            window: None,
+            max_bytes: None,
         })
         .unwrap();
 
@@ -1117,6 +1856,155 @@ fn session_trace_includes_summary() {
     assert_eq!(json["summary"]["intent"], "test session");
 }
 
+// --- similar_observations tests ---
+
+#[test]
+fn similar_observations_ranks_lookalikes() {
+    let server = make_server();
+    let failure = "error[E0382]: borrow of moved value `config` in src/serve.rs while compiling nmem tests";
+    {
+        let db = server.db_handle();
+        let db = db.lock().unwrap();
+        for (id, content) in [
+            (20, failure.to_string()),
+            (21, failure.replace("src/serve.rs", "src/record.rs")),
+            (22, "cargo fmt rewrote imports across the whole crate in one pass".to_string()),
+        ] {
+            db.execute(
+                "INSERT INTO observations (id, session_id, prompt_id, timestamp, obs_type, source_event, tool_name, content)
+                 VALUES (?1, 'sess-a', 1, 1707400100, 'command', 'PostToolUse', 'Bash', ?2)",
+                rusqlite::params![id, content],
+            )
+            .unwrap();
+        }
+        nmem::s1_minhash::backfill(&db, 100).unwrap();
+        // The anchor's signature is computed on the fly when missing
+        nmem::s1_minhash::remove(&db, 20).unwrap();
+    }
+    let similar = |params: serde_json::Value| server.do_similar_observations(serde_json::from_value(params).unwrap());
+
+    let json = result_json(&similar(serde_json::json!({"id": 20})).unwrap());
+    assert_eq!(json["comparable"], true);
+    let results = json["results"].as_array().unwrap();
+    assert_eq!(results.len(), 1, "{json}");
+    assert_eq!(results[0]["id"], 21);
+    assert_eq!(results[0]["project"], "myproj");
+    assert!(results[0]["similarity"].as_f64().unwrap() >= 0.5);
+
+    let json = result_json(&similar(serde_json::json!({"id": 1})).unwrap());
+    assert_eq!(json["comparable"], false, "too short: {json}");
+
+    let err = similar(serde_json::json!({"id": 999})).unwrap_err();
+    assert_eq!(err.code, rmcp::model::ErrorCode::INVALID_PARAMS);
+    let err = similar(serde_json::json!({"id": 20, "min_similarity": 1.5})).unwrap_err();
+    assert_eq!(err.data.unwrap()["reason"], "out_of_range");
+}
+
+// --- flaky_tests / test_history tests ---
+
+#[test]
+fn flaky_tests_and_test_history() {
+    let server = make_server();
+    {
+        let db = server.db_handle();
+        let db = db.lock().unwrap();
+        db.execute("INSERT INTO sessions (id, project, started_at) VALUES ('sess-c', 'myproj', 1707400200)", []).unwrap();
+        for (id, session, ts) in [(30, "sess-a", 1707400110), (31, "sess-c", 1707400210)] {
+            db.execute(
+                "INSERT INTO observations (id, session_id, timestamp, obs_type, source_event, tool_name, content)
+                 VALUES (?1, ?2, ?3, 'command', 'PostToolUseFailure', 'Bash', 'cargo test')",
+                rusqlite::params![id, session, ts],
+            )
+            .unwrap();
+            let failing = vec![
+                ("store::tests::test_reopen".to_string(), "cargo"),
+                (format!("api::tests::{session}"), "cargo"),
+            ];
+            nmem::s2_test_failure::record(&db, "myproj", session, id, ts, &failing).unwrap();
+        }
+        db.execute(
+            "INSERT INTO observations (id, session_id, timestamp, obs_type, source_event, tool_name, content)
+             VALUES (32, 'sess-b', 1707400120, 'command', 'PostToolUseFailure', 'Bash', 'pytest')",
+            [],
+        )
+        .unwrap();
+        let failing = vec![("tests/test_store.py::test_reopen".to_string(), "pytest")];
+        nmem::s2_test_failure::record(&db, "other", "sess-b", 32, 1707400120, &failing).unwrap();
+    }
+
+    let json = result_json(&server.do_flaky_tests(serde_json::from_value(serde_json::json!({})).unwrap()).unwrap());
+    let tests = json.as_array().unwrap();
+    assert_eq!(tests.len(), 1, "{json}");
+    assert_eq!(tests[0]["name"], "store::tests::test_reopen");
+    assert_eq!(tests[0]["sessions"], 2);
+    let json = result_json(
+        &server.do_flaky_tests(serde_json::from_value(serde_json::json!({"min_sessions": 1, "project": "myproj"})).unwrap()).unwrap(),
+    );
+    assert_eq!(json.as_array().unwrap().len(), 3, "{json}");
+
+    let history = |params: serde_json::Value| server.do_test_history(serde_json::from_value(params).unwrap());
+    let json = result_json(&history(serde_json::json!({"name": "tests::test_reopen", "project": "myproj"})).unwrap());
+    assert_eq!(json["name"], "store::tests::test_reopen");
+    let runs: Vec<i64> = json["runs"].as_array().unwrap().iter().map(|r| r["obs_id"].as_i64().unwrap()).collect();
+    assert_eq!(runs, vec![31, 30]);
+
+    // Without a project the tail also names the pytest test
+    let json = result_json(&history(serde_json::json!({"name": "test_reopen"})).unwrap());
+    assert_eq!(json["ambiguous"], true, "{json}");
+    assert_eq!(json["candidates"].as_array().unwrap().len(), 2);
+
+    let err = history(serde_json::json!({"name": "never_failed"})).unwrap_err();
+    assert_eq!(err.code, rmcp::model::ErrorCode::INVALID_PARAMS);
+}
+
+#[test]
+fn named_windows_bound_queries_and_summarize() {
+    let server = make_server();
+    let now = std::time::SystemTime::now().duration_since(std::time::UNIX_EPOCH).unwrap().as_secs() as i64;
+    {
+        let db = server.db_handle();
+        let db = db.lock().unwrap();
+        db.execute("INSERT INTO sessions (id, project, started_at) VALUES ('sess-now', 'myproj', ?1)", [now]).unwrap();
+        db.execute(
+            "INSERT INTO observations (id, session_id, timestamp, obs_type, source_event, tool_name, content, metadata)
+             VALUES (40, 'sess-now', ?1, 'command', 'PostToolUseFailure', 'Bash', 'cargo build', '{\"failed\":true}')",
+            [now],
+        )
+        .unwrap();
+        db.execute(
+            "INSERT INTO work_units (session_id, started_at, intent, outcome) VALUES ('sess-now', ?1, 'fix the build', 'blocked')",
+            [now],
+        )
+        .unwrap();
+    }
+
+    // The fixture's 2024 observations fall outside today
+    let recent = server
+        .do_recent_context(serde_json::from_value(serde_json::json!({"window": "today"})).unwrap())
+        .unwrap();
+    let ids: Vec<i64> = result_json(&recent).as_array().unwrap().iter().map(|o| o["id"].as_i64().unwrap()).collect();
+    assert_eq!(ids, vec![40]);
+
+    let json = result_json(
+        &server.do_window_summary(serde_json::from_value(serde_json::json!({"window": "Today"})).unwrap()).unwrap(),
+    );
+    assert_eq!(json["window"]["name"], "today");
+    assert_eq!(json["sessions"], 1);
+    assert_eq!(json["episodes"][0]["intent"], "fix the build");
+    assert_eq!(json["failures"]["total"], 1);
+    assert_eq!(json["failures"]["top"][0]["content"], "cargo build");
+
+    let err = server
+        .do_window_summary(serde_json::from_value(serde_json::json!({"window": "sprint-9"})).unwrap())
+        .unwrap_err();
+    assert_eq!(err.code, rmcp::model::ErrorCode::INVALID_PARAMS);
+    assert_eq!(err.data.unwrap()["limit"][0], "today");
+    let err = server
+        .do_search(serde_json::from_value(serde_json::json!({"query": "cargo", "window": "today", "before": 1000})).unwrap())
+        .unwrap_err();
+    assert_eq!(err.data.unwrap()["reason"], "empty_range");
+}
+
 // --- file_history tests ---
 
 #[test]
@@ -1128,7 +2016,10 @@ fn file_history_groups_by_session() {
             file_path: "/src/auth.rs".into(),
             before: None,
             after: None,
+            window: None,
             limit: None,
+            project: None,
+            max_bytes: None,
         })
         .unwrap();
 
@@ -1143,89 +2034,246 @@ fn file_history_groups_by_session() {
 
     let touches = sessions[0]["touches"].as_array().unwrap();
     assert_eq!(touches.len(), 3); // obs 1, 2, 6
+    assert!(json["risk"]["score"].is_number());
+    assert!(["low", "elevated", "high"].contains(&json["risk"]["level"].as_str().unwrap()));
 }
 
 #[test]
-fn file_history_includes_prompt_content() {
+fn file_history_leaves_out_suppressed_touches() {
+    let server = make_server();
+    let history = |path: &str| {
+        result_json(
+            &server
+                .do_file_history(FileHistoryParams {
+                    file_path: path.into(),
+                    before: None,
+                    after: None,
+                    window: None,
+                    limit: None,
+                    project: None,
+                    max_bytes: None,
+                })
+                .unwrap(),
+        )
+    };
+    {
+        let db = server.db_handle();
+        let db = db.lock().unwrap();
+        nmem::s3_suppress::add(&db, Some("myproj"), nmem::s3_suppress::TOPIC, "token validation", None, 1).unwrap();
+        nmem::s3_suppress::add(&db, Some("otherproj"), nmem::s3_suppress::FILE, "/src/auth.rs", None, 1).unwrap();
+    }
+    let json = history("/src/auth.rs");
+    assert_eq!(json["sessions"][0]["touches"].as_array().unwrap().len(), 2); // obs 1, 6
+    assert_eq!(json["suppressed"], 1);
+
+    {
+        let db = server.db_handle();
+        let db = db.lock().unwrap();
+        nmem::s3_suppress::add(&db, None, nmem::s3_suppress::FILE, "src/", Some(i64::MAX), 1).unwrap();
+    }
+    let json = history("/src/auth.rs");
+    assert_eq!(json["sessions"].as_array().unwrap().len(), 0);
+    assert_eq!(json["suppressed"], 3);
+}
+
+#[test]
+fn file_history_follows_renames() {
     let server = make_server();
+    {
+        let db = server.db_handle();
+        let db = db.lock().unwrap();
+        nmem::s1_alias::record_alias(&db, "/src/auth.rs", "/src/login.rs", "command", 1707400070).unwrap();
+        db.execute_batch(
+            "INSERT INTO observations (id, session_id, prompt_id, timestamp, obs_type, source_event, tool_name, file_path, content, metadata)
+                VALUES (7, 'sess-a', 1, 1707400080, 'file_edit', 'PostToolUse', 'Edit', '/src/login.rs', 'Edit /src/login.rs', NULL);",
+        )
+        .unwrap();
+    }
+
     let result = server
         .do_file_history(FileHistoryParams {
-            file_path: "/src/auth.rs".into(),
+            file_path: "/src/login.rs".into(),
             before: None,
             after: None,
+            window: None,
             limit: None,
+            project: None,
+            max_bytes: None,
         })
         .unwrap();
 
     let json = result_json(&result);
-    let sessions = json["sessions"].as_array().unwrap();
-    let touches = sessions[0]["touches"].as_array().unwrap();
-    // The observations for auth.rs are under prompt "Fix the login bug"
-    let has_prompt = touches
-        .iter()
-        .any(|t| t["prompt_content"].as_str() == Some("Fix the login bug"));
-    assert!(has_prompt, "should include user prompt content");
+    assert_eq!(json["aliases"], serde_json::json!(["/src/auth.rs"]));
+    let touches = json["sessions"][0]["touches"].as_array().unwrap();
+    assert_eq!(touches.len(), 4); // obs 7 plus 1, 2, 6 under the old name
+    assert!(touches[0].get("file_path").is_none());
+    assert_eq!(touches[1]["file_path"], "/src/auth.rs");
 }
 
 #[test]
-fn file_history_unknown_file_empty() {
+fn file_history_notes_other_projects_sharing_the_file() {
     let server = make_server();
+    {
+        let db = server.db_handle();
+        let db = db.lock().unwrap();
+        db.execute_batch(
+            r#"INSERT INTO sessions (id, project, started_at, summary)
+                VALUES ('sess-infra', 'infra', 1707400200, '{"intent":"tighten auth","learned":["auth.rs owns token expiry"]}');
+               INSERT INTO observations (session_id, timestamp, obs_type, source_event, tool_name, file_path, content)
+                VALUES ('sess-infra', 1707400210, 'file_edit', 'PostToolUse', 'Edit', '/src/auth.rs', 'Edit /src/auth.rs');"#,
+        )
+        .unwrap();
+    }
+
     let result = server
         .do_file_history(FileHistoryParams {
-            file_path: "/nonexistent/file.rs".into(),
+            file_path: "/src/auth.rs".into(),
             before: None,
             after: None,
+            window: None,
             limit: None,
+            project: Some("myproj".into()),
+            max_bytes: None,
         })
         .unwrap();
-
-    assert!(!result.is_error.unwrap_or(false));
     let json = result_json(&result);
-    let sessions = json["sessions"].as_array().unwrap();
-    assert!(sessions.is_empty());
-}
+    let shared = json["shared_with"].as_array().unwrap();
+    assert_eq!(shared.len(), 1);
+    assert_eq!(shared[0]["project"], "infra");
+    assert_eq!(shared[0]["last_decision"], "auth.rs owns token expiry");
 
-#[test]
-fn file_history_with_temporal_filter() {
-    let server = make_server();
-    // Only include touches before 1707400035 — should get obs 1 (1707400020) and 2 (1707400030) but not 6 (1707400060)
+    // Without a project, the newest touch's project is the caller's
     let result = server
         .do_file_history(FileHistoryParams {
             file_path: "/src/auth.rs".into(),
-            before: Some(1707400035),
+            before: None,
             after: None,
+            window: None,
             limit: None,
+            project: None,
+            max_bytes: None,
         })
         .unwrap();
-
     let json = result_json(&result);
-    let sessions = json["sessions"].as_array().unwrap();
-    assert_eq!(sessions.len(), 1);
-    let touches = sessions[0]["touches"].as_array().unwrap();
-    assert_eq!(touches.len(), 2);
-    for t in touches {
-        let ts = t["timestamp"].as_i64().unwrap();
-        assert!(ts < 1707400035, "expected timestamp < 1707400035, got {ts}");
-    }
+    assert_eq!(json["shared_with"][0]["project"], "myproj");
 }
 
 #[test]
-fn file_history_respects_limit() {
+fn file_history_includes_prompt_content() {
     let server = make_server();
-    // Limit to 1 observation total
     let result = server
         .do_file_history(FileHistoryParams {
             file_path: "/src/auth.rs".into(),
             before: None,
             after: None,
-            limit: Some(1),
+            window: None,
+            limit: None,
+            project: None,
+            max_bytes: None,
         })
         .unwrap();
 
     let json = result_json(&result);
     let sessions = json["sessions"].as_array().unwrap();
-    // With limit=1, only 1 observation returned → 1 session with 1 touch
-    let total_touches: usize = sessions
+    let touches = sessions[0]["touches"].as_array().unwrap();
+    // The observations for auth.rs are under prompt "Fix the login bug"
+    let has_prompt = touches
+        .iter()
+        .any(|t| t["prompt_content"].as_str() == Some("Fix the login bug"));
+    assert!(has_prompt, "should include user prompt content");
+}
+
+#[test]
+fn file_history_unknown_file_empty() {
+    let server = make_server();
+    let result = server
+        .do_file_history(FileHistoryParams {
+            file_path: "/nonexistent/file.rs".into(),
+            before: None,
+            after: None,
+            window: None,
+            limit: None,
+            project: None,
+            max_bytes: None,
+        })
+        .unwrap();
+
+    assert!(!result.is_error.unwrap_or(false));
+    let json = result_json(&result);
+    let sessions = json["sessions"].as_array().unwrap();
+    assert!(sessions.is_empty());
+}
+
+#[test]
+fn file_history_resolves_relative_paths() {
+    let server = make_server();
+    let result = server
+        .do_file_history(FileHistoryParams {
+            file_path: "auth.rs".into(),
+            before: None,
+            after: None,
+            window: None,
+            limit: None,
+            project: None,
+            max_bytes: None,
+        })
+        .unwrap();
+
+    let json = result_json(&result);
+    assert_eq!(json["file_path"], "auth.rs");
+    assert_eq!(json["resolved_path"], "/src/auth.rs");
+    assert_eq!(json["match"], "suffix");
+    assert_eq!(json["sessions"][0]["touches"].as_array().unwrap().len(), 3);
+    assert!(json["sessions"][0]["touches"][0].get("file_path").is_none());
+}
+
+#[test]
+fn file_history_with_temporal_filter() {
+    let server = make_server();
+    // Only include touches before 1707400035 — should get obs 1 (1707400020) and 2 (1707400030) but not 6 (1707400060)
+    let result = server
+        .do_file_history(FileHistoryParams {
+            file_path: "/src/auth.rs".into(),
+            before: Some(1707400035),
+            after: None,
+            window: None,
+            limit: None,
+            project: None,
+            max_bytes: None,
+        })
+        .unwrap();
+
+    let json = result_json(&result);
+    let sessions = json["sessions"].as_array().unwrap();
+    assert_eq!(sessions.len(), 1);
+    let touches = sessions[0]["touches"].as_array().unwrap();
+    assert_eq!(touches.len(), 2);
+    for t in touches {
+        let ts = t["timestamp"].as_i64().unwrap();
+        assert!(ts < 1707400035, "expected timestamp < 1707400035, got {ts}");
+    }
+}
+
+#[test]
+fn file_history_respects_limit() {
+    let server = make_server();
+    // Limit to 1 observation total
+    let result = server
+        .do_file_history(FileHistoryParams {
+            file_path: "/src/auth.rs".into(),
+            before: None,
+            after: None,
+            window: None,
+            limit: Some(1),
+            project: None,
+            max_bytes: None,
+        })
+        .unwrap();
+
+    let json = result_json(&result);
+    let sessions = json["sessions"].as_array().unwrap();
+    // With limit=1, only 1 observation returned → 1 session with 1 touch
+    let total_touches: usize = sessions
         .iter()
         .map(|s| s["touches"].as_array().unwrap().len())
         .sum();
@@ -1253,7 +2301,10 @@ fn file_history_includes_summary_intent() {
             file_path: "/src/auth.rs".into(),
             before: None,
             after: None,
+            window: None,
             limit: None,
+            project: None,
+            max_bytes: None,
         })
         .unwrap();
 
@@ -1266,6 +2317,133 @@ fn file_history_includes_summary_intent() {
     );
 }
 
+// --- bundle tests ---
+
+fn bundle_req(key: Option<&str>, tool: &str, params: serde_json::Value) -> BundleRequest {
+    BundleRequest {
+        key: key.map(String::from),
+        tool: tool.into(),
+        params,
+    }
+}
+
+#[test]
+fn max_bytes_caps_bundled_responses() {
+    let server = make_server();
+    let result = server
+        .do_bundle(BundleParams {
+            requests: vec![
+                bundle_req(Some("capped"), "get_observations", serde_json::json!({"ids": [1, 2, 3, 4, 5, 6], "max_bytes": 600})),
+                bundle_req(Some("full"), "get_observations", serde_json::json!({"ids": [1, 2, 3, 4, 5, 6]})),
+            ],
+            max_bytes: None,
+        })
+        .unwrap();
+    let json = result_json(&result);
+
+    let full = json["full"].as_array().unwrap();
+    assert_eq!(full.len(), 6);
+    let capped = &json["capped"];
+    assert_eq!(capped["truncated"], true);
+    let kept = capped["results"].as_array().unwrap();
+    assert!(!kept.is_empty() && kept.len() < 6, "{capped}");
+    assert_eq!(capped["omitted"].as_u64().unwrap() as usize + kept.len(), 6);
+    assert_eq!(kept[0], full[0], "elements are dropped from the end, not altered");
+    assert!(capped.to_string().len() <= 600);
+}
+
+#[test]
+fn bundle_returns_keyed_results() {
+    let server = make_server();
+    let result = server
+        .do_bundle(BundleParams {
+            requests: vec![
+                bundle_req(Some("hits"), "search", serde_json::json!({"query": "auth"})),
+                bundle_req(None, "get_observations", serde_json::json!({"ids": [1, 2]})),
+                bundle_req(None, "file_history", serde_json::json!({"file_path": "/src/auth.rs"})),
+                bundle_req(None, "session_summaries", serde_json::Value::Null),
+            ],
+            max_bytes: None,
+        })
+        .unwrap();
+
+    assert!(!result.is_error.unwrap_or(false));
+    let json = result_json(&result);
+    assert!(!json["hits"].as_array().unwrap().is_empty());
+    assert_eq!(json["get_observations"].as_array().unwrap().len(), 2);
+    assert_eq!(json["file_history"]["file_path"], "/src/auth.rs");
+    assert!(json["session_summaries"].as_array().unwrap().is_empty());
+}
+
+#[test]
+fn bundle_suffixes_repeated_tool_keys() {
+    let server = make_server();
+    let result = server
+        .do_bundle(BundleParams {
+            requests: vec![
+                bundle_req(None, "search", serde_json::json!({"query": "auth"})),
+                bundle_req(None, "search", serde_json::json!({"query": "cargo"})),
+            ],
+            max_bytes: None,
+        })
+        .unwrap();
+
+    let json = result_json(&result);
+    assert!(json.get("search").is_some());
+    assert!(json.get("search_1").is_some());
+}
+
+#[test]
+fn bundle_sub_request_error_is_isolated() {
+    let server = make_server();
+    let result = server
+        .do_bundle(BundleParams {
+            requests: vec![
+                bundle_req(Some("bad"), "timeline", serde_json::json!({"anchor": 999})),
+                bundle_req(Some("unknown"), "queue_task", serde_json::json!({})),
+                bundle_req(Some("malformed"), "search", serde_json::json!({"limit": 5})),
+                bundle_req(Some("empty_ids"), "get_observations", serde_json::json!({"ids": []})),
+                bundle_req(Some("good"), "get_observations", serde_json::json!({"ids": [3]})),
+            ],
+            max_bytes: None,
+        })
+        .unwrap();
+
+    let json = result_json(&result);
+    assert!(json["bad"]["error"].as_str().unwrap().contains("anchor"));
+    assert!(json["unknown"]["error"].as_str().unwrap().contains("unsupported"));
+    assert!(json["malformed"]["error"].as_str().unwrap().contains("query"));
+    assert!(json["empty_ids"]["error"].as_str().unwrap().contains("empty"));
+    assert_eq!(json["good"][0]["id"], 3);
+}
+
+#[test]
+fn bundle_rejects_duplicate_keys_and_oversized_requests() {
+    let server = make_server();
+    let dup = server.do_bundle(BundleParams {
+        requests: vec![
+            bundle_req(Some("k"), "search", serde_json::json!({"query": "auth"})),
+            bundle_req(Some("k"), "search", serde_json::json!({"query": "auth"})),
+        ],
+        max_bytes: None,
+    });
+    assert_eq!(dup.unwrap_err().code, rmcp::model::ErrorCode::INVALID_PARAMS);
+
+    let too_many = server.do_bundle(BundleParams {
+        requests: (0..11)
+            .map(|_| bundle_req(None, "recent_context", serde_json::json!({})))
+            .collect(),
+        max_bytes: None,
+    });
+    let err = too_many.unwrap_err();
+    assert_eq!(err.code, rmcp::model::ErrorCode::INVALID_PARAMS);
+    assert_eq!(err.data.unwrap()["reason"], "too_many");
+
+    let empty = server.do_bundle(BundleParams { requests: vec![], max_bytes: None }).unwrap_err();
+    assert_eq!(empty.code, rmcp::model::ErrorCode::INVALID_PARAMS);
+    assert_eq!(empty.data.unwrap()["reason"], "too_few");
+}
+
 // --- git_file_summary tests ---
 
 fn make_git_server() -> (NmemServer, tempfile::TempDir) {
@@ -1317,6 +2495,7 @@ fn git_file_summary_returns_dense_summary() {
             file_path: "src/lib.rs".into(),
             max_commits: 50,
             full: false,
+            max_bytes: None,
         })
         .unwrap();
 
@@ -1338,6 +2517,7 @@ fn git_file_summary_full_returns_json() {
             file_path: "src/lib.rs".into(),
             max_commits: 50,
             full: true,
+            max_bytes: None,
         })
         .unwrap();
 
@@ -1356,6 +2536,7 @@ fn git_file_summary_nonexistent_file_errors() {
         file_path: "nonexistent.rs".into(),
         max_commits: 50,
         full: false,
+        max_bytes: None,
     });
 
     assert!(result.is_err());
@@ -1383,3 +2564,981 @@ impl Drop for SetCwd {
         let _ = std::env::set_current_dir(&self.prev);
     }
 }
+
+// --- cursor pagination tests ---
+
+#[test]
+fn search_cursor_pages_are_stable_across_inserts() {
+    let now = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .unwrap()
+        .as_secs() as i64;
+    let server = scored_test_db(now);
+    for id in 1..=5 {
+        insert_obs(&server, id, "s1", now - id * 60, "command", None, "cargo build pagination");
+    }
+
+    let page = |cursor: &str, order_by: Option<&str>| {
+        result_json(
+            &server
+                .do_search(SearchParams {
+                    query: "pagination".into(),
+                    project: None,
+                    obs_type: None,
+                    limit: Some(2),
+                    offset: None,
+                    order_by: order_by.map(String::from),
+                    before: None,
+                    after: None,
+                    window: None,
+                    error_class: None,
+                    mcp_server: None,
+                    mcp_tool: None,
+                    cwd: None,
+                    facets: None,
+                    infer_project: None,
+                    include_archived: None,
+                    cursor: Some(cursor.into()),
+                    compact: None,
+                    max_bytes: None,
+                })
+                .unwrap(),
+        )
+    };
+
+    let mut expected: Vec<i64> = (1..=5).collect();
+    for (late_id, order_by) in [(100, None), (101, Some("blended"))] {
+        let first = page("", order_by);
+        assert_eq!(first["results"].as_array().unwrap().len(), 2);
+        let cursor = first["next_cursor"].as_str().unwrap().to_string();
+
+        // Arrives between pages — must not shift or appear in later pages
+        insert_obs(&server, late_id, "s1", now, "command", None, "cargo build pagination");
+
+        let mut ids: Vec<i64> = first["results"].as_array().unwrap().iter().map(|r| r["id"].as_i64().unwrap()).collect();
+        let mut cursor = Some(cursor);
+        while let Some(c) = cursor {
+            let next = page(&c, order_by);
+            ids.extend(next["results"].as_array().unwrap().iter().map(|r| r["id"].as_i64().unwrap()));
+            cursor = next["next_cursor"].as_str().map(String::from);
+        }
+        ids.sort();
+        assert_eq!(ids, expected, "order_by={order_by:?}");
+        expected.push(late_id);
+    }
+}
+
+#[test]
+fn search_cursor_survives_reranking_inserts() {
+    let now = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .unwrap()
+        .as_secs() as i64;
+    let server = scored_test_db(now);
+    // Term frequency and length vary, so corpus statistics reorder them
+    for id in 1..=12 {
+        let content = format!("{} {}", "flaky ".repeat(id as usize % 4 + 1), "filler ".repeat((id as usize * 7) % 30));
+        insert_obs(&server, id, "s1", now - id * 60, "command", None, &content);
+    }
+    let search = |cursor: &str| {
+        let mut params: SearchParams = serde_json::from_value(serde_json::json!({
+            "query": "flaky",
+            "limit": 3,
+            "cursor": cursor,
+        }))
+        .unwrap();
+        params.infer_project = Some(false);
+        result_json(&server.do_search(params).unwrap())
+    };
+
+    let mut ids = Vec::new();
+    let mut cursor = String::new();
+    let mut late = 100;
+    loop {
+        let page = search(&cursor);
+        ids.extend(page["results"].as_array().unwrap().iter().map(|r| r["id"].as_i64().unwrap()));
+        let Some(next) = page["next_cursor"].as_str() else { break };
+        cursor = next.to_string();
+        // Matching rows of every shape arrive between pages, shifting bm25's
+        // document frequency and average length
+        for _ in 0..10 {
+            late += 1;
+            let content = format!("flaky {}", "word ".repeat(late as usize % 50));
+            insert_obs(&server, late, "s1", now, "command", None, &content);
+        }
+    }
+    let mut sorted = ids.clone();
+    sorted.sort();
+    sorted.dedup();
+    assert_eq!(sorted.len(), ids.len(), "no result repeats: {ids:?}");
+    assert_eq!(sorted, (1..=12).collect::<Vec<_>>(), "none skipped, none added");
+
+    // A cursor whose snapshot is gone asks the caller to start over
+    let stale = "v1|search|12|0|0000000000000000|1:3".bytes().map(|b| format!("{b:02x}")).collect::<String>();
+    let mut params: SearchParams = serde_json::from_value(serde_json::json!({"query": "flaky", "cursor": stale})).unwrap();
+    params.infer_project = Some(false);
+    let err = server.do_search(params).unwrap_err();
+    assert!(err.message.contains("cursor expired"), "{}", err.message);
+}
+
+#[test]
+fn search_cursor_rejects_changed_filters() {
+    let now = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .unwrap()
+        .as_secs() as i64;
+    let server = scored_test_db(now);
+    for id in 1..=6 {
+        insert_obs(&server, id, "s1", now - id * 60, "command", None, "cargo build");
+        insert_obs(&server, id + 10, "s2", now - id * 60, "command", None, "cargo build");
+    }
+    let search = |project: &str, cursor: &str, limit: i64| {
+        server.do_search(
+            serde_json::from_value(serde_json::json!({
+                "query": "cargo", "project": project, "limit": limit, "cursor": cursor,
+            }))
+            .unwrap(),
+        )
+    };
+
+    let first = result_json(&search("proj-a", "", 2).unwrap());
+    let cursor = first["next_cursor"].as_str().unwrap().to_string();
+    // Page size may change between pages; the result set may not
+    let second = result_json(&search("proj-a", &cursor, 3).unwrap());
+    assert!(second["results"].as_array().unwrap().iter().all(|r| r["id"].as_i64().unwrap() <= 6));
+
+    let err = search("proj-b", &cursor, 2).unwrap_err();
+    assert_eq!(err.code, rmcp::model::ErrorCode::INVALID_PARAMS);
+    assert!(err.message.contains("different query or filters"), "{}", err.message);
+}
+
+#[test]
+fn recent_context_cursor_walks_all_rows() {
+    let now = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .unwrap()
+        .as_secs() as i64;
+    let server = scored_test_db(now);
+    for id in 1..=7 {
+        // Same type and age for several rows → ties broken by id
+        insert_obs(&server, id, "s1", now - (id / 3) * 3600, "command", None, "make");
+    }
+
+    let mut seen = Vec::new();
+    let mut cursor = String::new();
+    loop {
+        let page = result_json(
+            &server
+                .do_recent_context(RecentContextParams {
+                    project: Some("proj-a".into()),
+                    limit: Some(3),
+                    before: None,
+                    after: None,
+                    window: None,
+                    cursor: Some(cursor.clone()),
+                    session_id: None,
+                    compact: None,
+                    max_bytes: None,
+                })
+                .unwrap(),
+        );
+        seen.extend(page["results"].as_array().unwrap().iter().map(|r| r["id"].as_i64().unwrap()));
+        match page["next_cursor"].as_str() {
+            Some(c) => cursor = c.to_string(),
+            None => break,
+        }
+    }
+    assert_eq!(seen, vec![2, 1, 5, 4, 3, 7, 6]);
+}
+
+#[test]
+fn session_summaries_cursor_and_legacy_array() {
+    let mut conn = Connection::open_in_memory().unwrap();
+    nmem::schema_migrations().to_latest(&mut conn).unwrap();
+    register_udfs(&conn).unwrap();
+    conn.execute_batch(
+        "
+        INSERT INTO sessions (id, project, started_at, summary) VALUES ('s1', 'proj', 1000, '{}');
+        INSERT INTO sessions (id, project, started_at, summary) VALUES ('s2', 'proj', 2000, '{}');
+        INSERT INTO sessions (id, project, started_at, summary) VALUES ('s3', 'proj', 2000, '{}');
+        ",
+    )
+    .unwrap();
+    let server = NmemServer::new(Arc::new(Mutex::new(conn)));
+    let params = |cursor: Option<&str>| SessionSummariesParams {
+        project: None,
+        limit: Some(2),
+        before: None,
+        after: None,
+        window: None,
+        model: None,
+        cursor: cursor.map(String::from),
+        max_bytes: None,
+    };
+
+    // No cursor → plain array, as before
+    assert!(result_json(&server.do_session_summaries(params(None)).unwrap()).is_array());
+
+    let first = result_json(&server.do_session_summaries(params(Some(""))).unwrap());
+    let ids: Vec<&str> = first["results"].as_array().unwrap().iter().map(|r| r["session_id"].as_str().unwrap()).collect();
+    assert_eq!(ids, vec!["s3", "s2"]);
+
+    let second = result_json(&server.do_session_summaries(params(first["next_cursor"].as_str())).unwrap());
+    assert_eq!(second["results"][0]["session_id"], "s1");
+    assert!(second["next_cursor"].is_null());
+
+    // Cursors are tool-specific and tamper-evident
+    let err = server.do_session_summaries(params(Some("zz"))).unwrap_err();
+    assert!(err.message.contains("invalid cursor"));
+}
+
+#[test]
+fn working_set_reports_open_episode() {
+    let server = make_server();
+    {
+        let db = server.db_handle();
+        let db = db.lock().unwrap();
+        db.execute_batch(
+            "INSERT INTO observations (id, session_id, prompt_id, timestamp, obs_type, source_event, tool_name, file_path, content, metadata, error_class)
+                VALUES (7, 'sess-a', 1, 1707400070, 'command', 'PostToolUse', 'Bash', NULL, 'cargo test', '{\"failed\":true}', 'test_failure');
+             INSERT INTO observations (id, session_id, prompt_id, timestamp, obs_type, source_event, tool_name, file_path, content, metadata, error_class)
+                VALUES (8, 'sess-a', 1, 1707400080, 'command', 'PostToolUse', 'Bash', NULL, 'cargo test', NULL, NULL);
+             INSERT INTO observations (id, session_id, prompt_id, timestamp, obs_type, source_event, tool_name, file_path, content, metadata, error_class)
+                VALUES (9, 'sess-a', 1, 1707400090, 'command', 'PostToolUse', 'Bash', NULL, 'cargo clippy', '{\"failed\":true}', 'lint');
+             INSERT INTO work_units (session_id, started_at, intent, first_prompt_id, last_prompt_id, hot_files, phase_signature, obs_count)
+                VALUES ('sess-b', 1707300000, 'refactor token checks', 1, 1, '[\"/src/auth.rs\",\"/src/main.rs\"]', '{}', 4);",
+        )
+        .unwrap();
+    }
+
+    let result = server
+        .do_working_set(WorkingSetParams { session_id: Some("sess-a".into()), limit: None, max_bytes: None })
+        .unwrap();
+    let json = result_json(&result);
+
+    assert_eq!(json["intent"], "Fix the login bug");
+    assert_eq!(json["obs_count"], 8);
+    let hot = json["hot_files"].as_array().unwrap();
+    assert_eq!(hot.len(), 1);
+    assert_eq!(hot[0]["file_path"], "/src/auth.rs");
+    assert_eq!(hot[0]["reads"], 1);
+    assert_eq!(hot[0]["edits"], 2);
+
+    let failing = json["failing_commands"].as_array().unwrap();
+    assert_eq!(failing.len(), 2);
+    assert_eq!(failing[0]["command"], "cargo clippy");
+    assert_eq!(failing[0]["resolved"], false);
+    assert_eq!(failing[1]["command"], "cargo test");
+    assert_eq!(failing[1]["error_class"], "test_failure");
+    assert_eq!(failing[1]["resolved"], true);
+
+    let related = json["related_episodes"].as_array().unwrap();
+    assert_eq!(related.len(), 1);
+    assert_eq!(related[0]["session_id"], "sess-b");
+    assert_eq!(related[0]["shared_files"], serde_json::json!(["/src/auth.rs"]));
+}
+
+#[test]
+fn grep_matches_regex_and_rejects_bad_patterns() {
+    let server = make_server();
+    let params = |pattern: &str| GrepParams {
+        pattern: pattern.into(),
+        project: None,
+        obs_type: None,
+        fts: None,
+        ignore_case: None,
+        before: None,
+        after: None,
+        window: None,
+        limit: None,
+        max_bytes: None,
+    };
+
+    let result = server.do_grep(params(r"line \d+")).unwrap();
+    let json = result_json(&result);
+    let matches = json["matches"].as_array().unwrap();
+    assert_eq!(matches.len(), 1);
+    assert_eq!(matches[0]["id"], 4);
+    assert_eq!(matches[0]["matched"], "line 42");
+    assert_eq!(json["scanned"], 6);
+    assert_eq!(json["scan_limited"], false);
+
+    let narrowed = server
+        .do_grep(GrepParams { fts: Some("auth".into()), ..params(r"/src/\w+\.rs") })
+        .unwrap();
+    let ids: Vec<i64> = result_json(&narrowed)["matches"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|m| m["id"].as_i64().unwrap())
+        .collect();
+    assert_eq!(ids, vec![6, 2, 1]);
+
+    let err = server.do_grep(params("(unclosed")).unwrap_err();
+    assert!(err.message.contains("invalid grep pattern"));
+}
+
+#[test]
+fn working_set_empty_without_prompts() {
+    let server = make_server();
+    let result = server
+        .do_working_set(WorkingSetParams { session_id: Some("sess-b".into()), limit: None, max_bytes: None })
+        .unwrap();
+    let json = result_json(&result);
+    assert_eq!(json["session_id"], "sess-b");
+    assert!(json.get("intent").is_none());
+    assert!(json["hot_files"].as_array().unwrap().is_empty());
+    assert!(json["related_episodes"].as_array().unwrap().is_empty());
+}
+
+#[test]
+fn query_log_records_calls_and_feeds_usage_report() {
+    let db = test_db();
+    let server = NmemServer::new(db.clone()).with_query_log(db.clone());
+    server
+        .do_bundle(BundleParams {
+            requests: vec![
+                bundle_req(None, "get_observations", serde_json::json!({"ids": [1, 2]})),
+                bundle_req(None, "search", serde_json::json!({"query": "zzznomatch"})),
+                bundle_req(None, "timeline", serde_json::json!({"anchor": 9999})),
+            ],
+            max_bytes: None,
+        })
+        .unwrap();
+
+    {
+        let conn = db.lock().unwrap();
+        let rows: Vec<(String, Option<i64>, bool, Option<String>)> = conn
+            .prepare("SELECT tool, result_count, is_error, obs_ids FROM query_log ORDER BY id")
+            .unwrap()
+            .query_map([], |r| Ok((r.get(0)?, r.get(1)?, r.get(2)?, r.get(3)?)))
+            .unwrap()
+            .collect::<Result<_, _>>()
+            .unwrap();
+        assert_eq!(rows.len(), 3);
+        assert_eq!(rows[0], ("bundle.get_observations".into(), Some(2), false, Some("[1,2]".into())));
+        assert_eq!(rows[1], ("bundle.search".into(), Some(0), false, None));
+        assert!(rows[2].2);
+    }
+
+    let report = result_json(
+        &server
+            .do_memory_usage_report(MemoryUsageReportParams { days: None, limit: None, max_bytes: None })
+            .unwrap(),
+    );
+    assert_eq!(report["total_calls"], 3);
+    let ids: Vec<i64> = report["top_observations"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|o| o["id"].as_i64().unwrap())
+        .collect();
+    assert_eq!(ids, vec![2, 1]);
+    let search = report["tools"].as_array().unwrap().iter().find(|t| t["tool"] == "bundle.search").unwrap();
+    assert_eq!(search["empty"], 1);
+}
+
+#[test]
+fn marker_replies_form_a_thread_visible_in_related() {
+    let db = test_db();
+    let server = NmemServer::new(db.clone()).with_writer(db.clone());
+    let mark = |text: &str, reply_to: Option<i64>, status: Option<&str>| {
+        server.do_create_marker(CreateMarkerParams {
+            text: text.into(),
+            project: Some("myproj".into()),
+            reply_to,
+            status: status.map(String::from),
+        })
+    };
+    let root = result_json(&mark("use sqlite for the cache", None, Some("proposed")).unwrap())["observation_id"]
+        .as_i64()
+        .unwrap();
+    let reply = result_json(&mark("benchmarks look fine", Some(root), None).unwrap())["observation_id"]
+        .as_i64()
+        .unwrap();
+    let last = result_json(&mark("switched to redis after load test", Some(reply), Some("superseded")).unwrap());
+    assert_eq!(last["reply_to"], reply);
+    let last = last["observation_id"].as_i64().unwrap();
+
+    let err = mark("reply to a tool call", Some(1), None).unwrap_err();
+    assert!(err.message.contains("#1 is not a marker"), "{}", err.message);
+
+    let fetched = result_json(
+        &server
+            .do_get_observations(GetObservationsParams { ids: vec![last], related: Some(true), compact: None, max_bytes: None })
+            .unwrap(),
+    );
+    let obs = &fetched[0];
+    assert_eq!(obs["metadata"]["thread"], root);
+    assert_eq!(obs["metadata"]["status"], "superseded");
+    let thread: Vec<i64> = obs["related"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .filter(|r| r["relation"] == "thread")
+        .map(|r| r["id"].as_i64().unwrap())
+        .collect();
+    assert_eq!(thread.len(), 2);
+    assert!(thread.contains(&root) && thread.contains(&reply), "{thread:?}");
+}
+
+#[test]
+fn remember_and_forget_memories() {
+    let db = test_db();
+    let server = NmemServer::new(db.clone()).with_writer(db.clone());
+    let remember = |params: serde_json::Value| server.do_remember(serde_json::from_value(params).unwrap());
+    let forget = |params: serde_json::Value| server.do_forget(serde_json::from_value(params).unwrap());
+
+    let json = result_json(
+        &remember(serde_json::json!({"key": "test-command", "content": "cargo test -- --test-threads=1", "project": "myproj"}))
+            .unwrap(),
+    );
+    assert_eq!((json["status"].as_str(), json["project"].as_str()), (Some("created"), Some("myproj")));
+    let json = result_json(
+        &remember(serde_json::json!({"key": "test-command", "content": "cargo nextest run", "project": "myproj", "ttl": "7d"}))
+            .unwrap(),
+    );
+    assert_eq!(json["status"], "updated");
+    assert!(json["expires_at"].as_i64().unwrap() > json["updated_at"].as_i64().unwrap());
+    let json = result_json(&remember(serde_json::json!({"key": "style", "content": "no emoji", "scope": "global"})).unwrap());
+    assert!(json["project"].is_null(), "{json}");
+
+    let err = remember(serde_json::json!({"key": "k", "content": "c", "scope": "team"})).unwrap_err();
+    assert_eq!(err.data.unwrap()["reason"], "invalid_value");
+    let err = remember(serde_json::json!({"key": "k", "content": "c", "ttl": "soon"})).unwrap_err();
+    assert_eq!(err.data.unwrap()["reason"], "unparsable");
+
+    let json = result_json(&forget(serde_json::json!({"key": "test-command", "project": "myproj"})).unwrap());
+    assert_eq!(json["status"], "forgotten");
+    let json = result_json(&forget(serde_json::json!({"key": "style", "project": "myproj"})).unwrap());
+    assert_eq!(json["status"], "not_found", "global memories need scope global");
+    let json = result_json(&forget(serde_json::json!({"key": "style", "scope": "global"})).unwrap());
+    assert_eq!(json["status"], "forgotten");
+}
+
+#[test]
+fn write_tools_use_the_writer_connection() {
+    let db = test_db();
+    let read_only = NmemServer::new(db.clone());
+    let err = read_only
+        .do_create_marker(CreateMarkerParams {
+            text: "decided".into(),
+            project: Some("myproj".into()),
+            reply_to: None,
+            status: None,
+        })
+        .unwrap_err();
+    assert!(err.message.contains("write tools unavailable"), "{}", err.message);
+
+    let server = NmemServer::new(db.clone()).with_writer(db.clone());
+    let marker = result_json(
+        &server
+            .do_create_marker(CreateMarkerParams {
+                text: "decided to keep the session cache".into(),
+                project: Some("myproj".into()),
+                reply_to: None,
+                status: None,
+            })
+            .unwrap(),
+    );
+    let obs_id = marker["observation_id"].as_i64().unwrap();
+    assert!(obs_id > 0);
+
+    let task = result_json(
+        &server
+            .do_queue_task(QueueTaskParams {
+                prompt: "rerun the flaky suite".into(),
+                project: Some("myproj".into()),
+                cwd: Some("/tmp".into()),
+                after: "2h".into(),
+            })
+            .unwrap(),
+    );
+    let task_id = task["task_id"].as_i64().unwrap();
+    let bad = server.do_queue_task(QueueTaskParams {
+        prompt: "x".into(),
+        project: None,
+        cwd: None,
+        after: "someday".into(),
+    });
+    assert!(bad.is_err());
+
+    let started = result_json(
+        &server
+            .do_start_investigation(StartInvestigationParams {
+                question: "why does login fail?".into(),
+                project: Some("myproj".into()),
+            })
+            .unwrap(),
+    );
+    assert_eq!(started["status"], "open");
+    let concluded = result_json(
+        &server
+            .do_conclude_investigation(ConcludeInvestigationParams {
+                conclusion: "expired token".into(),
+                investigation_id: started["investigation_id"].as_i64(),
+                project: Some("myproj".into()),
+            })
+            .unwrap(),
+    );
+    assert_eq!(concluded["conclusion"], "expired token");
+
+    let conn = db.lock().unwrap();
+    let (obs_type, session): (String, String) = conn
+        .query_row("SELECT obs_type, session_id FROM observations WHERE id = ?1", [obs_id], |r| {
+            Ok((r.get(0)?, r.get(1)?))
+        })
+        .unwrap();
+    assert_eq!((obs_type.as_str(), session.as_str()), ("marker", "sess-a"));
+    let (project, cwd): (String, String) = conn
+        .query_row("SELECT project, cwd FROM tasks WHERE id = ?1", [task_id], |r| Ok((r.get(0)?, r.get(1)?)))
+        .unwrap();
+    assert_eq!((project.as_str(), cwd.as_str()), ("myproj", "/tmp"));
+}
+
+#[test]
+fn attached_search_merges_on_per_store_rank() {
+    // Local: two rare matches. Team: the term is in every row, so its raw
+    // BM25 ranks are all weaker than local's — but its best row is still
+    // the best the team store has.
+    let local = test_db();
+    local
+        .lock()
+        .unwrap()
+        .execute_batch(
+            "INSERT INTO observations (id, session_id, timestamp, obs_type, source_event, content) VALUES
+                (60, 'sess-a', 1707400300, 'command', 'PostToolUse', 'zebra'),
+                (61, 'sess-a', 1707400301, 'command', 'PostToolUse', 'zebra crossing painted over the old road markings');",
+        )
+        .unwrap();
+    let team = test_db();
+    {
+        let db = team.lock().unwrap();
+        for i in 0..20 {
+            let content = if i == 0 { "zebra zebra".to_string() } else { format!("zebra note {i} with several more words") };
+            db.execute(
+                "INSERT INTO observations (id, session_id, timestamp, obs_type, source_event, content)
+                 VALUES (?1, 'sess-b', 1707400400, 'command', 'PostToolUse', ?2)",
+                rusqlite::params![100 + i, content],
+            )
+            .unwrap();
+        }
+    }
+    let server = NmemServer::new(local).with_attached("team", team);
+    let params: SearchParams = serde_json::from_value(serde_json::json!({"query": "zebra", "limit": 2})).unwrap();
+    let arr = result_json(&server.do_search(params).unwrap());
+    let top: Vec<(i64, &str)> = arr
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|r| (r["id"].as_i64().unwrap(), r["origin"].as_str().unwrap()))
+        .collect();
+    assert_eq!(top, vec![(60, "local"), (100, "team")]);
+}
+
+#[test]
+fn attached_databases_are_merged_and_tagged_by_origin() {
+    let team = test_db();
+    {
+        let db = team.lock().unwrap();
+        db.execute_batch(
+            "UPDATE sessions SET summary = '{\"intent\":\"team work\"}' WHERE id = 'sess-b';
+             INSERT INTO observations (id, session_id, prompt_id, timestamp, obs_type, source_event, tool_name, file_path, content, metadata)
+                VALUES (50, 'sess-b', NULL, 1707400200, 'file_edit', 'PostToolUse', 'Edit', '/src/auth.rs', 'Edit /src/auth.rs: team token rotation', NULL);",
+        )
+        .unwrap();
+    }
+    let server = make_server().with_attached("team", team);
+
+    let search = |query: &str, limit: Option<i64>| SearchParams {
+        query: query.into(),
+        project: None,
+        obs_type: None,
+        limit,
+        offset: None,
+        order_by: None,
+        before: None,
+        after: None,
+        window: None,
+        error_class: None,
+        mcp_server: None,
+        mcp_tool: None,
+        cwd: None,
+        facets: None,
+        infer_project: None,
+        include_archived: None,
+        cursor: None,
+        compact: None,
+        max_bytes: None,
+    };
+
+    // Only the team store has this row
+    let arr = result_json(&server.do_search(search("rotation", None)).unwrap());
+    let items = arr.as_array().unwrap();
+    assert_eq!(items.len(), 1);
+    assert_eq!(items[0]["id"], 50);
+    assert_eq!(items[0]["origin"], "team");
+
+    // Both stores match; the merge is tagged and respects limit
+    let arr = result_json(&server.do_search(search("auth", Some(3))).unwrap());
+    let items = arr.as_array().unwrap();
+    assert_eq!(items.len(), 3);
+    let origins: std::collections::HashSet<_> = items.iter().map(|i| i["origin"].as_str().unwrap()).collect();
+    assert!(origins.contains("local") && origins.contains("team"), "{items:?}");
+
+    let arr = result_json(
+        &server
+            .do_recent_context(RecentContextParams {
+                project: None,
+                limit: Some(5),
+                before: None,
+                after: None,
+                window: None,
+                cursor: None,
+                session_id: None,
+                compact: None,
+                max_bytes: None,
+            })
+            .unwrap(),
+    );
+    let items = arr.as_array().unwrap();
+    assert_eq!(items.len(), 5);
+    assert!(items.iter().all(|i| i["origin"].is_string()));
+
+    let arr = result_json(
+        &server
+            .do_session_summaries(SessionSummariesParams {
+                project: None,
+                limit: None,
+                before: None,
+                after: None,
+                window: None,
+                model: None,
+                cursor: None,
+                max_bytes: None,
+            })
+            .unwrap(),
+    );
+    let items = arr.as_array().unwrap();
+    assert_eq!(items.len(), 1);
+    assert_eq!(items[0]["origin"], "team");
+
+    // Cursors can't span stores
+    let mut paged = search("auth", None);
+    paged.cursor = Some(String::new());
+    assert!(server.do_search(paged).is_err());
+
+    // Single-store output is unchanged: no origin field
+    let arr = result_json(&make_server().do_search(search("auth", None)).unwrap());
+    assert!(arr.as_array().unwrap().iter().all(|i| i.get("origin").is_none()));
+}
+
+// --- file_snapshot tests ---
+
+#[test]
+fn file_snapshot_compares_against_current_content() {
+    let dir = tempfile::TempDir::new().unwrap();
+    let file = dir.path().join("limits.rs");
+    let path = file.to_str().unwrap().to_string();
+    std::fs::write(&file, "pub const MAX: usize = 10;\n").unwrap();
+    let old_hash = nmem::s1_snapshot::current_hash(&path).unwrap();
+    std::fs::write(&file, "pub const MAX: usize = 20;\n").unwrap();
+    let new_hash = nmem::s1_snapshot::current_hash(&path).unwrap();
+
+    let server = make_server();
+    {
+        let db = server.db_handle();
+        let db = db.lock().unwrap();
+        db.execute(
+            "INSERT INTO file_snapshots (session_id, file_path, captured_at, size, blob_hash, head)
+             VALUES ('sess-a', ?1, 1707400010, 27, ?2, 'pub const MAX: usize = 10;'),
+                    ('sess-b', ?1, 1707500010, 27, ?3, 'pub const MAX: usize = 20;')",
+            rusqlite::params![path, old_hash, new_hash],
+        )
+        .unwrap();
+    }
+
+    let params = |before| FileSnapshotParams {
+        file_path: path.clone(),
+        before,
+        session_id: None,
+        limit: None,
+        max_bytes: None,
+    };
+    let json = result_json(&server.do_file_snapshot(params(None)).unwrap());
+    assert_eq!(json["current_blob_hash"], new_hash.as_str());
+    let snaps = json["snapshots"].as_array().unwrap();
+    assert_eq!(snaps.len(), 2);
+    assert_eq!(snaps[0]["session_id"], "sess-b");
+    assert_eq!(snaps[0]["unchanged"], true);
+    assert_eq!(snaps[1]["unchanged"], false);
+
+    // As of before the second session read it
+    let json = result_json(&server.do_file_snapshot(params(Some(1707500000))).unwrap());
+    let snaps = json["snapshots"].as_array().unwrap();
+    assert_eq!(snaps.len(), 1);
+    assert_eq!(snaps[0]["head"], "pub const MAX: usize = 10;");
+}
+
+#[test]
+fn plan_history_reports_status_transitions() {
+    let server = make_server();
+    {
+        let db = server.db_handle();
+        let db = db.lock().unwrap();
+        let filter = nmem::s5_filter::SecretFilter::with_params(Default::default());
+        let write = |todos: serde_json::Value, ts| {
+            nmem::s1_plan::record(&db, &filter, "sess-a", 1, "TodoWrite", &serde_json::json!({"todos": todos}), ts)
+                .unwrap()
+        };
+        write(serde_json::json!([{"content": "Find the login bug", "status": "in_progress"}]), 1707400020);
+        write(
+            serde_json::json!([
+                {"content": "Find the login bug", "status": "completed"},
+                {"content": "Add a regression test", "status": "pending"},
+            ]),
+            1707400030,
+        );
+    }
+
+    let json = result_json(
+        &server
+            .do_plan_history(PlanHistoryParams { session_id: None, project: None, limit: None, max_bytes: None })
+            .unwrap(),
+    );
+    let events = json.as_array().unwrap();
+    assert_eq!(events.len(), 3);
+    assert_eq!(events[0]["status"], "in_progress");
+    assert!(events[0].get("prev_status").is_none());
+    assert_eq!(events[1]["item"], "Find the login bug");
+    assert_eq!(events[1]["prev_status"], "in_progress");
+    assert_eq!(events[1]["status"], "completed");
+    assert_eq!(events[2]["item"], "Add a regression test");
+
+    let json = result_json(
+        &server
+            .do_plan_history(PlanHistoryParams { session_id: Some("sess-b".into()), project: None, limit: None, max_bytes: None })
+            .unwrap(),
+    );
+    assert!(json.as_array().unwrap().is_empty());
+}
+
+#[test]
+fn search_degrades_to_scan_when_fts_is_corrupt() {
+    let server = make_server();
+    {
+        let db = server.db_handle();
+        let db = db.lock().unwrap();
+        db.execute_batch("UPDATE observations_fts_data SET block = x'0102030405060708' WHERE id > 10")
+            .unwrap();
+    }
+    let params = |query: &str, facets| SearchParams {
+        query: query.into(),
+        project: None,
+        obs_type: None,
+        limit: None,
+        offset: None,
+        order_by: None,
+        before: None,
+        after: None,
+        window: None,
+        error_class: None,
+        mcp_server: None,
+        mcp_tool: None,
+        cwd: None,
+        facets,
+        infer_project: Some(false),
+        include_archived: None,
+        cursor: None,
+        compact: None,
+        max_bytes: None,
+    };
+
+    let json = result_json(&server.do_search(params("cargo test", Some(true))).unwrap());
+    assert_eq!(json["degraded"]["reason"], "fts_unavailable");
+    assert!(json.get("facets").is_none());
+    let results = json["results"].as_array().unwrap();
+    assert!(!results.is_empty());
+    assert!(results.iter().all(|r| r["content_preview"].as_str().unwrap().to_lowercase().contains("cargo")));
+    let ids: Vec<i64> = results.iter().map(|r| r["id"].as_i64().unwrap()).collect();
+    assert!(ids.windows(2).all(|w| w[0] > w[1]), "newest first: {ids:?}");
+}
+
+#[test]
+fn recent_context_boosts_session_lineage() {
+    let server = make_server();
+    {
+        let db = server.db_handle();
+        let db = db.lock().unwrap();
+        db.execute_batch(
+            "INSERT INTO sessions (id, project, started_at) VALUES ('lin-1', 'lin', 1707600000);
+             INSERT INTO sessions (id, project, started_at, parent_id) VALUES ('lin-2', 'lin', 1707600100, 'lin-1');
+             INSERT INTO sessions (id, project, started_at) VALUES ('other-1', 'lin', 1707600050);
+             INSERT INTO observations (session_id, timestamp, obs_type, source_event, tool_name, file_path, content) VALUES
+                 ('lin-1', 1707600200, 'file_edit', 'PostToolUse', 'Edit', '/lin/a.rs', 'edit a'),
+                 ('lin-2', 1707600200, 'file_edit', 'PostToolUse', 'Edit', '/lin/b.rs', 'edit b'),
+                 ('other-1', 1707600200, 'file_edit', 'PostToolUse', 'Edit', '/lin/c.rs', 'edit c');",
+        )
+        .unwrap();
+        assert_eq!(nmem::s1_record::session_lineage(&db, "lin-2").unwrap(), vec!["lin-2", "lin-1"]);
+    }
+    let sessions = |session_id: Option<&str>| -> Vec<String> {
+        let json = result_json(
+            &server
+                .do_recent_context(RecentContextParams {
+                    project: Some("lin".into()),
+                    limit: Some(3),
+                    before: None,
+                    after: Some(1707600150),
+                    window: None,
+                    cursor: None,
+                    session_id: session_id.map(String::from),
+                    compact: None,
+                    max_bytes: None,
+                })
+                .unwrap(),
+        );
+        json.as_array().unwrap().iter().map(|r| r["session_id"].as_str().unwrap().to_string()).collect()
+    };
+
+    // Equal scores fall back to newest id first
+    assert_eq!(sessions(None), vec!["other-1", "lin-2", "lin-1"]);
+    assert_eq!(sessions(Some("lin-2")), vec!["lin-2", "lin-1", "other-1"]);
+}
+
+// --- input limits ---
+
+#[test]
+fn oversized_inputs_are_invalid_params_with_data() {
+    let server = make_server();
+    let err = server
+        .do_search(serde_json::from_value(serde_json::json!({"query": "auth ".repeat(2000)})).unwrap())
+        .unwrap_err();
+    assert_eq!(err.code, rmcp::model::ErrorCode::INVALID_PARAMS);
+    assert_eq!(err.data.as_ref().unwrap()["param"], "query");
+
+    let err = server
+        .do_get_observations(serde_json::from_value(serde_json::json!({"ids": (1..=51).collect::<Vec<i64>>()})).unwrap())
+        .unwrap_err();
+    assert_eq!(err.data.unwrap()["reason"], "too_many");
+
+    let err = server
+        .do_file_history(serde_json::from_value(serde_json::json!({"file_path": "/src/auth.rs", "after": 20, "before": 10})).unwrap())
+        .unwrap_err();
+    assert_eq!(err.data.unwrap()["reason"], "empty_range");
+
+    // FTS syntax in a file path is matched literally
+    let result = server
+        .do_search(serde_json::from_value(serde_json::json!({"query": "/src/auth.rs\" OR content:*"})).unwrap())
+        .unwrap();
+    assert!(!result.is_error.unwrap_or(false));
+}
+
+/// Deterministic xorshift, so a failing case reproduces.
+struct Fuzz(u64);
+
+impl Fuzz {
+    fn next(&mut self) -> u64 {
+        self.0 ^= self.0 << 13;
+        self.0 ^= self.0 >> 7;
+        self.0 ^= self.0 << 17;
+        self.0
+    }
+
+    fn pick<T: Clone>(&mut self, items: &[T]) -> T {
+        items[(self.next() % items.len() as u64) as usize].clone()
+    }
+
+    fn text(&mut self) -> String {
+        const PIECES: &[&str] = &[
+            "auth", "\"", "*", "-", ":", "^", "(", ")", "NEAR", "AND", "OR", "NOT", "/src/auth.rs", "'", "\\", "%",
+            "_", "é", "\u{0}", "{", "}", " ", "  ", "content:", "NEAR(a b)", "+", "token", "日本", ".", "\t",
+        ];
+        let n = self.next() % 12;
+        let mut s: String = (0..n).map(|_| self.pick(PIECES)).collect();
+        if self.next().is_multiple_of(10) {
+            s = s.repeat(400);
+        }
+        s
+    }
+
+    fn int(&mut self) -> serde_json::Value {
+        let n = self.pick(&[i64::MIN, -1, 0, 1, 50, 51, 10_001, 1707400020, 253_402_300_800, i64::MAX]);
+        serde_json::json!(n)
+    }
+
+    /// Absent (null) a third of the time.
+    fn opt_text(&mut self) -> serde_json::Value {
+        if self.next().is_multiple_of(3) { serde_json::Value::Null } else { self.text().into() }
+    }
+
+    fn opt_int(&mut self) -> serde_json::Value {
+        if self.next().is_multiple_of(3) { serde_json::Value::Null } else { self.int() }
+    }
+}
+
+#[test]
+fn fuzzed_params_never_fail_internally() {
+    let server = make_server();
+    let mut f = Fuzz(0x9e37_79b9_7f4a_7c15);
+    let check = |tool: &str, params: &serde_json::Value, result: Result<rmcp::model::CallToolResult, rmcp::ErrorData>| {
+        if let Err(e) = result {
+            assert_eq!(e.code, rmcp::model::ErrorCode::INVALID_PARAMS, "{tool} {params}: {}", e.message);
+        }
+    };
+    fn parse<T: serde::de::DeserializeOwned>(params: &serde_json::Value) -> T {
+        let object: serde_json::Map<String, serde_json::Value> =
+            params.as_object().unwrap().iter().filter(|(_, v)| !v.is_null()).map(|(k, v)| (k.clone(), v.clone())).collect();
+        serde_json::from_value(serde_json::Value::Object(object)).unwrap()
+    }
+
+    for _ in 0..300 {
+        let p = serde_json::json!({
+            "query": f.text(), "project": f.opt_text(), "obs_type": f.opt_text(),
+            "limit": f.opt_int(), "offset": f.opt_int(), "before": f.opt_int(), "after": f.opt_int(),
+            "orderBy": f.pick(&["relevance", "blended"]),
+        });
+        check("search", &p, server.do_search(parse(&p)));
+
+        let ids: Vec<serde_json::Value> = (0..f.next() % 60).map(|_| f.int()).collect();
+        let p = serde_json::json!({"ids": ids, "related": f.next().is_multiple_of(2)});
+        check("get_observations", &p, server.do_get_observations(parse(&p)));
+
+        let p = serde_json::json!({"anchor": f.int(), "before": f.opt_int(), "after": f.opt_int()});
+        check("timeline", &p, server.do_timeline(parse(&p)));
+
+        let p = serde_json::json!({"project": f.opt_text(), "limit": f.opt_int(),
+                                   "before": f.opt_int(), "after": f.opt_int(), "session_id": f.opt_text()});
+        check("recent_context", &p, server.do_recent_context(parse(&p)));
+        check("session_summaries", &p, server.do_session_summaries(parse(&p)));
+
+        let p = serde_json::json!({"session_id": f.text(), "before": f.opt_int(), "after": f.opt_int()});
+        check("session_trace", &p, server.do_session_trace(parse(&p)));
+
+        let p = serde_json::json!({"file_path": f.text(), "limit": f.opt_int(), "project": f.opt_text(),
+                                   "before": f.opt_int(), "after": f.opt_int()});
+        check("file_history", &p, server.do_file_history(parse(&p)));
+
+        let p = serde_json::json!({"file_path": f.text(), "limit": f.opt_int(), "before": f.opt_int()});
+        check("file_snapshot", &p, server.do_file_snapshot(parse(&p)));
+
+        let p = serde_json::json!({"pattern": f.text(), "fts": f.opt_text(), "limit": f.opt_int(),
+                                   "before": f.opt_int(), "after": f.opt_int()});
+        check("grep", &p, server.do_grep(parse(&p)));
+
+        let p = serde_json::json!({"session_id": f.opt_text(), "limit": f.opt_int()});
+        check("working_set", &p, server.do_working_set(parse(&p)));
+        check("plan_history", &p, server.do_plan_history(parse(&p)));
+
+        let p = serde_json::json!({"id": f.int(), "limit": f.opt_int()});
+        check("similar_observations", &p, server.do_similar_observations(parse(&p)));
+
+        let p = serde_json::json!({"project": f.opt_text(), "min_sessions": f.opt_int(), "limit": f.opt_int()});
+        check("flaky_tests", &p, server.do_flaky_tests(parse(&p)));
+
+        let p = serde_json::json!({"name": f.text(), "project": f.opt_text(), "limit": f.opt_int()});
+        check("test_history", &p, server.do_test_history(parse(&p)));
+        let p = serde_json::json!({"window": f.text(), "project": f.opt_text(), "limit": f.opt_int()});
+        check("window_summary", &p, server.do_window_summary(parse(&p)));
+    }
+}